@@ -0,0 +1,54 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+// C interface for embedding the transform.
+//
+// Memory ownership:
+//
+// - Strings passed to these functions are borrowed for the duration of
+//   the call and must be null terminated UTF-8.
+// - Strings returned by `dnt_transform` are owned by the caller and must
+//   be freed with `dnt_string_free`.
+// - The `url` passed to the load callback is only valid until the callback
+//   returns. The request is owned by the caller and must be passed to
+//   exactly one of `dnt_load_respond` or `dnt_load_fail`, which free it.
+
+#ifndef DNT_H
+#define DNT_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct DntLoadRequest DntLoadRequest;
+
+// Called to load the module at `url`. It's always called on the thread
+// that called `dnt_transform`, but the request may be responded to later
+// from any thread.
+typedef void (*DntLoadCallback)(void* user_data, const char* url,
+                                DntLoadRequest* request);
+
+// Transforms with the options as JSON, which are the same as the options
+// of the `transform` function of the Deno wrapper with the urls of the
+// entry points. Blocks until the transform finishes.
+//
+// Returns `{ "output": ... }` as JSON with the output, or `{ "error": ... }`
+// with the message of the error.
+char* dnt_transform(const char* options_json, DntLoadCallback load,
+                    void* user_data);
+
+// Responds to a load request with the module as JSON, as in
+// `{ "specifier": ..., "headers": { ... }, "content": ... }` with the
+// specifier after redirects, or `NULL` when the module wasn't found.
+void dnt_load_respond(DntLoadRequest* request, const char* response_json);
+
+// Responds to a load request with an error.
+void dnt_load_fail(DntLoadRequest* request, const char* message);
+
+// Frees a string returned by `dnt_transform`.
+void dnt_string_free(char* value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
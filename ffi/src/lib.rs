@@ -0,0 +1,148 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+//! C interface for embedding the transform. See `dnt.h` for the rules of
+//! who owns what memory.
+
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::os::raw::c_void;
+use std::panic::AssertUnwindSafe;
+
+use anyhow::Context;
+use anyhow::Result;
+use dnt::CallbackLoader;
+use dnt::LoadRequest;
+use dnt::LoadResponse;
+use dnt::SerializableTransformOptions;
+
+pub type DntLoadCallback = extern "C" fn(
+  user_data: *mut c_void,
+  url: *const c_char,
+  request: *mut DntLoadRequest,
+);
+
+/// A request to load a module, which is freed when it's responded to.
+pub struct DntLoadRequest(LoadRequest);
+
+struct Callback {
+  load: DntLoadCallback,
+  user_data: *mut c_void,
+}
+
+// The loader is only called on the thread that called `dnt_transform`,
+// so the user data is never used from another thread.
+unsafe impl Send for Callback {}
+unsafe impl Sync for Callback {}
+
+impl Callback {
+  fn call(&self, request: LoadRequest) {
+    // urls can't have a null character
+    let url = CString::new(request.specifier().as_str()).unwrap();
+    let request = Box::into_raw(Box::new(DntLoadRequest(request)));
+    (self.load)(self.user_data, url.as_ptr(), request);
+  }
+}
+
+/// Transforms with the options as JSON and returns the output or error
+/// as JSON, which must be freed with `dnt_string_free`.
+///
+/// # Safety
+///
+/// The options must be a valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn dnt_transform(
+  options_json: *const c_char,
+  load: DntLoadCallback,
+  user_data: *mut c_void,
+) -> *mut c_char {
+  let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+    transform(options_json, Callback { load, user_data })
+  }));
+  let json = match result {
+    Ok(Ok(output)) => serde_json::json!({ "output": output }),
+    // include the anyhow context
+    Ok(Err(err)) => serde_json::json!({ "error": format!("{:#}", err) }),
+    Err(_) => serde_json::json!({ "error": "The transform panicked." }),
+  };
+  CString::new(json.to_string()).unwrap().into_raw()
+}
+
+unsafe fn transform(
+  options_json: *const c_char,
+  callback: Callback,
+) -> Result<serde_json::Value> {
+  let options_json = CStr::from_ptr(options_json)
+    .to_str()
+    .context("The options weren't valid UTF-8.")?;
+  let options: SerializableTransformOptions =
+    serde_json::from_str(options_json)
+      .context("Error deserializing the options.")?;
+  let loader = CallbackLoader::new(move |request| callback.call(request));
+  let options = options.into_transform_options(Box::new(loader))?;
+  let output = futures::executor::block_on(dnt::transform(options))?;
+  Ok(serde_json::to_value(&output)?)
+}
+
+/// Responds to a load request with the module as JSON or `NULL` when it
+/// wasn't found, which frees the request.
+///
+/// # Safety
+///
+/// The request must not have been responded to and the response must be
+/// `NULL` or a valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn dnt_load_respond(
+  request: *mut DntLoadRequest,
+  response_json: *const c_char,
+) {
+  let request = Box::from_raw(request).0;
+  let result = if response_json.is_null() {
+    Ok(None)
+  } else {
+    parse_load_response(request.specifier(), response_json).map(Some)
+  };
+  request.respond(result);
+}
+
+unsafe fn parse_load_response(
+  specifier: &dnt::ModuleSpecifier,
+  response_json: *const c_char,
+) -> Result<LoadResponse> {
+  let response_json = CStr::from_ptr(response_json)
+    .to_str()
+    .with_context(|| format!("The response for {} wasn't UTF-8.", specifier))?;
+  serde_json::from_str(response_json).with_context(|| {
+    format!("Error deserializing the response for {}.", specifier)
+  })
+}
+
+/// Responds to a load request with an error, which frees the request.
+///
+/// # Safety
+///
+/// The request must not have been responded to and the message must be a
+/// valid null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn dnt_load_fail(
+  request: *mut DntLoadRequest,
+  message: *const c_char,
+) {
+  let request = Box::from_raw(request).0;
+  let message = CStr::from_ptr(message).to_string_lossy();
+  let err =
+    anyhow::anyhow!("Error loading {}. {}", request.specifier(), message);
+  request.respond(Err(err));
+}
+
+/// Frees a string returned by `dnt_transform`.
+///
+/// # Safety
+///
+/// The string must have been returned by `dnt_transform` and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn dnt_string_free(value: *mut c_char) {
+  if !value.is_null() {
+    drop(CString::from_raw(value));
+  }
+}
@@ -0,0 +1,5 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+fn main() {
+  napi_build::setup();
+}
@@ -0,0 +1,119 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::sync::Mutex;
+
+use anyhow::bail;
+use dnt::CallbackLoader;
+use dnt::LoadRequest;
+use dnt::LoadResponse;
+use dnt::SerializableTransformOptions;
+use napi::threadsafe_function::ErrorStrategy;
+use napi::threadsafe_function::ThreadSafeCallContext;
+use napi::threadsafe_function::ThreadsafeFunction;
+use napi::threadsafe_function::ThreadsafeFunctionCallMode;
+use napi::CallContext;
+use napi::Env;
+use napi::JsFunction;
+use napi::JsObject;
+use napi::JsUnknown;
+use napi::ValueType;
+use napi_derive::napi;
+
+/// Transforms on a separate thread and resolves with the output.
+///
+/// Modules are loaded by calling `load` with the url of a module and a
+/// `done` callback, which is called with an error or the module, or
+/// nothing when it's not found. Invalid options throw, while the errors
+/// of the transform reject the promise.
+#[napi(
+  ts_args_type = "options: TransformOptions, load: (url: string, done: (err: Error | null, response?: LoadResponse) => void) => void",
+  ts_return_type = "Promise<TransformOutput>"
+)]
+pub fn transform(
+  env: Env,
+  options: serde_json::Value,
+  load: JsFunction,
+) -> napi::Result<JsObject> {
+  let options: SerializableTransformOptions = serde_json::from_value(options)
+    .map_err(|err| {
+    napi::Error::from_reason(format!(
+      "Error deserializing the options. {}",
+      err
+    ))
+  })?;
+  // validate here so the invalid options throw instead of rejecting once
+  // they're converted on the transform's thread
+  options
+    .validate()
+    .map_err(|err| napi::Error::from_reason(format!("{:#}", err)))?;
+  let load: ThreadsafeFunction<LoadRequest, ErrorStrategy::Fatal> = load
+    .create_threadsafe_function(
+      0,
+      |ctx: ThreadSafeCallContext<LoadRequest>| {
+        let url = ctx.env.create_string(ctx.value.specifier().as_str())?;
+        // the callback may be called more than once, but only the first
+        // call responds
+        let request = Mutex::new(Some(ctx.value));
+        let done =
+          ctx.env.create_function_from_closure("done", move |ctx| {
+            if let Some(request) = request.lock().unwrap().take() {
+              request.respond(get_load_result(&ctx));
+            }
+            ctx.env.get_undefined()
+          })?;
+        Ok(vec![url.into_unknown(), done.into_unknown()])
+      },
+    )?;
+  // the transform options aren't `Send`, so they're created on the thread
+  // the transform runs to completion on while the loader calls back into
+  // JavaScript on the main thread
+  let (deferred, promise) = env.create_deferred()?;
+  std::thread::spawn(move || {
+    let loader = CallbackLoader::new(move |request| {
+      load.call(request, ThreadsafeFunctionCallMode::NonBlocking);
+    });
+    let result = options
+      .into_transform_options(Box::new(loader))
+      .and_then(|options| futures::executor::block_on(dnt::transform(options)));
+    match result {
+      Ok(output) => deferred.resolve(move |env| env.to_js_value(&output)),
+      // need to include the anyhow context
+      Err(err) => {
+        deferred.reject(napi::Error::from_reason(format!("{:#}", err)))
+      }
+    }
+  });
+  Ok(promise)
+}
+
+fn get_load_result(ctx: &CallContext) -> anyhow::Result<Option<LoadResponse>> {
+  let err = ctx.get::<JsUnknown>(0).map_err(to_anyhow)?;
+  if !is_nullish(&err)? {
+    let message = err
+      .coerce_to_string()
+      .and_then(|message| message.into_utf8())
+      .and_then(|message| message.into_owned())
+      .map_err(to_anyhow)?;
+    bail!("{}", message);
+  }
+  if ctx.length < 2 {
+    return Ok(None);
+  }
+  let response = ctx.get::<JsUnknown>(1).map_err(to_anyhow)?;
+  if is_nullish(&response)? {
+    return Ok(None);
+  }
+  Ok(Some(ctx.env.from_js_value(response).map_err(to_anyhow)?))
+}
+
+fn is_nullish(value: &JsUnknown) -> anyhow::Result<bool> {
+  Ok(matches!(
+    value.get_type().map_err(to_anyhow)?,
+    ValueType::Null | ValueType::Undefined
+  ))
+}
+
+/// Gets an error that can be sent to the thread of the transform.
+fn to_anyhow(err: napi::Error) -> anyhow::Error {
+  anyhow::anyhow!("{}", err.reason)
+}
@@ -51,6 +51,8 @@ fn is_local_declaration_ident(node: Node) -> bool {
         .map(|i| i.range().contains(&node.range()))
         .unwrap_or(false),
       Node::TsModuleDecl(decl) => decl.id.range().contains(&node.range()),
+      Node::TsEnumDecl(decl) => decl.id.range().contains(&node.range()),
+      Node::TsImportEqualsDecl(decl) => decl.id.range().contains(&node.range()),
       Node::TsNamespaceDecl(decl) => decl.id.range().contains(&node.range()),
       Node::VarDeclarator(decl) => decl.name.range().contains(&node.range()),
       Node::ImportNamedSpecifier(decl) => {
@@ -1,5 +1,6 @@
 // Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
 
+use deno_ast::swc::ast::MetaPropKind;
 use deno_ast::view::*;
 use deno_ast::SourceRanged;
 
@@ -192,3 +193,90 @@ pub fn is_in_type(mut node: Node) -> bool {
 
   false
 }
+
+/// Gets the module specifier of a module worker that's created with a URL
+/// relative to the module, which can be rewritten to point at the output
+/// (ex. `new Worker(new URL("./worker.ts", import.meta.url), { type: "module" })`).
+///
+/// This also matches a `Worker` from a shim or `node:worker_threads`.
+pub fn get_module_worker_specifier<'a>(
+  new_expr: &'a NewExpr<'a>,
+) -> Option<&'a Str<'a>> {
+  let is_worker = match new_expr.callee {
+    Expr::Ident(ident) => ident.sym().as_ref() == "Worker",
+    Expr::Member(member_expr) => matches!(
+      member_expr.prop,
+      MemberProp::Ident(prop) if prop.sym().as_ref() == "Worker"
+    ),
+    _ => false,
+  };
+  if !is_worker {
+    return None;
+  }
+  let args = new_expr.args.as_ref()?;
+  if args.len() != 2 || !is_module_worker_options(args[1].expr) {
+    return None;
+  }
+  let url_expr = match args[0].expr {
+    Expr::New(url_expr) => url_expr,
+    _ => return None,
+  };
+  if !matches!(url_expr.callee, Expr::Ident(ident) if ident.sym().as_ref() == "URL")
+  {
+    return None;
+  }
+  let url_args = url_expr.args.as_ref()?;
+  if url_args.len() != 2 || !is_import_meta_url(url_args[1].expr) {
+    return None;
+  }
+  match url_args[0].expr {
+    Expr::Lit(Lit::Str(str)) => {
+      let value = str.value();
+      if value.starts_with("./") || value.starts_with("../") {
+        Some(str)
+      } else {
+        None
+      }
+    }
+    _ => None,
+  }
+}
+
+/// Gets if the expression is an object with `type: "module"`.
+fn is_module_worker_options(expr: Expr) -> bool {
+  let object_lit = match expr {
+    Expr::Object(object_lit) => object_lit,
+    _ => return false,
+  };
+  object_lit.props.iter().any(|prop| match prop {
+    PropOrSpread::Prop(Prop::KeyValue(key_value)) => {
+      let is_type_key = match key_value.key {
+        PropName::Ident(ident) => ident.sym().as_ref() == "type",
+        PropName::Str(str) => str.value().as_ref() == "type",
+        _ => false,
+      };
+      is_type_key
+        && matches!(
+          key_value.value,
+          Expr::Lit(Lit::Str(str)) if str.value().as_ref() == "module"
+        )
+    }
+    _ => false,
+  })
+}
+
+/// Gets if the expression is `import.meta.url`.
+fn is_import_meta_url(expr: Expr) -> bool {
+  match expr {
+    Expr::Member(member_expr) => {
+      matches!(
+        member_expr.obj,
+        Expr::MetaProp(meta_prop) if meta_prop.inner.kind == MetaPropKind::ImportMeta
+      ) && matches!(
+        member_expr.prop,
+        MemberProp::Ident(prop) if prop.sym().as_ref() == "url"
+      )
+    }
+    _ => false,
+  }
+}
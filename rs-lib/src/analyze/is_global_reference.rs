@@ -0,0 +1,73 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashSet;
+
+use deno_ast::swc::common::SyntaxContext;
+use deno_ast::view::*;
+use deno_ast::SourceRanged;
+
+/// Gets if the identifier references the global with its name rather than
+/// something the module declares, which is what the visitors that rewrite
+/// or warn about globals need to check.
+///
+/// The scope analysis gives the references to a declaration in any scope
+/// (ex. a parameter, catch binding, or class name) the context of the
+/// declaration, so only the unresolved identifiers can be globals. The
+/// top level declarations are also checked by name, because a declaration
+/// that's only a type (ex. an interface) doesn't resolve the values.
+pub fn is_global_reference(
+  ident: &Ident,
+  unresolved_context: SyntaxContext,
+  top_level_decls: &HashSet<String>,
+) -> bool {
+  ident.ctxt() == unresolved_context
+    && !top_level_decls.contains(ident.sym().as_ref())
+    && !is_declaration_ident(ident.into())
+}
+
+/// Gets if the identifier is the name of a declaration, an import or
+/// export specifier, or a property in a destructuring pattern instead of
+/// a reference.
+pub fn is_declaration_ident(node: Node) -> bool {
+  if let Some(parent) = node.parent() {
+    match parent {
+      Node::BindingIdent(decl) => decl.id.range().contains(&node.range()),
+      Node::ClassDecl(decl) => decl.ident.range().contains(&node.range()),
+      Node::ClassExpr(decl) => decl
+        .ident
+        .as_ref()
+        .map(|i| i.range().contains(&node.range()))
+        .unwrap_or(false),
+      Node::TsInterfaceDecl(decl) => decl.id.range().contains(&node.range()),
+      Node::FnDecl(decl) => decl.ident.range().contains(&node.range()),
+      Node::FnExpr(decl) => decl
+        .ident
+        .as_ref()
+        .map(|i| i.range().contains(&node.range()))
+        .unwrap_or(false),
+      Node::TsModuleDecl(decl) => decl.id.range().contains(&node.range()),
+      Node::TsEnumDecl(decl) => decl.id.range().contains(&node.range()),
+      Node::TsImportEqualsDecl(decl) => decl.id.range().contains(&node.range()),
+      Node::TsTypeAliasDecl(decl) => decl.id.range().contains(&node.range()),
+      Node::TsNamespaceDecl(decl) => decl.id.range().contains(&node.range()),
+      Node::VarDeclarator(decl) => decl.name.range().contains(&node.range()),
+      Node::ImportNamedSpecifier(decl) => decl.range().contains(&node.range()),
+      Node::ExportNamedSpecifier(decl) => decl.range().contains(&node.range()),
+      Node::ImportDefaultSpecifier(decl) => {
+        decl.range().contains(&node.range())
+      }
+      Node::ExportDefaultSpecifier(decl) => {
+        decl.range().contains(&node.range())
+      }
+      Node::ImportStarAsSpecifier(decl) => decl.range().contains(&node.range()),
+      Node::ExportNamespaceSpecifier(decl) => {
+        decl.range().contains(&node.range())
+      }
+      Node::KeyValuePatProp(decl) => decl.key.range().contains(&node.range()),
+      Node::AssignPatProp(decl) => decl.key.range().contains(&node.range()),
+      _ => false,
+    }
+  } else {
+    false
+  }
+}
@@ -3,7 +3,9 @@
 mod get_ignore_line_indexes;
 mod get_top_level_decls;
 mod helpers;
+mod is_global_reference;
 
 pub use get_ignore_line_indexes::*;
 pub use get_top_level_decls::*;
 pub use helpers::*;
+pub use is_global_reference::*;
@@ -0,0 +1,166 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use deno_ast::MediaType;
+use deno_ast::TextChange;
+
+use crate::bundle::is_code_file;
+use crate::bundle::is_relative_specifier;
+use crate::module_exports::ExportTables;
+use crate::text_changes::apply_text_changes;
+use crate::trace::log_debug;
+use crate::utils::get_relative_path;
+use crate::virtual_path::VirtualPath;
+use crate::TransformOutputEnvironment;
+
+/// Replaces the `export * from` statements of the code files that point
+/// to barrel modules, which only consist of re-exports, with the barrel's
+/// own re-exports of the modules it's re-exporting.
+///
+/// Chains of barrels are followed until the re-exports point to modules
+/// with code. A barrel is only flattened into a module when the module
+/// exports the same names from the same declarations afterwards, so
+/// names that are ambiguous between `export *` sources stay that way.
+/// The barrels stay in the output for the modules that import them.
+pub fn flatten_barrels(
+  environment: &mut TransformOutputEnvironment,
+) -> Result<()> {
+  let mut tables = ExportTables::new(&environment.files)?;
+  for file in environment.files.iter_mut() {
+    if !is_code_file(&file.file_path) {
+      continue;
+    }
+    let path = file.file_path.clone();
+    let mut attempted_barrels = HashSet::new();
+    while let Some((barrel_path, text_change)) = get_next_flatten_change(
+      &tables,
+      &path,
+      &file.file_text,
+      &mut attempted_barrels,
+    ) {
+      let exports = tables.resolve_exports(&path);
+      let file_text = apply_text_changes(&file.file_text, vec![text_change])?;
+      tables.update(&path, &file_text)?;
+      if tables.resolve_exports(&path) == exports {
+        file.file_text = file_text;
+      } else {
+        log_debug!(
+          "Kept the re-export of {} in {} because flattening it would change the exports.",
+          barrel_path.display(),
+          path.display()
+        );
+        tables.update(&path, &file.file_text)?;
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Gets the change that replaces the next `export *` of a barrel in the
+/// module with the barrel's re-exports.
+fn get_next_flatten_change(
+  tables: &ExportTables,
+  path: &Path,
+  file_text: &str,
+  attempted_barrels: &mut HashSet<PathBuf>,
+) -> Option<(PathBuf, TextChange)> {
+  let module = tables.get(path)?;
+  let explicit_names = module.explicit_names();
+  for star_export in module.star_exports.iter() {
+    let barrel_path = match &star_export.path {
+      Some(barrel_path) if barrel_path != path => barrel_path,
+      _ => continue,
+    };
+    let barrel = match tables.get(barrel_path) {
+      Some(barrel) if barrel.is_barrel => barrel,
+      _ => continue,
+    };
+    if !attempted_barrels.insert(barrel_path.clone())
+      || (!is_typescript(path) && is_typescript(barrel_path))
+    {
+      continue;
+    }
+
+    // keep the quotes of the statement that's replaced
+    let quote = file_text[star_export.range.clone()]
+      .chars()
+      .rev()
+      .find(|c| *c == '"' || *c == '\'')
+      .unwrap_or('"');
+    let get_specifier = |specifier: &str| {
+      let target = VirtualPath::new(barrel_path)
+        .parent()
+        .filter(|_| is_relative_specifier(specifier))
+        .map(|dir| dir.join(specifier).to_path_buf());
+      match target {
+        Some(target) => get_relative_path(path, target),
+        None => specifier.to_string(),
+      }
+    };
+    let get_specifier_text = |specifier: &str| {
+      format!("{}{}{}", quote, get_specifier(specifier), quote)
+    };
+    let mut statements = Vec::new();
+    for re_export in barrel.named_re_exports.iter() {
+      let names = re_export
+        .names
+        .iter()
+        // names the module exports itself take precedence
+        .filter(|name| {
+          name.exported != "default"
+            && !explicit_names.contains(name.exported.as_str())
+        })
+        .map(|name| name.text.as_str())
+        .collect::<Vec<_>>();
+      if names.is_empty() {
+        continue;
+      }
+      let specifier_text = get_specifier_text(&re_export.specifier);
+      statements.push(match names.as_slice() {
+        [name] if name.starts_with("* as ") => {
+          format!("export {} from {};", name, specifier_text)
+        }
+        _ => format!(
+          "export {}{{ {} }} from {};",
+          if re_export.is_type_only { "type " } else { "" },
+          names.join(", "),
+          specifier_text
+        ),
+      });
+    }
+    for barrel_star_export in barrel.star_exports.iter() {
+      let specifier = get_specifier(&barrel_star_export.specifier);
+      let is_duplicate =
+        module
+          .star_exports
+          .iter()
+          .any(|star_export| match &barrel_star_export.path {
+            Some(target) => star_export.path.as_ref() == Some(target),
+            None => star_export.specifier == specifier,
+          });
+      if !is_duplicate {
+        statements
+          .push(format!("export * from {}{}{};", quote, specifier, quote));
+      }
+    }
+    return Some((
+      barrel_path.clone(),
+      TextChange {
+        range: star_export.range.clone(),
+        new_text: statements.join("\n"),
+      },
+    ));
+  }
+  None
+}
+
+fn is_typescript(path: &Path) -> bool {
+  matches!(
+    MediaType::from(path),
+    MediaType::TypeScript | MediaType::Mts | MediaType::Tsx
+  )
+}
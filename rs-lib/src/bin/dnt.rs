@@ -0,0 +1,185 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+//! Transforms with the options in a JSON or TOML config file and writes
+//! the output to a directory.
+//!
+//! Usage: `dnt [config-file]`, where the config file defaults to `dnt.json`.
+//! The config has the same options as the `transform` function of the
+//! Deno wrapper along with the output directory (ex. `"outDir": "npm/src"`).
+//! Entry points and the output directory may be paths relative to the
+//! directory of the config file.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use deno_node_transform::transform;
+use deno_node_transform::DefaultLoader;
+use deno_node_transform::ModuleSpecifier;
+use deno_node_transform::SerializableTransformOptions;
+use deno_node_transform::TransformOutput;
+use deno_node_transform::WriteOptions;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Config {
+  out_dir: PathBuf,
+  /// Remove the files written by a previous run that aren't in the
+  /// output anymore.
+  #[serde(default)]
+  remove_stale_files: bool,
+  #[serde(flatten)]
+  options: SerializableTransformOptions,
+}
+
+#[tokio::main]
+async fn main() {
+  let colors = Colors {
+    enabled: std::env::var_os("NO_COLOR").is_none(),
+  };
+  if let Err(err) = run(&colors).await {
+    // include the anyhow context
+    eprintln!("{} {:#}", colors.red("error:"), err);
+    std::process::exit(1);
+  }
+}
+
+async fn run(colors: &Colors) -> Result<()> {
+  let mut args = std::env::args_os().skip(1);
+  let config_path = args
+    .next()
+    .map(PathBuf::from)
+    .unwrap_or_else(|| PathBuf::from("dnt.json"));
+  if args.next().is_some() {
+    bail!("Expected only the path to the config file.");
+  }
+  let config_path = config_path.canonicalize().with_context(|| {
+    format!("Error finding config file {}", config_path.display())
+  })?;
+  let config = read_config(&config_path)?;
+  let config_dir = config_path.parent().unwrap();
+
+  let mut options = config.options;
+  options.entry_points = resolve_specifiers(config_dir, options.entry_points)?;
+  options.test_entry_points =
+    resolve_specifiers(config_dir, options.test_entry_points)?;
+  let options =
+    options.into_transform_options(Box::new(DefaultLoader::new()))?;
+  let output = transform(options).await?;
+  print_diagnostics(&output, colors);
+
+  let out_dir = config_dir.join(config.out_dir);
+  let result = output.write_to(
+    &out_dir,
+    &WriteOptions {
+      remove_stale_files: config.remove_stale_files,
+    },
+  )?;
+  eprintln!(
+    "{} {} files to {} ({} created, {} updated, {} unchanged, {} removed)",
+    colors.green("Wrote"),
+    result.created.len() + result.updated.len() + result.unchanged.len(),
+    out_dir.display(),
+    result.created.len(),
+    result.updated.len(),
+    result.unchanged.len(),
+    result.removed.len(),
+  );
+  Ok(())
+}
+
+fn read_config(path: &Path) -> Result<Config> {
+  let text = std::fs::read_to_string(path)
+    .with_context(|| format!("Error reading {}", path.display()))?;
+  let is_toml = path
+    .extension()
+    .map(|ext| ext.eq_ignore_ascii_case("toml"))
+    .unwrap_or(false);
+  if is_toml {
+    toml::from_str(&text)
+      .with_context(|| format!("Error parsing {}", path.display()))
+  } else {
+    serde_json::from_str(&text)
+      .with_context(|| format!("Error parsing {}", path.display()))
+  }
+}
+
+/// Resolves the entry points that are paths to file urls.
+fn resolve_specifiers(
+  config_dir: &Path,
+  values: Vec<String>,
+) -> Result<Vec<String>> {
+  let mut specifiers = Vec::new();
+  for value in values {
+    // a single letter scheme is a windows drive (ex. `C:\mod.ts`)
+    let is_url = ModuleSpecifier::parse(&value)
+      .map(|url| url.scheme().len() > 1)
+      .unwrap_or(false);
+    if is_url {
+      specifiers.push(value);
+    } else {
+      let path = config_dir
+        .join(&value)
+        .canonicalize()
+        .with_context(|| format!("Error finding entry point {}", value))?;
+      specifiers.push(ModuleSpecifier::from_file_path(path).unwrap().into());
+    }
+  }
+  Ok(specifiers)
+}
+
+fn print_diagnostics(output: &TransformOutput, colors: &Colors) {
+  for error in output.parse_errors.iter() {
+    eprintln!(
+      "{} {}\n    at {}:{}:{}",
+      colors.red("error:"),
+      error.message,
+      error.specifier,
+      error.line_number,
+      error.column_number,
+    );
+  }
+  for usage in output.unshimmable_api_usages.iter() {
+    eprintln!(
+      "{} {} can't be shimmed and will throw at runtime\n    at {}:{}:{}",
+      colors.yellow("warning:"),
+      usage.api,
+      usage.specifier,
+      usage.line_number,
+      usage.column_number,
+    );
+  }
+  for warning in output.warnings.iter() {
+    eprintln!("{} {}", colors.yellow("warning:"), warning);
+  }
+}
+
+/// Colors the text with ANSI escape codes unless `NO_COLOR` is set.
+struct Colors {
+  enabled: bool,
+}
+
+impl Colors {
+  fn red(&self, text: &str) -> String {
+    self.paint("31", text)
+  }
+
+  fn yellow(&self, text: &str) -> String {
+    self.paint("33", text)
+  }
+
+  fn green(&self, text: &str) -> String {
+    self.paint("32", text)
+  }
+
+  fn paint(&self, code: &str, text: &str) -> String {
+    if self.enabled {
+      format!("\x1b[1;{}m{}\x1b[0m", code, text)
+    } else {
+      text.to_string()
+    }
+  }
+}
@@ -0,0 +1,1410 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Result;
+use deno_ast::parse_module;
+use deno_ast::swc::common::SyntaxContext;
+use deno_ast::view::*;
+use deno_ast::MediaType;
+use deno_ast::ParseParams;
+use deno_ast::ParsedSource;
+use deno_ast::SourceRange;
+use deno_ast::SourceRanged;
+use deno_ast::SourceTextInfo;
+use deno_ast::SourceTextInfoProvider;
+use deno_ast::TextChange;
+
+use crate::analyze::get_top_level_decls;
+use crate::text_changes::apply_text_changes;
+use crate::text_changes::to_byte_range;
+use crate::utils::get_relative_path;
+use crate::utils::percent_decode;
+use crate::utils::strip_bom;
+use crate::utils::with_extension;
+use crate::utils::with_js_extension;
+use crate::virtual_path::VirtualPath;
+use crate::OutputFile;
+use crate::TransformOutputEnvironment;
+
+const DEFAULT_EXPORT_KEY: &str = "#default";
+
+/// Bundles the entry points of the main and test environments.
+///
+/// The test files import the main modules by their relative path, so the
+/// main modules they import that aren't entry points are kept in the
+/// output along with the modules those import.
+pub fn bundle_environments(
+  main_environment: &mut TransformOutputEnvironment,
+  test_environment: &mut TransformOutputEnvironment,
+) -> Result<()> {
+  let kept_paths = get_kept_paths(main_environment, &test_environment.files)?;
+  bundle_environment(main_environment, &kept_paths)?;
+  bundle_environment(test_environment, &HashSet::new())
+}
+
+/// Replaces the code files of the environment with one file per entry
+/// point that contains all the code the entry point statically imports.
+///
+/// The modules are concatenated in the order they're evaluated in and
+/// share a single scope, with their top level declarations renamed when
+/// they would conflict. Imports of modules that aren't in the output,
+/// such as npm packages, are kept. Declaration files are left as is.
+fn bundle_environment(
+  environment: &mut TransformOutputEnvironment,
+  kept_paths: &HashSet<PathBuf>,
+) -> Result<()> {
+  let mut bundled_paths = HashSet::new();
+  let mut bundle_files = Vec::with_capacity(environment.entry_points.len());
+  for entry_point in environment.entry_points.iter_mut() {
+    let mut bundler = Bundler::new(&environment.files);
+    let file_text = bundler.bundle(entry_point)?;
+    bundled_paths.extend(bundler.paths.iter().cloned());
+    let file_path = get_bundle_path(entry_point, &bundler.modules);
+    *entry_point = file_path.clone();
+    bundle_files.push(OutputFile {
+      file_path,
+      file_text,
+    });
+  }
+  environment.files.retain(|file| {
+    !bundled_paths.contains(&file.file_path)
+      || kept_paths.contains(&file.file_path)
+  });
+  environment.files.extend(bundle_files);
+  Ok(())
+}
+
+/// Gets the main modules the test files import, which aren't entry
+/// points, along with the modules they import.
+fn get_kept_paths(
+  main_environment: &TransformOutputEnvironment,
+  test_files: &[OutputFile],
+) -> Result<HashSet<PathBuf>> {
+  let main_files = &main_environment.files;
+  let mut pending = Vec::new();
+  for file in test_files.iter() {
+    if is_code_file(&file.file_path) {
+      pending.extend(get_internal_imports(main_files, file)?);
+    }
+  }
+  let mut kept_paths = HashSet::new();
+  while let Some(path) = pending.pop() {
+    // the bundle of an entry point replaces it at the same path or one
+    // its specifiers still resolve to
+    if main_environment.entry_points.contains(&path)
+      || !kept_paths.insert(path.clone())
+    {
+      continue;
+    }
+    let file = main_files.iter().find(|f| f.file_path == path).unwrap();
+    pending.extend(get_internal_imports(main_files, file)?);
+  }
+  Ok(kept_paths)
+}
+
+/// Gets the paths of the code files in `files` that the file imports,
+/// re-exports from, or dynamically imports.
+fn get_internal_imports(
+  files: &[OutputFile],
+  file: &OutputFile,
+) -> Result<Vec<PathBuf>> {
+  Ok(
+    get_module_specifiers(file)?
+      .iter()
+      .filter_map(|specifier| {
+        resolve_code_file(files, &file.file_path, specifier)
+      })
+      .collect(),
+  )
+}
+
+/// Gets the specifiers of the imports, re-exports, and dynamic imports
+/// with a string literal of a code file.
+pub(crate) fn get_module_specifiers(file: &OutputFile) -> Result<Vec<String>> {
+  fn fill_specifiers(node: Node, specifiers: &mut Vec<String>) {
+    let src = match node {
+      Node::ImportDecl(import_decl) => Some(import_decl.src),
+      Node::NamedExport(named_export) => named_export.src,
+      Node::ExportAll(export_all) => Some(export_all.src),
+      Node::CallExpr(call_expr)
+        if matches!(call_expr.callee, Callee::Import(_)) =>
+      {
+        match call_expr.args.get(0).map(|a| a.expr) {
+          Some(Expr::Lit(Lit::Str(src))) => Some(src),
+          _ => None,
+        }
+      }
+      _ => None,
+    };
+    if let Some(src) = src {
+      specifiers.push(src.value().to_string());
+    }
+    for child in node.children() {
+      fill_specifiers(child, specifiers);
+    }
+  }
+
+  let parsed_source = parse_module(ParseParams {
+    specifier: file.file_path.to_string_lossy().to_string(),
+    text_info: SourceTextInfo::from_string(
+      strip_bom(&file.file_text).to_string(),
+    ),
+    media_type: MediaType::from(file.file_path.as_path()),
+    capture_tokens: false,
+    scope_analysis: false,
+    maybe_syntax: None,
+  })
+  .map_err(|diagnostic| {
+    anyhow!(
+      "Could not parse {} to get its specifiers. {}",
+      file.file_path.display(),
+      diagnostic
+    )
+  })?;
+  Ok(parsed_source.with_view(|program| {
+    let mut specifiers = Vec::new();
+    fill_specifiers(program.as_node(), &mut specifiers);
+    specifiers
+  }))
+}
+
+/// Resolves a specifier to the path of a code file in the files.
+fn resolve_code_file(
+  files: &[OutputFile],
+  referrer: &Path,
+  specifier: &str,
+) -> Option<PathBuf> {
+  if !is_relative_specifier(specifier) {
+    return None;
+  }
+  // the characters that have a meaning in a url are percent-encoded
+  let path = VirtualPath::new(referrer)
+    .parent()?
+    .join(&percent_decode(specifier));
+  files
+    .iter()
+    .map(|file| &file.file_path)
+    .filter(|file_path| is_code_file(file_path))
+    .find(|file_path| {
+      VirtualPath::new(file_path) == path
+        || VirtualPath::new(with_js_extension(file_path)) == path
+        || VirtualPath::new(with_extension(file_path, "")) == path
+    })
+    .cloned()
+}
+
+/// Gets the path of the bundle, which changes the extension of the entry
+/// point when it can't contain the code of the other modules.
+fn get_bundle_path(entry_point: &Path, modules: &[BundleModule]) -> PathBuf {
+  let is_ts = |media_type: MediaType| {
+    matches!(
+      media_type,
+      MediaType::TypeScript | MediaType::Mts | MediaType::Cts | MediaType::Tsx
+    )
+  };
+  let is_jsx = |media_type: MediaType| {
+    matches!(media_type, MediaType::Jsx | MediaType::Tsx)
+  };
+  let entry_media_type = MediaType::from(entry_point);
+  let has_ts = modules.iter().any(|m| is_ts(m.media_type));
+  let has_jsx = modules.iter().any(|m| is_jsx(m.media_type));
+  if is_ts(entry_media_type) == has_ts && is_jsx(entry_media_type) == has_jsx {
+    return entry_point.to_path_buf();
+  }
+  let ext = match (has_ts, has_jsx) {
+    (true, true) => "tsx",
+    (true, false) => "ts",
+    (false, true) => "jsx",
+    (false, false) => "js",
+  };
+  with_extension(entry_point, ext)
+}
+
+struct BundleModule {
+  media_type: MediaType,
+  parsed_source: ParsedSource,
+  /// Modules in the bundle this module imports or re-exports from, in
+  /// the order they appear.
+  dependencies: Vec<usize>,
+  /// Local names of the imports of modules in the bundle.
+  imports: HashMap<String, ImportBinding>,
+  exports: HashMap<String, ExportEntry>,
+  star_exports: Vec<usize>,
+  /// Keys of the top level declarations that need a name in the bundle.
+  ///
+  /// These are the declared identifiers along with keys that start with
+  /// a `#` for the default export and re-exports of external modules.
+  declarations: Vec<Declaration>,
+  type_only_names: HashSet<String>,
+}
+
+struct Declaration {
+  key: String,
+  /// Name to use when it doesn't conflict with another declaration.
+  hint: String,
+}
+
+enum ImportBinding {
+  Named(usize, String),
+  Namespace(usize),
+}
+
+enum ExportEntry {
+  /// A top level declaration or import of the module by its key.
+  Local(String),
+  ReExport(usize, String),
+  Namespace(usize),
+}
+
+#[derive(Clone)]
+enum Binding {
+  Local(usize, String),
+  Namespace(usize),
+}
+
+struct Bundler<'a> {
+  files: &'a [OutputFile],
+  paths: Vec<PathBuf>,
+  module_indexes: HashMap<PathBuf, usize>,
+  modules: Vec<BundleModule>,
+  /// Names of the globals used by the modules, which can't be declared.
+  unresolved_names: HashSet<String>,
+  /// Every identifier used by the modules.
+  ident_names: HashSet<String>,
+  assigned_names: HashSet<String>,
+  names: HashMap<(usize, String), String>,
+  namespace_names: HashMap<usize, String>,
+}
+
+impl<'a> Bundler<'a> {
+  fn new(files: &'a [OutputFile]) -> Self {
+    Self {
+      files,
+      paths: Vec::new(),
+      module_indexes: HashMap::new(),
+      modules: Vec::new(),
+      unresolved_names: HashSet::new(),
+      ident_names: HashSet::new(),
+      assigned_names: HashSet::new(),
+      names: HashMap::new(),
+      namespace_names: HashMap::new(),
+    }
+  }
+
+  fn bundle(&mut self, entry_point: &Path) -> Result<String> {
+    self.get_module_index(entry_point.to_path_buf());
+    while self.modules.len() < self.paths.len() {
+      let module = self.analyze_module(self.modules.len())?;
+      self.modules.push(module);
+    }
+
+    self.validate_imports()?;
+
+    let order = self.get_evaluation_order();
+    self.assign_names(&order);
+
+    let mut module_texts = Vec::with_capacity(order.len());
+    for index in order.iter() {
+      module_texts.push(self.get_module_text(*index)?);
+    }
+    let export_specifiers = self.get_entry_export_specifiers()?;
+    let namespace_texts = self.get_namespace_texts();
+
+    let mut text = String::new();
+    for (index, module_text) in order.iter().zip(module_texts) {
+      let module_text = module_text.trim();
+      if !module_text.is_empty() {
+        text.push_str(module_text);
+        text.push('\n');
+      }
+      if let Some(namespace_text) = namespace_texts.get(index) {
+        text.push_str(namespace_text);
+        text.push('\n');
+      }
+    }
+    if !export_specifiers.is_empty() {
+      text.push_str("export { ");
+      text.push_str(&export_specifiers.join(", "));
+      text.push_str(" };\n");
+    }
+    Ok(text)
+  }
+
+  fn get_module_index(&mut self, path: PathBuf) -> usize {
+    if let Some(index) = self.module_indexes.get(&path) {
+      return *index;
+    }
+    let index = self.paths.len();
+    self.module_indexes.insert(path.clone(), index);
+    self.paths.push(path);
+    index
+  }
+
+  /// Resolves a specifier to the path of a code file in the output.
+  fn resolve_internal(
+    &self,
+    referrer: &Path,
+    specifier: &str,
+  ) -> Option<PathBuf> {
+    resolve_code_file(self.files, referrer, specifier)
+  }
+
+  fn analyze_module(&mut self, index: usize) -> Result<BundleModule> {
+    let path = self.paths[index].clone();
+    let file = self.files.iter().find(|f| f.file_path == path).unwrap();
+    let media_type = MediaType::from(path.as_path());
+    let parsed_source = parse_module(ParseParams {
+      specifier: path.to_string_lossy().to_string(),
+      text_info: SourceTextInfo::from_string(
+        strip_bom(&file.file_text).to_string(),
+      ),
+      media_type,
+      capture_tokens: false,
+      scope_analysis: true,
+      maybe_syntax: None,
+    })
+    .map_err(|diagnostic| {
+      anyhow!(
+        "Could not parse {} to bundle it. {}",
+        path.display(),
+        diagnostic
+      )
+    })?;
+
+    let mut module = BundleModule {
+      media_type,
+      parsed_source: parsed_source.clone(),
+      dependencies: Vec::new(),
+      imports: HashMap::new(),
+      exports: HashMap::new(),
+      star_exports: Vec::new(),
+      declarations: Vec::new(),
+      type_only_names: HashSet::new(),
+    };
+    parsed_source.with_view(|program| -> Result<()> {
+      fill_ident_names(
+        program.as_node(),
+        parsed_source.unresolved_context(),
+        &mut self.unresolved_names,
+        &mut self.ident_names,
+      );
+      if let Some(specifier) = find_internal_dynamic_import(
+        program.as_node(),
+        &|specifier| self.resolve_internal(&path, specifier).is_some(),
+      ) {
+        bail!(
+          "Could not bundle {} because it dynamically imports {}, which is in the bundle.",
+          path.display(),
+          specifier,
+        );
+      }
+      let body = match program {
+        Program::Module(module) => &module.body,
+        Program::Script(_) => return Ok(()),
+      };
+      let mut declarations =
+        get_top_level_decls(&program, parsed_source.top_level_context())
+          .into_iter()
+          .collect::<BTreeSet<_>>();
+      let mut synthetic_declarations = Vec::new();
+      for (item_index, item) in body.iter().enumerate() {
+        let module_decl = match item {
+          ModuleItem::ModuleDecl(module_decl) => module_decl,
+          ModuleItem::Stmt(Stmt::Decl(decl)) => {
+            if is_global_augmentation(decl) {
+              declarations.remove("global");
+            }
+            if is_type_only_decl(decl) {
+              module.type_only_names.extend(get_decl_names(decl));
+            }
+            continue;
+          }
+          ModuleItem::Stmt(_) => continue,
+        };
+        match module_decl {
+          ModuleDecl::Import(import_decl) => {
+            let dependency = match self
+              .resolve_internal(&path, import_decl.src.value())
+            {
+              Some(dependency_path) => self.get_module_index(dependency_path),
+              None => continue,
+            };
+            module.dependencies.push(dependency);
+            for specifier in import_decl.specifiers.iter() {
+              let (local, binding) = match specifier {
+                ImportSpecifier::Named(named) => (
+                  named.local,
+                  ImportBinding::Named(
+                    dependency,
+                    named
+                      .imported
+                      .map(get_module_export_name)
+                      .unwrap_or_else(|| named.local.sym().to_string()),
+                  ),
+                ),
+                ImportSpecifier::Default(default) => (
+                  default.local,
+                  ImportBinding::Named(dependency, "default".to_string()),
+                ),
+                ImportSpecifier::Namespace(namespace) => {
+                  (namespace.local, ImportBinding::Namespace(dependency))
+                }
+              };
+              declarations.remove(local.sym().as_ref());
+              module.imports.insert(local.sym().to_string(), binding);
+            }
+          }
+          ModuleDecl::ExportDecl(export_decl) => {
+            if is_type_only_decl(&export_decl.decl) {
+              module
+                .type_only_names
+                .extend(get_decl_names(&export_decl.decl));
+            }
+            for name in get_decl_names(&export_decl.decl) {
+              module
+                .exports
+                .insert(name.clone(), ExportEntry::Local(name));
+            }
+          }
+          ModuleDecl::ExportNamed(named_export) => {
+            let src = match named_export.src {
+              Some(src) => src,
+              None => {
+                for specifier in named_export.specifiers.iter() {
+                  if let ExportSpecifier::Named(named) = specifier {
+                    let orig = get_module_export_name(named.orig);
+                    let exported = named
+                      .exported
+                      .map(get_module_export_name)
+                      .unwrap_or_else(|| orig.clone());
+                    module.exports.insert(exported, ExportEntry::Local(orig));
+                  }
+                }
+                continue;
+              }
+            };
+            match self.resolve_internal(&path, src.value()) {
+              Some(dependency_path) => {
+                let dependency = self.get_module_index(dependency_path);
+                module.dependencies.push(dependency);
+                for specifier in named_export.specifiers.iter() {
+                  let (exported, entry) = match specifier {
+                    ExportSpecifier::Named(named) => {
+                      let orig = get_module_export_name(named.orig);
+                      (
+                        named
+                          .exported
+                          .map(get_module_export_name)
+                          .unwrap_or_else(|| orig.clone()),
+                        ExportEntry::ReExport(dependency, orig),
+                      )
+                    }
+                    ExportSpecifier::Namespace(namespace) => (
+                      get_module_export_name(namespace.name),
+                      ExportEntry::Namespace(dependency),
+                    ),
+                    ExportSpecifier::Default(default) => (
+                      default.exported.sym().to_string(),
+                      ExportEntry::ReExport(dependency, "default".to_string()),
+                    ),
+                  };
+                  module.exports.insert(exported, entry);
+                }
+              }
+              None => {
+                // re-exports of external modules are changed to imports
+                // so the bundle can export what they declare
+                for (specifier_index, specifier) in
+                  named_export.specifiers.iter().enumerate()
+                {
+                  let (exported, hint) = match specifier {
+                    ExportSpecifier::Named(named) => {
+                      let orig = get_module_export_name(named.orig);
+                      let exported = named
+                        .exported
+                        .map(get_module_export_name)
+                        .unwrap_or_else(|| orig.clone());
+                      (exported, orig)
+                    }
+                    ExportSpecifier::Namespace(namespace) => {
+                      let exported = get_module_export_name(namespace.name);
+                      (exported.clone(), exported)
+                    }
+                    ExportSpecifier::Default(default) => {
+                      let exported = default.exported.sym().to_string();
+                      (exported.clone(), exported)
+                    }
+                  };
+                  let key = get_re_export_key(item_index, specifier_index);
+                  module.exports.insert(exported, ExportEntry::Local(key.clone()));
+                  synthetic_declarations.push(Declaration {
+                    key,
+                    hint: to_identifier(&hint),
+                  });
+                }
+              }
+            }
+          }
+          ModuleDecl::ExportDefaultDecl(export_default_decl) => {
+            let name = match &export_default_decl.decl {
+              DefaultDecl::Class(class_expr) => {
+                class_expr.ident.map(|i| i.sym().to_string())
+              }
+              DefaultDecl::Fn(fn_expr) => {
+                fn_expr.ident.map(|i| i.sym().to_string())
+              }
+              DefaultDecl::TsInterfaceDecl(interface_decl) => {
+                let name = interface_decl.id.sym().to_string();
+                module.type_only_names.insert(name.clone());
+                Some(name)
+              }
+            };
+            let key = match name {
+              Some(name) => name,
+              None => {
+                synthetic_declarations.push(Declaration {
+                  key: DEFAULT_EXPORT_KEY.to_string(),
+                  hint: "_default".to_string(),
+                });
+                DEFAULT_EXPORT_KEY.to_string()
+              }
+            };
+            module
+              .exports
+              .insert("default".to_string(), ExportEntry::Local(key));
+          }
+          ModuleDecl::ExportDefaultExpr(_) => {
+            synthetic_declarations.push(Declaration {
+              key: DEFAULT_EXPORT_KEY.to_string(),
+              hint: "_default".to_string(),
+            });
+            module.exports.insert(
+              "default".to_string(),
+              ExportEntry::Local(DEFAULT_EXPORT_KEY.to_string()),
+            );
+          }
+          ModuleDecl::ExportAll(export_all) => {
+            match self.resolve_internal(&path, export_all.src.value()) {
+              Some(dependency_path) => {
+                let dependency = self.get_module_index(dependency_path);
+                module.dependencies.push(dependency);
+                module.star_exports.push(dependency);
+              }
+              // the entry point keeps these, but there's no way to know
+              // what names they export from any other module
+              None if index == 0 => {}
+              None => bail!(
+                "Could not bundle {} because it re-exports everything from {}, which is not in the bundle.",
+                path.display(),
+                export_all.src.value(),
+              ),
+            }
+          }
+          ModuleDecl::TsImportEquals(_) | ModuleDecl::TsExportAssignment(_) => {
+            bail!(
+              "Could not bundle {} because it uses a TypeScript import or export assignment.",
+              path.display(),
+            );
+          }
+          ModuleDecl::TsNamespaceExport(_) => {}
+        }
+      }
+
+      module.declarations = declarations
+        .into_iter()
+        .map(|name| Declaration {
+          key: name.clone(),
+          hint: name,
+        })
+        .chain(synthetic_declarations)
+        .collect();
+      Ok(())
+    })?;
+    Ok(module)
+  }
+
+  fn validate_imports(&self) -> Result<()> {
+    for (index, module) in self.modules.iter().enumerate() {
+      let mut imports = module.imports.iter().collect::<Vec<_>>();
+      imports.sort_by(|a, b| a.0.cmp(b.0));
+      for (_, binding) in imports {
+        if let ImportBinding::Named(dependency, name) = binding {
+          if self.resolve_import(binding, &mut HashSet::new()).is_none() {
+            bail!(
+              "Could not bundle {} because it imports {} from {}, which does not export it.",
+              self.paths[index].display(),
+              name,
+              self.paths[*dependency].display(),
+            );
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Gets the order the modules are evaluated in, which is the order
+  /// their code is output in.
+  fn get_evaluation_order(&self) -> Vec<usize> {
+    fn visit(
+      modules: &[BundleModule],
+      index: usize,
+      visited: &mut HashSet<usize>,
+      order: &mut Vec<usize>,
+    ) {
+      if !visited.insert(index) {
+        return;
+      }
+      for dependency in modules[index].dependencies.iter() {
+        visit(modules, *dependency, visited, order);
+      }
+      order.push(index);
+    }
+
+    let mut order = Vec::with_capacity(self.modules.len());
+    visit(&self.modules, 0, &mut HashSet::new(), &mut order);
+    order
+  }
+
+  /// Gives every top level declaration a unique name in the bundle. The
+  /// entry point's declarations are named first so that they're the
+  /// least likely to change.
+  fn assign_names(&mut self, order: &[usize]) {
+    let indexes = std::iter::once(0)
+      .chain(order.iter().copied().filter(|index| *index != 0))
+      .collect::<Vec<_>>();
+    for index in indexes {
+      for i in 0..self.modules[index].declarations.len() {
+        let declaration = &self.modules[index].declarations[i];
+        let key = declaration.key.clone();
+        let hint = declaration.hint.clone();
+        // a synthetic declaration's name can't be any identifier that's
+        // in use because it might be referenced from a nested scope
+        let can_use_hint = !self.unresolved_names.contains(&hint)
+          && !self.assigned_names.contains(&hint)
+          && (!key.starts_with('#') || !self.ident_names.contains(&hint));
+        let name = if can_use_hint {
+          self.assigned_names.insert(hint.clone());
+          hint
+        } else {
+          self.get_unique_name(&hint)
+        };
+        self.names.insert((index, key), name);
+      }
+    }
+  }
+
+  fn get_unique_name(&mut self, hint: &str) -> String {
+    let mut count = 1;
+    loop {
+      let name = format!("{}${}", hint, count);
+      if !self.unresolved_names.contains(&name)
+        && !self.assigned_names.contains(&name)
+        && !self.ident_names.contains(&name)
+      {
+        self.assigned_names.insert(name.clone());
+        return name;
+      }
+      count += 1;
+    }
+  }
+
+  fn get_namespace_name(&mut self, index: usize) -> String {
+    if let Some(name) = self.namespace_names.get(&index) {
+      return name.clone();
+    }
+    let hint = to_identifier(
+      &self.paths[index]
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default(),
+    );
+    let name = self.get_unique_name(&hint);
+    self.namespace_names.insert(index, name.clone());
+    name
+  }
+
+  fn get_binding_name(&mut self, binding: &Binding) -> String {
+    match binding {
+      Binding::Local(index, key) => self.names[&(*index, key.clone())].clone(),
+      Binding::Namespace(index) => self.get_namespace_name(*index),
+    }
+  }
+
+  fn resolve_export(
+    &self,
+    index: usize,
+    name: &str,
+    visited: &mut HashSet<(usize, String)>,
+  ) -> Option<Binding> {
+    if !visited.insert((index, name.to_string())) {
+      return None;
+    }
+    let module = &self.modules[index];
+    if let Some(entry) = module.exports.get(name) {
+      return match entry {
+        ExportEntry::Local(key) => match module.imports.get(key) {
+          Some(binding) => self.resolve_import(binding, visited),
+          None => Some(Binding::Local(index, key.clone())),
+        },
+        ExportEntry::ReExport(dependency, name) => {
+          self.resolve_export(*dependency, name, visited)
+        }
+        ExportEntry::Namespace(dependency) => {
+          Some(Binding::Namespace(*dependency))
+        }
+      };
+    }
+    if name == "default" {
+      return None;
+    }
+    module
+      .star_exports
+      .iter()
+      .find_map(|dependency| self.resolve_export(*dependency, name, visited))
+  }
+
+  fn resolve_import(
+    &self,
+    binding: &ImportBinding,
+    visited: &mut HashSet<(usize, String)>,
+  ) -> Option<Binding> {
+    match binding {
+      ImportBinding::Named(dependency, name) => {
+        self.resolve_export(*dependency, name, visited)
+      }
+      ImportBinding::Namespace(dependency) => {
+        Some(Binding::Namespace(*dependency))
+      }
+    }
+  }
+
+  fn get_export_names(
+    &self,
+    index: usize,
+    visited: &mut HashSet<usize>,
+  ) -> BTreeSet<String> {
+    let module = &self.modules[index];
+    let mut names = module.exports.keys().cloned().collect::<BTreeSet<_>>();
+    for dependency in module.star_exports.iter() {
+      if visited.insert(*dependency) {
+        names.extend(
+          self
+            .get_export_names(*dependency, visited)
+            .into_iter()
+            .filter(|name| name != "default"),
+        );
+      }
+    }
+    names
+  }
+
+  fn is_type_only_binding(&self, binding: &Binding) -> bool {
+    match binding {
+      Binding::Local(index, key) => {
+        self.modules[*index].type_only_names.contains(key)
+      }
+      Binding::Namespace(_) => false,
+    }
+  }
+
+  fn get_entry_export_specifiers(&mut self) -> Result<Vec<String>> {
+    let mut specifiers = Vec::new();
+    let mut visited = HashSet::from([0]);
+    for name in self.get_export_names(0, &mut visited) {
+      let binding = match self.resolve_export(0, &name, &mut HashSet::new()) {
+        Some(binding) => binding,
+        None => bail!(
+          "Could not bundle {} because the export {} could not be resolved.",
+          self.paths[0].display(),
+          name,
+        ),
+      };
+      let binding_name = self.get_binding_name(&binding);
+      if binding_name == name {
+        specifiers.push(name);
+      } else {
+        specifiers.push(format!("{} as {}", binding_name, name));
+      }
+    }
+    Ok(specifiers)
+  }
+
+  /// Gets the objects for the namespace imports that are used as values,
+  /// which are output after the code of the module they're for.
+  fn get_namespace_texts(&mut self) -> HashMap<usize, String> {
+    let mut texts = HashMap::new();
+    loop {
+      let index = match self
+        .namespace_names
+        .keys()
+        .filter(|index| !texts.contains_key(*index))
+        .min()
+      {
+        Some(index) => *index,
+        None => break,
+      };
+      let mut visited = HashSet::from([index]);
+      let mut members = Vec::new();
+      for name in self.get_export_names(index, &mut visited) {
+        let binding = self.resolve_export(index, &name, &mut HashSet::new());
+        if let Some(binding) = binding {
+          if !self.is_type_only_binding(&binding) {
+            let binding_name = self.get_binding_name(&binding);
+            members.push(format!(
+              "  get {}() {{ return {}; }},\n",
+              get_property_name_text(&name),
+              binding_name
+            ));
+          }
+        }
+      }
+      texts.insert(
+        index,
+        format!(
+          "const {} = Object.freeze({{\n  __proto__: null,\n{}}});",
+          self.namespace_names[&index],
+          members.concat(),
+        ),
+      );
+    }
+    texts
+  }
+
+  fn get_module_text(&mut self, index: usize) -> Result<String> {
+    let parsed_source = self.modules[index].parsed_source.clone();
+    let text_changes =
+      parsed_source.with_view(|program| -> Result<Vec<TextChange>> {
+        let mut context = ModuleTextContext {
+          bundler: self,
+          index,
+          text_info: program.text_info(),
+          top_level_context: parsed_source.top_level_context(),
+          text_changes: Vec::new(),
+        };
+        context.visit_program(program)?;
+        Ok(context.text_changes)
+      })?;
+    apply_text_changes(parsed_source.text_info().text_str(), text_changes)
+  }
+}
+
+struct ModuleTextContext<'a, 'b> {
+  bundler: &'a mut Bundler<'b>,
+  index: usize,
+  text_info: &'a SourceTextInfo,
+  top_level_context: SyntaxContext,
+  text_changes: Vec<TextChange>,
+}
+
+impl<'a, 'b> ModuleTextContext<'a, 'b> {
+  fn visit_program(&mut self, program: Program) -> Result<()> {
+    let body = match program {
+      Program::Module(module) => &module.body,
+      Program::Script(_) => {
+        self.visit_node(program.as_node())?;
+        return Ok(());
+      }
+    };
+    for (item_index, item) in body.iter().enumerate() {
+      let module_decl = match item {
+        ModuleItem::ModuleDecl(module_decl) => module_decl,
+        ModuleItem::Stmt(stmt) => {
+          self.visit_node(stmt.into())?;
+          continue;
+        }
+      };
+      match module_decl {
+        ModuleDecl::Import(import_decl) => {
+          if self.is_internal(import_decl.src.value()) {
+            self.remove(import_decl.range());
+          } else {
+            self.update_external_src(import_decl.src);
+            for specifier in import_decl.specifiers.iter() {
+              self.visit_node(specifier.into())?;
+            }
+          }
+        }
+        ModuleDecl::ExportDecl(export_decl) => {
+          self.remove(SourceRange::new(
+            export_decl.start(),
+            export_decl.decl.start(),
+          ));
+          self.visit_node((&export_decl.decl).into())?;
+        }
+        ModuleDecl::ExportNamed(named_export) => match named_export.src {
+          Some(src) if !self.is_internal(src.value()) => {
+            let mut import_specifiers = Vec::new();
+            let mut namespace_names = Vec::new();
+            for (specifier_index, specifier) in
+              named_export.specifiers.iter().enumerate()
+            {
+              let key = get_re_export_key(item_index, specifier_index);
+              let name = self.bundler.names[&(self.index, key)].clone();
+              match specifier {
+                ExportSpecifier::Named(named) => {
+                  import_specifiers.push(format!(
+                    "{} as {}",
+                    get_module_export_name_text(named.orig),
+                    name
+                  ));
+                }
+                ExportSpecifier::Namespace(_) => namespace_names.push(name),
+                ExportSpecifier::Default(_) => {
+                  import_specifiers.push(format!("default as {}", name))
+                }
+              }
+            }
+            let src_text = self.get_external_src_text(src);
+            let mut statements = Vec::new();
+            if !import_specifiers.is_empty() {
+              statements.push(format!(
+                "import {{ {} }} from {};",
+                import_specifiers.join(", "),
+                src_text
+              ));
+            }
+            for name in namespace_names {
+              statements
+                .push(format!("import * as {} from {};", name, src_text));
+            }
+            self.replace(named_export.range(), statements.join("\n"));
+          }
+          _ => self.remove(named_export.range()),
+        },
+        ModuleDecl::ExportDefaultDecl(export_default_decl) => {
+          let decl = &export_default_decl.decl;
+          let is_anonymous = match decl {
+            DefaultDecl::Class(class_expr) => class_expr.ident.is_none(),
+            DefaultDecl::Fn(fn_expr) => fn_expr.ident.is_none(),
+            DefaultDecl::TsInterfaceDecl(_) => false,
+          };
+          self.remove(SourceRange::new(
+            export_default_decl.start(),
+            decl.start(),
+          ));
+          if is_anonymous {
+            // name it in place instead of assigning it to a variable so
+            // it's still hoisted, which a circular import may rely on
+            let name = self.get_default_export_name();
+            let (range, is_fn) = self.get_anonymous_name_range(decl);
+            let new_text = if is_fn {
+              format!(" {}", name)
+            } else {
+              format!(" {} ", name)
+            };
+            self.replace(range, new_text);
+          }
+          self.visit_node(decl.into())?;
+        }
+        ModuleDecl::ExportDefaultExpr(export_default_expr) => {
+          let name = self.get_default_export_name();
+          self.replace(
+            SourceRange::new(
+              export_default_expr.start(),
+              export_default_expr.expr.start(),
+            ),
+            format!("const {} = ", name),
+          );
+          self.visit_node((&export_default_expr.expr).into())?;
+        }
+        ModuleDecl::ExportAll(export_all) => {
+          if self.is_internal(export_all.src.value()) {
+            self.remove(export_all.range());
+          } else {
+            self.update_external_src(export_all.src);
+          }
+        }
+        ModuleDecl::TsImportEquals(_)
+        | ModuleDecl::TsExportAssignment(_)
+        | ModuleDecl::TsNamespaceExport(_) => {}
+      }
+    }
+    Ok(())
+  }
+
+  fn visit_node(&mut self, node: Node) -> Result<()> {
+    match node {
+      Node::Ident(ident) => {
+        self.visit_ident(ident);
+        return Ok(());
+      }
+      Node::MemberExpr(member_expr) => {
+        if let (Expr::Ident(obj), MemberProp::Ident(prop)) =
+          (member_expr.obj, member_expr.prop)
+        {
+          if self.replace_namespace_member(obj, prop, member_expr.range()) {
+            return Ok(());
+          }
+        }
+      }
+      Node::TsQualifiedName(qualified_name) => {
+        if let TsEntityName::Ident(left) = qualified_name.left {
+          if self.replace_namespace_member(
+            left,
+            qualified_name.right,
+            qualified_name.range(),
+          ) {
+            return Ok(());
+          }
+        }
+      }
+      Node::CallExpr(call_expr)
+        if matches!(call_expr.callee, Callee::Import(_)) =>
+      {
+        if let Some(Expr::Lit(Lit::Str(src))) =
+          call_expr.args.get(0).map(|a| a.expr)
+        {
+          self.update_external_src(src);
+        }
+      }
+      _ => {}
+    }
+    for child in node.children() {
+      self.visit_node(child)?;
+    }
+    Ok(())
+  }
+
+  fn visit_ident(&mut self, ident: &Ident) {
+    if ident.ctxt() != self.top_level_context {
+      return;
+    }
+    let sym = ident.sym().to_string();
+    let new_name = match self.get_import_binding(&sym) {
+      Some(binding) => self.bundler.get_binding_name(&binding),
+      None => match self.bundler.names.get(&(self.index, sym.clone())) {
+        Some(name) => name.clone(),
+        None => return,
+      },
+    };
+    if new_name == sym {
+      return;
+    }
+    let new_text = match ident.as_node().parent() {
+      // shorthand properties, such as `{ a }` or `const { a } = b`
+      Some(Node::ObjectLit(_)) => format!("{}: {}", sym, new_name),
+      Some(Node::AssignPatProp(prop)) if prop.key.range() == ident.range() => {
+        format!("{}: {}", sym, new_name)
+      }
+      Some(Node::BindingIdent(binding_ident))
+        if matches!(
+          binding_ident.as_node().parent(),
+          Some(Node::AssignPatProp(_))
+        ) =>
+      {
+        format!("{}: {}", sym, new_name)
+      }
+      Some(Node::ImportNamedSpecifier(named)) if named.imported.is_none() => {
+        format!("{} as {}", sym, new_name)
+      }
+      _ => new_name,
+    };
+    self.replace(ident.range(), new_text);
+  }
+
+  /// Replaces a member of a namespace import (ex. `ns.member`) with the
+  /// name of what it refers to so the namespace object isn't necessary.
+  fn replace_namespace_member(
+    &mut self,
+    obj: &Ident,
+    prop: &Ident,
+    range: SourceRange,
+  ) -> bool {
+    if obj.ctxt() != self.top_level_context {
+      return false;
+    }
+    let namespace_index = match self.get_import_binding(obj.sym().as_ref()) {
+      Some(Binding::Namespace(index)) => index,
+      _ => return false,
+    };
+    let binding = self.bundler.resolve_export(
+      namespace_index,
+      prop.sym().as_ref(),
+      &mut HashSet::new(),
+    );
+    match binding {
+      Some(binding) => {
+        let name = self.bundler.get_binding_name(&binding);
+        self.replace(range, name);
+        true
+      }
+      None => false,
+    }
+  }
+
+  fn get_import_binding(&self, local_name: &str) -> Option<Binding> {
+    let import_binding =
+      self.bundler.modules[self.index].imports.get(local_name)?;
+    self
+      .bundler
+      .resolve_import(import_binding, &mut HashSet::new())
+  }
+
+  fn get_default_export_name(&self) -> String {
+    self.bundler.names[&(self.index, DEFAULT_EXPORT_KEY.to_string())].clone()
+  }
+
+  /// Gets the range of the whitespace after the `function` keyword (or
+  /// the `*` of a generator) or the `class` keyword of an anonymous
+  /// default export, which is where its name goes, and whether it's a
+  /// function.
+  fn get_anonymous_name_range(
+    &self,
+    decl: &DefaultDecl,
+  ) -> (SourceRange, bool) {
+    let (search_start, keyword) = match decl {
+      DefaultDecl::Class(class_expr) => (
+        // skip the decorators
+        class_expr
+          .class
+          .decorators
+          .last()
+          .map(|decorator| decorator.end())
+          .unwrap_or_else(|| class_expr.start()),
+        "class",
+      ),
+      DefaultDecl::Fn(fn_expr) => (fn_expr.start(), "function"),
+      DefaultDecl::TsInterfaceDecl(_) => {
+        unreachable!("interfaces are always named")
+      }
+    };
+    let range =
+      to_byte_range(self.text_info, SourceRange::new(search_start, decl.end()));
+    let text = &self.text_info.text_str()[range];
+    let mut name_start = text.find(keyword).unwrap() + keyword.len();
+    let is_fn = keyword == "function";
+    if is_fn {
+      let after_keyword = text[name_start..].trim_start();
+      if after_keyword.starts_with('*') {
+        name_start = text.len() - after_keyword.len() + 1;
+      }
+    }
+    let name_end = text.len() - text[name_start..].trim_start().len();
+    (
+      SourceRange::new(search_start + name_start, search_start + name_end),
+      is_fn,
+    )
+  }
+
+  fn is_internal(&self, specifier: &str) -> bool {
+    self
+      .bundler
+      .resolve_internal(&self.bundler.paths[self.index], specifier)
+      .is_some()
+  }
+
+  /// Gets the text of a specifier of a module that's not in the bundle,
+  /// which is relative to the entry point when it's a relative path.
+  fn get_external_src_text(&self, src: &Str) -> String {
+    let range = to_byte_range(self.text_info, src.range());
+    let text = &self.text_info.text_str()[range];
+    match self.get_external_relative_specifier(src.value()) {
+      Some(specifier) => {
+        let quote = &text[..1];
+        format!("{}{}{}", quote, specifier, quote)
+      }
+      None => text.to_string(),
+    }
+  }
+
+  fn update_external_src(&mut self, src: &Str) {
+    if self.get_external_relative_specifier(src.value()).is_some() {
+      let new_text = self.get_external_src_text(src);
+      self.replace(src.range(), new_text);
+    }
+  }
+
+  fn get_external_relative_specifier(&self, specifier: &str) -> Option<String> {
+    if self.index == 0 || !is_relative_specifier(specifier) {
+      return None;
+    }
+    let path = VirtualPath::new(&self.bundler.paths[self.index])
+      .parent()?
+      .join(specifier);
+    Some(get_relative_path(
+      &self.bundler.paths[0],
+      path.to_path_buf(),
+    ))
+  }
+
+  fn remove(&mut self, range: SourceRange) {
+    self.replace(range, String::new());
+  }
+
+  fn replace(&mut self, range: SourceRange, new_text: String) {
+    self.text_changes.push(TextChange {
+      range: to_byte_range(self.text_info, range),
+      new_text,
+    });
+  }
+}
+
+pub(crate) fn is_relative_specifier(specifier: &str) -> bool {
+  specifier.starts_with("./") || specifier.starts_with("../")
+}
+
+pub(crate) fn is_code_file(path: &Path) -> bool {
+  matches!(
+    MediaType::from(path),
+    MediaType::JavaScript
+      | MediaType::Mjs
+      | MediaType::Jsx
+      | MediaType::TypeScript
+      | MediaType::Mts
+      | MediaType::Cts
+      | MediaType::Tsx
+  )
+}
+
+fn get_re_export_key(item_index: usize, specifier_index: usize) -> String {
+  format!("#{}:{}", item_index, specifier_index)
+}
+
+pub(crate) fn get_module_export_name(name: ModuleExportName) -> String {
+  match name {
+    ModuleExportName::Ident(ident) => ident.sym().to_string(),
+    ModuleExportName::Str(name) => name.value().to_string(),
+  }
+}
+
+pub(crate) fn get_module_export_name_text(name: ModuleExportName) -> String {
+  match name {
+    ModuleExportName::Ident(ident) => ident.sym().to_string(),
+    ModuleExportName::Str(name) => format!("{:?}", name.value().as_ref()),
+  }
+}
+
+fn get_property_name_text(name: &str) -> String {
+  if to_identifier(name) == name {
+    name.to_string()
+  } else {
+    format!("{:?}", name)
+  }
+}
+
+/// Converts text to a valid identifier for use as a name hint.
+fn to_identifier(text: &str) -> String {
+  let mut result = text
+    .chars()
+    .map(|c| {
+      if c.is_ascii_alphanumeric() || c == '_' || c == '$' {
+        c
+      } else {
+        '_'
+      }
+    })
+    .collect::<String>();
+  if result.is_empty() || result.chars().next().unwrap().is_ascii_digit() {
+    result.insert(0, '_');
+  }
+  result
+}
+
+fn fill_ident_names(
+  node: Node,
+  unresolved_context: SyntaxContext,
+  unresolved_names: &mut HashSet<String>,
+  ident_names: &mut HashSet<String>,
+) {
+  if let Node::Ident(ident) = node {
+    if ident.ctxt() == unresolved_context {
+      unresolved_names.insert(ident.sym().to_string());
+    }
+    ident_names.insert(ident.sym().to_string());
+  }
+  for child in node.children() {
+    fill_ident_names(child, unresolved_context, unresolved_names, ident_names);
+  }
+}
+
+fn find_internal_dynamic_import(
+  node: Node,
+  is_internal: &impl Fn(&str) -> bool,
+) -> Option<String> {
+  if let Node::CallExpr(call_expr) = node {
+    if matches!(call_expr.callee, Callee::Import(_)) {
+      if let Some(Expr::Lit(Lit::Str(src))) =
+        call_expr.args.get(0).map(|a| a.expr)
+      {
+        if is_internal(src.value()) {
+          return Some(src.value().to_string());
+        }
+      }
+    }
+  }
+  node
+    .children()
+    .into_iter()
+    .find_map(|child| find_internal_dynamic_import(child, is_internal))
+}
+
+fn is_global_augmentation(decl: &Decl) -> bool {
+  matches!(decl, Decl::TsModule(module_decl) if module_decl.inner.global)
+}
+
+pub(crate) fn is_type_only_decl(decl: &Decl) -> bool {
+  matches!(decl, Decl::TsInterface(_) | Decl::TsTypeAlias(_))
+}
+
+pub(crate) fn get_decl_names(decl: &Decl) -> Vec<String> {
+  let mut names = Vec::new();
+  match decl {
+    Decl::Class(class_decl) => names.push(class_decl.ident.sym().to_string()),
+    Decl::Fn(fn_decl) => names.push(fn_decl.ident.sym().to_string()),
+    Decl::Var(var_decl) => {
+      for declarator in var_decl.decls.iter() {
+        fill_pat_names(declarator.name, &mut names);
+      }
+    }
+    Decl::TsInterface(interface_decl) => {
+      names.push(interface_decl.id.sym().to_string())
+    }
+    Decl::TsTypeAlias(type_alias_decl) => {
+      names.push(type_alias_decl.id.sym().to_string())
+    }
+    Decl::TsEnum(enum_decl) => names.push(enum_decl.id.sym().to_string()),
+    Decl::TsModule(module_decl) => {
+      if let TsModuleName::Ident(ident) = module_decl.id {
+        if !module_decl.inner.global {
+          names.push(ident.sym().to_string());
+        }
+      }
+    }
+  }
+  names
+}
+
+fn fill_pat_names(pat: Pat, names: &mut Vec<String>) {
+  match pat {
+    Pat::Ident(binding_ident) => names.push(binding_ident.id.sym().to_string()),
+    Pat::Array(array_pat) => {
+      for elem in array_pat.elems.iter().flatten() {
+        fill_pat_names(*elem, names);
+      }
+    }
+    Pat::Rest(rest_pat) => fill_pat_names(rest_pat.arg, names),
+    Pat::Object(object_pat) => {
+      for prop in object_pat.props.iter() {
+        match prop {
+          ObjectPatProp::KeyValue(key_value) => {
+            fill_pat_names(key_value.value, names)
+          }
+          ObjectPatProp::Assign(assign) => {
+            names.push(assign.key.sym().to_string())
+          }
+          ObjectPatProp::Rest(rest_pat) => fill_pat_names(rest_pat.arg, names),
+        }
+      }
+    }
+    Pat::Assign(assign_pat) => fill_pat_names(assign_pat.left, names),
+    Pat::Invalid(_) | Pat::Expr(_) => {}
+  }
+}
@@ -0,0 +1,624 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use deno_ast::MediaType;
+use deno_ast::ModuleSpecifier;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::CommentDirectiveAction;
+use crate::CommentDirectiveOptions;
+use crate::CommonJsModules;
+use crate::DenoFeatureDetection;
+use crate::Dependency;
+use crate::GlobalName;
+use crate::ImportAttributeAction;
+use crate::ImportAttributeOptions;
+use crate::JsxOptions;
+use crate::JsxRuntime;
+use crate::LocalRoot;
+use crate::MappedSpecifier;
+use crate::ModuleShim;
+use crate::NodeGlobalShims;
+use crate::PackageMappedSpecifier;
+use crate::PackageShim;
+use crate::QualifiedGlobalAccess;
+use crate::RelativeSpecifierExtension;
+use crate::RemotePolicy;
+use crate::ServerShim;
+use crate::Shim;
+use crate::ShimImportStyle;
+use crate::SizeBudget;
+use crate::TestMainImports;
+use crate::VersionPolicy;
+
+/// A transformed file stored in the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedFile {
+  pub file_text: String,
+  pub warnings: Vec<String>,
+  /// Indexes of the found polyfills in `polyfills_for_target`.
+  pub polyfill_indexes: Vec<usize>,
+  /// Names of the globals that were replaced with their shims.
+  pub used_shim_globals: Vec<String>,
+  /// The relative specifiers the module specifiers were rewritten to.
+  pub relative_specifiers: Vec<String>,
+  /// Name, line number, and column number of each usage of an API that
+  /// can't be shimmed.
+  pub unshimmable_api_usages: Vec<(String, usize, usize)>,
+  /// If the file references the DOM libs or uses their globals.
+  pub uses_dom_apis: bool,
+}
+
+/// Stores the transformed files in a directory so that they can be
+/// reused by later transforms.
+pub struct TransformCache {
+  dir: PathBuf,
+}
+
+impl TransformCache {
+  pub fn new(dir: PathBuf) -> Self {
+    Self { dir }
+  }
+
+  /// Gets a cached file. Entries that can't be read or that were stored
+  /// for a different source are treated as missing.
+  pub fn get(&self, key: &str, source_hash: &str) -> Option<CachedFile> {
+    let text = std::fs::read_to_string(self.entry_path(key)).ok()?;
+    let (entry_source_hash, text) = text.split_once('\n')?;
+    if entry_source_hash != source_hash {
+      return None;
+    }
+    deserialize(text)
+  }
+
+  pub fn set(
+    &self,
+    key: &str,
+    source_hash: &str,
+    file: &CachedFile,
+  ) -> Result<()> {
+    let write = || -> std::io::Result<()> {
+      std::fs::create_dir_all(&self.dir)?;
+      // write to a temporary file first so that a partially written
+      // entry is never read
+      let entry_path = self.entry_path(key);
+      let temp_path = entry_path.with_extension("tmp");
+      std::fs::write(
+        &temp_path,
+        format!("{}\n{}", source_hash, serialize(file)),
+      )?;
+      std::fs::rename(&temp_path, &entry_path)
+    };
+    write().with_context(|| {
+      format!("Error writing to cache directory {}", self.dir.display())
+    })
+  }
+
+  fn entry_path(&self, key: &str) -> PathBuf {
+    self.dir.join(key)
+  }
+}
+
+/// Changed whenever the format of the entries changes so that entries
+/// in the old format are never read.
+const ENTRY_FORMAT_VERSION: u8 = 5;
+
+/// Gets the hex encoded SHA-256 hash of a file's source, which is stored
+/// in its cache entry.
+pub fn get_source_hash(source: &str) -> String {
+  to_hex(&Sha256::digest(source.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Hashes the inputs of a file transform into a cache key.
+///
+/// The values are written in a canonical form and hashed with SHA-256, so
+/// the keys are the same across platforms and toolchains.
+pub struct CacheKeyHasher(Sha256);
+
+impl Default for CacheKeyHasher {
+  fn default() -> Self {
+    let mut hasher = Self(Sha256::new());
+    // the output may change between versions
+    hasher
+      .write(env!("CARGO_PKG_VERSION"))
+      .write(ENTRY_FORMAT_VERSION);
+    hasher
+  }
+}
+
+impl CacheKeyHasher {
+  pub fn write(&mut self, value: impl CacheKeyValue) -> &mut Self {
+    value.write_key(self);
+    self
+  }
+
+  /// Writes the bytes prefixed with their length, so that consecutive
+  /// values can't run into each other (ex. `"ab", "c"` and `"a", "bc"`).
+  fn write_bytes(&mut self, bytes: &[u8]) {
+    self.0.update((bytes.len() as u64).to_le_bytes());
+    self.0.update(bytes);
+  }
+
+  /// Gets the hex encoded hash.
+  pub fn finish(self) -> String {
+    to_hex(&self.0.finalize())
+  }
+}
+
+/// A value that's written to a cache key in a canonical form, which
+/// doesn't depend on the `Debug` output or `Hash` implementation of the
+/// value.
+pub trait CacheKeyValue {
+  fn write_key(&self, hasher: &mut CacheKeyHasher);
+}
+
+impl<T: CacheKeyValue + ?Sized> CacheKeyValue for &T {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    (**self).write_key(hasher);
+  }
+}
+
+impl CacheKeyValue for str {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write_bytes(self.as_bytes());
+  }
+}
+
+impl CacheKeyValue for String {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    self.as_str().write_key(hasher);
+  }
+}
+
+impl CacheKeyValue for bool {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write_bytes(&[*self as u8]);
+  }
+}
+
+impl CacheKeyValue for u8 {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write_bytes(&[*self]);
+  }
+}
+
+impl CacheKeyValue for usize {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write_bytes(&(*self as u64).to_le_bytes());
+  }
+}
+
+impl CacheKeyValue for ModuleSpecifier {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    self.as_str().write_key(hasher);
+  }
+}
+
+impl CacheKeyValue for Path {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    // the output paths use forward slashes on every platform
+    self.to_string_lossy().replace('\\', "/").write_key(hasher);
+  }
+}
+
+impl CacheKeyValue for PathBuf {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    self.as_path().write_key(hasher);
+  }
+}
+
+impl<T: CacheKeyValue> CacheKeyValue for Option<T> {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    match self {
+      Some(value) => hasher.write(true).write(value),
+      None => hasher.write(false),
+    };
+  }
+}
+
+impl<T: CacheKeyValue> CacheKeyValue for [T] {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write(self.len());
+    for value in self {
+      hasher.write(value);
+    }
+  }
+}
+
+impl<T: CacheKeyValue> CacheKeyValue for Vec<T> {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    self.as_slice().write_key(hasher);
+  }
+}
+
+impl<T: CacheKeyValue> CacheKeyValue for BTreeSet<T> {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write(self.len());
+    for value in self {
+      hasher.write(value);
+    }
+  }
+}
+
+impl<K: CacheKeyValue, V: CacheKeyValue> CacheKeyValue for BTreeMap<K, V> {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write(self.len());
+    for (key, value) in self {
+      hasher.write(key).write(value);
+    }
+  }
+}
+
+/// Implements `CacheKeyValue` for enums without fields by writing the name
+/// of the variant, so that reordering the variants doesn't change the keys.
+macro_rules! impl_cache_key_value_for_unit_enums {
+  ($($enum_name:ident { $($variant:ident),* $(,)? })*) => {
+    $(
+      impl CacheKeyValue for $enum_name {
+        fn write_key(&self, hasher: &mut CacheKeyHasher) {
+          hasher.write(match self {
+            $($enum_name::$variant => stringify!($variant),)*
+          });
+        }
+      }
+    )*
+  };
+}
+
+impl_cache_key_value_for_unit_enums! {
+  MediaType {
+    JavaScript, Jsx, Mjs, Cjs, TypeScript, Mts, Cts, Dts, Dmts, Dcts, Tsx,
+    Json, Wasm, TsBuildInfo, SourceMap, Unknown,
+  }
+  JsxRuntime { Classic, Automatic, AutomaticDev, Precompile }
+  CommonJsModules { Error, Wrap }
+  CommentDirectiveAction { Keep, Remove, Rewrite }
+  ImportAttributeAction { Strip, With, Keep }
+  ShimImportStyle { Namespace, Named, SideEffect }
+  DenoFeatureDetection { Native, Shim }
+  QualifiedGlobalAccess { GlobalObject, Shim, Preserve }
+  RelativeSpecifierExtension { Js, Preserve, Strip }
+  VersionPolicy { Preserve, Exact, Caret, Tilde }
+}
+
+impl CacheKeyValue for JsxOptions {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write(self.runtime).write(&self.import_source);
+  }
+}
+
+impl CacheKeyValue for Dependency {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher
+      .write(&self.name)
+      .write(&self.version)
+      .write(self.peer_dependency);
+  }
+}
+
+impl CacheKeyValue for PackageMappedSpecifier {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher
+      .write(&self.name)
+      .write(&self.version)
+      .write(&self.sub_path)
+      .write(self.peer_dependency)
+      .write(&self.types_package);
+  }
+}
+
+impl CacheKeyValue for MappedSpecifier {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    match self {
+      MappedSpecifier::Package(package) => {
+        hasher.write("package").write(package)
+      }
+      MappedSpecifier::Module(specifier) => {
+        hasher.write("module").write(specifier)
+      }
+    };
+  }
+}
+
+impl CacheKeyValue for GlobalName {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher
+      .write(&self.name)
+      .write(&self.export_name)
+      .write(self.type_only);
+  }
+}
+
+impl CacheKeyValue for PackageShim {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher
+      .write(&self.package)
+      .write(&self.types_package)
+      .write(&self.global_names);
+  }
+}
+
+impl CacheKeyValue for ModuleShim {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write(&self.module).write(&self.global_names);
+  }
+}
+
+impl CacheKeyValue for Shim {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    match self {
+      Shim::Package(shim) => hasher.write("package").write(shim),
+      Shim::Module(shim) => hasher.write("module").write(shim),
+    };
+  }
+}
+
+impl CacheKeyValue for NodeGlobalShims {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write(&self.buffer).write(&self.process);
+  }
+}
+
+impl CacheKeyValue for ServerShim {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write(&self.package).write(&self.export_names);
+  }
+}
+
+impl CacheKeyValue for CommentDirectiveOptions {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher
+      .write(self.deno_types)
+      .write(self.ts_self_types)
+      .write(self.deno_lint_ignore)
+      .write(self.deno_fmt_ignore)
+      .write(&self.eslint_rules);
+  }
+}
+
+impl CacheKeyValue for ImportAttributeOptions {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write(&self.types).write(self.default);
+  }
+}
+
+impl CacheKeyValue for TestMainImports {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    match self {
+      TestMainImports::Relative => hasher.write("relative"),
+      TestMainImports::SelfReference(package_name) => {
+        hasher.write("selfReference").write(package_name)
+      }
+    };
+  }
+}
+
+impl CacheKeyValue for RemotePolicy {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher
+      .write(&self.allowed_origins)
+      .write(&self.denied_origins)
+      .write(self.max_depth);
+  }
+}
+
+impl CacheKeyValue for LocalRoot {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher.write(&self.dir).write(&self.output_dir);
+  }
+}
+
+impl CacheKeyValue for SizeBudget {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    hasher
+      .write(self.max_total_bytes)
+      .write(self.max_file_bytes);
+  }
+}
+
+// Format:
+// <comma separated used shim global names>
+// <comma separated polyfill indexes>
+// <1 if the file uses DOM APIs, otherwise 0>
+// <unshimmable api usage count>
+// <api name> <line number> <column number>
+// ...
+// <warning count>
+// <byte length of warning>
+// <warning text>
+// ...
+// <file text>
+fn serialize(file: &CachedFile) -> String {
+  let mut text = String::new();
+  text.push_str(&file.used_shim_globals.join(","));
+  text.push('\n');
+  text.push_str(
+    &file
+      .polyfill_indexes
+      .iter()
+      .map(|i| i.to_string())
+      .collect::<Vec<_>>()
+      .join(","),
+  );
+  text.push('\n');
+  text.push_str(if file.uses_dom_apis { "1\n" } else { "0\n" });
+  text.push_str(&format!("{}\n", file.relative_specifiers.len()));
+  for relative_specifier in file.relative_specifiers.iter() {
+    text.push_str(&format!("{}\n", relative_specifier));
+  }
+  text.push_str(&format!("{}\n", file.unshimmable_api_usages.len()));
+  for (api, line_number, column_number) in file.unshimmable_api_usages.iter() {
+    text.push_str(&format!("{} {} {}\n", api, line_number, column_number));
+  }
+  text.push_str(&format!("{}\n", file.warnings.len()));
+  for warning in file.warnings.iter() {
+    text.push_str(&format!("{}\n{}", warning.len(), warning));
+  }
+  text.push_str(&file.file_text);
+  text
+}
+
+fn deserialize(text: &str) -> Option<CachedFile> {
+  fn take_line(text: &str) -> Option<(&str, &str)> {
+    text.split_once('\n')
+  }
+
+  let (used_shim_globals, text) = take_line(text)?;
+  let used_shim_globals = if used_shim_globals.is_empty() {
+    Vec::new()
+  } else {
+    used_shim_globals
+      .split(',')
+      .map(ToOwned::to_owned)
+      .collect::<Vec<_>>()
+  };
+  let (polyfill_indexes, text) = take_line(text)?;
+  let polyfill_indexes = if polyfill_indexes.is_empty() {
+    Vec::new()
+  } else {
+    polyfill_indexes
+      .split(',')
+      .map(|i| i.parse().ok())
+      .collect::<Option<Vec<usize>>>()?
+  };
+  let (uses_dom_apis, text) = take_line(text)?;
+  let uses_dom_apis = match uses_dom_apis {
+    "1" => true,
+    "0" => false,
+    _ => return None,
+  };
+  let (specifier_count, mut text) = take_line(text)?;
+  let specifier_count = specifier_count.parse::<usize>().ok()?;
+  let mut relative_specifiers = Vec::with_capacity(specifier_count);
+  for _ in 0..specifier_count {
+    let (relative_specifier, remaining) = take_line(text)?;
+    relative_specifiers.push(relative_specifier.to_string());
+    text = remaining;
+  }
+  let (usage_count, mut text) = take_line(text)?;
+  let usage_count = usage_count.parse::<usize>().ok()?;
+  let mut unshimmable_api_usages = Vec::with_capacity(usage_count);
+  for _ in 0..usage_count {
+    let (line, remaining) = take_line(text)?;
+    let mut parts = line.split(' ');
+    let api = parts.next()?.to_string();
+    let line_number = parts.next()?.parse().ok()?;
+    let column_number = parts.next()?.parse().ok()?;
+    unshimmable_api_usages.push((api, line_number, column_number));
+    text = remaining;
+  }
+  let (warning_count, mut text) = take_line(text)?;
+  let warning_count = warning_count.parse::<usize>().ok()?;
+  let mut warnings = Vec::new();
+  for _ in 0..warning_count {
+    let (len, remaining) = take_line(text)?;
+    let len = len.parse::<usize>().ok()?;
+    warnings.push(remaining.get(..len)?.to_string());
+    text = remaining.get(len..)?;
+  }
+
+  Some(CachedFile {
+    file_text: text.to_string(),
+    warnings,
+    polyfill_indexes,
+    used_shim_globals,
+    relative_specifiers,
+    unshimmable_api_usages,
+    uses_dom_apis,
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn serializes_and_deserializes() {
+    let file = CachedFile {
+      file_text: "const a = 5;\n\nexport { a };\n".to_string(),
+      warnings: vec!["first\nwarning".to_string(), String::new()],
+      polyfill_indexes: vec![0, 3],
+      used_shim_globals: vec!["Deno".to_string(), "Deno.serve".to_string()],
+      relative_specifiers: vec![
+        "./a.js".to_string(),
+        "../deps/localhost/mod.js".to_string(),
+      ],
+      unshimmable_api_usages: vec![
+        ("Deno.dlopen".to_string(), 1, 5),
+        ("Deno.Command".to_string(), 10, 1),
+      ],
+      uses_dom_apis: true,
+    };
+    assert_eq!(deserialize(&serialize(&file)), Some(file));
+
+    let file = CachedFile {
+      file_text: String::new(),
+      warnings: Vec::new(),
+      polyfill_indexes: Vec::new(),
+      used_shim_globals: Vec::new(),
+      relative_specifiers: Vec::new(),
+      unshimmable_api_usages: Vec::new(),
+      uses_dom_apis: false,
+    };
+    assert_eq!(deserialize(&serialize(&file)), Some(file));
+  }
+
+  #[test]
+  fn cache_key_separates_values() {
+    let get_key = |first: &str, second: &str| {
+      let mut hasher = CacheKeyHasher::default();
+      hasher.write(first).write(second);
+      hasher.finish()
+    };
+    assert_eq!(get_key("ab", "c"), get_key("ab", "c"));
+    assert_ne!(get_key("ab", "c"), get_key("a", "bc"));
+    assert_eq!(get_key("a", "b").len(), 64);
+  }
+
+  #[test]
+  fn get_checks_source_hash() {
+    let dir = std::env::temp_dir().join(format!(
+      "dnt_cache_get_checks_source_hash_{}",
+      std::process::id()
+    ));
+    let cache = TransformCache::new(dir.clone());
+    let file = CachedFile {
+      file_text: "export const a = 5;\n".to_string(),
+      warnings: Vec::new(),
+      polyfill_indexes: Vec::new(),
+      used_shim_globals: Vec::new(),
+      relative_specifiers: Vec::new(),
+      unshimmable_api_usages: Vec::new(),
+      uses_dom_apis: false,
+    };
+    let source_hash = get_source_hash("export const a = 5;\n");
+    cache.set("key", &source_hash, &file).unwrap();
+    assert_eq!(cache.get("key", &source_hash), Some(file));
+    assert_eq!(
+      cache.get("key", &get_source_hash("export const a = 6;\n")),
+      None
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn deserialize_invalid() {
+    assert_eq!(deserialize(""), None);
+    assert_eq!(deserialize("2\n\n0\n0\n"), None);
+    assert_eq!(deserialize("0\n\n2\n0\n"), None);
+    assert_eq!(deserialize("0\n\n0\n2\n./a.js\n"), None);
+    assert_eq!(deserialize("0\n\n0\n0\n0\n1\n10\nshort"), None);
+    assert_eq!(deserialize("0\n\n0\n0\n1\nDeno.run 1\n0\n"), None);
+  }
+}
@@ -0,0 +1,83 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use deno_ast::ModuleSpecifier;
+
+use crate::graph::ModuleGraph;
+
+enum VisitState {
+  InProgress,
+  Done,
+}
+
+/// Gets the circular code dependencies between the provided modules.
+///
+/// Each cycle starts and ends with the same specifier.
+pub fn get_cycles<'a>(
+  module_graph: &ModuleGraph,
+  modules: impl Iterator<Item = &'a ModuleSpecifier>,
+) -> Vec<Vec<ModuleSpecifier>> {
+  let mut modules = modules.collect::<Vec<_>>();
+  modules.sort();
+  let module_set = modules.iter().copied().collect::<HashSet<_>>();
+  let get_dependencies = |specifier: &ModuleSpecifier| {
+    module_graph
+      .get(specifier)
+      .dependencies
+      .values()
+      .filter_map(|dep| dep.get_code())
+      .map(|specifier| module_graph.resolve(specifier))
+      .filter(|specifier| module_set.contains(specifier))
+      .collect::<Vec<_>>()
+  };
+
+  let mut cycles = Vec::new();
+  let mut states = HashMap::new();
+  for root in modules.iter() {
+    if states.contains_key(*root) {
+      continue;
+    }
+
+    // iterative to not overflow the stack on large graphs
+    states.insert((*root).clone(), VisitState::InProgress);
+    let mut stack = vec![((*root).clone(), get_dependencies(*root), 0)];
+    while !stack.is_empty() {
+      let next_dependency = {
+        let (_, dependencies, index) = stack.last_mut().unwrap();
+        let next_dependency = dependencies.get(*index).cloned();
+        *index += 1;
+        next_dependency
+      };
+      match next_dependency {
+        Some(dependency) => match states.get(&dependency) {
+          None => {
+            states.insert(dependency.clone(), VisitState::InProgress);
+            let dependencies = get_dependencies(&dependency);
+            stack.push((dependency, dependencies, 0));
+          }
+          Some(VisitState::InProgress) => {
+            let start = stack
+              .iter()
+              .position(|(specifier, _, _)| *specifier == dependency)
+              .unwrap();
+            let mut cycle = stack[start..]
+              .iter()
+              .map(|(specifier, _, _)| specifier.clone())
+              .collect::<Vec<_>>();
+            cycle.push(dependency);
+            cycles.push(cycle);
+          }
+          Some(VisitState::Done) => {}
+        },
+        None => {
+          let (specifier, _, _) = stack.pop().unwrap();
+          states.insert(specifier, VisitState::Done);
+        }
+      }
+    }
+  }
+
+  cycles
+}
@@ -1,6 +1,7 @@
 // Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
 
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::collections::HashSet;
 
 use anyhow::Result;
@@ -10,6 +11,7 @@ use deno_graph::ModuleKind;
 use deno_graph::Resolved;
 
 use crate::graph::ModuleGraph;
+use crate::trace::log_debug;
 use crate::PackageMappedSpecifier;
 
 #[derive(Debug)]
@@ -31,6 +33,7 @@ pub fn resolve_declaration_file_mappings(
   module_graph: &ModuleGraph,
   modules: &[&Module],
   mapped_specifiers: &BTreeMap<ModuleSpecifier, PackageMappedSpecifier>,
+  declaration_mappings: &HashMap<ModuleSpecifier, ModuleSpecifier>,
 ) -> Result<BTreeMap<ModuleSpecifier, DeclarationFileResolution>> {
   let mut type_dependencies = BTreeMap::new();
 
@@ -38,6 +41,24 @@ pub fn resolve_declaration_file_mappings(
     fill_types_for_module(module_graph, module, &mut type_dependencies)?;
   }
 
+  // add the declaration files the user specified, which take precedence
+  let mut user_declarations = HashMap::new();
+  for (code_specifier, types_specifier) in declaration_mappings.iter() {
+    let code_specifier = module_graph.resolve(code_specifier);
+    if !modules.iter().any(|m| m.specifier == code_specifier) {
+      continue;
+    }
+    let types_specifier = module_graph.resolve(types_specifier);
+    type_dependencies
+      .entry(code_specifier.clone())
+      .or_insert_with(HashSet::new)
+      .insert(TypesDependency {
+        specifier: types_specifier.clone(),
+        referrer: code_specifier.clone(),
+      });
+    user_declarations.insert(code_specifier, types_specifier);
+  }
+
   // get the resolved type dependencies
   let mut mappings = BTreeMap::new();
   for (code_specifier, deps) in type_dependencies.into_iter() {
@@ -47,8 +68,14 @@ pub fn resolve_declaration_file_mappings(
     }
 
     let deps = deps.into_iter().collect::<Vec<_>>();
-    let selected_dep =
-      select_best_types_dep(module_graph, &code_specifier, &deps);
+    let selected_dep = match user_declarations.get(&code_specifier) {
+      Some(types_specifier) => deps
+        .iter()
+        .find(|d| &d.specifier == types_specifier)
+        .unwrap()
+        .clone(),
+      None => select_best_types_dep(module_graph, &code_specifier, &deps),
+    };
 
     // get the declaration file specifiers that weren't used
     let mut ignored = deps
@@ -57,6 +84,25 @@ pub fn resolve_declaration_file_mappings(
       .collect::<Vec<_>>();
     ignored.sort();
 
+    if ignored.is_empty() {
+      log_debug!(
+        "Selected {} as the declaration file of {}",
+        selected_dep.specifier,
+        code_specifier
+      );
+    } else {
+      log_debug!(
+        "Selected {} as the declaration file of {} over {}",
+        selected_dep.specifier,
+        code_specifier,
+        ignored
+          .iter()
+          .map(|d| d.specifier.as_str())
+          .collect::<Vec<_>>()
+          .join(", ")
+      );
+    }
+
     mappings.insert(
       code_specifier,
       DeclarationFileResolution {
@@ -126,7 +172,8 @@ fn fill_types_for_module(
   module: &Module,
   type_dependencies: &mut BTreeMap<ModuleSpecifier, HashSet<TypesDependency>>,
 ) -> Result<()> {
-  // check for the module specifying its type dependency
+  // check for the module specifying its type dependency (ex. with a types
+  // reference, `@ts-self-types`, or the `X-TypeScript-Types` header)
   match &module.maybe_types_dependency {
     Some((text, Resolved::Err(err))) => anyhow::bail!(
       "Error resolving types for {} with reference {}. {}",
@@ -151,7 +198,7 @@ fn fill_types_for_module(
     _ => {}
   }
 
-  // find any @deno-types
+  // find any @deno-types (the analyzer treats @ts-types the same)
   for dep in module.dependencies.values() {
     if let Some(type_dep) = dep.get_type() {
       if let Some(code_dep) = dep.get_code() {
@@ -180,7 +227,7 @@ fn fill_types_for_module(
   }
 }
 
-fn is_declaration_file(media_type: deno_ast::MediaType) -> bool {
+pub fn is_declaration_file(media_type: deno_ast::MediaType) -> bool {
   // todo: use media_type.is_declaration() in deno_ast once available
   use deno_ast::MediaType::*;
   match media_type {
@@ -0,0 +1,93 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use deno_ast::ModuleSpecifier;
+
+use crate::graph::ModuleGraph;
+use crate::mappings::Mappings;
+use crate::provenance::find_version_segment;
+use crate::provenance::get_package_name_and_version;
+use crate::specifiers::Specifiers;
+use crate::DuplicatePackage;
+use crate::DuplicatePackageVersion;
+
+/// Gets the packages that more than one version of is in the module
+/// graph, which are the remote packages with a version in their url and
+/// the packages that remote modules were mapped to.
+///
+/// The remote modules of each version are output to the directory of the
+/// version, so the versions coexist in the output and each module imports
+/// the copy of the version it imported in the graph.
+pub fn get_duplicate_packages(
+  module_graph: &ModuleGraph,
+  specifiers: &Specifiers,
+  mappings: &Mappings,
+  entry_points: &[ModuleSpecifier],
+) -> Vec<DuplicatePackage> {
+  // name -> version -> modules
+  let mut packages: BTreeMap<
+    String,
+    BTreeMap<String, BTreeSet<&ModuleSpecifier>>,
+  > = BTreeMap::new();
+  let remote_packages = specifiers.remote.iter().filter_map(|specifier| {
+    let (name, version) = get_package_name_and_version(specifier)?;
+    Some((name, version, specifier))
+  });
+  let mapped_packages = specifiers
+    .main
+    .mapped
+    .iter()
+    .chain(specifiers.test.mapped.iter())
+    .filter_map(|(specifier, package)| {
+      let version = package.version.clone()?;
+      Some((package.name.clone(), version, specifier))
+    });
+  for (name, version, specifier) in remote_packages.chain(mapped_packages) {
+    packages
+      .entry(name)
+      .or_default()
+      .entry(version)
+      .or_default()
+      .insert(specifier);
+  }
+
+  packages
+    .into_iter()
+    .filter(|(_, versions)| versions.len() > 1)
+    .map(|(name, versions)| DuplicatePackage {
+      name,
+      versions: versions
+        .into_iter()
+        .map(|(version, modules)| {
+          let specifier = modules.into_iter().next().unwrap();
+          DuplicatePackageVersion {
+            version,
+            output_dir: get_package_output_dir(specifier, mappings),
+            referrer_chain: module_graph
+              .get_referrer_chain(entry_points, specifier),
+          }
+        })
+        .collect(),
+    })
+    .collect()
+}
+
+/// Gets the directory the version of the package a remote module is in
+/// is output to (ex. `deps/deno.land/x/oak@v10.0.0`), which is `None`
+/// for a module that's mapped to a package.
+fn get_package_output_dir(
+  specifier: &ModuleSpecifier,
+  mappings: &Mappings,
+) -> Option<PathBuf> {
+  let (index, _) = find_version_segment(specifier)?;
+  let segment_count = specifier.path_segments()?.count();
+  let mut dir = mappings.maybe_file_path(specifier)?.clone();
+  // the file path has the same segments after the version's segment
+  for _ in index + 1..segment_count {
+    dir.pop();
+  }
+  Some(dir)
+}
@@ -0,0 +1,121 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::fmt;
+
+use deno_ast::ModuleSpecifier;
+
+use crate::DuplicatePackage;
+
+/// An error that occurred while transforming or analyzing.
+///
+/// This can be converted to an `anyhow::Error` when the kind of error
+/// doesn't matter.
+#[derive(Debug)]
+pub enum TransformError {
+  /// The provided options were invalid.
+  InvalidOptions {
+    message: String,
+  },
+  /// The loader failed to load a module.
+  Load {
+    specifier: ModuleSpecifier,
+    referrer: Option<ModuleSpecifier>,
+    message: String,
+  },
+  /// A module failed to parse.
+  Parse {
+    specifier: ModuleSpecifier,
+    /// 1-indexed line number.
+    line_number: usize,
+    /// 1-indexed column number.
+    column_number: usize,
+    message: String,
+  },
+  /// The module graph couldn't be built, such as when a module specifier
+  /// couldn't be resolved.
+  ModuleGraph {
+    specifier: ModuleSpecifier,
+    referrer: Option<ModuleSpecifier>,
+    message: String,
+  },
+  /// A remote module isn't allowed by the remote policy.
+  RemotePolicy {
+    specifier: ModuleSpecifier,
+    /// Chain of modules from an entry point that led to the module.
+    referrer_chain: Vec<ModuleSpecifier>,
+    message: String,
+  },
+  /// A JavaScript module is CommonJS, which can't be output as an ES
+  /// module unless it's wrapped.
+  CommonJsModule {
+    specifier: ModuleSpecifier,
+    message: String,
+  },
+  /// Specifiers were mapped to the same package in conflicting ways.
+  MappingConflict {
+    message: String,
+  },
+  /// More than one version of a package was found when erroring on
+  /// duplicate packages.
+  DuplicatePackages {
+    packages: Vec<DuplicatePackage>,
+    message: String,
+  },
+  /// Circular dependencies were found when erroring on cycles.
+  Cycles {
+    /// The modules of each cycle, which start and end with the same
+    /// module.
+    cycles: Vec<Vec<ModuleSpecifier>>,
+    message: String,
+  },
+  /// Several errors occurred, such as when multiple modules failed to load.
+  Multiple(Vec<TransformError>),
+  Other(anyhow::Error),
+}
+
+impl fmt::Display for TransformError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TransformError::InvalidOptions { message }
+      | TransformError::Load { message, .. }
+      | TransformError::Parse { message, .. }
+      | TransformError::ModuleGraph { message, .. }
+      | TransformError::RemotePolicy { message, .. }
+      | TransformError::CommonJsModule { message, .. }
+      | TransformError::MappingConflict { message }
+      | TransformError::DuplicatePackages { message, .. }
+      | TransformError::Cycles { message, .. } => f.write_str(message),
+      TransformError::Multiple(errors) => {
+        for (i, error) in errors.iter().enumerate() {
+          if i > 0 {
+            f.write_str("\n\n")?;
+          }
+          fmt::Display::fmt(error, f)?;
+        }
+        Ok(())
+      }
+      // forward so the alternate flag includes the context
+      TransformError::Other(err) => fmt::Display::fmt(err, f),
+    }
+  }
+}
+
+impl std::error::Error for TransformError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      TransformError::Other(err) => err.source(),
+      _ => None,
+    }
+  }
+}
+
+impl From<anyhow::Error> for TransformError {
+  fn from(err: anyhow::Error) -> Self {
+    // internally errors are surfaced as `anyhow::Error`s, so recover
+    // the structured errors here
+    match err.downcast::<TransformError>() {
+      Ok(err) => err,
+      Err(err) => TransformError::Other(err),
+    }
+  }
+}
@@ -2,101 +2,225 @@
 
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
 
 use crate::loader::get_all_specifier_mappers;
 use crate::loader::Loader;
 use crate::loader::SourceLoader;
+use crate::parser::AssetImportAnalyzer;
+use crate::parser::DirectiveMappingAnalyzer;
+use crate::parser::DirectiveMappings;
+use crate::parser::HandledAssetImport;
+use crate::parser::RecoveringParser;
 use crate::parser::ScopeAnalysisParser;
+use crate::parser::TypesPragmaAnalyzer;
+use crate::parser::WorkerAnalyzer;
+use crate::parser::ASSET_ASSERTION_TYPES;
+use crate::provenance::load_license_files;
+use crate::remote_policy::get_blocked_module_errors;
+use crate::remote_policy::get_remote_depth_errors;
+use crate::remote_policy::OriginPolicy;
 use crate::specifiers::get_specifiers;
 use crate::specifiers::Specifiers;
+use crate::utils::url_to_file_path;
+use crate::JsxOptions;
 use crate::MappedSpecifier;
+use crate::RemotePolicy;
+use crate::TransformError;
 
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
+use deno_ast::Diagnostic;
 use deno_ast::ModuleSpecifier;
 use deno_ast::ParsedSource;
 use deno_graph::CapturingModuleAnalyzer;
 use deno_graph::Module;
+use deno_graph::ModuleAnalyzer;
+use deno_graph::ModuleGraphError;
 use deno_graph::ParsedSourceStore;
+use deno_graph::Resolved;
 
 pub struct ModuleGraphOptions<'a> {
   pub entry_points: Vec<ModuleSpecifier>,
   pub test_entry_points: Vec<ModuleSpecifier>,
-  pub loader: Option<Box<dyn Loader>>,
+  pub loader: Box<dyn Loader>,
   pub specifier_mappings: &'a HashMap<ModuleSpecifier, MappedSpecifier>,
+  pub declaration_mappings: &'a HashMap<ModuleSpecifier, ModuleSpecifier>,
   pub import_map: Option<ModuleSpecifier>,
+  pub jsx: Option<&'a JsxOptions>,
+  pub continue_on_parse_error: bool,
+  pub github_dependencies: bool,
+  pub asset_imports: bool,
+  /// `type` attributes of the imports that have an asset handler.
+  pub asset_handler_types: HashSet<String>,
+  /// File names to try in a directory when a local import isn't found.
+  pub directory_index_files: &'a [String],
+  /// Load the license files adjacent to the remote modules.
+  pub provenance: bool,
+  pub remote_policy: Option<&'a RemotePolicy>,
 }
 
 /// Wrapper around deno_graph::ModuleGraph.
+///
+/// Modules are parsed with scope analysis when building the graph and the
+/// parsed sources are kept so the modules that end up being transformed
+/// don't need to be parsed again. Dropping the graph releases the parsed
+/// sources that weren't taken.
 pub struct ModuleGraph {
   graph: deno_graph::ModuleGraph,
   capturing_analyzer: CapturingModuleAnalyzer,
+  parse_errors: Vec<(ModuleSpecifier, Diagnostic)>,
+  license_files: HashMap<ModuleSpecifier, String>,
 }
 
 impl ModuleGraph {
   pub async fn build_with_specifiers(
     options: ModuleGraphOptions<'_>,
   ) -> Result<(Self, Specifiers)> {
-    let loader = options.loader.unwrap_or_else(|| {
-      #[cfg(feature = "tokio-loader")]
-      return Box::new(crate::loader::DefaultLoader::new());
-      #[cfg(not(feature = "tokio-loader"))]
-      panic!("You must provide a loader or use the 'tokio-loader' feature.")
-    });
-    let resolver = match options.import_map {
+    let loader = options.loader;
+    let import_map = match options.import_map {
       Some(import_map_url) => Some(
-        ImportMapResolver::load(&import_map_url, &*loader)
+        load_import_map(&import_map_url, &*loader)
           .await
           .context("Error loading import map.")?,
       ),
       None => None,
     };
+    let jsx_import_source = options
+      .jsx
+      .filter(|jsx| jsx.runtime.is_automatic())
+      .and_then(|jsx| jsx.import_source.clone());
+    // only use a resolver when necessary to keep the default resolution
+    let resolver = if import_map.is_some() || options.jsx.is_some() {
+      Some(GraphResolver {
+        import_map,
+        jsx_import_source,
+        jsx_import_source_module: options
+          .jsx
+          .map(|jsx| jsx.runtime.import_source_module())
+          .unwrap_or("jsx-runtime"),
+      })
+    } else {
+      None
+    };
+    let specifier_mappers =
+      get_all_specifier_mappers(options.github_dependencies);
+    let origin_policy = match options.remote_policy {
+      Some(policy) => Some(OriginPolicy::new(policy)?),
+      None => None,
+    };
+    let directive_mappings = Arc::new(Mutex::new(DirectiveMappings::default()));
+    let has_assets =
+      options.asset_imports || !options.asset_handler_types.is_empty();
     let mut loader = SourceLoader::new(
       loader,
-      get_all_specifier_mappers(),
+      specifier_mappers,
       options.specifier_mappings,
+      directive_mappings.clone(),
+      has_assets,
+      options.directory_index_files,
+      origin_policy,
     );
-    let source_parser = ScopeAnalysisParser::new();
-    let capturing_analyzer =
-      CapturingModuleAnalyzer::new(Some(Box::new(source_parser)), None);
-    let graph = Self {
-      graph: deno_graph::create_graph(
-        options
-          .entry_points
-          .iter()
-          .chain(options.test_entry_points.iter())
-          .map(|s| (s.to_owned(), deno_graph::ModuleKind::Esm))
-          .collect(),
-        false,
-        None,
-        &mut loader,
-        resolver.as_ref().map(|r| r.as_resolver()),
-        None,
-        Some(&capturing_analyzer),
-        None,
-      )
-      .await,
-      capturing_analyzer,
+    let parse_errors = Arc::new(Mutex::new(Vec::new()));
+    let analyzer = if options.continue_on_parse_error {
+      let parser = RecoveringParser::new(parse_errors.clone());
+      CapturingModuleAnalyzer::new(Some(Box::new(parser)), None)
+    } else {
+      let parser = ScopeAnalysisParser::new();
+      CapturingModuleAnalyzer::new(Some(Box::new(parser)), None)
+    };
+    let handled_asset_imports = Arc::new(Mutex::new(Vec::new()));
+    let maybe_asset_analyzer = if has_assets {
+      Some(AssetImportAnalyzer::new(
+        &analyzer,
+        options.asset_imports,
+        &options.asset_handler_types,
+        handled_asset_imports.clone(),
+      ))
+    } else {
+      None
+    };
+    let worker_analyzer = WorkerAnalyzer::new(match &maybe_asset_analyzer {
+      Some(analyzer) => analyzer as &dyn ModuleAnalyzer,
+      None => &analyzer as &dyn ModuleAnalyzer,
+    });
+    let types_pragma_analyzer = TypesPragmaAnalyzer::new(&worker_analyzer);
+    let directive_mapping_analyzer = DirectiveMappingAnalyzer::new(
+      &types_pragma_analyzer,
+      resolver.as_ref().and_then(|r| r.import_map.as_ref()),
+      directive_mappings.clone(),
+    );
+    let graph = deno_graph::create_graph(
+      options
+        .entry_points
+        .iter()
+        .chain(options.test_entry_points.iter())
+        .chain(options.declaration_mappings.values())
+        .map(|s| (s.to_owned(), deno_graph::ModuleKind::Esm))
+        .collect(),
+      false,
+      None,
+      &mut loader,
+      resolver.as_ref().map(|r| r.as_resolver()),
+      None,
+      Some(&directive_mapping_analyzer as &dyn ModuleAnalyzer),
+      None,
+    )
+    .await;
+    // drop the wrapping analyzers so the capturing one can be kept
+    drop(directive_mapping_analyzer);
+    drop(types_pragma_analyzer);
+    drop(worker_analyzer);
+    drop(maybe_asset_analyzer);
+    let mut graph = Self {
+      graph,
+      capturing_analyzer: analyzer,
+      parse_errors: std::mem::take(&mut *parse_errors.lock().unwrap()),
+      license_files: HashMap::new(),
     };
 
-    let errors = graph.graph.errors().into_iter().collect::<Vec<_>>();
-    if !errors.is_empty() {
-      let mut error_message = String::new();
-      for error in errors {
-        if !error_message.is_empty() {
-          error_message.push_str("\n\n");
-        }
-        error_message.push_str(&error.to_string());
-        if !error_message.contains(error.specifier().as_str()) {
-          error_message.push_str(&format!(" ({})", error.specifier()));
-        }
-      }
-      bail!("{}", error_message);
+    let mut errors = graph
+      .graph
+      .errors()
+      .into_iter()
+      .map(|error| graph.get_transform_error(&error))
+      .collect::<Vec<_>>();
+    errors.extend(
+      std::mem::take(&mut directive_mappings.lock().unwrap().errors)
+        .into_iter()
+        .map(|(specifier, message)| TransformError::ModuleGraph {
+          specifier,
+          referrer: None,
+          message,
+        }),
+    );
+    if errors.len() == 1 {
+      bail!(errors.remove(0));
+    } else if !errors.is_empty() {
+      bail!(TransformError::Multiple(errors));
     }
 
+    let inner_loader = loader.inner_loader();
     let loader_specifiers = loader.into_specifiers();
+    let roots = options
+      .entry_points
+      .iter()
+      .chain(options.test_entry_points.iter())
+      .cloned()
+      .collect::<Vec<_>>();
+
+    let mut errors =
+      get_blocked_module_errors(&graph, &roots, &loader_specifiers.blocked);
+    if errors.len() == 1 {
+      bail!(errors.remove(0));
+    } else if !errors.is_empty() {
+      bail!(TransformError::Multiple(errors));
+    }
 
     let not_found_module_mappings = options
       .specifier_mappings
@@ -108,18 +232,50 @@ impl ModuleGraph {
       .filter(|s| !loader_specifiers.mapped_modules.contains_key(s))
       .collect::<Vec<_>>();
     if !not_found_module_mappings.is_empty() {
-      bail!(
-        "The following specifiers were indicated to be mapped to a module, but were not found:\n{}",
-        format_specifiers_for_message(not_found_module_mappings),
-      );
+      bail!(TransformError::InvalidOptions {
+        message: format!(
+          "The following specifiers were indicated to be mapped to a module, but were not found:\n{}",
+          format_specifiers_for_message(not_found_module_mappings),
+        ),
+      });
     }
 
-    let specifiers = get_specifiers(
+    let mut specifiers = get_specifiers(
       &options.entry_points,
+      &options.test_entry_points,
       loader_specifiers,
       &graph,
       &graph.all_modules(),
+      options.declaration_mappings,
     )?;
+    specifiers.asset_types = graph.get_asset_types(
+      &specifiers.assets,
+      std::mem::take(&mut *handled_asset_imports.lock().unwrap()),
+    );
+
+    if let Some(max_depth) = options.remote_policy.and_then(|p| p.max_depth) {
+      let mut errors =
+        get_remote_depth_errors(&graph, &roots, &specifiers.remote, max_depth);
+      if errors.len() == 1 {
+        bail!(errors.remove(0));
+      } else if !errors.is_empty() {
+        bail!(TransformError::Multiple(errors));
+      }
+    }
+
+    let not_found_declaration_mappings = options
+      .declaration_mappings
+      .keys()
+      .filter(|s| !specifiers.types.contains_key(&graph.resolve(s)))
+      .collect::<Vec<_>>();
+    if !not_found_declaration_mappings.is_empty() {
+      bail!(TransformError::InvalidOptions {
+        message: format!(
+          "The following specifiers were indicated to have a declaration file, but were not found:\n{}",
+          format_specifiers_for_message(not_found_declaration_mappings),
+        ),
+      });
+    }
 
     let not_found_package_specifiers = options
       .specifier_mappings
@@ -131,10 +287,25 @@ impl ModuleGraph {
       .filter(|s| !specifiers.has_mapped(s))
       .collect::<Vec<_>>();
     if !not_found_package_specifiers.is_empty() {
-      bail!(
-        "The following specifiers were indicated to be mapped to a package, but were not found:\n{}",
-        format_specifiers_for_message(not_found_package_specifiers),
-      );
+      bail!(TransformError::InvalidOptions {
+        message: format!(
+          "The following specifiers were indicated to be mapped to a package, but were not found:\n{}",
+          format_specifiers_for_message(not_found_package_specifiers),
+        ),
+      });
+    }
+
+    if options.provenance {
+      graph.license_files = load_license_files(
+        &**inner_loader,
+        specifiers
+          .remote
+          .iter()
+          .chain(specifiers.types.values().map(|d| &d.selected.specifier))
+          .chain(specifiers.main.mapped.keys())
+          .chain(specifiers.test.mapped.keys()),
+      )
+      .await;
     }
 
     Ok((graph, specifiers))
@@ -148,23 +319,275 @@ impl ModuleGraph {
     self.graph.resolve(specifier)
   }
 
+  /// Gets the source parsed with scope analysis when building the graph,
+  /// which is `None` for the modules that aren't JavaScript or TypeScript.
+  pub fn get_parsed_source(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Option<ParsedSource> {
+    let specifier = self.graph.resolve(specifier);
+    self.capturing_analyzer.get_parsed_source(&specifier)
+  }
+
   pub fn get(&self, specifier: &ModuleSpecifier) -> &Module {
     self.graph.get(specifier).unwrap_or_else(|| {
       panic!("dnt bug - Did not find specifier: {}", specifier);
     })
   }
 
-  pub fn get_parsed_source(&self, specifier: &ModuleSpecifier) -> ParsedSource {
-    let specifier = self.graph.resolve(specifier);
-    self
-      .capturing_analyzer
-      .get_parsed_source(&specifier)
-      .unwrap_or_else(|| {
-        panic!(
-          "dnt bug - Did not find parsed source for specifier: {}",
-          specifier
+  fn get_transform_error(&self, error: &ModuleGraphError) -> TransformError {
+    let specifier = error.specifier().clone();
+    let mut message = error.to_string();
+    if !message.contains(specifier.as_str()) {
+      message.push_str(&format!(" ({})", specifier));
+    }
+    if let ModuleGraphError::UnsupportedImportAssertionType(_, kind) = error {
+      if ASSET_ASSERTION_TYPES.contains(&kind.as_str()) {
+        message.push_str(
+          "\n\nEnable asset imports to output a module that exports the text of the file.",
         );
+      }
+    }
+    match error {
+      ModuleGraphError::LoadingErr(..) | ModuleGraphError::Missing(..) => {
+        TransformError::Load {
+          referrer: self.find_referrer(&specifier),
+          specifier,
+          message,
+        }
+      }
+      ModuleGraphError::ParseErr(_, diagnostic) => TransformError::Parse {
+        specifier,
+        line_number: diagnostic.display_position.line_number,
+        column_number: diagnostic.display_position.column_number,
+        message,
+      },
+      ModuleGraphError::ResolutionError(err) => TransformError::ModuleGraph {
+        specifier,
+        referrer: Some(err.range().specifier.clone()),
+        message,
+      },
+      _ => TransformError::ModuleGraph {
+        referrer: self.find_referrer(&specifier),
+        specifier,
+        message,
+      },
+    }
+  }
+
+  fn find_referrer(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Option<ModuleSpecifier> {
+    self.get_referrers(specifier).into_iter().next()
+  }
+
+  /// Gets the modules that directly depend on the specifier.
+  pub fn get_referrers(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Vec<ModuleSpecifier> {
+    let specifier = self.resolve(specifier);
+    self
+      .all_modules()
+      .into_iter()
+      .filter(|module| {
+        module
+          .dependencies
+          .values()
+          .flat_map(|dep| [dep.get_code(), dep.get_type()])
+          .flatten()
+          .any(|s| self.resolve(s) == specifier)
       })
+      .map(|module| module.specifier.clone())
+      .collect()
+  }
+
+  /// Modules that failed to parse when continuing on parse errors. These
+  /// are in the graph as modules without any dependencies.
+  pub fn parse_errors(&self) -> &[(ModuleSpecifier, Diagnostic)] {
+    &self.parse_errors
+  }
+
+  /// License files adjacent to the remote modules by their specifier,
+  /// which are only loaded when collecting the provenance.
+  pub fn license_files(&self) -> &HashMap<ModuleSpecifier, String> {
+    &self.license_files
+  }
+
+  /// Gets the `type` attribute of the assets that are imported with the
+  /// type of an asset handler. An asset imported with more than one type
+  /// gets the type of the first import.
+  fn get_asset_types(
+    &self,
+    assets: &HashSet<ModuleSpecifier>,
+    handled_imports: Vec<HandledAssetImport>,
+  ) -> HashMap<ModuleSpecifier, String> {
+    let mut asset_types = HashMap::new();
+    for import in handled_imports {
+      let specifier = self
+        .graph
+        .resolve_dependency(
+          &import.value,
+          &import.referrer,
+          /* prefer_types */ false,
+        )
+        .map(|specifier| self.resolve(specifier));
+      if let Some(specifier) = specifier.filter(|s| assets.contains(s)) {
+        asset_types
+          .entry(specifier)
+          .or_insert(import.attribute_type);
+      }
+    }
+    asset_types
+  }
+
+  /// Gets a resolver for the dependencies of the modules in the graph.
+  pub fn dependency_resolver(&self) -> DependencyResolver {
+    let mut dependencies = HashMap::new();
+    for module in self.graph.modules() {
+      let mut module_dependencies = HashMap::new();
+      for value in module.dependencies.keys() {
+        if let Some(specifier) = self.graph.resolve_dependency(
+          value,
+          &module.specifier,
+          /* prefer_types */ false,
+        ) {
+          module_dependencies.insert(value.clone(), specifier.clone());
+        }
+      }
+      dependencies.insert(module.specifier.clone(), module_dependencies);
+    }
+    DependencyResolver {
+      redirects: self
+        .graph
+        .redirects
+        .keys()
+        .map(|specifier| (specifier.clone(), self.graph.resolve(specifier)))
+        .collect(),
+      dependencies,
+    }
+  }
+
+  pub fn all_modules(&self) -> Vec<&Module> {
+    self.graph.modules()
+  }
+
+  /// Gets the shortest chain of modules from one of the provided roots
+  /// to the specifier. The chain starts with the root and ends with
+  /// the specifier.
+  pub fn get_referrer_chain(
+    &self,
+    roots: &[ModuleSpecifier],
+    specifier: &ModuleSpecifier,
+  ) -> Vec<ModuleSpecifier> {
+    let specifier = self.resolve(specifier);
+    self
+      .walk_shortest_referrers(roots, Some(&specifier))
+      .get_chain(&specifier)
+  }
+
+  /// Gets the shortest chains of modules from the provided roots to every
+  /// module reachable from them.
+  pub fn get_shortest_referrers(
+    &self,
+    roots: &[ModuleSpecifier],
+  ) -> ShortestReferrers {
+    self.walk_shortest_referrers(roots, None)
+  }
+
+  fn walk_shortest_referrers(
+    &self,
+    roots: &[ModuleSpecifier],
+    stop_at: Option<&ModuleSpecifier>,
+  ) -> ShortestReferrers {
+    let mut referrers: HashMap<ModuleSpecifier, ModuleSpecifier> =
+      HashMap::new();
+    let mut visited = HashSet::new();
+    let mut pending = VecDeque::new();
+    for root in roots {
+      let root = self.resolve(root);
+      if visited.insert(root.clone()) {
+        pending.push_back(root);
+      }
+    }
+
+    while let Some(current) = pending.pop_front() {
+      if Some(&current) == stop_at {
+        break;
+      }
+      for child in self.get_dependencies(&current) {
+        if visited.insert(child.clone()) {
+          referrers.insert(child.clone(), current.clone());
+          pending.push_back(child);
+        }
+      }
+    }
+
+    ShortestReferrers(referrers)
+  }
+
+  /// Gets the resolved specifiers of the modules a module imports,
+  /// including its types.
+  pub fn get_dependencies(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Vec<ModuleSpecifier> {
+    let module = match self.graph.get(specifier) {
+      Some(module) => module,
+      None => return Vec::new(),
+    };
+    let mut children = Vec::new();
+    for dep in module.dependencies.values() {
+      children.extend(dep.get_code());
+      children.extend(dep.get_type());
+    }
+    if let Some((_, Resolved::Ok { specifier, .. })) =
+      &module.maybe_types_dependency
+    {
+      children.push(specifier);
+    }
+    children.into_iter().map(|s| self.resolve(s)).collect()
+  }
+}
+
+/// The module each module was first imported by when walking the module
+/// graph breadth first from some roots, which are the referrers in the
+/// shortest chains of imports from the roots.
+pub struct ShortestReferrers(HashMap<ModuleSpecifier, ModuleSpecifier>);
+
+impl ShortestReferrers {
+  /// Gets the chain of modules from a root to the resolved specifier,
+  /// which starts with the root and ends with the specifier.
+  pub fn get_chain(&self, specifier: &ModuleSpecifier) -> Vec<ModuleSpecifier> {
+    let mut chain = vec![specifier.clone()];
+    let mut current = specifier;
+    while let Some(referrer) = self.0.get(current) {
+      chain.push(referrer.clone());
+      current = referrer;
+    }
+    chain.reverse();
+    chain
+  }
+}
+
+/// Resolves the dependencies of the modules in a module graph.
+///
+/// Unlike the module graph, this can be shared between threads.
+pub struct DependencyResolver {
+  redirects: HashMap<ModuleSpecifier, ModuleSpecifier>,
+  dependencies: HashMap<ModuleSpecifier, HashMap<String, ModuleSpecifier>>,
+}
+
+impl DependencyResolver {
+  /// Gets the resolved dependencies of a module.
+  pub fn dependencies(
+    &self,
+    referrer: &ModuleSpecifier,
+  ) -> Option<&HashMap<String, ModuleSpecifier>> {
+    self
+      .dependencies
+      .get(self.redirects.get(referrer).unwrap_or(referrer))
   }
 
   pub fn resolve_dependency(
@@ -173,8 +596,9 @@ impl ModuleGraph {
     referrer: &ModuleSpecifier,
   ) -> Option<ModuleSpecifier> {
     self
-      .graph
-      .resolve_dependency(value, referrer, /* prefer_types */ false)
+      .dependencies
+      .get(self.redirects.get(referrer).unwrap_or(referrer))
+      .and_then(|dependencies| dependencies.get(value))
       .cloned()
       .or_else(|| {
         let value_lower = value.to_lowercase();
@@ -192,10 +616,6 @@ impl ModuleGraph {
         }
       })
   }
-
-  pub fn all_modules(&self) -> Vec<&Module> {
-    self.graph.modules()
-  }
 }
 
 fn format_specifiers_for_message(
@@ -209,41 +629,121 @@ fn format_specifiers_for_message(
     .join("\n")
 }
 
-#[derive(Debug)]
-struct ImportMapResolver(import_map::ImportMap);
-
-impl ImportMapResolver {
-  pub async fn load(
-    import_map_url: &ModuleSpecifier,
-    loader: &dyn Loader,
-  ) -> Result<Self> {
-    let response = loader
-      .load(import_map_url.clone())
-      .await?
-      .ok_or_else(|| anyhow!("Could not find {}", import_map_url))?;
-    let result =
-      import_map::parse_from_json(import_map_url, &response.content)?;
-    // if !result.diagnostics.is_empty() {
-    //   todo: surface diagnostics maybe? It seems like this should not be hard error according to import map spec
-    //   bail!("Import map diagnostics:\n{}", result.diagnostics.into_iter().map(|d| format!("  - {}", d)).collect::<Vec<_>>().join("\n"));
-    //}
-    Ok(ImportMapResolver(result.import_map))
+/// Validates the entry points up front so that the user gets a more
+/// helpful error than what would occur when building the graph.
+pub async fn validate_entry_points(
+  entry_points: &[ModuleSpecifier],
+  test_entry_points: &[ModuleSpecifier],
+  loader: &dyn Loader,
+) -> Result<()> {
+  let mut messages = Vec::new();
+  let mut seen = HashSet::new();
+  for (kind, entry_point) in entry_points
+    .iter()
+    .map(|s| ("Entry point", s))
+    .chain(test_entry_points.iter().map(|s| ("Test entry point", s)))
+  {
+    if !seen.insert(entry_point) {
+      messages.push(format!(
+        "{} {} was specified more than once.",
+        kind, entry_point
+      ));
+      continue;
+    }
+    match entry_point.scheme() {
+      "file" => {
+        let file_path = url_to_file_path(entry_point)
+          .map(|p| p.display().to_string())
+          .unwrap_or_else(|_| entry_point.path().to_string());
+        if entry_point.path().ends_with('/') {
+          messages.push(format!(
+            "{} {} was a directory ({}). Specify a file instead.",
+            kind, entry_point, file_path
+          ));
+          continue;
+        }
+        match loader.load(entry_point.clone()).await {
+          Ok(Some(_)) => {}
+          Ok(None) => messages.push(format!(
+            "{} {} was not found. Tried to load {}",
+            kind, entry_point, file_path
+          )),
+          Err(err) => messages.push(format!(
+            "{} {} could not be loaded from {}: {:#}",
+            kind, entry_point, file_path, err
+          )),
+        }
+      }
+      "http" | "https" => {}
+      scheme => messages.push(format!(
+        "{} {} had an unsupported scheme '{}'. Use a file, http, or https URL.",
+        kind, entry_point, scheme
+      )),
+    }
+  }
+
+  if !messages.is_empty() {
+    bail!(TransformError::InvalidOptions {
+      message: messages.join("\n"),
+    });
   }
+  Ok(())
+}
+
+async fn load_import_map(
+  import_map_url: &ModuleSpecifier,
+  loader: &dyn Loader,
+) -> Result<import_map::ImportMap> {
+  let response = loader
+    .load(import_map_url.clone())
+    .await?
+    .ok_or_else(|| anyhow!("Could not find {}", import_map_url))?;
+  let result = import_map::parse_from_json(import_map_url, &response.content)?;
+  // if !result.diagnostics.is_empty() {
+  //   todo: surface diagnostics maybe? It seems like this should not be hard error according to import map spec
+  //   bail!("Import map diagnostics:\n{}", result.diagnostics.into_iter().map(|d| format!("  - {}", d)).collect::<Vec<_>>().join("\n"));
+  //}
+  Ok(result.import_map)
+}
 
+#[derive(Debug)]
+struct GraphResolver {
+  import_map: Option<import_map::ImportMap>,
+  /// Default import source of the automatic JSX runtime.
+  jsx_import_source: Option<String>,
+  jsx_import_source_module: &'static str,
+}
+
+impl GraphResolver {
   pub fn as_resolver(&self) -> &dyn deno_graph::source::Resolver {
     self
   }
 }
 
-impl deno_graph::source::Resolver for ImportMapResolver {
+impl deno_graph::source::Resolver for GraphResolver {
+  fn jsx_import_source_module(&self) -> &str {
+    self.jsx_import_source_module
+  }
+
+  fn default_jsx_import_source(&self) -> Option<String> {
+    self.jsx_import_source.clone()
+  }
+
   fn resolve(
     &self,
     specifier: &str,
     referrer: &ModuleSpecifier,
   ) -> deno_graph::source::ResolveResponse {
-    match self.0.resolve(specifier, referrer) {
+    let result = match &self.import_map {
+      Some(import_map) => import_map
+        .resolve(specifier, referrer)
+        .map_err(anyhow::Error::from),
+      None => deno_graph::resolve_import(specifier, referrer)
+        .map_err(anyhow::Error::from),
+    };
+    match result {
       Ok(specifier) => specifier.into(),
-      Err(err) => deno_graph::source::ResolveResponse::Err(err.into()),
+      Err(err) => deno_graph::source::ResolveResponse::Err(err),
     }
   }
 }
@@ -0,0 +1,373 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::path::PathBuf;
+
+use deno_ast::MediaType;
+use deno_ast::ModuleSpecifier;
+
+use crate::graph::ModuleGraph;
+use crate::graph::ShortestReferrers;
+use crate::mappings::Mappings;
+use crate::specifiers::Specifiers;
+use crate::OutputEnvironment;
+use crate::PackageMappedSpecifier;
+
+/// Read-only information about the modules in the module graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphInfo {
+  /// Modules sorted by specifier.
+  pub modules: Vec<ModuleInfo>,
+}
+
+impl GraphInfo {
+  pub fn get(&self, specifier: &ModuleSpecifier) -> Option<&ModuleInfo> {
+    self
+      .modules
+      .binary_search_by(|m| m.specifier.cmp(specifier))
+      .ok()
+      .map(|index| &self.modules[index])
+  }
+
+  /// Explains why a module is in the graph with the shortest chain of
+  /// imports from an entry point to it, or gets `None` when the module
+  /// isn't in the graph.
+  pub fn explain(&self, specifier: &ModuleSpecifier) -> Option<String> {
+    let module = self.get(specifier)?;
+    let environment = match module.environment {
+      OutputEnvironment::Main => "main",
+      OutputEnvironment::Test => "test",
+    };
+    let mut text = if module.referrer_chain.len() <= 1 {
+      format!(
+        "{} is an entry point of the {} environment.",
+        specifier, environment
+      )
+    } else {
+      format!(
+        "{} is in the {} environment because it's imported through {}",
+        specifier,
+        environment,
+        module
+          .referrer_chain
+          .iter()
+          .map(|s| s.as_str())
+          .collect::<Vec<_>>()
+          .join(" -> ")
+      )
+    };
+    if let Some(mapped_package) = &module.mapped_package {
+      text.push_str(&format!(
+        "\nIt's mapped to {} instead of being output.",
+        mapped_package.module_specifier_text()
+      ));
+    }
+    Some(text)
+  }
+
+  /// Gets the graph in the Graphviz DOT format.
+  ///
+  /// Test modules are dashed, modules mapped to a package are boxes, and
+  /// type only dependencies are dotted edges.
+  pub fn to_dot(&self) -> String {
+    let mut text = String::from("digraph {\n");
+    for module in self.modules.iter() {
+      let mut attributes = Vec::new();
+      if let Some(mapped_package) = &module.mapped_package {
+        attributes.push("shape=box".to_string());
+        attributes.push(format!(
+          "label={}",
+          quote(&format!(
+            "{}\n{}",
+            mapped_package.module_specifier_text(),
+            module.specifier
+          ))
+        ));
+      }
+      if module.environment == OutputEnvironment::Test {
+        attributes.push("style=dashed".to_string());
+      }
+      text.push_str(&format!("  {}", quote(module.specifier.as_str())));
+      if !attributes.is_empty() {
+        text.push_str(&format!(" [{}]", attributes.join(", ")));
+      }
+      text.push_str(";\n");
+    }
+    for module in self.modules.iter() {
+      let from = quote(module.specifier.as_str());
+      for dep in module.dependencies.iter() {
+        if let Some(code) = dep.code.as_ref().filter(|s| self.contains(s)) {
+          text.push_str(&format!("  {} -> {};\n", from, quote(code.as_str())));
+        }
+        if let Some(types) = dep.types.as_ref().filter(|s| self.contains(s)) {
+          text.push_str(&format!(
+            "  {} -> {} [style=dotted];\n",
+            from,
+            quote(types.as_str())
+          ));
+        }
+      }
+      if let Some(declaration_file) = &module.declaration_file {
+        text.push_str(&format!(
+          "  {} -> {} [style=dotted];\n",
+          from,
+          quote(declaration_file.as_str())
+        ));
+      }
+    }
+    text.push_str("}\n");
+    text
+  }
+
+  /// Gets the graph as JSON.
+  ///
+  /// The schema is stable and looks like the following:
+  ///
+  /// ```json
+  /// {
+  ///   "modules": [{
+  ///     "specifier": "file:///mod.ts",
+  ///     "mediaType": "TypeScript",
+  ///     "environment": "main",
+  ///     "size": 20,
+  ///     "outputPath": "mod.ts",
+  ///     "mappedPackage": null,
+  ///     "declarationFile": null,
+  ///     "referrerChain": ["file:///mod.ts"],
+  ///     "dependencies": [{
+  ///       "value": "./other.ts",
+  ///       "code": "file:///other.ts",
+  ///       "types": null
+  ///     }]
+  ///   }]
+  /// }
+  /// ```
+  ///
+  /// The mapped packages look like `{ "name": "code-block-writer",
+  /// "version": "^11.0.0", "subPath": null }`.
+  pub fn to_json(&self) -> String {
+    fn optional(value: Option<String>) -> String {
+      value.unwrap_or_else(|| "null".to_string())
+    }
+
+    let modules = self
+      .modules
+      .iter()
+      .map(|module| {
+        let dependencies = module
+          .dependencies
+          .iter()
+          .map(|dep| {
+            format!(
+              "{{\"value\":{},\"code\":{},\"types\":{}}}",
+              quote(&dep.value),
+              optional(dep.code.as_ref().map(|s| quote(s.as_str()))),
+              optional(dep.types.as_ref().map(|s| quote(s.as_str()))),
+            )
+          })
+          .collect::<Vec<_>>();
+        format!(
+          concat!(
+            "{{\"specifier\":{},\"mediaType\":{},\"environment\":{},",
+            "\"size\":{},\"outputPath\":{},\"mappedPackage\":{},",
+            "\"declarationFile\":{},\"referrerChain\":[{}],",
+            "\"dependencies\":[{}]}}",
+          ),
+          quote(module.specifier.as_str()),
+          quote(&format!("{:?}", module.media_type)),
+          quote(match module.environment {
+            OutputEnvironment::Main => "main",
+            OutputEnvironment::Test => "test",
+          }),
+          module.size,
+          optional(
+            module
+              .output_path
+              .as_ref()
+              .map(|p| quote(&p.to_string_lossy().replace('\\', "/")))
+          ),
+          optional(module.mapped_package.as_ref().map(|p| {
+            format!(
+              "{{\"name\":{},\"version\":{},\"subPath\":{}}}",
+              quote(&p.name),
+              optional(p.version.as_deref().map(quote)),
+              optional(p.sub_path.as_deref().map(quote)),
+            )
+          })),
+          optional(module.declaration_file.as_ref().map(|s| quote(s.as_str()))),
+          module
+            .referrer_chain
+            .iter()
+            .map(|s| quote(s.as_str()))
+            .collect::<Vec<_>>()
+            .join(","),
+          dependencies.join(","),
+        )
+      })
+      .collect::<Vec<_>>();
+    format!("{{\"modules\":[{}]}}", modules.join(","))
+  }
+
+  fn contains(&self, specifier: &ModuleSpecifier) -> bool {
+    self.get(specifier).is_some()
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleInfo {
+  pub specifier: ModuleSpecifier,
+  pub media_type: MediaType,
+  pub environment: OutputEnvironment,
+  /// Size of the module's source in bytes.
+  pub size: usize,
+  /// Path the module would be output to, which is `None` for modules
+  /// mapped to a package.
+  pub output_path: Option<PathBuf>,
+  /// Package this module is mapped to.
+  pub mapped_package: Option<PackageMappedSpecifier>,
+  /// Declaration file that was selected for this module.
+  pub declaration_file: Option<ModuleSpecifier>,
+  /// Shortest chain of imports from an entry point of the module's
+  /// environment to the module, which starts with the entry point and
+  /// ends with the module.
+  pub referrer_chain: Vec<ModuleSpecifier>,
+  pub dependencies: Vec<DependencyInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyInfo {
+  /// Specifier text as written in the module.
+  pub value: String,
+  pub code: Option<ModuleSpecifier>,
+  pub types: Option<ModuleSpecifier>,
+}
+
+pub fn get_graph_info(
+  module_graph: &ModuleGraph,
+  specifiers: &Specifiers,
+  mappings: &Mappings,
+  entry_points: &[ModuleSpecifier],
+  test_entry_points: &[ModuleSpecifier],
+) -> GraphInfo {
+  let get_environment = |specifier: &ModuleSpecifier| {
+    if specifiers.test_modules.contains(specifier) {
+      OutputEnvironment::Test
+    } else {
+      OutputEnvironment::Main
+    }
+  };
+  let main_referrers = module_graph.get_shortest_referrers(entry_points);
+  let test_referrers = module_graph.get_shortest_referrers(test_entry_points);
+  let context = ModuleInfoContext {
+    module_graph,
+    specifiers,
+    mappings,
+    main_referrers: &main_referrers,
+    test_referrers: &test_referrers,
+  };
+  let mut modules = Vec::new();
+
+  for specifier in specifiers.local.iter().chain(specifiers.remote.iter()) {
+    modules.push(get_module_info(
+      &context,
+      specifier,
+      get_environment(specifier),
+    ));
+  }
+  for (code_specifier, d) in specifiers.types.iter() {
+    let environment = get_environment(code_specifier);
+    let mut info =
+      get_module_info(&context, &d.selected.specifier, environment);
+    if info.referrer_chain.len() == 1 {
+      // the declaration file was selected by the code module's specifier
+      // (ex. with the declaration mappings), so it's not imported
+      info.referrer_chain = match environment {
+        OutputEnvironment::Main => &main_referrers,
+        OutputEnvironment::Test => &test_referrers,
+      }
+      .get_chain(code_specifier);
+      info.referrer_chain.push(d.selected.specifier.clone());
+    }
+    modules.push(info);
+  }
+  for (environment, mapped) in [
+    (OutputEnvironment::Main, &specifiers.main.mapped),
+    (OutputEnvironment::Test, &specifiers.test.mapped),
+  ] {
+    for (specifier, mapped_package) in mapped.iter() {
+      let mut info = get_module_info(&context, specifier, environment);
+      info.output_path = None;
+      info.mapped_package = Some(mapped_package.clone());
+      modules.push(info);
+    }
+  }
+
+  modules.sort_by(|a, b| a.specifier.cmp(&b.specifier));
+  modules.dedup_by(|a, b| a.specifier == b.specifier);
+  GraphInfo { modules }
+}
+
+struct ModuleInfoContext<'a> {
+  module_graph: &'a ModuleGraph,
+  specifiers: &'a Specifiers,
+  mappings: &'a Mappings,
+  main_referrers: &'a ShortestReferrers,
+  test_referrers: &'a ShortestReferrers,
+}
+
+fn get_module_info(
+  context: &ModuleInfoContext,
+  specifier: &ModuleSpecifier,
+  environment: OutputEnvironment,
+) -> ModuleInfo {
+  let module_graph = context.module_graph;
+  let module = module_graph.get(specifier);
+  let referrers = match environment {
+    OutputEnvironment::Main => context.main_referrers,
+    OutputEnvironment::Test => context.test_referrers,
+  };
+  ModuleInfo {
+    specifier: module.specifier.clone(),
+    media_type: module.media_type,
+    environment,
+    size: module.maybe_source.as_ref().map(|s| s.len()).unwrap_or(0),
+    output_path: context.mappings.maybe_file_path(&module.specifier).cloned(),
+    mapped_package: None,
+    declaration_file: context
+      .specifiers
+      .types
+      .get(&module.specifier)
+      .map(|d| d.selected.specifier.clone()),
+    referrer_chain: referrers.get_chain(&module.specifier),
+    dependencies: module
+      .dependencies
+      .iter()
+      .map(|(value, dep)| DependencyInfo {
+        value: value.clone(),
+        code: dep.get_code().map(|s| module_graph.resolve(s)),
+        types: dep.get_type().map(|s| module_graph.resolve(s)),
+      })
+      .collect(),
+  }
+}
+
+/// Quotes and escapes text so it's a valid JSON string, which
+/// is also a valid DOT identifier.
+fn quote(text: &str) -> String {
+  let mut result = String::with_capacity(text.len() + 2);
+  result.push('"');
+  for c in text.chars() {
+    match c {
+      '"' => result.push_str("\\\""),
+      '\\' => result.push_str("\\\\"),
+      '\n' => result.push_str("\\n"),
+      '\r' => result.push_str("\\r"),
+      '\t' => result.push_str("\\t"),
+      c if (c as u32) < 0x20 => {
+        result.push_str(&format!("\\u{:04x}", c as u32))
+      }
+      c => result.push(c),
+    }
+  }
+  result.push('"');
+  result
+}
@@ -0,0 +1,319 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use deno_ast::parse_module;
+use deno_ast::view::*;
+use deno_ast::MediaType;
+use deno_ast::ModuleSpecifier;
+use deno_ast::ParseParams;
+use deno_ast::SourceRange;
+use deno_ast::SourceRanged;
+use deno_ast::SourceTextInfo;
+use deno_ast::SourceTextInfoProvider;
+use deno_ast::TextChange;
+
+use crate::text_changes::apply_text_changes;
+use crate::text_changes::to_byte_range;
+
+pub struct InlineModuleParams<'a> {
+  pub specifier: &'a ModuleSpecifier,
+  pub media_type: MediaType,
+  pub source: &'a Arc<str>,
+}
+
+/// Gets the text of the importer with the module inlined in place of its
+/// import declaration, or `None` when the module can't be inlined safely.
+///
+/// Only modules that declare and export things without any other top level
+/// statements are inlined and only when the importer imports them once with
+/// named imports that aren't renamed. The module's declarations also can't
+/// conflict with any names used in the importer.
+pub fn inline_module(
+  importer: &InlineModuleParams,
+  module: &InlineModuleParams,
+  import_specifier_text: &str,
+) -> Option<String> {
+  if !can_inline_media_type(importer.media_type, module.media_type)
+    || module.source.contains("import.meta")
+  {
+    return None;
+  }
+  let module_parsed_source = parse(module)?;
+  let module_result = module_parsed_source.with_view(get_module_text)?;
+  let importer_parsed_source = parse(importer)?;
+  importer_parsed_source.with_view(|program| {
+    let import_decl = get_sole_import_decl(program, import_specifier_text)?;
+    for specifier in import_decl.specifiers.iter() {
+      let named = match specifier {
+        ImportSpecifier::Named(named) => named,
+        ImportSpecifier::Default(_) | ImportSpecifier::Namespace(_) => {
+          return None
+        }
+      };
+      let local_name = named.local.sym().as_ref();
+      let is_renamed = match named.imported {
+        Some(ModuleExportName::Ident(imported)) => {
+          imported.sym().as_ref() != local_name
+        }
+        Some(ModuleExportName::Str(_)) => true,
+        None => false,
+      };
+      if is_renamed || !module_result.exported_names.contains(local_name) {
+        return None;
+      }
+    }
+
+    let mut importer_names = HashSet::new();
+    fill_ident_names(program.as_node(), &mut importer_names);
+    let imported_names = import_decl
+      .specifiers
+      .iter()
+      .filter_map(|s| match s {
+        ImportSpecifier::Named(named) => Some(named.local.sym().to_string()),
+        _ => None,
+      })
+      .collect::<HashSet<_>>();
+    if module_result.declared_names.iter().any(|name| {
+      importer_names.contains(name) && !imported_names.contains(name)
+    }) {
+      return None;
+    }
+
+    let text_info = program.text_info();
+    let mut new_text = format!("// inlined from {}\n", module.specifier);
+    new_text.push_str(module_result.text.trim());
+    apply_text_changes(
+      text_info.text_str(),
+      vec![TextChange {
+        range: to_byte_range(text_info, import_decl.range()),
+        new_text,
+      }],
+    )
+    .ok()
+  })
+}
+
+struct ModuleText {
+  /// Text of the module without any exports.
+  text: String,
+  exported_names: HashSet<String>,
+  declared_names: HashSet<String>,
+}
+
+fn parse(params: &InlineModuleParams) -> Option<deno_ast::ParsedSource> {
+  parse_module(ParseParams {
+    specifier: params.specifier.to_string(),
+    text_info: SourceTextInfo::new(params.source.clone()),
+    media_type: params.media_type,
+    capture_tokens: false,
+    scope_analysis: false,
+    maybe_syntax: None,
+  })
+  .ok()
+}
+
+fn can_inline_media_type(importer: MediaType, module: MediaType) -> bool {
+  match module {
+    MediaType::JavaScript | MediaType::Mjs => matches!(
+      importer,
+      MediaType::JavaScript
+        | MediaType::Mjs
+        | MediaType::Jsx
+        | MediaType::TypeScript
+        | MediaType::Mts
+        | MediaType::Tsx
+    ),
+    MediaType::TypeScript | MediaType::Mts => matches!(
+      importer,
+      MediaType::TypeScript | MediaType::Mts | MediaType::Tsx
+    ),
+    _ => false,
+  }
+}
+
+/// Gets the text of a module with its exports removed.
+fn get_module_text(program: Program) -> Option<ModuleText> {
+  let module = match program {
+    Program::Module(module) => module,
+    Program::Script(_) => return None,
+  };
+  let text_info = program.text_info();
+  let mut text_changes = Vec::new();
+  let mut exported_names = HashSet::new();
+  let mut declared_names = HashSet::new();
+  for item in module.body.iter() {
+    match item {
+      ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export_decl)) => {
+        let names = get_decl_names(&export_decl.decl)?;
+        exported_names.extend(names.iter().cloned());
+        declared_names.extend(names);
+        // remove the `export` keyword
+        text_changes.push(TextChange {
+          range: to_byte_range(
+            text_info,
+            SourceRange::new(export_decl.start(), export_decl.decl.start()),
+          ),
+          new_text: String::new(),
+        });
+      }
+      ModuleItem::ModuleDecl(ModuleDecl::ExportNamed(named_export))
+        if named_export.src.is_none() =>
+      {
+        for specifier in named_export.specifiers.iter() {
+          let named = match specifier {
+            ExportSpecifier::Named(named) => named,
+            _ => return None,
+          };
+          let orig_name = match named.orig {
+            ModuleExportName::Ident(ident) => ident.sym().to_string(),
+            ModuleExportName::Str(_) => return None,
+          };
+          let is_renamed = match named.exported {
+            Some(ModuleExportName::Ident(exported)) => {
+              exported.sym().as_ref() != orig_name
+            }
+            Some(ModuleExportName::Str(_)) => true,
+            None => false,
+          };
+          if is_renamed {
+            return None;
+          }
+          exported_names.insert(orig_name);
+        }
+        text_changes.push(TextChange {
+          range: to_byte_range(text_info, named_export.range()),
+          new_text: String::new(),
+        });
+      }
+      ModuleItem::Stmt(Stmt::Decl(decl)) => {
+        declared_names.extend(get_decl_names(decl)?);
+      }
+      ModuleItem::Stmt(Stmt::Empty(_)) => {}
+      // imports, re-exports, default exports, and statements with
+      // side effects aren't supported
+      _ => return None,
+    }
+  }
+  // exports in a list need to refer to a declaration in the module
+  if !exported_names
+    .iter()
+    .all(|name| declared_names.contains(name))
+  {
+    return None;
+  }
+
+  Some(ModuleText {
+    text: apply_text_changes(text_info.text_str(), text_changes).ok()?,
+    exported_names,
+    declared_names,
+  })
+}
+
+fn get_decl_names(decl: &Decl) -> Option<Vec<String>> {
+  Some(match decl {
+    Decl::Class(class_decl) => vec![class_decl.ident.sym().to_string()],
+    Decl::Fn(fn_decl) => vec![fn_decl.ident.sym().to_string()],
+    Decl::Var(var_decl) => {
+      let mut names = Vec::with_capacity(var_decl.decls.len());
+      for declarator in var_decl.decls.iter() {
+        match declarator.name {
+          Pat::Ident(binding_ident) => {
+            names.push(binding_ident.id.sym().to_string())
+          }
+          // destructuring isn't supported
+          _ => return None,
+        }
+      }
+      names
+    }
+    Decl::TsInterface(interface_decl) => {
+      vec![interface_decl.id.sym().to_string()]
+    }
+    Decl::TsTypeAlias(type_alias_decl) => {
+      vec![type_alias_decl.id.sym().to_string()]
+    }
+    Decl::TsEnum(enum_decl) => vec![enum_decl.id.sym().to_string()],
+    Decl::TsModule(module_decl) => match module_decl.id {
+      TsModuleName::Ident(ident) => vec![ident.sym().to_string()],
+      TsModuleName::Str(_) => return None,
+    },
+  })
+}
+
+/// Gets the import declaration of the specifier when it's the only
+/// reference to it in the program.
+fn get_sole_import_decl<'a>(
+  program: Program<'a>,
+  specifier_text: &str,
+) -> Option<&'a ImportDecl<'a>> {
+  let module = match program {
+    Program::Module(module) => module,
+    Program::Script(_) => return None,
+  };
+  let mut found_import_decl = None;
+  for item in module.body.iter() {
+    match item {
+      ModuleItem::ModuleDecl(ModuleDecl::Import(import_decl))
+        if import_decl.src.value().as_ref() == specifier_text =>
+      {
+        if found_import_decl.is_some() || import_decl.asserts.is_some() {
+          return None;
+        }
+        found_import_decl = Some(*import_decl);
+      }
+      _ => {}
+    }
+  }
+  let import_decl = found_import_decl?;
+  if has_other_reference(program.as_node(), import_decl, specifier_text) {
+    return None;
+  }
+  Some(import_decl)
+}
+
+/// Checks for re-exports, dynamic imports, and import types of the
+/// specifier other than the import declaration.
+fn has_other_reference(
+  node: Node,
+  import_decl: &ImportDecl,
+  specifier_text: &str,
+) -> bool {
+  let src = match node {
+    Node::ImportDecl(decl) if decl.range() == import_decl.range() => {
+      return false;
+    }
+    Node::ExportAll(export_all) => Some(export_all.src),
+    Node::NamedExport(named_export) => named_export.src,
+    Node::TsImportType(import_type) => Some(import_type.arg),
+    Node::CallExpr(call_expr)
+      if matches!(call_expr.callee, Callee::Import(_)) =>
+    {
+      match call_expr.args.get(0).map(|a| a.expr) {
+        Some(Expr::Lit(Lit::Str(src))) => Some(src),
+        // a non-literal dynamic import could be anything
+        _ => return true,
+      }
+    }
+    _ => None,
+  };
+  if let Some(src) = src {
+    if src.value().as_ref() == specifier_text {
+      return true;
+    }
+  }
+  node
+    .children()
+    .into_iter()
+    .any(|child| has_other_reference(child, import_decl, specifier_text))
+}
+
+fn fill_ident_names(node: Node, names: &mut HashSet<String>) {
+  if let Node::Ident(ident) = node {
+    names.insert(ident.sym().to_string());
+  }
+  for child in node.children() {
+    fill_ident_names(child, names);
+  }
+}
@@ -5,56 +5,182 @@
 #![deny(clippy::disallowed_types)]
 
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use analyze::get_top_level_decls;
 use anyhow::Context;
 use anyhow::Result;
+use cache::get_source_hash;
+use cache::CacheKeyHasher;
+use cache::CachedFile;
+use cache::TransformCache;
 
 use analyze::get_ignore_line_indexes;
 use anyhow::bail;
-use deno_ast::apply_text_changes;
+use cycles::get_cycles;
+use deno_ast::MediaType;
+use deno_ast::ParsedSource;
 use deno_ast::TextChange;
 use deno_graph::ModuleKind;
+use deno_graph::ModuleParser;
+use duplicate_packages::get_duplicate_packages;
+use graph::validate_entry_points;
+use graph::DependencyResolver;
 use graph::ModuleGraphOptions;
+use graph::ShortestReferrers;
+use graph_info::get_graph_info;
+use licenses::build_third_party_licenses_text;
+use licenses::get_license_banner;
+use loader::get_all_specifier_mappers;
 use mappings::Mappings;
 use mappings::SYNTHETIC_SPECIFIERS;
 use mappings::SYNTHETIC_TEST_SPECIFIERS;
+use module_exports::get_entry_point_exports;
+use module_exports::get_star_export_conflict_warnings;
+use parser::ScopeAnalysisParser;
 use polyfills::build_polyfill_file;
 use polyfills::polyfills_for_target;
 use polyfills::Polyfill;
+use provenance::get_module_provenance;
+use specifiers::get_module_classification;
 use specifiers::Specifiers;
+use stats::get_output_stats;
+use stats::get_size_budget_warnings;
+use text_changes::apply_text_changes;
+use trace::log_debug;
+use trace::trace_span;
+use trace::Stopwatch;
+use typescript_paths::get_typescript_paths;
+use utils::canonicalize_file_specifier;
 use utils::get_relative_specifier;
 use utils::prepend_statement_to_text;
+use verify_output::get_output_rewritten_specifiers;
+use verify_output::verify_relative_specifiers;
+use verify_output::RewrittenSpecifiers;
 use visitors::fill_polyfills;
-use visitors::get_deno_comment_directive_text_changes;
-use visitors::get_global_text_changes;
-use visitors::get_import_exports_text_changes;
+use visitors::get_declaration_text_changes;
+use visitors::get_define_text_changes;
+use visitors::get_deno_api_text_changes;
+use visitors::get_deno_env_text_changes;
+use visitors::get_text_changes;
+use visitors::get_unshimmable_api_category;
+use visitors::CommonJsRule;
+use visitors::Defines;
+use visitors::DenoFeatureDetectionRule;
+use visitors::DomLibRule;
 use visitors::FillPolyfillsParams;
+use visitors::GetCommonJsParams;
+use visitors::GetDeclarationTextChangesParams;
+use visitors::GetDefineTextChangesParams;
+use visitors::GetDenoApiTextChangesParams;
+use visitors::GetDenoEnvTextChangesParams;
+use visitors::GetDenoFeatureDetectionWarningsParams;
+use visitors::GetDomLibParams;
 use visitors::GetGlobalTextChangesParams;
 use visitors::GetImportExportsTextChangesParams;
+use visitors::GetServerWarningsParams;
+use visitors::GetTextChangesParams;
+use visitors::GetUnshimmableApiUsagesParams;
+use visitors::ServerRule;
+use visitors::UnshimmableApisRule;
+use visitors::DENO_SERVER_APIS;
 
 pub use deno_ast::ModuleSpecifier;
+pub use error::TransformError;
+pub use graph_info::DependencyInfo;
+pub use graph_info::GraphInfo;
+pub use graph_info::ModuleInfo;
+pub use licenses::THIRD_PARTY_LICENSES_FILE_NAME;
+pub use loader::CallbackLoader;
+#[cfg(feature = "tokio-loader")]
+pub use loader::DefaultLoader;
+#[cfg(feature = "tokio-loader")]
+pub use loader::DefaultLoaderOptions;
+pub use loader::IntegrityCacheLoader;
+pub use loader::LoadRequest;
 pub use loader::LoadResponse;
 pub use loader::Loader;
+pub use loader::MirrorLoader;
+pub use loader::UrlMirror;
+pub use manifest::TransformManifest;
+pub use output_diff::DependencyChange;
+pub use output_diff::ModifiedFile;
+pub use output_diff::OutputDiff;
+#[cfg(feature = "serialization")]
+pub use serializable_options::SerializableTransformOptions;
+pub use specifiers::EnvironmentClassification;
+pub use specifiers::ModuleClassification;
+pub use transform_visitor::TransformVisitor;
+pub use transform_visitor::TransformVisitorContext;
+pub use utils::file_path_to_url;
+pub use utils::resolve_entry_point;
 pub use utils::url_to_file_path;
+pub use workspace::transform_workspace;
+pub use workspace::TransformWorkspaceOptions;
+pub use workspace::TransformWorkspaceOutput;
+pub use workspace::WorkspaceDependency;
+pub use workspace::WorkspaceMember;
+pub use workspace::WorkspaceMemberOutput;
+pub use write::WriteOptions;
+pub use write::WriteResult;
+pub use write::MANIFEST_FILE_NAME;
 
+use crate::declaration_file_resolution::is_declaration_file;
 use crate::declaration_file_resolution::TypesDependency;
+use crate::inline::inline_module;
+use crate::inline::InlineModuleParams;
+use crate::manifest::ManifestBuilder;
+use crate::package::PackageJsonExport;
 use crate::utils::strip_bom;
+use crate::utils::to_js_string_literal;
+use crate::utils::with_extension;
+use crate::utils::with_new_line_kind;
+use crate::utils::NewLineKind;
+use crate::utils::BOM_CHAR;
 
 mod analyze;
+mod barrels;
+mod bundle;
+mod cache;
+mod cycles;
 mod declaration_file_resolution;
+mod duplicate_packages;
+mod error;
 mod graph;
+mod graph_info;
+mod inline;
+mod licenses;
 mod loader;
+mod manifest;
 mod mappings;
+mod minify;
+mod module_exports;
+mod output_diff;
+pub mod package;
 mod parser;
 mod polyfills;
+mod provenance;
+mod remote_policy;
+#[cfg(feature = "serialization")]
+mod serializable_options;
 mod specifiers;
+mod stats;
+pub mod text_changes;
+mod trace;
+mod transform_visitor;
+mod typescript_paths;
 mod utils;
+mod verify_output;
+mod virtual_path;
 mod visitors;
+mod workspace;
+mod write;
 
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 #[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
@@ -75,22 +201,287 @@ pub struct Dependency {
   pub peer_dependency: bool,
 }
 
+/// The result of a transform.
+///
+/// The output is the same on every run with the same inputs, so the
+/// collections are in a stable order that doesn't depend on the order
+/// modules finished loading in.
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 #[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
 #[derive(Debug, PartialEq)]
 pub struct TransformOutput {
   pub main: TransformOutputEnvironment,
   pub test: TransformOutputEnvironment,
+  /// Warnings grouped by kind, with each kind ordered by module.
   pub warnings: Vec<String>,
+  /// Remote JavaScript modules that no declaration file could be found
+  /// for, sorted by specifier.
+  pub untyped_dependencies: Vec<UntypedDependency>,
+  /// Packages that more than one version of is in the module graph,
+  /// sorted by name.
+  pub duplicate_packages: Vec<DuplicatePackage>,
+  /// Declaration files that were selected for the modules that had one
+  /// specified, sorted by the specifier of the code module.
+  pub types: Vec<ResolvedTypes>,
+  /// Modules that were loaded, but excluded from the output because
+  /// nothing reachable from the entry points needed them. Sorted.
+  pub excluded_modules: Vec<ModuleSpecifier>,
+  /// Local files the transform depended on, which is useful for knowing
+  /// what files to watch in order to re-run the transform. Sorted.
+  pub file_dependencies: Vec<PathBuf>,
+  /// Where the directories of the local files were output to, including
+  /// the directory the files that aren't in a configured local root have
+  /// in common. Sorted by directory.
+  pub local_roots: Vec<LocalRoot>,
+  /// Modules that failed to parse, which were left out of the output
+  /// when continuing on parse errors. Sorted by specifier.
+  pub parse_errors: Vec<ParseDiagnostic>,
+  /// Usages of `Deno` APIs that can't be shimmed, which will throw at
+  /// runtime. Sorted by specifier and then position.
+  pub unshimmable_api_usages: Vec<UnshimmableApiUsage>,
+  /// Node `exports` for the main entry points, which can be used in a
+  /// package.json for output with ES modules and CommonJS modules.
+  ///
+  /// The first entry point is exported as `.` and the others by their
+  /// path without the extension, where a `mod` or `index` file is
+  /// exported by its directory (ex. `sub/mod.ts` is exported as `./sub`).
+  pub exports: BTreeMap<String, PackageJsonExport>,
+  /// Where the remote modules that were copied to the output or mapped
+  /// to a package came from when `provenance` is enabled. Sorted by
+  /// specifier.
+  pub provenance: Vec<ModuleProvenance>,
+  /// TypeScript `compilerOptions.paths` alias map from the original
+  /// specifiers of the remote modules to where they were output or the
+  /// package they were mapped to when `typescript_paths` is enabled. The
+  /// paths are relative to the output directory.
+  pub typescript_paths: BTreeMap<String, Vec<String>>,
+  /// Summary of the transform when `emit_manifest` is enabled.
+  pub manifest: Option<TransformManifest>,
+  /// How long the phases of the transform took, which are only measured
+  /// with the `tracing` feature.
+  #[cfg_attr(feature = "serialization", serde(skip))]
+  pub timings: Option<TransformTimings>,
+}
+
+/// How long the phases of a transform took. The per-file phases are
+/// summed across files, so with the `parallel` feature they may add up
+/// to more than the total.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TransformTimings {
+  /// Loading the modules and building the module graph.
+  pub build_graph: Duration,
+  /// Parsing the transformed modules.
+  pub parse: Duration,
+  /// Running the visitors over the transformed modules, including the
+  /// source rewrites and custom visitors.
+  pub visit: Duration,
+  /// Applying the text changes of the visitors.
+  pub apply_text_changes: Duration,
+  pub total: Duration,
+}
+
+/// The declaration file that was selected for a code module.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct ResolvedTypes {
+  pub code_specifier: ModuleSpecifier,
+  pub declaration_specifier: ModuleSpecifier,
+  /// Output file path of the declaration file.
+  pub declaration_path: PathBuf,
+  /// Module that specified the declaration file (ex. with `@deno-types`),
+  /// which is the code module itself for a types reference or header.
+  pub referrer: ModuleSpecifier,
+  /// Declaration files that were also specified for the code module, but
+  /// weren't selected. Sorted by specifier.
+  pub ignored: Vec<IgnoredTypes>,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct IgnoredTypes {
+  pub declaration_specifier: ModuleSpecifier,
+  pub referrer: ModuleSpecifier,
+}
+
+/// Origin of a remote module that was copied to the output or mapped to a
+/// package, which is useful for auditing the third party code in a package.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct ModuleProvenance {
+  /// The url of the module after redirects.
+  pub specifier: ModuleSpecifier,
+  /// Name of the package the module was mapped to.
+  pub mapped_package: Option<String>,
+  /// Version of the mapped package or otherwise the version in the url
+  /// (ex. `0.150.0` for `https://deno.land/std@0.150.0/fs/mod.ts`).
+  pub version: Option<String>,
+  /// SPDX identifier of the license (ex. `MIT`) when it could be detected
+  /// from the license banner of the module or its license file.
+  pub license: Option<String>,
+  /// License file found at the root of the module's package, or in its
+  /// directory when the package root isn't known from the url.
+  pub license_file: Option<ModuleSpecifier>,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct UntypedDependency {
+  /// The module without a declaration file.
+  pub specifier: ModuleSpecifier,
+  /// Chain of modules from an entry point that led to this module.
+  pub referrer_chain: Vec<ModuleSpecifier>,
+}
+
+/// A package that more than one version of is in the module graph.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct DuplicatePackage {
+  /// The url of a remote package without the version
+  /// (ex. `https://deno.land/x/oak`) or the name of a mapped package.
+  pub name: String,
+  /// Sorted by version.
+  pub versions: Vec<DuplicatePackageVersion>,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct DuplicatePackageVersion {
+  pub version: String,
+  /// Directory the remote modules of this version are output to, which
+  /// is `None` when the version is of a package modules were mapped to.
+  pub output_dir: Option<PathBuf>,
+  /// Chain of modules from an entry point that led to this version.
+  pub referrer_chain: Vec<ModuleSpecifier>,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct ParseDiagnostic {
+  pub specifier: ModuleSpecifier,
+  /// 1-indexed line number.
+  pub line_number: usize,
+  /// 1-indexed column number.
+  pub column_number: usize,
+  pub message: String,
+  /// Modules that import the module that failed to parse. Their imports
+  /// of it will point to a file that's not in the output.
+  pub dependents: Vec<ModuleSpecifier>,
+}
+
+/// Usage of a `Deno` API that can't be shimmed in Node.js, such as
+/// `Deno.dlopen`.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnshimmableApiUsage {
+  pub specifier: ModuleSpecifier,
+  /// 1-indexed line number.
+  pub line_number: usize,
+  /// 1-indexed column number.
+  pub column_number: usize,
+  /// Name of the API (ex. `Deno.Command`).
+  pub api: String,
+  pub category: UnshimmableApiCategory,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnshimmableApiCategory {
+  /// Foreign function interface (ex. `Deno.dlopen`).
+  Ffi,
+  /// Subprocesses (ex. `Deno.Command`).
+  Subprocess,
 }
 
 #[cfg_attr(feature = "serialization", derive(serde::Serialize))]
 #[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
 #[derive(Debug, PartialEq, Default)]
 pub struct TransformOutputEnvironment {
+  /// Output file paths of the entry points in the order they were
+  /// specified in.
   pub entry_points: Vec<PathBuf>,
+  /// Sorted by file path.
   pub files: Vec<OutputFile>,
+  /// Sorted by name and then version.
   pub dependencies: Vec<Dependency>,
+  /// Output file paths of code files and their declaration file, sorted
+  /// by the code file path.
+  pub declarations: Vec<(PathBuf, PathBuf)>,
+  /// The shims each file that imports the shim file uses, sorted by file
+  /// path. Files that don't use a shim aren't included.
+  pub shims_used: Vec<FileShimUsage>,
+  /// Statistics about the output files, including the ones that were
+  /// already handed to a sink.
+  pub stats: OutputStats,
+  /// The names each entry point exports, in the same order as the entry
+  /// points. Only analyzed when `entry_point_exports` is enabled.
+  pub entry_point_exports: Vec<EntryPointExports>,
+}
+
+/// The names an entry point exports.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq, Eq)]
+pub struct EntryPointExports {
+  /// Output file path of the entry point.
+  pub file_path: PathBuf,
+  /// Sorted names of the exported values, including `default`.
+  pub values: Vec<String>,
+  /// Sorted names of the exports that are only types (ex. interfaces).
+  pub types: Vec<String>,
+}
+
+/// Statistics about the output files of an environment.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq, Default)]
+pub struct OutputStats {
+  pub file_count: usize,
+  /// Total size of the output files in bytes.
+  pub total_bytes: usize,
+  /// The ten largest remote modules that were output, largest first.
+  pub largest_remote_modules: Vec<RemoteModuleSize>,
+  /// Number of distinct globals and APIs that were replaced with shims.
+  pub shimmed_api_count: usize,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct RemoteModuleSize {
+  pub specifier: ModuleSpecifier,
+  /// Output file path.
+  pub file_path: PathBuf,
+  pub bytes: usize,
+  /// Shortest chain of imports from an entry point to the module, which
+  /// explains why it's in the output.
+  pub referrer_chain: Vec<ModuleSpecifier>,
+}
+
+/// The shims a file in the output uses.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct FileShimUsage {
+  pub specifier: ModuleSpecifier,
+  /// Output file path.
+  pub file_path: PathBuf,
+  /// Names of the globals that were replaced with their shims (ex. `Deno`).
+  /// A reference to `globalThis` that was replaced with the object of all
+  /// the shimmed globals is `globalThis` and a server API is `Deno.<api>`.
+  /// Sorted.
+  pub globals: Vec<String>,
+  /// Names of the packages, or the modules, of the shims that provide the
+  /// globals (ex. `@deno/shim-deno`). Sorted.
+  pub shims: Vec<String>,
 }
 
 #[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
@@ -101,6 +492,8 @@ pub struct TransformOutputEnvironment {
 #[derive(Clone, Debug)]
 pub enum MappedSpecifier {
   Package(PackageMappedSpecifier),
+  /// Another module to use instead, such as a local copy of a remote
+  /// module, which is output where the remote module would've been.
   Module(ModuleSpecifier),
 }
 
@@ -118,6 +511,10 @@ pub struct PackageMappedSpecifier {
   /// If this is suggested to be a peer dependency.
   #[serde(default)]
   pub peer_dependency: bool,
+  /// Npm package that has the type declarations for this package
+  /// (ex. `@types/node`). When provided, it's added as a dependency and
+  /// any `@deno-types` declaration file for the specifier is not output.
+  pub types_package: Option<Dependency>,
 }
 
 impl PackageMappedSpecifier {
@@ -202,6 +599,304 @@ impl ModuleShim {
   }
 }
 
+/// Packages to shim the Node.js globals with when they're found in the code.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Debug, Default)]
+pub struct NodeGlobalShims {
+  /// Package to import `Buffer` from (ex. the `buffer` package).
+  pub buffer: Option<PackageMappedSpecifier>,
+  /// Package whose default export is used for `process` (ex. the
+  /// `process` package).
+  pub process: Option<PackageMappedSpecifier>,
+}
+
+/// Package to replace the server APIs of the `Deno` namespace with
+/// (`Deno.serve`, `Deno.listen`, and `Deno.serveHttp`).
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Debug)]
+pub struct ServerShim {
+  pub package: PackageMappedSpecifier,
+  /// Names of the package's exports by the name of the API they replace
+  /// (ex. `serve`). An API uses the export with the same name by default.
+  #[cfg_attr(feature = "serialization", serde(default))]
+  pub export_names: BTreeMap<String, String>,
+}
+
+impl ServerShim {
+  pub fn export_name<'a>(&'a self, api: &'a str) -> &'a str {
+    self
+      .export_names
+      .get(api)
+      .map(|n| n.as_str())
+      .unwrap_or(api)
+  }
+}
+
+/// The extension of the relative specifiers that are rewritten to point
+/// at the output files. Declaration files are always imported without
+/// their extension.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelativeSpecifierExtension {
+  /// `./mod.js`, which is what tsc expects when emitting ES modules for
+  /// Node.js (ex. `"moduleResolution": "nodenext"`).
+  Js,
+  /// The extension of the output file (ex. `./mod.ts`), for tools that
+  /// import TypeScript files directly (ex. `allowImportingTsExtensions`).
+  Preserve,
+  /// No extension (ex. `./mod`), for bundlers that resolve it.
+  Strip,
+}
+
+impl Default for RelativeSpecifierExtension {
+  fn default() -> Self {
+    RelativeSpecifierExtension::Js
+  }
+}
+
+/// How the versions of the dependencies are written in the output.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VersionPolicy {
+  /// Keep the versions as they were specified or inferred.
+  Preserve,
+  /// Pin the dependencies to a single version (ex. `^1.2.3` to `1.2.3`),
+  /// which is common for applications.
+  Exact,
+  /// Allow compatible minor and patch versions (ex. `1.2.3` to `^1.2.3`),
+  /// which is common for libraries.
+  Caret,
+  /// Allow compatible patch versions (ex. `1.2.3` to `~1.2.3`).
+  Tilde,
+}
+
+impl Default for VersionPolicy {
+  fn default() -> Self {
+    VersionPolicy::Preserve
+  }
+}
+
+/// How the modules that use a shimmed global import the shim file.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShimImportStyle {
+  /// `import * as dntShim from "./_dnt.shims.js";` with the globals
+  /// accessed on it (ex. `dntShim.Deno`).
+  Namespace,
+  /// `import { Deno } from "./_dnt.shims.js";` with the globals left as is.
+  Named,
+  /// `import "./_dnt.shims.js";` with the shim file installing the globals
+  /// on `globalThis`, so the globals are left as is. The types of the
+  /// globals must be declared globally for type checking.
+  SideEffect,
+}
+
+impl Default for ShimImportStyle {
+  fn default() -> Self {
+    ShimImportStyle::Namespace
+  }
+}
+
+/// What the checks for whether the code is running in Deno check once the
+/// `Deno` shim is used (ex. `typeof Deno !== "undefined"`,
+/// `typeof globalThis.Deno`, or `"Deno" in globalThis`).
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DenoFeatureDetection {
+  /// Leave the checks referencing the global `Deno`, so the code they
+  /// guard only runs in Deno.
+  Native,
+  /// Check the `Deno` shim, which is always defined, so the code they
+  /// guard also runs in Node.js.
+  Shim,
+}
+
+impl Default for DenoFeatureDetection {
+  fn default() -> Self {
+    DenoFeatureDetection::Native
+  }
+}
+
+/// What to do with the JavaScript modules that are CommonJS, which is
+/// detected from their syntax (ex. `module.exports` or `require()` without
+/// any import or export declarations) or their `.cjs` extension.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommonJsModules {
+  /// Error because the output is ES modules.
+  Error,
+  /// Wrap the code in a function that provides `module`, `exports`, and
+  /// `require` (using `createRequire` from "node:module"), with
+  /// `module.exports` as the default export.
+  Wrap,
+}
+
+impl Default for CommonJsModules {
+  fn default() -> Self {
+    CommonJsModules::Error
+  }
+}
+
+/// How the test environment imports the modules of the main environment,
+/// which are only output once so that the tests use the same instances
+/// of the classes and values as the main code.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(
+  feature = "serialization",
+  serde(tag = "kind", content = "value", rename_all = "camelCase")
+)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TestMainImports {
+  /// Import the main environment's output file by its relative path.
+  Relative,
+  /// Import the main entry points by the name of the package (ex.
+  /// `my-package/sub`), which Node resolves to the package itself through
+  /// the `exports` of its package.json. The main modules that aren't
+  /// entry points are imported by their relative path.
+  SelfReference(String),
+}
+
+impl Default for TestMainImports {
+  fn default() -> Self {
+    TestMainImports::Relative
+  }
+}
+
+/// How the shimmed globals that are accessed on the global object (ex.
+/// `globalThis.Deno.readTextFile()`, `window.Deno`, or `self.Deno`) are
+/// output. `window` and `self` are always changed to `globalThis`.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QualifiedGlobalAccess {
+  /// Access them on the shim's global object, which has the shimmed
+  /// globals merged into it (ex. `dntShim.dntGlobalThis.Deno`).
+  GlobalObject,
+  /// Replace the access with the shimmed global, the same as an
+  /// unqualified reference to it (ex. `dntShim.Deno`). Assignments, and
+  /// names declared in the module with a non-namespace shim import style,
+  /// stay on the shim's global object.
+  Shim,
+  /// Leave them accessing the runtime's global object.
+  Preserve,
+}
+
+impl Default for QualifiedGlobalAccess {
+  fn default() -> Self {
+    QualifiedGlobalAccess::GlobalObject
+  }
+}
+
+/// What to do with a kind of comment directive in the output.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommentDirectiveAction {
+  /// Leave the comment as is.
+  Keep,
+  /// Remove the comment.
+  Remove,
+  /// Change the file the comment points at to its output location. The
+  /// comment is removed when the file isn't output.
+  Rewrite,
+}
+
+/// How the Deno specific comment directives are handled in the output.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(default, rename_all = "camelCase"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommentDirectiveOptions {
+  /// `// @deno-types="./mod.d.ts"` and `// @ts-types="./mod.d.ts"`
+  /// comments, which are removed by default.
+  pub deno_types: CommentDirectiveAction,
+  /// `// @ts-self-types="./mod.d.ts"` comments, which are removed by
+  /// default because the declaration file is output next to the module.
+  pub ts_self_types: CommentDirectiveAction,
+  /// `// deno-lint-ignore` and `// deno-lint-ignore-file` comments, which
+  /// are kept by default. Rewriting translates them to ESLint directives
+  /// (ex. `// eslint-disable-next-line no-var`).
+  pub deno_lint_ignore: CommentDirectiveAction,
+  /// `// deno-fmt-ignore` and `// deno-fmt-ignore-file` comments, which
+  /// are kept by default. Rewriting translates `deno-fmt-ignore` to
+  /// `prettier-ignore` and removes `deno-fmt-ignore-file`, which Prettier
+  /// has no comment for.
+  pub deno_fmt_ignore: CommentDirectiveAction,
+  /// ESLint rule names by the Deno lint rule name they replace when
+  /// rewriting `deno-lint-ignore` comments, which take precedence over
+  /// the built-in ones (ex. `no-explicit-any` is
+  /// `@typescript-eslint/no-explicit-any`). Rules without an ESLint name
+  /// are left out, and a comment is removed when none of its rules have
+  /// one.
+  pub eslint_rules: BTreeMap<String, String>,
+}
+
+impl Default for CommentDirectiveOptions {
+  fn default() -> Self {
+    Self {
+      deno_types: CommentDirectiveAction::Remove,
+      ts_self_types: CommentDirectiveAction::Remove,
+      deno_lint_ignore: CommentDirectiveAction::Keep,
+      deno_fmt_ignore: CommentDirectiveAction::Keep,
+      eslint_rules: BTreeMap::new(),
+    }
+  }
+}
+
+/// What to do with the attributes of an import (ex. `with { type: "json" }`)
+/// in the output.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportAttributeAction {
+  /// Remove the attributes.
+  Strip,
+  /// Keep the attributes, changing the deprecated `assert` keyword to
+  /// `with`.
+  With,
+  /// Leave the attributes as they are.
+  Keep,
+}
+
+impl Default for ImportAttributeAction {
+  fn default() -> Self {
+    ImportAttributeAction::Strip
+  }
+}
+
+/// How the attributes of static and dynamic imports and re-exports are
+/// handled in the output, based on their `type` attribute.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(default, rename_all = "camelCase"))]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImportAttributeOptions {
+  /// Actions for the attributes by the value of their `type` attribute
+  /// (ex. `json` or `text`).
+  pub types: BTreeMap<String, ImportAttributeAction>,
+  /// Action for the attributes whose type isn't in `types` or that don't
+  /// have a type that's a string literal, which strips them by default.
+  pub default: ImportAttributeAction,
+}
+
+impl ImportAttributeOptions {
+  /// Gets the action for the attributes with the `type` attribute.
+  pub fn get_action(
+    &self,
+    attribute_type: Option<&str>,
+  ) -> ImportAttributeAction {
+    attribute_type
+      .and_then(|attribute_type| self.types.get(attribute_type))
+      .copied()
+      .unwrap_or(self.default)
+  }
+}
+
 // make sure to update `ScriptTarget` in the TS code when changing the names on this
 #[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
 #[derive(Clone, Copy, Debug)]
@@ -218,37 +913,602 @@ pub enum ScriptTarget {
   Latest = 9,
 }
 
+/// How JSX is transformed, which is the `compilerOptions.jsx` of a
+/// deno.json file.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsxRuntime {
+  /// `react`
+  #[cfg_attr(feature = "serialization", serde(rename = "react"))]
+  Classic,
+  /// `react-jsx`
+  #[cfg_attr(feature = "serialization", serde(rename = "react-jsx"))]
+  Automatic,
+  /// `react-jsxdev`
+  #[cfg_attr(feature = "serialization", serde(rename = "react-jsxdev"))]
+  AutomaticDev,
+  /// `precompile`
+  #[cfg_attr(feature = "serialization", serde(rename = "precompile"))]
+  Precompile,
+}
+
+impl JsxRuntime {
+  /// Gets if the JSX runtime is imported from the import source.
+  pub fn is_automatic(&self) -> bool {
+    !matches!(self, JsxRuntime::Classic)
+  }
+
+  /// Gets the module of the import source that the runtime is imported
+  /// from (ex. `preact/jsx-runtime`).
+  pub(crate) fn import_source_module(&self) -> &'static str {
+    match self {
+      JsxRuntime::AutomaticDev => "jsx-dev-runtime",
+      JsxRuntime::Classic | JsxRuntime::Automatic | JsxRuntime::Precompile => {
+        "jsx-runtime"
+      }
+    }
+  }
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsxOptions {
+  pub runtime: JsxRuntime,
+  /// Specifier the automatic runtime is imported from (ex.
+  /// `https://esm.sh/preact`), which is the `compilerOptions.jsxImportSource`
+  /// of a deno.json file.
+  pub import_source: Option<String>,
+}
+
+/// Limits on the size of the main output, which are reported as warnings
+/// when they're exceeded.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SizeBudget {
+  /// Maximum total size of the output files in bytes.
+  pub max_total_bytes: Option<usize>,
+  /// Maximum size of a single output file in bytes.
+  pub max_file_bytes: Option<usize>,
+}
+
+/// Restrictions on the remote modules that may be loaded, which are
+/// errors with the chain of imports that led to the module when they're
+/// violated.
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(default, rename_all = "camelCase"))]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RemotePolicy {
+  /// Origins remote modules may be loaded from (ex. `https://deno.land`).
+  /// Any origin is allowed when this is empty.
+  pub allowed_origins: Vec<String>,
+  /// Origins remote modules may not be loaded from, which takes
+  /// precedence over the allowed origins. Modules from these origins are
+  /// never requested.
+  pub denied_origins: Vec<String>,
+  /// Maximum number of remote modules a chain of imports from an entry
+  /// point may go through (ex. `1` only allows the remote modules that
+  /// local modules import).
+  pub max_depth: Option<usize>,
+}
+
+/// Directory of local files and the directory in the output they're
+/// output to.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LocalRoot {
+  /// Url of the directory (ex. `file:///project/shared/src/`).
+  pub dir: ModuleSpecifier,
+  /// Path in the output relative to the output root (ex. `shared`), which
+  /// is empty for the output root.
+  pub output_dir: PathBuf,
+}
+
 pub struct TransformOptions {
+  /// Entry points of the main code. Use `resolve_entry_point` to get
+  /// these from file paths.
   pub entry_points: Vec<ModuleSpecifier>,
   pub test_entry_points: Vec<ModuleSpecifier>,
   pub shims: Vec<Shim>,
   pub test_shims: Vec<Shim>,
+  /// Loader for the modules. Defaults to a `DefaultLoader`, which can be
+  /// created with `DefaultLoader::with_options` to configure its requests.
   pub loader: Option<Box<dyn Loader>>,
-  /// Maps specifiers to an npm package or module.
+  /// Maps specifiers to an npm package or module. These take precedence
+  /// over the `@dnt-map` directives in the source (ex.
+  /// `// @dnt-map: npm:chalk@^5` above an import).
   pub specifier_mappings: HashMap<ModuleSpecifier, MappedSpecifier>,
+  /// Maps code specifiers to a declaration file that should be used
+  /// for them. This overrides any `@deno-types` or `X-TypeScript-Types`
+  /// declaration file.
+  pub declaration_mappings: HashMap<ModuleSpecifier, ModuleSpecifier>,
   /// Version of ECMAScript that the final code will target.
   /// This controls whether certain polyfills should occur.
   pub target: ScriptTarget,
   /// Optional import map.
   pub import_map: Option<ModuleSpecifier>,
+  /// How the JSX in `.jsx` and `.tsx` modules is transformed. The import
+  /// source is mapped like any other import and written to the output
+  /// modules as a `@jsxImportSource` pragma.
+  pub jsx: Option<JsxOptions>,
+  /// Error instead of warning when circular dependencies are found.
+  pub error_on_cycles: bool,
+  /// Error instead of warning when more than one version of a package is
+  /// in the module graph.
+  pub error_on_duplicate_packages: bool,
+  /// Leave modules that fail to parse out of the output and report them
+  /// in the output instead of erroring.
+  pub continue_on_parse_error: bool,
+  /// Use `\n` line endings and remove any byte order mark in the output
+  /// files instead of keeping the ones of the original files.
+  pub normalize_output_text: bool,
+  /// Map imports of files on GitHub (`https://raw.githubusercontent.com/...`)
+  /// to a git dependency on their repository instead of outputting them.
+  pub github_dependencies: bool,
+  /// Restrict the origins remote modules are loaded from and how deep
+  /// into remote modules the imports go.
+  pub remote_policy: Option<RemotePolicy>,
+  /// How the versions of the dependencies are written in the output.
+  /// Versions that aren't a single version or a caret or tilde range of
+  /// one (ex. `>=1.0.0 <2.0.0`, dist tags, or git urls) are kept as they
+  /// are. Versions that are only partially specified (ex. `^1.2`) can't
+  /// be pinned exactly, so they're also kept for `Exact`.
+  pub version_policy: VersionPolicy,
+  /// Support importing CSS and text files with an import assertion
+  /// (ex. `import styles from "./styles.css" assert { type: "css" }`).
+  /// The import is changed to a module that exports the text of the file,
+  /// which is output along with a copy of the file.
+  pub asset_imports: bool,
+  /// Handlers for the files imported with other `type` attributes by the
+  /// type (ex. `sql` for `with { type: "sql" }`), which produce the module
+  /// the imports point to. These take precedence over `asset_imports`.
+  pub asset_handlers: HashMap<String, Box<dyn AssetHandler>>,
+  /// File names to try in a directory (ex. `["mod.ts", "index.ts"]`) when
+  /// a local import without an extension (ex. `./utils` or `./utils/`)
+  /// isn't found. Each import that's expanded is reported as a warning.
+  pub directory_index_files: Vec<String>,
+  /// Directories of local files to output to a directory of the output,
+  /// which gives local files from disjoint directories (ex. `../shared/src`
+  /// and `./src`) a predictable layout. A file in more than one of them is
+  /// output with the most specific one. The other local files are output
+  /// relative to the directory they have in common.
+  pub local_roots: Vec<LocalRoot>,
+  /// Shims to add for `Buffer` and `process` when they aren't shimmed
+  /// already. References to these and other CommonJS globals that aren't
+  /// shimmed are reported as warnings.
+  pub node_global_shims: NodeGlobalShims,
+  /// Package with the types of the DOM (ex. `@types/web`), which is added
+  /// as a dependency when the modules use DOM APIs such as `document` or
+  /// `HTMLElement`. The `/// <reference lib="dom" />` comments are changed
+  /// to reference its types. Without it, modules that use DOM APIs without
+  /// referencing the DOM lib are reported as warnings.
+  pub dom_types_package: Option<Dependency>,
+  /// Declare `__dirname` and `__filename` using `import.meta.url` in the
+  /// modules that use them. This can also be enabled for a single module
+  /// with a `// dnt-inject-dirname` comment.
+  pub inject_dirname: bool,
+  /// What to do with the JavaScript modules that are CommonJS, which is
+  /// an error by default. Their `.cjs` extension is changed to `.js`
+  /// since they're output as ES modules.
+  pub commonjs_modules: CommonJsModules,
+  /// Shim for `Deno.serve`, `Deno.listen`, and `Deno.serveHttp`, which are
+  /// otherwise reported as unsupported.
+  pub server_shim: Option<ServerShim>,
+  /// How the `@deno-types`, `@ts-self-types`, and `deno-lint-ignore`
+  /// comments are handled in the output.
+  pub comment_directives: CommentDirectiveOptions,
+  /// How the attributes of imports (ex. `with { type: "text" }`) are
+  /// handled in the output. They're stripped by default.
+  ///
+  /// The attributes of imports of modules in the output are always
+  /// stripped because the modules are output as JavaScript (ex. a JSON
+  /// module is output as `data.js`), so this applies to the imports of
+  /// packages and other modules that aren't in the output.
+  pub import_attributes: ImportAttributeOptions,
+  /// Inline the remote modules up to this size in bytes into the module
+  /// that imports them when it's the only one, which reduces the number
+  /// of files in the output. Only modules without imports or top level
+  /// statements other than declarations are inlined.
+  pub inline_remote_module_max_size: Option<usize>,
+  /// Output a single file for each entry point that contains the code of
+  /// every module it statically imports instead of a file per module.
+  ///
+  /// Imports of npm packages and other modules that aren't in the output
+  /// are kept. Declaration files are output as they are otherwise, so
+  /// bundling their types is left to the caller.
+  pub bundle: bool,
+  /// Replace the `export * from` statements that point to barrel modules,
+  /// which only consist of re-exports, with direct re-exports of the
+  /// modules the barrels re-export. This reduces how many modules are
+  /// resolved to get to an export and helps bundlers tree shake the
+  /// published package.
+  ///
+  /// A barrel is only flattened into a module when the module exports the
+  /// same names afterwards. The barrels are kept in the output for the
+  /// modules that import them.
+  pub flatten_barrels: bool,
+  /// Minify the JavaScript files of the output by removing their
+  /// whitespace and comments and shortening their local identifiers. The
+  /// TypeScript files are left as is.
+  pub minify: bool,
+  /// Analyze the names each entry point exports and report them in the
+  /// `entry_point_exports` of the output environments, which is useful
+  /// for generating the `exports` of a package.json or listing the
+  /// public API.
+  pub entry_point_exports: bool,
+  /// Output only the type declarations of the TypeScript modules, which
+  /// is useful for publishing a types only package or generating
+  /// documentation. The bodies of functions, methods, and accessors, the
+  /// values of variables and properties, and the statements other than
+  /// declarations are removed, and the declarations that remain are made
+  /// ambient with `declare`. Declarations whose type can't be determined
+  /// without their value are declared as `any` and reported as warnings.
+  ///
+  /// The output files keep their paths and imports, so they can be type
+  /// checked and compiled to declaration files like the full output.
+  /// JavaScript modules are output as they are.
+  pub declarations_only: bool,
+  /// Collect the license banners at the top of the remote modules into a
+  /// `THIRD_PARTY_LICENSES` file in the main output.
+  pub third_party_licenses: bool,
+  /// Record the origin url, version, and license of each remote module in
+  /// the `provenance` of the output. Finding the licenses loads the
+  /// `LICENSE` files next to the remote packages.
+  pub provenance: bool,
+  /// Expressions to replace with literal values, which allows building
+  /// for a specific target without checking the environment at runtime.
+  ///
+  /// The keys are globals, property accesses, or calls with string
+  /// arguments (ex. `import.meta.env.MODE` or `Deno.env.get("TARGET")`)
+  /// and the values are string, number, boolean, null, or undefined
+  /// literals (ex. `"production"` or `true`). Expressions aren't replaced
+  /// where they're assigned to or where the global is shadowed.
+  pub define: HashMap<String, String>,
+  /// Rewrite `Deno.env.get("X")`, `.set`, `.has`, `.delete`, and
+  /// `.toObject()` calls to use `process.env` directly instead of the
+  /// `Deno` shim, so packages that only use the environment variables
+  /// don't need the shim. Requires the Node.js types for type checking.
+  pub deno_env_to_process_env: bool,
+  /// Translate the simple calls of common Deno APIs (ex. `Deno.readTextFile`,
+  /// `Deno.writeTextFile`, `Deno.stat`, `Deno.cwd`, and `Deno.exit`) to
+  /// their `node:fs/promises` and `node:process` equivalents. Calls that
+  /// can't be translated use the `Deno` shim. Requires the Node.js types
+  /// for type checking.
+  pub translate_deno_apis: bool,
+  /// How the modules that use a shimmed global import the shim file,
+  /// which is a namespace import by default.
+  pub shim_import_style: ShimImportStyle,
+  /// What the checks for whether the code is running in Deno check, which
+  /// is the global `Deno` by default. A warning is emitted for each check.
+  pub deno_feature_detection: DenoFeatureDetection,
+  /// How the shimmed globals that are accessed on the global object (ex.
+  /// `globalThis.Deno`) are output, which is on the shim's global object
+  /// by default.
+  pub qualified_global_access: QualifiedGlobalAccess,
+  /// The extension of the rewritten relative specifiers, which is `.js`
+  /// by default.
+  pub relative_specifier_extension: RelativeSpecifierExtension,
+  /// The extension of the rewritten relative specifiers in the test
+  /// environment when it differs from `relative_specifier_extension`.
+  pub test_relative_specifier_extension: Option<RelativeSpecifierExtension>,
+  /// How the test environment imports the modules of the main environment.
+  pub test_main_imports: TestMainImports,
+  /// Add a comment with the original url next to each import or export
+  /// of a remote module that was rewritten (ex. `// was: https://...`).
+  pub annotate_rewrites: bool,
+  /// Add a short hash of the source to the file names of the remote
+  /// modules (ex. `deps/deno.land/x/mod.1a2b3c4d.ts`), which is useful for
+  /// caching the files long term. The relative specifiers point at the
+  /// hashed names.
+  pub hash_remote_file_names: bool,
+  /// Provide a TypeScript `compilerOptions.paths` alias map for the
+  /// remote modules in the output, so that type checking the output with
+  /// `tsc` resolves their original specifiers.
+  pub typescript_paths: bool,
+  /// Provide a manifest summarizing the transform in the output.
+  pub emit_manifest: bool,
+  /// Warn when the main output is over these limits.
+  pub size_budget: Option<SizeBudget>,
+  /// Optional directory to cache the transformed modules in. When
+  /// provided, later transforms only transform the modules that changed.
+  pub cache_dir: Option<PathBuf>,
+  /// Custom passes to run on each module along with the built-in passes.
+  pub custom_visitors: Vec<Box<dyn TransformVisitor>>,
+  /// Optional handler for adjusting the text of each output file.
+  pub file_text_handler: Option<Box<dyn FileTextHandler>>,
+  /// Optional handler for choosing the version of each dependency, which
+  /// is called after the `version_policy` is applied.
+  pub version_handler: Option<Box<dyn VersionHandler>>,
 }
 
 struct EnvironmentContext<'a> {
+  output_environment: OutputEnvironment,
   environment: TransformOutputEnvironment,
-  searching_polyfills: Vec<Box<dyn Polyfill>>,
   found_polyfills: Vec<Box<dyn Polyfill>>,
   shim_file_specifier: &'a ModuleSpecifier,
   shim_global_names: HashSet<&'a str>,
   shims: &'a Vec<Shim>,
   used_shim: bool,
+  relative_specifier_extension: RelativeSpecifierExtension,
+  /// Bare specifiers that modules are imported by instead of the path of
+  /// their output file.
+  package_specifier_mappings: &'a HashMap<ModuleSpecifier, String>,
+  /// Paths and sizes of the files that were already handed to the sink.
+  sink_file_sizes: Vec<(PathBuf, usize)>,
+  /// Remote modules by their output file path.
+  remote_modules: HashMap<PathBuf, ModuleSpecifier>,
+  /// Shortest chains of imports from the environment's entry points.
+  referrers: ShortestReferrers,
+}
+
+/// The environment an output file is for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputEnvironment {
+  Main,
+  Test,
+}
+
+/// Receives the output files as they're transformed.
+pub trait OutputSink: Sync {
+  fn write_file(
+    &self,
+    environment: OutputEnvironment,
+    file: OutputFile,
+  ) -> Result<()>;
+}
+
+/// Adjusts the text of each output file before it's added to its output
+/// environment, which is useful for adding banners or license headers.
+///
+/// Entry points are handled after the polyfill import is added to them.
+/// The file path should not be changed.
+pub trait FileTextHandler: Sync {
+  fn handle_file_text(
+    &self,
+    environment: OutputEnvironment,
+    specifier: &ModuleSpecifier,
+    file: &mut OutputFile,
+  ) -> Result<()>;
 }
 
-pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
+/// Chooses the version range a dependency is output with, which allows a
+/// different publishing policy for each dependency.
+pub trait VersionHandler: Sync {
+  /// Gets the version for the dependency, which has the version the
+  /// `version_policy` chose.
+  fn handle_version(
+    &self,
+    environment: OutputEnvironment,
+    dependency: &Dependency,
+  ) -> Result<String>;
+}
+
+/// Produces the module for the files imported with a `type` attribute
+/// that the transform doesn't support (ex. `with { type: "sql" }`), which
+/// allows supporting the module types of custom loaders.
+pub trait AssetHandler: Sync {
+  fn handle_asset(&self, asset: &AssetFile) -> Result<AssetOutput>;
+}
+
+/// A file imported with the `type` attribute of an asset handler.
+pub struct AssetFile<'a> {
+  pub specifier: &'a ModuleSpecifier,
+  /// Value of the `type` attribute (ex. `sql`).
+  pub attribute_type: &'a str,
+  pub text: &'a str,
+}
+
+/// What a file imported with the `type` attribute of an asset handler is
+/// output as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetOutput {
+  /// Text of the module that the imports of the file point to (ex.
+  /// `export default "SELECT 1";`). Its imports aren't rewritten.
+  pub module_text: String,
+  /// Copy the file next to the module (ex. `query.sql` for
+  /// `query.sql.js`), such as for reading it at runtime.
+  pub copy_file: bool,
+}
+
+pub async fn transform(
+  options: TransformOptions,
+) -> Result<TransformOutput, TransformError> {
+  Ok(transform_inner(options, None).await?)
+}
+
+/// Transforms while handing over each output file to the sink as soon
+/// as it's ready instead of storing it in the returned output, so the
+/// output environments will have no files.
+///
+/// Entry points, along with the polyfill and shim files, are handed over
+/// last because they're only known once every file has been transformed.
+pub async fn transform_with_sink(
+  options: TransformOptions,
+  sink: &dyn OutputSink,
+) -> Result<TransformOutput, TransformError> {
+  Ok(transform_inner(options, Some(sink)).await?)
+}
+
+/// Builds the module graph and gets information about its modules
+/// without transforming them.
+pub async fn analyze(
+  mut options: TransformOptions,
+) -> Result<GraphInfo, TransformError> {
+  let loader = take_loader(&mut options);
+  let (module_graph, specifiers) = build_module_graph(&options, loader).await?;
+  let mappings = Mappings::new(
+    &module_graph,
+    &specifiers,
+    &options.local_roots,
+    options.hash_remote_file_names,
+  )?;
+  Ok(get_graph_info(
+    &module_graph,
+    &specifiers,
+    &mappings,
+    &options.entry_points,
+    &options.test_entry_points,
+  ))
+}
+
+/// Builds the module graph and gets which modules are output to which
+/// environment and which are mapped to packages without transforming
+/// them, which is useful for tasks like type checking only the modules
+/// of an environment.
+pub async fn classify_modules(
+  mut options: TransformOptions,
+) -> Result<ModuleClassification, TransformError> {
+  let loader = take_loader(&mut options);
+  let (_, specifiers) = build_module_graph(&options, loader).await?;
+  Ok(get_module_classification(&specifiers))
+}
+
+/// What an import is mapped to in the output.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MappedResult {
+  /// An npm package or a Node.js module, imported by the specifier
+  /// (ex. `preact/hooks` or `fs`).
+  Package {
+    specifier: String,
+    mapping: PackageMappedSpecifier,
+  },
+  /// Another module that's used instead, such as a local copy of a
+  /// remote module.
+  Module(ModuleSpecifier),
+  /// The module itself, which is output.
+  Unmapped(ModuleSpecifier),
+}
+
+/// Gets what an import in a module is mapped to in the output without
+/// building the module graph, which is useful for showing what an import
+/// becomes in an editor or linter.
+///
+/// The specifier mappings of the options and the mappings of the CDN and
+/// `deno.land/std/node` urls to packages are applied, but not the import
+/// map or the `@dnt-map` comment directives, which are only known when
+/// building the module graph.
+pub fn resolve_mapped_specifier(
+  raw: &str,
+  referrer: &ModuleSpecifier,
+  options: &TransformOptions,
+) -> Result<MappedResult, TransformError> {
+  let specifier = deno_graph::resolve_import(raw, referrer).map_err(|err| {
+    TransformError::ModuleGraph {
+      specifier: referrer.clone(),
+      referrer: None,
+      message: format!("Could not resolve \"{}\". {}", raw, err),
+    }
+  })?;
+  if let Some(mapping) = options.specifier_mappings.get(&specifier) {
+    return Ok(match mapping {
+      MappedSpecifier::Package(mapping) => MappedResult::Package {
+        specifier: mapping.module_specifier_text(),
+        mapping: mapping.clone(),
+      },
+      MappedSpecifier::Module(specifier) => {
+        MappedResult::Module(specifier.clone())
+      }
+    });
+  }
+  let mapping = get_all_specifier_mappers(options.github_dependencies)
+    .iter()
+    .find_map(|mapper| mapper.map(&specifier));
+  Ok(match mapping {
+    Some(mapping) => MappedResult::Package {
+      specifier: mapping.module_specifier_text(),
+      mapping,
+    },
+    None => MappedResult::Unmapped(specifier),
+  })
+}
+
+/// Takes the loader from the options. When there is none, the files are
+/// loaded from the file system so the `file:` entry points are
+/// canonicalized to prevent the same file being loaded as separate modules.
+fn take_loader(options: &mut TransformOptions) -> Option<Box<dyn Loader>> {
+  let loader = options.loader.take();
+  if loader.is_none() {
+    for entry_points in
+      [&mut options.entry_points, &mut options.test_entry_points]
+    {
+      *entry_points = std::mem::take(entry_points)
+        .into_iter()
+        .map(canonicalize_file_specifier)
+        .collect();
+    }
+  }
+  loader
+}
+
+/// Adds the shims of the Node.js globals to both environments, unless
+/// another shim already provides the global.
+fn add_node_global_shims(options: &mut TransformOptions) {
+  let node_global_shims = [
+    (
+      "Buffer",
+      "Buffer",
+      options.node_global_shims.buffer.as_ref(),
+    ),
+    (
+      "process",
+      "default",
+      options.node_global_shims.process.as_ref(),
+    ),
+  ];
+  for (name, export_name, package) in node_global_shims {
+    let package = match package {
+      Some(package) => package,
+      None => continue,
+    };
+    for shims in [&mut options.shims, &mut options.test_shims] {
+      let has_global = shims
+        .iter()
+        .any(|s| s.global_names().iter().any(|n| n.name == name));
+      if !has_global {
+        shims.push(Shim::Package(PackageShim {
+          package: package.clone(),
+          types_package: None,
+          global_names: vec![GlobalName {
+            name: name.to_string(),
+            export_name: Some(export_name.to_string()),
+            type_only: false,
+          }],
+        }));
+      }
+    }
+  }
+}
+
+async fn build_module_graph(
+  options: &TransformOptions,
+  loader: Option<Box<dyn Loader>>,
+) -> Result<(crate::graph::ModuleGraph, Specifiers)> {
   if options.entry_points.is_empty() {
-    anyhow::bail!("at least one entry point must be specified");
+    bail!(TransformError::InvalidOptions {
+      message: "at least one entry point must be specified".to_string(),
+    });
   }
 
-  let (module_graph, specifiers) =
+  validate_package_mappings(options)?;
+  validate_local_roots(&options.local_roots)?;
+
+  let loader = loader.unwrap_or_else(|| {
+    #[cfg(feature = "tokio-loader")]
+    return Box::new(crate::loader::DefaultLoader::new());
+    #[cfg(not(feature = "tokio-loader"))]
+    panic!("You must provide a loader or use the 'tokio-loader' feature.")
+  });
+  validate_entry_points(
+    &options.entry_points,
+    &options.test_entry_points,
+    &*loader,
+  )
+  .await?;
+
+  let build_graph =
     crate::graph::ModuleGraph::build_with_specifiers(ModuleGraphOptions {
       entry_points: options
         .entry_points
@@ -268,12 +1528,187 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
         )
         .collect(),
       specifier_mappings: &options.specifier_mappings,
-      loader: options.loader,
-      import_map: options.import_map,
+      declaration_mappings: &options.declaration_mappings,
+      loader,
+      import_map: options.import_map.clone(),
+      jsx: options.jsx.as_ref(),
+      continue_on_parse_error: options.continue_on_parse_error,
+      github_dependencies: options.github_dependencies,
+      asset_imports: options.asset_imports,
+      asset_handler_types: options.asset_handlers.keys().cloned().collect(),
+      directory_index_files: &options.directory_index_files,
+      provenance: options.provenance,
+      remote_policy: options.remote_policy.as_ref(),
+    });
+  #[cfg(feature = "tracing")]
+  let build_graph = tracing::Instrument::instrument(
+    build_graph,
+    tracing::info_span!("build_graph"),
+  );
+  build_graph.await
+}
+
+/// Checks that the packages being mapped to have valid names and versions
+/// so that they don't end up in unusable dependency lists.
+fn validate_package_mappings(options: &TransformOptions) -> Result<()> {
+  let mut packages = Vec::new();
+  let mut specifier_mappings =
+    options.specifier_mappings.iter().collect::<Vec<_>>();
+  specifier_mappings.sort_by(|a, b| a.0.cmp(b.0));
+  for (specifier, mapped) in specifier_mappings {
+    if let MappedSpecifier::Package(package) = mapped {
+      packages.push((format!("mapping for {}", specifier), package, None));
+    }
+  }
+  if let Some(server_shim) = &options.server_shim {
+    packages.push(("server shim".to_string(), &server_shim.package, None));
+  }
+  for shim in options.shims.iter().chain(options.test_shims.iter()) {
+    if let Shim::Package(shim) = shim {
+      packages.push((
+        format!("shim {}", shim.package.name),
+        &shim.package,
+        shim.types_package.as_ref(),
+      ));
+    }
+  }
+
+  let mut messages = Vec::new();
+  for (description, mapped_package, shim_types_package) in packages {
+    let mut dependencies = vec![(
+      mapped_package.name.as_str(),
+      mapped_package.version.as_deref(),
+    )];
+    dependencies.extend(
+      mapped_package
+        .types_package
+        .iter()
+        .chain(shim_types_package)
+        .map(|d| (d.name.as_str(), Some(d.version.as_str()))),
+    );
+    for (name, version) in dependencies {
+      // allow mapping to Node's built-in modules (ex. `node:fs`)
+      if name.starts_with("node:") && version.is_none() {
+        continue;
+      }
+      if let Err(err) = package::validate_package_name(name) {
+        messages.push(format!(
+          "Invalid package name \"{}\" in the {}. {}",
+          name, description, err
+        ));
+      }
+      if let Some(version) = version {
+        if let Err(err) = package::validate_version_range(version) {
+          messages.push(format!(
+            "Invalid version \"{}\" of package {} in the {}. {}",
+            version, name, description, err
+          ));
+        }
+      }
+    }
+    if let Some(sub_path) = &mapped_package.sub_path {
+      if sub_path.is_empty() || sub_path.starts_with('/') {
+        messages.push(format!(
+          "Invalid sub path \"{}\" of package {} in the {}. It must be a relative path within the package.",
+          sub_path, mapped_package.name, description
+        ));
+      }
+    }
+  }
+
+  if let Some(server_shim) = &options.server_shim {
+    for api in server_shim.export_names.keys() {
+      if !DENO_SERVER_APIS.iter().any(|(name, _)| name == api) {
+        messages.push(format!(
+          "Unknown API \"{}\" in the export names of the server shim. Expected one of: {}",
+          api,
+          DENO_SERVER_APIS
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(", "),
+        ));
+      }
+    }
+  }
+
+  if messages.is_empty() {
+    Ok(())
+  } else {
+    bail!(TransformError::InvalidOptions {
+      message: messages.join("\n"),
+    })
+  }
+}
+
+fn validate_local_roots(local_roots: &[LocalRoot]) -> Result<()> {
+  let mut messages = Vec::new();
+  for root in local_roots {
+    if root.dir.scheme() != "file" {
+      messages.push(format!(
+        "Invalid local root {}. It must be a file url.",
+        root.dir
+      ));
+    }
+    let is_relative = root
+      .output_dir
+      .components()
+      .all(|c| matches!(c, std::path::Component::Normal(_)));
+    if !is_relative {
+      messages.push(format!(
+        "Invalid output directory \"{}\" of the local root {}. It must be a relative path within the output.",
+        root.output_dir.display(),
+        root.dir
+      ));
+    }
+  }
+
+  if messages.is_empty() {
+    Ok(())
+  } else {
+    bail!(TransformError::InvalidOptions {
+      message: messages.join("\n"),
     })
-    .await?;
+  }
+}
 
-  let mappings = Mappings::new(&module_graph, &specifiers)?;
+async fn transform_inner(
+  mut options: TransformOptions,
+  sink: Option<&dyn OutputSink>,
+) -> Result<TransformOutput> {
+  let loader = take_loader(&mut options);
+  add_node_global_shims(&mut options);
+  let total_stopwatch = Stopwatch::start();
+  let defines = Defines::new(&options.define)?;
+  let build_graph_stopwatch = Stopwatch::start();
+  let (module_graph, specifiers) = build_module_graph(&options, loader).await?;
+  let mut timings = TransformTimings {
+    build_graph: build_graph_stopwatch.elapsed(),
+    ..Default::default()
+  };
+
+  let mappings = Mappings::new(
+    &module_graph,
+    &specifiers,
+    &options.local_roots,
+    options.hash_remote_file_names,
+  )?;
+  let mut manifest_builder = if options.emit_manifest {
+    Some(ManifestBuilder::new(&options, &specifiers, &mappings))
+  } else {
+    None
+  };
+  let parse_errors = module_graph
+    .parse_errors()
+    .iter()
+    .map(|(specifier, diagnostic)| ParseDiagnostic {
+      specifier: specifier.clone(),
+      line_number: diagnostic.display_position.line_number,
+      column_number: diagnostic.display_position.column_number,
+      message: diagnostic.to_string(),
+      dependents: module_graph.get_referrers(specifier),
+    })
+    .collect::<Vec<_>>();
   let all_package_specifier_mappings: HashMap<ModuleSpecifier, String> =
     specifiers
       .main
@@ -282,9 +1717,91 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
       .chain(specifiers.test.mapped.iter())
       .map(|m| (m.0.clone(), m.1.module_specifier_text()))
       .collect();
+  let test_package_specifier_mappings = match &options.test_main_imports {
+    TestMainImports::Relative => all_package_specifier_mappings.clone(),
+    TestMainImports::SelfReference(package_name) => {
+      package::validate_package_name(package_name)
+        .context("Invalid package name for the test main imports")?;
+      let mut test_mappings = all_package_specifier_mappings.clone();
+      test_mappings.extend(get_self_reference_mappings(
+        package_name,
+        &options.entry_points,
+        &mappings,
+      ));
+      test_mappings
+    }
+  };
+  let provenance = if options.provenance {
+    get_provenance(&module_graph, &specifiers)
+  } else {
+    Vec::new()
+  };
+  let typescript_paths = if options.typescript_paths {
+    get_typescript_paths(&specifiers, &mappings)
+  } else {
+    BTreeMap::new()
+  };
 
-  let mut warnings = get_declaration_warnings(&specifiers);
+  let mut warnings =
+    get_declaration_warnings(&specifiers, &options.declaration_mappings);
+  let untyped_dependencies = get_untyped_dependencies(
+    &module_graph,
+    &specifiers,
+    &options
+      .entry_points
+      .iter()
+      .chain(options.test_entry_points.iter())
+      .cloned()
+      .collect::<Vec<_>>(),
+  );
+  warnings.extend(untyped_dependencies.iter().map(get_untyped_warning));
+  let duplicate_packages = get_duplicate_packages(
+    &module_graph,
+    &specifiers,
+    &mappings,
+    &options
+      .entry_points
+      .iter()
+      .chain(options.test_entry_points.iter())
+      .cloned()
+      .collect::<Vec<_>>(),
+  );
+  let duplicate_package_messages = duplicate_packages
+    .iter()
+    .map(get_duplicate_package_message)
+    .collect::<Vec<_>>();
+  if options.error_on_duplicate_packages
+    && !duplicate_package_messages.is_empty()
+  {
+    bail!(TransformError::DuplicatePackages {
+      packages: duplicate_packages,
+      message: duplicate_package_messages.join("\n\n"),
+    });
+  }
+  warnings.extend(duplicate_package_messages);
+  warnings.extend(
+    specifiers
+      .directory_imports
+      .iter()
+      .map(|(from, to)| get_directory_import_warning(from, to)),
+  );
+  let cycles = get_cycles(
+    &module_graph,
+    specifiers.local.iter().chain(specifiers.remote.iter()),
+  );
+  let cycle_messages = cycles
+    .iter()
+    .map(|cycle| get_cycle_message(cycle))
+    .collect::<Vec<_>>();
+  if options.error_on_cycles && !cycle_messages.is_empty() {
+    bail!(TransformError::Cycles {
+      cycles,
+      message: cycle_messages.join("\n\n"),
+    });
+  }
+  warnings.extend(cycle_messages);
   let mut main_env_context = EnvironmentContext {
+    output_environment: OutputEnvironment::Main,
     environment: TransformOutputEnvironment {
       entry_points: options
         .entry_points
@@ -294,7 +1811,6 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
       dependencies: get_dependencies(specifiers.main.mapped),
       ..Default::default()
     },
-    searching_polyfills: polyfills_for_target(options.target),
     found_polyfills: Default::default(),
     shim_file_specifier: &SYNTHETIC_SPECIFIERS.shims,
     shim_global_names: options
@@ -304,8 +1820,14 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
       .collect(),
     shims: &options.shims,
     used_shim: false,
+    relative_specifier_extension: options.relative_specifier_extension,
+    package_specifier_mappings: &all_package_specifier_mappings,
+    sink_file_sizes: Vec::new(),
+    remote_modules: HashMap::new(),
+    referrers: module_graph.get_shortest_referrers(&options.entry_points),
   };
   let mut test_env_context = EnvironmentContext {
+    output_environment: OutputEnvironment::Test,
     environment: TransformOutputEnvironment {
       entry_points: options
         .test_entry_points
@@ -315,7 +1837,6 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
       dependencies: get_dependencies(specifiers.test.mapped),
       ..Default::default()
     },
-    searching_polyfills: polyfills_for_target(options.target),
     found_polyfills: Default::default(),
     shim_file_specifier: &SYNTHETIC_TEST_SPECIFIERS.shims,
     shim_global_names: options
@@ -325,105 +1846,311 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
       .collect(),
     shims: &options.test_shims,
     used_shim: false,
+    relative_specifier_extension: options
+      .test_relative_specifier_extension
+      .unwrap_or(options.relative_specifier_extension),
+    package_specifier_mappings: &test_package_specifier_mappings,
+    sink_file_sizes: Vec::new(),
+    remote_modules: HashMap::new(),
+    referrers: module_graph.get_shortest_referrers(&options.test_entry_points),
   };
 
+  let mut types = Vec::with_capacity(specifiers.types.len());
+  for (code_specifier, d) in specifiers.types.iter() {
+    let env_context = if specifiers.test_modules.contains(code_specifier) {
+      &mut test_env_context
+    } else {
+      &mut main_env_context
+    };
+    let declaration_path = mappings.get_file_path(&d.selected.specifier);
+    env_context.environment.declarations.push((
+      mappings.get_file_path(code_specifier).to_owned(),
+      declaration_path.to_owned(),
+    ));
+    types.push(ResolvedTypes {
+      code_specifier: code_specifier.clone(),
+      declaration_specifier: d.selected.specifier.clone(),
+      declaration_path: declaration_path.to_owned(),
+      referrer: d.selected.referrer.clone(),
+      ignored: d
+        .ignored
+        .iter()
+        .map(|dep| IgnoredTypes {
+          declaration_specifier: dep.specifier.clone(),
+          referrer: dep.referrer.clone(),
+        })
+        .collect(),
+    });
+  }
+
+  // gather everything needed from the module graph up front since
+  // it can't be shared between threads
+  let mut pending_files = Vec::new();
   for specifier in specifiers
     .local
     .iter()
     .chain(specifiers.remote.iter())
     .chain(specifiers.types.iter().map(|(_, d)| &d.selected.specifier))
   {
+    if parse_errors.iter().any(|e| &e.specifier == specifier) {
+      continue;
+    }
     let module = module_graph.get(specifier);
-    let env_context = if specifiers.test_modules.contains(specifier) {
-      &mut test_env_context
+    let kind = match module.kind {
+      ModuleKind::Esm => PendingFileKind::Esm,
+      ModuleKind::Asserted if specifiers.assets.contains(specifier) => {
+        match specifiers.asset_types.get(specifier) {
+          Some(attribute_type) => {
+            PendingFileKind::HandledAsset(attribute_type.clone())
+          }
+          None => PendingFileKind::Asset,
+        }
+      }
+      ModuleKind::Asserted => PendingFileKind::Asserted,
+      _ => bail!(
+        "Not implemented module kind {:?} for {}",
+        module.kind,
+        module.specifier
+      ),
+    };
+    let source = match &module.maybe_source {
+      Some(source) => source.clone(),
+      None => continue,
+    };
+    let is_test = specifiers.test_modules.contains(specifier);
+    let env_context = if is_test {
+      &test_env_context
     } else {
-      &mut main_env_context
+      &main_env_context
     };
+    pending_files.push(PendingFile {
+      specifier,
+      is_test,
+      is_entry_point: env_context
+        .environment
+        .entry_points
+        .contains(mappings.get_file_path(specifier)),
+      media_type: module.media_type,
+      parsed_source: match kind {
+        PendingFileKind::Esm => module_graph.get_parsed_source(specifier),
+        _ => None,
+      },
+      kind,
+      source,
+    });
+  }
 
-    let file_text = match module.kind {
-      ModuleKind::Esm => {
-        let parsed_source = module_graph.get_parsed_source(specifier);
-        let text_changes = parsed_source
-          .with_view(|program| -> Result<Vec<TextChange>> {
-            let ignore_line_indexes =
-              get_ignore_line_indexes(parsed_source.specifier(), &program);
-            let top_level_decls =
-              get_top_level_decls(&program, parsed_source.top_level_context());
-            warnings.extend(ignore_line_indexes.warnings);
-
-            fill_polyfills(&mut FillPolyfillsParams {
-              found_polyfills: &mut env_context.found_polyfills,
-              searching_polyfills: &mut env_context.searching_polyfills,
-              program: &program,
-              unresolved_context: parsed_source.unresolved_context(),
-              top_level_decls: &top_level_decls,
-            });
-
-            let mut text_changes = Vec::new();
-
-            // shim changes
-            {
-              let shim_relative_specifier = get_relative_specifier(
-                mappings.get_file_path(specifier),
-                mappings.get_file_path(env_context.shim_file_specifier),
-              );
-              let result =
-                get_global_text_changes(&GetGlobalTextChangesParams {
-                  program: &program,
-                  unresolved_context: parsed_source.unresolved_context(),
-                  shim_specifier: &shim_relative_specifier,
-                  shim_global_names: &env_context.shim_global_names,
-                  ignore_line_indexes: &ignore_line_indexes.line_indexes,
-                  top_level_decls: &top_level_decls,
-                });
-              text_changes.extend(result.text_changes);
-              if result.imported_shim {
-                env_context.used_shim = true;
-              }
-            }
-
-            text_changes
-              .extend(get_deno_comment_directive_text_changes(&program));
-            text_changes.extend(get_import_exports_text_changes(
-              &GetImportExportsTextChangesParams {
-                specifier,
-                module_graph: &module_graph,
-                mappings: &mappings,
-                program: &program,
-                package_specifier_mappings: &all_package_specifier_mappings,
-              },
-            )?);
+  // get these before any modules are inlined into their importers
+  let third_party_licenses_text = if options.third_party_licenses {
+    build_third_party_licenses_text(
+      pending_files
+        .iter()
+        .filter(|file| file.specifier.scheme() != "file")
+        .filter_map(|file| {
+          get_license_banner(&file.source)
+            .map(|banner| (file.specifier, banner))
+        }),
+    )
+  } else {
+    None
+  };
 
-            Ok(text_changes)
-          })
-          .with_context(|| {
-            format!(
-              "Issue getting text changes from {}",
-              parsed_source.specifier()
-            )
-          })?;
+  if let Some(max_size) = options.inline_remote_module_max_size {
+    let inlined = inline_small_remote_modules(
+      &mut pending_files,
+      &module_graph,
+      &specifiers,
+      max_size,
+    );
+    if let Some(manifest_builder) = &mut manifest_builder {
+      manifest_builder.remove_modules(&inlined);
+    }
+  }
 
-        apply_text_changes(parsed_source.text_info().text_str(), text_changes)
-      }
-      ModuleKind::Asserted => {
-        if let Some(source) = &module.maybe_source {
-          format!("export default {};", strip_bom(source).trim(),)
-        } else {
-          continue;
+  let dependency_resolver = module_graph.dependency_resolver();
+  let file_dependencies =
+    get_file_dependencies(&module_graph, options.import_map.as_ref())?;
+  // release the sources and parsed sources of any modules that won't
+  // be transformed
+  drop(module_graph);
+  let cache = options.cache_dir.map(TransformCache::new);
+  let transform_file_context = TransformFileContext {
+    cache: cache.as_ref(),
+    mappings: &mappings,
+    dependency_resolver: &dependency_resolver,
+    target: options.target,
+    jsx: options.jsx.as_ref(),
+    normalize_output_text: options.normalize_output_text,
+    inject_dirname: options.inject_dirname,
+    commonjs_modules: options.commonjs_modules,
+    server_shim: options.server_shim.as_ref(),
+    dom_types_package: options.dom_types_package.as_ref(),
+    declarations_only: options.declarations_only,
+    comment_directives: &options.comment_directives,
+    import_attributes: &options.import_attributes,
+    defines: &defines,
+    deno_env_to_process_env: options.deno_env_to_process_env,
+    translate_deno_apis: options.translate_deno_apis,
+    shim_import_style: options.shim_import_style,
+    deno_feature_detection: options.deno_feature_detection,
+    qualified_global_access: options.qualified_global_access,
+    annotate_rewrites: options.annotate_rewrites,
+    custom_visitors: &options.custom_visitors,
+    asset_handlers: &options.asset_handlers,
+  };
+  // entry points might have the polyfill import added to them later
+  let write_to_sink_early = |file: &PendingFile| {
+    sink.is_some()
+      && !file.is_entry_point
+      && !options.bundle
+      && !options.flatten_barrels
+      && !options.entry_point_exports
+      && !options.minify
+  };
+  let file_text_handler = options.file_text_handler.as_deref();
+  let transform_pending_file = |file: &PendingFile| -> Result<TransformedFile> {
+    let env_context = if file.is_test {
+      &test_env_context
+    } else {
+      &main_env_context
+    };
+    let mut transformed_file =
+      transform_file(file, env_context, &transform_file_context)?;
+    let mut output_file = OutputFile {
+      file_path: mappings.get_file_path(file.specifier).to_owned(),
+      file_text: std::mem::take(&mut transformed_file.file_text),
+    };
+    if matches!(file.kind, PendingFileKind::Asset) {
+      // copy the file next to the module that exports its text
+      // (ex. `styles.css` for `styles.css.js`)
+      transformed_file.asset_file = Some(OutputFile {
+        file_path: with_extension(&output_file.file_path, ""),
+        file_text: file.source.to_string(),
+      });
+    }
+    if let Some(handler) = file_text_handler.filter(|_| {
+      !file.is_entry_point && !options.bundle && !options.flatten_barrels
+    }) {
+      handler.handle_file_text(
+        env_context.output_environment,
+        file.specifier,
+        &mut output_file,
+      )?;
+    }
+    match sink.filter(|_| write_to_sink_early(file)) {
+      Some(sink) => {
+        for file in [Some(output_file), transformed_file.asset_file.take()]
+          .into_iter()
+          .flatten()
+        {
+          transformed_file
+            .sink_file_sizes
+            .push((file.file_path.clone(), file.file_text.len()));
+          sink.write_file(env_context.output_environment, file)?;
         }
       }
-      _ => bail!(
-        "Not implemented module kind {:?} for {}",
-        module.kind,
-        module.specifier
-      ),
-    };
-
-    let file_path = mappings.get_file_path(specifier).to_owned();
-    env_context.environment.files.push(OutputFile {
-      file_path,
-      file_text,
-    });
+      None => transformed_file.file_text = output_file.file_text,
+    }
+    Ok(transformed_file)
+  };
+  #[cfg(feature = "parallel")]
+  let transformed_files = {
+    use rayon::prelude::*;
+    pending_files
+      .par_iter()
+      .map(transform_pending_file)
+      .collect::<Result<Vec<_>>>()?
+  };
+  #[cfg(not(feature = "parallel"))]
+  let transformed_files = pending_files
+    .iter()
+    .map(transform_pending_file)
+    .collect::<Result<Vec<_>>>()?;
+
+  // merge the results in order so the output is deterministic
+  let mut unshimmable_api_usages = Vec::new();
+  let mut rewritten_specifiers = Vec::new();
+  // the files that were already handed to the sink
+  let mut sink_file_paths = Vec::new();
+  for (file, transformed_file) in
+    pending_files.iter().zip(transformed_files.into_iter())
+  {
+    let env_context = if file.is_test {
+      &mut test_env_context
+    } else {
+      &mut main_env_context
+    };
+    warnings.extend(transformed_file.warnings);
+    unshimmable_api_usages.extend(transformed_file.unshimmable_api_usages);
+    timings.parse += transformed_file.timings.parse;
+    timings.visit += transformed_file.timings.visit;
+    timings.apply_text_changes += transformed_file.timings.apply_text_changes;
+    for polyfill in transformed_file.found_polyfills {
+      if !env_context
+        .found_polyfills
+        .iter()
+        .any(|p| p.get_file_text() == polyfill.get_file_text())
+      {
+        env_context.found_polyfills.push(polyfill);
+      }
+    }
+    if !transformed_file.used_shim_globals.is_empty() {
+      log_debug!(
+        "Injected the shim for {} into {}",
+        transformed_file.used_shim_globals.join(", "),
+        file.specifier
+      );
+      env_context.used_shim = true;
+      env_context.environment.shims_used.push(FileShimUsage {
+        specifier: file.specifier.clone(),
+        file_path: mappings.get_file_path(file.specifier).to_owned(),
+        shims: get_shims_for_globals(
+          &transformed_file.used_shim_globals,
+          env_context.shims,
+          options.server_shim.as_ref(),
+        ),
+        globals: transformed_file.used_shim_globals,
+      });
+    }
+    if let Some(package) = &options.dom_types_package {
+      if transformed_file.uses_dom_apis
+        && !env_context
+          .environment
+          .dependencies
+          .iter()
+          .any(|d| d.name == package.name)
+      {
+        env_context.environment.dependencies.push(package.clone());
+      }
+    }
+    if matches!(file.specifier.scheme(), "http" | "https") {
+      env_context.remote_modules.insert(
+        mappings.get_file_path(file.specifier).to_owned(),
+        file.specifier.clone(),
+      );
+    }
+    env_context
+      .sink_file_sizes
+      .extend(transformed_file.sink_file_sizes);
+    if !transformed_file.relative_specifiers.is_empty() {
+      rewritten_specifiers.push(RewrittenSpecifiers {
+        specifier: Some(file.specifier.clone()),
+        file_path: mappings.get_file_path(file.specifier).to_owned(),
+        relative_specifiers: transformed_file.relative_specifiers,
+      });
+    }
+    if !write_to_sink_early(file) {
+      env_context.environment.files.push(OutputFile {
+        file_path: mappings.get_file_path(file.specifier).to_owned(),
+        file_text: transformed_file.file_text,
+      });
+      env_context
+        .environment
+        .files
+        .extend(transformed_file.asset_file);
+    } else {
+      sink_file_paths.push(mappings.get_file_path(file.specifier).to_owned());
+    }
   }
 
   check_add_polyfill_file_to_environment(
@@ -437,19 +2164,126 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
   check_add_shim_file_to_environment(
     &mut main_env_context,
     mappings.get_file_path(&SYNTHETIC_SPECIFIERS.shims),
+    options.server_shim.as_ref(),
+    options.shim_import_style,
     &mappings,
   );
   check_add_shim_file_to_environment(
     &mut test_env_context,
     mappings.get_file_path(&SYNTHETIC_TEST_SPECIFIERS.shims),
+    options.server_shim.as_ref(),
+    options.shim_import_style,
     &mappings,
   );
 
+  if let Some(file_text) = third_party_licenses_text {
+    main_env_context.environment.files.push(OutputFile {
+      file_path: PathBuf::from(THIRD_PARTY_LICENSES_FILE_NAME),
+      file_text,
+    });
+  }
+
+  for env_context in [&main_env_context, &test_env_context] {
+    warnings.extend(get_star_export_conflict_warnings(
+      &env_context.environment.files,
+    ));
+  }
+
+  if options.flatten_barrels {
+    barrels::flatten_barrels(&mut main_env_context.environment)?;
+    barrels::flatten_barrels(&mut test_env_context.environment)?;
+  }
+
+  if options.bundle {
+    bundle::bundle_environments(
+      &mut main_env_context.environment,
+      &mut test_env_context.environment,
+    )?;
+  }
+
+  // verify the final files, whose specifiers were changed after they
+  // were transformed when flattening the barrels or bundling
+  let output_files = main_env_context
+    .environment
+    .files
+    .iter()
+    .chain(test_env_context.environment.files.iter());
+  let rewritten_specifiers = if options.flatten_barrels || options.bundle {
+    let specifiers_by_path = pending_files
+      .iter()
+      .map(|file| (mappings.get_file_path(file.specifier), file.specifier))
+      .collect::<HashMap<_, _>>();
+    get_output_rewritten_specifiers(output_files.clone(), &specifiers_by_path)?
+  } else {
+    rewritten_specifiers
+  };
+  verify_relative_specifiers(
+    &rewritten_specifiers,
+    sink_file_paths
+      .iter()
+      .chain(output_files.map(|file| &file.file_path)),
+  )?;
+
+  if let Some(handler) = file_text_handler {
+    // these files are only complete once the polyfill and shim files exist
+    for (env_context, synthetic_specifiers) in [
+      (&mut main_env_context, &*SYNTHETIC_SPECIFIERS),
+      (&mut test_env_context, &*SYNTHETIC_TEST_SPECIFIERS),
+    ] {
+      let is_test = env_context.output_environment == OutputEnvironment::Test;
+      let specifiers = pending_files
+        .iter()
+        // flattened barrels change the other files after they're transformed
+        .filter(|file| {
+          (file.is_entry_point || options.flatten_barrels)
+            && file.is_test == is_test
+        })
+        .map(|file| file.specifier)
+        .chain([&synthetic_specifiers.polyfills, &synthetic_specifiers.shims]);
+      for specifier in specifiers {
+        let file_path = mappings.get_file_path(specifier);
+        if let Some(file) = env_context
+          .environment
+          .files
+          .iter_mut()
+          .find(|f| &f.file_path == file_path)
+        {
+          handler.handle_file_text(
+            env_context.output_environment,
+            specifier,
+            file,
+          )?;
+        }
+      }
+    }
+  }
+
+  if options.minify {
+    minify::minify_environment(&mut main_env_context.environment)?;
+    minify::minify_environment(&mut test_env_context.environment)?;
+  }
+
+  if options.entry_point_exports {
+    for env_context in [&mut main_env_context, &mut test_env_context] {
+      let environment = &mut env_context.environment;
+      environment.entry_point_exports = get_entry_point_exports(environment)?;
+    }
+  }
+
   add_shim_types_packages_to_test_environment(
     &mut test_env_context.environment,
     options.shims.iter().chain(options.test_shims.iter()),
   );
 
+  for env_context in [&mut main_env_context, &mut test_env_context] {
+    apply_dependency_versions(
+      env_context.output_environment,
+      &mut env_context.environment.dependencies,
+      options.version_policy,
+      options.version_handler.as_deref(),
+    )?;
+  }
+
   // Remove any dependencies from the test environment that
   // are found in the main environment. Only check for exact
   // matches in order to cause an npm install error if there
@@ -461,11 +2295,922 @@ pub async fn transform(options: TransformOptions) -> Result<TransformOutput> {
     .filter(|d| !main_env_context.environment.dependencies.contains(d))
     .collect();
 
-  Ok(TransformOutput {
+  for env_context in [&mut main_env_context, &mut test_env_context] {
+    let mut file_sizes = std::mem::take(&mut env_context.sink_file_sizes);
+    file_sizes.extend(
+      env_context
+        .environment
+        .files
+        .iter()
+        .map(|file| (file.file_path.clone(), file.file_text.len())),
+    );
+    let environment = &mut env_context.environment;
+    environment.stats = get_output_stats(
+      &file_sizes,
+      &env_context.remote_modules,
+      &env_context.referrers,
+      &environment.shims_used,
+    );
+    if env_context.output_environment == OutputEnvironment::Main {
+      if let Some(size_budget) = &options.size_budget {
+        warnings.extend(get_size_budget_warnings(
+          &file_sizes,
+          &env_context.remote_modules,
+          &env_context.referrers,
+          &environment.stats,
+          size_budget,
+        ));
+      }
+    }
+  }
+
+  let used_shims = main_env_context.used_shim;
+  let used_test_shims = test_env_context.used_shim;
+  let mut output = TransformOutput {
     main: main_env_context.environment,
     test: test_env_context.environment,
     warnings,
-  })
+    untyped_dependencies,
+    duplicate_packages,
+    types,
+    excluded_modules: specifiers.excluded,
+    file_dependencies,
+    local_roots: mappings.local_roots().to_vec(),
+    parse_errors,
+    unshimmable_api_usages,
+    exports: Default::default(),
+    provenance,
+    typescript_paths,
+    manifest: None,
+    timings: None,
+  };
+  sort_output(&mut output);
+  output.exports = package::get_exports(&output.main);
+  output.manifest = manifest_builder
+    .map(|builder| builder.build(&output, used_shims, used_test_shims));
+
+  if let Some(sink) = sink {
+    for (environment, output_environment) in [
+      (&mut output.main, OutputEnvironment::Main),
+      (&mut output.test, OutputEnvironment::Test),
+    ] {
+      for file in environment.files.drain(..) {
+        sink.write_file(output_environment, file)?;
+      }
+    }
+  }
+
+  if cfg!(feature = "tracing") {
+    timings.total = total_stopwatch.elapsed();
+    output.timings = Some(timings);
+  }
+
+  Ok(output)
+}
+
+/// Sorts the output so that it's the same on every run regardless of
+/// iteration order or the order modules finished loading in.
+fn sort_output(output: &mut TransformOutput) {
+  for environment in [&mut output.main, &mut output.test] {
+    environment
+      .files
+      .sort_by(|a, b| a.file_path.cmp(&b.file_path));
+    environment
+      .dependencies
+      .sort_by(|a, b| a.name.cmp(&b.name).then(a.version.cmp(&b.version)));
+    environment.declarations.sort();
+    environment
+      .shims_used
+      .sort_by(|a, b| a.file_path.cmp(&b.file_path));
+  }
+  output
+    .untyped_dependencies
+    .sort_by(|a, b| a.specifier.cmp(&b.specifier));
+  output
+    .types
+    .sort_by(|a, b| a.code_specifier.cmp(&b.code_specifier));
+  for resolved_types in output.types.iter_mut() {
+    resolved_types.ignored.sort_by(|a, b| {
+      a.declaration_specifier
+        .cmp(&b.declaration_specifier)
+        .then(a.referrer.cmp(&b.referrer))
+    });
+  }
+  output.excluded_modules.sort();
+  output.file_dependencies.sort();
+  output
+    .parse_errors
+    .sort_by(|a, b| a.specifier.cmp(&b.specifier));
+  output.unshimmable_api_usages.sort_by(|a, b| {
+    a.specifier
+      .cmp(&b.specifier)
+      .then(a.line_number.cmp(&b.line_number))
+      .then(a.column_number.cmp(&b.column_number))
+  });
+  for parse_error in output.parse_errors.iter_mut() {
+    parse_error.dependents.sort();
+  }
+  output
+    .provenance
+    .sort_by(|a, b| a.specifier.cmp(&b.specifier));
+}
+
+fn get_provenance(
+  module_graph: &crate::graph::ModuleGraph,
+  specifiers: &Specifiers,
+) -> Vec<ModuleProvenance> {
+  let copied = specifiers
+    .remote
+    .iter()
+    .chain(specifiers.types.values().map(|d| &d.selected.specifier))
+    .filter(|s| s.scheme() != "file")
+    .map(|specifier| {
+      let source = module_graph.get(specifier).maybe_source.as_deref();
+      get_module_provenance(
+        specifier,
+        None,
+        source,
+        module_graph.license_files(),
+      )
+    });
+  let mapped = specifiers
+    .main
+    .mapped
+    .iter()
+    .chain(specifiers.test.mapped.iter())
+    .filter(|(s, _)| s.scheme() != "file")
+    .map(|(specifier, package)| {
+      get_module_provenance(
+        specifier,
+        Some(package),
+        None,
+        module_graph.license_files(),
+      )
+    });
+  let mut provenance = copied.chain(mapped).collect::<Vec<_>>();
+  provenance.sort_by(|a, b| a.specifier.cmp(&b.specifier));
+  provenance.dedup_by(|a, b| a.specifier == b.specifier);
+  provenance
+}
+
+/// Gets the local files that were loaded when building the module graph.
+fn get_file_dependencies(
+  module_graph: &crate::graph::ModuleGraph,
+  import_map: Option<&ModuleSpecifier>,
+) -> Result<Vec<PathBuf>> {
+  let mut file_paths = module_graph
+    .all_modules()
+    .into_iter()
+    .map(|module| &module.specifier)
+    .chain(import_map)
+    .filter(|specifier| specifier.scheme() == "file")
+    .map(url_to_file_path)
+    .collect::<Result<Vec<_>>>()?;
+  file_paths.sort();
+  file_paths.dedup();
+  Ok(file_paths)
+}
+
+/// Inlines the small remote modules into the module that imports them
+/// when it's the only one, returning the modules that were inlined.
+fn inline_small_remote_modules(
+  pending_files: &mut Vec<PendingFile>,
+  module_graph: &crate::graph::ModuleGraph,
+  specifiers: &Specifiers,
+  max_size: usize,
+) -> HashSet<ModuleSpecifier> {
+  let mut inlined = HashSet::new();
+  for index in 0..pending_files.len() {
+    let file = &pending_files[index];
+    if file.source.len() > max_size
+      || file.is_entry_point
+      || !matches!(file.kind, PendingFileKind::Esm)
+      || is_declaration_file(file.media_type)
+      || !specifiers.remote.contains(file.specifier)
+      || specifiers.types.contains_key(file.specifier)
+    {
+      continue;
+    }
+    let module = module_graph.get(file.specifier);
+    if !module.dependencies.is_empty()
+      || module.maybe_types_dependency.is_some()
+    {
+      continue;
+    }
+    let referrers = module_graph.get_referrers(file.specifier);
+    if referrers.len() != 1 {
+      continue;
+    }
+    let importer_index = match pending_files.iter().position(|f| {
+      *f.specifier == referrers[0]
+        && matches!(f.kind, PendingFileKind::Esm)
+        && !is_declaration_file(f.media_type)
+        && f.is_test == file.is_test
+    }) {
+      Some(index) => index,
+      None => continue,
+    };
+    let mut import_texts = module_graph
+      .get(&referrers[0])
+      .dependencies
+      .iter()
+      .filter(|(_, dep)| {
+        dep
+          .get_code()
+          .map(|s| module_graph.resolve(s) == *file.specifier)
+          .unwrap_or(false)
+      })
+      .map(|(text, _)| text);
+    let import_text = match (import_texts.next(), import_texts.next()) {
+      (Some(text), None) => text,
+      _ => continue,
+    };
+    let importer = &pending_files[importer_index];
+    let new_source = inline_module(
+      &InlineModuleParams {
+        specifier: importer.specifier,
+        media_type: importer.media_type,
+        source: &importer.source,
+      },
+      &InlineModuleParams {
+        specifier: file.specifier,
+        media_type: file.media_type,
+        source: &file.source,
+      },
+      import_text,
+    );
+    if let Some(new_source) = new_source {
+      inlined.insert(file.specifier.clone());
+      let importer = &mut pending_files[importer_index];
+      importer.source = new_source.into();
+      importer.parsed_source = None;
+    }
+  }
+  pending_files.retain(|f| !inlined.contains(f.specifier));
+  inlined
+}
+
+/// A file to transform along with what's necessary from the module graph.
+struct PendingFile<'a> {
+  specifier: &'a ModuleSpecifier,
+  is_test: bool,
+  is_entry_point: bool,
+  media_type: MediaType,
+  /// The source parsed when building the graph, which is reused when
+  /// the source isn't changed before being transformed.
+  parsed_source: Option<ParsedSource>,
+  kind: PendingFileKind,
+  source: Arc<str>,
+}
+
+enum PendingFileKind {
+  Esm,
+  Asserted,
+  /// A CSS or text file.
+  Asset,
+  /// A file imported with the `type` attribute of an asset handler.
+  HandledAsset(String),
+}
+
+struct TransformFileContext<'a> {
+  cache: Option<&'a TransformCache>,
+  mappings: &'a Mappings,
+  dependency_resolver: &'a DependencyResolver,
+  target: ScriptTarget,
+  jsx: Option<&'a JsxOptions>,
+  normalize_output_text: bool,
+  inject_dirname: bool,
+  commonjs_modules: CommonJsModules,
+  server_shim: Option<&'a ServerShim>,
+  dom_types_package: Option<&'a Dependency>,
+  declarations_only: bool,
+  comment_directives: &'a CommentDirectiveOptions,
+  import_attributes: &'a ImportAttributeOptions,
+  defines: &'a Defines,
+  deno_env_to_process_env: bool,
+  translate_deno_apis: bool,
+  shim_import_style: ShimImportStyle,
+  deno_feature_detection: DenoFeatureDetection,
+  qualified_global_access: QualifiedGlobalAccess,
+  annotate_rewrites: bool,
+  custom_visitors: &'a [Box<dyn TransformVisitor>],
+  asset_handlers: &'a HashMap<String, Box<dyn AssetHandler>>,
+}
+
+struct TransformedFile {
+  file_text: String,
+  warnings: Vec<String>,
+  found_polyfills: Vec<Box<dyn Polyfill>>,
+  used_shim_globals: Vec<String>,
+  /// The relative specifiers the module specifiers were rewritten to.
+  relative_specifiers: Vec<String>,
+  unshimmable_api_usages: Vec<UnshimmableApiUsage>,
+  /// If the file references the DOM libs or uses their globals.
+  uses_dom_apis: bool,
+  /// Paths and sizes of the files that were already handed to the sink.
+  sink_file_sizes: Vec<(PathBuf, usize)>,
+  /// Copy of the file when it's an asset.
+  asset_file: Option<OutputFile>,
+  /// How long the per-file phases took, which is zero when cached.
+  timings: TransformTimings,
+}
+
+fn transform_file(
+  file: &PendingFile,
+  env_context: &EnvironmentContext,
+  context: &TransformFileContext,
+) -> Result<TransformedFile> {
+  let cache = match context.cache {
+    Some(cache) => cache,
+    None => return transform_file_without_cache(file, env_context, context),
+  };
+  let cache_key = match get_cache_key(file, env_context, context) {
+    Some(cache_key) => cache_key,
+    None => return transform_file_without_cache(file, env_context, context),
+  };
+  let source_hash = get_source_hash(&file.source);
+
+  if let Some(cached_file) = cache.get(&cache_key, &source_hash) {
+    let mut polyfills = polyfills_for_target(context.target)
+      .into_iter()
+      .map(Some)
+      .collect::<Vec<_>>();
+    return Ok(TransformedFile {
+      file_text: cached_file.file_text,
+      warnings: cached_file.warnings,
+      found_polyfills: cached_file
+        .polyfill_indexes
+        .iter()
+        .filter_map(|i| polyfills.get_mut(*i).and_then(|p| p.take()))
+        .collect(),
+      used_shim_globals: cached_file.used_shim_globals,
+      relative_specifiers: cached_file.relative_specifiers,
+      unshimmable_api_usages: cached_file
+        .unshimmable_api_usages
+        .into_iter()
+        .filter_map(|(api, line_number, column_number)| {
+          Some(UnshimmableApiUsage {
+            specifier: file.specifier.clone(),
+            line_number,
+            column_number,
+            category: get_unshimmable_api_category(&api)?,
+            api,
+          })
+        })
+        .collect(),
+      uses_dom_apis: cached_file.uses_dom_apis,
+      asset_file: None,
+      sink_file_sizes: Vec::new(),
+      timings: Default::default(),
+    });
+  }
+
+  let transformed_file =
+    transform_file_without_cache(file, env_context, context)?;
+  let polyfills = polyfills_for_target(context.target);
+  cache.set(
+    &cache_key,
+    &source_hash,
+    &CachedFile {
+      file_text: transformed_file.file_text.clone(),
+      warnings: transformed_file.warnings.clone(),
+      polyfill_indexes: transformed_file
+        .found_polyfills
+        .iter()
+        .filter_map(|found| {
+          polyfills
+            .iter()
+            .position(|p| p.get_file_text() == found.get_file_text())
+        })
+        .collect(),
+      used_shim_globals: transformed_file.used_shim_globals.clone(),
+      relative_specifiers: transformed_file.relative_specifiers.clone(),
+      unshimmable_api_usages: transformed_file
+        .unshimmable_api_usages
+        .iter()
+        .map(|u| (u.api.clone(), u.line_number, u.column_number))
+        .collect(),
+      uses_dom_apis: transformed_file.uses_dom_apis,
+    },
+  )?;
+  Ok(transformed_file)
+}
+
+/// Gets a key for everything that affects the output of a file or
+/// `None` when the file shouldn't be cached.
+fn get_cache_key(
+  file: &PendingFile,
+  env_context: &EnvironmentContext,
+  context: &TransformFileContext,
+) -> Option<String> {
+  if matches!(file.kind, PendingFileKind::HandledAsset(_)) {
+    // the handlers can't say what affects their output
+    return None;
+  }
+  let mappings = context.mappings;
+  let mut hasher = CacheKeyHasher::default();
+  hasher
+    .write(file.specifier)
+    .write(&*file.source)
+    .write(file.media_type)
+    .write(matches!(file.kind, PendingFileKind::Esm))
+    .write(matches!(file.kind, PendingFileKind::Asset))
+    .write(context.target as u8)
+    .write(context.jsx)
+    .write(context.normalize_output_text)
+    .write(context.inject_dirname)
+    .write(context.commonjs_modules)
+    .write(context.server_shim)
+    .write(context.dom_types_package.map(|p| &p.name))
+    .write(context.declarations_only)
+    .write(context.comment_directives)
+    .write(context.import_attributes)
+    .write(context.defines)
+    .write(context.deno_env_to_process_env)
+    .write(context.translate_deno_apis)
+    .write(context.shim_import_style)
+    .write(context.deno_feature_detection)
+    .write(context.qualified_global_access)
+    .write(context.annotate_rewrites)
+    .write(mappings.get_file_path(file.specifier))
+    .write(mappings.get_file_path(env_context.shim_file_specifier))
+    .write(env_context.relative_specifier_extension);
+  let mut shim_global_names =
+    env_context.shim_global_names.iter().collect::<Vec<_>>();
+  shim_global_names.sort();
+  hasher.write(shim_global_names);
+  if let Some(dependencies) =
+    context.dependency_resolver.dependencies(file.specifier)
+  {
+    let dependencies = dependencies.iter().collect::<BTreeMap<_, _>>();
+    for (value, specifier) in dependencies {
+      hasher
+        .write(value)
+        .write(env_context.package_specifier_mappings.get(specifier))
+        .write(mappings.maybe_file_path(specifier));
+    }
+  }
+  for visitor in context.custom_visitors.iter() {
+    hasher.write(visitor.cache_key()?);
+  }
+  Some(hasher.finish())
+}
+
+fn transform_file_without_cache(
+  file: &PendingFile,
+  env_context: &EnvironmentContext,
+  context: &TransformFileContext,
+) -> Result<TransformedFile> {
+  let mut transformed_file = TransformedFile {
+    file_text: String::new(),
+    warnings: Vec::new(),
+    found_polyfills: Vec::new(),
+    used_shim_globals: Vec::new(),
+    relative_specifiers: Vec::new(),
+    unshimmable_api_usages: Vec::new(),
+    uses_dom_apis: false,
+    asset_file: None,
+    sink_file_sizes: Vec::new(),
+    timings: Default::default(),
+  };
+  let specifier = file.specifier;
+  let mappings = context.mappings;
+  trace_span!("transform_file", specifier = %specifier);
+
+  transformed_file.file_text = match file.kind {
+    PendingFileKind::Esm => {
+      let has_bom = file.source.starts_with(BOM_CHAR);
+      let source = if has_bom {
+        Arc::from(strip_bom(&file.source))
+      } else {
+        file.source.clone()
+      };
+      let has_source_rewrites = (!context.defines.is_empty()
+        && context.defines.may_match(&source))
+        || ((context.deno_env_to_process_env || context.translate_deno_apis)
+          && source.contains("Deno"));
+      // the source rewrites only apply to the values, which the
+      // declarations don't have
+      let is_declarations_only = context.declarations_only
+        && matches!(
+          file.media_type,
+          MediaType::TypeScript
+            | MediaType::Mts
+            | MediaType::Cts
+            | MediaType::Tsx
+        );
+      let source = if is_declarations_only {
+        let stopwatch = Stopwatch::start();
+        let (source, warnings) = strip_to_declarations(
+          specifier,
+          source,
+          file.media_type,
+          file.parsed_source.as_ref(),
+        )?;
+        transformed_file.warnings.extend(warnings);
+        transformed_file.timings.visit += stopwatch.elapsed();
+        source
+      } else if has_source_rewrites && !is_declaration_file(file.media_type) {
+        let stopwatch = Stopwatch::start();
+        let source = apply_source_rewrites(
+          specifier,
+          source,
+          file.media_type,
+          file.parsed_source.as_ref(),
+          context,
+        )?;
+        transformed_file.timings.visit += stopwatch.elapsed();
+        source
+      } else {
+        source
+      };
+      let new_line_kind = if context.normalize_output_text {
+        NewLineKind::LineFeed
+      } else {
+        NewLineKind::detect(&source)
+      };
+      // the parsed source is shared by all the passes
+      let parsed_source = {
+        trace_span!("parse");
+        let stopwatch = Stopwatch::start();
+        let parsed_source = reuse_or_parse_module(
+          specifier,
+          source,
+          file.media_type,
+          file.parsed_source.as_ref(),
+        )?;
+        transformed_file.timings.parse += stopwatch.elapsed();
+        parsed_source
+      };
+      let is_declaration_file = is_declaration_file(file.media_type);
+      let visit_stopwatch = Stopwatch::start();
+      let text_changes = parsed_source
+        .with_view(|program| -> Result<Vec<TextChange>> {
+          trace_span!("visit", pass = "text_changes");
+          let ignore_line_indexes =
+            get_ignore_line_indexes(parsed_source.specifier(), &program);
+          let top_level_decls =
+            get_top_level_decls(&program, parsed_source.top_level_context());
+          transformed_file
+            .warnings
+            .extend(ignore_line_indexes.warnings);
+
+          fill_polyfills(&mut FillPolyfillsParams {
+            found_polyfills: &mut transformed_file.found_polyfills,
+            searching_polyfills: &mut polyfills_for_target(context.target),
+            program: &program,
+            unresolved_context: parsed_source.unresolved_context(),
+            top_level_decls: &top_level_decls,
+          });
+
+          let shim_relative_specifier = get_relative_specifier(
+            mappings.get_file_path(specifier),
+            mappings.get_file_path(env_context.shim_file_specifier),
+            env_context.relative_specifier_extension,
+          );
+          let commonjs_params = GetCommonJsParams {
+            specifier,
+            program: &program,
+            media_type: file.media_type,
+            is_declaration_file,
+            unresolved_context: parsed_source.unresolved_context(),
+            shim_global_names: &env_context.shim_global_names,
+            ignore_line_indexes: &ignore_line_indexes.line_indexes,
+            top_level_decls: &top_level_decls,
+            inject_dirname: context.inject_dirname,
+            commonjs_modules: context.commonjs_modules,
+          };
+          let mut commonjs_rule = CommonJsRule::new(&commonjs_params);
+          let server_params = GetServerWarningsParams {
+            specifier,
+            program: &program,
+            is_declaration_file,
+            unresolved_context: parsed_source.unresolved_context(),
+            ignore_line_indexes: &ignore_line_indexes.line_indexes,
+            top_level_decls: &top_level_decls,
+            server_shim: context.server_shim,
+          };
+          let mut server_rule = ServerRule::new(&server_params);
+          let deno_feature_detection_params =
+            GetDenoFeatureDetectionWarningsParams {
+              specifier,
+              program: &program,
+              is_declaration_file,
+              unresolved_context: parsed_source.unresolved_context(),
+              shim_global_names: &env_context.shim_global_names,
+              ignore_line_indexes: &ignore_line_indexes.line_indexes,
+              top_level_decls: &top_level_decls,
+              deno_feature_detection: context.deno_feature_detection,
+              import_style: context.shim_import_style,
+            };
+          let mut deno_feature_detection_rule =
+            DenoFeatureDetectionRule::new(&deno_feature_detection_params);
+          let unshimmable_apis_params = GetUnshimmableApiUsagesParams {
+            specifier,
+            program: &program,
+            is_declaration_file,
+            unresolved_context: parsed_source.unresolved_context(),
+            ignore_line_indexes: &ignore_line_indexes.line_indexes,
+            top_level_decls: &top_level_decls,
+          };
+          let mut unshimmable_apis_rule =
+            UnshimmableApisRule::new(&unshimmable_apis_params);
+          let dom_lib_params = GetDomLibParams {
+            specifier,
+            program: &program,
+            unresolved_context: parsed_source.unresolved_context(),
+            ignore_line_indexes: &ignore_line_indexes.line_indexes,
+            top_level_decls: &top_level_decls,
+            types_package_name: context
+              .dom_types_package
+              .map(|p| p.name.as_str()),
+          };
+          let mut dom_lib_rule = DomLibRule::new(&dom_lib_params);
+          let result = get_text_changes(
+            &GetTextChangesParams {
+              globals: GetGlobalTextChangesParams {
+                program: &program,
+                unresolved_context: parsed_source.unresolved_context(),
+                shim_specifier: &shim_relative_specifier,
+                shim_global_names: &env_context.shim_global_names,
+                ignore_line_indexes: &ignore_line_indexes.line_indexes,
+                top_level_decls: &top_level_decls,
+                server_shim: context.server_shim,
+                import_style: context.shim_import_style,
+                deno_feature_detection: context.deno_feature_detection,
+                qualified_global_access: context.qualified_global_access,
+              },
+              import_exports: GetImportExportsTextChangesParams {
+                specifier,
+                media_type: file.media_type,
+                is_declaration_file,
+                jsx: context.jsx,
+                dependency_resolver: context.dependency_resolver,
+                mappings,
+                program: &program,
+                package_specifier_mappings: env_context
+                  .package_specifier_mappings,
+                relative_specifier_extension: env_context
+                  .relative_specifier_extension,
+                annotate_rewrites: context.annotate_rewrites,
+                import_attributes: context.import_attributes,
+              },
+              comment_directives: context.comment_directives,
+            },
+            &mut [
+              &mut commonjs_rule,
+              &mut server_rule,
+              &mut deno_feature_detection_rule,
+              &mut unshimmable_apis_rule,
+              &mut dom_lib_rule,
+            ],
+          )?;
+          transformed_file.unshimmable_api_usages =
+            unshimmable_apis_rule.into_usages();
+          let commonjs_result = commonjs_rule.into_result()?;
+          transformed_file.warnings.extend(commonjs_result.warnings);
+          transformed_file
+            .warnings
+            .extend(server_rule.into_warnings());
+          transformed_file
+            .warnings
+            .extend(deno_feature_detection_rule.into_warnings());
+          let dom_lib_result = dom_lib_rule.into_result();
+          transformed_file.warnings.extend(dom_lib_result.warnings);
+          transformed_file.uses_dom_apis = dom_lib_result.uses_dom_apis;
+          transformed_file.used_shim_globals = result.used_shim_globals;
+          transformed_file.relative_specifiers = result.relative_specifiers;
+
+          let mut text_changes = result.text_changes;
+          text_changes.extend(commonjs_result.text_changes);
+          text_changes.extend(dom_lib_result.text_changes);
+          let visitor_context = TransformVisitorContext {
+            program: &program,
+            specifier,
+            environment: env_context.output_environment,
+            mappings,
+          };
+          for visitor in context.custom_visitors.iter() {
+            trace_span!("visit", pass = "custom_visitor");
+            text_changes.extend(visitor.visit(&visitor_context)?);
+          }
+          // after the statements the other changes add to the top
+          text_changes.extend(commonjs_result.wrapper_text_changes);
+
+          Ok(text_changes)
+        })
+        .with_context(|| {
+          format!(
+            "Issue getting text changes from {}",
+            parsed_source.specifier()
+          )
+        })?;
+      transformed_file.timings.visit += visit_stopwatch.elapsed();
+
+      trace_span!("apply_text_changes");
+      let stopwatch = Stopwatch::start();
+      let text_changes = text_changes
+        .into_iter()
+        .map(|change| TextChange {
+          new_text: with_new_line_kind(&change.new_text, new_line_kind),
+          range: change.range,
+        })
+        .collect();
+      let file_text =
+        apply_text_changes(parsed_source.text_info().text_str(), text_changes)
+          .with_context(|| {
+            format!("Issue applying text changes to {}", specifier)
+          })?;
+      transformed_file.timings.apply_text_changes += stopwatch.elapsed();
+      if context.normalize_output_text {
+        with_new_line_kind(&file_text, NewLineKind::LineFeed)
+      } else if has_bom {
+        format!("{}{}", BOM_CHAR, file_text)
+      } else {
+        file_text
+      }
+    }
+    PendingFileKind::Asserted => {
+      let file_text =
+        format!("export default {};", strip_bom(&file.source).trim());
+      if context.normalize_output_text {
+        with_new_line_kind(&file_text, NewLineKind::LineFeed)
+      } else {
+        file_text
+      }
+    }
+    PendingFileKind::Asset => {
+      let text = strip_bom(&file.source);
+      let text = if context.normalize_output_text {
+        with_new_line_kind(text, NewLineKind::LineFeed)
+      } else {
+        text.to_string()
+      };
+      format!("export default {};\n", to_js_string_literal(&text))
+    }
+    PendingFileKind::HandledAsset(attribute_type) => {
+      let asset_output = context.asset_handlers[attribute_type]
+        .handle_asset(&AssetFile {
+          specifier,
+          attribute_type,
+          text: strip_bom(&file.source),
+        })
+        .with_context(|| {
+          format!(
+            "Issue handling {} imported with type \"{}\"",
+            specifier, attribute_type
+          )
+        })?;
+      if asset_output.copy_file {
+        transformed_file.asset_file = Some(OutputFile {
+          file_path: with_extension(mappings.get_file_path(specifier), ""),
+          file_text: file.source.to_string(),
+        });
+      }
+      asset_output.module_text
+    }
+  };
+
+  Ok(transformed_file)
+}
+
+/// Replaces the defined expressions and rewrites the `Deno.env` and other
+/// Deno API calls in the source before it's parsed for the other passes,
+/// so those passes don't see the replaced expressions (ex. the `Deno`
+/// global of `Deno.env.get("TARGET")` isn't shimmed).
+fn apply_source_rewrites(
+  specifier: &ModuleSpecifier,
+  source: Arc<str>,
+  media_type: MediaType,
+  maybe_parsed_source: Option<&ParsedSource>,
+  context: &TransformFileContext,
+) -> Result<Arc<str>> {
+  trace_span!("visit", pass = "source_rewrites");
+  let parsed_source = reuse_or_parse_module(
+    specifier,
+    source.clone(),
+    media_type,
+    maybe_parsed_source,
+  )?;
+  let text_changes = parsed_source.with_view(|program| {
+    let mut text_changes =
+      get_define_text_changes(&GetDefineTextChangesParams {
+        program,
+        unresolved_context: parsed_source.unresolved_context(),
+        defines: context.defines,
+      });
+    // the defines take precedence
+    let define_ranges = text_changes
+      .iter()
+      .map(|change| change.range.clone())
+      .collect::<Vec<_>>();
+    if context.deno_env_to_process_env {
+      text_changes.extend(get_deno_env_text_changes(
+        &GetDenoEnvTextChangesParams {
+          program,
+          unresolved_context: parsed_source.unresolved_context(),
+          media_type,
+          excluded_ranges: &define_ranges,
+        },
+      ));
+    }
+    if context.translate_deno_apis {
+      text_changes.extend(get_deno_api_text_changes(
+        &GetDenoApiTextChangesParams {
+          program,
+          unresolved_context: parsed_source.unresolved_context(),
+          excluded_ranges: &define_ranges,
+        },
+      ));
+    }
+    text_changes
+  });
+  if text_changes.is_empty() {
+    return Ok(source);
+  }
+  let text = apply_text_changes(&source, text_changes).with_context(|| {
+    format!("Issue applying source rewrites to {}", specifier)
+  })?;
+  Ok(Arc::from(text))
+}
+
+/// Reduces a TypeScript module to its type declarations before it's parsed
+/// for the other passes, so the imports and globals left in the
+/// declarations are handled like those of any other module.
+fn strip_to_declarations(
+  specifier: &ModuleSpecifier,
+  source: Arc<str>,
+  media_type: MediaType,
+  maybe_parsed_source: Option<&ParsedSource>,
+) -> Result<(Arc<str>, Vec<String>)> {
+  trace_span!("visit", pass = "declarations");
+  let parsed_source = reuse_or_parse_module(
+    specifier,
+    source.clone(),
+    media_type,
+    maybe_parsed_source,
+  )?;
+  let result = parsed_source.with_view(|program| {
+    get_declaration_text_changes(&GetDeclarationTextChangesParams {
+      specifier,
+      program,
+    })
+  });
+  let text =
+    apply_text_changes(&source, result.text_changes).with_context(|| {
+      format!("Issue stripping {} to declarations", specifier)
+    })?;
+  Ok((Arc::from(text), result.warnings))
+}
+
+/// Reuses the source parsed when building the graph when it's for the same
+/// text, otherwise parses the text, which was changed by an earlier pass
+/// (ex. the source rewrites) or had its BOM stripped.
+fn reuse_or_parse_module(
+  specifier: &ModuleSpecifier,
+  source: Arc<str>,
+  media_type: MediaType,
+  maybe_parsed_source: Option<&ParsedSource>,
+) -> Result<ParsedSource> {
+  if let Some(parsed_source) = maybe_parsed_source {
+    if parsed_source.text_info().text_str() == &*source {
+      return Ok(parsed_source.clone());
+    }
+  }
+  ScopeAnalysisParser::new()
+    .parse_module(specifier, source, media_type)
+    .map_err(|diagnostic| {
+      TransformError::Parse {
+        specifier: specifier.clone(),
+        line_number: diagnostic.display_position.line_number,
+        column_number: diagnostic.display_position.column_number,
+        message: diagnostic.to_string(),
+      }
+      .into()
+    })
+}
+
+/// Gets the bare specifiers the test environment imports the main entry
+/// points by, which are the names they're exported by in the package.json.
+fn get_self_reference_mappings(
+  package_name: &str,
+  entry_points: &[ModuleSpecifier],
+  mappings: &Mappings,
+) -> HashMap<ModuleSpecifier, String> {
+  entry_points
+    .iter()
+    .enumerate()
+    .map(|(i, entry_point)| {
+      let export_name = if i == 0 {
+        ".".to_string()
+      } else {
+        package::get_export_name(mappings.get_file_path(entry_point))
+      };
+      // ex. `./sub` is imported as `my-package/sub`
+      let bare_specifier = format!("{}{}", package_name, &export_name[1..]);
+      (entry_point.clone(), bare_specifier)
+    })
+    .collect()
 }
 
 fn add_shim_types_packages_to_test_environment<'a>(
@@ -505,7 +3250,11 @@ fn check_add_polyfill_file_to_environment(
           &mut file.file_text,
           &format!(
             "import \"{}\";",
-            get_relative_specifier(&file.file_path, &polyfill_file_path)
+            get_relative_specifier(
+              &file.file_path,
+              &polyfill_file_path,
+              env_context.relative_specifier_extension,
+            )
           ),
         );
       }
@@ -513,34 +3262,78 @@ fn check_add_polyfill_file_to_environment(
   }
 }
 
+/// Gets the names of the packages, or the modules, of the shims that
+/// provide the globals a file uses.
+fn get_shims_for_globals(
+  globals: &[String],
+  shims: &[Shim],
+  server_shim: Option<&ServerShim>,
+) -> Vec<String> {
+  let mut names = BTreeSet::new();
+  for global in globals {
+    if global.starts_with("Deno.") {
+      if let Some(server_shim) = server_shim {
+        names.insert(server_shim.package.name.clone());
+        continue;
+      }
+    }
+    for shim in shims {
+      // `globalThis` is replaced with an object of every shimmed global
+      if global == "globalThis"
+        || shim.global_names().iter().any(|n| &n.name == global)
+      {
+        names.insert(match shim {
+          Shim::Package(shim) => shim.package.name.clone(),
+          Shim::Module(shim) => shim.module.clone(),
+        });
+      }
+    }
+  }
+  names.into_iter().collect()
+}
+
 fn check_add_shim_file_to_environment(
   env_context: &mut EnvironmentContext,
   shim_file_path: &Path,
+  server_shim: Option<&ServerShim>,
+  import_style: ShimImportStyle,
   mappings: &Mappings,
 ) {
   if env_context.used_shim {
-    let shim_file_text =
-      build_shim_file(env_context.shims, shim_file_path, mappings);
+    let shim_file_text = build_shim_file(
+      env_context.shims,
+      server_shim,
+      import_style,
+      shim_file_path,
+      mappings,
+      env_context.relative_specifier_extension,
+    );
     env_context.environment.files.push(OutputFile {
       file_path: shim_file_path.to_path_buf(),
       file_text: shim_file_text,
     });
 
-    for shim in env_context.shims.iter() {
-      if let Shim::Package(shim) = shim {
-        if !env_context
-          .environment
-          .dependencies
-          .iter()
-          .any(|d| d.name == shim.package.name)
-        {
-          if let Some(version) = &shim.package.version {
-            env_context.environment.dependencies.push(Dependency {
-              name: shim.package.name.to_string(),
-              version: version.clone(),
-              peer_dependency: shim.package.peer_dependency,
-            });
-          }
+    let packages = env_context
+      .shims
+      .iter()
+      .filter_map(|shim| match shim {
+        Shim::Package(shim) => Some(&shim.package),
+        Shim::Module(_) => None,
+      })
+      .chain(server_shim.map(|s| &s.package));
+    for package in packages {
+      if !env_context
+        .environment
+        .dependencies
+        .iter()
+        .any(|d| d.name == package.name)
+      {
+        if let Some(version) = &package.version {
+          env_context.environment.dependencies.push(Dependency {
+            name: package.name.to_string(),
+            version: version.clone(),
+            peer_dependency: package.peer_dependency,
+          });
         }
       }
     }
@@ -548,8 +3341,11 @@ fn check_add_shim_file_to_environment(
 
   fn build_shim_file(
     shims: &[Shim],
+    server_shim: Option<&ServerShim>,
+    import_style: ShimImportStyle,
     shim_file_path: &Path,
     mappings: &Mappings,
+    extension: RelativeSpecifierExtension,
   ) -> String {
     fn get_specifer_text(n: &GlobalName) -> String {
       let name_text = if let Some(export_name) = &n.export_name {
@@ -568,13 +3364,14 @@ fn check_add_shim_file_to_environment(
       shim: &Shim,
       shim_file_path: &Path,
       mappings: &Mappings,
+      extension: RelativeSpecifierExtension,
     ) -> String {
       match shim {
         Shim::Package(shim) => shim.package.module_specifier_text(),
         Shim::Module(shim) => match shim.maybe_specifier() {
           Some(specifier) => {
             let to = mappings.get_file_path(&specifier);
-            get_relative_specifier(shim_file_path, to)
+            get_relative_specifier(shim_file_path, to, extension)
           }
           None => shim.module.clone(),
         },
@@ -589,7 +3386,7 @@ fn check_add_shim_file_to_environment(
         .filter(|n| !n.type_only)
         .collect::<Vec<_>>();
       let module_specifier_text =
-        get_module_specifier_text(shim, shim_file_path, mappings);
+        get_module_specifier_text(shim, shim_file_path, mappings, extension);
       if !declaration_names.is_empty() {
         text.push_str(&format!(
           "import {{ {} }} from \"{}\";\n",
@@ -614,6 +3411,14 @@ fn check_add_shim_file_to_environment(
       ));
     }
 
+    if let Some(server_shim) = server_shim {
+      // not a global, so it's only used for the server APIs of `Deno`
+      text.push_str(&format!(
+        "export * as dntServer from \"{}\";\n",
+        server_shim.package.module_specifier_text(),
+      ));
+    }
+
     if !text.is_empty() {
       text.push('\n');
     }
@@ -626,6 +3431,14 @@ fn check_add_shim_file_to_environment(
     }
     text.push_str("};\n");
     text.push_str("export const dntGlobalThis = createMergeProxy(globalThis, dntGlobals);\n\n");
+    if import_style == ShimImportStyle::SideEffect {
+      // the modules importing this file use the globals as is
+      text.push_str(concat!(
+        "for (const [name, value] of Object.entries(dntGlobals)) {\n",
+        "  Object.defineProperty(globalThis, name, { value, writable: true, configurable: true });\n",
+        "}\n\n",
+      ));
+    }
 
     text.push_str(
       &include_str!("scripts/createMergeProxy.ts")
@@ -641,16 +3454,19 @@ fn get_dependencies(
 ) -> Vec<Dependency> {
   let mut dependencies = mappings
     .into_iter()
-    .filter_map(|entry| {
+    .flat_map(|entry| {
+      let mut dependencies = Vec::with_capacity(2);
       if let Some(version) = entry.1.version {
-        Some(Dependency {
+        dependencies.push(Dependency {
           name: entry.1.name,
           version,
           peer_dependency: entry.1.peer_dependency,
-        })
-      } else {
-        None
+        });
+      }
+      if let Some(types_package) = entry.1.types_package {
+        dependencies.push(types_package);
       }
+      dependencies
     })
     .collect::<Vec<_>>();
   dependencies.sort_by(|a, b| a.name.cmp(&b.name));
@@ -658,9 +3474,55 @@ fn get_dependencies(
   dependencies
 }
 
-fn get_declaration_warnings(specifiers: &Specifiers) -> Vec<String> {
+fn apply_dependency_versions(
+  environment: OutputEnvironment,
+  dependencies: &mut Vec<Dependency>,
+  version_policy: VersionPolicy,
+  version_handler: Option<&dyn VersionHandler>,
+) -> Result<()> {
+  for dependency in dependencies.iter_mut() {
+    dependency.version =
+      package::apply_version_policy(&dependency.version, version_policy);
+    if let Some(handler) = version_handler {
+      let version = handler.handle_version(environment, dependency)?;
+      if let Err(err) = package::validate_version_range(&version) {
+        bail!(
+          "Invalid version \"{}\" for dependency {} from the version handler. {}",
+          version,
+          dependency.name,
+          err
+        );
+      }
+      dependency.version = version;
+    }
+  }
+  // different versions may now be the same
+  dependencies
+    .sort_by(|a, b| a.name.cmp(&b.name).then(a.version.cmp(&b.version)));
+  dependencies.dedup();
+  Ok(())
+}
+
+fn get_directory_import_warning(
+  specifier: &ModuleSpecifier,
+  index_specifier: &ModuleSpecifier,
+) -> String {
+  format!(
+    "Resolved the directory import {} to {}. Import the file directly to avoid relying on the directory index files.",
+    specifier, index_specifier
+  )
+}
+
+fn get_declaration_warnings(
+  specifiers: &Specifiers,
+  declaration_mappings: &HashMap<ModuleSpecifier, ModuleSpecifier>,
+) -> Vec<String> {
   let mut messages = Vec::new();
   for (code_specifier, d) in specifiers.types.iter() {
+    if declaration_mappings.contains_key(code_specifier) {
+      // the user explicitly chose this declaration file
+      continue;
+    }
     if d.selected.referrer.scheme() == "file" {
       let local_referrers =
         d.ignored.iter().filter(|d| d.referrer.scheme() == "file");
@@ -694,3 +3556,63 @@ fn get_declaration_warnings(specifiers: &Specifiers) -> Vec<String> {
     format!("Duplicate declaration file found for {}\n  Specified {} in {}\n  Selected {}\n  {}", code_specifier, dep.specifier, dep.referrer, selected_dep.specifier, post_message)
   }
 }
+
+fn get_untyped_dependencies(
+  module_graph: &crate::graph::ModuleGraph,
+  specifiers: &Specifiers,
+  entry_points: &[ModuleSpecifier],
+) -> Vec<UntypedDependency> {
+  specifiers
+    .untyped
+    .iter()
+    .map(|specifier| UntypedDependency {
+      specifier: specifier.clone(),
+      referrer_chain: module_graph.get_referrer_chain(entry_points, specifier),
+    })
+    .collect()
+}
+
+fn get_cycle_message(cycle: &[ModuleSpecifier]) -> String {
+  format!(
+    "Circular dependency found: {}",
+    cycle
+      .iter()
+      .map(|s| s.as_str())
+      .collect::<Vec<_>>()
+      .join(" -> ")
+  )
+}
+
+fn get_duplicate_package_message(package: &DuplicatePackage) -> String {
+  let mut message =
+    format!("Multiple versions of {} were found:", package.name);
+  for version in package.versions.iter() {
+    message.push_str(&format!("\n  {}", version.version));
+    if let Some(output_dir) = &version.output_dir {
+      message.push_str(&format!(" (output to {})", output_dir.display()));
+    }
+    message.push_str(&format!(
+      ": {}",
+      version
+        .referrer_chain
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+    ));
+  }
+  message
+}
+
+fn get_untyped_warning(dep: &UntypedDependency) -> String {
+  format!(
+    "Could not find a declaration file for {}\n  Referrer chain: {}\n  Supress this warning by specifying a declaration file for this module locally via `@deno-types`.",
+    dep.specifier,
+    dep
+      .referrer_chain
+      .iter()
+      .map(|s| s.to_string())
+      .collect::<Vec<_>>()
+      .join(" -> "),
+  )
+}
@@ -0,0 +1,225 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use deno_ast::ModuleSpecifier;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::utils::strip_bom;
+
+/// Name of the file in the main output that contains the license banners
+/// of the remote modules when `third_party_licenses` is enabled.
+pub const THIRD_PARTY_LICENSES_FILE_NAME: &str = "THIRD_PARTY_LICENSES";
+
+static LICENSE_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r"(?i)\b(copyright|licen[cs]ed?|spdx-license-identifier)\b|\(c\)|©|@preserve")
+    .unwrap()
+});
+
+/// Gets the license banner at the top of a module, which is made up of
+/// the leading comments that mention a copyright or license.
+pub fn get_license_banner(text: &str) -> Option<String> {
+  let banners = get_leading_comment_blocks(text)
+    .into_iter()
+    .filter(|block| LICENSE_RE.is_match(block))
+    .collect::<Vec<_>>();
+  if banners.is_empty() {
+    None
+  } else {
+    Some(banners.join("\n\n"))
+  }
+}
+
+/// Builds the text of the file containing the license banners of the
+/// remote modules, where modules with the same banner are listed together.
+pub fn build_third_party_licenses_text<'a>(
+  banners: impl Iterator<Item = (&'a ModuleSpecifier, String)>,
+) -> Option<String> {
+  let mut specifiers_by_banner: BTreeMap<String, BTreeSet<String>> =
+    BTreeMap::new();
+  for (specifier, banner) in banners {
+    specifiers_by_banner
+      .entry(banner)
+      .or_default()
+      .insert(specifier.to_string());
+  }
+  let mut groups = specifiers_by_banner
+    .into_iter()
+    .map(|(banner, specifiers)| (specifiers, banner))
+    .collect::<Vec<_>>();
+  if groups.is_empty() {
+    return None;
+  }
+  groups.sort();
+
+  let mut text = String::new();
+  for (i, (specifiers, banner)) in groups.into_iter().enumerate() {
+    if i > 0 {
+      text.push_str(&"-".repeat(80));
+      text.push_str("\n\n");
+    }
+    for specifier in specifiers {
+      text.push_str(&specifier);
+      text.push('\n');
+    }
+    text.push('\n');
+    text.push_str(&banner);
+    text.push_str("\n\n");
+  }
+  text.pop();
+  Some(text)
+}
+
+/// Gets the text of the comments before any code, where consecutive line
+/// comments are combined into a single block.
+fn get_leading_comment_blocks(text: &str) -> Vec<String> {
+  let mut text = strip_bom(text);
+  if text.starts_with("#!") {
+    text = text.find('\n').map(|index| &text[index..]).unwrap_or("");
+  }
+
+  let mut blocks = Vec::new();
+  let mut line_comments: Vec<&str> = Vec::new();
+  loop {
+    let trimmed = text.trim_start();
+    // a blank line ends a block of line comments
+    let whitespace = &text[..text.len() - trimmed.len()];
+    if whitespace.matches('\n').count() > 1 {
+      flush_line_comments(&mut line_comments, &mut blocks);
+    }
+    text = trimmed;
+
+    if let Some(rest) = text.strip_prefix("//") {
+      let end = rest.find('\n').unwrap_or(rest.len());
+      let line = rest[..end].trim_end();
+      if line.starts_with("/ <") || line.starts_with("/<") {
+        // triple slash directives aren't part of a banner
+        flush_line_comments(&mut line_comments, &mut blocks);
+      } else {
+        line_comments.push(line.strip_prefix(' ').unwrap_or(line));
+      }
+      text = &rest[end..];
+    } else if let Some(rest) = text.strip_prefix("/*") {
+      flush_line_comments(&mut line_comments, &mut blocks);
+      let end = match rest.find("*/") {
+        Some(end) => end,
+        None => break,
+      };
+      blocks.push(get_block_comment_text(&rest[..end]));
+      text = &rest[end + 2..];
+    } else {
+      break;
+    }
+  }
+  flush_line_comments(&mut line_comments, &mut blocks);
+  blocks
+}
+
+fn flush_line_comments(
+  line_comments: &mut Vec<&str>,
+  blocks: &mut Vec<String>,
+) {
+  if !line_comments.is_empty() {
+    blocks.push(line_comments.join("\n").trim().to_string());
+    line_comments.clear();
+  }
+}
+
+/// Gets the text of a block comment without its leading asterisks.
+fn get_block_comment_text(comment_text: &str) -> String {
+  let comment_text = comment_text
+    .strip_prefix('!')
+    .or_else(|| comment_text.strip_prefix('*'))
+    .unwrap_or(comment_text);
+  comment_text
+    .lines()
+    .map(|line| {
+      let line = line.trim();
+      match line.strip_prefix('*') {
+        Some(line) => line.strip_prefix(' ').unwrap_or(line),
+        None => line,
+      }
+    })
+    .collect::<Vec<_>>()
+    .join("\n")
+    .trim()
+    .to_string()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_get_license_banner() {
+    assert_eq!(
+      get_license_banner(concat!(
+        "/*!\n",
+        " * Copyright 2022 Someone.\n",
+        " * MIT license.\n",
+        " */\n",
+        "export const a = 5;",
+      )),
+      Some("Copyright 2022 Someone.\nMIT license.".to_string()),
+    );
+    assert_eq!(
+      get_license_banner(concat!(
+        "\u{FEFF}#!/usr/bin/env node\n",
+        "// Copyright 2022 Someone. All rights reserved.\n",
+        "// MIT license.\n",
+        "\n",
+        "// Some other comment.\n",
+        "/// <reference types=\"./mod.d.ts\" />\n",
+        "export const a = 5; // (c) not a banner",
+      )),
+      Some(
+        "Copyright 2022 Someone. All rights reserved.\nMIT license."
+          .to_string()
+      ),
+    );
+    assert_eq!(
+      get_license_banner("// Some comment.\nexport const a = 5;"),
+      None,
+    );
+    assert_eq!(
+      get_license_banner("export const a = 5;\n// Copyright 2022 Someone."),
+      None,
+    );
+  }
+
+  #[test]
+  fn test_build_third_party_licenses_text() {
+    let a = ModuleSpecifier::parse("https://localhost/a.ts").unwrap();
+    let b = ModuleSpecifier::parse("https://localhost/b.ts").unwrap();
+    let c = ModuleSpecifier::parse("https://localhost/c.ts").unwrap();
+    assert_eq!(build_third_party_licenses_text(std::iter::empty()), None);
+    assert_eq!(
+      build_third_party_licenses_text(
+        [
+          (&c, "Copyright A".to_string()),
+          (&b, "Copyright B".to_string()),
+          (&a, "Copyright A".to_string()),
+        ]
+        .into_iter()
+      )
+      .unwrap(),
+      format!(
+        concat!(
+          "https://localhost/a.ts\n",
+          "https://localhost/c.ts\n",
+          "\n",
+          "Copyright A\n",
+          "\n",
+          "{}\n",
+          "\n",
+          "https://localhost/b.ts\n",
+          "\n",
+          "Copyright B\n",
+        ),
+        "-".repeat(80),
+      ),
+    );
+  }
+}
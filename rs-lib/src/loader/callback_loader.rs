@@ -0,0 +1,111 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::bail;
+use anyhow::Result;
+use deno_ast::ModuleSpecifier;
+use futures::channel::oneshot;
+use futures::Future;
+
+use super::LoadResponse;
+use super::Loader;
+
+/// A request to load a module made by a `CallbackLoader`, which can be
+/// sent to and responded to on any thread.
+pub struct LoadRequest {
+  specifier: ModuleSpecifier,
+  sender: oneshot::Sender<Result<Option<LoadResponse>>>,
+}
+
+impl LoadRequest {
+  pub fn specifier(&self) -> &ModuleSpecifier {
+    &self.specifier
+  }
+
+  /// Responds with the loaded module or `None` when it's not found.
+  pub fn respond(self, result: Result<Option<LoadResponse>>) {
+    // the transform stops waiting for the response when it fails
+    let _ = self.sender.send(result);
+  }
+}
+
+/// Loader that hands each request to a callback, which may respond later
+/// from another thread.
+///
+/// The futures of a `Loader` run on the thread of the transform, so this
+/// bridges the transform to loaders that run elsewhere (ex. JavaScript
+/// functions called from a Node-API addon with a thread-safe function).
+pub struct CallbackLoader {
+  callback: Arc<dyn Fn(LoadRequest) + Send + Sync>,
+}
+
+impl CallbackLoader {
+  pub fn new(callback: impl Fn(LoadRequest) + Send + Sync + 'static) -> Self {
+    Self {
+      callback: Arc::new(callback),
+    }
+  }
+}
+
+impl Loader for CallbackLoader {
+  fn load(
+    &self,
+    specifier: ModuleSpecifier,
+  ) -> Pin<Box<dyn Future<Output = Result<Option<LoadResponse>>> + 'static>> {
+    let (sender, receiver) = oneshot::channel();
+    (self.callback)(LoadRequest {
+      specifier: specifier.clone(),
+      sender,
+    });
+    Box::pin(async move {
+      match receiver.await {
+        Ok(result) => result,
+        Err(_) => bail!(
+          "The request to load {} was dropped without a response.",
+          specifier
+        ),
+      }
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use futures::executor::block_on;
+
+  use super::*;
+
+  #[test]
+  fn responds_from_another_thread() {
+    let loader = CallbackLoader::new(|request| {
+      std::thread::spawn(move || {
+        let response = LoadResponse {
+          specifier: request.specifier().clone(),
+          headers: None,
+          content: "export const a = 1;".to_string(),
+        };
+        request.respond(Ok(Some(response)));
+      });
+    });
+    let specifier = ModuleSpecifier::parse("https://localhost/mod.ts").unwrap();
+    let response = block_on(loader.load(specifier.clone())).unwrap().unwrap();
+    assert_eq!(response.specifier, specifier);
+    assert_eq!(response.content, "export const a = 1;");
+  }
+
+  #[test]
+  fn errors_when_request_dropped() {
+    let loader = CallbackLoader::new(drop);
+    let specifier = ModuleSpecifier::parse("https://localhost/mod.ts").unwrap();
+    let err = match block_on(loader.load(specifier)) {
+      Ok(_) => unreachable!(),
+      Err(err) => err,
+    };
+    assert_eq!(
+      err.to_string(),
+      "The request to load https://localhost/mod.ts was dropped without a response.",
+    );
+  }
+}
@@ -1,21 +1,70 @@
 // Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
 
 use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
 use std::pin::Pin;
 
+use anyhow::Context;
 use anyhow::Result;
 use deno_ast::ModuleSpecifier;
 use futures::Future;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderName;
+use reqwest::header::HeaderValue;
 
 use crate::utils::url_to_file_path;
+use crate::utils::with_file_path;
 use crate::LoadResponse;
 use crate::Loader;
 
-pub struct DefaultLoader {}
+/// Options for the requests the default loader makes for remote modules,
+/// which is useful for loading from a mirror that requires a certain user
+/// agent or uses an internal certificate authority.
+#[derive(Clone, Debug, Default)]
+pub struct DefaultLoaderOptions {
+  /// Value of the `user-agent` header. No user agent is sent by default.
+  pub user_agent: Option<String>,
+  /// Headers sent with every request (ex. an `authorization` header).
+  pub headers: Vec<(String, String)>,
+  /// PEM encoded certificates to trust in addition to the built-in root
+  /// certificates (ex. the contents of a custom CA bundle file).
+  pub root_certificates: Vec<Vec<u8>>,
+}
+
+pub struct DefaultLoader {
+  client: reqwest::Client,
+}
 
 impl DefaultLoader {
   pub fn new() -> Self {
-    Self {}
+    Self {
+      client: reqwest::Client::new(),
+    }
+  }
+
+  pub fn with_options(options: DefaultLoaderOptions) -> Result<Self> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(user_agent) = options.user_agent {
+      builder = builder.user_agent(user_agent);
+    }
+    let mut headers = HeaderMap::new();
+    for (name, value) in options.headers {
+      let header_name = HeaderName::from_bytes(name.as_bytes())
+        .with_context(|| format!("Invalid header name \"{}\".", name))?;
+      let header_value = HeaderValue::from_str(&value)
+        .with_context(|| format!("Invalid value for header \"{}\".", name))?;
+      headers.append(header_name, header_value);
+    }
+    builder = builder.default_headers(headers);
+    for (i, pem) in options.root_certificates.iter().enumerate() {
+      let certificate = reqwest::Certificate::from_pem(pem)
+        .with_context(|| format!("Invalid root certificate at index {}.", i))?;
+      builder = builder.add_root_certificate(certificate);
+    }
+    Ok(Self {
+      client: builder.build()?,
+    })
   }
 }
 
@@ -24,12 +73,19 @@ impl Loader for DefaultLoader {
     &self,
     specifier: ModuleSpecifier,
   ) -> Pin<Box<dyn Future<Output = Result<Option<LoadResponse>>> + 'static>> {
+    let client = self.client.clone();
     Box::pin(async move {
       if specifier.scheme() == "file" {
         let file_path = url_to_file_path(&specifier)?;
-        return match tokio::fs::read_to_string(file_path).await {
-          Ok(result) => Ok(Some(LoadResponse {
-            specifier,
+        return match read_local_file(&file_path).await {
+          Ok((canonical_path, result)) => Ok(Some(LoadResponse {
+            // use the canonical path so that a file referenced via a
+            // symlink is the same module as the file itself
+            specifier: if canonical_path == file_path {
+              specifier
+            } else {
+              with_file_path(&specifier, &canonical_path)
+            },
             content: result,
             headers: None,
           })),
@@ -43,7 +99,7 @@ impl Loader for DefaultLoader {
         };
       }
 
-      let response = reqwest::get(specifier.clone()).await?;
+      let response = client.get(specifier.clone()).send().await?;
       let headers = response
         .headers()
         .into_iter()
@@ -63,3 +119,11 @@ impl Loader for DefaultLoader {
     })
   }
 }
+
+async fn read_local_file(
+  file_path: &Path,
+) -> std::io::Result<(PathBuf, String)> {
+  let canonical_path = tokio::fs::canonicalize(file_path).await?;
+  let text = tokio::fs::read_to_string(&canonical_path).await?;
+  Ok((canonical_path, text))
+}
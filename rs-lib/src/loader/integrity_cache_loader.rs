@@ -0,0 +1,307 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use deno_ast::ModuleSpecifier;
+use futures::future;
+use futures::Future;
+use sha2::Digest;
+use sha2::Sha256;
+
+use super::LoadResponse;
+use super::Loader;
+
+/// Loader that stores the remote modules by the checksum of their content
+/// and looks them up by the checksum in a lockfile instead of by url.
+///
+/// Rebuilding an old release with its lockfile then uses exactly the bytes
+/// it was originally built with, even if a url now serves something else.
+/// Modules that aren't in the cache are loaded with the wrapped loader and
+/// must match the checksum in the lockfile when they're in it.
+pub struct IntegrityCacheLoader {
+  inner: Box<dyn Loader>,
+  cache: Arc<IntegrityCache>,
+  lockfile: Arc<HashMap<ModuleSpecifier, String>>,
+}
+
+impl IntegrityCacheLoader {
+  /// Creates the loader with the directory to store the modules in and
+  /// the sha-256 checksums of the modules in hex by their url, which is
+  /// the format of the remote modules in a Deno lockfile.
+  pub fn new(
+    inner: Box<dyn Loader>,
+    cache_dir: PathBuf,
+    lockfile: HashMap<ModuleSpecifier, String>,
+  ) -> Self {
+    Self {
+      inner,
+      cache: Arc::new(IntegrityCache { dir: cache_dir }),
+      lockfile: Arc::new(
+        lockfile
+          .into_iter()
+          .map(|(specifier, checksum)| (specifier, checksum.to_lowercase()))
+          .collect(),
+      ),
+    }
+  }
+}
+
+impl Loader for IntegrityCacheLoader {
+  fn load(
+    &self,
+    specifier: ModuleSpecifier,
+  ) -> Pin<Box<dyn Future<Output = Result<Option<LoadResponse>>> + 'static>> {
+    if specifier.scheme() == "file" {
+      return self.inner.load(specifier);
+    }
+    let expected_checksum = self.lockfile.get(&specifier).cloned();
+    if let Some(checksum) = &expected_checksum {
+      if let Some(response) = self.cache.get(checksum) {
+        return Box::pin(future::ready(Ok(Some(response))));
+      }
+    }
+
+    let cache = self.cache.clone();
+    let lockfile = self.lockfile.clone();
+    let response_future = self.inner.load(specifier.clone());
+    Box::pin(async move {
+      let response = match response_future.await? {
+        Some(response) => response,
+        None => return Ok(None),
+      };
+      let checksum = get_checksum(&response.content);
+      // the lockfile may have the url after redirects
+      let expected_checksum = expected_checksum
+        .or_else(|| lockfile.get(&response.specifier).cloned());
+      if let Some(expected_checksum) = expected_checksum {
+        if expected_checksum != checksum {
+          bail!(
+            "Integrity check failed for {}. The lockfile has a checksum of {}, but the loaded module has a checksum of {}.",
+            specifier,
+            expected_checksum,
+            checksum,
+          );
+        }
+      }
+      cache.set(&checksum, &response)?;
+      Ok(Some(response))
+    })
+  }
+}
+
+struct IntegrityCache {
+  dir: PathBuf,
+}
+
+impl IntegrityCache {
+  /// Gets a cached module. Entries that can't be read or whose content
+  /// doesn't match the checksum are treated as missing.
+  pub fn get(&self, checksum: &str) -> Option<LoadResponse> {
+    let text = std::fs::read_to_string(self.entry_path(checksum)?).ok()?;
+    let response = deserialize(&text)?;
+    if get_checksum(&response.content) == checksum {
+      Some(response)
+    } else {
+      None
+    }
+  }
+
+  pub fn set(&self, checksum: &str, response: &LoadResponse) -> Result<()> {
+    let entry_path = match self.entry_path(checksum) {
+      Some(entry_path) => entry_path,
+      None => return Ok(()),
+    };
+    let write = || -> std::io::Result<()> {
+      std::fs::create_dir_all(&self.dir)?;
+      // write to a temporary file first so that a partially written
+      // entry is never read
+      let temp_path = entry_path.with_extension("tmp");
+      std::fs::write(&temp_path, serialize(response))?;
+      std::fs::rename(&temp_path, &entry_path)
+    };
+    write().with_context(|| {
+      format!("Error writing to cache directory {}", self.dir.display())
+    })
+  }
+
+  fn entry_path(&self, checksum: &str) -> Option<PathBuf> {
+    // don't allow a lockfile to point outside the directory
+    if checksum.is_empty() || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+      return None;
+    }
+    Some(self.dir.join(format!("sha256-{}", checksum)))
+  }
+}
+
+/// Gets the checksum of a module's content in the format of a lockfile.
+fn get_checksum(content: &str) -> String {
+  Sha256::digest(content.as_bytes())
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect()
+}
+
+// Format:
+// <specifier>
+// <header count>
+// <header name>
+// <header value>
+// ...
+// <content>
+fn serialize(response: &LoadResponse) -> String {
+  let mut headers = response
+    .headers
+    .iter()
+    .flat_map(|headers| headers.iter())
+    .collect::<Vec<_>>();
+  headers.sort();
+  let mut text = format!("{}\n{}\n", response.specifier, headers.len());
+  for (name, value) in headers {
+    text.push_str(&format!("{}\n{}\n", name, value));
+  }
+  text.push_str(&response.content);
+  text
+}
+
+fn deserialize(text: &str) -> Option<LoadResponse> {
+  fn take_line(text: &str) -> Option<(&str, &str)> {
+    text.split_once('\n')
+  }
+
+  let (specifier, text) = take_line(text)?;
+  let specifier = ModuleSpecifier::parse(specifier).ok()?;
+  let (header_count, mut text) = take_line(text)?;
+  let header_count = header_count.parse::<usize>().ok()?;
+  let mut headers = HashMap::with_capacity(header_count);
+  for _ in 0..header_count {
+    let (name, remaining) = take_line(text)?;
+    let (value, remaining) = take_line(remaining)?;
+    headers.insert(name.to_string(), value.to_string());
+    text = remaining;
+  }
+
+  Some(LoadResponse {
+    specifier,
+    headers: if header_count == 0 {
+      None
+    } else {
+      Some(headers)
+    },
+    content: text.to_string(),
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use futures::executor::block_on;
+
+  use super::*;
+
+  struct TestLoader(HashMap<ModuleSpecifier, String>);
+
+  impl Loader for TestLoader {
+    fn load(
+      &self,
+      specifier: ModuleSpecifier,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<LoadResponse>>> + 'static>>
+    {
+      let content = self.0.get(&specifier).cloned();
+      let response = content.map(|content| LoadResponse {
+        specifier,
+        headers: Some(HashMap::from([(
+          "content-type".to_string(),
+          "application/typescript".to_string(),
+        )])),
+        content,
+      });
+      Box::pin(future::ready(Ok(response)))
+    }
+  }
+
+  #[test]
+  fn checksum() {
+    assert_eq!(
+      get_checksum(""),
+      "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+    );
+  }
+
+  #[test]
+  fn serializes_and_deserializes() {
+    let response = LoadResponse {
+      specifier: ModuleSpecifier::parse("https://localhost/mod.ts").unwrap(),
+      headers: Some(HashMap::from([
+        (
+          "content-type".to_string(),
+          "application/typescript".to_string(),
+        ),
+        ("x-other".to_string(), "value".to_string()),
+      ])),
+      content: "export const a = 5;\n\n".to_string(),
+    };
+    let deserialized = deserialize(&serialize(&response)).unwrap();
+    assert_eq!(deserialized.specifier, response.specifier);
+    assert_eq!(deserialized.headers, response.headers);
+    assert_eq!(deserialized.content, response.content);
+  }
+
+  #[test]
+  fn loads_from_cache_by_checksum() {
+    let cache_dir = std::env::temp_dir()
+      .join(format!("dnt_integrity_cache_loader_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache_dir);
+    let specifier = ModuleSpecifier::parse("https://localhost/mod.ts").unwrap();
+    let original_content = "export const a = 5;";
+    let lockfile = HashMap::from([(
+      specifier.clone(),
+      get_checksum(original_content).to_uppercase(),
+    )]);
+
+    // loads and caches the original content
+    let loader = IntegrityCacheLoader::new(
+      Box::new(TestLoader(HashMap::from([(
+        specifier.clone(),
+        original_content.to_string(),
+      )]))),
+      cache_dir.clone(),
+      lockfile.clone(),
+    );
+    let response = block_on(loader.load(specifier.clone())).unwrap().unwrap();
+    assert_eq!(response.content, original_content);
+
+    // uses the cached content even though the url now serves something else
+    let loader = IntegrityCacheLoader::new(
+      Box::new(TestLoader(HashMap::from([(
+        specifier.clone(),
+        "export const a = 6;".to_string(),
+      )]))),
+      cache_dir.clone(),
+      lockfile.clone(),
+    );
+    let response = block_on(loader.load(specifier.clone())).unwrap().unwrap();
+    assert_eq!(response.content, original_content);
+
+    // errors when the content doesn't match and isn't cached
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+    let err = match block_on(loader.load(specifier.clone())) {
+      Ok(_) => unreachable!(),
+      Err(err) => err,
+    };
+    assert_eq!(
+      err.to_string(),
+      format!(
+        "Integrity check failed for {}. The lockfile has a checksum of {}, but the loaded module has a checksum of {}.",
+        specifier,
+        get_checksum(original_content),
+        get_checksum("export const a = 6;"),
+      ),
+    );
+    assert!(!cache_dir.exists());
+  }
+}
@@ -0,0 +1,271 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use deno_ast::ModuleSpecifier;
+use futures::future;
+use futures::Future;
+
+use super::LoadResponse;
+use super::Loader;
+use crate::trace::log_debug;
+
+/// Loads the urls that start with an origin from a mirror instead.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UrlMirror {
+  /// Start of the urls to load from the mirror (ex.
+  /// `https://deno.land/x/oak`).
+  pub origin: String,
+  /// Url to load them from, where `{path}` is replaced with the rest of
+  /// the url after the origin.
+  ///
+  /// When the rest of the url starts with a version (ex. `@v12.0.0/mod.ts`
+  /// for `https://deno.land/x/oak@v12.0.0/mod.ts`), `{version}` is replaced
+  /// with the version and `{path}` with the path after it, so a template
+  /// of `https://raw.githubusercontent.com/oakserver/oak/{version}/{path}`
+  /// loads the tagged source from GitHub. Urls without a version don't use
+  /// the mirror when the template has a `{version}`.
+  pub template: String,
+}
+
+impl UrlMirror {
+  /// Gets the url to load the specifier from or `None` when it's not for
+  /// this mirror.
+  fn get_mirror_url(&self, specifier: &ModuleSpecifier) -> Option<String> {
+    let rest = specifier.as_str().strip_prefix(&self.origin)?;
+    // the origin must end at a path segment or version boundary so that
+    // `https://deno.land/x/oak` doesn't match `https://deno.land/x/oak_x`
+    if !self.origin.ends_with('/')
+      && !rest.is_empty()
+      && !rest.starts_with('/')
+      && !rest.starts_with('@')
+    {
+      return None;
+    }
+    if !self.template.contains("{version}") {
+      return Some(self.template.replace("{path}", rest));
+    }
+    let (version, path) = rest.strip_prefix('@')?.split_once('/')?;
+    if version.is_empty() {
+      return None;
+    }
+    Some(
+      self
+        .template
+        .replace("{version}", version)
+        .replace("{path}", path),
+    )
+  }
+
+  /// Gets the url at the origin for a url of the mirror, which is only
+  /// possible when the template ends with `{path}` and has no version.
+  fn get_origin_url(&self, mirror_url: &ModuleSpecifier) -> Option<String> {
+    let prefix = self.template.strip_suffix("{path}")?;
+    if prefix.contains('{') {
+      return None;
+    }
+    let rest = mirror_url.as_str().strip_prefix(prefix)?;
+    Some(format!("{}{}", self.origin, rest))
+  }
+}
+
+/// Loader that loads remote modules from mirrors of their origins, which
+/// is useful when a registry is down or rate limits requests.
+///
+/// The responses keep the original url as their specifier, so the module
+/// graph, the output, and a lockfile checked by an `IntegrityCacheLoader`
+/// that wraps this loader all see the canonical urls. When a mirror
+/// redirects, the url it redirected to is mapped back to the origin if
+/// the template allows it and otherwise the requested url is kept.
+pub struct MirrorLoader {
+  inner: Box<dyn Loader>,
+  mirrors: Arc<Vec<UrlMirror>>,
+}
+
+impl MirrorLoader {
+  /// Creates the loader with the mirrors to use, where the first mirror
+  /// that applies to a url is used. Urls no mirror applies to are loaded
+  /// as they are.
+  pub fn new(inner: Box<dyn Loader>, mirrors: Vec<UrlMirror>) -> Self {
+    Self {
+      inner,
+      mirrors: Arc::new(mirrors),
+    }
+  }
+}
+
+impl Loader for MirrorLoader {
+  fn load(
+    &self,
+    specifier: ModuleSpecifier,
+  ) -> Pin<Box<dyn Future<Output = Result<Option<LoadResponse>>> + 'static>> {
+    let found = self
+      .mirrors
+      .iter()
+      .enumerate()
+      .find_map(|(i, mirror)| Some((i, mirror.get_mirror_url(&specifier)?)));
+    let (mirror_index, mirror_url) = match found {
+      Some((mirror_index, mirror_url)) => {
+        match ModuleSpecifier::parse(&mirror_url) {
+          Ok(mirror_url) => (mirror_index, mirror_url),
+          Err(err) => {
+            let err = anyhow!(
+              "Invalid mirror url {} for {}. {}",
+              mirror_url,
+              specifier,
+              err
+            );
+            return Box::pin(future::ready(Err(err)));
+          }
+        }
+      }
+      None => return self.inner.load(specifier),
+    };
+    log_debug!("Loading {} from {}", specifier, mirror_url);
+    let mirrors = self.mirrors.clone();
+    let response_future = self.inner.load(mirror_url.clone());
+    Box::pin(async move {
+      let mut response = match response_future.await? {
+        Some(response) => response,
+        None => return Ok(None),
+      };
+      response.specifier = if response.specifier == mirror_url {
+        specifier
+      } else {
+        mirrors[mirror_index]
+          .get_origin_url(&response.specifier)
+          .and_then(|url| ModuleSpecifier::parse(&url).ok())
+          .unwrap_or(specifier)
+      };
+      Ok(Some(response))
+    })
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashMap;
+
+  use futures::executor::block_on;
+
+  use super::*;
+
+  /// Serves the content by url and redirects the urls in the map.
+  struct TestLoader {
+    files: HashMap<String, String>,
+    redirects: HashMap<String, String>,
+  }
+
+  impl Loader for TestLoader {
+    fn load(
+      &self,
+      specifier: ModuleSpecifier,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<LoadResponse>>> + 'static>>
+    {
+      let final_url = self
+        .redirects
+        .get(specifier.as_str())
+        .cloned()
+        .unwrap_or_else(|| specifier.to_string());
+      let response = self.files.get(&final_url).map(|content| LoadResponse {
+        specifier: ModuleSpecifier::parse(&final_url).unwrap(),
+        headers: None,
+        content: content.clone(),
+      });
+      Box::pin(future::ready(Ok(response)))
+    }
+  }
+
+  fn load(loader: &MirrorLoader, url: &str) -> Option<LoadResponse> {
+    block_on(loader.load(ModuleSpecifier::parse(url).unwrap())).unwrap()
+  }
+
+  #[test]
+  fn loads_from_mirror() {
+    let loader = MirrorLoader::new(
+      Box::new(TestLoader {
+        files: HashMap::from([
+          (
+            "https://raw.githubusercontent.com/oakserver/oak/v12.0.0/mod.ts"
+              .to_string(),
+            "export const oak = 1;".to_string(),
+          ),
+          (
+            "https://mirror.example.com/x/std@0.150.0/mod.ts".to_string(),
+            "export const std = 1;".to_string(),
+          ),
+          (
+            "https://mirror.example.com/x/oak/mod.ts".to_string(),
+            "export const unversioned = 1;".to_string(),
+          ),
+        ]),
+        redirects: HashMap::from([(
+          "https://mirror.example.com/x/std/mod.ts".to_string(),
+          "https://mirror.example.com/x/std@0.150.0/mod.ts".to_string(),
+        )]),
+      }),
+      vec![
+        UrlMirror {
+          origin: "https://deno.land/x/oak".to_string(),
+          template:
+            "https://raw.githubusercontent.com/oakserver/oak/{version}/{path}"
+              .to_string(),
+        },
+        UrlMirror {
+          origin: "https://deno.land/".to_string(),
+          template: "https://mirror.example.com/{path}".to_string(),
+        },
+      ],
+    );
+
+    let response = load(&loader, "https://deno.land/x/oak@v12.0.0/mod.ts");
+    let response = response.unwrap();
+    assert_eq!(
+      response.specifier.as_str(),
+      "https://deno.land/x/oak@v12.0.0/mod.ts"
+    );
+    assert_eq!(response.content, "export const oak = 1;");
+
+    // the redirect of the mirror is mapped back to the origin
+    let response = load(&loader, "https://deno.land/x/std/mod.ts").unwrap();
+    assert_eq!(
+      response.specifier.as_str(),
+      "https://deno.land/x/std@0.150.0/mod.ts"
+    );
+    assert_eq!(response.content, "export const std = 1;");
+
+    // the first mirror needs a version
+    let response = load(&loader, "https://deno.land/x/oak/mod.ts").unwrap();
+    assert_eq!(
+      response.specifier.as_str(),
+      "https://deno.land/x/oak/mod.ts"
+    );
+    assert_eq!(response.content, "export const unversioned = 1;");
+
+    assert!(load(&loader, "https://deno.land/x/other@1.0.0/mod.ts").is_none());
+  }
+
+  #[test]
+  fn mirror_origin_matches_at_boundary() {
+    let mirror = UrlMirror {
+      origin: "https://deno.land/x/oak".to_string(),
+      template: "https://mirror.example.com/oak{path}".to_string(),
+    };
+    let get =
+      |url: &str| mirror.get_mirror_url(&ModuleSpecifier::parse(url).unwrap());
+
+    assert_eq!(
+      get("https://deno.land/x/oak/mod.ts").as_deref(),
+      Some("https://mirror.example.com/oak/mod.ts")
+    );
+    assert_eq!(
+      get("https://deno.land/x/oak@v12.0.0/mod.ts").as_deref(),
+      Some("https://mirror.example.com/oak@v12.0.0/mod.ts")
+    );
+    assert_eq!(get("https://deno.land/x/oak_sessions/mod.ts"), None);
+    assert_eq!(get("https://deno.land/x/oakserver@1.0.0/mod.ts"), None);
+  }
+}
@@ -1,23 +1,36 @@
 // Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
 
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::Mutex;
 
 use anyhow::Result;
+use deno_ast::MediaType;
 use deno_ast::ModuleSpecifier;
 use futures::future;
 use futures::Future;
 
+mod callback_loader;
 #[cfg(feature = "tokio-loader")]
 mod default_loader;
+mod integrity_cache_loader;
+mod mirror_loader;
 mod specifier_mappers;
 
+pub use callback_loader::*;
 #[cfg(feature = "tokio-loader")]
 pub use default_loader::*;
+pub use integrity_cache_loader::*;
+pub use mirror_loader::*;
 pub use specifier_mappers::*;
 
+use crate::parser::DirectiveMappings;
+use crate::remote_policy::OriginPolicy;
+use crate::trace::log_debug;
 use crate::MappedSpecifier;
 use crate::PackageMappedSpecifier;
 
@@ -41,6 +54,14 @@ pub trait Loader {
 pub struct LoaderSpecifiers {
   pub mapped_packages: BTreeMap<ModuleSpecifier, PackageMappedSpecifier>,
   pub mapped_modules: HashMap<ModuleSpecifier, ModuleSpecifier>,
+  /// Files that aren't modules (ex. CSS files), which were loaded as
+  /// JSON modules so they can be imported with an import assertion.
+  pub assets: HashSet<ModuleSpecifier>,
+  /// Local imports of a directory by the index file they were resolved to.
+  pub directory_imports: BTreeMap<ModuleSpecifier, ModuleSpecifier>,
+  /// Remote modules that weren't loaded because the remote policy doesn't
+  /// allow their origin.
+  pub blocked: BTreeSet<ModuleSpecifier>,
 }
 
 pub struct SourceLoader<'a> {
@@ -48,6 +69,16 @@ pub struct SourceLoader<'a> {
   specifiers: LoaderSpecifiers,
   specifier_mappers: Vec<Box<dyn SpecifierMapper>>,
   specifier_mappings: &'a HashMap<ModuleSpecifier, MappedSpecifier>,
+  /// Mappings from the `@dnt-map` directives of the analyzed modules.
+  directive_mappings: Arc<Mutex<DirectiveMappings>>,
+  /// Assets are only loaded when this is `Some`.
+  assets: Option<Arc<Mutex<HashSet<ModuleSpecifier>>>>,
+  /// File names to try in a directory when a local import isn't found.
+  directory_index_files: Arc<Vec<String>>,
+  directory_imports: Arc<Mutex<BTreeMap<ModuleSpecifier, ModuleSpecifier>>>,
+  /// Remote modules are only loaded from the origins this allows.
+  origin_policy: Option<Arc<OriginPolicy>>,
+  blocked: Arc<Mutex<BTreeSet<ModuleSpecifier>>>,
 }
 
 impl<'a> SourceLoader<'a> {
@@ -55,17 +86,44 @@ impl<'a> SourceLoader<'a> {
     loader: Box<dyn Loader>,
     specifier_mappers: Vec<Box<dyn SpecifierMapper>>,
     specifier_mappings: &'a HashMap<ModuleSpecifier, MappedSpecifier>,
+    directive_mappings: Arc<Mutex<DirectiveMappings>>,
+    asset_imports: bool,
+    directory_index_files: &[String],
+    origin_policy: Option<OriginPolicy>,
   ) -> Self {
     Self {
       loader: Arc::new(loader),
       specifiers: Default::default(),
       specifier_mappers,
       specifier_mappings,
+      directive_mappings,
+      assets: if asset_imports {
+        Some(Default::default())
+      } else {
+        None
+      },
+      directory_index_files: Arc::new(directory_index_files.to_vec()),
+      directory_imports: Default::default(),
+      origin_policy: origin_policy.map(Arc::new),
+      blocked: Default::default(),
     }
   }
 
+  /// Gets the wrapped loader, which is used to load files that aren't
+  /// part of the module graph.
+  pub fn inner_loader(&self) -> Arc<Box<dyn Loader>> {
+    self.loader.clone()
+  }
+
   pub fn into_specifiers(self) -> LoaderSpecifiers {
-    self.specifiers
+    let mut specifiers = self.specifiers;
+    if let Some(assets) = self.assets {
+      specifiers.assets = std::mem::take(&mut *assets.lock().unwrap());
+    }
+    specifiers.directory_imports =
+      std::mem::take(&mut *self.directory_imports.lock().unwrap());
+    specifiers.blocked = std::mem::take(&mut *self.blocked.lock().unwrap());
+    specifiers
   }
 }
 
@@ -78,6 +136,11 @@ impl<'a> deno_graph::source::Loader for SourceLoader<'a> {
   ) -> deno_graph::source::LoadFuture {
     let specifier = match self.specifier_mappings.get(specifier) {
       Some(MappedSpecifier::Package(mapping)) => {
+        log_debug!(
+          "Mapped {} to {}",
+          specifier,
+          mapping.module_specifier_text()
+        );
         self
           .specifiers
           .mapped_packages
@@ -86,6 +149,7 @@ impl<'a> deno_graph::source::Loader for SourceLoader<'a> {
         return get_dummy_module(specifier);
       }
       Some(MappedSpecifier::Module(redirect)) => {
+        log_debug!("Mapped {} to {}", specifier, redirect);
         self
           .specifiers
           .mapped_modules
@@ -93,8 +157,33 @@ impl<'a> deno_graph::source::Loader for SourceLoader<'a> {
         redirect
       }
       None => {
+        let directive_mapping = self
+          .directive_mappings
+          .lock()
+          .unwrap()
+          .packages
+          .get(specifier)
+          .cloned();
+        if let Some(mapping) = directive_mapping {
+          log_debug!(
+            "Mapped {} to {} by a comment directive",
+            specifier,
+            mapping.module_specifier_text()
+          );
+          self
+            .specifiers
+            .mapped_packages
+            .insert(specifier.clone(), mapping);
+          // provide a dummy file so that this module can be analyzed later
+          return get_dummy_module(specifier);
+        }
         for mapper in self.specifier_mappers.iter() {
           if let Some(entry) = mapper.map(specifier) {
+            log_debug!(
+              "Mapped {} to {}",
+              specifier,
+              entry.module_specifier_text()
+            );
             self
               .specifiers
               .mapped_packages
@@ -107,34 +196,133 @@ impl<'a> deno_graph::source::Loader for SourceLoader<'a> {
       }
     };
 
+    if let Some(origin_policy) = &self.origin_policy {
+      if !origin_policy.allows(specifier) {
+        log_debug!("Blocked {} by the remote policy", specifier);
+        self.blocked.lock().unwrap().insert(specifier.clone());
+        // provide a dummy file so the graph can still be built and the
+        // import chain found
+        return get_dummy_module(specifier);
+      }
+    }
+
     let loader = self.loader.clone();
+    let assets = self.assets.clone();
+    let directory_index_files = self.directory_index_files.clone();
+    let directory_imports = self.directory_imports.clone();
+    let origin_policy = self.origin_policy.clone();
+    let blocked = self.blocked.clone();
     let specifier = specifier.to_owned();
     Box::pin(async move {
-      let resp = loader.load(specifier.clone()).await;
+      let mut resp = loader.load(specifier.clone()).await;
+      if !matches!(resp, Ok(Some(_))) {
+        // a directory fails to load or isn't found
+        for index_specifier in
+          get_directory_index_specifiers(&specifier, &directory_index_files)
+        {
+          if let Ok(Some(index_resp)) =
+            loader.load(index_specifier.clone()).await
+          {
+            log_debug!(
+              "Resolved the directory {} to {}",
+              specifier,
+              index_resp.specifier
+            );
+            directory_imports
+              .lock()
+              .unwrap()
+              .insert(specifier.clone(), index_resp.specifier.clone());
+            resp = Ok(Some(index_resp));
+            break;
+          }
+        }
+      }
       resp.map(|r| {
-        r.map(|r| deno_graph::source::LoadResponse::Module {
-          specifier: r.specifier,
-          content: r.content.into(),
-          maybe_headers: r.headers,
+        r.map(|mut r| {
+          if r.specifier == specifier {
+            log_debug!("Loaded {}", specifier);
+          } else {
+            log_debug!("Loaded {} (redirected to {})", specifier, r.specifier);
+            if let Some(origin_policy) = &origin_policy {
+              // don't use what a redirect to another origin served
+              if !origin_policy.allows(&r.specifier) {
+                log_debug!("Blocked {} by the remote policy", r.specifier);
+                blocked.lock().unwrap().insert(r.specifier.clone());
+                r.content = String::new();
+                r.headers = Some(get_dummy_headers());
+              }
+            }
+          }
+          if let Some(assets) = &assets {
+            let media_type = MediaType::from_specifier_and_headers(
+              &r.specifier,
+              r.headers.as_ref(),
+            );
+            if media_type == MediaType::Unknown {
+              // present the file as a JSON module so the graph accepts it
+              // when it's imported with an import assertion
+              assets.lock().unwrap().insert(r.specifier.clone());
+              r.headers = Some(HashMap::from([(
+                "content-type".to_string(),
+                "application/json".to_string(),
+              )]));
+            }
+          }
+          deno_graph::source::LoadResponse::Module {
+            specifier: r.specifier,
+            content: r.content.into(),
+            maybe_headers: r.headers,
+          }
         })
       })
     })
   }
 }
 
+/// Gets the index files to try for a local specifier that may be a
+/// directory, which is one that ends with a slash or whose file name
+/// doesn't have an extension.
+fn get_directory_index_specifiers(
+  specifier: &ModuleSpecifier,
+  directory_index_files: &[String],
+) -> Vec<ModuleSpecifier> {
+  if specifier.scheme() != "file" || directory_index_files.is_empty() {
+    return Vec::new();
+  }
+  let path = specifier.path();
+  let file_name = path.rsplit('/').next().unwrap();
+  let directory = if file_name.is_empty() {
+    specifier.clone()
+  } else if !file_name.contains('.') {
+    let mut directory = specifier.clone();
+    directory.set_path(&format!("{}/", path));
+    directory
+  } else {
+    return Vec::new();
+  };
+  directory_index_files
+    .iter()
+    .filter_map(|file_name| directory.join(file_name).ok())
+    .collect()
+}
+
 fn get_dummy_module(
   specifier: &ModuleSpecifier,
 ) -> deno_graph::source::LoadFuture {
-  let mut headers = HashMap::new();
-  headers.insert(
-    "content-type".to_string(),
-    "application/javascript".to_string(),
-  );
   Box::pin(future::ready(Ok(Some(
     deno_graph::source::LoadResponse::Module {
       specifier: specifier.clone(),
       content: "".into(),
-      maybe_headers: Some(headers),
+      maybe_headers: Some(get_dummy_headers()),
     },
   ))))
 }
+
+fn get_dummy_headers() -> HashMap<String, String> {
+  let mut headers = HashMap::new();
+  headers.insert(
+    "content-type".to_string(),
+    "application/javascript".to_string(),
+  );
+  headers
+}
@@ -10,8 +10,12 @@ pub trait SpecifierMapper {
   fn map(&self, specifier: &ModuleSpecifier) -> Option<PackageMappedSpecifier>;
 }
 
-pub fn get_all_specifier_mappers() -> Vec<Box<dyn SpecifierMapper>> {
-  vec![
+/// Gets the mappers of remote specifiers to packages, which includes the
+/// mapper of files on GitHub when `github_dependencies` is enabled.
+pub fn get_all_specifier_mappers(
+  github_dependencies: bool,
+) -> Vec<Box<dyn SpecifierMapper>> {
+  let mut mappers: Vec<Box<dyn SpecifierMapper>> = vec![
     Box::new(NodeSpecifierMapper::new("assert")),
     Box::new(NodeSpecifierMapper::new("buffer")),
     Box::new(NodeSpecifierMapper::new("child_process")),
@@ -34,7 +38,11 @@ pub fn get_all_specifier_mappers() -> Vec<Box<dyn SpecifierMapper>> {
     Box::new(NodeSpecifierMapper::new("util")),
     Box::new(SkypackMapper),
     Box::new(EsmShMapper),
-  ]
+  ];
+  if github_dependencies {
+    mappers.push(Box::new(GitHubMapper));
+  }
+  mappers
 }
 
 // good enough for a first pass
@@ -48,6 +56,12 @@ static ESMSH_MAPPING_RE: Lazy<Regex> = Lazy::new(|| {
   Regex::new(r"^https://esm\.sh/(@?[^@?]+)@([0-9.\^~\-A-Za-z]+)(?:/([^#?]+))?$")
     .unwrap()
 });
+static GITHUB_RAW_MAPPING_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(
+    r"^https://raw\.githubusercontent\.com/([^/?#]+)/([^/?#]+)/([^/?#]+)/([^?#]+)$",
+  )
+  .unwrap()
+});
 static ESMSH_IGNORE_MAPPING_RE: Lazy<Regex> = Lazy::new(|| {
   // internal urls
   Regex::new(r"^https://esm\.sh/v[0-9]+/.*/.*/").unwrap()
@@ -86,6 +100,7 @@ impl SpecifierMapper for SkypackMapper {
       version: Some(version),
       sub_path,
       peer_dependency: false,
+      types_package: None,
     })
   }
 }
@@ -116,6 +131,34 @@ impl SpecifierMapper for EsmShMapper {
       version: Some(captures.get(2).unwrap().as_str().to_string()),
       sub_path,
       peer_dependency: false,
+      types_package: None,
+    })
+  }
+}
+
+/// Maps files on GitHub to a git dependency on their repository
+/// (ex. `github:owner/repo#tag`).
+pub struct GitHubMapper;
+
+impl SpecifierMapper for GitHubMapper {
+  fn map(&self, specifier: &ModuleSpecifier) -> Option<PackageMappedSpecifier> {
+    let captures = GITHUB_RAW_MAPPING_RE.captures(specifier.as_str())?;
+    let owner = captures.get(1).unwrap().as_str();
+    let repo = captures.get(2).unwrap().as_str();
+    let tag = captures.get(3).unwrap().as_str();
+    let sub_path = captures.get(4).unwrap().as_str();
+
+    // don't use the package for declaration file imports
+    if sub_path.to_lowercase().ends_with(".d.ts") {
+      return None;
+    }
+
+    Some(PackageMappedSpecifier {
+      name: repo.to_lowercase(),
+      version: Some(format!("github:{}/{}#{}", owner, repo, tag)),
+      sub_path: Some(sub_path.to_string()),
+      peer_dependency: false,
+      types_package: None,
     })
   }
 }
@@ -146,6 +189,7 @@ impl SpecifierMapper for NodeSpecifierMapper {
         version: None,
         sub_path: None,
         peer_dependency: false,
+        types_package: None,
       })
     } else {
       None
@@ -177,6 +221,7 @@ mod test {
         version: Some("5.6.2".to_string()),
         peer_dependency: false,
         sub_path: None,
+        types_package: None,
       }),
     );
     assert_eq!(
@@ -185,6 +230,44 @@ mod test {
     );
   }
 
+  #[test]
+  fn test_github_mapper() {
+    let mapper = GitHubMapper;
+    assert_eq!(
+      mapper.map(
+        &ModuleSpecifier::parse(
+          "https://raw.githubusercontent.com/owner/Repo/v1.0.0/src/mod.ts"
+        )
+        .unwrap()
+      ),
+      Some(PackageMappedSpecifier {
+        name: "repo".to_string(),
+        version: Some("github:owner/Repo#v1.0.0".to_string()),
+        peer_dependency: false,
+        sub_path: Some("src/mod.ts".to_string()),
+        types_package: None,
+      }),
+    );
+    assert_eq!(
+      mapper.map(
+        &ModuleSpecifier::parse(
+          "https://raw.githubusercontent.com/owner/repo/v1.0.0/mod.d.ts"
+        )
+        .unwrap()
+      ),
+      None,
+    );
+    assert_eq!(
+      mapper.map(
+        &ModuleSpecifier::parse(
+          "https://raw.githubusercontent.com/owner/mod.ts"
+        )
+        .unwrap()
+      ),
+      None,
+    );
+  }
+
   #[test]
   fn test_esm_sh_mapper() {
     let mapper = EsmShMapper;
@@ -202,6 +285,7 @@ mod test {
         version: Some("5.6.2".to_string()),
         peer_dependency: false,
         sub_path: None,
+        types_package: None,
       }),
     );
     assert_eq!(
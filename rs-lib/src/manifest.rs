@@ -0,0 +1,196 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+
+use deno_ast::ModuleSpecifier;
+
+use crate::cache::CacheKeyHasher;
+use crate::mappings::Mappings;
+use crate::specifiers::Specifiers;
+use crate::virtual_path::VirtualPath;
+use crate::Dependency;
+use crate::TransformOptions;
+use crate::TransformOutput;
+
+/// Machine readable summary of a transform, which is useful for diffing
+/// between releases to catch changes such as new remote dependencies.
+///
+/// The collections are in a stable order and paths are `/` separated on
+/// every platform so the manifest only changes when the inputs change.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct TransformManifest {
+  pub entry_points: Vec<String>,
+  pub test_entry_points: Vec<String>,
+  /// Hash of the options that affect the output, which also changes
+  /// between versions of this crate.
+  pub options_hash: String,
+  /// Output file path of each module by its specifier.
+  pub mappings: BTreeMap<String, String>,
+  /// Remote modules that were output.
+  pub remote_modules: Vec<String>,
+  pub dependencies: Vec<Dependency>,
+  pub test_dependencies: Vec<Dependency>,
+  /// If the main code imports the shims.
+  pub used_shims: bool,
+  /// If the test code imports the test shims.
+  pub used_test_shims: bool,
+  pub warnings: Vec<String>,
+  pub parse_errors: Vec<String>,
+}
+
+impl TransformManifest {
+  /// Gets the manifest as pretty printed JSON.
+  #[cfg(feature = "serialization")]
+  pub fn to_json(&self) -> String {
+    serde_json::to_string_pretty(self).unwrap()
+  }
+}
+
+/// Collects what's needed for the manifest before the module graph and
+/// specifiers are released.
+pub(crate) struct ManifestBuilder {
+  entry_points: Vec<String>,
+  test_entry_points: Vec<String>,
+  options_hash: String,
+  mappings: BTreeMap<String, String>,
+  remote_modules: Vec<String>,
+}
+
+impl ManifestBuilder {
+  pub fn new(
+    options: &TransformOptions,
+    specifiers: &Specifiers,
+    mappings: &Mappings,
+  ) -> Self {
+    let output_specifiers = specifiers
+      .local
+      .iter()
+      .chain(specifiers.remote.iter())
+      .chain(specifiers.types.values().map(|d| &d.selected.specifier));
+    ManifestBuilder {
+      entry_points: options
+        .entry_points
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+      test_entry_points: options
+        .test_entry_points
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+      options_hash: get_options_hash(options),
+      mappings: output_specifiers
+        .map(|specifier| {
+          (
+            specifier.to_string(),
+            VirtualPath::new(mappings.get_file_path(specifier)).to_string(),
+          )
+        })
+        .collect(),
+      remote_modules: specifiers.remote.iter().map(|s| s.to_string()).collect(),
+    }
+  }
+
+  /// Removes modules that aren't output, such as inlined modules.
+  pub fn remove_modules(&mut self, specifiers: &HashSet<ModuleSpecifier>) {
+    self.mappings.retain(|specifier, _| {
+      !specifiers.iter().any(|s| s.as_str() == specifier)
+    });
+    self
+      .remote_modules
+      .retain(|specifier| !specifiers.iter().any(|s| s.as_str() == specifier));
+  }
+
+  pub fn build(
+    self,
+    output: &TransformOutput,
+    used_shims: bool,
+    used_test_shims: bool,
+  ) -> TransformManifest {
+    let mut remote_modules = self.remote_modules;
+    remote_modules.sort();
+    TransformManifest {
+      entry_points: self.entry_points,
+      test_entry_points: self.test_entry_points,
+      options_hash: self.options_hash,
+      mappings: self.mappings,
+      remote_modules,
+      dependencies: output.main.dependencies.clone(),
+      test_dependencies: output.test.dependencies.clone(),
+      used_shims,
+      used_test_shims,
+      warnings: output.warnings.clone(),
+      parse_errors: output
+        .parse_errors
+        .iter()
+        .map(|e| e.message.clone())
+        .collect(),
+    }
+  }
+}
+
+fn get_options_hash(options: &TransformOptions) -> String {
+  let mut hasher = CacheKeyHasher::default();
+  hasher
+    .write(&options.shims)
+    .write(&options.test_shims)
+    .write(
+      options
+        .specifier_mappings
+        .iter()
+        .collect::<BTreeMap<_, _>>(),
+    )
+    .write(
+      options
+        .declaration_mappings
+        .iter()
+        .collect::<BTreeMap<_, _>>(),
+    )
+    .write(options.target as u8)
+    .write(&options.import_map)
+    .write(&options.jsx)
+    .write(options.error_on_cycles)
+    .write(options.error_on_duplicate_packages)
+    .write(options.continue_on_parse_error)
+    .write(options.normalize_output_text)
+    .write(options.github_dependencies)
+    .write(&options.remote_policy)
+    .write(&options.version_policy)
+    .write(options.asset_imports)
+    .write(options.asset_handlers.keys().collect::<BTreeSet<_>>())
+    .write(&options.directory_index_files)
+    .write(&options.local_roots)
+    .write(&options.node_global_shims)
+    .write(&options.dom_types_package)
+    .write(options.inject_dirname)
+    .write(&options.commonjs_modules)
+    .write(&options.server_shim)
+    .write(&options.comment_directives)
+    .write(&options.import_attributes)
+    .write(options.inline_remote_module_max_size)
+    .write(options.bundle)
+    .write(options.flatten_barrels)
+    .write(options.minify)
+    .write(options.entry_point_exports)
+    .write(options.declarations_only)
+    .write(options.third_party_licenses)
+    .write(options.provenance)
+    .write(options.define.iter().collect::<BTreeMap<_, _>>())
+    .write(options.deno_env_to_process_env)
+    .write(options.translate_deno_apis)
+    .write(&options.shim_import_style)
+    .write(&options.deno_feature_detection)
+    .write(&options.qualified_global_access)
+    .write(&options.relative_specifier_extension)
+    .write(&options.test_relative_specifier_extension)
+    .write(&options.test_main_imports)
+    .write(options.annotate_rewrites)
+    .write(options.hash_remote_file_names)
+    .write(options.typescript_paths)
+    .write(&options.size_budget);
+  hasher.finish()
+}
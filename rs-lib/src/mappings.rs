@@ -1,6 +1,8 @@
 // Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
 
 use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::path::Component;
@@ -12,13 +14,19 @@ use anyhow::Result;
 use deno_ast::MediaType;
 use deno_ast::ModuleSpecifier;
 use once_cell::sync::Lazy;
+use sha2::Digest;
+use sha2::Sha256;
 
 use crate::graph::ModuleGraph;
 use crate::specifiers::Specifiers;
+use crate::utils::file_path_to_url;
 use crate::utils::get_unique_path;
 use crate::utils::partition_by_root_specifiers;
+use crate::utils::percent_decode;
 use crate::utils::url_to_file_path;
 use crate::utils::with_extension;
+use crate::virtual_path::VirtualPath;
+use crate::LocalRoot;
 
 pub struct SyntheticSpecifiers {
   pub polyfills: ModuleSpecifier,
@@ -38,33 +46,94 @@ pub static SYNTHETIC_TEST_SPECIFIERS: Lazy<SyntheticSpecifiers> =
 
 pub struct Mappings {
   inner: HashMap<ModuleSpecifier, PathBuf>,
+  local_roots: Vec<LocalRoot>,
 }
 
 impl Mappings {
+  /// Assigns the output paths of all the modules up front in the order of
+  /// their sorted specifiers, so the path a module gets when its name
+  /// collides with another (ex. `mod_2.ts`) doesn't depend on the order
+  /// the modules were loaded or transformed in.
+  ///
+  /// Local files in one of the local roots are output to the root's output
+  /// directory and the others are output relative to the directory they
+  /// have in common. Local copies of remote modules are output with the
+  /// remote modules instead, so they don't change the common directory.
+  ///
+  /// When hashing the remote file names, the names of the remote modules
+  /// have a short hash of their source before the extension (ex.
+  /// `mod.1a2b3c4d.ts`), so a file's name changes when its content does.
   pub fn new(
     module_graph: &ModuleGraph,
     specifiers: &Specifiers,
+    local_roots: &[LocalRoot],
+    hash_remote_file_names: bool,
   ) -> Result<Self> {
     let mut mappings = HashMap::new();
     let mut mapped_filepaths_no_ext = HashSet::new();
-    let base_dir = get_base_dir(&specifiers.local)?;
     let mut root_local_dirs = HashSet::new();
+    let mut local_roots = local_roots
+      .iter()
+      .map(|root| LocalRoot {
+        dir: with_trailing_slash(&root.dir),
+        output_dir: root.output_dir.clone(),
+      })
+      .collect::<Vec<_>>();
+    // prefer the most specific root when the roots are nested
+    let mut root_dirs = local_roots
+      .iter()
+      .map(|root| Ok((url_to_file_path(&root.dir)?, &root.output_dir)))
+      .collect::<Result<Vec<_>>>()?;
+    root_dirs.sort_by_key(|(dir, _)| Reverse(dir.components().count()));
+    let mut local_specifiers = specifiers.local.iter().collect::<Vec<_>>();
+    local_specifiers.sort();
 
-    for specifier in specifiers.local.iter() {
+    let mut relative_file_paths = Vec::new();
+    let mut other_specifiers = Vec::new();
+    for specifier in local_specifiers {
+      if specifiers.local_copies.contains_key(specifier) {
+        continue;
+      }
       let file_path = url_to_file_path(specifier)?;
-      let relative_file_path =
-        file_path.strip_prefix(&base_dir).map_err(|_| {
-          anyhow::anyhow!(
-            "Error stripping prefix of {} with base {}",
-            file_path.display(),
-            base_dir.display()
-          )
-        })?;
+      let root_file_path = root_dirs.iter().find_map(|(dir, output_dir)| {
+        Some(output_dir.join(file_path.strip_prefix(dir).ok()?))
+      });
+      match root_file_path {
+        Some(root_file_path) => {
+          relative_file_paths.push((specifier, root_file_path))
+        }
+        None => other_specifiers.push(specifier.clone()),
+      }
+    }
+    if !other_specifiers.is_empty() {
+      let base_dir = get_base_dir(&other_specifiers)?;
+      for specifier in other_specifiers.iter() {
+        let file_path = url_to_file_path(specifier)?;
+        let relative_file_path =
+          file_path.strip_prefix(&base_dir).map_err(|_| {
+            anyhow::anyhow!(
+              "Error stripping prefix of {} with base {}",
+              file_path.display(),
+              base_dir.display()
+            )
+          })?;
+        relative_file_paths.push((specifier, relative_file_path.to_owned()));
+      }
+      local_roots.push(LocalRoot {
+        dir: with_trailing_slash(&file_path_to_url(&base_dir)),
+        output_dir: PathBuf::new(),
+      });
+    }
+    local_roots.sort_by(|a, b| a.dir.cmp(&b.dir));
+    relative_file_paths.sort();
+
+    for (specifier, relative_file_path) in relative_file_paths {
       mappings.insert(
         specifier.clone(),
         get_mapped_file_path(
-          relative_file_path.into(),
+          module_graph.get(specifier).media_type,
           &relative_file_path,
+          None,
           &mut mapped_filepaths_no_ext,
         ),
       );
@@ -77,15 +146,33 @@ impl Mappings {
 
     let deps_path =
       get_unique_path(PathBuf::from("deps"), &mut root_local_dirs);
-    for (specifier, suggested_path) in
-      remote_specifiers_to_paths(specifiers.remote.iter())
-    {
-      let media_type = module_graph.get(&specifier).media_type;
+    let remote_copies = specifiers
+      .local_copies
+      .iter()
+      .map(|(local, remote)| (remote, local))
+      .collect::<HashMap<_, _>>();
+    for (specifier, suggested_path) in remote_specifiers_to_paths(
+      specifiers
+        .remote
+        .iter()
+        .chain(remote_copies.keys().copied()),
+    ) {
+      let specifier = match remote_copies.get(&specifier) {
+        Some(local) => (*local).clone(),
+        None => specifier,
+      };
+      let module = module_graph.get(&specifier);
+      let content_hash = module
+        .maybe_source
+        .as_ref()
+        .filter(|_| hash_remote_file_names)
+        .map(|source| get_content_hash(source));
       mappings.insert(
         specifier,
         get_mapped_file_path(
-          media_type,
+          module.media_type,
           &deps_path.join(suggested_path),
+          content_hash.as_deref(),
           &mut mapped_filepaths_no_ext,
         ),
       );
@@ -133,6 +220,7 @@ impl Mappings {
         get_mapped_file_path(
           MediaType::TypeScript,
           &specifier.to_string()["dnt://".len()..],
+          None,
           mapped_filepaths_no_ext,
         ),
       );
@@ -159,14 +247,36 @@ impl Mappings {
       &SYNTHETIC_TEST_SPECIFIERS.shims,
     );
 
-    Ok(Mappings { inner: mappings })
+    // use `/` separated paths so the output is the same on every platform
+    for file_path in mappings.values_mut() {
+      *file_path = VirtualPath::new(&file_path).to_path_buf();
+    }
+
+    Ok(Mappings {
+      inner: mappings,
+      local_roots,
+    })
   }
 
   pub fn get_file_path(&self, specifier: &ModuleSpecifier) -> &PathBuf {
-    self.inner.get(specifier).unwrap_or_else(|| {
+    self.maybe_file_path(specifier).unwrap_or_else(|| {
       panic!("Could not find file path for specifier: {}", specifier)
     })
   }
+
+  /// Directories of the local files and where they're output to, which
+  /// includes the directory the local files that aren't in a configured
+  /// root have in common. Sorted by directory.
+  pub fn local_roots(&self) -> &[LocalRoot] {
+    &self.local_roots
+  }
+
+  pub fn maybe_file_path(
+    &self,
+    specifier: &ModuleSpecifier,
+  ) -> Option<&PathBuf> {
+    self.inner.get(specifier)
+  }
 }
 
 /// Takes a group of remote specifiers for the provided base directory
@@ -288,15 +398,21 @@ fn remote_specifiers_to_paths_with_truncation<'a>(
 
   // for this code, we assume byte length is equivalent to character length
   // because this is an approximation and we want it to be faster
-  let mut dirs_exceeding_length: HashMap<String, Rc<RefCell<Directory>>> =
-    HashMap::default();
+  let mut dirs_exceeding_length: BTreeMap<String, Rc<RefCell<Directory>>> =
+    BTreeMap::default();
   let root_dir = Directory::new_root();
-  let root_remote_specifiers = partition_by_root_specifiers(specifiers);
+  // the unique names are given out in order, so sort for stable paths
+  let mut specifiers = specifiers.collect::<Vec<_>>();
+  specifiers.sort();
+  let root_remote_specifiers =
+    partition_by_root_specifiers(specifiers.into_iter());
   for (root, specifiers) in root_remote_specifiers {
     let base_dir_original_name = dir_name_for_root(&root);
     for specifier in specifiers {
-      let file_path =
-        base_dir_original_name.join(sanitize_filepath(&specifier.path()[1..]));
+      let file_path = match github_file_path(&specifier) {
+        Some(file_path) => file_path,
+        None => base_dir_original_name.join(url_path_to_file_path(&specifier)),
+      };
       let dir_path = file_path.parent().unwrap().to_owned();
 
       let dir = Directory::get_or_create_dir(&root_dir, &dir_path);
@@ -355,7 +471,13 @@ fn remote_specifiers_to_paths_with_truncation<'a>(
       }
       let mut dir = dir.borrow_mut();
       let original_name = dir.name.clone().to_string_lossy().to_string();
-      let new_name = &original_name[..std::cmp::min(original_name.len(), 5)];
+      // keep the version in the directory name of a package so the copies of
+      // different versions of a package stay distinguishable
+      let new_name = if is_package_version_dir_name(&dir.original_name) {
+        &original_name
+      } else {
+        &original_name[..std::cmp::min(original_name.len(), 5)]
+      };
       if new_name != original_name {
         if let Some(parent) = dir.parent.as_ref().cloned() {
           let mut parent = parent.borrow_mut();
@@ -385,6 +507,15 @@ fn remote_specifiers_to_paths_with_truncation<'a>(
   result
 }
 
+/// Gets if the directory name is of a package version (ex. `oak@v10.0.0`
+/// or `@scope/name@1.0.0`'s `name@1.0.0`).
+fn is_package_version_dir_name(name: &str) -> bool {
+  match name.rfind('@') {
+    Some(index) => index > 0 && index < name.len() - 1,
+    None => false,
+  }
+}
+
 fn split_stem_and_ext(path: &str) -> Option<(&str, &str)> {
   let d_ts_ext = ".d.ts";
   if path.to_lowercase().ends_with(d_ts_ext) {
@@ -402,6 +533,7 @@ fn split_stem_and_ext(path: &str) -> Option<(&str, &str)> {
 fn get_mapped_file_path(
   media_type: MediaType,
   path: impl AsRef<Path>,
+  content_hash: Option<&str>,
   mapped_filepaths_no_ext: &mut HashSet<String>,
 ) -> PathBuf {
   fn without_ext(path: impl AsRef<Path>) -> PathBuf {
@@ -416,10 +548,19 @@ fn get_mapped_file_path(
     }
   }
 
+  let mut filepath_no_ext = without_ext(path);
+  if let Some(content_hash) = content_hash {
+    filepath_no_ext = PathBuf::from(format!(
+      "{}.{}",
+      filepath_no_ext.to_string_lossy(),
+      content_hash
+    ));
+  }
   let filepath_no_ext =
-    get_unique_path(without_ext(path), mapped_filepaths_no_ext);
+    get_unique_path(filepath_no_ext, mapped_filepaths_no_ext);
   let extension = match media_type {
-    MediaType::Json => "js",
+    // CommonJS modules are wrapped to be ES modules
+    MediaType::Json | MediaType::Cjs => "js",
     _ => &media_type.as_ts_extension()[1..],
   };
   with_extension(
@@ -432,6 +573,16 @@ fn get_mapped_file_path(
   )
 }
 
+/// Gets the first eight hex characters of the SHA-256 hash of the source,
+/// which is stable across platforms and versions.
+fn get_content_hash(source: &str) -> String {
+  Sha256::digest(source.as_bytes())
+    .iter()
+    .take(4)
+    .map(|byte| format!("{:02x}", byte))
+    .collect()
+}
+
 /// Gets the directory name to use for the provided root.
 fn dir_name_for_root(root: &ModuleSpecifier) -> PathBuf {
   let mut result = String::new();
@@ -447,20 +598,67 @@ fn dir_name_for_root(root: &ModuleSpecifier) -> PathBuf {
   let mut result = PathBuf::from(result);
   if let Some(segments) = root.path_segments() {
     for segment in segments.filter(|s| !s.is_empty()) {
-      result = result.join(sanitize_segment(segment));
+      result = result.join(sanitize_segment(&percent_decode(segment)));
     }
   }
 
   result
 }
 
-fn sanitize_filepath(text: &str) -> String {
-  text
-    .chars()
-    .map(|c| if is_banned_path_char(c) { '_' } else { c })
+/// Gets the relative file path of the decoded path segments of a url, so
+/// that a segment like `caf%C3%A9` is output as `café`. Decoded slashes
+/// don't create directories (ex. `a%2Fb.ts` is output as `a_b.ts`).
+fn url_path_to_file_path(specifier: &ModuleSpecifier) -> PathBuf {
+  specifier
+    .path_segments()
+    .into_iter()
+    .flatten()
+    .filter(|s| !s.is_empty())
+    .map(|segment| sanitize_segment(&percent_decode(segment)))
     .collect()
 }
 
+/// Gets a path for a file on GitHub that's based on the repository and
+/// the tag or commit instead of the host, such as
+/// `github.com/owner/repo@tag/mod.ts` or
+/// `gist.github.com/owner/id@revision/mod.ts`.
+fn github_file_path(specifier: &ModuleSpecifier) -> Option<PathBuf> {
+  let segments = specifier.path_segments()?.collect::<Vec<_>>();
+  let (dir_name, owner, repo, tag, file_segments) = match specifier.domain()? {
+    // /<owner>/<repo>/<tag>/<path>
+    "raw.githubusercontent.com" if segments.len() > 3 => (
+      "github.com",
+      segments[0],
+      segments[1],
+      segments[2],
+      &segments[3..],
+    ),
+    // /<owner>/<id>/raw/<revision>/<path>
+    "gist.githubusercontent.com"
+      if segments.len() > 4 && segments[2] == "raw" =>
+    {
+      (
+        "gist.github.com",
+        segments[0],
+        segments[1],
+        segments[3],
+        &segments[4..],
+      )
+    }
+    _ => return None,
+  };
+  let mut path = PathBuf::from(dir_name)
+    .join(sanitize_segment(&percent_decode(owner)))
+    .join(sanitize_segment(&percent_decode(&format!(
+      "{}@{}",
+      repo, tag
+    ))));
+  for segment in file_segments.iter().filter(|s| !s.is_empty()) {
+    path = path.join(sanitize_segment(&percent_decode(segment)));
+  }
+  Some(path)
+}
+
 fn is_banned_path_char(c: char) -> bool {
   matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*')
 }
@@ -473,7 +671,16 @@ fn sanitize_segment(text: &str) -> String {
 }
 
 fn is_banned_segment_char(c: char) -> bool {
-  matches!(c, '/' | '\\') || is_banned_path_char(c)
+  // `%` and `#` would need to be encoded in the relative specifiers
+  matches!(c, '/' | '\\' | '%' | '#') || is_banned_path_char(c)
+}
+
+fn with_trailing_slash(dir: &ModuleSpecifier) -> ModuleSpecifier {
+  let mut dir = dir.clone();
+  if !dir.path().ends_with('/') {
+    dir.set_path(&format!("{}/", dir.path()));
+  }
+  dir
 }
 
 fn get_base_dir(specifiers: &[ModuleSpecifier]) -> Result<PathBuf> {
@@ -515,7 +722,8 @@ mod test {
   fn should_get_dir_name_root() {
     run_test("http://deno.land/x/test", "deno.land/x/test");
     run_test("http://localhost", "localhost");
-    run_test("http://localhost/test%20:test", "localhost/test%20_test");
+    run_test("http://localhost/test%20:test", "localhost/test _test");
+    run_test("http://localhost/a%2Fb%25", "localhost/a_b_");
 
     fn run_test(specifier: &str, expected: &str) {
       assert_eq!(
@@ -562,13 +770,13 @@ mod test {
       &[
         (
           "http://localhost/FOLDER/file.json",
-          "localhost/FOLDER_2/file.json",
+          "localhost/FOLDER/file.json",
         ),
         ("http://localhost/file.json", "localhost/file.json"),
         ("http://localhost/file.ts", "localhost/file.ts"),
         (
           "http://localhost/folder/file.json",
-          "localhost/folder/file.json",
+          "localhost/folder_2/file.json",
         ),
         ("http://localhost/other.ts", "localhost/other.ts"),
         ("http://localhost/other.ts?query", "localhost/other_2.ts"),
@@ -590,6 +798,84 @@ mod test {
     )
   }
 
+  #[test]
+  fn test_remote_specifiers_to_paths_github() {
+    run_remote_specifiers_to_paths_test(
+      &[
+        "https://raw.githubusercontent.com/owner/repo/v1.0.0/mod.ts",
+        "https://raw.githubusercontent.com/owner/repo/v1.0.0/src/a.ts",
+        "https://raw.githubusercontent.com/owner/repo/main/mod.ts",
+        "https://raw.githubusercontent.com/owner/mod.ts",
+        "https://gist.githubusercontent.com/owner/1a2b/raw/3c4d/mod.ts",
+        "https://gist.githubusercontent.com/owner/1a2b/raw/mod.ts",
+      ],
+      &[
+        (
+          "https://gist.githubusercontent.com/owner/1a2b/raw/3c4d/mod.ts",
+          "gist.github.com/owner/1a2b@3c4d/mod.ts",
+        ),
+        (
+          "https://gist.githubusercontent.com/owner/1a2b/raw/mod.ts",
+          "gist.githubusercontent.com/owner/1a2b/raw/mod.ts",
+        ),
+        (
+          "https://raw.githubusercontent.com/owner/mod.ts",
+          "raw.githubusercontent.com/owner/mod.ts",
+        ),
+        (
+          "https://raw.githubusercontent.com/owner/repo/main/mod.ts",
+          "github.com/owner/repo@main/mod.ts",
+        ),
+        (
+          "https://raw.githubusercontent.com/owner/repo/v1.0.0/mod.ts",
+          "github.com/owner/repo@v1.0.0/mod.ts",
+        ),
+        (
+          "https://raw.githubusercontent.com/owner/repo/v1.0.0/src/a.ts",
+          "github.com/owner/repo@v1.0.0/src/a.ts",
+        ),
+      ],
+      260,
+    )
+  }
+
+  #[test]
+  fn test_remote_specifiers_to_paths_encoded() {
+    run_remote_specifiers_to_paths_test(
+      &[
+        "https://deno.land/x/my%20mod/file%20name.ts",
+        "https://deno.land/x/a%2Fb.ts",
+        "https://deno.land/x/a%23b.ts",
+        "https://deno.land/x/100%25.ts",
+        "https://deno.land/x/%E6%97%A5%E6%9C%AC/mod.ts",
+        "https://deno.land/x/pkg@1.0.0+build/mod.ts",
+        "https://raw.githubusercontent.com/owner/repo/v1%20beta/a%20b.ts",
+      ],
+      &[
+        ("https://deno.land/x/100%25.ts", "deno.land/x/100_.ts"),
+        (
+          "https://deno.land/x/%E6%97%A5%E6%9C%AC/mod.ts",
+          "deno.land/x/\u{65e5}\u{672c}/mod.ts",
+        ),
+        ("https://deno.land/x/a%23b.ts", "deno.land/x/a_b.ts"),
+        ("https://deno.land/x/a%2Fb.ts", "deno.land/x/a_b_2.ts"),
+        (
+          "https://deno.land/x/my%20mod/file%20name.ts",
+          "deno.land/x/my mod/file name.ts",
+        ),
+        (
+          "https://deno.land/x/pkg@1.0.0+build/mod.ts",
+          "deno.land/x/pkg@1.0.0+build/mod.ts",
+        ),
+        (
+          "https://raw.githubusercontent.com/owner/repo/v1%20beta/a%20b.ts",
+          "github.com/owner/repo@v1 beta/a b.ts",
+        ),
+      ],
+      260,
+    )
+  }
+
   #[test]
   fn test_remote_specifiers_to_paths_filename_truncation() {
     run_remote_specifiers_to_paths_test(
@@ -637,6 +923,25 @@ mod test {
       25,
     );
 
+    // the versions of a package aren't truncated
+    run_remote_specifiers_to_paths_test(
+      &[
+        "http://localhost/a/pkg@1.0.0/mod.ts",
+        "http://localhost/a/pkg@1.1.0/mod.ts",
+      ],
+      &[
+        (
+          "http://localhost/a/pkg@1.0.0/mod.ts",
+          "local/a/pkg@1.0.0/mod.ts",
+        ),
+        (
+          "http://localhost/a/pkg@1.1.0/mod.ts",
+          "local/a/pkg@1.1.0/mod.ts",
+        ),
+      ],
+      25,
+    );
+
     run_remote_specifiers_to_paths_test(
       &["http://localhost/1234567890123456789/123.5678"],
       &[(
@@ -658,6 +963,14 @@ mod test {
       .collect::<Vec<_>>();
     let result =
       remote_specifiers_to_paths_with_truncation(specifiers.iter(), max_length);
+    // the paths shouldn't depend on the order of the specifiers
+    assert_eq!(
+      remote_specifiers_to_paths_with_truncation(
+        specifiers.iter().rev(),
+        max_length
+      ),
+      result,
+    );
     let result_as_strings = result
       .into_iter()
       .map(|(url, path)| {
@@ -672,6 +985,40 @@ mod test {
     assert_eq!(result_as_strs, expected);
   }
 
+  #[test]
+  fn test_get_mapped_file_path_content_hash() {
+    let mut mapped_filepaths_no_ext = HashSet::new();
+    let mut run_test = |media_type: MediaType, path: &str, expected: &str| {
+      assert_eq!(
+        get_mapped_file_path(
+          media_type,
+          path,
+          Some(&get_content_hash("")),
+          &mut mapped_filepaths_no_ext,
+        ),
+        PathBuf::from(expected)
+      );
+    };
+    run_test(MediaType::TypeScript, "deps/mod.ts", "deps/mod.e3b0c442.ts");
+    run_test(
+      MediaType::TypeScript,
+      "deps/other/mod.ts",
+      "deps/other/mod.e3b0c442.ts",
+    );
+    // same source and path
+    run_test(
+      MediaType::TypeScript,
+      "deps/mod.ts",
+      "deps/mod_2.e3b0c442.ts",
+    );
+    run_test(MediaType::Json, "deps/data.json", "deps/data.e3b0c442.js");
+    run_test(
+      MediaType::Dts,
+      "deps/types.d.ts",
+      "deps/types.e3b0c442.d.ts",
+    );
+  }
+
   #[test]
   fn test_split_stem_and_ext() {
     assert_eq!(split_stem_and_ext("test.ts"), Some(("test", "ts")));
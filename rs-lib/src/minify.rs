@@ -0,0 +1,151 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::path::Path;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use deno_ast::swc::ast::EsVersion;
+use deno_ast::swc::codegen;
+use deno_ast::swc::codegen::text_writer::omit_trailing_semi;
+use deno_ast::swc::codegen::text_writer::JsWriter;
+use deno_ast::swc::common::sync::Lrc;
+use deno_ast::swc::common::FileName;
+use deno_ast::swc::common::Globals;
+use deno_ast::swc::common::Mark;
+use deno_ast::swc::common::SourceMap;
+use deno_ast::swc::common::GLOBALS;
+use deno_ast::swc::parser::lexer::Lexer;
+use deno_ast::swc::parser::Parser;
+use deno_ast::swc::parser::StringInput;
+use deno_ast::swc::parser::Syntax;
+use deno_ast::swc::transforms::fixer;
+use deno_ast::swc::transforms::resolver;
+use deno_ast::swc::visit::FoldWith;
+use deno_ast::MediaType;
+use swc_ecma_minifier::optimize;
+use swc_ecma_minifier::option::ExtraOptions;
+use swc_ecma_minifier::option::MangleOptions;
+use swc_ecma_minifier::option::MinifyOptions;
+
+use crate::utils::strip_bom;
+use crate::TransformOutputEnvironment;
+
+/// Minifies the JavaScript files of the environment. The TypeScript files
+/// are left as is.
+pub fn minify_environment(
+  environment: &mut TransformOutputEnvironment,
+) -> Result<()> {
+  for file in environment.files.iter_mut() {
+    if is_js_file(&file.file_path) {
+      file.file_text = minify_js(&file.file_path, &file.file_text)?;
+    }
+  }
+  Ok(())
+}
+
+fn is_js_file(path: &Path) -> bool {
+  matches!(
+    MediaType::from(path),
+    MediaType::JavaScript | MediaType::Mjs | MediaType::Cjs
+  )
+}
+
+/// Removes the whitespace and comments of a JavaScript module and shortens
+/// its local identifiers. The top level names of a CommonJS module are
+/// kept because they might be assigned to `exports` by name.
+fn minify_js(file_path: &Path, file_text: &str) -> Result<String> {
+  let cm: Lrc<SourceMap> = Default::default();
+  let source_file = cm.new_source_file(
+    FileName::Custom(file_path.display().to_string()),
+    strip_bom(file_text).to_string(),
+  );
+  let lexer = Lexer::new(
+    Syntax::Es(Default::default()),
+    EsVersion::latest(),
+    StringInput::from(&*source_file),
+    None,
+  );
+  let module = Parser::new_from(lexer).parse_module().map_err(|err| {
+    anyhow!(
+      "Could not parse {} to minify it. {}",
+      file_path.display(),
+      err.kind().msg()
+    )
+  })?;
+  let is_commonjs = MediaType::from(file_path) == MediaType::Cjs;
+
+  GLOBALS.set(&Globals::new(), || {
+    let unresolved_mark = Mark::new();
+    let top_level_mark = Mark::new();
+    let module =
+      module.fold_with(&mut resolver(unresolved_mark, top_level_mark, false));
+    let module = optimize(
+      module,
+      cm.clone(),
+      None,
+      None,
+      &MinifyOptions {
+        compress: Some(Default::default()),
+        mangle: Some(MangleOptions {
+          top_level: !is_commonjs,
+          ..Default::default()
+        }),
+        ..Default::default()
+      },
+      &ExtraOptions {
+        unresolved_mark,
+        top_level_mark,
+      },
+    );
+    let module = module.fold_with(&mut fixer(None));
+
+    let mut buf = Vec::new();
+    {
+      let mut emitter = codegen::Emitter {
+        cfg: codegen::Config {
+          minify: true,
+          ..Default::default()
+        },
+        cm: cm.clone(),
+        comments: None,
+        wr: omit_trailing_semi(JsWriter::new(cm.clone(), "\n", &mut buf, None)),
+      };
+      emitter.emit_module(&module)?;
+    }
+    Ok(String::from_utf8(buf)?)
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_minify_js() {
+    let text = minify_js(
+      Path::new("mod.js"),
+      concat!(
+        "// adds the values\n",
+        "export function add(first, second) {\n",
+        "  return first + second;\n",
+        "}\n",
+      ),
+    )
+    .unwrap();
+    assert!(text.starts_with("export function add("));
+    assert!(!text.contains('\n'));
+    assert!(!text.contains("adds the values"));
+    assert!(!text.contains("first"));
+  }
+
+  #[test]
+  fn test_minify_js_commonjs_keeps_top_level_names() {
+    let text = minify_js(
+      Path::new("mod.cjs"),
+      "function add(first, second) {\n  return first + second;\n}\nexports.add = add;\n",
+    )
+    .unwrap();
+    assert!(text.contains("function add("));
+    assert!(!text.contains("first"));
+  }
+}
@@ -0,0 +1,659 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use deno_ast::parse_module;
+use deno_ast::view::*;
+use deno_ast::MediaType;
+use deno_ast::ParseParams;
+use deno_ast::SourceRanged;
+use deno_ast::SourceTextInfo;
+use deno_ast::SourceTextInfoProvider;
+
+use crate::bundle::get_decl_names;
+use crate::bundle::get_module_export_name;
+use crate::bundle::get_module_export_name_text;
+use crate::bundle::is_code_file;
+use crate::bundle::is_relative_specifier;
+use crate::bundle::is_type_only_decl;
+use crate::text_changes::to_byte_range;
+use crate::trace::log_debug;
+use crate::utils::strip_bom;
+use crate::utils::with_extension;
+use crate::virtual_path::VirtualPath;
+use crate::EntryPointExports;
+use crate::OutputFile;
+use crate::TransformOutputEnvironment;
+
+/// Name of a namespace re-export (ex. `export * as ns from "./a.ts"`) in
+/// the module it's from.
+const NAMESPACE_NAME: &str = "*";
+
+/// An `export * from` statement of a module.
+pub struct StarExport {
+  pub specifier: String,
+  /// Output file the specifier resolves to or `None` when the module
+  /// isn't in the output (ex. an npm package).
+  pub path: Option<PathBuf>,
+  /// Byte range of the statement.
+  pub range: Range<usize>,
+}
+
+/// An `export { ... } from` or `export * as ns from` statement of a module.
+pub struct NamedReExport {
+  pub specifier: String,
+  pub path: Option<PathBuf>,
+  pub is_type_only: bool,
+  pub names: Vec<ReExportName>,
+}
+
+pub struct ReExportName {
+  /// Name the module exports it as.
+  pub exported: String,
+  /// Name of the export in the module it's from, which is `*` for a
+  /// namespace re-export.
+  pub orig: String,
+  /// Text of the export specifier (ex. `a as b` or `type T`).
+  pub text: String,
+  /// If the specifier has a `type` modifier.
+  pub is_type_only: bool,
+}
+
+/// The exports of an output module as they're written in it.
+pub struct ModuleExports {
+  /// Names exported by the module's own declarations and export lists.
+  pub local_names: BTreeSet<String>,
+  /// The local names that are only types (ex. interfaces).
+  pub type_names: BTreeSet<String>,
+  pub named_re_exports: Vec<NamedReExport>,
+  pub star_exports: Vec<StarExport>,
+  /// If the module only consists of re-exports.
+  pub is_barrel: bool,
+}
+
+impl ModuleExports {
+  /// Names the module exports explicitly, which take precedence over the
+  /// names of its `export *` sources.
+  pub fn explicit_names(&self) -> BTreeSet<&str> {
+    self
+      .local_names
+      .iter()
+      .map(|name| name.as_str())
+      .chain(
+        self
+          .named_re_exports
+          .iter()
+          .flat_map(|re_export| re_export.names.iter())
+          .map(|name| name.exported.as_str()),
+      )
+      .collect()
+  }
+}
+
+/// Where an exported name is declared.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExportOrigin {
+  /// An export of a module in the output, or its namespace when the name
+  /// is `*`.
+  Module(PathBuf, String),
+  /// An export of a module that isn't in the output.
+  External(String, String),
+}
+
+/// Names exported by a module and where they're declared. A name with
+/// more than one origin comes from conflicting `export *` sources, so
+/// it's ambiguous and not actually exported.
+pub type ResolvedExports = BTreeMap<String, BTreeSet<ExportOrigin>>;
+
+/// A name that more than one `export *` of a module exports from
+/// different declarations.
+pub struct StarExportConflict {
+  pub path: PathBuf,
+  pub name: String,
+  /// Specifiers of the `export *` statements that export the name.
+  pub specifiers: Vec<String>,
+  pub origins: BTreeSet<ExportOrigin>,
+}
+
+/// The exports of the code files of an output environment.
+pub struct ExportTables {
+  file_paths: Vec<PathBuf>,
+  modules: HashMap<PathBuf, ModuleExports>,
+}
+
+impl ExportTables {
+  pub fn new(files: &[OutputFile]) -> Result<Self> {
+    let mut tables = Self::empty(files);
+    for file in files.iter().filter(|f| is_code_file(&f.file_path)) {
+      tables.update(&file.file_path, &file.file_text)?;
+    }
+    Ok(tables)
+  }
+
+  /// Only analyzes the entry points and the modules their exports are
+  /// re-exported from.
+  pub fn for_entry_points(
+    files: &[OutputFile],
+    entry_points: &[PathBuf],
+  ) -> Result<Self> {
+    let mut tables = Self::empty(files);
+    let mut pending = entry_points.to_vec();
+    while let Some(path) = pending.pop() {
+      if tables.modules.contains_key(&path) || !is_code_file(&path) {
+        continue;
+      }
+      let file = match files.iter().find(|file| file.file_path == path) {
+        Some(file) => file,
+        None => continue,
+      };
+      tables.update(&path, &file.file_text)?;
+      let module = &tables.modules[&path];
+      pending.extend(
+        module
+          .named_re_exports
+          .iter()
+          .filter_map(|re_export| re_export.path.clone())
+          .chain(
+            module
+              .star_exports
+              .iter()
+              .filter_map(|star_export| star_export.path.clone()),
+          ),
+      );
+    }
+    Ok(tables)
+  }
+
+  fn empty(files: &[OutputFile]) -> Self {
+    Self {
+      file_paths: files
+        .iter()
+        .map(|file| file.file_path.clone())
+        .filter(|file_path| is_code_file(file_path))
+        .collect(),
+      modules: HashMap::new(),
+    }
+  }
+
+  pub fn get(&self, path: &Path) -> Option<&ModuleExports> {
+    self.modules.get(path)
+  }
+
+  /// Analyzes the exports of a module again after its text changed.
+  pub fn update(&mut self, path: &Path, file_text: &str) -> Result<()> {
+    let module = self.analyze_module(path, file_text)?;
+    self.modules.insert(path.to_path_buf(), module);
+    Ok(())
+  }
+
+  /// Resolves a specifier to the path of a code file in the output.
+  pub fn resolve(&self, referrer: &Path, specifier: &str) -> Option<PathBuf> {
+    if !is_relative_specifier(specifier) {
+      return None;
+    }
+    let path = VirtualPath::new(referrer).parent()?.join(specifier);
+    self
+      .file_paths
+      .iter()
+      .find(|file_path| {
+        VirtualPath::new(file_path) == path
+          || VirtualPath::new(with_extension(file_path, "js")) == path
+          || VirtualPath::new(with_extension(file_path, "")) == path
+      })
+      .cloned()
+  }
+
+  /// Gets the names a module exports, following its re-exports to where
+  /// the names are declared.
+  pub fn resolve_exports(&self, path: &Path) -> ResolvedExports {
+    self.resolve_exports_inner(path, &mut Vec::new())
+  }
+
+  /// Gets if a name a module exports is only a type, following its
+  /// re-exports to where the name is declared.
+  pub fn is_type_export(&self, path: &Path, name: &str) -> bool {
+    self.is_type_export_inner(path, name, &mut Vec::new())
+  }
+
+  /// Gets the names that are ambiguous because the `export *` sources of
+  /// a module export them from different declarations. Each conflict is
+  /// only reported for the module whose `export *` statements meet, not
+  /// for the modules that re-export the ambiguous name.
+  pub fn get_star_export_conflicts(&self) -> Vec<StarExportConflict> {
+    let mut conflicts = Vec::new();
+    let mut paths = self.modules.keys().collect::<Vec<_>>();
+    paths.sort();
+    for path in paths {
+      let module = &self.modules[path];
+      if module.star_exports.len() < 2 {
+        continue;
+      }
+      let star_exports = module
+        .star_exports
+        .iter()
+        .filter_map(|star_export| {
+          let target = star_export.path.as_ref()?;
+          Some((star_export, self.resolve_exports(target)))
+        })
+        .collect::<Vec<_>>();
+      for (name, origins) in self.resolve_exports(path) {
+        if origins.len() < 2 {
+          continue;
+        }
+        let sources = star_exports
+          .iter()
+          .filter_map(|(star_export, exports)| {
+            Some((star_export, exports.get(&name)?))
+          })
+          .collect::<Vec<_>>();
+        if sources.len() < 2
+          || sources.iter().any(|(_, origins)| origins.len() > 1)
+        {
+          continue;
+        }
+        conflicts.push(StarExportConflict {
+          path: path.clone(),
+          specifiers: sources
+            .iter()
+            .map(|(star_export, _)| star_export.specifier.clone())
+            .collect(),
+          name,
+          origins,
+        });
+      }
+    }
+    conflicts
+  }
+
+  fn resolve_exports_inner(
+    &self,
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+  ) -> ResolvedExports {
+    let module = match self.modules.get(path) {
+      // a cycle of `export *` doesn't export anything more
+      Some(module) if !stack.iter().any(|p| p == path) => module,
+      _ => return ResolvedExports::new(),
+    };
+    stack.push(path.to_path_buf());
+    let mut exports = ResolvedExports::new();
+    for name in module.local_names.iter() {
+      exports.insert(
+        name.clone(),
+        BTreeSet::from([ExportOrigin::Module(
+          path.to_path_buf(),
+          name.clone(),
+        )]),
+      );
+    }
+    for re_export in module.named_re_exports.iter() {
+      let target_exports = match &re_export.path {
+        Some(target) => self.resolve_exports_inner(target, stack),
+        None => ResolvedExports::new(),
+      };
+      for name in re_export.names.iter() {
+        let origins = match &re_export.path {
+          Some(target) => target_exports
+            .get(&name.orig)
+            .filter(|_| name.orig != NAMESPACE_NAME)
+            .cloned()
+            .unwrap_or_else(|| {
+              BTreeSet::from([ExportOrigin::Module(
+                target.clone(),
+                name.orig.clone(),
+              )])
+            }),
+          None => BTreeSet::from([ExportOrigin::External(
+            re_export.specifier.clone(),
+            name.orig.clone(),
+          )]),
+        };
+        exports.insert(name.exported.clone(), origins);
+      }
+    }
+    let mut star_names = ResolvedExports::new();
+    for star_export in module.star_exports.iter() {
+      let target = match &star_export.path {
+        Some(target) => target,
+        None => continue,
+      };
+      for (name, origins) in self.resolve_exports_inner(target, stack) {
+        if name != "default" && !exports.contains_key(&name) {
+          star_names.entry(name).or_default().extend(origins);
+        }
+      }
+    }
+    exports.extend(star_names);
+    stack.pop();
+    exports
+  }
+
+  fn is_type_export_inner(
+    &self,
+    path: &Path,
+    name: &str,
+    stack: &mut Vec<PathBuf>,
+  ) -> bool {
+    let module = match self.modules.get(path) {
+      Some(module) if !stack.iter().any(|p| p == path) => module,
+      _ => return false,
+    };
+    let re_export = module.named_re_exports.iter().find_map(|re_export| {
+      let re_export_name =
+        re_export.names.iter().find(|n| n.exported == name)?;
+      Some((re_export, re_export_name))
+    });
+    if re_export.is_none() && module.local_names.contains(name) {
+      return module.type_names.contains(name);
+    }
+    stack.push(path.to_path_buf());
+    let is_type = match re_export {
+      Some((re_export, re_export_name)) => {
+        re_export.is_type_only
+          || re_export_name.is_type_only
+          || (re_export_name.orig != NAMESPACE_NAME
+            && match &re_export.path {
+              Some(target) => {
+                self.is_type_export_inner(target, &re_export_name.orig, stack)
+              }
+              None => false,
+            })
+      }
+      None => {
+        name != "default"
+          && module
+            .star_exports
+            .iter()
+            .filter_map(|star_export| star_export.path.as_ref())
+            .any(|target| {
+              self.resolve_exports_inner(target, stack).contains_key(name)
+                && self.is_type_export_inner(target, name, stack)
+            })
+      }
+    };
+    stack.pop();
+    is_type
+  }
+
+  fn analyze_module(
+    &self,
+    path: &Path,
+    file_text: &str,
+  ) -> Result<ModuleExports> {
+    let parsed_source = parse_module(ParseParams {
+      specifier: path.to_string_lossy().to_string(),
+      text_info: SourceTextInfo::from_string(strip_bom(file_text).to_string()),
+      media_type: MediaType::from(path),
+      capture_tokens: false,
+      scope_analysis: false,
+      maybe_syntax: None,
+    })
+    .map_err(|diagnostic| {
+      anyhow!(
+        "Could not parse {} to analyze its exports. {}",
+        path.display(),
+        diagnostic
+      )
+    })?;
+
+    let mut module = ModuleExports {
+      local_names: BTreeSet::new(),
+      type_names: BTreeSet::new(),
+      named_re_exports: Vec::new(),
+      star_exports: Vec::new(),
+      is_barrel: true,
+    };
+    parsed_source.with_view(|program| {
+      let text_info = program.text_info();
+      let body = match program {
+        Program::Module(module) => &module.body,
+        Program::Script(_) => {
+          module.is_barrel = false;
+          return;
+        }
+      };
+      // the local binding of each local export and if it's type only
+      let mut local_exports = Vec::new();
+      let mut type_decl_names = HashSet::new();
+      let mut value_decl_names = HashSet::new();
+      for item in body.iter() {
+        match item {
+          ModuleItem::Stmt(Stmt::Decl(decl)) => {
+            module.is_barrel = false;
+            if is_type_only_decl(decl) {
+              type_decl_names.extend(get_decl_names(decl));
+            } else {
+              value_decl_names.extend(get_decl_names(decl));
+            }
+          }
+          ModuleItem::ModuleDecl(ModuleDecl::Import(import_decl)) => {
+            module.is_barrel = false;
+            for specifier in import_decl.specifiers.iter() {
+              let (local, is_type_only) = match specifier {
+                ImportSpecifier::Named(named) => {
+                  (named.local, named.inner.is_type_only)
+                }
+                ImportSpecifier::Default(default) => (default.local, false),
+                ImportSpecifier::Namespace(namespace) => {
+                  (namespace.local, false)
+                }
+              };
+              if import_decl.inner.type_only || is_type_only {
+                type_decl_names.insert(local.sym().to_string());
+              } else {
+                value_decl_names.insert(local.sym().to_string());
+              }
+            }
+          }
+          ModuleItem::ModuleDecl(ModuleDecl::ExportAll(export_all)) => {
+            let specifier = export_all.src.value().to_string();
+            module.star_exports.push(StarExport {
+              path: self.resolve(path, &specifier),
+              specifier,
+              range: to_byte_range(text_info, export_all.range()),
+            });
+          }
+          ModuleItem::ModuleDecl(ModuleDecl::ExportNamed(named_export)) => {
+            let names = named_export
+              .specifiers
+              .iter()
+              .map(|specifier| match specifier {
+                ExportSpecifier::Named(named) => {
+                  let orig = get_module_export_name(named.orig);
+                  ReExportName {
+                    exported: named
+                      .exported
+                      .map(get_module_export_name)
+                      .unwrap_or_else(|| orig.clone()),
+                    orig,
+                    text: named.text_fast(program).to_string(),
+                    is_type_only: named.inner.is_type_only,
+                  }
+                }
+                ExportSpecifier::Namespace(namespace) => ReExportName {
+                  exported: get_module_export_name(namespace.name),
+                  orig: NAMESPACE_NAME.to_string(),
+                  text: format!(
+                    "* as {}",
+                    get_module_export_name_text(namespace.name)
+                  ),
+                  is_type_only: false,
+                },
+                ExportSpecifier::Default(default) => ReExportName {
+                  exported: default.exported.sym().to_string(),
+                  orig: "default".to_string(),
+                  text: format!("default as {}", default.exported.sym()),
+                  is_type_only: false,
+                },
+              })
+              .collect::<Vec<_>>();
+            match named_export.src {
+              Some(src) => {
+                let specifier = src.value().to_string();
+                module.named_re_exports.push(NamedReExport {
+                  path: self.resolve(path, &specifier),
+                  specifier,
+                  is_type_only: named_export.inner.type_only,
+                  names,
+                });
+              }
+              None => {
+                module.is_barrel = module.is_barrel && names.is_empty();
+                for name in names {
+                  local_exports.push((
+                    name.exported,
+                    name.orig,
+                    named_export.inner.type_only || name.is_type_only,
+                  ));
+                }
+              }
+            }
+          }
+          ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export_decl)) => {
+            module.is_barrel = false;
+            let names = get_decl_names(&export_decl.decl);
+            if is_type_only_decl(&export_decl.decl) {
+              type_decl_names.extend(names.iter().cloned());
+            } else {
+              value_decl_names.extend(names.iter().cloned());
+            }
+            for name in names {
+              local_exports.push((name.clone(), name, false));
+            }
+          }
+          ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultDecl(
+            export_default_decl,
+          )) => {
+            module.is_barrel = false;
+            let orig = match &export_default_decl.decl {
+              DefaultDecl::TsInterfaceDecl(interface_decl) => {
+                let name = interface_decl.id.sym().to_string();
+                type_decl_names.insert(name.clone());
+                name
+              }
+              _ => "default".to_string(),
+            };
+            local_exports.push(("default".to_string(), orig, false));
+          }
+          ModuleItem::ModuleDecl(ModuleDecl::ExportDefaultExpr(
+            export_default_expr,
+          )) => {
+            module.is_barrel = false;
+            // `export default T;` can export an interface or type alias
+            let orig = match export_default_expr.expr {
+              Expr::Ident(ident) => ident.sym().to_string(),
+              _ => "default".to_string(),
+            };
+            local_exports.push(("default".to_string(), orig, false));
+          }
+          ModuleItem::Stmt(Stmt::Empty(_)) => {}
+          _ => module.is_barrel = false,
+        }
+      }
+      for (exported, orig, is_type_only) in local_exports {
+        // a name can be declared as both a type and a value (ex. an
+        // interface merged with a class)
+        if is_type_only
+          || (type_decl_names.contains(&orig)
+            && !value_decl_names.contains(&orig))
+        {
+          module.type_names.insert(exported.clone());
+        }
+        module.local_names.insert(exported);
+      }
+    });
+    Ok(module)
+  }
+}
+
+/// Gets the names each entry point of an output environment exports,
+/// split into values and types. Ambiguous names aren't included because
+/// they're not actually exported.
+pub fn get_entry_point_exports(
+  environment: &TransformOutputEnvironment,
+) -> Result<Vec<EntryPointExports>> {
+  let tables = ExportTables::for_entry_points(
+    &environment.files,
+    &environment.entry_points,
+  )?;
+  Ok(
+    environment
+      .entry_points
+      .iter()
+      .map(|entry_point| {
+        let mut entry_point_exports = EntryPointExports {
+          file_path: entry_point.clone(),
+          values: Vec::new(),
+          types: Vec::new(),
+        };
+        for (name, origins) in tables.resolve_exports(entry_point) {
+          if origins.len() > 1 {
+            continue;
+          }
+          if tables.is_type_export(entry_point, &name) {
+            entry_point_exports.types.push(name);
+          } else {
+            entry_point_exports.values.push(name);
+          }
+        }
+        entry_point_exports
+      })
+      .collect(),
+  )
+}
+
+/// Gets warnings for the names that are ambiguous between the `export *`
+/// statements of the output modules, which bundlers handle differently.
+pub fn get_star_export_conflict_warnings(files: &[OutputFile]) -> Vec<String> {
+  if !files.iter().any(|file| file.file_text.contains("export *")) {
+    return Vec::new();
+  }
+  let tables = match ExportTables::new(files) {
+    Ok(tables) => tables,
+    Err(err) => {
+      log_debug!("Skipped checking for conflicting exports. {:#}", err);
+      return Vec::new();
+    }
+  };
+  tables
+    .get_star_export_conflicts()
+    .iter()
+    .map(get_star_export_conflict_message)
+    .collect()
+}
+
+fn get_star_export_conflict_message(conflict: &StarExportConflict) -> String {
+  format!(
+    "{} exports `{}` from more than one `export *` ({}), which declare it in {}. The name is ambiguous, so it isn't exported and bundlers handle it differently. Export it explicitly from the module it should come from.",
+    conflict.path.display(),
+    conflict.name,
+    conflict.specifiers.join(", "),
+    conflict
+      .origins
+      .iter()
+      .map(|origin| match origin {
+        ExportOrigin::Module(path, name) if *name == conflict.name => {
+          path.display().to_string()
+        }
+        ExportOrigin::Module(path, name) => {
+          format!("{} as `{}`", path.display(), name)
+        }
+        ExportOrigin::External(specifier, name) if *name == conflict.name => {
+          specifier.clone()
+        }
+        ExportOrigin::External(specifier, name) => {
+          format!("{} as `{}`", specifier, name)
+        }
+      })
+      .collect::<Vec<_>>()
+      .join(" and ")
+  )
+}
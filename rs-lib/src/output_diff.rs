@@ -0,0 +1,268 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::Dependency;
+use crate::OutputEnvironment;
+use crate::TransformOutput;
+
+/// Number of unchanged lines shown around the changed lines of a file.
+const CONTEXT_LINE_COUNT: usize = 3;
+
+/// What changed between two outputs, which is empty when publishing the
+/// new output wouldn't change anything. The file paths are sorted.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct OutputDiff {
+  pub added_files: Vec<PathBuf>,
+  pub removed_files: Vec<PathBuf>,
+  pub modified_files: Vec<ModifiedFile>,
+  /// Sorted by environment and then name.
+  pub dependency_changes: Vec<DependencyChange>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ModifiedFile {
+  pub file_path: PathBuf,
+  /// The changed lines of the file in the unified diff format.
+  pub diff: String,
+}
+
+/// A dependency that was added, removed, or changed its version.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DependencyChange {
+  pub environment: OutputEnvironment,
+  pub name: String,
+  /// Version in the previous output or `None` when it was added.
+  pub previous_version: Option<String>,
+  /// Version in the new output or `None` when it was removed.
+  pub version: Option<String>,
+}
+
+impl OutputDiff {
+  pub fn is_empty(&self) -> bool {
+    self.added_files.is_empty()
+      && self.removed_files.is_empty()
+      && self.modified_files.is_empty()
+      && self.dependency_changes.is_empty()
+  }
+}
+
+impl fmt::Display for OutputDiff {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.is_empty() {
+      return writeln!(f, "No changes.");
+    }
+    for file_path in self.added_files.iter() {
+      writeln!(f, "Added {}", file_path.display())?;
+    }
+    for file_path in self.removed_files.iter() {
+      writeln!(f, "Removed {}", file_path.display())?;
+    }
+    for file in self.modified_files.iter() {
+      writeln!(f, "Modified {}", file.file_path.display())?;
+    }
+    for change in self.dependency_changes.iter() {
+      let environment = match change.environment {
+        OutputEnvironment::Main => "",
+        OutputEnvironment::Test => " (test)",
+      };
+      match (&change.previous_version, &change.version) {
+        (None, Some(version)) => writeln!(
+          f,
+          "Added dependency {}@{}{}",
+          change.name, version, environment
+        )?,
+        (Some(version), None) => writeln!(
+          f,
+          "Removed dependency {}@{}{}",
+          change.name, version, environment
+        )?,
+        (previous_version, version) => writeln!(
+          f,
+          "Changed dependency {} from {} to {}{}",
+          change.name,
+          previous_version.as_deref().unwrap_or_default(),
+          version.as_deref().unwrap_or_default(),
+          environment
+        )?,
+      }
+    }
+    for file in self.modified_files.iter() {
+      let path = file.file_path.display();
+      write!(f, "\n--- a/{}\n+++ b/{}\n{}", path, path, file.diff)?;
+    }
+    Ok(())
+  }
+}
+
+impl TransformOutput {
+  /// Compares the output to the output of a previous transform, such as
+  /// to skip publishing when nothing changed or to show what changed in
+  /// a pull request.
+  pub fn diff(&self, previous: &TransformOutput) -> OutputDiff {
+    let get_files = |output: &TransformOutput| {
+      output
+        .main
+        .files
+        .iter()
+        .chain(output.test.files.iter())
+        .map(|file| (file.file_path.clone(), file.file_text.as_str()))
+        .collect::<BTreeMap<_, _>>()
+    };
+    let files = get_files(self);
+    let previous_files = get_files(previous);
+
+    let mut diff = OutputDiff::default();
+    for (file_path, file_text) in files.iter() {
+      match previous_files.get(file_path) {
+        Some(previous_text) if previous_text == file_text => {}
+        Some(previous_text) => diff.modified_files.push(ModifiedFile {
+          file_path: file_path.clone(),
+          diff: get_text_diff(previous_text, file_text),
+        }),
+        None => diff.added_files.push(file_path.clone()),
+      }
+    }
+    diff.removed_files = previous_files
+      .keys()
+      .filter(|file_path| !files.contains_key(*file_path))
+      .cloned()
+      .collect();
+
+    for (environment, dependencies, previous_dependencies) in [
+      (
+        OutputEnvironment::Main,
+        &self.main.dependencies,
+        &previous.main.dependencies,
+      ),
+      (
+        OutputEnvironment::Test,
+        &self.test.dependencies,
+        &previous.test.dependencies,
+      ),
+    ] {
+      diff.dependency_changes.extend(get_dependency_changes(
+        environment,
+        dependencies,
+        previous_dependencies,
+      ));
+    }
+    diff
+  }
+}
+
+fn get_dependency_changes(
+  environment: OutputEnvironment,
+  dependencies: &[Dependency],
+  previous_dependencies: &[Dependency],
+) -> Vec<DependencyChange> {
+  let get_versions = |dependencies: &[Dependency]| {
+    let mut versions: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for dependency in dependencies {
+      versions
+        .entry(dependency.name.clone())
+        .or_default()
+        .push(dependency.version.clone());
+    }
+    versions
+  };
+  let versions = get_versions(dependencies);
+  let mut previous_versions = get_versions(previous_dependencies);
+  let mut changes = Vec::new();
+  for (name, versions) in versions {
+    let previous_versions = previous_versions.remove(&name);
+    if previous_versions.as_ref() == Some(&versions) {
+      continue;
+    }
+    changes.push(DependencyChange {
+      environment,
+      name,
+      previous_version: previous_versions.map(|v| v.join(", ")),
+      version: Some(versions.join(", ")),
+    });
+  }
+  for (name, previous_versions) in previous_versions {
+    changes.push(DependencyChange {
+      environment,
+      name,
+      previous_version: Some(previous_versions.join(", ")),
+      version: None,
+    });
+  }
+  changes.sort_by(|a, b| a.name.cmp(&b.name));
+  changes
+}
+
+/// Gets a unified diff of the lines that changed between the texts as a
+/// single hunk from the first to the last changed line, which keeps it
+/// cheap for large files.
+fn get_text_diff(old_text: &str, new_text: &str) -> String {
+  let old_lines = old_text.lines().collect::<Vec<_>>();
+  let new_lines = new_text.lines().collect::<Vec<_>>();
+  let prefix_len = old_lines
+    .iter()
+    .zip(new_lines.iter())
+    .take_while(|(a, b)| a == b)
+    .count();
+  let suffix_len = old_lines[prefix_len..]
+    .iter()
+    .rev()
+    .zip(new_lines[prefix_len..].iter().rev())
+    .take_while(|(a, b)| a == b)
+    .count();
+  let start = prefix_len.saturating_sub(CONTEXT_LINE_COUNT);
+  let old_end =
+    (old_lines.len() - suffix_len + CONTEXT_LINE_COUNT).min(old_lines.len());
+  let new_end =
+    (new_lines.len() - suffix_len + CONTEXT_LINE_COUNT).min(new_lines.len());
+
+  let mut text = format!(
+    "@@ -{} +{} @@\n",
+    get_hunk_range(start, old_end),
+    get_hunk_range(start, new_end),
+  );
+  for line in old_lines[start..prefix_len].iter() {
+    text.push_str(&format!(" {}\n", line));
+  }
+  for line in old_lines[prefix_len..old_lines.len() - suffix_len].iter() {
+    text.push_str(&format!("-{}\n", line));
+  }
+  for line in new_lines[prefix_len..new_lines.len() - suffix_len].iter() {
+    text.push_str(&format!("+{}\n", line));
+  }
+  for line in new_lines[new_lines.len() - suffix_len..new_end].iter() {
+    text.push_str(&format!(" {}\n", line));
+  }
+  text
+}
+
+/// Gets the 1-indexed start line and line count of a hunk.
+fn get_hunk_range(start: usize, end: usize) -> String {
+  let count = end - start;
+  if count == 0 {
+    // an empty range is given by the line before it
+    format!("{},0", start)
+  } else {
+    format!("{},{}", start + 1, count)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn text_diff() {
+    assert_eq!(
+      get_text_diff(
+        "1\n2\n3\n4\n5\n6\n7\n8\n9\n",
+        "1\n2\n3\n4\nfive\n6\n7\n8\n9\n"
+      ),
+      "@@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n",
+    );
+    assert_eq!(get_text_diff("a\n", "a\nb\n"), "@@ -1,1 +1,2 @@\n a\n+b\n");
+    assert_eq!(get_text_diff("a\n", ""), "@@ -1,1 +0,0 @@\n-a\n");
+  }
+}
@@ -0,0 +1,585 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+//! Helpers for generating a package.json file for the output of a
+//! transform.
+//!
+//! The paths assume the output is emitted to an `esm` directory for
+//! ES modules, a `script` directory for CommonJS modules, and a `types`
+//! directory for declaration files.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::bail;
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::utils::with_extension;
+use crate::virtual_path::VirtualPath;
+use crate::TransformOutput;
+use crate::TransformOutputEnvironment;
+use crate::VersionPolicy;
+
+/// Command that's used for the `test` script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TestRunner {
+  /// dnt's test runner file that's output next to the package.json.
+  Dnt,
+  /// Node's built-in test runner.
+  NodeTest,
+  /// A custom command.
+  Custom(String),
+}
+
+impl TestRunner {
+  fn command(&self) -> &str {
+    match self {
+      TestRunner::Dnt => "node test_runner.js",
+      TestRunner::NodeTest => "node --test",
+      TestRunner::Custom(command) => command,
+    }
+  }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PackageJsonOptions {
+  pub name: String,
+  pub version: String,
+  pub description: Option<String>,
+  /// Adds a `test` script and the test dependencies as dev dependencies
+  /// when provided.
+  pub test_runner: Option<TestRunner>,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageJson {
+  pub name: String,
+  pub version: String,
+  #[cfg_attr(
+    feature = "serialization",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub description: Option<String>,
+  /// CommonJS module of the first entry point.
+  #[cfg_attr(
+    feature = "serialization",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub main: Option<String>,
+  /// ES module of the first entry point.
+  #[cfg_attr(
+    feature = "serialization",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub module: Option<String>,
+  /// Declaration file of the first entry point.
+  #[cfg_attr(
+    feature = "serialization",
+    serde(skip_serializing_if = "Option::is_none")
+  )]
+  pub types: Option<String>,
+  pub exports: BTreeMap<String, PackageJsonExport>,
+  #[cfg_attr(
+    feature = "serialization",
+    serde(skip_serializing_if = "BTreeMap::is_empty")
+  )]
+  pub scripts: BTreeMap<String, String>,
+  #[cfg_attr(
+    feature = "serialization",
+    serde(skip_serializing_if = "BTreeMap::is_empty")
+  )]
+  pub dependencies: BTreeMap<String, String>,
+  #[cfg_attr(
+    feature = "serialization",
+    serde(skip_serializing_if = "BTreeMap::is_empty")
+  )]
+  pub peer_dependencies: BTreeMap<String, String>,
+  #[cfg_attr(
+    feature = "serialization",
+    serde(skip_serializing_if = "BTreeMap::is_empty")
+  )]
+  pub dev_dependencies: BTreeMap<String, String>,
+}
+
+/// Conditional export of an entry point for the ES module and CommonJS
+/// output.
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageJsonExport {
+  pub import: PackageJsonExportTarget,
+  pub require: PackageJsonExportTarget,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackageJsonExportTarget {
+  pub types: String,
+  pub default: String,
+}
+
+/// Gets a package.json for the output of a transform.
+pub fn get_package_json(
+  output: &TransformOutput,
+  options: &PackageJsonOptions,
+) -> PackageJson {
+  let mut dependencies = BTreeMap::new();
+  let mut peer_dependencies = BTreeMap::new();
+  for dependency in output.main.dependencies.iter() {
+    let dependencies = if dependency.peer_dependency {
+      &mut peer_dependencies
+    } else {
+      &mut dependencies
+    };
+    dependencies.insert(dependency.name.clone(), dependency.version.clone());
+  }
+
+  let mut scripts = BTreeMap::new();
+  let mut dev_dependencies = BTreeMap::new();
+  if let Some(test_runner) = &options.test_runner {
+    scripts.insert("test".to_string(), test_runner.command().to_string());
+    for dependency in output.test.dependencies.iter() {
+      dev_dependencies
+        .insert(dependency.name.clone(), dependency.version.clone());
+    }
+  }
+
+  let main_export = output.exports.get(".");
+  PackageJson {
+    name: options.name.clone(),
+    version: options.version.clone(),
+    description: options.description.clone(),
+    main: main_export.map(|e| e.require.default.clone()),
+    module: main_export.map(|e| e.import.default.clone()),
+    types: main_export.map(|e| e.import.types.clone()),
+    exports: output.exports.clone(),
+    scripts,
+    dependencies,
+    peer_dependencies,
+    dev_dependencies,
+  }
+}
+
+/// Gets the `exports` of a package.json for the entry points.
+pub(crate) fn get_exports(
+  environment: &TransformOutputEnvironment,
+) -> BTreeMap<String, PackageJsonExport> {
+  let mut exports = BTreeMap::new();
+  for (i, entry_point) in environment.entry_points.iter().enumerate() {
+    let export_name = if i == 0 {
+      ".".to_string()
+    } else {
+      get_export_name(entry_point)
+    };
+    // use the declaration file when there is one, such as for JavaScript
+    let types_path = environment
+      .declarations
+      .iter()
+      .find(|(code_path, _)| code_path == entry_point)
+      .map(|(_, types_path)| types_path.as_path())
+      .unwrap_or(entry_point);
+    let types = format!(
+      "./types/{}",
+      VirtualPath::new(with_extension(types_path, "d.ts"))
+    );
+    exports.entry(export_name).or_insert(PackageJsonExport {
+      import: PackageJsonExportTarget {
+        types: types.clone(),
+        default: get_code_path("esm", entry_point),
+      },
+      require: PackageJsonExportTarget {
+        types,
+        default: get_code_path("script", entry_point),
+      },
+    });
+  }
+  exports
+}
+
+/// Gets the name a file is exported by in the `exports` of a package.json.
+pub(crate) fn get_export_name(entry_point: &Path) -> String {
+  let path = VirtualPath::new(with_extension(entry_point, ""));
+  let is_index = matches!(
+    entry_point
+      .file_stem()
+      .map(|s| s.to_string_lossy().to_lowercase())
+      .as_deref(),
+    Some("mod" | "index")
+  );
+  match path.parent().filter(|_| is_index) {
+    Some(parent) if !parent.to_string().is_empty() => {
+      format!("./{}", parent)
+    }
+    _ => format!("./{}", path),
+  }
+}
+
+/// Validates that the text is a valid npm package name, which may be
+/// scoped (ex. `@scope/name`).
+pub fn validate_package_name(name: &str) -> Result<()> {
+  if name.is_empty() {
+    bail!("Package name must not be empty.");
+  }
+  if name.len() > 214 {
+    bail!("Package name must not be longer than 214 characters.");
+  }
+  if name.trim() != name {
+    bail!("Package name must not have leading or trailing spaces.");
+  }
+  if name.starts_with('.') || name.starts_with('_') {
+    bail!("Package name must not start with a period or underscore.");
+  }
+  if name.to_lowercase() != name {
+    bail!("Package name must be lowercase.");
+  }
+  let parts = match name.strip_prefix('@') {
+    Some(scoped_name) => match scoped_name.split_once('/') {
+      Some((scope, name)) if !scope.is_empty() && !name.is_empty() => {
+        vec![scope, name]
+      }
+      _ => bail!("Scoped package name must be in the format @scope/name."),
+    },
+    None => vec![name],
+  };
+  if parts.iter().any(|part| part.contains('/')) {
+    bail!(
+      "Package name must not contain a path. Specify the path within the package as the sub path instead."
+    );
+  }
+  if parts.iter().any(|part| {
+    !part
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'))
+  }) {
+    bail!("Package name can only contain URL-friendly characters.");
+  }
+  Ok(())
+}
+
+/// Validates that the text is a version range that npm understands, which
+/// is a semver range, a dist tag (ex. `latest`), or a protocol such as
+/// `npm:` or `file:`.
+pub fn validate_version_range(version: &str) -> Result<()> {
+  static PARTIAL_VERSION_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+      r"^(<=|>=|<|>|=|~>|~|\^)?v?(\*|x|X|\d+)(\.(\*|x|X|\d+)(\.(\*|x|X|\d+)(-[0-9A-Za-z.-]+)?(\+[0-9A-Za-z.-]+)?)?)?$",
+    )
+    .unwrap()
+  });
+  static DIST_TAG_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[A-Za-z][A-Za-z0-9._-]*$").unwrap());
+  const PROTOCOLS: [&str; 10] = [
+    "npm:",
+    "file:",
+    "link:",
+    "workspace:",
+    "git:",
+    "git+",
+    "github:",
+    "http:",
+    "https:",
+    "gitlab:",
+  ];
+
+  if version.trim().is_empty() {
+    bail!("Version must not be empty.");
+  }
+  if version.contains(|c: char| c == '\n' || c == '\r') {
+    bail!("Version must not contain line breaks.");
+  }
+  if PROTOCOLS.iter().any(|p| version.starts_with(p))
+    || DIST_TAG_RE.is_match(version)
+  {
+    return Ok(());
+  }
+  for range in version.split("||") {
+    let comparators = range.split_whitespace().collect::<Vec<_>>();
+    if comparators.is_empty() {
+      bail!("Version range must not have an empty part.");
+    }
+    for (i, comparator) in comparators.iter().enumerate() {
+      // allow hyphen ranges (ex. `1.0.0 - 2.0.0`)
+      let is_hyphen = *comparator == "-"
+        && i > 0
+        && i + 1 < comparators.len()
+        && comparators[i - 1] != "-";
+      if !is_hyphen && !PARTIAL_VERSION_RE.is_match(comparator) {
+        bail!(
+          "Version range contained invalid comparator \"{}\".",
+          comparator
+        );
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Rewrites a version according to the policy. Versions that aren't a
+/// single version or a caret or tilde range of one are returned as is,
+/// as are partial versions (ex. `^1.2`) when pinning them exactly.
+pub fn apply_version_policy(version: &str, policy: VersionPolicy) -> String {
+  static SINGLE_VERSION_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+      r"^(?:=|~|\^)?v?(\d+(?:\.\d+(\.\d+(?:-[0-9A-Za-z.-]+)?(?:\+[0-9A-Za-z.-]+)?)?)?)$",
+    )
+    .unwrap()
+  });
+
+  let captures = match SINGLE_VERSION_RE.captures(version) {
+    Some(captures) => captures,
+    None => return version.to_string(),
+  };
+  let base_version = &captures[1];
+  let is_full_version = captures.get(2).is_some();
+  match policy {
+    VersionPolicy::Exact if is_full_version => base_version.to_string(),
+    VersionPolicy::Exact | VersionPolicy::Preserve => version.to_string(),
+    VersionPolicy::Caret => format!("^{}", base_version),
+    VersionPolicy::Tilde => format!("~{}", base_version),
+  }
+}
+
+fn get_code_path(dir: &str, entry_point: &Path) -> String {
+  format!(
+    "./{}/{}",
+    dir,
+    VirtualPath::new(with_extension(entry_point, "js"))
+  )
+}
+
+#[cfg(test)]
+mod test {
+  use std::path::PathBuf;
+
+  use super::*;
+  use crate::Dependency;
+  use pretty_assertions::assert_eq;
+
+  fn dependency(name: &str, peer_dependency: bool) -> Dependency {
+    Dependency {
+      name: name.to_string(),
+      version: "^1.0.0".to_string(),
+      peer_dependency,
+    }
+  }
+
+  fn export(path: &str) -> PackageJsonExport {
+    PackageJsonExport {
+      import: PackageJsonExportTarget {
+        types: format!("./types/{}.d.ts", path),
+        default: format!("./esm/{}.js", path),
+      },
+      require: PackageJsonExportTarget {
+        types: format!("./types/{}.d.ts", path),
+        default: format!("./script/{}.js", path),
+      },
+    }
+  }
+
+  fn map(items: &[(&str, &str)]) -> BTreeMap<String, String> {
+    items
+      .iter()
+      .map(|(k, v)| (k.to_string(), v.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn validates_package_names() {
+    for name in [
+      "preact",
+      "node-fetch",
+      "lodash.merge",
+      "@deno/shim-deno",
+      "@types/node",
+      "fs",
+      "a~b_c",
+    ] {
+      assert!(validate_package_name(name).is_ok(), "name: {}", name);
+    }
+    for (name, message) in [
+      ("", "Package name must not be empty."),
+      (" name", "Package name must not have leading or trailing spaces."),
+      (".name", "Package name must not start with a period or underscore."),
+      ("_name", "Package name must not start with a period or underscore."),
+      ("Preact", "Package name must be lowercase."),
+      ("@scope", "Scoped package name must be in the format @scope/name."),
+      ("@/name", "Scoped package name must be in the format @scope/name."),
+      ("@scope/", "Scoped package name must be in the format @scope/name."),
+      ("name/sub", "Package name must not contain a path. Specify the path within the package as the sub path instead."),
+      ("@scope/name/sub", "Package name must not contain a path. Specify the path within the package as the sub path instead."),
+      ("na me", "Package name can only contain URL-friendly characters."),
+      ("name@1", "Package name can only contain URL-friendly characters."),
+    ] {
+      assert_eq!(
+        validate_package_name(name).err().map(|err| err.to_string()),
+        Some(message.to_string()),
+        "name: {}",
+        name
+      );
+    }
+    assert!(validate_package_name(&"a".repeat(215)).is_err());
+  }
+
+  #[test]
+  fn validates_version_ranges() {
+    for version in [
+      "1.0.0",
+      "^1.0.0",
+      "~1.2",
+      ">=1.0.0 <2.0.0",
+      "1.x || >=2.5.0 || 5.0.0 - 7.2.3",
+      "*",
+      "1.0.0-beta.1+build.5",
+      "v1",
+      "latest",
+      "next",
+      "npm:preact@^10.0.0",
+      "file:../package",
+      "https://example.com/package.tgz",
+    ] {
+      assert!(
+        validate_version_range(version).is_ok(),
+        "version: {}",
+        version
+      );
+    }
+    for version in [
+      "", " ", "^", "1.0.0 ||", "1.0.0 - ", "1.0.0\n", "1..0", "^1.0.0a",
+    ] {
+      assert!(
+        validate_version_range(version).is_err(),
+        "version: {}",
+        version
+      );
+    }
+  }
+
+  #[test]
+  fn applies_version_policies() {
+    for (version, exact, caret, tilde) in [
+      ("1.2.3", "1.2.3", "^1.2.3", "~1.2.3"),
+      ("^1.2.3", "1.2.3", "^1.2.3", "~1.2.3"),
+      (
+        "~1.2.3-beta.1",
+        "1.2.3-beta.1",
+        "^1.2.3-beta.1",
+        "~1.2.3-beta.1",
+      ),
+      ("=v1.2.3", "1.2.3", "^1.2.3", "~1.2.3"),
+      ("^1.2", "^1.2", "^1.2", "~1.2"),
+      (
+        ">=1.0.0 <2.0.0",
+        ">=1.0.0 <2.0.0",
+        ">=1.0.0 <2.0.0",
+        ">=1.0.0 <2.0.0",
+      ),
+      ("latest", "latest", "latest", "latest"),
+      (
+        "github:user/repo#v1.0.0",
+        "github:user/repo#v1.0.0",
+        "github:user/repo#v1.0.0",
+        "github:user/repo#v1.0.0",
+      ),
+    ] {
+      assert_eq!(
+        apply_version_policy(version, VersionPolicy::Preserve),
+        version
+      );
+      assert_eq!(apply_version_policy(version, VersionPolicy::Exact), exact);
+      assert_eq!(apply_version_policy(version, VersionPolicy::Caret), caret);
+      assert_eq!(apply_version_policy(version, VersionPolicy::Tilde), tilde);
+    }
+  }
+
+  #[test]
+  fn gets_exports() {
+    let environment = TransformOutputEnvironment {
+      entry_points: vec![
+        PathBuf::from("mod.ts"),
+        PathBuf::from("sub/mod.ts"),
+        PathBuf::from("other/index.js"),
+        PathBuf::from("a.ts"),
+        PathBuf::from("b/c.d.ts"),
+      ],
+      declarations: vec![(
+        PathBuf::from("other/index.js"),
+        PathBuf::from("other/index.d.ts"),
+      )],
+      ..Default::default()
+    };
+    assert_eq!(
+      get_exports(&environment),
+      BTreeMap::from([
+        (".".to_string(), export("mod")),
+        ("./sub".to_string(), export("sub/mod")),
+        ("./other".to_string(), export("other/index")),
+        ("./a".to_string(), export("a")),
+        ("./b/c".to_string(), export("b/c")),
+      ])
+    );
+  }
+
+  #[test]
+  fn gets_package_json() {
+    let main = TransformOutputEnvironment {
+      entry_points: vec![PathBuf::from("mod.ts"), PathBuf::from("sub/a.ts")],
+      dependencies: vec![dependency("a", false), dependency("b", true)],
+      ..Default::default()
+    };
+    let output = TransformOutput {
+      exports: get_exports(&main),
+      main,
+      test: TransformOutputEnvironment {
+        entry_points: vec![PathBuf::from("mod.test.ts")],
+        dependencies: vec![dependency("c", false)],
+        ..Default::default()
+      },
+      warnings: Vec::new(),
+      untyped_dependencies: Vec::new(),
+      duplicate_packages: Vec::new(),
+      types: Vec::new(),
+      excluded_modules: Vec::new(),
+      file_dependencies: Vec::new(),
+      local_roots: Vec::new(),
+      parse_errors: Vec::new(),
+      unshimmable_api_usages: Vec::new(),
+      provenance: Vec::new(),
+      manifest: None,
+      timings: None,
+    };
+    let mut options = PackageJsonOptions {
+      name: "package".to_string(),
+      version: "0.1.0".to_string(),
+      description: Some("Description.".to_string()),
+      test_runner: Some(TestRunner::Dnt),
+    };
+
+    let package_json = get_package_json(&output, &options);
+    assert_eq!(
+      package_json,
+      PackageJson {
+        name: "package".to_string(),
+        version: "0.1.0".to_string(),
+        description: Some("Description.".to_string()),
+        main: Some("./script/mod.js".to_string()),
+        module: Some("./esm/mod.js".to_string()),
+        types: Some("./types/mod.d.ts".to_string()),
+        exports: BTreeMap::from([
+          (".".to_string(), export("mod")),
+          ("./sub/a".to_string(), export("sub/a")),
+        ]),
+        scripts: map(&[("test", "node test_runner.js")]),
+        dependencies: map(&[("a", "^1.0.0")]),
+        peer_dependencies: map(&[("b", "^1.0.0")]),
+        dev_dependencies: map(&[("c", "^1.0.0")]),
+      }
+    );
+
+    options.test_runner = None;
+    let package_json = get_package_json(&output, &options);
+    assert!(package_json.scripts.is_empty());
+    assert!(package_json.dev_dependencies.is_empty());
+  }
+}
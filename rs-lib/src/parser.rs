@@ -1,17 +1,44 @@
 // Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
 
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::sync::Mutex;
 
 use anyhow::Result;
 use deno_ast::parse_module;
+use deno_ast::view::*;
 use deno_ast::Diagnostic;
 use deno_ast::MediaType;
 use deno_ast::ModuleSpecifier;
 use deno_ast::ParseParams;
 use deno_ast::ParsedSource;
+use deno_ast::RootNode;
+use deno_ast::SourcePos;
+use deno_ast::SourceRange;
+use deno_ast::SourceRanged;
+use deno_ast::SourceRangedForSpanned;
 use deno_ast::SourceTextInfo;
+use deno_ast::SourceTextInfoProvider;
+use deno_graph::DependencyDescriptor;
+use deno_graph::DependencyKind;
+use deno_graph::ImportAssertion;
+use deno_graph::ImportAssertions;
+use deno_graph::ModuleAnalyzer;
+use deno_graph::ModuleInfo;
 use deno_graph::ModuleParser;
+use deno_graph::Position;
+use deno_graph::PositionRange;
+use deno_graph::SpecifierWithRange;
+use deno_graph::TypeScriptReference;
 
+use crate::analyze::get_module_worker_specifier;
+use crate::visitors::parse_dnt_map_directive;
+use crate::visitors::TS_SELF_TYPES_RE;
+use crate::visitors::TS_TYPES_RE;
+use crate::PackageMappedSpecifier;
+
+/// Parses modules with scope analysis, which is necessary for transforming.
 #[derive(Default)]
 pub struct ScopeAnalysisParser;
 
@@ -38,3 +65,404 @@ impl ModuleParser for ScopeAnalysisParser {
     })
   }
 }
+
+/// Parses modules with scope analysis like `ScopeAnalysisParser`, but
+/// stores the errors and parses an empty module in place of modules
+/// that fail to parse so the rest of the graph can still be built.
+pub struct RecoveringParser {
+  errors: Arc<Mutex<Vec<(ModuleSpecifier, Diagnostic)>>>,
+}
+
+impl RecoveringParser {
+  pub fn new(errors: Arc<Mutex<Vec<(ModuleSpecifier, Diagnostic)>>>) -> Self {
+    RecoveringParser { errors }
+  }
+}
+
+impl ModuleParser for RecoveringParser {
+  fn parse_module(
+    &self,
+    specifier: &ModuleSpecifier,
+    source: Arc<str>,
+    media_type: MediaType,
+  ) -> Result<ParsedSource, Diagnostic> {
+    let parse = |text_info: SourceTextInfo| {
+      parse_module(ParseParams {
+        specifier: specifier.to_string(),
+        text_info,
+        media_type,
+        capture_tokens: true,
+        scope_analysis: true,
+        maybe_syntax: None,
+      })
+    };
+    match parse(SourceTextInfo::new(source)) {
+      Ok(parsed_source) => Ok(parsed_source),
+      Err(diagnostic) => {
+        self
+          .errors
+          .lock()
+          .unwrap()
+          .push((specifier.clone(), diagnostic));
+        parse(SourceTextInfo::from_string(String::new()))
+      }
+    }
+  }
+}
+
+/// Import assertion types of files that are imported as their text.
+pub const ASSET_ASSERTION_TYPES: [&str; 2] = ["css", "text"];
+
+/// An import of a file with the `type` attribute of an asset handler.
+pub struct HandledAssetImport {
+  pub referrer: ModuleSpecifier,
+  /// The module specifier as written in the referrer.
+  pub value: String,
+  pub attribute_type: String,
+}
+
+/// Analyzes modules for the module graph while treating the imports of
+/// CSS and text files, and of the types with an asset handler, as JSON
+/// imports, which is what the graph supports.
+pub struct AssetImportAnalyzer<'a> {
+  inner: &'a dyn ModuleAnalyzer,
+  asset_types: HashSet<String>,
+  handler_types: &'a HashSet<String>,
+  handled_imports: Arc<Mutex<Vec<HandledAssetImport>>>,
+}
+
+impl<'a> AssetImportAnalyzer<'a> {
+  /// The CSS and text types are only treated as assets when `asset_imports`
+  /// is enabled. The imports of the handler types are stored in
+  /// `handled_imports` so the files can be given to their handler.
+  pub fn new(
+    inner: &'a dyn ModuleAnalyzer,
+    asset_imports: bool,
+    handler_types: &'a HashSet<String>,
+    handled_imports: Arc<Mutex<Vec<HandledAssetImport>>>,
+  ) -> Self {
+    let mut asset_types = handler_types.clone();
+    if asset_imports {
+      asset_types.extend(ASSET_ASSERTION_TYPES.iter().map(|t| t.to_string()));
+    }
+    AssetImportAnalyzer {
+      inner,
+      asset_types,
+      handler_types,
+      handled_imports,
+    }
+  }
+}
+
+impl<'a> ModuleAnalyzer for AssetImportAnalyzer<'a> {
+  fn analyze(
+    &self,
+    specifier: &ModuleSpecifier,
+    source: Arc<str>,
+    media_type: MediaType,
+  ) -> Result<ModuleInfo, Diagnostic> {
+    let mut module_info = self.inner.analyze(specifier, source, media_type)?;
+    for dependency in module_info.dependencies.iter_mut() {
+      if let ImportAssertions::Known(assertions) =
+        &mut dependency.import_assertions
+      {
+        if let Some(ImportAssertion::Known(kind)) = assertions.get_mut("type") {
+          if self.handler_types.contains(kind.as_str()) {
+            self
+              .handled_imports
+              .lock()
+              .unwrap()
+              .push(HandledAssetImport {
+                referrer: specifier.clone(),
+                value: dependency.specifier.clone(),
+                attribute_type: kind.clone(),
+              });
+          }
+          if self.asset_types.contains(kind.as_str()) {
+            *kind = "json".to_string();
+          }
+        }
+      }
+    }
+    Ok(module_info)
+  }
+}
+
+/// Analyzes modules for the module graph while also treating the modules
+/// of module workers (ex. `new Worker(new URL("./worker.ts", import.meta.url),
+/// { type: "module" })`) as dynamic imports so they're included in the graph.
+pub struct WorkerAnalyzer<'a> {
+  inner: &'a dyn ModuleAnalyzer,
+}
+
+impl<'a> WorkerAnalyzer<'a> {
+  pub fn new(inner: &'a dyn ModuleAnalyzer) -> Self {
+    WorkerAnalyzer { inner }
+  }
+}
+
+impl<'a> ModuleAnalyzer for WorkerAnalyzer<'a> {
+  fn analyze(
+    &self,
+    specifier: &ModuleSpecifier,
+    source: Arc<str>,
+    media_type: MediaType,
+  ) -> Result<ModuleInfo, Diagnostic> {
+    let mut module_info =
+      self.inner.analyze(specifier, source.clone(), media_type)?;
+    // avoid parsing the modules that can't have a worker again
+    if !source.contains("Worker") {
+      return Ok(module_info);
+    }
+    let parsed_source = parse_module(ParseParams {
+      specifier: specifier.to_string(),
+      text_info: SourceTextInfo::new(source),
+      media_type,
+      capture_tokens: false,
+      scope_analysis: false,
+      maybe_syntax: None,
+    });
+    // parse errors are surfaced by the inner analyzer
+    if let Ok(parsed_source) = parsed_source {
+      parsed_source.with_view(|program| {
+        add_worker_dependencies(
+          program.as_node(),
+          program,
+          &mut module_info.dependencies,
+        );
+      });
+    }
+    Ok(module_info)
+  }
+}
+
+fn add_worker_dependencies(
+  node: Node,
+  program: &Program,
+  dependencies: &mut Vec<DependencyDescriptor>,
+) {
+  if let Node::NewExpr(new_expr) = node {
+    if let Some(str) = get_module_worker_specifier(new_expr) {
+      let text_info = program.text_info();
+      dependencies.push(DependencyDescriptor {
+        kind: DependencyKind::Import,
+        is_dynamic: true,
+        leading_comments: Vec::new(),
+        range: get_position_range(text_info, new_expr.range()),
+        specifier: str.value().to_string(),
+        specifier_range: get_position_range(text_info, str.range()),
+        import_assertions: ImportAssertions::None,
+      });
+    }
+  }
+  for child in node.children() {
+    add_worker_dependencies(child, program, dependencies);
+  }
+}
+
+fn get_position_range(
+  text_info: &SourceTextInfo,
+  range: SourceRange,
+) -> PositionRange {
+  let get_position = |pos: SourcePos| {
+    let line_and_column = text_info.line_and_column_index(pos);
+    Position {
+      line: line_and_column.line_index,
+      character: line_and_column.column_index,
+    }
+  };
+  PositionRange {
+    start: get_position(range.start),
+    end: get_position(range.end),
+  }
+}
+
+/// Analyzes modules for the module graph while supporting the `@ts-types`
+/// and `@ts-self-types` pragmas of newer versions of Deno, which the graph
+/// doesn't know about. The `@ts-types` pragma is treated as `@deno-types`
+/// and the `@ts-self-types` pragma as a types reference.
+pub struct TypesPragmaAnalyzer<'a> {
+  inner: &'a dyn ModuleAnalyzer,
+}
+
+impl<'a> TypesPragmaAnalyzer<'a> {
+  pub fn new(inner: &'a dyn ModuleAnalyzer) -> Self {
+    TypesPragmaAnalyzer { inner }
+  }
+}
+
+impl<'a> ModuleAnalyzer for TypesPragmaAnalyzer<'a> {
+  fn analyze(
+    &self,
+    specifier: &ModuleSpecifier,
+    source: Arc<str>,
+    media_type: MediaType,
+  ) -> Result<ModuleInfo, Diagnostic> {
+    let mut module_info =
+      self.inner.analyze(specifier, source.clone(), media_type)?;
+    for dependency in module_info.dependencies.iter_mut() {
+      for comment in dependency.leading_comments.iter_mut() {
+        if TS_TYPES_RE.is_match(&comment.text) {
+          comment.text = TS_TYPES_RE
+            .replace(&comment.text, "${1}@deno-types${2}")
+            .to_string();
+        }
+      }
+    }
+    // avoid parsing the modules that don't have the pragma again
+    if !source.contains("@ts-self-types") {
+      return Ok(module_info);
+    }
+    let parsed_source = parse_module(ParseParams {
+      specifier: specifier.to_string(),
+      text_info: SourceTextInfo::new(source),
+      media_type,
+      capture_tokens: false,
+      scope_analysis: false,
+      maybe_syntax: None,
+    });
+    // parse errors are surfaced by the inner analyzer
+    if let Ok(parsed_source) = parsed_source {
+      parsed_source.with_view(|program| {
+        let text_info = program.text_info();
+        for comment in program.leading_comments_fast(program) {
+          if let Some(captures) = TS_SELF_TYPES_RE.captures(&comment.text) {
+            let value = captures.get(1).or_else(|| captures.get(2)).unwrap();
+            // the comment text does not include the leading `//` or `/*`
+            let text_start = comment.start() + 2;
+            module_info.ts_references.push(TypeScriptReference::Types(
+              SpecifierWithRange {
+                text: value.as_str().to_string(),
+                range: get_position_range(
+                  text_info,
+                  SourceRange::new(
+                    text_start + value.start(),
+                    text_start + value.end(),
+                  ),
+                ),
+              },
+            ));
+          }
+        }
+      });
+    }
+    Ok(module_info)
+  }
+}
+
+/// Specifiers mapped to a package by `@dnt-map` directives along with the
+/// problems found with the directives.
+#[derive(Default)]
+pub struct DirectiveMappings {
+  pub packages: HashMap<ModuleSpecifier, PackageMappedSpecifier>,
+  /// Messages of the invalid or conflicting directives by the specifier
+  /// of the module they're in.
+  pub errors: Vec<(ModuleSpecifier, String)>,
+}
+
+/// Analyzes modules for the module graph while collecting the packages
+/// that the imports are mapped to with `@dnt-map` directives
+/// (ex. `// @dnt-map: npm:chalk@^5`).
+///
+/// Modules are analyzed before their dependencies are loaded, so the
+/// loader can use these mappings the same way as the specifier mappings
+/// of the options.
+pub struct DirectiveMappingAnalyzer<'a> {
+  inner: &'a dyn ModuleAnalyzer,
+  import_map: Option<&'a import_map::ImportMap>,
+  mappings: Arc<Mutex<DirectiveMappings>>,
+}
+
+impl<'a> DirectiveMappingAnalyzer<'a> {
+  pub fn new(
+    inner: &'a dyn ModuleAnalyzer,
+    import_map: Option<&'a import_map::ImportMap>,
+    mappings: Arc<Mutex<DirectiveMappings>>,
+  ) -> Self {
+    DirectiveMappingAnalyzer {
+      inner,
+      import_map,
+      mappings,
+    }
+  }
+
+  fn resolve(
+    &self,
+    specifier: &str,
+    referrer: &ModuleSpecifier,
+  ) -> Option<ModuleSpecifier> {
+    match self.import_map {
+      Some(import_map) => import_map.resolve(specifier, referrer).ok(),
+      None => deno_graph::resolve_import(specifier, referrer).ok(),
+    }
+  }
+}
+
+impl<'a> ModuleAnalyzer for DirectiveMappingAnalyzer<'a> {
+  fn analyze(
+    &self,
+    specifier: &ModuleSpecifier,
+    source: Arc<str>,
+    media_type: MediaType,
+  ) -> Result<ModuleInfo, Diagnostic> {
+    let module_info = self.inner.analyze(specifier, source, media_type)?;
+    for dependency in module_info.dependencies.iter() {
+      for comment in dependency.leading_comments.iter() {
+        let result = match parse_dnt_map_directive(&comment.text) {
+          Some(result) => result,
+          None => continue,
+        };
+        let position = format!(
+          "{}:{}:{}",
+          specifier,
+          comment.range.start.line + 1,
+          comment.range.start.character + 1
+        );
+        let mut mappings = self.mappings.lock().unwrap();
+        let package = match result {
+          Ok(package) => package,
+          Err(message) => {
+            mappings.errors.push((
+              specifier.clone(),
+              format!("{}\n    at {}", message, position),
+            ));
+            continue;
+          }
+        };
+        let resolved = match self.resolve(&dependency.specifier, specifier) {
+          Some(resolved) => resolved,
+          // the resolution error is surfaced by the graph
+          None => continue,
+        };
+        match mappings.packages.get(&resolved) {
+          Some(existing) if *existing != package => {
+            let message = format!(
+              "The @dnt-map directive for {} maps it to {}, but another directive maps it to {}.\n    at {}",
+              resolved,
+              get_package_display_text(&package),
+              get_package_display_text(existing),
+              position,
+            );
+            mappings.errors.push((specifier.clone(), message));
+          }
+          Some(_) => {}
+          None => {
+            mappings.packages.insert(resolved, package);
+          }
+        }
+      }
+    }
+    Ok(module_info)
+  }
+}
+
+fn get_package_display_text(package: &PackageMappedSpecifier) -> String {
+  let mut text = package.name.clone();
+  if let Some(version) = &package.version {
+    text.push_str(&format!("@{}", version));
+  }
+  if let Some(sub_path) = &package.sub_path {
+    text.push_str(&format!("/{}", sub_path));
+  }
+  text
+}
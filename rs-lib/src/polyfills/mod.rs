@@ -13,7 +13,7 @@ mod error_cause;
 mod object_has_own;
 mod string_replace_all;
 
-pub trait Polyfill {
+pub trait Polyfill: Send + Sync {
   fn use_for_target(&self, target: ScriptTarget) -> bool;
   fn visit_node(&self, node: Node, context: &PolyfillVisitContext<'_>) -> bool;
   fn get_file_text(&self) -> &'static str;
@@ -88,7 +88,8 @@ impl PolyfillTester {
       let mut searching_polyfills = vec![(self.create_polyfill)()];
       let mut found_polyfills = Vec::new();
       let unresolved_context = parsed_source.unresolved_context();
-      let top_level_decls = get_top_level_decls(&program, unresolved_context);
+      let top_level_decls =
+        get_top_level_decls(&program, parsed_source.top_level_context());
       fill_polyfills(&mut FillPolyfillsParams {
         program: &program,
         unresolved_context,
@@ -0,0 +1,340 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use deno_ast::ModuleSpecifier;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::licenses::get_license_banner;
+use crate::loader::Loader;
+use crate::ModuleProvenance;
+use crate::PackageMappedSpecifier;
+
+const LICENSE_FILE_NAMES: [&str; 3] = ["LICENSE", "LICENSE.md", "LICENSE.txt"];
+
+static SPDX_IDENTIFIER_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r"(?m)SPDX-License-Identifier:\s*([A-Za-z0-9.+\-() ]+?)\s*(\*/|$)")
+    .unwrap()
+});
+
+/// Gets the provenance of a remote module that was copied to the output
+/// or mapped to a package.
+pub fn get_module_provenance(
+  specifier: &ModuleSpecifier,
+  mapped_package: Option<&PackageMappedSpecifier>,
+  source: Option<&str>,
+  license_files: &HashMap<ModuleSpecifier, String>,
+) -> ModuleProvenance {
+  let mut license = None;
+  let mut license_file = None;
+  if let Some(banner) = source.and_then(get_license_banner) {
+    license = detect_license_identifier(&banner);
+  }
+  if license.is_none() {
+    for candidate in get_license_file_candidates(specifier) {
+      if let Some(text) = license_files.get(&candidate) {
+        license = detect_license_identifier(text);
+        license_file = Some(candidate);
+        break;
+      }
+    }
+  }
+  ModuleProvenance {
+    specifier: specifier.clone(),
+    mapped_package: mapped_package.map(|p| p.name.clone()),
+    version: mapped_package
+      .and_then(|p| p.version.clone())
+      .or_else(|| get_version(specifier)),
+    license,
+    license_file,
+  }
+}
+
+/// Loads the license files adjacent to the remote modules, keyed by the
+/// specifier of the license file. Files that couldn't be loaded are left
+/// out since a missing license file isn't an error.
+pub async fn load_license_files<'a>(
+  loader: &dyn Loader,
+  specifiers: impl Iterator<Item = &'a ModuleSpecifier>,
+) -> HashMap<ModuleSpecifier, String> {
+  let candidates = specifiers
+    .filter(|s| s.scheme() != "file")
+    .flat_map(get_license_file_candidates)
+    .collect::<BTreeSet<_>>();
+  let responses = futures::future::join_all(
+    candidates
+      .iter()
+      .map(|candidate| loader.load(candidate.clone())),
+  )
+  .await;
+  candidates
+    .into_iter()
+    .zip(responses)
+    .filter_map(|(candidate, response)| match response {
+      Ok(Some(response)) => Some((candidate, response.content)),
+      Ok(None) | Err(_) => None,
+    })
+    .collect()
+}
+
+/// Gets the license files that may apply to a remote module in order of
+/// preference, which are the ones at the root of its package when the
+/// package can be determined from the url and otherwise the ones in the
+/// module's directory.
+fn get_license_file_candidates(
+  specifier: &ModuleSpecifier,
+) -> Vec<ModuleSpecifier> {
+  let directory = match get_package_root(specifier) {
+    Some(root) => root,
+    None => match specifier.join("./") {
+      Ok(directory) => directory,
+      Err(_) => return Vec::new(),
+    },
+  };
+  LICENSE_FILE_NAMES
+    .iter()
+    .filter_map(|name| directory.join(name).ok())
+    .collect()
+}
+
+/// Gets the root directory of the package a module is in, which is the
+/// directory with the version in its name (ex. `https://deno.land/std@0.150.0/`)
+/// or the git ref directory of a GitHub url.
+fn get_package_root(specifier: &ModuleSpecifier) -> Option<ModuleSpecifier> {
+  let (index, _) = find_version_segment(specifier)?;
+  let segments = specifier.path_segments()?.collect::<Vec<_>>();
+  let mut root = specifier.clone();
+  root.set_query(None);
+  root.set_fragment(None);
+  root.set_path(&format!("/{}/", segments[..=index].join("/")));
+  Some(root)
+}
+
+/// Gets the name and version of the package a remote module is in from
+/// its url, where the name is the url of the package without the version
+/// (ex. `https://deno.land/x/oak` for `https://deno.land/x/oak@v10.0.0/mod.ts`).
+pub fn get_package_name_and_version(
+  specifier: &ModuleSpecifier,
+) -> Option<(String, String)> {
+  let (index, version) = find_version_segment(specifier)?;
+  let segments = specifier.path_segments()?.collect::<Vec<_>>();
+  let mut name_segments = segments[..index].to_vec();
+  // the git ref of a GitHub url is the whole segment
+  if let Some(name) = segments[index].strip_suffix(&format!("@{}", version)) {
+    name_segments.push(name);
+  }
+  let name = format!(
+    "{}://{}/{}",
+    specifier.scheme(),
+    specifier.host_str()?,
+    name_segments.join("/")
+  );
+  Some((name, version))
+}
+
+/// Gets the version of the package a remote module is in from its url.
+fn get_version(specifier: &ModuleSpecifier) -> Option<String> {
+  find_version_segment(specifier).map(|(_, version)| version)
+}
+
+/// Finds the path segment with the version of the package, which is
+/// either a `name@version` segment (ex. `std@0.150.0`) or the git ref of
+/// a GitHub url (ex. `owner/repo/v1.0.0/mod.ts`).
+pub fn find_version_segment(
+  specifier: &ModuleSpecifier,
+) -> Option<(usize, String)> {
+  let segments = specifier.path_segments()?.collect::<Vec<_>>();
+  if specifier.host_str() == Some("raw.githubusercontent.com") {
+    return if segments.len() > 3 {
+      Some((2, segments[2].to_string()))
+    } else {
+      None
+    };
+  }
+  // the last segment is the file name
+  let directories = &segments[..segments.len().saturating_sub(1)];
+  directories.iter().enumerate().find_map(|(i, segment)| {
+    // scoped packages (ex. `@scope/name@1.0.0`) start with an `@`
+    let index = segment.rfind('@')?;
+    if index > 0 && index < segment.len() - 1 {
+      Some((i, segment[index + 1..].to_string()))
+    } else {
+      None
+    }
+  })
+}
+
+/// Detects the SPDX identifier of a license from its text, which is
+/// either from an `SPDX-License-Identifier` comment or from the wording
+/// of the common licenses.
+fn detect_license_identifier(text: &str) -> Option<String> {
+  if let Some(captures) = SPDX_IDENTIFIER_RE.captures(text) {
+    return Some(captures[1].trim().to_string());
+  }
+
+  let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+  let lower_text = text.to_lowercase();
+  let contains = |value: &str| lower_text.contains(&value.to_lowercase());
+  let identifier = if contains("Apache License") && contains("Version 2.0") {
+    "Apache-2.0"
+  } else if contains("GNU Lesser General Public License") {
+    if contains("Version 3") {
+      "LGPL-3.0"
+    } else {
+      "LGPL-2.1"
+    }
+  } else if contains("GNU Affero General Public License") {
+    "AGPL-3.0"
+  } else if contains("GNU General Public License") {
+    if contains("Version 3") {
+      "GPL-3.0"
+    } else {
+      "GPL-2.0"
+    }
+  } else if contains("Mozilla Public License") && contains("2.0") {
+    "MPL-2.0"
+  } else if contains("Redistribution and use in source and binary forms") {
+    if contains("Neither the name") {
+      "BSD-3-Clause"
+    } else {
+      "BSD-2-Clause"
+    }
+  } else if contains(
+    "Permission to use, copy, modify, and/or distribute this software for any purpose",
+  ) {
+    "ISC"
+  } else if contains("unencumbered software released into the public domain")
+  {
+    "Unlicense"
+  } else if contains("Permission is hereby granted, free of charge")
+    || contains("MIT License")
+    || contains("MIT license")
+  {
+    "MIT"
+  } else {
+    return None;
+  };
+  Some(identifier.to_string())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn get_package_root_text(specifier: &str) -> Option<String> {
+    get_package_root(&ModuleSpecifier::parse(specifier).unwrap())
+      .map(|s| s.to_string())
+  }
+
+  fn get_version_text(specifier: &str) -> Option<String> {
+    get_version(&ModuleSpecifier::parse(specifier).unwrap())
+  }
+
+  #[test]
+  fn test_get_package_root() {
+    assert_eq!(
+      get_package_root_text("https://deno.land/std@0.150.0/fs/mod.ts"),
+      Some("https://deno.land/std@0.150.0/".to_string()),
+    );
+    assert_eq!(
+      get_package_root_text("https://deno.land/x/oak@v10.0.0/mod.ts?a=b"),
+      Some("https://deno.land/x/oak@v10.0.0/".to_string()),
+    );
+    assert_eq!(
+      get_package_root_text("https://esm.sh/@scope/name@1.2.3/mod.js"),
+      Some("https://esm.sh/@scope/name@1.2.3/".to_string()),
+    );
+    assert_eq!(
+      get_package_root_text(
+        "https://raw.githubusercontent.com/owner/repo/v1.0.0/src/mod.ts"
+      ),
+      Some("https://raw.githubusercontent.com/owner/repo/v1.0.0/".to_string()),
+    );
+    assert_eq!(get_package_root_text("https://localhost/mod.ts"), None);
+    assert_eq!(get_package_root_text("https://localhost/a@b.ts"), None);
+  }
+
+  #[test]
+  fn test_get_version() {
+    assert_eq!(
+      get_version_text("https://deno.land/std@0.150.0/fs/mod.ts"),
+      Some("0.150.0".to_string()),
+    );
+    assert_eq!(
+      get_version_text("https://esm.sh/@scope/name@1.2.3/mod.js"),
+      Some("1.2.3".to_string()),
+    );
+    assert_eq!(
+      get_version_text(
+        "https://raw.githubusercontent.com/owner/repo/main/mod.ts"
+      ),
+      Some("main".to_string()),
+    );
+    assert_eq!(get_version_text("https://esm.sh/@scope/name/mod.js"), None);
+  }
+
+  #[test]
+  fn test_get_package_name_and_version() {
+    let get = |specifier: &str| {
+      get_package_name_and_version(&ModuleSpecifier::parse(specifier).unwrap())
+    };
+    assert_eq!(
+      get("https://deno.land/std@0.150.0/fs/mod.ts"),
+      Some(("https://deno.land/std".to_string(), "0.150.0".to_string())),
+    );
+    assert_eq!(
+      get("https://esm.sh/@scope/name@1.2.3/mod.js"),
+      Some((
+        "https://esm.sh/@scope/name".to_string(),
+        "1.2.3".to_string()
+      )),
+    );
+    assert_eq!(
+      get("https://raw.githubusercontent.com/owner/repo/v1.0.0/src/mod.ts"),
+      Some((
+        "https://raw.githubusercontent.com/owner/repo".to_string(),
+        "v1.0.0".to_string()
+      )),
+    );
+    assert_eq!(get("https://localhost/mod.ts"), None);
+  }
+
+  #[test]
+  fn test_detect_license_identifier() {
+    assert_eq!(
+      detect_license_identifier(
+        "// SPDX-License-Identifier: Apache-2.0 OR MIT"
+      ),
+      Some("Apache-2.0 OR MIT".to_string()),
+    );
+    assert_eq!(
+      detect_license_identifier("/* SPDX-License-Identifier: MIT */"),
+      Some("MIT".to_string()),
+    );
+    assert_eq!(
+      detect_license_identifier(concat!(
+        "MIT License\n\nCopyright (c) 2022 Someone\n\n",
+        "Permission is hereby granted, free of charge, to any person",
+      )),
+      Some("MIT".to_string()),
+    );
+    assert_eq!(
+      detect_license_identifier(concat!(
+        "Redistribution and use in source and binary forms, with or\n",
+        "without modification, are permitted ...\n",
+        "3. Neither the name of the copyright holder ...",
+      )),
+      Some("BSD-3-Clause".to_string()),
+    );
+    assert_eq!(
+      detect_license_identifier("Apache License\n  Version 2.0, January 2004"),
+      Some("Apache-2.0".to_string()),
+    );
+    assert_eq!(
+      detect_license_identifier("Copyright 2022 Someone. All rights reserved."),
+      None,
+    );
+  }
+}
@@ -0,0 +1,162 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use deno_ast::ModuleSpecifier;
+
+use crate::graph::ModuleGraph;
+use crate::RemotePolicy;
+use crate::TransformError;
+
+/// The origins of a remote policy, which decide what remote modules the
+/// loader may load.
+pub struct OriginPolicy {
+  allowed: HashSet<String>,
+  denied: HashSet<String>,
+}
+
+impl OriginPolicy {
+  pub fn new(policy: &RemotePolicy) -> Result<Self, TransformError> {
+    Ok(Self {
+      allowed: parse_origins(&policy.allowed_origins)?,
+      denied: parse_origins(&policy.denied_origins)?,
+    })
+  }
+
+  /// Gets if a module may be loaded, which is always the case for the
+  /// modules that aren't remote.
+  pub fn allows(&self, specifier: &ModuleSpecifier) -> bool {
+    if !matches!(specifier.scheme(), "http" | "https") {
+      return true;
+    }
+    let origin = specifier.origin().ascii_serialization();
+    !self.denied.contains(&origin)
+      && (self.allowed.is_empty() || self.allowed.contains(&origin))
+  }
+}
+
+fn parse_origins(
+  origins: &[String],
+) -> Result<HashSet<String>, TransformError> {
+  origins
+    .iter()
+    .map(|origin| match ModuleSpecifier::parse(origin) {
+      Ok(url) if url.origin().is_tuple() => {
+        Ok(url.origin().ascii_serialization())
+      }
+      _ => Err(TransformError::InvalidOptions {
+        message: format!(
+          "Invalid origin \"{}\" in the remote policy. Expected an origin such as \"https://deno.land\".",
+          origin
+        ),
+      }),
+    })
+    .collect()
+}
+
+/// Gets the errors for the remote modules that weren't loaded because the
+/// remote policy doesn't allow their origin.
+pub fn get_blocked_module_errors(
+  module_graph: &ModuleGraph,
+  roots: &[ModuleSpecifier],
+  blocked: &BTreeSet<ModuleSpecifier>,
+) -> Vec<TransformError> {
+  blocked
+    .iter()
+    .map(|specifier| {
+      let referrer_chain = module_graph.get_referrer_chain(roots, specifier);
+      TransformError::RemotePolicy {
+        message: format!(
+          "The remote policy doesn't allow loading {} from {}.\n  Referrer chain: {}",
+          specifier,
+          specifier.origin().ascii_serialization(),
+          format_chain(&referrer_chain),
+        ),
+        specifier: specifier.clone(),
+        referrer_chain,
+      }
+    })
+    .collect()
+}
+
+/// Gets the errors for the remote modules that can only be reached through
+/// more remote modules than the maximum depth of the remote policy. Only
+/// the first modules past the maximum are reported.
+pub fn get_remote_depth_errors(
+  module_graph: &ModuleGraph,
+  roots: &[ModuleSpecifier],
+  remote: &[ModuleSpecifier],
+  max_depth: usize,
+) -> Vec<TransformError> {
+  let remote = remote.iter().collect::<HashSet<_>>();
+  // the fewest remote modules each module can be reached through
+  let mut depths: HashMap<ModuleSpecifier, usize> = HashMap::new();
+  let mut referrers: HashMap<ModuleSpecifier, ModuleSpecifier> = HashMap::new();
+  let mut pending = VecDeque::new();
+  for root in roots {
+    let root = module_graph.resolve(root);
+    let depth = usize::from(remote.contains(&root));
+    if depths.get(&root).map(|d| depth < *d).unwrap_or(true) {
+      depths.insert(root.clone(), depth);
+      pending.push_back(root);
+    }
+  }
+  while let Some(current) = pending.pop_front() {
+    let depth = depths[&current];
+    for child in module_graph.get_dependencies(&current) {
+      let child_depth = depth + usize::from(remote.contains(&child));
+      if depths.get(&child).map(|d| child_depth < *d).unwrap_or(true) {
+        depths.insert(child.clone(), child_depth);
+        referrers.insert(child.clone(), current.clone());
+        // local modules are at the same depth, so visit them first
+        if child_depth == depth {
+          pending.push_front(child);
+        } else {
+          pending.push_back(child);
+        }
+      }
+    }
+  }
+
+  let mut specifiers = depths
+    .iter()
+    .filter(|(_, depth)| **depth == max_depth + 1)
+    .map(|(specifier, _)| specifier)
+    .collect::<Vec<_>>();
+  specifiers.sort();
+  specifiers
+    .into_iter()
+    .map(|specifier| {
+      let mut referrer_chain = vec![specifier.clone()];
+      let mut current = specifier;
+      while let Some(referrer) = referrers.get(current) {
+        referrer_chain.push(referrer.clone());
+        current = referrer;
+      }
+      referrer_chain.reverse();
+      TransformError::RemotePolicy {
+        message: format!(
+          "{} is imported through {} remote modules, which is more than the maximum depth of {} in the remote policy.\n  Referrer chain: {}",
+          specifier,
+          max_depth + 1,
+          max_depth,
+          format_chain(&referrer_chain),
+        ),
+        specifier: specifier.clone(),
+        referrer_chain,
+      }
+    })
+    .collect()
+}
+
+fn format_chain(chain: &[ModuleSpecifier]) -> String {
+  chain
+    .iter()
+    .map(|s| s.as_str())
+    .collect::<Vec<_>>()
+    .join(" -> ")
+}
@@ -0,0 +1,212 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use deno_ast::ModuleSpecifier;
+use serde::Deserialize;
+
+use crate::CommentDirectiveOptions;
+use crate::CommonJsModules;
+use crate::DenoFeatureDetection;
+use crate::Dependency;
+use crate::ImportAttributeOptions;
+use crate::JsxOptions;
+use crate::Loader;
+use crate::LocalRoot;
+use crate::MappedSpecifier;
+use crate::NodeGlobalShims;
+use crate::QualifiedGlobalAccess;
+use crate::RelativeSpecifierExtension;
+use crate::RemotePolicy;
+use crate::ScriptTarget;
+use crate::ServerShim;
+use crate::Shim;
+use crate::ShimImportStyle;
+use crate::SizeBudget;
+use crate::TestMainImports;
+use crate::TransformOptions;
+use crate::VersionPolicy;
+
+/// Transform options that can be deserialized, which is how the options
+/// are provided from JavaScript by the Wasm and Node bindings.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SerializableTransformOptions {
+  pub entry_points: Vec<String>,
+  #[serde(default)]
+  pub test_entry_points: Vec<String>,
+  #[serde(default)]
+  pub shims: Vec<Shim>,
+  #[serde(default)]
+  pub test_shims: Vec<Shim>,
+  #[serde(default)]
+  pub mappings: HashMap<ModuleSpecifier, MappedSpecifier>,
+  #[serde(default)]
+  pub declaration_mappings: HashMap<ModuleSpecifier, ModuleSpecifier>,
+  pub target: ScriptTarget,
+  pub import_map: Option<ModuleSpecifier>,
+  #[serde(default)]
+  pub jsx: Option<JsxOptions>,
+  #[serde(default)]
+  pub error_on_cycles: bool,
+  #[serde(default)]
+  pub error_on_duplicate_packages: bool,
+  #[serde(default)]
+  pub continue_on_parse_error: bool,
+  #[serde(default)]
+  pub normalize_output_text: bool,
+  #[serde(default)]
+  pub github_dependencies: bool,
+  #[serde(default)]
+  pub remote_policy: Option<RemotePolicy>,
+  #[serde(default)]
+  pub version_policy: VersionPolicy,
+  #[serde(default)]
+  pub asset_imports: bool,
+  #[serde(default)]
+  pub directory_index_files: Vec<String>,
+  #[serde(default)]
+  pub local_roots: Vec<LocalRoot>,
+  #[serde(default)]
+  pub node_global_shims: NodeGlobalShims,
+  #[serde(default)]
+  pub dom_types_package: Option<Dependency>,
+  #[serde(default)]
+  pub inject_dirname: bool,
+  #[serde(default)]
+  pub commonjs_modules: CommonJsModules,
+  pub server_shim: Option<ServerShim>,
+  #[serde(default)]
+  pub comment_directives: CommentDirectiveOptions,
+  #[serde(default)]
+  pub import_attributes: ImportAttributeOptions,
+  pub inline_remote_module_max_size: Option<usize>,
+  #[serde(default)]
+  pub bundle: bool,
+  #[serde(default)]
+  pub flatten_barrels: bool,
+  #[serde(default)]
+  pub minify: bool,
+  #[serde(default)]
+  pub entry_point_exports: bool,
+  #[serde(default)]
+  pub declarations_only: bool,
+  #[serde(default)]
+  pub third_party_licenses: bool,
+  #[serde(default)]
+  pub provenance: bool,
+  #[serde(default)]
+  pub define: HashMap<String, String>,
+  #[serde(default)]
+  pub deno_env_to_process_env: bool,
+  #[serde(default)]
+  pub translate_deno_apis: bool,
+  #[serde(default)]
+  pub shim_import_style: ShimImportStyle,
+  #[serde(default)]
+  pub deno_feature_detection: DenoFeatureDetection,
+  #[serde(default)]
+  pub qualified_global_access: QualifiedGlobalAccess,
+  #[serde(default)]
+  pub relative_specifier_extension: RelativeSpecifierExtension,
+  #[serde(default)]
+  pub test_relative_specifier_extension: Option<RelativeSpecifierExtension>,
+  #[serde(default)]
+  pub test_main_imports: TestMainImports,
+  #[serde(default)]
+  pub annotate_rewrites: bool,
+  #[serde(default)]
+  pub hash_remote_file_names: bool,
+  #[serde(default)]
+  pub typescript_paths: bool,
+  #[serde(default)]
+  pub emit_manifest: bool,
+  pub size_budget: Option<SizeBudget>,
+}
+
+impl SerializableTransformOptions {
+  /// Checks the options that `into_transform_options` would error for,
+  /// which is useful for reporting invalid options before transforming
+  /// on another thread.
+  pub fn validate(&self) -> Result<()> {
+    parse_module_specifiers(&self.entry_points)?;
+    parse_module_specifiers(&self.test_entry_points)?;
+    Ok(())
+  }
+
+  /// Gets the options to transform with, which load the modules with
+  /// the provided loader.
+  pub fn into_transform_options(
+    self,
+    loader: Box<dyn Loader>,
+  ) -> Result<TransformOptions> {
+    Ok(TransformOptions {
+      entry_points: parse_module_specifiers(&self.entry_points)?,
+      test_entry_points: parse_module_specifiers(&self.test_entry_points)?,
+      shims: self.shims,
+      test_shims: self.test_shims,
+      loader: Some(loader),
+      specifier_mappings: self.mappings,
+      declaration_mappings: self.declaration_mappings,
+      target: self.target,
+      import_map: self.import_map,
+      jsx: self.jsx,
+      error_on_cycles: self.error_on_cycles,
+      error_on_duplicate_packages: self.error_on_duplicate_packages,
+      continue_on_parse_error: self.continue_on_parse_error,
+      normalize_output_text: self.normalize_output_text,
+      github_dependencies: self.github_dependencies,
+      remote_policy: self.remote_policy,
+      version_policy: self.version_policy,
+      asset_imports: self.asset_imports,
+      asset_handlers: HashMap::new(),
+      directory_index_files: self.directory_index_files,
+      local_roots: self.local_roots,
+      node_global_shims: self.node_global_shims,
+      dom_types_package: self.dom_types_package,
+      inject_dirname: self.inject_dirname,
+      commonjs_modules: self.commonjs_modules,
+      server_shim: self.server_shim,
+      comment_directives: self.comment_directives,
+      import_attributes: self.import_attributes,
+      inline_remote_module_max_size: self.inline_remote_module_max_size,
+      bundle: self.bundle,
+      flatten_barrels: self.flatten_barrels,
+      minify: self.minify,
+      entry_point_exports: self.entry_point_exports,
+      declarations_only: self.declarations_only,
+      third_party_licenses: self.third_party_licenses,
+      provenance: self.provenance,
+      define: self.define,
+      deno_env_to_process_env: self.deno_env_to_process_env,
+      translate_deno_apis: self.translate_deno_apis,
+      shim_import_style: self.shim_import_style,
+      deno_feature_detection: self.deno_feature_detection,
+      qualified_global_access: self.qualified_global_access,
+      relative_specifier_extension: self.relative_specifier_extension,
+      test_relative_specifier_extension: self.test_relative_specifier_extension,
+      test_main_imports: self.test_main_imports,
+      annotate_rewrites: self.annotate_rewrites,
+      hash_remote_file_names: self.hash_remote_file_names,
+      typescript_paths: self.typescript_paths,
+      emit_manifest: self.emit_manifest,
+      size_budget: self.size_budget,
+      // the bindings don't cache to the file system
+      cache_dir: None,
+      custom_visitors: Vec::new(),
+      file_text_handler: None,
+      version_handler: None,
+    })
+  }
+}
+
+fn parse_module_specifiers(values: &[String]) -> Result<Vec<ModuleSpecifier>> {
+  let mut specifiers = Vec::new();
+  for value in values {
+    let entry_point = ModuleSpecifier::parse(value)
+      .map_err(|err| anyhow::anyhow!("Error parsing {}. {}", value, err))?;
+    specifiers.push(entry_point);
+  }
+  Ok(specifiers)
+}
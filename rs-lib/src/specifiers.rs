@@ -5,22 +5,44 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 
 use anyhow::Result;
+use deno_ast::MediaType;
 use deno_ast::ModuleSpecifier;
 use deno_graph::Module;
 use deno_graph::Resolved;
 
 use crate::declaration_file_resolution::resolve_declaration_file_mappings;
 use crate::declaration_file_resolution::DeclarationFileResolution;
+use crate::get_dependencies;
 use crate::graph::ModuleGraph;
 use crate::loader::LoaderSpecifiers;
+use crate::Dependency;
 use crate::PackageMappedSpecifier;
+use crate::TransformError;
 
 #[derive(Debug)]
 pub struct Specifiers {
   pub local: Vec<ModuleSpecifier>,
   pub remote: Vec<ModuleSpecifier>,
   pub types: BTreeMap<ModuleSpecifier, DeclarationFileResolution>,
+  /// Remote JavaScript modules that don't have a declaration file.
+  pub untyped: Vec<ModuleSpecifier>,
+  /// Modules in the graph that aren't output because nothing reachable
+  /// from the entry points needs them.
+  pub excluded: Vec<ModuleSpecifier>,
+  /// Modules only reachable from the test entry points.
+  ///
+  /// Any module reachable from the main entry points is in the main
+  /// environment, so the main code must never import a test entry point.
   pub test_modules: HashSet<ModuleSpecifier>,
+  /// CSS and text files imported with an import assertion.
+  pub assets: HashSet<ModuleSpecifier>,
+  /// `type` attribute of the assets that are output by an asset handler.
+  pub asset_types: HashMap<ModuleSpecifier, String>,
+  /// Local imports of a directory by the index file they were resolved to.
+  pub directory_imports: BTreeMap<ModuleSpecifier, ModuleSpecifier>,
+  /// Local files that a remote module is mapped to by the remote specifier,
+  /// which are output where the remote module would've been.
+  pub local_copies: BTreeMap<ModuleSpecifier, ModuleSpecifier>,
   pub main: EnvironmentSpecifiers,
   pub test: EnvironmentSpecifiers,
 }
@@ -37,21 +59,115 @@ pub struct EnvironmentSpecifiers {
   pub mapped: BTreeMap<ModuleSpecifier, PackageMappedSpecifier>,
 }
 
+/// Which modules end up in which output environment and which are mapped
+/// to packages, which is how a transform partitions the module graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleClassification {
+  pub main: EnvironmentClassification,
+  pub test: EnvironmentClassification,
+  /// Remote JavaScript modules that don't have a declaration file. Sorted.
+  pub untyped: Vec<ModuleSpecifier>,
+  /// Modules in the graph that aren't output because nothing reachable
+  /// from the entry points needs them. Sorted.
+  pub excluded: Vec<ModuleSpecifier>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EnvironmentClassification {
+  /// Local modules that are output. Sorted.
+  pub local: Vec<ModuleSpecifier>,
+  /// Remote modules that are copied to the output. Sorted.
+  pub remote: Vec<ModuleSpecifier>,
+  /// Declaration files that were selected by the specifier of the code
+  /// module they're for.
+  pub types: BTreeMap<ModuleSpecifier, ModuleSpecifier>,
+  /// Modules that are mapped to a package instead of being output.
+  pub mapped: BTreeMap<ModuleSpecifier, PackageMappedSpecifier>,
+  /// Package dependencies of the environment from the mapped modules,
+  /// which for the test environment excludes the main dependencies.
+  pub dependencies: Vec<Dependency>,
+}
+
+pub fn get_module_classification(
+  specifiers: &Specifiers,
+) -> ModuleClassification {
+  let main = EnvironmentClassification {
+    mapped: specifiers.main.mapped.clone(),
+    dependencies: get_dependencies(specifiers.main.mapped.clone()),
+    ..Default::default()
+  };
+  let test = EnvironmentClassification {
+    mapped: specifiers.test.mapped.clone(),
+    dependencies: get_dependencies(specifiers.test.mapped.clone())
+      .into_iter()
+      .filter(|d| !main.dependencies.contains(d))
+      .collect(),
+    ..Default::default()
+  };
+  // main is at index 0 and test is at index 1
+  let mut environments = [main, test];
+  let get_index =
+    |specifier| usize::from(specifiers.test_modules.contains(specifier));
+  for specifier in specifiers.local.iter() {
+    environments[get_index(specifier)]
+      .local
+      .push(specifier.clone());
+  }
+  for specifier in specifiers.remote.iter() {
+    environments[get_index(specifier)]
+      .remote
+      .push(specifier.clone());
+  }
+  for (code_specifier, d) in specifiers.types.iter() {
+    environments[get_index(code_specifier)]
+      .types
+      .insert(code_specifier.clone(), d.selected.specifier.clone());
+  }
+  for environment in environments.iter_mut() {
+    environment.local.sort();
+    environment.remote.sort();
+  }
+  let [main, test] = environments;
+
+  let mut untyped = specifiers.untyped.clone();
+  untyped.sort();
+  let mut excluded = specifiers.excluded.clone();
+  excluded.sort();
+  ModuleClassification {
+    main,
+    test,
+    untyped,
+    excluded,
+  }
+}
+
 pub fn get_specifiers(
   entry_points: &[ModuleSpecifier],
+  test_entry_points: &[ModuleSpecifier],
   mut specifiers: LoaderSpecifiers,
   module_graph: &ModuleGraph,
   modules: &[&Module],
+  declaration_mappings: &HashMap<ModuleSpecifier, ModuleSpecifier>,
 ) -> Result<Specifiers> {
   let mut local_specifiers = Vec::new();
   let mut remote_specifiers = Vec::new();
 
+  let all_graph_specifiers = modules.iter().map(|m| &m.specifier);
   let mut modules: BTreeMap<&ModuleSpecifier, &Module> =
     modules.iter().map(|m| (&m.specifier, *m)).collect();
 
   let mut found_module_specifiers = Vec::new();
   let mut found_mapped_specifiers = BTreeMap::new();
 
+  // the declaration files for mapped packages with a types package are not necessary
+  for specifier in get_types_package_declaration_specifiers(
+    module_graph,
+    &modules,
+    &specifiers.mapped_packages,
+  ) {
+    modules.remove(&specifier);
+  }
+
   // search for all the non-test modules
   for entry_point in entry_points.iter() {
     let module = module_graph.get(entry_point);
@@ -83,11 +199,21 @@ pub fn get_specifiers(
           {
             pending.push(specifier);
           }
+          if let Some(specifier) = declaration_mappings.get(&module.specifier) {
+            pending.push(specifier);
+          }
         }
       }
     }
   }
 
+  ensure_test_entry_points_not_in_main(
+    entry_points,
+    test_entry_points,
+    &found_module_specifiers,
+    module_graph,
+  )?;
+
   // clear out all the mapped modules
   for specifier in specifiers.mapped_packages.keys() {
     modules.remove(specifier);
@@ -111,24 +237,62 @@ pub fn get_specifiers(
     }
   }
 
-  let types = resolve_declaration_file_mappings(
+  let mut types = resolve_declaration_file_mappings(
     module_graph,
     &all_modules,
     &found_mapped_specifiers,
+    declaration_mappings,
   )?;
-  let mut declaration_specifiers = HashSet::new();
-  for value in types.values() {
-    declaration_specifiers.insert(&value.selected.specifier);
-    for dep in value.ignored.iter() {
-      declaration_specifiers.insert(&dep.specifier);
-    }
-  }
+  // prune any modules that are only referenced by an ignored declaration file
+  let reachable_specifiers = get_reachable_specifiers(
+    module_graph,
+    entry_points.iter().chain(test_entry_points.iter()),
+    &types,
+  );
+  local_specifiers.retain(|s| reachable_specifiers.contains(s));
+  remote_specifiers.retain(|s| reachable_specifiers.contains(s));
+  types
+    .retain(|code_specifier, _| reachable_specifiers.contains(code_specifier));
+  let declaration_specifiers = types
+    .values()
+    .map(|value| &value.selected.specifier)
+    .collect::<HashSet<_>>();
+  let mut excluded = all_graph_specifiers
+    .filter(|s| !reachable_specifiers.contains(*s))
+    .cloned()
+    .collect::<Vec<_>>();
+  excluded.sort();
+
+  let untyped = remote_specifiers
+    .iter()
+    .filter(|s| {
+      !declaration_specifiers.contains(s)
+        && !types.contains_key(*s)
+        && is_untyped_media_type(module_graph.get(s).media_type)
+    })
+    .cloned()
+    .collect();
 
   ensure_package_mapped_specifiers_valid(
     &found_mapped_specifiers,
     &specifiers.mapped_packages,
   )?;
 
+  let mut local_copies = BTreeMap::new();
+  let mut module_mappings =
+    specifiers.mapped_modules.iter().collect::<Vec<_>>();
+  module_mappings.sort();
+  for (from, to) in module_mappings {
+    if from.scheme() != "file"
+      && to.scheme() == "file"
+      && local_specifiers.contains(to)
+    {
+      local_copies
+        .entry(to.clone())
+        .or_insert_with(|| from.clone());
+    }
+  }
+
   Ok(Specifiers {
     local: local_specifiers
       .into_iter()
@@ -139,7 +303,13 @@ pub fn get_specifiers(
       .filter(|l| !declaration_specifiers.contains(&l))
       .collect(),
     types,
+    untyped,
+    excluded,
     test_modules: test_modules.values().map(|k| k.specifier.clone()).collect(),
+    assets: specifiers.assets,
+    asset_types: HashMap::new(),
+    directory_imports: specifiers.directory_imports,
+    local_copies,
     main: EnvironmentSpecifiers {
       mapped: found_mapped_specifiers,
     },
@@ -149,6 +319,161 @@ pub fn get_specifiers(
   })
 }
 
+/// Gets the modules reachable from the provided entry points without
+/// going through a declaration file that was ignored.
+fn get_reachable_specifiers<'a>(
+  module_graph: &ModuleGraph,
+  entry_points: impl Iterator<Item = &'a ModuleSpecifier>,
+  types: &BTreeMap<ModuleSpecifier, DeclarationFileResolution>,
+) -> HashSet<ModuleSpecifier> {
+  let is_ignored_types_dep =
+    |code_specifier: &ModuleSpecifier, types_specifier: &ModuleSpecifier| {
+      types
+        .get(code_specifier)
+        .map(|d| d.selected.specifier != module_graph.resolve(types_specifier))
+        .unwrap_or(false)
+    };
+  let mut reachable = HashSet::new();
+  let mut pending = entry_points
+    .map(|s| module_graph.resolve(s))
+    .collect::<Vec<_>>();
+
+  while let Some(specifier) = pending.pop() {
+    if !reachable.insert(specifier.clone()) {
+      continue;
+    }
+    let module = module_graph.get(&specifier);
+    for dep in module.dependencies.values() {
+      if let Some(code_specifier) = dep.get_code() {
+        pending.push(module_graph.resolve(code_specifier));
+        if let Some(types_specifier) = dep.get_type() {
+          if !is_ignored_types_dep(code_specifier, types_specifier) {
+            pending.push(module_graph.resolve(types_specifier));
+          }
+        }
+      } else if let Some(types_specifier) = dep.get_type() {
+        pending.push(module_graph.resolve(types_specifier));
+      }
+    }
+    if let Some((
+      _,
+      Resolved::Ok {
+        specifier: types_specifier,
+        ..
+      },
+    )) = &module.maybe_types_dependency
+    {
+      if !is_ignored_types_dep(&module.specifier, types_specifier) {
+        pending.push(module_graph.resolve(types_specifier));
+      }
+    }
+    if let Some(d) = types.get(&module.specifier) {
+      pending.push(d.selected.specifier.clone());
+    }
+  }
+
+  reachable
+}
+
+/// Gets the declaration files specified via `@deno-types` for mapped packages
+/// that have a types package and that nothing else references.
+fn get_types_package_declaration_specifiers(
+  module_graph: &ModuleGraph,
+  modules: &BTreeMap<&ModuleSpecifier, &Module>,
+  mapped_packages: &BTreeMap<ModuleSpecifier, PackageMappedSpecifier>,
+) -> HashSet<ModuleSpecifier> {
+  let mut candidates = HashSet::new();
+  let mut other_references = HashSet::new();
+  for module in modules.values() {
+    for dep in module.dependencies.values() {
+      if let Some(code_specifier) = dep.get_code() {
+        other_references.insert(module_graph.resolve(code_specifier));
+      }
+      if let Some(type_specifier) = dep.get_type() {
+        let type_specifier = module_graph.resolve(type_specifier);
+        let has_types_package = dep
+          .get_code()
+          .and_then(|s| mapped_packages.get(s))
+          .map(|m| m.types_package.is_some())
+          .unwrap_or(false);
+        if has_types_package {
+          candidates.insert(type_specifier);
+        } else {
+          other_references.insert(type_specifier);
+        }
+      }
+    }
+    if let Some((_, Resolved::Ok { specifier, .. })) =
+      &module.maybe_types_dependency
+    {
+      other_references.insert(module_graph.resolve(specifier));
+    }
+  }
+  candidates.retain(|s| !other_references.contains(s));
+  candidates
+}
+
+fn is_untyped_media_type(media_type: MediaType) -> bool {
+  matches!(
+    media_type,
+    MediaType::JavaScript | MediaType::Jsx | MediaType::Mjs | MediaType::Cjs
+  )
+}
+
+/// Ensures the main code doesn't import any test entry points, which
+/// would otherwise cause them to be output in the main environment.
+fn ensure_test_entry_points_not_in_main(
+  entry_points: &[ModuleSpecifier],
+  test_entry_points: &[ModuleSpecifier],
+  main_specifiers: &[ModuleSpecifier],
+  module_graph: &ModuleGraph,
+) -> Result<()> {
+  let main_specifiers_set = main_specifiers.iter().collect::<HashSet<_>>();
+  let entry_points = entry_points
+    .iter()
+    .map(|s| module_graph.resolve(s))
+    .collect::<HashSet<_>>();
+  let mut messages = Vec::new();
+
+  for test_entry_point in test_entry_points {
+    let test_entry_point = module_graph.resolve(test_entry_point);
+    // entry points may be shared, such as with module shims
+    if entry_points.contains(&test_entry_point)
+      || !main_specifiers_set.contains(&test_entry_point)
+    {
+      continue;
+    }
+
+    let mut message = format!(
+      "Test entry point {} is imported by code in the main environment.",
+      test_entry_point
+    );
+    for referrer in main_specifiers {
+      for dep in module_graph.get(referrer).dependencies.values() {
+        for resolved in [&dep.maybe_code, &dep.maybe_type] {
+          if let Resolved::Ok { specifier, range } = resolved {
+            if module_graph.resolve(specifier) == test_entry_point {
+              message.push_str(&format!(
+                "\n    at {}:{}:{}",
+                range.specifier,
+                range.start.line + 1,
+                range.start.character + 1,
+              ));
+            }
+          }
+        }
+      }
+    }
+    messages.push(message);
+  }
+
+  if !messages.is_empty() {
+    anyhow::bail!("{}", messages.join("\n\n"));
+  }
+
+  Ok(())
+}
+
 fn ensure_package_mapped_specifiers_valid(
   mapped_specifiers: &BTreeMap<ModuleSpecifier, PackageMappedSpecifier>,
   test_mapped_specifiers: &BTreeMap<ModuleSpecifier, PackageMappedSpecifier>,
@@ -163,12 +488,14 @@ fn ensure_package_mapped_specifiers_valid(
   {
     if let Some(specifier) = specifier_for_name.get(&mapped_specifier.name) {
       if specifier.1.version != mapped_specifier.version {
-        anyhow::bail!("Specifier {} with version {} did not match specifier {} with version {}.",
-          specifier.0,
-          specifier.1.version.as_deref().unwrap_or("<unknown>"),
-          from_specifier,
-          mapped_specifier.version.as_deref().unwrap_or("<unknown>"),
-        );
+        anyhow::bail!(TransformError::MappingConflict {
+          message: format!("Specifier {} with version {} did not match specifier {} with version {}.",
+            specifier.0,
+            specifier.1.version.as_deref().unwrap_or("<unknown>"),
+            from_specifier,
+            mapped_specifier.version.as_deref().unwrap_or("<unknown>"),
+          ),
+        });
       }
     } else {
       specifier_for_name.insert(
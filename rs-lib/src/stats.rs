@@ -0,0 +1,105 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use deno_ast::ModuleSpecifier;
+
+use crate::graph::ShortestReferrers;
+use crate::FileShimUsage;
+use crate::OutputStats;
+use crate::RemoteModuleSize;
+use crate::SizeBudget;
+
+/// Number of remote modules in `largest_remote_modules`.
+const LARGEST_REMOTE_MODULES_LEN: usize = 10;
+
+/// Gets the statistics of an output environment from the paths and sizes
+/// of all its files, including any that were already handed to a sink.
+pub fn get_output_stats(
+  file_sizes: &[(PathBuf, usize)],
+  remote_modules: &HashMap<PathBuf, ModuleSpecifier>,
+  referrers: &ShortestReferrers,
+  shims_used: &[FileShimUsage],
+) -> OutputStats {
+  let mut largest_remote_modules = file_sizes
+    .iter()
+    .filter_map(|(file_path, bytes)| {
+      Some(RemoteModuleSize {
+        specifier: remote_modules.get(file_path)?.clone(),
+        file_path: file_path.clone(),
+        bytes: *bytes,
+        referrer_chain: Vec::new(),
+      })
+    })
+    .collect::<Vec<_>>();
+  largest_remote_modules.sort_by(|a, b| {
+    b.bytes
+      .cmp(&a.bytes)
+      .then_with(|| a.specifier.cmp(&b.specifier))
+  });
+  largest_remote_modules.truncate(LARGEST_REMOTE_MODULES_LEN);
+  for module in largest_remote_modules.iter_mut() {
+    module.referrer_chain = referrers.get_chain(&module.specifier);
+  }
+
+  OutputStats {
+    file_count: file_sizes.len(),
+    total_bytes: file_sizes.iter().map(|(_, bytes)| bytes).sum(),
+    largest_remote_modules,
+    shimmed_api_count: shims_used
+      .iter()
+      .flat_map(|usage| usage.globals.iter())
+      .collect::<BTreeSet<_>>()
+      .len(),
+  }
+}
+
+/// Gets a warning for each limit of the size budget the output is over.
+pub fn get_size_budget_warnings(
+  file_sizes: &[(PathBuf, usize)],
+  remote_modules: &HashMap<PathBuf, ModuleSpecifier>,
+  referrers: &ShortestReferrers,
+  stats: &OutputStats,
+  budget: &SizeBudget,
+) -> Vec<String> {
+  let mut warnings = Vec::new();
+  if let Some(max_total_bytes) = budget.max_total_bytes {
+    if stats.total_bytes > max_total_bytes {
+      warnings.push(format!(
+        "The output is {} bytes, which is over the size budget of {} bytes.",
+        stats.total_bytes, max_total_bytes
+      ));
+    }
+  }
+  if let Some(max_file_bytes) = budget.max_file_bytes {
+    let mut file_sizes = file_sizes
+      .iter()
+      .filter(|(_, bytes)| *bytes > max_file_bytes)
+      .collect::<Vec<_>>();
+    file_sizes.sort();
+    for (file_path, bytes) in file_sizes {
+      let mut warning = format!(
+        "{} is {} bytes, which is over the size budget of {} bytes for a file.",
+        file_path.display(),
+        bytes,
+        max_file_bytes
+      );
+      // explain why a remote module is in the output
+      if let Some(specifier) = remote_modules.get(file_path) {
+        warning.push_str(&format!(
+          "\n  Referrer chain: {}",
+          referrers
+            .get_chain(specifier)
+            .iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<_>>()
+            .join(" -> ")
+        ));
+      }
+      warnings.push(warning);
+    }
+  }
+  warnings
+}
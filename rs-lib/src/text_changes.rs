@@ -0,0 +1,182 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+//! Utilities for working with text changes, which is useful for applying
+//! custom rewrites along with the ones done by the transform.
+
+use std::ops::Range;
+
+use anyhow::bail;
+use anyhow::Result;
+use deno_ast::SourceRange;
+use deno_ast::SourceTextInfo;
+
+pub use deno_ast::TextChange;
+
+/// Applies the text changes to the text, building the result in a
+/// single allocation.
+///
+/// Errors when a change is out of bounds or overlaps another change.
+pub fn apply_text_changes(
+  text: &str,
+  changes: Vec<TextChange>,
+) -> Result<String> {
+  let changes = sort_and_validate(changes)?;
+
+  let mut final_len = text.len();
+  for change in changes.iter() {
+    let range = &change.range;
+    if range.end > text.len()
+      || !text.is_char_boundary(range.start)
+      || !text.is_char_boundary(range.end)
+    {
+      bail!(
+        "Text change {:?} was not within the text of length {}.",
+        range,
+        text.len()
+      );
+    }
+    final_len = final_len - range.len() + change.new_text.len();
+  }
+
+  let mut result = String::with_capacity(final_len);
+  let mut last_end = 0;
+  for change in changes {
+    result.push_str(&text[last_end..change.range.start]);
+    result.push_str(&change.new_text);
+    last_end = change.range.end;
+  }
+  result.push_str(&text[last_end..]);
+  Ok(result)
+}
+
+/// Combines multiple sets of text changes for the same text into one
+/// sorted set of changes.
+///
+/// Errors when a change in one set conflicts with a change in another.
+pub fn compose_text_changes(
+  change_sets: impl IntoIterator<Item = Vec<TextChange>>,
+) -> Result<Vec<TextChange>> {
+  sort_and_validate(change_sets.into_iter().flatten().collect())
+}
+
+/// Gets the byte range in the text for a source range.
+pub fn to_byte_range(
+  text_info: &SourceTextInfo,
+  range: SourceRange,
+) -> Range<usize> {
+  range.as_byte_range(text_info.range().start)
+}
+
+/// Gets if two ranges overlap. Touching ranges do not overlap, but an
+/// empty range inside another range does.
+pub fn ranges_overlap(a: &Range<usize>, b: &Range<usize>) -> bool {
+  a.start < b.end && b.start < a.end
+}
+
+fn sort_and_validate(mut changes: Vec<TextChange>) -> Result<Vec<TextChange>> {
+  // stable so that insertions at the same position stay in order
+  changes.sort_by(|a, b| {
+    a.range
+      .start
+      .cmp(&b.range.start)
+      .then(a.range.end.cmp(&b.range.end))
+  });
+
+  for (i, change) in changes.iter().enumerate() {
+    if change.range.start > change.range.end {
+      bail!("Text change {:?} had an invalid range.", change.range);
+    }
+    if i > 0 && change.range.start < changes[i - 1].range.end {
+      bail!(
+        "Text change {:?} overlapped text change {:?}.",
+        change.range,
+        changes[i - 1].range
+      );
+    }
+  }
+
+  Ok(changes)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use pretty_assertions::assert_eq;
+
+  fn change(range: Range<usize>, new_text: &str) -> TextChange {
+    TextChange {
+      range,
+      new_text: new_text.to_string(),
+    }
+  }
+
+  #[test]
+  fn test_apply_text_changes() {
+    assert_eq!(
+      apply_text_changes(
+        "const a = window;",
+        vec![
+          change(10..16, "globalThis"),
+          change(0..0, "// a\n"),
+          change(17..17, "\nexport {};"),
+          change(0..0, "// b\n"),
+        ]
+      )
+      .unwrap(),
+      "// a\n// b\nconst a = globalThis;\nexport {};"
+    );
+    assert_eq!(
+      apply_text_changes("test", vec![change(0..0, "a"), change(0..4, "b")])
+        .unwrap(),
+      "ab"
+    );
+    assert_eq!(
+      apply_text_changes("test", vec![change(0..3, "a"), change(2..4, "b")])
+        .err()
+        .unwrap()
+        .to_string(),
+      "Text change 2..4 overlapped text change 0..3."
+    );
+    assert_eq!(
+      apply_text_changes("test", vec![change(2..5, "a")])
+        .err()
+        .unwrap()
+        .to_string(),
+      "Text change 2..5 was not within the text of length 4."
+    );
+  }
+
+  #[test]
+  fn test_compose_text_changes() {
+    let changes = compose_text_changes(vec![
+      vec![change(4..6, "b"), change(0..1, "a")],
+      vec![change(2..3, "c")],
+    ])
+    .unwrap();
+    assert_eq!(
+      changes.iter().map(|c| c.range.clone()).collect::<Vec<_>>(),
+      vec![0..1, 2..3, 4..6]
+    );
+    assert_eq!(
+      compose_text_changes(vec![
+        vec![change(0..2, "a")],
+        vec![change(1..3, "b")]
+      ])
+      .err()
+      .unwrap()
+      .to_string(),
+      "Text change 1..3 overlapped text change 0..2."
+    );
+  }
+
+  #[test]
+  fn test_ranges_overlap() {
+    assert!(ranges_overlap(&(0..2), &(1..3)));
+    assert!(ranges_overlap(&(1..3), &(0..2)));
+    assert!(!ranges_overlap(&(0..2), &(2..3)));
+    assert!(ranges_overlap(&(1..1), &(0..2)));
+    assert!(ranges_overlap(&(0..2), &(1..1)));
+    assert!(!ranges_overlap(&(0..0), &(0..2)));
+    assert!(!ranges_overlap(&(0..1), &(1..1)));
+  }
+}
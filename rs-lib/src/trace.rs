@@ -0,0 +1,56 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::time::Duration;
+
+/// Enters a span until the end of the current block when the `tracing`
+/// feature is enabled and otherwise does nothing.
+macro_rules! trace_span {
+  ($($args:tt)*) => {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!($($args)*).entered();
+  };
+}
+
+pub(crate) use trace_span;
+
+/// Logs a debug message with the `log` crate when the `log` feature is
+/// enabled and otherwise does nothing.
+macro_rules! log_debug {
+  ($($args:tt)*) => {{
+    #[cfg(feature = "log")]
+    log::debug!($($args)*);
+    // use the arguments without evaluating them so they don't warn as
+    // unused when logging is disabled
+    #[cfg(not(feature = "log"))]
+    let _ = || {
+      let _ = format_args!($($args)*);
+    };
+  }};
+}
+
+pub(crate) use log_debug;
+
+/// Measures the time a phase of the transform took when the `tracing`
+/// feature is enabled. Otherwise the elapsed time is always zero because
+/// `Instant` isn't available on every target (ex. Wasm).
+pub struct Stopwatch {
+  #[cfg(feature = "tracing")]
+  start: std::time::Instant,
+}
+
+impl Stopwatch {
+  pub fn start() -> Self {
+    Self {
+      #[cfg(feature = "tracing")]
+      start: std::time::Instant::now(),
+    }
+  }
+
+  pub fn elapsed(&self) -> Duration {
+    #[cfg(feature = "tracing")]
+    let elapsed = self.start.elapsed();
+    #[cfg(not(feature = "tracing"))]
+    let elapsed = Duration::ZERO;
+    elapsed
+  }
+}
@@ -0,0 +1,47 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::path::Path;
+
+use anyhow::Result;
+use deno_ast::view::Program;
+use deno_ast::ModuleSpecifier;
+use deno_ast::TextChange;
+
+use crate::mappings::Mappings;
+use crate::OutputEnvironment;
+
+/// A custom pass that rewrites modules along with the built-in passes.
+///
+/// The returned text changes are merged with the text changes of the
+/// built-in passes, so they must not overlap with them.
+pub trait TransformVisitor: Sync {
+  fn visit(&self, context: &TransformVisitorContext)
+    -> Result<Vec<TextChange>>;
+
+  /// Text that identifies what this visitor does, which is used in the
+  /// key of modules stored in the cache directory. Modules aren't cached
+  /// when this is `None`.
+  fn cache_key(&self) -> Option<String> {
+    None
+  }
+}
+
+/// The module being transformed.
+pub struct TransformVisitorContext<'a> {
+  pub program: &'a Program<'a>,
+  pub specifier: &'a ModuleSpecifier,
+  pub environment: OutputEnvironment,
+  pub(crate) mappings: &'a Mappings,
+}
+
+impl<'a> TransformVisitorContext<'a> {
+  /// Gets the path relative to the output directory that a module is
+  /// output to, which is `None` for modules that aren't output, such
+  /// as modules mapped to a package.
+  pub fn output_path(&self, specifier: &ModuleSpecifier) -> Option<&Path> {
+    self
+      .mappings
+      .maybe_file_path(specifier)
+      .map(|p| p.as_path())
+  }
+}
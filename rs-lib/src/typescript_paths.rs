@@ -0,0 +1,155 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use deno_ast::ModuleSpecifier;
+
+use crate::mappings::Mappings;
+use crate::specifiers::Specifiers;
+use crate::utils::partition_by_root_specifiers;
+use crate::utils::percent_decode;
+
+/// Gets a TypeScript `compilerOptions.paths` alias map from the remote
+/// specifiers to where they were output, relative to the output directory.
+///
+/// Each vendored remote root is aliased with a wildcard (ex.
+/// `https://deno.land/*` to `./deps/deno.land/*`). The modules whose
+/// output path differs from their url's path (ex. when shortened or
+/// renamed to be unique) get an exact alias, which TypeScript prefers over
+/// the wildcard. Remote modules mapped to a package are aliased to the
+/// package in `node_modules`.
+pub fn get_typescript_paths(
+  specifiers: &Specifiers,
+  mappings: &Mappings,
+) -> BTreeMap<String, Vec<String>> {
+  let mut paths = BTreeMap::new();
+  let remote_copies = specifiers
+    .local_copies
+    .iter()
+    .map(|(local, remote)| (remote, local))
+    .collect::<HashMap<_, _>>();
+  let remote_specifiers = specifiers
+    .remote
+    .iter()
+    .chain(remote_copies.keys().copied())
+    .chain(specifiers.types.values().map(|d| &d.selected.specifier))
+    .filter(|s| s.scheme() != "file");
+
+  for (root, specifiers) in partition_by_root_specifiers(remote_specifiers) {
+    let modules = specifiers
+      .iter()
+      .map(|specifier| {
+        let output_specifier =
+          remote_copies.get(specifier).copied().unwrap_or(specifier);
+        let file_path = mappings.get_file_path(output_specifier);
+        (specifier, file_path, get_root_dir(specifier, file_path))
+      })
+      .collect::<Vec<_>>();
+    // the directory most of the root's modules are output to
+    let mut dir_counts = BTreeMap::new();
+    for dir in modules.iter().filter_map(|(_, _, dir)| dir.as_ref()) {
+      *dir_counts.entry(dir).or_insert(0) += 1;
+    }
+    let root_dir = dir_counts
+      .into_iter()
+      .rev()
+      .max_by_key(|(_, count)| *count)
+      .map(|(dir, _)| dir.clone());
+    if let Some(root_dir) = &root_dir {
+      paths.insert(
+        format!("{}*", root),
+        vec![format!("./{}/*", root_dir.to_string_lossy())],
+      );
+    }
+    for (specifier, file_path, dir) in modules {
+      if dir.is_none() || dir != root_dir {
+        paths.insert(
+          specifier.to_string(),
+          vec![format!("./{}", file_path.to_string_lossy())],
+        );
+      }
+    }
+  }
+
+  for (specifier, package) in specifiers
+    .main
+    .mapped
+    .iter()
+    .chain(specifiers.test.mapped.iter())
+  {
+    // built-in modules (ex. "fs") don't have a directory to point at
+    if specifier.scheme() == "file" || package.version.is_none() {
+      continue;
+    }
+    let mut package_paths = vec![format!(
+      "./node_modules/{}",
+      package.module_specifier_text()
+    )];
+    if let Some(types_package) = &package.types_package {
+      package_paths.push(format!("./node_modules/{}", types_package.name));
+    }
+    paths.insert(specifier.to_string(), package_paths);
+  }
+
+  paths
+}
+
+/// Gets the directory the remote module's url path was output relative
+/// to, which is `None` when the output path differs from the url's path.
+fn get_root_dir(
+  specifier: &ModuleSpecifier,
+  file_path: &Path,
+) -> Option<PathBuf> {
+  if specifier.query().is_some() {
+    return None;
+  }
+  let url_path = specifier
+    .path_segments()?
+    .filter(|s| !s.is_empty())
+    .map(percent_decode)
+    .collect::<PathBuf>();
+  if url_path.as_os_str().is_empty() || !file_path.ends_with(&url_path) {
+    return None;
+  }
+  let mut dir = file_path.to_path_buf();
+  for _ in url_path.components() {
+    dir.pop();
+  }
+  Some(dir)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_get_root_dir() {
+    let get = |specifier: &str, file_path: &str| {
+      get_root_dir(
+        &ModuleSpecifier::parse(specifier).unwrap(),
+        Path::new(file_path),
+      )
+    };
+    assert_eq!(
+      get("https://deno.land/x/mod/a.ts", "deps/deno.land/x/mod/a.ts"),
+      Some(PathBuf::from("deps/deno.land"))
+    );
+    assert_eq!(
+      get(
+        "https://deno.land/x/mod/a.ts",
+        "deps/deno.land/x/mod/a_2.ts"
+      ),
+      None
+    );
+    assert_eq!(
+      get(
+        "https://deno.land/x/mod/a.ts?v=1",
+        "deps/deno.land/x/mod/a.ts"
+      ),
+      None
+    );
+  }
+}
@@ -2,14 +2,16 @@
 
 use std::collections::BTreeMap;
 use std::collections::HashSet;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
+use std::path::Prefix;
 
 use anyhow::Result;
-use deno_ast::apply_text_changes;
 use deno_ast::parse_module;
 use deno_ast::view::NodeTrait;
 use deno_ast::view::Program;
+use deno_ast::MediaType;
 use deno_ast::ModuleSpecifier;
 use deno_ast::ParseParams;
 use deno_ast::SourceRangedForSpanned;
@@ -17,44 +19,111 @@ use deno_ast::SourceTextInfo;
 use deno_ast::SourceTextInfoProvider;
 use deno_ast::TextChange;
 
+use crate::text_changes::apply_text_changes;
+use crate::virtual_path::VirtualPath;
+use crate::RelativeSpecifierExtension;
+
 pub const BOM_CHAR: char = '\u{FEFF}';
 
+/// Gets the specifier to import a file with from another file. Declaration
+/// files are always imported without their extension.
+///
+/// The characters that have a meaning in a url (ex. `#` starts the
+/// fragment) are percent-encoded, because Node resolves relative
+/// specifiers as urls. Other characters, such as spaces, are left as is.
 pub fn get_relative_specifier(
   from: impl AsRef<Path>,
   to: impl AsRef<Path>,
+  extension: RelativeSpecifierExtension,
 ) -> String {
-  let to = with_extension(
-    to.as_ref(),
-    if to
-      .as_ref()
-      .to_string_lossy()
-      .to_lowercase()
-      .ends_with(".d.ts")
-    {
-      ""
-    } else {
-      "js"
-    },
-  );
-  let relative_path = get_relative_path(from, &to);
-  let relative_path_str = relative_path
-    .to_string_lossy()
-    .to_string()
-    .replace('\\', "/");
-
-  if relative_path_str.starts_with("../") || relative_path_str.starts_with("./")
-  {
-    relative_path_str
-  } else {
-    format!("./{}", relative_path_str)
+  let to = to.as_ref();
+  let is_declaration_file =
+    to.to_string_lossy().to_lowercase().ends_with(".d.ts");
+  let to = match extension {
+    _ if is_declaration_file => with_extension(to, ""),
+    RelativeSpecifierExtension::Js => with_js_extension(to),
+    RelativeSpecifierExtension::Preserve => to.to_path_buf(),
+    RelativeSpecifierExtension::Strip => with_extension(to, ""),
+  };
+  percent_encode_specifier(get_relative_path(from, &to))
+}
+
+/// Gets the path of the JavaScript file tsc emits for a code file, which
+/// is the path the file's `.js` specifiers point at.
+pub fn with_js_extension(path: &Path) -> PathBuf {
+  match MediaType::from(path) {
+    // tsc keeps the module format of these in the extension
+    MediaType::Mts | MediaType::Mjs => with_extension(path, "mjs"),
+    MediaType::Cts => with_extension(path, "cjs"),
+    _ => with_extension(path, "js"),
+  }
+}
+
+fn percent_encode_specifier(specifier: String) -> String {
+  if !specifier.contains(|c| matches!(c, '%' | '#' | '?')) {
+    return specifier;
+  }
+  let mut encoded = String::with_capacity(specifier.len());
+  for c in specifier.chars() {
+    match c {
+      '%' => encoded.push_str("%25"),
+      '#' => encoded.push_str("%23"),
+      '?' => encoded.push_str("%3F"),
+      c => encoded.push(c),
+    }
+  }
+  encoded
+}
+
+/// Decodes the percent-encoded bytes of a url path segment (ex. `%20` is
+/// a space). Invalid escapes are left as is and bytes that aren't valid
+/// UTF-8 are replaced.
+pub fn percent_decode(text: &str) -> String {
+  fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+      b'0'..=b'9' => Some(byte - b'0'),
+      b'a'..=b'f' => Some(byte - b'a' + 10),
+      b'A'..=b'F' => Some(byte - b'A' + 10),
+      _ => None,
+    }
+  }
+
+  if !text.contains('%') {
+    return text.to_string();
+  }
+  let bytes = text.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    if bytes[i] == b'%' && i + 2 < bytes.len() {
+      if let (Some(high), Some(low)) =
+        (hex_value(bytes[i + 1]), hex_value(bytes[i + 2]))
+      {
+        decoded.push(high * 16 + low);
+        i += 3;
+        continue;
+      }
+    }
+    decoded.push(bytes[i]);
+    i += 1;
   }
+  String::from_utf8_lossy(&decoded).to_string()
 }
 
+/// Gets the `/` separated path to a file relative to the directory of
+/// another file, which always starts with `./` or `../`.
 pub fn get_relative_path(
   from: impl AsRef<Path>,
   to: impl AsRef<Path>,
-) -> PathBuf {
-  pathdiff::diff_paths(to, from.as_ref().parent().unwrap()).unwrap()
+) -> String {
+  let from_dir = VirtualPath::new(from).parent().unwrap();
+  let to = VirtualPath::new(to);
+  match to.relative_to(&from_dir) {
+    Some(relative_path) if relative_path.starts_with("../") => relative_path,
+    Some(relative_path) => format!("./{}", relative_path),
+    // ex. on a different drive
+    None => to.to_string(),
+  }
 }
 
 pub fn url_to_file_path(module_specifier: &ModuleSpecifier) -> Result<PathBuf> {
@@ -64,6 +133,7 @@ pub fn url_to_file_path(module_specifier: &ModuleSpecifier) -> Result<PathBuf> {
   let path_segments = module_specifier
     .path_segments()
     .unwrap()
+    .map(percent_decode)
     .collect::<Vec<_>>();
   let mut final_text = String::new();
   for segment in path_segments.iter() {
@@ -72,12 +142,112 @@ pub fn url_to_file_path(module_specifier: &ModuleSpecifier) -> Result<PathBuf> {
     }
     final_text.push_str(segment);
   }
-  if !is_windows_path_segment(path_segments[0]) {
+  if !is_windows_path_segment(&path_segments[0]) {
     final_text = format!("/{}", final_text);
   }
+  if let Some(host) = module_specifier.host_str().filter(|h| !h.is_empty()) {
+    // UNC path (ex. file://server/share/mod.ts)
+    final_text = format!("//{}{}", host, final_text);
+  }
   Ok(PathBuf::from(final_text))
 }
 
+/// Gets the file url for an absolute path. This works in Wasm, unlike
+/// `Url::from_file_path`.
+pub fn file_path_to_url(path: &Path) -> ModuleSpecifier {
+  let path = normalize_path(path);
+  let mut url = ModuleSpecifier::parse("file:///").unwrap();
+  let mut segments = Vec::new();
+  for component in path.components() {
+    match component {
+      Component::Prefix(prefix) => match prefix.kind() {
+        Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+          segments.push(format!("{}:", letter as char));
+        }
+        Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => {
+          // ex. file://server/share/mod.ts
+          let _ = url.set_host(Some(&server.to_string_lossy()));
+          segments.push(share.to_string_lossy().to_string());
+        }
+        _ => segments.push(prefix.as_os_str().to_string_lossy().to_string()),
+      },
+      Component::Normal(name) => {
+        segments.push(name.to_string_lossy().to_string())
+      }
+      Component::RootDir | Component::CurDir | Component::ParentDir => {}
+    }
+  }
+  url.path_segments_mut().unwrap().clear().extend(segments);
+  url
+}
+
+/// Resolves an entry point, which is either a url or a path relative to
+/// the current working directory, to a module specifier.
+///
+/// Paths and `file:` urls are canonicalized when the file exists, which
+/// resolves symlinks and gets the casing used on the file system, so that
+/// different ways of referencing the same file don't end up as separate
+/// modules.
+pub fn resolve_entry_point(value: &str, cwd: &Path) -> ModuleSpecifier {
+  match ModuleSpecifier::parse(value) {
+    // a single letter scheme is a Windows drive (ex. `C:\mod.ts`)
+    Ok(specifier) if specifier.scheme().len() > 1 => {
+      canonicalize_file_specifier(specifier)
+    }
+    _ => {
+      let path = cwd.join(value);
+      let path = std::fs::canonicalize(&path).unwrap_or(path);
+      file_path_to_url(&path)
+    }
+  }
+}
+
+/// Canonicalizes a `file:` specifier when the file exists. Other
+/// specifiers are returned as-is.
+pub fn canonicalize_file_specifier(
+  specifier: ModuleSpecifier,
+) -> ModuleSpecifier {
+  if specifier.scheme() != "file" {
+    return specifier;
+  }
+  let file_path = match url_to_file_path(&specifier) {
+    Ok(file_path) => file_path,
+    Err(_) => return specifier,
+  };
+  match std::fs::canonicalize(&file_path) {
+    Ok(path) if path != file_path => with_file_path(&specifier, &path),
+    _ => specifier,
+  }
+}
+
+/// Gets the `file:` specifier for a path while keeping the query and
+/// fragment of the provided specifier.
+pub fn with_file_path(
+  specifier: &ModuleSpecifier,
+  path: &Path,
+) -> ModuleSpecifier {
+  let mut new_specifier = file_path_to_url(path);
+  new_specifier.set_query(specifier.query());
+  new_specifier.set_fragment(specifier.fragment());
+  new_specifier
+}
+
+/// Resolves the `.` and `..` components of a path without accessing
+/// the file system.
+fn normalize_path(path: &Path) -> PathBuf {
+  let mut result = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::CurDir => {}
+      Component::ParentDir => {
+        result.pop();
+      }
+      component => result.push(component),
+    }
+  }
+  result
+}
+
 fn is_windows_path_segment(specifier: &str) -> bool {
   let mut chars = specifier.chars();
 
@@ -147,6 +317,29 @@ pub fn strip_bom(text: &str) -> &str {
   }
 }
 
+/// Gets a double quoted JavaScript string literal for the text.
+pub fn to_js_string_literal(text: &str) -> String {
+  let mut result = String::with_capacity(text.len() + 2);
+  result.push('"');
+  for c in text.chars() {
+    match c {
+      '"' => result.push_str("\\\""),
+      '\\' => result.push_str("\\\\"),
+      '\n' => result.push_str("\\n"),
+      '\r' => result.push_str("\\r"),
+      '\t' => result.push_str("\\t"),
+      // these end a line in string literals of older engines
+      '\u{2028}' | '\u{2029}' => {
+        result.push_str(&format!("\\u{:04x}", c as u32))
+      }
+      c if c.is_control() => result.push_str(&format!("\\u{:04x}", c as u32)),
+      c => result.push(c),
+    }
+  }
+  result.push('"');
+  result
+}
+
 /// Partitions the provided specifiers by the non-path and non-query parts of a specifier.
 pub fn partition_by_root_specifiers<'a>(
   specifiers: impl Iterator<Item = &'a ModuleSpecifier>,
@@ -169,6 +362,14 @@ pub fn prepend_statement_to_text(
   file_text: &mut String,
   statement_text: &str,
 ) {
+  // insert after the byte order mark and keep the file's line endings
+  let bom = if file_text.starts_with(BOM_CHAR) {
+    file_text.drain(..BOM_CHAR.len_utf8());
+    BOM_CHAR.to_string()
+  } else {
+    String::new()
+  };
+  let new_line_kind = NewLineKind::detect(file_text);
   // It's not great to have to reparse the file for this. Perhaps there is a utility
   // function in swc or maybe add one to deno_ast for parsing out the leading comments
   let text_info = SourceTextInfo::from_string(std::mem::take(file_text));
@@ -182,15 +383,24 @@ pub fn prepend_statement_to_text(
   });
   match parsed_module {
     Ok(parsed_module) => parsed_module.with_view(|program| {
-      let text_change =
+      let mut text_change =
         text_change_for_prepend_statement_to_text(&program, statement_text);
-      *file_text = apply_text_changes(text_info.text_str(), vec![text_change]);
+      text_change.new_text =
+        with_new_line_kind(&text_change.new_text, new_line_kind);
+      *file_text =
+        apply_text_changes(text_info.text_str(), vec![text_change]).unwrap();
     }),
     Err(_) => {
       // should never happen... fallback...
-      *file_text = format!("{}\n{}", statement_text, text_info.text_str(),);
+      *file_text = format!(
+        "{}{}{}",
+        statement_text,
+        new_line_kind.as_str(),
+        text_info.text_str(),
+      );
     }
   }
+  file_text.insert_str(0, &bom);
 }
 
 pub fn text_change_for_prepend_statement_to_text(
@@ -208,11 +418,25 @@ pub fn text_change_for_prepend_statement_to_text(
   }
 }
 
+/// Gets the position after the shebang and the leading comments, such as
+/// license banners, so that they stay at the top of the file.
 fn top_file_insert_pos(program: &Program) -> usize {
-  let mut pos = 0;
+  let text = program.text_info().text_str();
+  let mut pos = if text.starts_with("#!") {
+    text
+      .find('\n')
+      .map(|index| text[..index].trim_end_matches('\r').len())
+      .unwrap_or(text.len())
+  } else {
+    0
+  };
   for comment in program.leading_comments() {
-    // insert before any @ts-ignore or @ts-expect
-    if comment.text_fast(program).to_lowercase().contains("@ts-") {
+    // insert before any @ts-ignore or @ts-expect-error since they
+    // apply to the line that follows them
+    let comment_text = comment.text_fast(program).to_lowercase();
+    if comment_text.contains("@ts-ignore")
+      || comment_text.contains("@ts-expect-error")
+    {
       break;
     }
     pos = comment
@@ -222,6 +446,51 @@ fn top_file_insert_pos(program: &Program) -> usize {
   pos
 }
 
+/// Line endings of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NewLineKind {
+  LineFeed,
+  CarriageReturnLineFeed,
+}
+
+impl NewLineKind {
+  /// Detects the kind of line endings based on the first line ending.
+  pub fn detect(text: &str) -> Self {
+    match text.find('\n') {
+      Some(index) if text[..index].ends_with('\r') => {
+        NewLineKind::CarriageReturnLineFeed
+      }
+      _ => NewLineKind::LineFeed,
+    }
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      NewLineKind::LineFeed => "\n",
+      NewLineKind::CarriageReturnLineFeed => "\r\n",
+    }
+  }
+}
+
+/// Gets the text with all its line endings changed to the provided kind.
+pub fn with_new_line_kind(text: &str, kind: NewLineKind) -> String {
+  match kind {
+    NewLineKind::LineFeed => text.replace("\r\n", "\n"),
+    NewLineKind::CarriageReturnLineFeed => {
+      let mut result = String::with_capacity(text.len());
+      let mut last_char = None;
+      for c in text.chars() {
+        if c == '\n' && last_char != Some('\r') {
+          result.push('\r');
+        }
+        result.push(c);
+        last_char = Some(c);
+      }
+      result
+    }
+  }
+}
+
 /// `with_extension` that handles `.d.ts` files
 pub fn with_extension(path: &Path, ext: &str) -> PathBuf {
   let lower = path.to_string_lossy().to_lowercase();
@@ -245,6 +514,79 @@ mod test {
   use super::*;
   use pretty_assertions::assert_eq;
 
+  #[test]
+  fn test_file_path_to_url() {
+    assert_eq!(
+      file_path_to_url(Path::new("/dir/mod.ts")).to_string(),
+      "file:///dir/mod.ts"
+    );
+    assert_eq!(
+      file_path_to_url(Path::new("/dir/./sub/../other dir/mod.ts")).to_string(),
+      "file:///dir/other%20dir/mod.ts"
+    );
+  }
+
+  #[test]
+  fn test_resolve_entry_point() {
+    let cwd = Path::new("/not_exists/dir");
+    assert_eq!(
+      resolve_entry_point("https://localhost/mod.ts", cwd).to_string(),
+      "https://localhost/mod.ts"
+    );
+    assert_eq!(
+      resolve_entry_point("file:///not_exists/mod.ts", cwd).to_string(),
+      "file:///not_exists/mod.ts"
+    );
+    assert_eq!(
+      resolve_entry_point("./mod.ts", cwd).to_string(),
+      "file:///not_exists/dir/mod.ts"
+    );
+    assert_eq!(
+      resolve_entry_point("../other/mod.ts", cwd).to_string(),
+      "file:///not_exists/other/mod.ts"
+    );
+    assert_eq!(
+      resolve_entry_point("/not_exists/mod.ts", cwd).to_string(),
+      "file:///not_exists/mod.ts"
+    );
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_resolve_entry_point_symlink() {
+    let dir = std::env::temp_dir()
+      .join(format!("dnt_resolve_entry_point_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("real")).unwrap();
+    std::fs::write(dir.join("real/mod.ts"), "").unwrap();
+    let _ = std::fs::remove_file(dir.join("link"));
+    std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+
+    let expected = file_path_to_url(
+      &std::fs::canonicalize(dir.join("real/mod.ts")).unwrap(),
+    );
+    assert_eq!(resolve_entry_point("link/mod.ts", &dir), expected);
+    assert_eq!(resolve_entry_point("./real/../real/mod.ts", &dir), expected);
+    assert_eq!(
+      resolve_entry_point(
+        file_path_to_url(&dir.join("link/mod.ts")).as_str(),
+        &dir
+      ),
+      expected
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn test_to_js_string_literal() {
+    assert_eq!(to_js_string_literal(""), r#""""#);
+    assert_eq!(
+      to_js_string_literal("a {\n\tcolor: \"red\";\r\n}\\"),
+      r#""a {\n\tcolor: \"red\";\r\n}\\""#
+    );
+    assert_eq!(to_js_string_literal("\u{0}\u{2028}é"), r#""\u0000\u2028é""#);
+  }
+
   #[test]
   fn test_path_with_stem_suffix() {
     assert_eq!(
@@ -425,4 +767,159 @@ mod test {
       PathBuf::from("/test/test")
     );
   }
+
+  #[test]
+  fn test_new_line_kind() {
+    assert_eq!(NewLineKind::detect(""), NewLineKind::LineFeed);
+    assert_eq!(NewLineKind::detect("a\nb\r\n"), NewLineKind::LineFeed);
+    assert_eq!(
+      NewLineKind::detect("a\r\nb\n"),
+      NewLineKind::CarriageReturnLineFeed
+    );
+    assert_eq!(
+      with_new_line_kind("a\nb\r\nc\n", NewLineKind::CarriageReturnLineFeed),
+      "a\r\nb\r\nc\r\n"
+    );
+    assert_eq!(
+      with_new_line_kind("a\nb\r\nc\n", NewLineKind::LineFeed),
+      "a\nb\nc\n"
+    );
+  }
+
+  #[test]
+  fn test_prepend_statement_to_text() {
+    let mut text = format!("{}// comment\r\nconst a = 5;\r\n", BOM_CHAR);
+    prepend_statement_to_text(
+      Path::new("/mod.ts"),
+      &mut text,
+      "import \"./a.js\";",
+    );
+    assert_eq!(
+      text,
+      format!(
+        "{}// comment\r\nimport \"./a.js\";\r\nconst a = 5;\r\n",
+        BOM_CHAR
+      )
+    );
+  }
+
+  #[test]
+  fn test_prepend_statement_to_text_license_banner() {
+    let mut text = concat!(
+      "#!/usr/bin/env node\n",
+      "// @ts-nocheck\n",
+      "/*! Copyright 2022 Someone. MIT license. */\n",
+      "// @ts-ignore\n",
+      "const a: number = \"5\";\n",
+    )
+    .to_string();
+    prepend_statement_to_text(
+      Path::new("/mod.ts"),
+      &mut text,
+      "import \"./a.js\";",
+    );
+    assert_eq!(
+      text,
+      concat!(
+        "#!/usr/bin/env node\n",
+        "// @ts-nocheck\n",
+        "/*! Copyright 2022 Someone. MIT license. */\n",
+        "import \"./a.js\";\n",
+        "\n",
+        "// @ts-ignore\n",
+        "const a: number = \"5\";\n",
+      )
+    );
+
+    let mut text = "#!/usr/bin/env node\nconst a = 5;\n".to_string();
+    prepend_statement_to_text(
+      Path::new("/mod.ts"),
+      &mut text,
+      "import \"./a.js\";",
+    );
+    assert_eq!(
+      text,
+      "#!/usr/bin/env node\nimport \"./a.js\";\n\nconst a = 5;\n"
+    );
+  }
+
+  #[test]
+  fn test_get_relative_specifier() {
+    fn get_js(from: &str, to: &str) -> String {
+      get_relative_specifier(from, to, RelativeSpecifierExtension::Js)
+    }
+
+    assert_eq!(get_js("mod.ts", "other.ts"), "./other.js");
+    assert_eq!(get_js("a/mod.ts", "b/c.d.ts"), "../b/c");
+    assert_eq!(get_js("mod.ts", "other.mts"), "./other.mjs");
+    assert_eq!(get_js("mod.ts", "other.mjs"), "./other.mjs");
+    assert_eq!(get_js("mod.ts", "other.cts"), "./other.cjs");
+    assert_eq!(get_js(r"a\mod.ts", r"a\b\c.ts"), "./b/c.js");
+    assert_eq!(get_js(r"C:\a\mod.ts", r"c:\b\c.ts"), "../b/c.js");
+    assert_eq!(
+      get_js(r"\\server\share\mod.ts", r"\\server\share\a.ts"),
+      "./a.js"
+    );
+    assert_eq!(get_js("mod.ts", "my dir/a b.ts"), "./my dir/a b.js");
+    assert_eq!(get_js("mod.ts", "100%.ts"), "./100%25.js");
+    assert_eq!(get_js("mod.ts", "a#b?.ts"), "./a%23b%3F.js");
+    assert_eq!(
+      get_js("mod.ts", "\u{65e5}\u{672c}.ts"),
+      "./\u{65e5}\u{672c}.js"
+    );
+    assert_eq!(
+      get_js("mod.ts", "pkg@1.0.0+build/mod.ts"),
+      "./pkg@1.0.0+build/mod.js"
+    );
+    for (extension, expected) in [
+      (RelativeSpecifierExtension::Preserve, ["./b/c.tsx", "./b/c"]),
+      (RelativeSpecifierExtension::Strip, ["./b/c", "./b/c"]),
+    ] {
+      assert_eq!(
+        get_relative_specifier("a/mod.ts", "a/b/c.tsx", extension),
+        expected[0]
+      );
+      assert_eq!(
+        get_relative_specifier("a/mod.ts", "a/b/c.d.ts", extension),
+        expected[1]
+      );
+    }
+  }
+
+  #[test]
+  fn test_percent_decode() {
+    assert_eq!(percent_decode("a%20b"), "a b");
+    assert_eq!(percent_decode("a%2Fb%2f"), "a/b/");
+    assert_eq!(percent_decode("%E6%97%A5%E6%9C%AC"), "\u{65e5}\u{672c}");
+    assert_eq!(percent_decode("1.0.0+build"), "1.0.0+build");
+    assert_eq!(percent_decode("100%"), "100%");
+    assert_eq!(percent_decode("%zz%2"), "%zz%2");
+    assert_eq!(percent_decode("%FF"), "\u{FFFD}");
+  }
+
+  #[test]
+  fn test_url_to_file_path() {
+    let file_path =
+      |url: &str| url_to_file_path(&ModuleSpecifier::parse(url).unwrap());
+    assert_eq!(
+      file_path("file:///a/b.ts").unwrap(),
+      PathBuf::from("/a/b.ts")
+    );
+    assert_eq!(
+      file_path("file:///C:/a/b.ts").unwrap(),
+      PathBuf::from("C:/a/b.ts")
+    );
+    assert_eq!(
+      file_path("file://server/share/b.ts").unwrap(),
+      PathBuf::from("//server/share/b.ts")
+    );
+    assert_eq!(
+      file_path("file:///my%20dir/%E6%97%A5%E6%9C%AC.ts").unwrap(),
+      PathBuf::from("/my dir/\u{65e5}\u{672c}.ts")
+    );
+    assert_eq!(
+      file_path("file:///C:/a%20b.ts").unwrap(),
+      PathBuf::from("C:/a b.ts")
+    );
+  }
 }
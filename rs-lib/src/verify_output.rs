@@ -0,0 +1,194 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Result;
+use deno_ast::ModuleSpecifier;
+
+use crate::bundle::get_module_specifiers;
+use crate::bundle::is_code_file;
+use crate::bundle::is_relative_specifier;
+use crate::utils::percent_decode;
+use crate::utils::with_extension;
+use crate::virtual_path::VirtualPath;
+use crate::OutputFile;
+
+/// The relative specifiers an output file's module specifiers were
+/// rewritten to.
+pub struct RewrittenSpecifiers {
+  /// The module the file was output for, which is `None` for a file that
+  /// doesn't correspond to a single module (ex. a bundle).
+  pub specifier: Option<ModuleSpecifier>,
+  pub file_path: PathBuf,
+  pub relative_specifiers: Vec<String>,
+}
+
+/// Gets the relative specifiers of the code files from their text, which
+/// is used in place of the ones from transforming the files when they're
+/// changed afterwards (ex. by flattening the barrels or bundling).
+pub fn get_output_rewritten_specifiers<'a>(
+  files: impl Iterator<Item = &'a OutputFile>,
+  specifiers_by_path: &HashMap<&PathBuf, &ModuleSpecifier>,
+) -> Result<Vec<RewrittenSpecifiers>> {
+  let mut rewritten_specifiers = Vec::new();
+  for file in files.filter(|file| is_code_file(&file.file_path)) {
+    let relative_specifiers = get_module_specifiers(file)?
+      .into_iter()
+      .filter(|specifier| is_relative_specifier(specifier))
+      .collect::<Vec<_>>();
+    if !relative_specifiers.is_empty() {
+      rewritten_specifiers.push(RewrittenSpecifiers {
+        specifier: specifiers_by_path
+          .get(&file.file_path)
+          .map(|specifier| (*specifier).clone()),
+        file_path: file.file_path.clone(),
+        relative_specifiers,
+      });
+    }
+  }
+  Ok(rewritten_specifiers)
+}
+
+/// Verifies that every rewritten relative specifier resolves to an output
+/// file, which catches a specifier that would only fail once the package
+/// is run (ex. one that differs from the output file in case or extension).
+pub fn verify_relative_specifiers<'a>(
+  rewritten_specifiers: &[RewrittenSpecifiers],
+  output_file_paths: impl Iterator<Item = &'a PathBuf>,
+) -> Result<()> {
+  let mut paths = HashSet::new();
+  let mut paths_without_extension = HashSet::new();
+  for file_path in output_file_paths {
+    paths.insert(VirtualPath::new(file_path).to_string());
+    paths_without_extension
+      .insert(VirtualPath::new(with_extension(file_path, "")).to_string());
+  }
+
+  let mut messages = Vec::new();
+  for file in rewritten_specifiers {
+    let dir = VirtualPath::new(&file.file_path).parent().unwrap();
+    for relative_specifier in file.relative_specifiers.iter() {
+      // the characters that have a meaning in a url are percent-encoded
+      let path = dir.join(&percent_decode(relative_specifier)).to_string();
+      // the specifier may have the extension of the output file, `.js`,
+      // or no extension depending on the options
+      let is_found = paths.contains(&path)
+        || paths_without_extension.contains(&path)
+        || path
+          .strip_suffix(".js")
+          .map(|path| paths_without_extension.contains(path))
+          .unwrap_or(false);
+      if !is_found {
+        messages.push(format!(
+          "  * {} in {}{} resolves to {}",
+          relative_specifier,
+          file.file_path.display(),
+          match &file.specifier {
+            Some(specifier) => format!(" ({})", specifier),
+            None => String::new(),
+          },
+          path,
+        ));
+      }
+    }
+  }
+
+  if !messages.is_empty() {
+    bail!(
+      "The following rewritten specifiers don't resolve to an output file. This is a bug in dnt.\n{}",
+      messages.join("\n")
+    );
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn verify(relative_specifiers: &[&str]) -> Result<()> {
+    let output_file_paths = ["mod.ts", "sub/a.ts", "types.d.ts", "data.json"]
+      .iter()
+      .map(PathBuf::from)
+      .collect::<Vec<_>>();
+    verify_relative_specifiers(
+      &[RewrittenSpecifiers {
+        specifier: Some(ModuleSpecifier::parse("file:///sub/b.ts").unwrap()),
+        file_path: PathBuf::from("sub/b.ts"),
+        relative_specifiers: relative_specifiers
+          .iter()
+          .map(ToString::to_string)
+          .collect(),
+      }],
+      output_file_paths.iter(),
+    )
+  }
+
+  #[test]
+  fn resolves_relative_specifiers() {
+    assert!(verify(&[
+      "./a.js",
+      "./a.ts",
+      "./a",
+      "../mod.js",
+      "../types",
+      "../data.json"
+    ])
+    .is_ok());
+  }
+
+  #[test]
+  fn verifies_output_file_specifiers() {
+    let files = vec![
+      OutputFile {
+        file_path: PathBuf::from("mod.js"),
+        file_text: concat!(
+          "import { a } from './a.js';\n",
+          "import chalk from 'chalk';\n",
+          "export const b = () => import('./b.js');\n",
+        )
+        .to_string(),
+      },
+      OutputFile {
+        file_path: PathBuf::from("a.js"),
+        file_text: "export const a = 1;\n".to_string(),
+      },
+    ];
+    let rewritten_specifiers =
+      get_output_rewritten_specifiers(files.iter(), &HashMap::new()).unwrap();
+    let err = verify_relative_specifiers(
+      &rewritten_specifiers,
+      files.iter().map(|file| &file.file_path),
+    )
+    .err()
+    .unwrap();
+    assert_eq!(
+      err.to_string(),
+      concat!(
+        "The following rewritten specifiers don't resolve to an output file. This is a bug in dnt.\n",
+        "  * ./b.js in mod.js resolves to b.js",
+      ),
+    );
+  }
+
+  #[test]
+  fn errors_for_unresolved_specifiers() {
+    let err =
+      verify(&["./a.js", "./A.js", "../sub/c.js", "./a.tsx", "../../mod.js"])
+        .err()
+        .unwrap();
+    assert_eq!(
+      err.to_string(),
+      concat!(
+        "The following rewritten specifiers don't resolve to an output file. This is a bug in dnt.\n",
+        "  * ./A.js in sub/b.ts (file:///sub/b.ts) resolves to sub/A.js\n",
+        "  * ../sub/c.js in sub/b.ts (file:///sub/b.ts) resolves to sub/c.js\n",
+        "  * ./a.tsx in sub/b.ts (file:///sub/b.ts) resolves to sub/a.tsx\n",
+        "  * ../../mod.js in sub/b.ts (file:///sub/b.ts) resolves to ../mod.js",
+      ),
+    );
+  }
+}
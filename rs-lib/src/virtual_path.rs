@@ -0,0 +1,225 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A normalized path that's always `/` separated regardless of the
+/// platform, which is used for generating module specifiers and output
+/// file paths so that the output is the same on every platform.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VirtualPath {
+  /// Ex. `/`, `C:/`, `//server/share/`, or empty when relative.
+  root: String,
+  components: Vec<String>,
+}
+
+impl VirtualPath {
+  pub fn new(path: impl AsRef<Path>) -> Self {
+    let text = path.as_ref().to_string_lossy();
+    // remove the prefixes of verbatim (long) paths on Windows
+    let text = if let Some(unc_path) = text.strip_prefix(r"\\?\UNC\") {
+      format!("//{}", unc_path)
+    } else if let Some(path) = text.strip_prefix(r"\\?\") {
+      path.to_string()
+    } else {
+      text.to_string()
+    };
+    let text = text.replace('\\', "/");
+
+    let (root, rest) = if let Some(unc_path) = text.strip_prefix("//") {
+      // a UNC path's root includes the server and share names
+      let mut parts = unc_path.splitn(3, '/');
+      let server = parts.next().unwrap_or("");
+      let share = parts.next().unwrap_or("");
+      (
+        format!("//{}/{}/", server, share),
+        parts.next().unwrap_or(""),
+      )
+    } else if is_drive_letter_prefix(&text) {
+      (format!("{}:/", text[..1].to_uppercase()), &text[2..])
+    } else if let Some(rest) = text.strip_prefix('/') {
+      ("/".to_string(), rest)
+    } else {
+      (String::new(), text.as_str())
+    };
+
+    let mut path = Self {
+      root,
+      components: Vec::new(),
+    };
+    for component in rest.split('/') {
+      path.push_component(component);
+    }
+    path
+  }
+
+  pub fn is_absolute(&self) -> bool {
+    !self.root.is_empty()
+  }
+
+  pub fn parent(&self) -> Option<VirtualPath> {
+    if self.components.is_empty() {
+      None
+    } else {
+      Some(Self {
+        root: self.root.clone(),
+        components: self.components[..self.components.len() - 1].to_vec(),
+      })
+    }
+  }
+
+  /// Joins a relative path to this path.
+  pub fn join(&self, path: &str) -> VirtualPath {
+    let mut result = self.clone();
+    for component in VirtualPath::new(path).components {
+      result.push_component(&component);
+    }
+    result
+  }
+
+  /// Gets this path relative to the provided directory or `None` when
+  /// the paths don't share a root (ex. they're on different drives).
+  pub fn relative_to(&self, dir: &VirtualPath) -> Option<String> {
+    if self.root != dir.root {
+      return None;
+    }
+    let common_len = self
+      .components
+      .iter()
+      .zip(dir.components.iter())
+      .take_while(|(a, b)| a == b)
+      .count();
+    if dir.components[common_len..].iter().any(|c| c == "..") {
+      // can't know what directory the `..` refers to
+      return None;
+    }
+    let mut parts = vec![".."; dir.components.len() - common_len];
+    parts.extend(self.components[common_len..].iter().map(|c| c.as_str()));
+    Some(parts.join("/"))
+  }
+
+  pub fn to_path_buf(&self) -> PathBuf {
+    PathBuf::from(self.to_string())
+  }
+
+  fn push_component(&mut self, component: &str) {
+    match component {
+      "" | "." => {}
+      ".." => {
+        if self.components.last().map(|c| c != "..").unwrap_or(false) {
+          self.components.pop();
+        } else if self.root.is_empty() {
+          self.components.push(component.to_string());
+        }
+      }
+      _ => self.components.push(component.to_string()),
+    }
+  }
+}
+
+impl fmt::Display for VirtualPath {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.root)?;
+    f.write_str(&self.components.join("/"))
+  }
+}
+
+fn is_drive_letter_prefix(text: &str) -> bool {
+  let bytes = text.as_bytes();
+  bytes.len() >= 2
+    && bytes[0].is_ascii_alphabetic()
+    && bytes[1] == b':'
+    && (bytes.len() == 2 || bytes[2] == b'/')
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use pretty_assertions::assert_eq;
+
+  #[test]
+  fn normalizes_paths() {
+    let cases = [
+      ("/a/b/c.ts", "/a/b/c.ts"),
+      ("/a/./b/../c.ts", "/a/c.ts"),
+      ("/../a.ts", "/a.ts"),
+      ("a/b.ts", "a/b.ts"),
+      ("./a/../../b.ts", "../b.ts"),
+      (r"a\b.ts", "a/b.ts"),
+      (r"C:\a\b.ts", "C:/a/b.ts"),
+      ("c:/a/b.ts", "C:/a/b.ts"),
+      ("C:", "C:/"),
+      (r"\\server\share\a\b.ts", "//server/share/a/b.ts"),
+      ("//server/share/a.ts", "//server/share/a.ts"),
+      (r"\\?\C:\a\b.ts", "C:/a/b.ts"),
+      (r"\\?\UNC\server\share\a.ts", "//server/share/a.ts"),
+    ];
+    for (input, expected) in cases {
+      let path = VirtualPath::new(input);
+      assert_eq!(path.to_string(), expected, "input: {}", input);
+      // normalizing again shouldn't change anything
+      assert_eq!(VirtualPath::new(path.to_string()), path, "input: {}", input);
+    }
+  }
+
+  #[test]
+  fn gets_relative_paths() {
+    let cases = [
+      ("/a/b/c.ts", "/a/b", Some("c.ts")),
+      ("/a/c.ts", "/a/b", Some("../c.ts")),
+      ("/d/e.ts", "/a/b/c", Some("../../../d/e.ts")),
+      (r"C:\a\b.ts", "c:/a", Some("b.ts")),
+      (r"C:\a\b.ts", r"D:\a", None),
+      (
+        r"\\server\share\a\b.ts",
+        r"\\server\share\c",
+        Some("../a/b.ts"),
+      ),
+      (r"\\server\share\a.ts", r"\\other\share", None),
+      (r"\\?\C:\a\b\c.ts", r"C:\a", Some("b/c.ts")),
+      ("a/b.ts", "c", Some("../a/b.ts")),
+      ("a/b.ts", "../c", None),
+    ];
+    for (path, dir, expected) in cases {
+      assert_eq!(
+        VirtualPath::new(path).relative_to(&VirtualPath::new(dir)),
+        expected.map(ToString::to_string),
+        "path: {}, dir: {}",
+        path,
+        dir,
+      );
+    }
+  }
+
+  #[test]
+  fn relative_paths_resolve_to_path() {
+    let long_dir = "very/long/path/".repeat(30);
+    let roots = [
+      "/",
+      "C:/",
+      r"\\server\share\",
+      r"\\?\C:\",
+      r"\\?\UNC\server\share\",
+    ];
+    let dirs = ["", "a", r"a\b", "a/b/c", long_dir.as_str()];
+    let files = ["mod.ts", r"a\mod.ts", "x/y/z.d.ts", "a/b/c/d.ts"];
+    for root in roots {
+      for dir in dirs {
+        for file in files {
+          let dir = VirtualPath::new(format!("{}{}", root, dir));
+          let path = dir.join(file);
+          assert!(path.is_absolute());
+          assert!(!path.to_string().contains('\\'));
+          let relative = path.relative_to(&dir).unwrap();
+          assert_eq!(dir.join(&relative), path, "dir: {}, file: {}", dir, file);
+          if let Some(parent) = dir.parent() {
+            let relative = path.relative_to(&parent).unwrap();
+            assert!(!relative.contains('\\'));
+            assert_eq!(parent.join(&relative), path);
+          }
+        }
+      }
+    }
+  }
+}
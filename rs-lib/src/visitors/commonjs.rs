@@ -0,0 +1,344 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use deno_ast::swc::common::SyntaxContext;
+use deno_ast::view::*;
+use deno_ast::MediaType;
+use deno_ast::ModuleSpecifier;
+use deno_ast::SourceRanged;
+use deno_ast::SourceTextInfoProvider;
+use deno_ast::TextChange;
+
+use super::globals::get_unique_name;
+use super::NodeRule;
+use crate::analyze::is_in_type;
+use crate::utils::text_change_for_prepend_statement_to_text;
+use crate::CommonJsModules;
+use crate::TransformError;
+
+/// Comment that enables injecting `__dirname` and `__filename` in a file.
+const INJECT_DIRNAME_DIRECTIVE: &str = "dnt-inject-dirname";
+
+pub struct GetCommonJsParams<'a> {
+  pub specifier: &'a ModuleSpecifier,
+  pub program: &'a Program<'a>,
+  pub media_type: MediaType,
+  pub is_declaration_file: bool,
+  pub unresolved_context: SyntaxContext,
+  pub shim_global_names: &'a HashSet<&'a str>,
+  pub ignore_line_indexes: &'a HashSet<usize>,
+  pub top_level_decls: &'a HashSet<String>,
+  /// Declare `__dirname` and `__filename` when they're used instead of
+  /// warning about them.
+  pub inject_dirname: bool,
+  pub commonjs_modules: CommonJsModules,
+}
+
+pub struct GetCommonJsResult {
+  pub warnings: Vec<String>,
+  pub text_changes: Vec<TextChange>,
+  /// Changes that wrap the code of a CommonJS module, which go after any
+  /// statements the other changes add to the top of the module.
+  pub wrapper_text_changes: Vec<TextChange>,
+}
+
+/// Rule that warns about references to CommonJS and Node.js globals
+/// (ex. `require` or `__dirname`) that don't exist in ES modules and
+/// aren't being shimmed, and about `export =` assignments. When enabled, `__dirname` and `__filename` are
+/// declared at the top of the module instead.
+///
+/// JavaScript modules that are CommonJS are an error or are wrapped in a
+/// function that provides the CommonJS globals.
+pub struct CommonJsRule<'a> {
+  params: &'a GetCommonJsParams<'a>,
+  inject_dirname: bool,
+  warnings: Vec<String>,
+  /// Warnings about the CommonJS globals, which don't apply when the
+  /// module is wrapped.
+  commonjs_global_warnings: Vec<String>,
+  uses_commonjs_globals: bool,
+  used_dirname: bool,
+  used_filename: bool,
+  all_ident_names: HashSet<String>,
+}
+
+impl<'a> CommonJsRule<'a> {
+  pub fn new(params: &'a GetCommonJsParams<'a>) -> Self {
+    let inject_dirname = params.inject_dirname
+      || params
+        .program
+        .comment_container()
+        .all_comments()
+        .any(|c| c.text.trim().to_lowercase() == INJECT_DIRNAME_DIRECTIVE);
+    Self {
+      params,
+      inject_dirname,
+      warnings: Vec::new(),
+      commonjs_global_warnings: Vec::new(),
+      uses_commonjs_globals: false,
+      used_dirname: false,
+      used_filename: false,
+      all_ident_names: HashSet::new(),
+    }
+  }
+
+  pub fn into_result(mut self) -> Result<GetCommonJsResult> {
+    let mut wrapper_text_changes = Vec::new();
+    if self.is_commonjs_module() {
+      match self.params.commonjs_modules {
+        CommonJsModules::Error => {
+          return Err(
+            TransformError::CommonJsModule {
+              specifier: self.params.specifier.clone(),
+              message: format!(
+                concat!(
+                  "{} is a CommonJS module, which can't be output as an ES module. ",
+                  "Convert it to an ES module or wrap it by setting the CommonJS modules option to wrap."
+                ),
+                self.params.specifier,
+              ),
+            }
+            .into(),
+          );
+        }
+        CommonJsModules::Wrap => {
+          wrapper_text_changes = self.get_wrapper_text_changes();
+        }
+      }
+    } else {
+      self.warnings.append(&mut self.commonjs_global_warnings);
+    }
+
+    let mut text_changes = Vec::new();
+    if self.used_dirname || self.used_filename {
+      let file_url_to_path_name =
+        get_unique_name("dntFileURLToPath", &self.all_ident_names);
+      let mut statements = vec![format!(
+        "import {{ fileURLToPath as {} }} from \"node:url\";",
+        file_url_to_path_name
+      )];
+      let mut filename_text =
+        format!("{}(import.meta.url)", file_url_to_path_name);
+      if self.used_dirname {
+        let dirname_name = get_unique_name("dntDirname", &self.all_ident_names);
+        statements.push(format!(
+          "import {{ dirname as {} }} from \"node:path\";",
+          dirname_name
+        ));
+        if self.used_filename {
+          statements.push(format!("const __filename = {};", filename_text));
+          filename_text = "__filename".to_string();
+        }
+        statements.push(format!(
+          "const __dirname = {}({});",
+          dirname_name, filename_text
+        ));
+      } else {
+        statements.push(format!("const __filename = {};", filename_text));
+      }
+      text_changes.push(text_change_for_prepend_statement_to_text(
+        self.params.program,
+        &statements.join("\n"),
+      ));
+    }
+
+    Ok(GetCommonJsResult {
+      warnings: self.warnings,
+      text_changes,
+      wrapper_text_changes,
+    })
+  }
+
+  fn may_be_commonjs_module(&self) -> bool {
+    matches!(
+      self.params.media_type,
+      MediaType::JavaScript | MediaType::Jsx | MediaType::Cjs
+    )
+  }
+
+  /// Gets if this is a JavaScript module that uses the CommonJS globals
+  /// without any import or export declarations, or is a `.cjs` module.
+  fn is_commonjs_module(&self) -> bool {
+    match self.params.media_type {
+      MediaType::Cjs => true,
+      MediaType::JavaScript | MediaType::Jsx => {
+        self.uses_commonjs_globals && !has_module_decls(self.params.program)
+      }
+      _ => false,
+    }
+  }
+
+  fn get_wrapper_text_changes(&self) -> Vec<TextChange> {
+    let program = self.params.program;
+    let create_require_name =
+      get_unique_name("dntCreateRequire", &self.all_ident_names);
+    let module_name = get_unique_name("dntModule", &self.all_ident_names);
+    let text = program.text_info().text_str();
+    let header = text_change_for_prepend_statement_to_text(
+      program,
+      &format!(
+        concat!(
+          "import {{ createRequire as {} }} from \"node:module\";\n",
+          "const {} = {{ exports: {{}} }};\n",
+          "(function (module, exports, require) {{",
+        ),
+        create_require_name, module_name,
+      ),
+    );
+    let footer = TextChange {
+      range: text.len()..text.len(),
+      new_text: format!(
+        concat!(
+          "{}}}).call({module}.exports, {module}, {module}.exports, {}(import.meta.url));\n",
+          "export default {module}.exports;\n",
+        ),
+        if text.ends_with('\n') { "" } else { "\n" },
+        create_require_name,
+        module = module_name,
+      ),
+    };
+    vec![header, footer]
+  }
+
+  fn get_suggestion(&self, ident: &Ident) -> Option<&'static str> {
+    let params = self.params;
+    if params.is_declaration_file {
+      return None;
+    }
+    // identifiers that are declared in the module get a different context,
+    // so this excludes anything shadowing the global
+    if ident.inner.to_id().1 != params.unresolved_context {
+      return None;
+    }
+
+    let name = ident.text_fast(params.program);
+    let suggestion = match name {
+      "require" => "Use an import declaration or `createRequire` from \"node:module\" instead.",
+      "module" if is_module_exports(ident) => {
+        "Use an export declaration instead of `module.exports`."
+      }
+      "exports" => "Use an export declaration instead.",
+      "__dirname" | "__filename" => {
+        "Use `import.meta.url` with `fileURLToPath` from \"node:url\" instead."
+      }
+      "Buffer" => "Import it from \"node:buffer\" or configure a shim for it.",
+      "process" => {
+        "Import it from \"node:process\" or configure a shim for it."
+      }
+      _ => return None,
+    };
+    if params.shim_global_names.contains(name)
+      || params.top_level_decls.contains(name)
+      || params
+        .ignore_line_indexes
+        .contains(&ident.start_line_fast(params.program))
+      || is_in_type(ident.into())
+    {
+      return None;
+    }
+    Some(suggestion)
+  }
+}
+
+impl<'a> CommonJsRule<'a> {
+  /// Warns about `export =`, which only exists for CommonJS output.
+  fn visit_export_assignment(
+    &mut self,
+    export_assignment: &TsExportAssignment,
+  ) {
+    let program = self.params.program;
+    if self
+      .params
+      .ignore_line_indexes
+      .contains(&export_assignment.start_line_fast(program))
+    {
+      return;
+    }
+    let expr_text = export_assignment.expr.text_fast(program);
+    let suggestion = if expr_text.contains('\n') {
+      "Use `export default` instead.".to_string()
+    } else {
+      format!("Use `export default {};` instead.", expr_text)
+    };
+    let position = program
+      .text_info()
+      .line_and_column_display(export_assignment.start());
+    self.warnings.push(format!(
+      "`export =` is not available in ES modules. {}\n    at {}:{}:{}",
+      suggestion,
+      self.params.specifier,
+      position.line_number,
+      position.column_number,
+    ));
+  }
+}
+
+impl<'a> NodeRule<'a> for CommonJsRule<'a> {
+  fn visit_node(&mut self, node: Node<'a>) -> Result<()> {
+    if let Node::TsExportAssignment(export_assignment) = node {
+      self.visit_export_assignment(export_assignment);
+      return Ok(());
+    }
+    if let Node::Ident(ident) = node {
+      if self.inject_dirname || self.may_be_commonjs_module() {
+        self.all_ident_names.insert(ident.sym().to_string());
+      }
+      if let Some(suggestion) = self.get_suggestion(ident) {
+        let program = self.params.program;
+        let name = ident.text_fast(program);
+        let is_commonjs_global =
+          matches!(name, "require" | "module" | "exports");
+        if is_commonjs_global {
+          self.uses_commonjs_globals = true;
+        }
+        if self.inject_dirname && name == "__dirname" {
+          self.used_dirname = true;
+        } else if self.inject_dirname && name == "__filename" {
+          self.used_filename = true;
+        } else {
+          let position =
+            program.text_info().line_and_column_display(ident.start());
+          let warning = format!(
+            "{} is not available in ES modules. {}\n    at {}:{}:{}",
+            name,
+            suggestion,
+            self.params.specifier,
+            position.line_number,
+            position.column_number,
+          );
+          if is_commonjs_global {
+            self.commonjs_global_warnings.push(warning);
+          } else {
+            self.warnings.push(warning);
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+fn has_module_decls(program: &Program) -> bool {
+  match program {
+    Program::Module(module) => module
+      .body
+      .iter()
+      .any(|item| matches!(item, ModuleItem::ModuleDecl(_))),
+    Program::Script(_) => false,
+  }
+}
+
+fn is_module_exports(ident: &Ident) -> bool {
+  match ident.parent() {
+    Node::MemberExpr(member_expr) => {
+      member_expr.obj.range() == ident.range()
+        && matches!(
+          member_expr.prop,
+          MemberProp::Ident(prop) if prop.sym().as_ref() == "exports"
+        )
+    }
+    _ => false,
+  }
+}
@@ -0,0 +1,903 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashSet;
+
+use deno_ast::swc::ast::Accessibility;
+use deno_ast::swc::ast::MethodKind;
+use deno_ast::swc::ast::UnaryOp;
+use deno_ast::swc::ast::VarDeclKind;
+use deno_ast::view::*;
+use deno_ast::ModuleSpecifier;
+use deno_ast::SourcePos;
+use deno_ast::SourceRange;
+use deno_ast::SourceRanged;
+use deno_ast::SourceTextInfoProvider;
+use deno_ast::TextChange;
+
+use crate::text_changes::to_byte_range;
+
+pub struct GetDeclarationTextChangesParams<'a> {
+  pub specifier: &'a ModuleSpecifier,
+  pub program: Program<'a>,
+}
+
+pub struct DeclarationTextChanges {
+  pub text_changes: Vec<TextChange>,
+  /// Declarations that were declared as `any` because their type can't
+  /// be determined without their value.
+  pub warnings: Vec<String>,
+}
+
+/// Gets the text changes that reduce a TypeScript module to its type
+/// declarations, which removes the bodies of functions, methods, and
+/// accessors, the initializers of variables and properties, and the
+/// statements other than declarations. The declarations that remain are
+/// made ambient with `declare`.
+///
+/// Imports and exports are kept so the module can be output like any
+/// other module.
+pub fn get_declaration_text_changes(
+  params: &GetDeclarationTextChangesParams,
+) -> DeclarationTextChanges {
+  let mut context = Context {
+    params,
+    text_changes: Vec::new(),
+    warnings: Vec::new(),
+  };
+  if let Program::Module(module) = params.program {
+    context.visit_module_items(&module.body, false);
+  }
+  DeclarationTextChanges {
+    text_changes: context.text_changes,
+    warnings: context.warnings,
+  }
+}
+
+struct Context<'a> {
+  params: &'a GetDeclarationTextChangesParams<'a>,
+  text_changes: Vec<TextChange>,
+  warnings: Vec<String>,
+}
+
+impl<'a> Context<'a> {
+  fn text(&self, range: &impl SourceRanged) -> &'a str {
+    range.text_fast(&self.params.program)
+  }
+
+  fn replace(&mut self, range: SourceRange, new_text: String) {
+    self.text_changes.push(TextChange {
+      range: to_byte_range(self.params.program.text_info(), range),
+      new_text,
+    });
+  }
+
+  fn remove(&mut self, range: SourceRange) {
+    self.replace(range, String::new());
+  }
+
+  /// Removes the text of a range along with its line when nothing else
+  /// is on it, so no blank line is left behind.
+  fn remove_line(&mut self, range: SourceRange) {
+    let text_info = self.params.program.text_info();
+    let text = text_info.text_str();
+    let mut byte_range = to_byte_range(text_info, range);
+    let is_space = |c: char| c == ' ' || c == '\t';
+    let line_start = text[..byte_range.start].trim_end_matches(is_space).len();
+    let rest = &text[byte_range.end..];
+    let after = rest.trim_start_matches(is_space);
+    let newline_len = if after.starts_with("\r\n") {
+      Some(2)
+    } else if after.starts_with('\n') {
+      Some(1)
+    } else if after.is_empty() {
+      Some(0)
+    } else {
+      None
+    };
+    let is_line_start = line_start == 0 || text[..line_start].ends_with('\n');
+    if let Some(newline_len) = newline_len.filter(|_| is_line_start) {
+      byte_range =
+        line_start..byte_range.end + (rest.len() - after.len()) + newline_len;
+    }
+    self.text_changes.push(TextChange {
+      range: byte_range,
+      new_text: String::new(),
+    });
+  }
+
+  fn insert(&mut self, pos: SourcePos, new_text: &str) {
+    if !new_text.is_empty() {
+      self.replace(SourceRange::new(pos, pos), new_text.to_string());
+    }
+  }
+
+  fn warn_any(&mut self, description: &str, pos: SourcePos) {
+    let position = self.params.program.text_info().line_and_column_display(pos);
+    self.warnings.push(format!(
+      "Declared {} as `any` because its type can't be determined without its value. Add a type annotation to keep its type.\n    at {}:{}:{}",
+      description,
+      self.params.specifier,
+      position.line_number,
+      position.column_number,
+    ));
+  }
+
+  /// Gets the whitespace before the position on its line.
+  fn get_indent(&self, pos: SourcePos) -> String {
+    let text_info = self.params.program.text_info();
+    let line_start = text_info.line_start(text_info.line_index(pos));
+    let text = &text_info.text_str()
+      [to_byte_range(text_info, SourceRange::new(line_start, pos))];
+    text.chars().take_while(|c| c.is_whitespace()).collect()
+  }
+
+  fn visit_module_items(&mut self, items: &[ModuleItem], in_ambient: bool) {
+    let overloaded_names = get_overloaded_function_names(items);
+    for item in items.iter() {
+      match item {
+        ModuleItem::ModuleDecl(module_decl) => match module_decl {
+          ModuleDecl::ExportDecl(export_decl) => {
+            self.visit_decl(
+              &export_decl.decl,
+              export_decl.range(),
+              in_ambient,
+              &overloaded_names,
+            );
+          }
+          ModuleDecl::ExportDefaultDecl(export_default_decl) => {
+            self.visit_export_default_decl(export_default_decl);
+          }
+          ModuleDecl::ExportDefaultExpr(export_default_expr) => {
+            self.visit_export_default_expr(export_default_expr);
+          }
+          ModuleDecl::Import(_)
+          | ModuleDecl::ExportNamed(_)
+          | ModuleDecl::ExportAll(_)
+          | ModuleDecl::TsImportEquals(_)
+          | ModuleDecl::TsExportAssignment(_)
+          | ModuleDecl::TsNamespaceExport(_) => {}
+        },
+        ModuleItem::Stmt(Stmt::Decl(decl)) => {
+          self.visit_decl(decl, decl.range(), in_ambient, &overloaded_names);
+        }
+        ModuleItem::Stmt(Stmt::Empty(_)) => {}
+        ModuleItem::Stmt(stmt) => self.remove_line(stmt.range()),
+      }
+    }
+  }
+
+  /// Visits a declaration, where the item range includes the `export`
+  /// keyword of an exported declaration.
+  fn visit_decl(
+    &mut self,
+    decl: &Decl,
+    item_range: SourceRange,
+    in_ambient: bool,
+    overloaded_names: &HashSet<String>,
+  ) {
+    let declare = if in_ambient { "" } else { "declare " };
+    match decl {
+      Decl::Fn(fn_decl) => {
+        if fn_decl.inner.declare {
+          return;
+        }
+        let name = fn_decl.ident.sym().to_string();
+        if fn_decl.function.body.is_none() {
+          // overloads become ambient along with the implementation
+          self.insert(fn_decl.start(), declare);
+        } else if overloaded_names.contains(&name) {
+          // the overloads are the declaration
+          self.remove_line(item_range);
+        } else {
+          let signature = self.get_signature_text(
+            fn_decl.function,
+            &name,
+            fn_decl.ident.start(),
+          );
+          self.replace(
+            fn_decl.range(),
+            format!("{}function {}{};", declare, name, signature),
+          );
+        }
+      }
+      Decl::Class(class_decl) => {
+        if class_decl.inner.declare {
+          return;
+        }
+        let keyword_start = self.get_class_keyword_start(class_decl.class);
+        self.insert(keyword_start, declare);
+        self.visit_class(class_decl.class);
+      }
+      Decl::Var(var_decl) => {
+        if var_decl.inner.declare {
+          return;
+        }
+        self.visit_var_decl(var_decl, declare);
+      }
+      Decl::TsEnum(enum_decl) => {
+        if !enum_decl.inner.declare {
+          self.insert(enum_decl.start(), declare);
+        }
+      }
+      Decl::TsModule(module_decl) => {
+        if module_decl.inner.declare || module_decl.inner.global {
+          return;
+        }
+        self.insert(module_decl.start(), declare);
+        let mut body = module_decl.body;
+        while let Some(namespace_body) = body {
+          match namespace_body {
+            TsNamespaceBody::TsModuleBlock(block) => {
+              self.visit_module_items(&block.body, true);
+              body = None;
+            }
+            TsNamespaceBody::TsNamespaceDecl(namespace_decl) => {
+              body = Some(namespace_decl.body);
+            }
+          }
+        }
+      }
+      Decl::TsInterface(_) | Decl::TsTypeAlias(_) => {}
+    }
+  }
+
+  fn visit_export_default_decl(
+    &mut self,
+    export_default_decl: &ExportDefaultDecl,
+  ) {
+    match &export_default_decl.decl {
+      DefaultDecl::Fn(fn_expr) => {
+        let name = fn_expr
+          .ident
+          .map(|ident| ident.sym().to_string())
+          .unwrap_or_else(|| "_default".to_string());
+        let signature = self.get_signature_text(
+          fn_expr.function,
+          &name,
+          export_default_decl.start(),
+        );
+        self.replace(
+          export_default_decl.range(),
+          format!(
+            "declare function {}{};\nexport default {};",
+            name, signature, name
+          ),
+        );
+      }
+      DefaultDecl::Class(class_expr) => {
+        let class = class_expr.class;
+        let class_start = class
+          .decorators
+          .first()
+          .map(|decorator| decorator.start())
+          .unwrap_or_else(|| class.start())
+          .min(class.start());
+        // remove the `export default` and export the name afterwards
+        self.remove(SourceRange::new(export_default_decl.start(), class_start));
+        let keyword_start = self.get_class_keyword_start(class);
+        self.insert(keyword_start, "declare ");
+        let name = match class_expr.ident {
+          Some(ident) => ident.sym().to_string(),
+          None => {
+            let text = self.text(&SourceRange::new(keyword_start, class.end()));
+            let class_keyword_end =
+              keyword_start + text.find("class").unwrap_or(0) + "class".len();
+            self.insert(class_keyword_end, " _default");
+            "_default".to_string()
+          }
+        };
+        self.visit_class(class);
+        self.insert(
+          export_default_decl.end(),
+          &format!("\nexport default {};", name),
+        );
+      }
+      DefaultDecl::TsInterfaceDecl(_) => {}
+    }
+  }
+
+  fn visit_export_default_expr(
+    &mut self,
+    export_default_expr: &ExportDefaultExpr,
+  ) {
+    if matches!(export_default_expr.expr, Expr::Ident(_)) {
+      return;
+    }
+    let type_text = match self.get_expr_type_text(export_default_expr.expr) {
+      Some(type_text) => type_text,
+      None => {
+        self.warn_any("the default export", export_default_expr.start());
+        "any".to_string()
+      }
+    };
+    self.replace(
+      export_default_expr.range(),
+      format!(
+        "declare const _default: {};\nexport default _default;",
+        type_text
+      ),
+    );
+  }
+
+  fn visit_var_decl(&mut self, var_decl: &VarDecl, declare: &str) {
+    let kind = match var_decl.inner.kind {
+      VarDeclKind::Var => "var",
+      VarDeclKind::Let => "let",
+      VarDeclKind::Const => "const",
+    };
+    let mut declarators = Vec::with_capacity(var_decl.decls.len());
+    for declarator in var_decl.decls.iter() {
+      match declarator.name {
+        Pat::Ident(binding_ident) => {
+          let name = binding_ident.id.sym().to_string();
+          let type_text = match (binding_ident.type_ann, declarator.init) {
+            (Some(type_ann), _) => self.text(&type_ann.type_ann).to_string(),
+            // ambient constants can keep a literal value
+            (None, Some(init))
+              if kind == "const" && is_primitive_literal(init) =>
+            {
+              declarators.push(format!("{} = {}", name, self.text(&init)));
+              continue;
+            }
+            (None, init) => {
+              match init.and_then(|init| self.get_expr_type_text(init)) {
+                Some(type_text) => type_text,
+                None => {
+                  self.warn_any(&format!("`{}`", name), binding_ident.start());
+                  "any".to_string()
+                }
+              }
+            }
+          };
+          declarators.push(format!("{}: {}", name, type_text));
+        }
+        // ambient declarations can't be destructured, so each name
+        // is declared on its own
+        pat => {
+          for ident in get_pat_binding_idents(pat) {
+            let name = ident.sym().to_string();
+            self.warn_any(&format!("`{}`", name), ident.start());
+            declarators.push(format!("{}: any", name));
+          }
+        }
+      }
+    }
+    let semicolon = if self.text(var_decl).ends_with(';') {
+      ";"
+    } else {
+      ""
+    };
+    self.replace(
+      var_decl.range(),
+      format!(
+        "{}{} {}{}",
+        declare,
+        kind,
+        declarators.join(", "),
+        semicolon
+      ),
+    );
+  }
+
+  fn visit_class(&mut self, class: &Class) {
+    for decorator in class.decorators.iter() {
+      self.remove(decorator.range());
+    }
+    let overloaded_keys = class
+      .body
+      .iter()
+      .filter_map(|member| match member {
+        ClassMember::Method(method) if method.function.body.is_none() => {
+          Some(self.text(&method.key).to_string())
+        }
+        ClassMember::Constructor(ctor) if ctor.body.is_none() => {
+          Some("constructor".to_string())
+        }
+        _ => None,
+      })
+      .collect::<HashSet<_>>();
+    for member in class.body.iter() {
+      match member {
+        ClassMember::Constructor(ctor) => {
+          if ctor.body.is_some() {
+            self.visit_constructor(ctor, &overloaded_keys);
+          }
+        }
+        ClassMember::Method(method) => {
+          if method.function.body.is_some() {
+            self.visit_method(method, &overloaded_keys);
+          }
+        }
+        ClassMember::ClassProp(prop) => self.visit_class_prop(prop),
+        ClassMember::PrivateMethod(_)
+        | ClassMember::PrivateProp(_)
+        | ClassMember::StaticBlock(_) => self.remove_line(member.range()),
+        ClassMember::TsIndexSignature(_) | ClassMember::Empty(_) => {}
+      }
+    }
+  }
+
+  fn visit_constructor(
+    &mut self,
+    ctor: &Constructor,
+    overloaded_keys: &HashSet<String>,
+  ) {
+    let range = get_member_range(ctor.range(), &[]);
+    if overloaded_keys.contains("constructor") {
+      self.remove_line(range);
+      return;
+    }
+    let indent = self.get_indent(range.start);
+    let mut text = String::new();
+    let mut params = Vec::with_capacity(ctor.params.len());
+    for param in ctor.params.iter() {
+      match param {
+        ParamOrTsParamProp::Param(param) => {
+          params.push(self.get_param_text(param.pat))
+        }
+        // parameter properties are only allowed in an implementation,
+        // so they're declared as properties of the class
+        ParamOrTsParamProp::TsParamProp(param_prop) => {
+          let (pat, prop_text) = match param_prop.param {
+            TsParamPropParam::Ident(binding_ident) => (
+              Pat::Ident(binding_ident),
+              self.get_param_text(Pat::Ident(binding_ident)),
+            ),
+            TsParamPropParam::Assign(assign_pat) => (
+              Pat::Assign(assign_pat),
+              format!(
+                "{}: {}",
+                self.get_binding_text(assign_pat.left),
+                self.get_assign_type_text(assign_pat)
+              ),
+            ),
+          };
+          text
+            .push_str(&get_accessibility_text(param_prop.inner.accessibility));
+          if param_prop.inner.is_override {
+            text.push_str("override ");
+          }
+          if param_prop.inner.readonly {
+            text.push_str("readonly ");
+          }
+          text.push_str(&prop_text);
+          text.push_str(";\n");
+          text.push_str(&indent);
+          params.push(self.get_param_text(pat));
+        }
+      }
+    }
+    text.push_str(&get_accessibility_text(ctor.inner.accessibility));
+    text.push_str(&format!("constructor({});", params.join(", ")));
+    self.replace(range, text);
+  }
+
+  fn visit_method(
+    &mut self,
+    method: &ClassMethod,
+    overloaded_keys: &HashSet<String>,
+  ) {
+    let range = get_member_range(method.range(), &method.function.decorators);
+    let key = self.text(&method.key);
+    if overloaded_keys.contains(key) {
+      self.remove_line(range);
+      return;
+    }
+    let mut text = get_accessibility_text(method.inner.accessibility);
+    if method.inner.is_static {
+      text.push_str("static ");
+    }
+    if method.inner.is_override {
+      text.push_str("override ");
+    }
+    match method.inner.kind {
+      MethodKind::Getter => text.push_str("get "),
+      MethodKind::Setter => text.push_str("set "),
+      MethodKind::Method => {}
+    }
+    text.push_str(key);
+    if method.inner.is_optional {
+      text.push('?');
+    }
+    let signature = if method.inner.kind == MethodKind::Setter {
+      // setters can't have a return type
+      let params = method
+        .function
+        .params
+        .iter()
+        .map(|param| self.get_param_text(param.pat))
+        .collect::<Vec<_>>();
+      format!("({})", params.join(", "))
+    } else {
+      self.get_signature_text(method.function, key, method.key.start())
+    };
+    text.push_str(&signature);
+    text.push(';');
+    self.replace(range, text);
+  }
+
+  fn visit_class_prop(&mut self, prop: &ClassProp) {
+    for decorator in prop.decorators.iter() {
+      self.remove(decorator.range());
+    }
+    let value = match prop.value {
+      Some(value) => value,
+      None => return,
+    };
+    // remove the initializer along with the definite assignment assertion
+    let new_text = match prop.type_ann {
+      Some(type_ann) => {
+        self.replace(
+          SourceRange::new(prop.key.end(), type_ann.start()),
+          if prop.inner.is_optional { "?" } else { "" }.to_string(),
+        );
+        self.remove(SourceRange::new(type_ann.end(), value.end()));
+        return;
+      }
+      None => match self.get_expr_type_text(value) {
+        Some(type_text) => type_text,
+        None => {
+          self
+            .warn_any(&format!("`{}`", self.text(&prop.key)), prop.key.start());
+          "any".to_string()
+        }
+      },
+    };
+    self.replace(
+      SourceRange::new(prop.key.end(), value.end()),
+      format!(
+        "{}: {}",
+        if prop.inner.is_optional { "?" } else { "" },
+        new_text
+      ),
+    );
+  }
+
+  /// Gets the type parameters, parameters, and return type of a function
+  /// (ex. `<T>(value: T): T`).
+  fn get_signature_text(
+    &mut self,
+    function: &Function,
+    name: &str,
+    pos: SourcePos,
+  ) -> String {
+    let params = function
+      .params
+      .iter()
+      .map(|param| self.get_param_text(param.pat))
+      .collect::<Vec<_>>();
+    let return_type = match function.return_type {
+      Some(return_type) => self.text(&return_type.type_ann).to_string(),
+      None => self.get_inferred_return_type(
+        function.body.map(|body| body.as_node()),
+        function.inner.is_async,
+        function.inner.is_generator,
+        name,
+        pos,
+      ),
+    };
+    format!(
+      "{}({}): {}",
+      function.type_params.map(|p| self.text(p)).unwrap_or(""),
+      params.join(", "),
+      return_type,
+    )
+  }
+
+  /// Gets the return type of a function without a return type annotation,
+  /// which is only known when it doesn't return a value.
+  fn get_inferred_return_type(
+    &mut self,
+    body: Option<Node>,
+    is_async: bool,
+    is_generator: bool,
+    name: &str,
+    pos: SourcePos,
+  ) -> String {
+    let returns_value = body.map(has_return_value).unwrap_or(true);
+    match (returns_value || is_generator, is_async) {
+      (false, false) => "void".to_string(),
+      (false, true) => "Promise<void>".to_string(),
+      (true, _) => {
+        self.warn_any(&format!("the return type of `{}`", name), pos);
+        "any".to_string()
+      }
+    }
+  }
+
+  /// Gets the text of a parameter without its default value, which makes
+  /// it optional instead.
+  fn get_param_text(&self, pat: Pat) -> String {
+    match pat {
+      Pat::Assign(assign_pat) => format!(
+        "{}?: {}",
+        self.get_binding_text(assign_pat.left),
+        self.get_assign_type_text(assign_pat)
+      ),
+      Pat::Rest(rest_pat) => format!(
+        "...{}{}",
+        self.get_binding_text(rest_pat.arg),
+        self.get_type_ann_text(rest_pat.type_ann)
+      ),
+      Pat::Ident(binding_ident) => format!(
+        "{}{}{}",
+        binding_ident.id.sym(),
+        if binding_ident.id.inner.optional {
+          "?"
+        } else {
+          ""
+        },
+        self.get_type_ann_text(binding_ident.type_ann)
+      ),
+      Pat::Object(object_pat) => format!(
+        "{}{}{}",
+        self.get_binding_text(pat),
+        if object_pat.inner.optional { "?" } else { "" },
+        self.get_type_ann_text(object_pat.type_ann)
+      ),
+      Pat::Array(array_pat) => format!(
+        "{}{}{}",
+        self.get_binding_text(pat),
+        if array_pat.inner.optional { "?" } else { "" },
+        self.get_type_ann_text(array_pat.type_ann)
+      ),
+      Pat::Expr(_) | Pat::Invalid(_) => self.text(&pat).to_string(),
+    }
+  }
+
+  /// Gets the text of a binding pattern without its default values or
+  /// type annotations.
+  fn get_binding_text(&self, pat: Pat) -> String {
+    match pat {
+      Pat::Ident(binding_ident) => binding_ident.id.sym().to_string(),
+      Pat::Assign(assign_pat) => self.get_binding_text(assign_pat.left),
+      Pat::Rest(rest_pat) => {
+        format!("...{}", self.get_binding_text(rest_pat.arg))
+      }
+      Pat::Object(object_pat) => {
+        let props = object_pat
+          .props
+          .iter()
+          .map(|prop| match prop {
+            ObjectPatProp::KeyValue(key_value) => format!(
+              "{}: {}",
+              self.text(&key_value.key),
+              self.get_binding_text(key_value.value)
+            ),
+            ObjectPatProp::Assign(assign) => assign.key.sym().to_string(),
+            ObjectPatProp::Rest(rest_pat) => {
+              format!("...{}", self.get_binding_text(rest_pat.arg))
+            }
+          })
+          .collect::<Vec<_>>();
+        if props.is_empty() {
+          "{}".to_string()
+        } else {
+          format!("{{ {} }}", props.join(", "))
+        }
+      }
+      Pat::Array(array_pat) => {
+        let elems = array_pat
+          .elems
+          .iter()
+          .map(|elem| {
+            elem
+              .map(|elem| self.get_binding_text(elem))
+              .unwrap_or_default()
+          })
+          .collect::<Vec<_>>();
+        format!("[{}]", elems.join(", "))
+      }
+      Pat::Expr(_) | Pat::Invalid(_) => self.text(&pat).to_string(),
+    }
+  }
+
+  /// Gets the type of a parameter with a default value, which is taken
+  /// from the value when it isn't annotated.
+  fn get_assign_type_text(&self, assign_pat: &AssignPat) -> String {
+    match get_pat_type_ann(assign_pat.left) {
+      Some(type_ann) => self.text(&type_ann.type_ann).to_string(),
+      None => self
+        .get_expr_type_text(assign_pat.right)
+        .unwrap_or_else(|| "any".to_string()),
+    }
+  }
+
+  fn get_type_ann_text(&self, type_ann: Option<&TsTypeAnn>) -> String {
+    type_ann
+      .map(|type_ann| format!(": {}", self.text(&type_ann.type_ann)))
+      .unwrap_or_default()
+  }
+
+  /// Gets the type of an expression when it can be determined from the
+  /// expression alone.
+  fn get_expr_type_text(&self, expr: Expr) -> Option<String> {
+    match expr {
+      Expr::Lit(Lit::Str(_)) | Expr::Tpl(_) => Some("string".to_string()),
+      Expr::Lit(Lit::Num(_)) => Some("number".to_string()),
+      Expr::Lit(Lit::Bool(_)) => Some("boolean".to_string()),
+      Expr::Lit(Lit::BigInt(_)) => Some("bigint".to_string()),
+      Expr::Lit(Lit::Regex(_)) => Some("RegExp".to_string()),
+      Expr::Unary(unary) if is_primitive_literal(expr) => {
+        self.get_expr_type_text(unary.arg)
+      }
+      Expr::Paren(paren) => self.get_expr_type_text(paren.expr),
+      Expr::TsAs(as_expr) => Some(self.text(&as_expr.type_ann).to_string()),
+      Expr::TsTypeAssertion(assertion) => {
+        Some(self.text(&assertion.type_ann).to_string())
+      }
+      Expr::New(new_expr) => match new_expr.callee {
+        Expr::Ident(ident) => Some(format!(
+          "{}{}",
+          ident.sym(),
+          new_expr.type_args.map(|a| self.text(a)).unwrap_or("")
+        )),
+        _ => None,
+      },
+      Expr::Arrow(arrow) => {
+        let return_type = arrow.return_type.map(|t| self.text(&t.type_ann))?;
+        let params = arrow
+          .params
+          .iter()
+          .map(|pat| self.get_param_text(*pat))
+          .collect::<Vec<_>>();
+        Some(format!(
+          "{}({}) => {}",
+          arrow.type_params.map(|p| self.text(p)).unwrap_or(""),
+          params.join(", "),
+          return_type,
+        ))
+      }
+      Expr::Fn(fn_expr) => {
+        let function = fn_expr.function;
+        let return_type =
+          function.return_type.map(|t| self.text(&t.type_ann))?;
+        let params = function
+          .params
+          .iter()
+          .map(|param| self.get_param_text(param.pat))
+          .collect::<Vec<_>>();
+        Some(format!(
+          "{}({}) => {}",
+          function.type_params.map(|p| self.text(p)).unwrap_or(""),
+          params.join(", "),
+          return_type,
+        ))
+      }
+      _ => None,
+    }
+  }
+
+  /// Gets the start of the `class` keyword, or the `abstract` keyword
+  /// before it, after any decorators.
+  fn get_class_keyword_start(&self, class: &Class) -> SourcePos {
+    let start = class
+      .decorators
+      .last()
+      .map(|decorator| decorator.end())
+      .unwrap_or_else(|| class.start())
+      .max(class.start());
+    let text = self.text(&SourceRange::new(start, class.end()));
+    let keyword = if class.inner.is_abstract {
+      "abstract"
+    } else {
+      "class"
+    };
+    start + text.find(keyword).unwrap_or(0)
+  }
+}
+
+/// Gets the names of the functions that have overload signatures, where
+/// the implementation is left out of the declarations.
+fn get_overloaded_function_names(items: &[ModuleItem]) -> HashSet<String> {
+  items
+    .iter()
+    .filter_map(|item| match item {
+      ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export_decl)) => {
+        Some(&export_decl.decl)
+      }
+      ModuleItem::Stmt(Stmt::Decl(decl)) => Some(decl),
+      _ => None,
+    })
+    .filter_map(|decl| match decl {
+      Decl::Fn(fn_decl)
+        if !fn_decl.inner.declare && fn_decl.function.body.is_none() =>
+      {
+        Some(fn_decl.ident.sym().to_string())
+      }
+      _ => None,
+    })
+    .collect()
+}
+
+/// Gets the range of a class member including its decorators.
+fn get_member_range(
+  range: SourceRange,
+  decorators: &[&Decorator],
+) -> SourceRange {
+  let start = decorators
+    .first()
+    .map(|decorator| decorator.start().min(range.start))
+    .unwrap_or(range.start);
+  SourceRange::new(start, range.end)
+}
+
+fn get_accessibility_text(accessibility: Option<Accessibility>) -> String {
+  match accessibility {
+    Some(Accessibility::Public) => "public ",
+    Some(Accessibility::Protected) => "protected ",
+    Some(Accessibility::Private) => "private ",
+    None => "",
+  }
+  .to_string()
+}
+
+/// Gets if the expression is a string, number, boolean, or bigint literal,
+/// which ambient constants can be initialized with.
+fn is_primitive_literal(expr: Expr) -> bool {
+  match expr {
+    Expr::Lit(Lit::Str(_) | Lit::Num(_) | Lit::Bool(_) | Lit::BigInt(_)) => {
+      true
+    }
+    Expr::Unary(unary) => {
+      unary.inner.op == UnaryOp::Minus
+        && matches!(unary.arg, Expr::Lit(Lit::Num(_) | Lit::BigInt(_)))
+    }
+    _ => false,
+  }
+}
+
+/// Gets if a function body returns a value, not counting the functions
+/// and classes declared inside it.
+fn has_return_value(node: Node) -> bool {
+  for child in node.children() {
+    match child {
+      Node::ReturnStmt(return_stmt) if return_stmt.arg.is_some() => {
+        return true
+      }
+      Node::Function(_) | Node::ArrowExpr(_) | Node::Class(_) => {}
+      _ => {
+        if has_return_value(child) {
+          return true;
+        }
+      }
+    }
+  }
+  false
+}
+
+fn get_pat_type_ann<'a>(pat: Pat<'a>) -> Option<&'a TsTypeAnn<'a>> {
+  match pat {
+    Pat::Ident(binding_ident) => binding_ident.type_ann,
+    Pat::Object(object_pat) => object_pat.type_ann,
+    Pat::Array(array_pat) => array_pat.type_ann,
+    Pat::Rest(rest_pat) => rest_pat.type_ann,
+    Pat::Assign(_) | Pat::Expr(_) | Pat::Invalid(_) => None,
+  }
+}
+
+fn get_pat_binding_idents<'a>(pat: Pat<'a>) -> Vec<&'a Ident<'a>> {
+  match pat {
+    Pat::Ident(binding_ident) => vec![binding_ident.id],
+    Pat::Assign(assign_pat) => get_pat_binding_idents(assign_pat.left),
+    Pat::Rest(rest_pat) => get_pat_binding_idents(rest_pat.arg),
+    Pat::Object(object_pat) => object_pat
+      .props
+      .iter()
+      .flat_map(|prop| match prop {
+        ObjectPatProp::KeyValue(key_value) => {
+          get_pat_binding_idents(key_value.value)
+        }
+        ObjectPatProp::Assign(assign) => vec![assign.key],
+        ObjectPatProp::Rest(rest_pat) => get_pat_binding_idents(rest_pat.arg),
+      })
+      .collect(),
+    Pat::Array(array_pat) => array_pat
+      .elems
+      .iter()
+      .flatten()
+      .flat_map(|elem| get_pat_binding_idents(*elem))
+      .collect(),
+    Pat::Expr(_) | Pat::Invalid(_) => Vec::new(),
+  }
+}
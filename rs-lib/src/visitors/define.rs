@@ -0,0 +1,327 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::bail;
+use anyhow::Result;
+use deno_ast::parse_module;
+use deno_ast::swc::ast::MetaPropKind;
+use deno_ast::swc::ast::UnaryOp;
+use deno_ast::swc::common::SyntaxContext;
+use deno_ast::view::*;
+use deno_ast::MediaType;
+use deno_ast::ParseParams;
+use deno_ast::SourceRanged;
+use deno_ast::SourceTextInfo;
+use deno_ast::SourceTextInfoProvider;
+use deno_ast::TextChange;
+
+use crate::analyze::is_in_type;
+use crate::cache::CacheKeyHasher;
+use crate::cache::CacheKeyValue;
+use crate::text_changes::to_byte_range;
+use crate::TransformError;
+
+/// Expressions to replace with literal values, which are keyed by a
+/// normalized form of the expression so that `a["b"]` matches `a.b`.
+#[derive(Debug, Default)]
+pub struct Defines {
+  values: BTreeMap<String, String>,
+  /// Text that must be in a module for it to have a match, which is
+  /// the global or `import.meta` each expression starts with.
+  root_names: Vec<String>,
+}
+
+impl CacheKeyValue for Defines {
+  fn write_key(&self, hasher: &mut CacheKeyHasher) {
+    // the root names are derived from the values
+    hasher.write(&self.values);
+  }
+}
+
+impl Defines {
+  pub fn new(define: &HashMap<String, String>) -> Result<Self> {
+    let mut defines = Defines::default();
+    let mut messages = Vec::new();
+    let mut define = define.iter().collect::<Vec<_>>();
+    define.sort();
+    for (expr_text, value_text) in define {
+      let key = parse_expr(expr_text, |expr| get_expr_key(expr, None));
+      let key = match key {
+        Some(key) => key,
+        None => {
+          messages.push(format!(
+            "Invalid define expression \"{}\". It must be a global, a property access, or a call with string arguments (ex. `import.meta.env.MODE` or `Deno.env.get(\"MODE\")`).",
+            expr_text
+          ));
+          continue;
+        }
+      };
+      if !parse_expr(value_text, is_literal).unwrap_or(false) {
+        messages.push(format!(
+          "Invalid define value {} for \"{}\". It must be a string, number, boolean, null, or undefined literal (ex. `\"production\"` or `true`).",
+          value_text, expr_text
+        ));
+        continue;
+      }
+      let root_name = key
+        .split(|c| c == '.' || c == '(')
+        .next()
+        .unwrap()
+        .to_string();
+      if !defines.root_names.contains(&root_name) {
+        defines.root_names.push(root_name);
+      }
+      defines.values.insert(key, value_text.trim().to_string());
+    }
+
+    if messages.is_empty() {
+      Ok(defines)
+    } else {
+      bail!(TransformError::InvalidOptions {
+        message: messages.join("\n"),
+      })
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.values.is_empty()
+  }
+
+  /// Gets if the text might have an expression to replace, which is a
+  /// quick check to avoid parsing modules that can't have any.
+  pub fn may_match(&self, text: &str) -> bool {
+    self
+      .root_names
+      .iter()
+      .any(|name| text.contains(name.as_str()))
+  }
+}
+
+pub struct GetDefineTextChangesParams<'a> {
+  pub program: Program<'a>,
+  pub unresolved_context: SyntaxContext,
+  pub defines: &'a Defines,
+}
+
+/// Gets the text changes that replace the defined expressions with their
+/// values. Expressions that are assigned to, deleted, or in a type aren't
+/// replaced and neither are ones where the global is shadowed.
+pub fn get_define_text_changes(
+  params: &GetDefineTextChangesParams,
+) -> Vec<TextChange> {
+  let mut text_changes = Vec::new();
+  visit_children(params.program.as_node(), params, &mut text_changes);
+  text_changes
+}
+
+fn visit_children(
+  node: Node,
+  params: &GetDefineTextChangesParams,
+  text_changes: &mut Vec<TextChange>,
+) {
+  for child in node.children() {
+    visit_node(child, params, text_changes);
+  }
+}
+
+fn visit_node(
+  node: Node,
+  params: &GetDefineTextChangesParams,
+  text_changes: &mut Vec<TextChange>,
+) {
+  let value = node_to_expr(node)
+    .and_then(|expr| get_expr_key(expr, Some(params.unresolved_context)))
+    .and_then(|key| params.defines.values.get(&key));
+  let value = match value {
+    Some(value) if can_replace(node) => value,
+    _ => {
+      visit_children(node, params, text_changes);
+      return;
+    }
+  };
+
+  let is_member_obj = matches!(
+    node.parent(),
+    Some(Node::MemberExpr(member)) if member.obj.range() == node.range()
+  );
+  let is_number = value.starts_with(|c: char| c.is_ascii_digit() || c == '.');
+  let new_text = if value.starts_with('-') || is_member_obj && is_number {
+    format!("({})", value)
+  } else {
+    value.to_string()
+  };
+  // expand shorthand properties (ex. `{ __DEV__ }`)
+  let new_text = match (node, node.parent()) {
+    (Node::Ident(ident), Some(Node::ObjectLit(_))) => {
+      format!("{}: {}", ident.sym(), new_text)
+    }
+    _ => new_text,
+  };
+  text_changes.push(TextChange {
+    range: to_byte_range(params.program.text_info(), node.range()),
+    new_text,
+  });
+}
+
+fn node_to_expr(node: Node) -> Option<Expr> {
+  match node {
+    Node::Ident(ident) => Some(Expr::Ident(ident)),
+    Node::MetaPropExpr(meta_prop) => Some(Expr::MetaProp(meta_prop)),
+    Node::MemberExpr(member) => Some(Expr::Member(member)),
+    Node::CallExpr(call) => Some(Expr::Call(call)),
+    _ => None,
+  }
+}
+
+pub(super) fn can_replace(node: Node) -> bool {
+  if is_in_type(node) {
+    return false;
+  }
+  match node.parent() {
+    // the left side of an assignment
+    Some(Node::AssignExpr(assign)) => node.start() >= assign.right.start(),
+    Some(Node::UpdateExpr(_)) => false,
+    Some(Node::UnaryExpr(unary)) => unary.inner.op != UnaryOp::Delete,
+    _ => true,
+  }
+}
+
+/// Gets the normalized form of an expression that can be defined, which
+/// is only checked to be a global when there's an unresolved context.
+fn get_expr_key(
+  expr: Expr,
+  unresolved_context: Option<SyntaxContext>,
+) -> Option<String> {
+  match expr {
+    Expr::Ident(ident) => {
+      if let Some(unresolved_context) = unresolved_context {
+        if ident.ctxt() != unresolved_context {
+          return None;
+        }
+      }
+      Some(ident.sym().to_string())
+    }
+    Expr::MetaProp(meta_prop)
+      if meta_prop.inner.kind == MetaPropKind::ImportMeta =>
+    {
+      Some("import.meta".to_string())
+    }
+    Expr::Member(member) => {
+      let obj = get_expr_key(member.obj, unresolved_context)?;
+      let prop = match member.prop {
+        MemberProp::Ident(ident) => ident.sym().to_string(),
+        MemberProp::Computed(computed) => match computed.expr {
+          Expr::Lit(Lit::Str(str)) => str.value().to_string(),
+          _ => return None,
+        },
+        MemberProp::PrivateName(_) => return None,
+      };
+      Some(format!("{}.{}", obj, prop))
+    }
+    Expr::Call(call) => {
+      let callee = match call.callee {
+        Callee::Expr(expr) => get_expr_key(expr, unresolved_context)?,
+        Callee::Super(_) | Callee::Import(_) => return None,
+      };
+      let mut args = Vec::with_capacity(call.args.len());
+      for arg in call.args.iter() {
+        match arg.expr {
+          Expr::Lit(Lit::Str(str)) if arg.inner.spread.is_none() => {
+            args.push(format!("{:?}", str.value().as_ref()))
+          }
+          _ => return None,
+        }
+      }
+      Some(format!("{}({})", callee, args.join(",")))
+    }
+    Expr::Paren(paren) => get_expr_key(paren.expr, unresolved_context),
+    _ => None,
+  }
+}
+
+/// Gets if the expression is a literal that's safe to put in place of an
+/// expression.
+fn is_literal(expr: Expr) -> bool {
+  match expr {
+    Expr::Lit(Lit::Str(_) | Lit::Num(_) | Lit::Bool(_) | Lit::Null(_)) => true,
+    Expr::Ident(ident) => ident.sym().as_ref() == "undefined",
+    Expr::Unary(unary) => {
+      unary.inner.op == UnaryOp::Minus
+        && matches!(unary.arg, Expr::Lit(Lit::Num(_)))
+    }
+    _ => false,
+  }
+}
+
+/// Parses the text as a single expression statement and gets the result
+/// of the function for the expression.
+fn parse_expr<T>(text: &str, f: impl FnOnce(Expr) -> T) -> Option<T> {
+  let parsed_source = parse_module(ParseParams {
+    specifier: "file:///define.ts".to_string(),
+    text_info: SourceTextInfo::new(Arc::from(format!("({});", text.trim()))),
+    media_type: MediaType::TypeScript,
+    capture_tokens: false,
+    scope_analysis: false,
+    maybe_syntax: None,
+  })
+  .ok()?;
+  parsed_source.with_view(|program| {
+    let module = match program {
+      Program::Module(module) => module,
+      Program::Script(_) => return None,
+    };
+    if module.body.len() != 1 {
+      return None;
+    }
+    match module.body[0] {
+      ModuleItem::Stmt(Stmt::Expr(expr_stmt)) => match expr_stmt.expr {
+        Expr::Paren(paren) => Some(f(paren.expr)),
+        _ => None,
+      },
+      _ => None,
+    }
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn get_key(text: &str) -> Option<String> {
+    parse_expr(text, |expr| get_expr_key(expr, None)).flatten()
+  }
+
+  #[test]
+  fn test_get_expr_key() {
+    assert_eq!(get_key("__DEV__"), Some("__DEV__".to_string()));
+    assert_eq!(
+      get_key("import.meta.env.MODE"),
+      Some("import.meta.env.MODE".to_string())
+    );
+    assert_eq!(
+      get_key("process.env[\"NODE_ENV\"]"),
+      Some("process.env.NODE_ENV".to_string())
+    );
+    assert_eq!(
+      get_key("Deno.env.get('BUILD_TARGET')"),
+      Some("Deno.env.get(\"BUILD_TARGET\")".to_string())
+    );
+    assert_eq!(get_key("Deno.env.get(name)"), None);
+    assert_eq!(get_key("a + b"), None);
+    assert_eq!(get_key("a; b"), None);
+  }
+
+  #[test]
+  fn test_is_literal() {
+    for text in ["\"a\"", "'a'", "5", "-1.5", "true", "null", "undefined"] {
+      assert_eq!(parse_expr(text, is_literal), Some(true), "{}", text);
+    }
+    for text in ["a", "`a`", "[]", "{}", "f()", "-a", "production"] {
+      assert_eq!(parse_expr(text, is_literal), Some(false), "{}", text);
+    }
+    assert_eq!(parse_expr("\"a", is_literal), None);
+  }
+}
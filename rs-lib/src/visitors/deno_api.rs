@@ -0,0 +1,182 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use deno_ast::swc::common::SyntaxContext;
+use deno_ast::view::*;
+use deno_ast::SourcePos;
+use deno_ast::SourceRange;
+use deno_ast::SourceRanged;
+use deno_ast::SourceTextInfoProvider;
+use deno_ast::TextChange;
+
+use super::globals::get_unique_name;
+use crate::text_changes::ranges_overlap;
+use crate::text_changes::to_byte_range;
+use crate::utils::text_change_for_prepend_statement_to_text;
+
+/// The modules the translated calls use, which are imported as a
+/// namespace with the name given to the placeholder in the translated
+/// text. The names are only known once every identifier in the module is.
+const MODULES: [(&str, &str, &str); 2] = [
+  ("$fs", "dntFs", "node:fs/promises"),
+  ("$process", "dntProcess", "node:process"),
+];
+
+/// Converts a Node.js `Stats` object to the shape of a `Deno.FileInfo`.
+const TO_FILE_INFO_TEXT: &str = concat!(
+  ".then((stat) => ({ isFile: stat.isFile(), isDirectory: stat.isDirectory(), ",
+  "isSymlink: stat.isSymbolicLink(), size: stat.size, mtime: stat.mtime, ",
+  "atime: stat.atime, birthtime: stat.birthtime, dev: stat.dev, ino: stat.ino, ",
+  "mode: stat.mode, nlink: stat.nlink, uid: stat.uid, gid: stat.gid, ",
+  "rdev: stat.rdev, blksize: stat.blksize, blocks: stat.blocks }))",
+);
+
+pub struct GetDenoApiTextChangesParams<'a> {
+  pub program: Program<'a>,
+  pub unresolved_context: SyntaxContext,
+  /// Ranges that are already being changed (ex. by a define), where
+  /// calls that overlap them aren't rewritten.
+  pub excluded_ranges: &'a [Range<usize>],
+}
+
+/// Gets the text changes that translate the simple calls of common Deno
+/// APIs to their Node.js equivalents (ex. `Deno.readTextFile(path)` to
+/// `readFile(path, "utf8")` from `node:fs/promises`) and import what
+/// they use.
+///
+/// Calls that can't be translated, such as ones with options or a spread
+/// argument, are left for the `Deno` shim.
+pub fn get_deno_api_text_changes(
+  params: &GetDenoApiTextChangesParams,
+) -> Vec<TextChange> {
+  let mut context = Context {
+    params,
+    text_changes: Vec::new(),
+    all_ident_names: HashSet::new(),
+  };
+  visit_node(params.program.as_node(), &mut context);
+
+  let mut text_changes = context.text_changes;
+  let mut statements = Vec::new();
+  for (placeholder, name, module) in MODULES {
+    if !text_changes
+      .iter()
+      .any(|c| c.new_text.contains(placeholder))
+    {
+      continue;
+    }
+    let name = get_unique_name(name, &context.all_ident_names);
+    for change in text_changes.iter_mut() {
+      change.new_text = change.new_text.replace(placeholder, &name);
+    }
+    statements.push(format!("import * as {} from \"{}\";", name, module));
+  }
+  if !statements.is_empty() {
+    text_changes.push(text_change_for_prepend_statement_to_text(
+      &params.program,
+      &statements.join("\n"),
+    ));
+  }
+  text_changes
+}
+
+struct Context<'a> {
+  params: &'a GetDenoApiTextChangesParams<'a>,
+  text_changes: Vec<TextChange>,
+  all_ident_names: HashSet<String>,
+}
+
+fn visit_node(node: Node, context: &mut Context) {
+  match node {
+    Node::Ident(ident) => {
+      context.all_ident_names.insert(ident.sym().to_string());
+    }
+    Node::CallExpr(call_expr) => {
+      if let Some(changes) = get_call_text_changes(call_expr, context.params) {
+        let is_excluded = changes.iter().any(|change| {
+          context
+            .params
+            .excluded_ranges
+            .iter()
+            .any(|range| ranges_overlap(range, &change.range))
+        });
+        if !is_excluded {
+          context.text_changes.extend(changes);
+        }
+      }
+    }
+    _ => {}
+  }
+  for child in node.children() {
+    visit_node(child, context);
+  }
+}
+
+fn get_call_text_changes(
+  call_expr: &CallExpr,
+  params: &GetDenoApiTextChangesParams,
+) -> Option<Vec<TextChange>> {
+  let (callee, name) = get_deno_member(call_expr, params)?;
+  if call_expr.args.iter().any(|arg| arg.inner.spread.is_some()) {
+    return None;
+  }
+  let text_info = params.program.text_info();
+  let change = |start: SourcePos, end: SourcePos, new_text: &str| TextChange {
+    range: to_byte_range(text_info, SourceRange::new(start, end)),
+    new_text: new_text.to_string(),
+  };
+  let callee_change =
+    |new_text: &str| change(callee.start(), callee.end(), new_text);
+  let args = &call_expr.args;
+  // inserts text after the last argument, before any trailing comma
+  let append_arg = |text: &str| {
+    let end = args[args.len() - 1].end();
+    change(end, end, text)
+  };
+  let fs_method = |method: &str| format!("$fs.{}", method);
+
+  Some(match (name, args.len()) {
+    ("readTextFile", 1) => vec![
+      callee_change(&fs_method("readFile")),
+      append_arg(", \"utf8\""),
+    ],
+    ("readFile", 1) => vec![callee_change(&fs_method("readFile"))],
+    ("writeTextFile" | "writeFile", 2) => {
+      vec![callee_change(&fs_method("writeFile"))]
+    }
+    ("stat", 1) => vec![
+      callee_change(&fs_method("stat")),
+      change(call_expr.end(), call_expr.end(), TO_FILE_INFO_TEXT),
+    ],
+    ("cwd", 0) => vec![callee_change("$process.cwd")],
+    ("exit", 0 | 1) => vec![callee_change("$process.exit")],
+    _ => return None,
+  })
+}
+
+/// Gets the callee and the name of the member when the call is
+/// `Deno.<name>(...)` and `Deno` is the global.
+fn get_deno_member<'a>(
+  call_expr: &CallExpr<'a>,
+  params: &GetDenoApiTextChangesParams,
+) -> Option<(&'a MemberExpr<'a>, &'a str)> {
+  let callee = match call_expr.callee {
+    Callee::Expr(Expr::Member(member)) => member,
+    _ => return None,
+  };
+  let name = match callee.prop {
+    MemberProp::Ident(ident) => ident.sym().as_ref(),
+    _ => return None,
+  };
+  match callee.obj {
+    Expr::Ident(ident)
+      if ident.sym().as_ref() == "Deno"
+        && ident.ctxt() == params.unresolved_context =>
+    {
+      Some((callee, name))
+    }
+    _ => None,
+  }
+}
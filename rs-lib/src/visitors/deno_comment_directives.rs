@@ -1,7 +1,11 @@
 // Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
 
+use std::collections::HashMap;
+
 use deno_ast::swc::common::comments::Comment;
+use deno_ast::swc::common::comments::CommentKind;
 use deno_ast::view::*;
+use deno_ast::ModuleSpecifier;
 use deno_ast::RootNode;
 use deno_ast::SourceRanged;
 use deno_ast::SourceRangedForSpanned;
@@ -10,57 +14,388 @@ use deno_ast::TextChange;
 use once_cell::sync::Lazy;
 use regex::Regex;
 
+use crate::graph::DependencyResolver;
+use crate::mappings::Mappings;
+use crate::package::validate_package_name;
+use crate::package::validate_version_range;
+use crate::utils::get_relative_path;
+use crate::CommentDirectiveAction;
+use crate::CommentDirectiveOptions;
+use crate::PackageMappedSpecifier;
+
 // lifted from deno_graph
-/// Matched the `@deno-types` pragma.
+/// Matched the `@deno-types` pragma or the `@ts-types` pragma of newer
+/// versions of Deno.
 static DENO_TYPES_RE: Lazy<Regex> = Lazy::new(|| {
-  Regex::new(r#"(?i)^\s*@deno-types\s*=\s*(?:["']([^"']+)["']|(\S+))"#).unwrap()
+  Regex::new(r#"(?i)^\s*@(?:deno|ts)-types\s*=\s*(?:["']([^"']+)["']|(\S+))"#)
+    .unwrap()
 });
+/// Matches the start of the `@ts-types` pragma, which is the newer name
+/// of the `@deno-types` pragma.
+pub(crate) static TS_TYPES_RE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"(?i)^(\s*)@ts-types(\s*=)").unwrap());
+/// Matches the `@ts-self-types` pragma.
+pub(crate) static TS_SELF_TYPES_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r#"(?i)^\s*@ts-self-types\s*=\s*(?:["']([^"']+)["']|(\S+))"#)
+    .unwrap()
+});
+/// Matches the `deno-lint-ignore` and `deno-lint-ignore-file` directives.
+static DENO_LINT_IGNORE_RE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"(?i)^\s*deno-lint-ignore(-file)?(\s|$)").unwrap());
+/// Matches the `deno-fmt-ignore` and `deno-fmt-ignore-file` directives.
+static DENO_FMT_IGNORE_RE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"(?i)^\s*deno-fmt-ignore(-file)?(\s|$)").unwrap());
+/// Matches the `@dnt-map` directive, which maps the specifier of the
+/// import below it to a package (ex. `// @dnt-map: npm:chalk@^5`).
+static DNT_MAP_RE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"(?i)^\s*@dnt-map\s*:\s*(\S*)\s*$").unwrap());
+/// ESLint names of the Deno lint rules that have an equivalent in ESLint or
+/// typescript-eslint.
+const ESLINT_RULES: [(&str, &str); 32] = [
+  (
+    "adjacent-overload-signatures",
+    "@typescript-eslint/adjacent-overload-signatures",
+  ),
+  ("ban-ts-comment", "@typescript-eslint/ban-ts-comment"),
+  ("ban-types", "@typescript-eslint/ban-types"),
+  ("camelcase", "camelcase"),
+  ("eqeqeq", "eqeqeq"),
+  (
+    "explicit-function-return-type",
+    "@typescript-eslint/explicit-function-return-type",
+  ),
+  (
+    "explicit-module-boundary-types",
+    "@typescript-eslint/explicit-module-boundary-types",
+  ),
+  ("no-async-promise-executor", "no-async-promise-executor"),
+  ("no-case-declarations", "no-case-declarations"),
+  ("no-cond-assign", "no-cond-assign"),
+  ("no-console", "no-console"),
+  ("no-constant-condition", "no-constant-condition"),
+  ("no-control-regex", "no-control-regex"),
+  ("no-debugger", "no-debugger"),
+  ("no-empty", "no-empty"),
+  (
+    "no-empty-interface",
+    "@typescript-eslint/no-empty-interface",
+  ),
+  ("no-eval", "no-eval"),
+  ("no-explicit-any", "@typescript-eslint/no-explicit-any"),
+  ("no-fallthrough", "no-fallthrough"),
+  (
+    "no-inferrable-types",
+    "@typescript-eslint/no-inferrable-types",
+  ),
+  ("no-inner-declarations", "no-inner-declarations"),
+  ("no-namespace", "@typescript-eslint/no-namespace"),
+  (
+    "no-non-null-assertion",
+    "@typescript-eslint/no-non-null-assertion",
+  ),
+  ("no-prototype-builtins", "no-prototype-builtins"),
+  ("no-redeclare", "no-redeclare"),
+  ("no-self-assign", "no-self-assign"),
+  ("no-this-alias", "@typescript-eslint/no-this-alias"),
+  ("no-unsafe-finally", "no-unsafe-finally"),
+  ("no-unused-vars", "@typescript-eslint/no-unused-vars"),
+  ("no-var", "no-var"),
+  ("prefer-const", "prefer-const"),
+  ("require-await", "require-await"),
+];
 /// Matches a `/// <reference ... />` comment reference.
-static TRIPLE_SLASH_REFERENCE_RE: Lazy<Regex> =
+pub(crate) static TRIPLE_SLASH_REFERENCE_RE: Lazy<Regex> =
   Lazy::new(|| Regex::new(r"(?i)^/\s*<reference\s.*?/>").unwrap());
 /// Matches a types reference, which for JavaScript files indicates the
 /// location of types to use when type checking a program that includes it as
 /// a dependency.
-static TYPES_REFERENCE_RE: Lazy<Regex> =
+pub(crate) static TYPES_REFERENCE_RE: Lazy<Regex> =
   Lazy::new(|| Regex::new(r#"(?i)\stypes\s*=\s*["']([^"']*)["']"#).unwrap());
+/// Matches a path reference, which adds the referenced file to the program.
+pub(crate) static PATH_REFERENCE_RE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r#"(?i)\spath\s*=\s*["']([^"']*)["']"#).unwrap());
+
+/// Gets the package of a `@dnt-map` directive when the comment text is one.
+///
+/// The package is either an npm package with an optional version and sub
+/// path (ex. `npm:@scope/name@^1.0.0/sub/path`) or a Node.js built-in
+/// module (ex. `node:fs`).
+pub fn parse_dnt_map_directive(
+  comment_text: &str,
+) -> Option<Result<PackageMappedSpecifier, String>> {
+  let captures = DNT_MAP_RE.captures(comment_text)?;
+  let text = captures.get(1).unwrap().as_str();
+  Some(
+    parse_dnt_map_package(text).map_err(|err| {
+      format!("Invalid @dnt-map directive \"{}\". {}", text, err)
+    }),
+  )
+}
+
+fn parse_dnt_map_package(text: &str) -> Result<PackageMappedSpecifier, String> {
+  if let Some(module) = text.strip_prefix("node:") {
+    if module.is_empty() {
+      return Err("Expected a module name after \"node:\".".to_string());
+    }
+    return Ok(PackageMappedSpecifier {
+      name: text.to_string(),
+      version: None,
+      sub_path: None,
+      peer_dependency: false,
+      types_package: None,
+    });
+  }
+  let text = match text.strip_prefix("npm:") {
+    Some(text) => text,
+    None => {
+      return Err(
+        "Expected an npm package (ex. \"npm:chalk@^5\") or a Node.js module (ex. \"node:fs\").".to_string(),
+      )
+    }
+  };
+  // scoped package names have a slash in them
+  let name_end = if text.starts_with('@') {
+    match text.find('/') {
+      Some(index) => text[index + 1..]
+        .find(|c| c == '@' || c == '/')
+        .map(|i| i + index + 1),
+      None => return Err("Expected a name after the scope.".to_string()),
+    }
+  } else {
+    text.find(|c| c == '@' || c == '/')
+  }
+  .unwrap_or(text.len());
+  let name = &text[..name_end];
+  let mut rest = &text[name_end..];
+  let mut version = None;
+  if let Some(version_text) = rest.strip_prefix('@') {
+    let version_end = version_text.find('/').unwrap_or(version_text.len());
+    version = Some(version_text[..version_end].to_string());
+    rest = &version_text[version_end..];
+  }
+  let sub_path = rest
+    .strip_prefix('/')
+    .filter(|p| !p.is_empty())
+    .map(|p| p.to_string());
+  validate_package_name(name).map_err(|err| err.to_string())?;
+  if let Some(version) = &version {
+    validate_version_range(version).map_err(|err| err.to_string())?;
+  }
+  Ok(PackageMappedSpecifier {
+    name: name.to_string(),
+    version,
+    sub_path,
+    peer_dependency: false,
+    types_package: None,
+  })
+}
+
+pub struct GetDenoCommentDirectiveTextChangesParams<'a> {
+  pub specifier: &'a ModuleSpecifier,
+  pub program: &'a Program<'a>,
+  pub is_declaration_file: bool,
+  pub options: &'a CommentDirectiveOptions,
+  pub dependency_resolver: &'a DependencyResolver,
+  pub mappings: &'a Mappings,
+  pub package_specifier_mappings: &'a HashMap<ModuleSpecifier, String>,
+}
 
 pub fn get_deno_comment_directive_text_changes(
-  program: &Program,
+  params: &GetDenoCommentDirectiveTextChangesParams,
 ) -> Vec<TextChange> {
+  let program = params.program;
   let mut text_changes = Vec::new();
 
-  // strip deno specific path triple slash references
-  for comment in program.leading_comments_fast(program) {
-    if TRIPLE_SLASH_REFERENCE_RE.is_match(&comment.text) {
-      if let Some(captures) = TYPES_REFERENCE_RE.captures(&comment.text) {
-        let specifier = captures.get(1).unwrap().as_str().to_lowercase();
-        if specifier.starts_with("./")
-          || specifier.starts_with("../")
-          || specifier.starts_with("https://")
-          || specifier.starts_with("http://")
-        {
-          text_changes.push(TextChange {
-            new_text: String::new(),
-            range: get_extended_comment_range(program, comment),
-          });
+  // strip deno specific path triple slash references (declaration
+  // files keep these because they get rewritten to the output paths)
+  if !params.is_declaration_file {
+    for comment in program.leading_comments_fast(program) {
+      if TRIPLE_SLASH_REFERENCE_RE.is_match(&comment.text) {
+        if let Some(captures) = TYPES_REFERENCE_RE.captures(&comment.text) {
+          let specifier = captures.get(1).unwrap().as_str().to_lowercase();
+          if specifier.starts_with("./")
+            || specifier.starts_with("../")
+            || specifier.starts_with("https://")
+            || specifier.starts_with("http://")
+          {
+            text_changes.push(TextChange {
+              new_text: String::new(),
+              range: get_extended_comment_range(program, comment),
+            });
+          }
         }
       }
     }
   }
 
-  // strip all `@deno-types` comments
   for comment in program.comment_container().all_comments() {
-    if DENO_TYPES_RE.is_match(&comment.text) {
+    // always strip the `@dnt-map` comments
+    if DNT_MAP_RE.is_match(&comment.text) {
       text_changes.push(TextChange {
         new_text: String::new(),
         range: get_extended_comment_range(program, comment),
       });
+      continue;
+    }
+    // the `@ts-self-types` directive describes the whole file, so its line
+    // is removed along with it rather than leaving a blank line at the top
+    let (captures, action, remove_line) =
+      if let Some(captures) = DENO_TYPES_RE.captures(&comment.text) {
+        (Some(captures), params.options.deno_types, false)
+      } else if let Some(captures) = TS_SELF_TYPES_RE.captures(&comment.text) {
+        (Some(captures), params.options.ts_self_types, true)
+      } else if DENO_LINT_IGNORE_RE.is_match(&comment.text) {
+        (None, params.options.deno_lint_ignore, false)
+      } else if DENO_FMT_IGNORE_RE.is_match(&comment.text) {
+        (None, params.options.deno_fmt_ignore, false)
+      } else {
+        continue;
+      };
+    let removal_range = || {
+      if remove_line {
+        get_comment_line_range(program, comment)
+      } else {
+        get_extended_comment_range(program, comment)
+      }
+    };
+    let value = captures.and_then(|c| c.get(1).or_else(|| c.get(2)));
+    match (action, value) {
+      (CommentDirectiveAction::Keep, _) => {}
+      (CommentDirectiveAction::Rewrite, Some(value)) => {
+        match get_rewritten_target(value.as_str(), params) {
+          Some(new_text) => {
+            let range = get_extended_comment_range(program, comment);
+            // the comment text does not include the leading `//` or `/*`
+            let text_start = range.start + 2;
+            text_changes.push(TextChange {
+              new_text,
+              range: text_start + value.start()..text_start + value.end(),
+            });
+          }
+          None => text_changes.push(TextChange {
+            new_text: String::new(),
+            range: removal_range(),
+          }),
+        }
+      }
+      (CommentDirectiveAction::Rewrite, None) => {
+        text_changes.push(TextChange {
+          new_text: get_node_ignore_directive(comment, params.options)
+            .unwrap_or_default(),
+          range: get_extended_comment_range(program, comment),
+        });
+      }
+      (CommentDirectiveAction::Remove | CommentDirectiveAction::Rewrite, _) => {
+        text_changes.push(TextChange {
+          new_text: String::new(),
+          range: removal_range(),
+        });
+      }
     }
   }
 
   text_changes
 }
 
+/// Gets the specifier of the output location of the file a directive
+/// points at, or the package it's mapped to.
+fn get_rewritten_target(
+  value: &str,
+  params: &GetDenoCommentDirectiveTextChangesParams,
+) -> Option<String> {
+  let specifier = params
+    .dependency_resolver
+    .resolve_dependency(value, params.specifier)?;
+  if let Some(bare_specifier) =
+    params.package_specifier_mappings.get(&specifier)
+  {
+    return Some(bare_specifier.to_string());
+  }
+  let file_path = params.mappings.maybe_file_path(&specifier)?;
+  Some(get_relative_path(
+    params.mappings.get_file_path(params.specifier),
+    file_path,
+  ))
+}
+
+/// Translates a `deno-lint-ignore` or `deno-fmt-ignore` comment to the
+/// ESLint or Prettier comment that does the same, which is `None` when
+/// there isn't one.
+fn get_node_ignore_directive(
+  comment: &Comment,
+  options: &CommentDirectiveOptions,
+) -> Option<String> {
+  if let Some(captures) = DENO_FMT_IGNORE_RE.captures(&comment.text) {
+    // prettier can only ignore the next node
+    return match captures.get(1) {
+      Some(_) => None,
+      None => Some(format_comment(comment.kind, "prettier-ignore")),
+    };
+  }
+  let captures = DENO_LINT_IGNORE_RE.captures(&comment.text)?;
+  let is_file = captures.get(1).is_some();
+  let rest = &comment.text[captures.get(0).unwrap().end()..];
+  let (rules_text, reason) = match rest.split_once("--") {
+    Some((rules_text, reason)) => (rules_text, Some(reason.trim())),
+    None => (rest, None),
+  };
+  let rule_names = rules_text
+    .split(|c: char| c.is_whitespace() || c == ',')
+    .filter(|name| !name.is_empty())
+    .collect::<Vec<_>>();
+  let eslint_rules = rule_names
+    .iter()
+    .filter_map(|name| get_eslint_rule(name, options))
+    .collect::<Vec<_>>();
+  // don't turn off every rule when none of the rules are known
+  if !rule_names.is_empty() && eslint_rules.is_empty() {
+    return None;
+  }
+  let mut text = if is_file {
+    "eslint-disable".to_string()
+  } else {
+    "eslint-disable-next-line".to_string()
+  };
+  if !eslint_rules.is_empty() {
+    text.push(' ');
+    text.push_str(&eslint_rules.join(", "));
+  }
+  if let Some(reason) = reason.filter(|r| !r.is_empty()) {
+    text.push_str(" -- ");
+    text.push_str(reason);
+  }
+  // eslint only reads the file wide directive from block comments
+  let kind = if is_file {
+    CommentKind::Block
+  } else {
+    comment.kind
+  };
+  Some(format_comment(kind, &text))
+}
+
+fn get_eslint_rule<'a>(
+  deno_rule: &str,
+  options: &'a CommentDirectiveOptions,
+) -> Option<&'a str> {
+  options
+    .eslint_rules
+    .get(deno_rule)
+    .map(|name| name.as_str())
+    .or_else(|| {
+      ESLINT_RULES
+        .iter()
+        .find(|(name, _)| *name == deno_rule)
+        .map(|(_, eslint_name)| *eslint_name)
+    })
+}
+
+fn format_comment(kind: CommentKind, text: &str) -> String {
+  match kind {
+    CommentKind::Line => format!("// {}", text),
+    CommentKind::Block => format!("/* {} */", text),
+  }
+}
+
 fn get_extended_comment_range(
   program: &Program,
   comment: &Comment,
@@ -71,3 +406,93 @@ fn get_extended_comment_range(
   let end_pos = range.end().as_byte_index(start_pos);
   range.start().as_byte_index(start_pos)..end_pos
 }
+
+/// Gets the range of the comment including its indentation and line
+/// ending when it's the only thing on its line, so that removing it
+/// doesn't leave a blank line behind.
+fn get_comment_line_range(
+  program: &Program,
+  comment: &Comment,
+) -> std::ops::Range<usize> {
+  let range = get_extended_comment_range(program, comment);
+  let text = program.text_info().text_str();
+  let line_start = text[..range.start]
+    .rfind('\n')
+    .map(|index| index + 1)
+    .unwrap_or(0);
+  let indent = &text[line_start..range.start];
+  if !indent.trim_start_matches('\u{FEFF}').trim().is_empty() {
+    return range;
+  }
+  let rest_of_line = text[range.end..].trim_start_matches([' ', '\t']);
+  let line_ending_len = if rest_of_line.starts_with("\r\n") {
+    2
+  } else if rest_of_line.starts_with('\n') {
+    1
+  } else {
+    return range;
+  };
+  let end = text.len() - rest_of_line.len() + line_ending_len;
+  // keep a byte order mark at the start of the file
+  let start = range.start - indent.trim_start_matches('\u{FEFF}').len();
+  start..end
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use pretty_assertions::assert_eq;
+
+  fn package(
+    name: &str,
+    version: Option<&str>,
+    sub_path: Option<&str>,
+  ) -> PackageMappedSpecifier {
+    PackageMappedSpecifier {
+      name: name.to_string(),
+      version: version.map(|v| v.to_string()),
+      sub_path: sub_path.map(|p| p.to_string()),
+      peer_dependency: false,
+      types_package: None,
+    }
+  }
+
+  #[test]
+  fn parses_dnt_map_directives() {
+    assert_eq!(parse_dnt_map_directive(" some comment"), None);
+    assert_eq!(
+      parse_dnt_map_directive(" @dnt-map: npm:chalk@^5"),
+      Some(Ok(package("chalk", Some("^5"), None)))
+    );
+    assert_eq!(
+      parse_dnt_map_directive(" @dnt-map:npm:chalk"),
+      Some(Ok(package("chalk", None, None)))
+    );
+    assert_eq!(
+      parse_dnt_map_directive(" @dnt-map: npm:@scope/pkg@1.2.0/sub/path.js"),
+      Some(Ok(package(
+        "@scope/pkg",
+        Some("1.2.0"),
+        Some("sub/path.js")
+      )))
+    );
+    assert_eq!(
+      parse_dnt_map_directive(" @dnt-map: npm:preact/hooks"),
+      Some(Ok(package("preact", None, Some("hooks"))))
+    );
+    assert_eq!(
+      parse_dnt_map_directive(" @dnt-map: node:fs"),
+      Some(Ok(package("node:fs", None, None)))
+    );
+    assert_eq!(
+      parse_dnt_map_directive(" @dnt-map: chalk@^5"),
+      Some(Err(
+        "Invalid @dnt-map directive \"chalk@^5\". Expected an npm package (ex. \"npm:chalk@^5\") or a Node.js module (ex. \"node:fs\").".to_string()
+      ))
+    );
+    assert!(matches!(
+      parse_dnt_map_directive(" @dnt-map: npm:chalk@"),
+      Some(Err(_))
+    ));
+  }
+}
@@ -0,0 +1,144 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::ops::Range;
+
+use deno_ast::swc::common::SyntaxContext;
+use deno_ast::view::*;
+use deno_ast::MediaType;
+use deno_ast::SourcePos;
+use deno_ast::SourceRange;
+use deno_ast::SourceRanged;
+use deno_ast::SourceTextInfoProvider;
+use deno_ast::TextChange;
+
+use crate::text_changes::ranges_overlap;
+use crate::text_changes::to_byte_range;
+
+pub struct GetDenoEnvTextChangesParams<'a> {
+  pub program: Program<'a>,
+  pub unresolved_context: SyntaxContext,
+  pub media_type: MediaType,
+  /// Ranges that are already being changed (ex. by a define), where
+  /// calls that overlap them aren't rewritten.
+  pub excluded_ranges: &'a [Range<usize>],
+}
+
+/// Gets the text changes that rewrite the `Deno.env` calls to use
+/// `process.env` directly (ex. `Deno.env.get("X")` to `process.env["X"]`).
+///
+/// Only the parts around the arguments are changed so that calls in the
+/// arguments are rewritten as well.
+pub fn get_deno_env_text_changes(
+  params: &GetDenoEnvTextChangesParams,
+) -> Vec<TextChange> {
+  let mut text_changes = Vec::new();
+  visit_node(params.program.as_node(), params, &mut text_changes);
+  text_changes
+}
+
+fn visit_node(
+  node: Node,
+  params: &GetDenoEnvTextChangesParams,
+  text_changes: &mut Vec<TextChange>,
+) {
+  if let Node::CallExpr(call_expr) = node {
+    if let Some(changes) = get_call_text_changes(call_expr, params) {
+      let is_excluded = changes.iter().any(|change| {
+        params
+          .excluded_ranges
+          .iter()
+          .any(|range| ranges_overlap(range, &change.range))
+      });
+      if !is_excluded {
+        text_changes.extend(changes);
+      }
+    }
+  }
+  for child in node.children() {
+    visit_node(child, params, text_changes);
+  }
+}
+
+fn get_call_text_changes(
+  call_expr: &CallExpr,
+  params: &GetDenoEnvTextChangesParams,
+) -> Option<Vec<TextChange>> {
+  let method_name = get_deno_env_method_name(call_expr, params)?;
+  if call_expr.args.iter().any(|arg| arg.inner.spread.is_some()) {
+    return None;
+  }
+  let text_info = params.program.text_info();
+  let change = |start: SourcePos, end: SourcePos, new_text: &str| TextChange {
+    range: to_byte_range(text_info, SourceRange::new(start, end)),
+    new_text: new_text.to_string(),
+  };
+  let args = &call_expr.args;
+  // from the start of the call to the first argument
+  let head =
+    |new_text: &str| change(call_expr.start(), args[0].start(), new_text);
+  // from the last argument to the end of the call
+  let tail = |new_text: &str| {
+    change(args[args.len() - 1].end(), call_expr.end(), new_text)
+  };
+
+  Some(match (method_name, args.len()) {
+    ("get", 1) => vec![head("process.env["), tail("]")],
+    ("has", 1) => vec![head("(process.env["), tail("] !== undefined)")],
+    ("set", 2) => vec![
+      head("(process.env["),
+      change(args[0].end(), args[1].start(), "] = "),
+      tail(")"),
+    ],
+    ("delete", 1) => vec![head("(void delete process.env["), tail("])")],
+    ("toObject", 0) => {
+      let new_text = if is_typescript(params.media_type) {
+        "({ ...process.env } as { [index: string]: string })"
+      } else {
+        "({ ...process.env })"
+      };
+      vec![change(call_expr.start(), call_expr.end(), new_text)]
+    }
+    _ => return None,
+  })
+}
+
+/// Gets the name of the method when the call is `Deno.env.<method>(...)`
+/// and `Deno` is the global.
+fn get_deno_env_method_name<'a>(
+  call_expr: &CallExpr<'a>,
+  params: &GetDenoEnvTextChangesParams,
+) -> Option<&'a str> {
+  let callee = match call_expr.callee {
+    Callee::Expr(Expr::Member(member)) => member,
+    _ => return None,
+  };
+  let method_name = match callee.prop {
+    MemberProp::Ident(ident) => ident.sym().as_ref(),
+    _ => return None,
+  };
+  let env = match callee.obj {
+    Expr::Member(member) => member,
+    _ => return None,
+  };
+  let is_env = matches!(
+    env.prop,
+    MemberProp::Ident(ident) if ident.sym().as_ref() == "env"
+  );
+  let is_deno = matches!(
+    env.obj,
+    Expr::Ident(ident) if ident.sym().as_ref() == "Deno"
+      && ident.ctxt() == params.unresolved_context
+  );
+  if is_env && is_deno {
+    Some(method_name)
+  } else {
+    None
+  }
+}
+
+fn is_typescript(media_type: MediaType) -> bool {
+  matches!(
+    media_type,
+    MediaType::TypeScript | MediaType::Mts | MediaType::Cts | MediaType::Tsx
+  )
+}
@@ -0,0 +1,145 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use deno_ast::swc::ast::BinaryOp;
+use deno_ast::swc::ast::UnaryOp;
+use deno_ast::swc::common::SyntaxContext;
+use deno_ast::view::*;
+use deno_ast::ModuleSpecifier;
+use deno_ast::SourceRanged;
+use deno_ast::SourceTextInfoProvider;
+
+use super::NodeRule;
+use crate::analyze::is_global_reference;
+use crate::DenoFeatureDetection;
+use crate::ShimImportStyle;
+
+/// Gets the check for whether the code is running in Deno when the
+/// identifier is the `Deno` of `typeof Deno`, or the global object of
+/// `typeof globalThis.Deno` or `"Deno" in globalThis` (also `window` and
+/// `self`).
+pub fn get_deno_feature_detection<'a>(
+  ident: &'a Ident<'a>,
+) -> Option<Node<'a>> {
+  match ident.sym().as_ref() {
+    "Deno" => {
+      let unary_expr = ident.parent().to::<UnaryExpr>()?;
+      (unary_expr.inner.op == UnaryOp::TypeOf
+        && unary_expr.arg.range() == ident.range())
+      .then(|| unary_expr.into())
+    }
+    "globalThis" | "window" | "self" => match ident.parent() {
+      Node::MemberExpr(member_expr) => {
+        let is_deno_prop = match member_expr.prop {
+          MemberProp::Ident(prop) => prop.sym().as_ref() == "Deno",
+          MemberProp::Computed(computed) => matches!(
+            computed.expr,
+            Expr::Lit(Lit::Str(str)) if str.value().as_ref() == "Deno"
+          ),
+          MemberProp::PrivateName(_) => false,
+        };
+        if !is_deno_prop || member_expr.obj.range() != ident.range() {
+          return None;
+        }
+        let unary_expr = member_expr.parent().to::<UnaryExpr>()?;
+        (unary_expr.inner.op == UnaryOp::TypeOf).then(|| unary_expr.into())
+      }
+      Node::BinExpr(bin_expr) => {
+        let is_deno_in = bin_expr.inner.op == BinaryOp::In
+          && bin_expr.right.range() == ident.range()
+          && matches!(
+            bin_expr.left,
+            Expr::Lit(Lit::Str(str)) if str.value().as_ref() == "Deno"
+          );
+        is_deno_in.then(|| bin_expr.into())
+      }
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+pub struct GetDenoFeatureDetectionWarningsParams<'a> {
+  pub specifier: &'a ModuleSpecifier,
+  pub program: &'a Program<'a>,
+  pub is_declaration_file: bool,
+  pub unresolved_context: SyntaxContext,
+  pub shim_global_names: &'a HashSet<&'a str>,
+  pub ignore_line_indexes: &'a HashSet<usize>,
+  pub top_level_decls: &'a HashSet<String>,
+  pub deno_feature_detection: DenoFeatureDetection,
+  pub import_style: ShimImportStyle,
+}
+
+/// Rule that warns about the checks for whether the code is running in
+/// Deno, which behave differently once the `Deno` shim is used.
+pub struct DenoFeatureDetectionRule<'a> {
+  params: &'a GetDenoFeatureDetectionWarningsParams<'a>,
+  warnings: Vec<String>,
+}
+
+impl<'a> DenoFeatureDetectionRule<'a> {
+  pub fn new(params: &'a GetDenoFeatureDetectionWarningsParams<'a>) -> Self {
+    Self {
+      params,
+      warnings: Vec::new(),
+    }
+  }
+
+  pub fn into_warnings(self) -> Vec<String> {
+    self.warnings
+  }
+}
+
+impl<'a> NodeRule<'a> for DenoFeatureDetectionRule<'a> {
+  fn visit_node(&mut self, node: Node<'a>) -> Result<()> {
+    let params = self.params;
+    if params.is_declaration_file || !params.shim_global_names.contains("Deno")
+    {
+      return Ok(());
+    }
+    let ident = match node {
+      Node::Ident(ident) => ident,
+      _ => return Ok(()),
+    };
+    if !is_global_reference(
+      ident,
+      params.unresolved_context,
+      params.top_level_decls,
+    ) || params
+      .ignore_line_indexes
+      .contains(&ident.start_line_fast(params.program))
+    {
+      return Ok(());
+    }
+    if let Some(check) = get_deno_feature_detection(ident) {
+      let check_text = check.text_fast(params.program);
+      // the side effect import installs the shim on `globalThis`
+      let message = if params.deno_feature_detection
+        == DenoFeatureDetection::Shim
+        || params.import_style == ShimImportStyle::SideEffect
+      {
+        format!(
+          "`{}` checks the `Deno` shim, which is always defined, so the code it guards also runs in Node.js.",
+          check_text,
+        )
+      } else {
+        format!(
+          "`{}` checks the global `Deno` rather than the `Deno` shim, so the code it guards only runs in Deno.",
+          check_text,
+        )
+      };
+      let position = params
+        .program
+        .text_info()
+        .line_and_column_display(check.start());
+      self.warnings.push(format!(
+        "{}\n    at {}:{}:{}",
+        message, params.specifier, position.line_number, position.column_number,
+      ));
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,193 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+
+use anyhow::Result;
+use deno_ast::swc::common::SyntaxContext;
+use deno_ast::view::*;
+use deno_ast::ModuleSpecifier;
+use deno_ast::RootNode;
+use deno_ast::SourceRanged;
+use deno_ast::SourceRangedForSpanned;
+use deno_ast::SourceTextInfoProvider;
+use deno_ast::TextChange;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::NodeRule;
+use super::TRIPLE_SLASH_REFERENCE_RE;
+use crate::analyze::is_global_reference;
+
+/// Matches a reference to one of the DOM libs (ex. `lib="dom"` or
+/// `lib="dom.iterable"`).
+static DOM_LIB_REFERENCE_RE: Lazy<Regex> = Lazy::new(|| {
+  Regex::new(r#"(?i)\slib\s*=\s*["']dom(\.[^"']*)?["']"#).unwrap()
+});
+
+/// Globals and types that are only declared by the DOM libs. Globals that
+/// Deno also declares (ex. `window` and `navigator`) aren't included.
+const DOM_GLOBALS: [&str; 28] = [
+  "CSSStyleDeclaration",
+  "DOMParser",
+  "Document",
+  "DocumentFragment",
+  "Element",
+  "HTMLAnchorElement",
+  "HTMLButtonElement",
+  "HTMLCanvasElement",
+  "HTMLCollection",
+  "HTMLDivElement",
+  "HTMLElement",
+  "HTMLFormElement",
+  "HTMLImageElement",
+  "HTMLInputElement",
+  "HTMLTemplateElement",
+  "IntersectionObserver",
+  "KeyboardEvent",
+  "MouseEvent",
+  "MutationObserver",
+  "NodeList",
+  "ResizeObserver",
+  "ShadowRoot",
+  "cancelAnimationFrame",
+  "customElements",
+  "document",
+  "getComputedStyle",
+  "requestAnimationFrame",
+  "sessionStorage",
+];
+
+pub struct GetDomLibParams<'a> {
+  pub specifier: &'a ModuleSpecifier,
+  pub program: &'a Program<'a>,
+  pub unresolved_context: SyntaxContext,
+  pub ignore_line_indexes: &'a HashSet<usize>,
+  pub top_level_decls: &'a HashSet<String>,
+  /// Name of the package with the DOM types when one is configured.
+  pub types_package_name: Option<&'a str>,
+}
+
+pub struct DomLibResult {
+  pub text_changes: Vec<TextChange>,
+  pub warnings: Vec<String>,
+  /// If the module references the DOM libs or uses their globals.
+  pub uses_dom_apis: bool,
+}
+
+/// Rule that finds the usages of the globals and types of the DOM, which
+/// aren't in the types of Node.js.
+pub struct DomLibRule<'a> {
+  params: &'a GetDomLibParams<'a>,
+  used_globals: BTreeSet<String>,
+}
+
+impl<'a> DomLibRule<'a> {
+  pub fn new(params: &'a GetDomLibParams<'a>) -> Self {
+    Self {
+      params,
+      used_globals: BTreeSet::new(),
+    }
+  }
+
+  pub fn into_result(self) -> DomLibResult {
+    let params = self.params;
+    let program = params.program;
+    let mut text_changes = Vec::new();
+    let mut has_lib_reference = false;
+    for comment in program.leading_comments_fast(program) {
+      if !TRIPLE_SLASH_REFERENCE_RE.is_match(&comment.text)
+        || !DOM_LIB_REFERENCE_RE.is_match(&comment.text)
+      {
+        continue;
+      }
+      has_lib_reference = true;
+      if let Some(types_package_name) = params.types_package_name {
+        let start_pos = program.text_info().range().start;
+        let range = comment.range();
+        text_changes.push(TextChange {
+          new_text: format!(
+            "/// <reference types=\"{}\" />",
+            get_types_reference_name(types_package_name)
+          ),
+          range: range.start().as_byte_index(start_pos)
+            ..range.end().as_byte_index(start_pos),
+        });
+      }
+    }
+
+    let uses_dom_apis = has_lib_reference || !self.used_globals.is_empty();
+    let mut warnings = Vec::new();
+    // a lib reference already includes the DOM types when type checking
+    if !self.used_globals.is_empty()
+      && !has_lib_reference
+      && params.types_package_name.is_none()
+    {
+      warnings.push(format!(
+        "{} uses DOM APIs ({}), which aren't in the types of Node.js. Specify a DOM types package (ex. @types/web) or add the \"dom\" lib to the compiler options.",
+        params.specifier,
+        self.used_globals.into_iter().collect::<Vec<_>>().join(", "),
+      ));
+    }
+
+    DomLibResult {
+      text_changes,
+      warnings,
+      uses_dom_apis,
+    }
+  }
+}
+
+impl<'a> NodeRule<'a> for DomLibRule<'a> {
+  fn visit_node(&mut self, node: Node<'a>) -> Result<()> {
+    let params = self.params;
+    let ident = match node {
+      Node::Ident(ident) => ident,
+      _ => return Ok(()),
+    };
+    let name = ident.sym().as_ref();
+    if !DOM_GLOBALS.contains(&name)
+      || !is_global_reference(
+        ident,
+        params.unresolved_context,
+        params.top_level_decls,
+      )
+      || params
+        .ignore_line_indexes
+        .contains(&ident.start_line_fast(params.program))
+    {
+      return Ok(());
+    }
+    if !self.used_globals.contains(name) {
+      self.used_globals.insert(name.to_string());
+    }
+    Ok(())
+  }
+}
+
+/// Gets the name to reference the types of a package with, which doesn't
+/// include the `@types/` scope (ex. `web` for `@types/web`).
+fn get_types_reference_name(package_name: &str) -> &str {
+  package_name.strip_prefix("@types/").unwrap_or(package_name)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn types_reference_name() {
+    assert_eq!(get_types_reference_name("@types/web"), "web");
+    assert_eq!(get_types_reference_name("dom-types"), "dom-types");
+  }
+
+  #[test]
+  fn dom_lib_reference() {
+    assert!(DOM_LIB_REFERENCE_RE.is_match(r#"/ <reference lib="dom" />"#));
+    assert!(
+      DOM_LIB_REFERENCE_RE.is_match(r#"/ <reference lib='dom.iterable' />"#)
+    );
+    assert!(!DOM_LIB_REFERENCE_RE.is_match(r#"/ <reference lib="deno.ns" />"#));
+    assert!(!DOM_LIB_REFERENCE_RE.is_match(r#"/ <reference lib="domain" />"#));
+  }
+}
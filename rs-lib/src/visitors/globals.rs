@@ -1,7 +1,10 @@
 // Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
 
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashSet;
 
+use anyhow::Result;
 use deno_ast::swc::common::SyntaxContext;
 use deno_ast::view::*;
 use deno_ast::SourcePos;
@@ -10,8 +13,18 @@ use deno_ast::SourceRanged;
 use deno_ast::SourceTextInfoProvider;
 use deno_ast::TextChange;
 
+use super::can_replace;
+use super::get_deno_feature_detection;
+use super::get_deno_server_api;
+use super::NodeRule;
+use crate::analyze::is_declaration_ident;
+use crate::analyze::is_global_reference;
 use crate::analyze::is_in_type;
 use crate::utils::text_change_for_prepend_statement_to_text;
+use crate::DenoFeatureDetection;
+use crate::QualifiedGlobalAccess;
+use crate::ServerShim;
+use crate::ShimImportStyle;
 
 pub struct GetGlobalTextChangesParams<'a> {
   pub program: &'a Program<'a>,
@@ -20,11 +33,25 @@ pub struct GetGlobalTextChangesParams<'a> {
   pub shim_global_names: &'a HashSet<&'a str>,
   pub ignore_line_indexes: &'a HashSet<usize>,
   pub top_level_decls: &'a HashSet<String>,
+  pub server_shim: Option<&'a ServerShim>,
+  pub import_style: ShimImportStyle,
+  pub deno_feature_detection: DenoFeatureDetection,
+  pub qualified_global_access: QualifiedGlobalAccess,
 }
 
 pub struct GetGlobalTextChangesResult {
   pub text_changes: Vec<TextChange>,
-  pub imported_shim: bool,
+  /// Names of the globals that were replaced with their shims, which is
+  /// `globalThis` for the merged global object and `Deno.<api>` for the
+  /// server APIs. Sorted and empty when the shim wasn't imported.
+  pub used_shim_globals: Vec<String>,
+}
+
+/// Rule that replaces the globals in a program with their shims.
+pub struct GlobalsRule<'a> {
+  context: Context<'a>,
+  shim_specifier: &'a str,
+  import_style: ShimImportStyle,
 }
 
 struct Context<'a> {
@@ -32,76 +59,284 @@ struct Context<'a> {
   unresolved_context: SyntaxContext,
   top_level_decls: &'a HashSet<String>,
   shim_global_names: &'a HashSet<&'a str>,
-  import_shim: bool,
   text_changes: Vec<TextChange>,
+  /// Ranges to replace with `<shim import name>.<text>`. The import name is
+  /// only known once all the identifiers in the program have been seen.
+  shim_text_changes: Vec<(std::ops::Range<usize>, String)>,
+  /// Ranges of the shimmed globals accessed on the global object to
+  /// replace with the shimmed global by its name.
+  qualified_shim_text_changes: Vec<(std::ops::Range<usize>, String)>,
+  used_shim_globals: BTreeSet<String>,
+  all_ident_names: HashSet<String>,
+  declared_names: HashSet<String>,
+  /// Ranges of the identifiers that are shorthand properties of an
+  /// object literal, which need to keep the property name when changed.
+  shorthand_prop_ranges: HashSet<std::ops::Range<usize>>,
   ignore_line_indexes: &'a HashSet<usize>,
+  server_shim: Option<&'a ServerShim>,
+  deno_feature_detection: DenoFeatureDetection,
+  qualified_global_access: QualifiedGlobalAccess,
 }
 
-pub fn get_global_text_changes(
-  params: &GetGlobalTextChangesParams<'_>,
-) -> GetGlobalTextChangesResult {
-  let mut context = Context {
-    program: params.program,
-    unresolved_context: params.unresolved_context,
-    top_level_decls: params.top_level_decls,
-    shim_global_names: params.shim_global_names,
-    import_shim: false,
-    text_changes: Vec::new(),
-    ignore_line_indexes: params.ignore_line_indexes,
-  };
-  let program = params.program;
+impl<'a> GlobalsRule<'a> {
+  pub fn new(params: &GetGlobalTextChangesParams<'a>) -> Self {
+    Self {
+      context: Context {
+        program: params.program,
+        unresolved_context: params.unresolved_context,
+        top_level_decls: params.top_level_decls,
+        shim_global_names: params.shim_global_names,
+        text_changes: Vec::new(),
+        shim_text_changes: Vec::new(),
+        qualified_shim_text_changes: Vec::new(),
+        used_shim_globals: BTreeSet::new(),
+        all_ident_names: HashSet::new(),
+        declared_names: HashSet::new(),
+        shorthand_prop_ranges: HashSet::new(),
+        ignore_line_indexes: params.ignore_line_indexes,
+        server_shim: params.server_shim,
+        deno_feature_detection: params.deno_feature_detection,
+        qualified_global_access: params.qualified_global_access,
+      },
+      shim_specifier: params.shim_specifier,
+      import_style: params.import_style,
+    }
+  }
 
+  pub fn into_result(self) -> GetGlobalTextChangesResult {
+    let mut context = self.context;
+    // a name declared in the module would shadow the shimmed global when
+    // it's imported by name or installed on `globalThis`
+    let qualified_shim_text_changes =
+      std::mem::take(&mut context.qualified_shim_text_changes);
+    for (range, name) in qualified_shim_text_changes {
+      if self.import_style != ShimImportStyle::Namespace
+        && context.declared_names.contains(&name)
+      {
+        context.used_shim_globals.insert("globalThis".to_string());
+        context
+          .shim_text_changes
+          .push((range, format!("dntGlobalThis.{}", name)));
+      } else {
+        context.used_shim_globals.insert(name.clone());
+        context.shim_text_changes.push((range, name));
+      }
+    }
+    if !context.shim_text_changes.is_empty() {
+      let shim_text_changes =
+        context.shim_text_changes.drain(..).collect::<Vec<_>>();
+      let statement = match self.import_style {
+        ShimImportStyle::Namespace => add_namespace_import_text_changes(
+          shim_text_changes,
+          self.shim_specifier,
+          &mut context,
+        ),
+        ShimImportStyle::Named => add_named_import_text_changes(
+          shim_text_changes,
+          self.shim_specifier,
+          &mut context,
+        ),
+        ShimImportStyle::SideEffect => {
+          // the shim file installs the globals on `globalThis`, so only the
+          // server APIs, which aren't globals, need the shim's exports
+          let (server_text_changes, global_text_changes): (Vec<_>, Vec<_>) =
+            shim_text_changes
+              .into_iter()
+              .partition(|(_, text)| text.starts_with("dntServer."));
+          for (range, text) in global_text_changes {
+            push_text_change_if_changed(
+              range,
+              text.replace("dntGlobalThis", "globalThis"),
+              &mut context,
+            );
+          }
+          if server_text_changes.is_empty() {
+            format!("import \"{}\";", self.shim_specifier)
+          } else {
+            add_namespace_import_text_changes(
+              server_text_changes,
+              self.shim_specifier,
+              &mut context,
+            )
+          }
+        }
+      };
+      context
+        .text_changes
+        .push(text_change_for_prepend_statement_to_text(
+          context.program,
+          &statement,
+        ));
+    }
+
+    GetGlobalTextChangesResult {
+      text_changes: context.text_changes,
+      used_shim_globals: context.used_shim_globals.into_iter().collect(),
+    }
+  }
+}
+
+/// Changes the shimmed globals to be accessed on a namespace import of
+/// the shim file (ex. `dntShim.Deno`), returning the import statement.
+fn add_namespace_import_text_changes(
+  shim_text_changes: Vec<(std::ops::Range<usize>, String)>,
+  shim_specifier: &str,
+  context: &mut Context,
+) -> String {
   // currently very crude. This should be improved to only look
   // at binding declarations
-  let all_ident_names = get_all_ident_names(context.program);
-  let global_shim_name = get_unique_name("dntShim", &all_ident_names);
-
-  visit_children(program.into(), &global_shim_name, &mut context);
-
-  if context.import_shim {
-    context
-      .text_changes
-      .push(text_change_for_prepend_statement_to_text(
-        program,
-        &format!(
-          "import * as {} from \"{}\";",
-          global_shim_name, params.shim_specifier,
-        ),
-      ));
+  let global_shim_name = get_unique_name("dntShim", &context.all_ident_names);
+  for (range, text) in shim_text_changes {
+    push_text_change_if_changed(
+      range,
+      format!("{}.{}", global_shim_name, text),
+      context,
+    );
   }
+  format!(
+    "import * as {} from \"{}\";",
+    global_shim_name, shim_specifier
+  )
+}
+
+/// Imports each of the shimmed globals by name from the shim file,
+/// returning the import statement. The globals keep their names since
+/// nothing in scope where they're referenced has the same name, but the
+/// exports that aren't globals (ex. `dntGlobalThis`) are given a unique one.
+fn add_named_import_text_changes(
+  shim_text_changes: Vec<(std::ops::Range<usize>, String)>,
+  shim_specifier: &str,
+  context: &mut Context,
+) -> String {
+  let mut local_names: BTreeMap<String, String> = BTreeMap::new();
+  for (range, text) in shim_text_changes {
+    let (export_name, rest) =
+      text.split_at(text.find('.').unwrap_or(text.len()));
+    let local_name = local_names
+      .entry(export_name.to_string())
+      .or_insert_with(|| {
+        if context.shim_global_names.contains(export_name) {
+          export_name.to_string()
+        } else {
+          get_unique_name(export_name, &context.all_ident_names)
+        }
+      })
+      .clone();
+    push_text_change_if_changed(
+      range,
+      format!("{}{}", local_name, rest),
+      context,
+    );
+  }
+  format!(
+    "import {{ {} }} from \"{}\";",
+    local_names
+      .iter()
+      .map(|(export_name, local_name)| if export_name == local_name {
+        export_name.to_string()
+      } else {
+        format!("{} as {}", export_name, local_name)
+      })
+      .collect::<Vec<_>>()
+      .join(", "),
+    shim_specifier
+  )
+}
 
-  GetGlobalTextChangesResult {
-    text_changes: context.text_changes,
-    imported_shim: context.import_shim,
+fn push_text_change_if_changed(
+  range: std::ops::Range<usize>,
+  new_text: String,
+  context: &mut Context,
+) {
+  let text = &context.program.text_info().text_str()[range.clone()];
+  if text != new_text {
+    // keep the name of a shorthand property (ex. `{ Deno }`)
+    let new_text = if context.shorthand_prop_ranges.contains(&range) {
+      format!("{}: {}", text, new_text)
+    } else {
+      new_text
+    };
+    context.text_changes.push(TextChange { range, new_text });
   }
 }
 
-fn visit_children(node: Node, import_name: &str, context: &mut Context) {
-  for child in node.children() {
-    visit_children(child, import_name, context);
+impl<'a> NodeRule<'a> for GlobalsRule<'a> {
+  fn visit_node(&mut self, node: Node<'a>) -> Result<()> {
+    visit_node(node, &mut self.context);
+    Ok(())
   }
+}
 
+fn visit_node(node: Node, context: &mut Context) {
   if let Node::Ident(ident) = node {
+    context.all_ident_names.insert(ident.sym().to_string());
+    if is_declaration_ident(ident.into()) {
+      context.declared_names.insert(ident.sym().to_string());
+    }
+    if matches!(ident.parent(), Node::ObjectLit(_)) {
+      context.shorthand_prop_ranges.insert(create_range(
+        ident.start(),
+        ident.end(),
+        context,
+      ));
+    }
+
     let id = ident.inner.to_id();
     let is_unresolved_context = id.1 == context.unresolved_context;
     let ident_text = ident.text_fast(context.program);
 
     if is_unresolved_context {
-      // change `window` -> `globalThis`
-      if ident_text == "window" {
-        if !context.top_level_decls.contains("window")
-          && !has_ignore_comment(ident.into(), context)
+      // leave `typeof Deno` checking the global `Deno`
+      if ident_text == "Deno"
+        && is_native_deno_feature_detection(ident, context)
+      {
+        return;
+      }
+
+      // change `Deno.serve` -> `<shim import name>.dntServer.serve`
+      if let Some(server_shim) = context.server_shim {
+        if let Some((member_expr, api)) = get_deno_server_api(ident) {
+          if is_global_reference(
+            ident,
+            context.unresolved_context,
+            context.top_level_decls,
+          ) && !has_ignore_comment(ident.into(), context)
+          {
+            context.shim_text_changes.push((
+              create_range(member_expr.start(), member_expr.end(), context),
+              format!("dntServer.{}", server_shim.export_name(api)),
+            ));
+            context.used_shim_globals.insert(format!("Deno.{}", api));
+          }
+          return;
+        }
+      }
+
+      // change `window` and `self.<global>` -> `globalThis`
+      if ident_text == "window"
+        || (ident_text == "self"
+          && get_accessed_shim_global(ident, context).is_some())
+      {
+        if is_global_reference(
+          ident,
+          context.unresolved_context,
+          context.top_level_decls,
+        ) && !has_ignore_comment(ident.into(), context)
         {
           if let Some(text_change) =
-            get_global_this_text_change(ident, import_name, context)
+            get_qualified_shim_text_change(ident, context)
+          {
+            context.qualified_shim_text_changes.push(text_change);
+          } else if let Some(shim_text_change) =
+            get_global_this_text_change(ident, context)
           {
-            context.text_changes.push(text_change);
-            context.import_shim = true;
+            push_global_this_text_change(shim_text_change, context);
           } else {
-            context.text_changes.push(TextChange {
-              range: create_range(ident.start(), ident.end(), context),
-              new_text: "globalThis".to_string(),
-            });
+            push_text_change_if_changed(
+              create_range(ident.start(), ident.end(), context),
+              "globalThis".to_string(),
+              context,
+            );
           }
         }
         return;
@@ -110,10 +345,13 @@ fn visit_children(node: Node, import_name: &str, context: &mut Context) {
       // check to replace globalThis
       if ident_text == "globalThis" {
         if let Some(text_change) =
-          get_global_this_text_change(ident, import_name, context)
+          get_qualified_shim_text_change(ident, context)
+        {
+          context.qualified_shim_text_changes.push(text_change);
+        } else if let Some(shim_text_change) =
+          get_global_this_text_change(ident, context)
         {
-          context.text_changes.push(text_change);
-          context.import_shim = true;
+          push_global_this_text_change(shim_text_change, context);
         }
         return;
       }
@@ -121,14 +359,18 @@ fn visit_children(node: Node, import_name: &str, context: &mut Context) {
       // check if global should be imported
       for &name in context.shim_global_names.iter() {
         if ident_text == name
-          && !context.top_level_decls.contains(name)
-          && !should_ignore(ident.into(), context)
+          && is_global_reference(
+            ident,
+            context.unresolved_context,
+            context.top_level_decls,
+          )
+          && !has_ignore_comment(ident.into(), context)
         {
-          context.text_changes.push(TextChange {
-            range: create_range(ident.start(), ident.end(), context),
-            new_text: format!("{}.{}", import_name, ident_text),
-          });
-          context.import_shim = true;
+          context.shim_text_changes.push((
+            create_range(ident.start(), ident.end(), context),
+            ident_text.to_string(),
+          ));
+          context.used_shim_globals.insert(name.to_string());
           return;
         }
       }
@@ -136,12 +378,78 @@ fn visit_children(node: Node, import_name: &str, context: &mut Context) {
   }
 }
 
+fn push_global_this_text_change(
+  shim_text_change: (std::ops::Range<usize>, String),
+  context: &mut Context,
+) {
+  // a qualified name in a type is replaced with the shimmed global
+  let global_name = if shim_text_change.1 == "dntGlobalThis" {
+    "globalThis".to_string()
+  } else {
+    shim_text_change.1.clone()
+  };
+  context.used_shim_globals.insert(global_name);
+  context.shim_text_changes.push(shim_text_change);
+}
+
+/// Gets the shimmed global that the identifier is the global object of a
+/// member expression for (ex. the `globalThis` of `globalThis.Deno`).
+fn get_accessed_shim_global<'a>(
+  ident: &'a Ident<'a>,
+  context: &Context,
+) -> Option<(&'a MemberExpr<'a>, String)> {
+  let member_expr = ident.parent().to::<MemberExpr>()?;
+  if member_expr.obj.range() != ident.range() {
+    return None;
+  }
+  let name = match member_expr.prop {
+    MemberProp::Ident(prop_ident) => prop_ident.sym().to_string(),
+    MemberProp::Computed(computed) => match computed.expr {
+      Expr::Lit(Lit::Str(str)) => str.value().to_string(),
+      _ => return None,
+    },
+    MemberProp::PrivateName(_) => return None,
+  };
+  if context.shim_global_names.contains(name.as_str()) {
+    Some((member_expr, name))
+  } else {
+    None
+  }
+}
+
+/// Gets the change of a shimmed global accessed on the global object to
+/// the shimmed global (ex. `globalThis.Deno` -> `Deno`).
+fn get_qualified_shim_text_change(
+  ident: &Ident,
+  context: &Context,
+) -> Option<(std::ops::Range<usize>, String)> {
+  if context.qualified_global_access != QualifiedGlobalAccess::Shim
+    || has_ignore_comment(ident.into(), context)
+    || is_native_deno_feature_detection(ident, context)
+  {
+    return None;
+  }
+  let (member_expr, name) = get_accessed_shim_global(ident, context)?;
+  if can_replace(member_expr.into()) {
+    Some((
+      create_range(member_expr.start(), member_expr.end(), context),
+      name,
+    ))
+  } else {
+    None
+  }
+}
+
 fn get_global_this_text_change(
   ident: &Ident,
-  import_name: &str,
   context: &Context,
-) -> Option<TextChange> {
-  if should_ignore_global_this(ident, context) {
+) -> Option<(std::ops::Range<usize>, String)> {
+  if should_ignore_global_this(ident, context)
+    || is_native_deno_feature_detection(ident, context)
+    || (context.qualified_global_access == QualifiedGlobalAccess::Preserve
+      && !is_in_type(ident.into())
+      && get_accessed_shim_global(ident, context).is_some())
+  {
     return None;
   }
   if is_in_type(ident.into()) {
@@ -149,30 +457,26 @@ fn get_global_this_text_change(
       Node::TsQualifiedName(parent) => {
         let right_name = parent.right.text_fast(context.program);
         if context.shim_global_names.contains(&right_name) {
-          Some(TextChange {
-            range: create_range(parent.start(), parent.end(), context),
-            new_text: format!(
-              "{}.{}",
-              import_name,
-              // doesn't seem exactly right... will wait for a bug to open
-              parent.right.text_fast(context.program),
-            ),
-          })
+          Some((
+            create_range(parent.start(), parent.end(), context),
+            // doesn't seem exactly right... will wait for a bug to open
+            right_name.to_string(),
+          ))
         } else {
           None
         }
       }
-      Node::TsTypeQuery(_) => Some(TextChange {
-        range: create_range(ident.start(), ident.end(), context),
-        new_text: format!("{}.dntGlobalThis", import_name),
-      }),
+      Node::TsTypeQuery(_) => Some((
+        create_range(ident.start(), ident.end(), context),
+        "dntGlobalThis".to_string(),
+      )),
       _ => None,
     }
   } else {
-    Some(TextChange {
-      range: create_range(ident.start(), ident.end(), context),
-      new_text: format!("{}.dntGlobalThis", import_name),
-    })
+    Some((
+      create_range(ident.start(), ident.end(), context),
+      "dntGlobalThis".to_string(),
+    ))
   }
 }
 
@@ -211,8 +515,9 @@ fn should_ignore_global_this(ident: &Ident, context: &Context) -> bool {
   false
 }
 
-fn should_ignore(node: Node, context: &Context) -> bool {
-  has_ignore_comment(node, context) || is_declaration_ident(node)
+fn is_native_deno_feature_detection(ident: &Ident, context: &Context) -> bool {
+  context.deno_feature_detection == DenoFeatureDetection::Native
+    && get_deno_feature_detection(ident).is_some()
 }
 
 fn has_ignore_comment(node: Node, context: &Context) -> bool {
@@ -221,64 +526,10 @@ fn has_ignore_comment(node: Node, context: &Context) -> bool {
     .contains(&node.start_line_fast(context.program))
 }
 
-fn is_declaration_ident(node: Node) -> bool {
-  if let Some(parent) = node.parent() {
-    match parent {
-      Node::BindingIdent(decl) => decl.id.range().contains(&node.range()),
-      Node::ClassDecl(decl) => decl.ident.range().contains(&node.range()),
-      Node::ClassExpr(decl) => decl
-        .ident
-        .as_ref()
-        .map(|i| i.range().contains(&node.range()))
-        .unwrap_or(false),
-      Node::TsInterfaceDecl(decl) => decl.id.range().contains(&node.range()),
-      Node::FnDecl(decl) => decl.ident.range().contains(&node.range()),
-      Node::FnExpr(decl) => decl
-        .ident
-        .as_ref()
-        .map(|i| i.range().contains(&node.range()))
-        .unwrap_or(false),
-      Node::TsModuleDecl(decl) => decl.id.range().contains(&node.range()),
-      Node::TsNamespaceDecl(decl) => decl.id.range().contains(&node.range()),
-      Node::VarDeclarator(decl) => decl.name.range().contains(&node.range()),
-      Node::ImportNamedSpecifier(decl) => decl.range().contains(&node.range()),
-      Node::ExportNamedSpecifier(decl) => decl.range().contains(&node.range()),
-      Node::ImportDefaultSpecifier(decl) => {
-        decl.range().contains(&node.range())
-      }
-      Node::ExportDefaultSpecifier(decl) => {
-        decl.range().contains(&node.range())
-      }
-      Node::ImportStarAsSpecifier(decl) => decl.range().contains(&node.range()),
-      Node::ExportNamespaceSpecifier(decl) => {
-        decl.range().contains(&node.range())
-      }
-      Node::KeyValuePatProp(decl) => decl.key.range().contains(&node.range()),
-      Node::AssignPatProp(decl) => decl.key.range().contains(&node.range()),
-      _ => false,
-    }
-  } else {
-    false
-  }
-}
-
-fn get_all_ident_names(program: &Program) -> HashSet<String> {
-  let mut result = HashSet::new();
-  visit_children(&program.into(), &mut result);
-  return result;
-
-  fn visit_children(node: &Node, result: &mut HashSet<String>) {
-    for child in node.children() {
-      visit_children(&child, result);
-    }
-
-    if let Node::Ident(ident) = node {
-      result.insert(ident.sym().to_string());
-    }
-  }
-}
-
-fn get_unique_name(name: &str, all_idents: &HashSet<String>) -> String {
+pub(super) fn get_unique_name(
+  name: &str,
+  all_idents: &HashSet<String>,
+) -> String {
   let mut count = 0;
   let mut new_name = name.to_string();
   while all_idents.contains(&new_name) {
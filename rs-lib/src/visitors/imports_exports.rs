@@ -5,6 +5,7 @@ use std::path::PathBuf;
 
 use anyhow::Result;
 use deno_ast::view::*;
+use deno_ast::MediaType;
 use deno_ast::ModuleSpecifier;
 use deno_ast::SourcePos;
 use deno_ast::SourceRange;
@@ -12,120 +13,357 @@ use deno_ast::SourceRanged;
 use deno_ast::SourceRangedForSpanned;
 use deno_ast::SourceTextInfoProvider;
 use deno_ast::TextChange;
+use once_cell::sync::Lazy;
+use regex::Regex;
 
-use crate::graph::ModuleGraph;
+use super::NodeRule;
+use super::PATH_REFERENCE_RE;
+use super::TRIPLE_SLASH_REFERENCE_RE;
+use super::TYPES_REFERENCE_RE;
+use crate::analyze::get_module_worker_specifier;
+use crate::graph::DependencyResolver;
 use crate::mappings::Mappings;
+use crate::utils::get_relative_path;
 use crate::utils::get_relative_specifier;
+use crate::utils::with_extension;
+use crate::ImportAttributeAction;
+use crate::ImportAttributeOptions;
+use crate::JsxOptions;
+use crate::RelativeSpecifierExtension;
+
+// lifted from deno_graph
+/// Matches the `@jsxImportSource` pragma.
+static JSX_IMPORT_SOURCE_RE: Lazy<Regex> =
+  Lazy::new(|| Regex::new(r"(?i)^[\s*]*@jsxImportSource\s+(\S+)").unwrap());
 
 pub struct GetImportExportsTextChangesParams<'a> {
   pub specifier: &'a ModuleSpecifier,
-  pub module_graph: &'a ModuleGraph,
+  pub media_type: MediaType,
+  pub is_declaration_file: bool,
+  pub jsx: Option<&'a JsxOptions>,
+  pub dependency_resolver: &'a DependencyResolver,
   pub mappings: &'a Mappings,
   pub program: &'a Program<'a>,
   pub package_specifier_mappings: &'a HashMap<ModuleSpecifier, String>,
+  pub relative_specifier_extension: RelativeSpecifierExtension,
+  /// Add a comment with the original url next to the rewritten
+  /// specifiers of remote modules.
+  pub annotate_rewrites: bool,
+  pub import_attributes: &'a ImportAttributeOptions,
+}
+
+pub struct GetImportExportsTextChangesResult {
+  pub text_changes: Vec<TextChange>,
+  /// The relative specifiers the module specifiers were rewritten to,
+  /// which should each resolve to an output file.
+  pub relative_specifiers: Vec<String>,
+}
+
+/// Rule that rewrites the module specifiers in a program to point
+/// at the output files.
+pub struct ImportExportsRule<'a> {
+  context: Context<'a>,
+  /// The range of the last handled node, whose descendants are skipped.
+  skip_range: Option<SourceRange>,
 }
 
 struct Context<'a> {
   program: &'a Program<'a>,
   specifier: &'a ModuleSpecifier,
-  module_graph: &'a ModuleGraph,
+  is_declaration_file: bool,
+  dependency_resolver: &'a DependencyResolver,
   mappings: &'a Mappings,
   output_file_path: &'a PathBuf,
   text_changes: Vec<TextChange>,
+  relative_specifiers: Vec<String>,
   package_specifier_mappings: &'a HashMap<ModuleSpecifier, String>,
+  relative_specifier_extension: RelativeSpecifierExtension,
+  annotate_rewrites: bool,
+  import_attributes: &'a ImportAttributeOptions,
 }
 
-pub fn get_import_exports_text_changes(
-  params: &GetImportExportsTextChangesParams<'_>,
-) -> Result<Vec<TextChange>> {
-  let mut context = Context {
-    program: params.program,
-    specifier: params.specifier,
-    module_graph: params.module_graph,
-    mappings: params.mappings,
-    output_file_path: params.mappings.get_file_path(params.specifier),
-    text_changes: Vec::new(),
-    package_specifier_mappings: params.package_specifier_mappings,
-  };
+impl<'a> ImportExportsRule<'a> {
+  pub fn new(params: &GetImportExportsTextChangesParams<'a>) -> Self {
+    let mut context = Context {
+      program: params.program,
+      specifier: params.specifier,
+      is_declaration_file: params.is_declaration_file,
+      dependency_resolver: params.dependency_resolver,
+      mappings: params.mappings,
+      output_file_path: params.mappings.get_file_path(params.specifier),
+      text_changes: Vec::new(),
+      relative_specifiers: Vec::new(),
+      package_specifier_mappings: params.package_specifier_mappings,
+      relative_specifier_extension: params.relative_specifier_extension,
+      annotate_rewrites: params.annotate_rewrites,
+      import_attributes: params.import_attributes,
+    };
+
+    if params.is_declaration_file {
+      visit_triple_slash_references(&mut context);
+    }
+    if matches!(params.media_type, MediaType::Jsx | MediaType::Tsx) {
+      visit_jsx_import_source(&mut context, params.jsx);
+    }
 
-  visit_children(params.program.as_node(), &mut context)?;
+    Self {
+      context,
+      skip_range: None,
+    }
+  }
 
-  Ok(context.text_changes)
+  pub fn into_result(self) -> GetImportExportsTextChangesResult {
+    GetImportExportsTextChangesResult {
+      text_changes: self.context.text_changes,
+      relative_specifiers: self.context.relative_specifiers,
+    }
+  }
 }
 
-fn visit_children(node: Node, context: &mut Context) -> Result<()> {
-  for child in node.children() {
-    match child {
-      Node::ImportDecl(import_decl) => {
-        visit_module_specifier(import_decl.src, context);
-        if let Some(asserts) = import_decl.asserts {
-          visit_asserts(asserts, context);
-        }
+impl<'a> NodeRule<'a> for ImportExportsRule<'a> {
+  fn visit_node(&mut self, node: Node<'a>) -> Result<()> {
+    // nodes are visited in pre-order, so once a node is outside
+    // the skipped range then so are all the nodes after it
+    if let Some(skip_range) = &self.skip_range {
+      if skip_range.contains(&node.range()) {
+        return Ok(());
       }
-      Node::ExportAll(export_all) => {
-        visit_module_specifier(export_all.src, context);
-        if let Some(asserts) = export_all.asserts {
-          visit_asserts(asserts, context);
-        }
+      self.skip_range = None;
+    }
+
+    if visit_node(node, &mut self.context) {
+      self.skip_range = Some(node.range());
+    }
+
+    Ok(())
+  }
+}
+
+/// Rewrites the `path` and `types` triple slash references in a declaration
+/// file so they point at the output location of the referenced files.
+fn visit_triple_slash_references(context: &mut Context) {
+  let program = context.program;
+  for comment in program.leading_comments_fast(program) {
+    if !TRIPLE_SLASH_REFERENCE_RE.is_match(&comment.text) {
+      continue;
+    }
+    let (captures, is_path) = if let Some(captures) =
+      PATH_REFERENCE_RE.captures(&comment.text)
+    {
+      (captures, true)
+    } else if let Some(captures) = TYPES_REFERENCE_RE.captures(&comment.text) {
+      (captures, false)
+    } else {
+      continue;
+    };
+    let value = captures.get(1).unwrap();
+    let specifier = match context
+      .dependency_resolver
+      .resolve_dependency(value.as_str(), context.specifier)
+    {
+      Some(specifier) => specifier,
+      None => continue,
+    };
+    let file_path = match context.mappings.maybe_file_path(&specifier) {
+      Some(file_path) => file_path,
+      None => continue,
+    };
+    let new_text = if is_path {
+      get_relative_path(context.output_file_path, file_path)
+    } else {
+      get_relative_specifier(
+        context.output_file_path,
+        file_path,
+        context.relative_specifier_extension,
+      )
+    };
+    context.relative_specifiers.push(new_text.clone());
+    // the comment text does not include the leading `//`
+    let text_start = comment.start() + 2;
+    context.text_changes.push(TextChange {
+      range: create_range(
+        text_start + value.start(),
+        text_start + value.end(),
+        context,
+      ),
+      new_text,
+    });
+  }
+}
+
+/// Rewrites the `@jsxImportSource` pragma of a JSX module so it points at
+/// the output location of the import source. When there's no pragma, one
+/// is added for the import source of the automatic runtime in the options.
+fn visit_jsx_import_source(context: &mut Context, jsx: Option<&JsxOptions>) {
+  let module_name = jsx
+    .map(|jsx| jsx.runtime.import_source_module())
+    .unwrap_or("jsx-runtime");
+  let program = context.program;
+  for comment in program.leading_comments_fast(program) {
+    if let Some(captures) = JSX_IMPORT_SOURCE_RE.captures(&comment.text) {
+      let value = captures.get(1).unwrap();
+      if let Some(new_text) =
+        get_jsx_import_source_text(value.as_str(), module_name, context)
+      {
+        // the comment text does not include the leading `//` or `/*`
+        let text_start = comment.start() + 2;
+        context.text_changes.push(TextChange {
+          range: create_range(
+            text_start + value.start(),
+            text_start + value.end(),
+            context,
+          ),
+          new_text,
+        });
       }
-      Node::NamedExport(named_export) => {
-        if let Some(src) = &named_export.src {
-          visit_module_specifier(src, context);
-        }
-        if let Some(asserts) = named_export.asserts {
-          visit_asserts(asserts, context);
-        }
+      return;
+    }
+  }
+
+  let import_source = jsx
+    .filter(|jsx| jsx.runtime.is_automatic())
+    .and_then(|jsx| jsx.import_source.as_ref());
+  if let Some(import_source) = import_source {
+    if let Some(new_text) =
+      get_jsx_import_source_text(import_source, module_name, context)
+    {
+      let start = program.text_info().range().start;
+      context.text_changes.push(TextChange {
+        range: create_range(start, start, context),
+        new_text: format!("/** @jsxImportSource {} */\n", new_text),
+      });
+    }
+  }
+}
+
+/// Gets the import source to use in the output, which the compiler
+/// appends the runtime module name to (ex. `preact` for `preact/jsx-runtime`).
+fn get_jsx_import_source_text(
+  import_source: &str,
+  module_name: &str,
+  context: &Context,
+) -> Option<String> {
+  let specifier = context.dependency_resolver.resolve_dependency(
+    &format!("{}/{}", import_source, module_name),
+    context.specifier,
+  )?;
+  let runtime_specifier = if let Some(bare_specifier) =
+    context.package_specifier_mappings.get(&specifier)
+  {
+    bare_specifier.to_string()
+  } else {
+    let file_path = context.mappings.maybe_file_path(&specifier)?;
+    get_relative_path(context.output_file_path, with_extension(file_path, ""))
+  };
+  runtime_specifier
+    .strip_suffix(&format!("/{}", module_name))
+    .map(ToOwned::to_owned)
+}
+
+/// Visits the node, returning `true` when it was handled and its
+/// descendants should not be visited.
+fn visit_node(node: Node, context: &mut Context) -> bool {
+  match node {
+    Node::ImportDecl(import_decl) => {
+      let specifier = visit_module_specifier(import_decl.src, context);
+      let is_output_module = is_output_module(specifier.as_ref(), context);
+      if !import_decl.inner.type_only
+        && is_output_declaration_file(specifier.as_ref(), context)
+      {
+        visit_declaration_file_import(import_decl, context);
+      }
+      annotate_statement(import_decl.range(), specifier, context);
+      if let Some(asserts) = import_decl.asserts {
+        visit_asserts(asserts, is_output_module, context);
       }
-      Node::TsImportType(ts_import_type) => {
-        visit_module_specifier(ts_import_type.arg, context);
+      true
+    }
+    Node::ExportAll(export_all) => {
+      let specifier = visit_module_specifier(export_all.src, context);
+      let is_output_module = is_output_module(specifier.as_ref(), context);
+      annotate_statement(export_all.range(), specifier, context);
+      if let Some(asserts) = export_all.asserts {
+        visit_asserts(asserts, is_output_module, context);
       }
-      Node::TsModuleDecl(module_decl) => {
-        if let TsModuleName::Str(src) = &module_decl.id {
-          visit_module_specifier(src, context);
+      true
+    }
+    Node::NamedExport(named_export) => {
+      let mut is_output_module = false;
+      if let Some(src) = &named_export.src {
+        let specifier = visit_module_specifier(src, context);
+        is_output_module = self::is_output_module(specifier.as_ref(), context);
+        if !named_export.inner.type_only
+          && is_output_declaration_file(specifier.as_ref(), context)
+        {
+          visit_declaration_file_re_export(named_export, context);
         }
+        annotate_statement(named_export.range(), specifier, context);
+      }
+      if let Some(asserts) = named_export.asserts {
+        visit_asserts(asserts, is_output_module, context);
+      }
+      true
+    }
+    Node::TsImportEqualsDecl(import_equals_decl) => {
+      // ex. `import foo = require("./foo.ts");`
+      if let TsModuleRef::TsExternalModuleRef(module_ref) =
+        import_equals_decl.module_ref
+      {
+        let specifier = visit_module_specifier(module_ref.expr, context);
+        annotate_statement(import_equals_decl.range(), specifier, context);
       }
-      Node::CallExpr(call_expr) => {
-        if matches!(call_expr.callee, Callee::Import(_)) {
-          if let Some(Node::Str(src)) =
-            call_expr.args.get(0).map(|a| a.expr.as_node())
-          {
-            visit_module_specifier(src, context);
-            if call_expr.args.len() > 1 {
-              let assert_arg = call_expr.args[1];
-              let comma_token =
-                assert_arg.previous_token_fast(context.program).unwrap();
-              context.text_changes.push(TextChange {
-                range: create_range(
-                  comma_token.start(),
-                  assert_arg.end(),
-                  context,
-                ),
-                new_text: String::new(),
-              });
-            }
+      true
+    }
+    Node::TsImportType(ts_import_type) => {
+      let specifier = visit_module_specifier(ts_import_type.arg, context);
+      annotate_expr(ts_import_type.arg.range(), specifier, context);
+      true
+    }
+    Node::TsModuleDecl(module_decl) => {
+      if let TsModuleName::Str(src) = &module_decl.id {
+        let specifier = visit_module_specifier(src, context);
+        annotate_expr(src.range(), specifier, context);
+      }
+      true
+    }
+    Node::NewExpr(new_expr) => {
+      if let Some(src) = get_module_worker_specifier(new_expr) {
+        let specifier = visit_module_specifier(src, context);
+        annotate_expr(src.range(), specifier, context);
+      }
+      false
+    }
+    Node::CallExpr(call_expr) => {
+      if matches!(call_expr.callee, Callee::Import(_)) {
+        if let Some(Node::Str(src)) =
+          call_expr.args.get(0).map(|a| a.expr.as_node())
+        {
+          let specifier = visit_module_specifier(src, context);
+          let is_output_module = is_output_module(specifier.as_ref(), context);
+          annotate_expr(src.range(), specifier, context);
+          if call_expr.args.len() > 1 {
+            visit_dynamic_import_options(call_expr, is_output_module, context);
           }
-        } else {
-          visit_children(child, context)?;
         }
-      }
-      _ => {
-        visit_children(child, context)?;
+        true
+      } else {
+        false
       }
     }
+    _ => false,
   }
-
-  Ok(())
 }
 
-fn visit_module_specifier(str: &Str, context: &mut Context) {
+/// Rewrites the module specifier, returning the specifier it resolved to
+/// when it was rewritten.
+fn visit_module_specifier(
+  str: &Str,
+  context: &mut Context,
+) -> Option<ModuleSpecifier> {
   let value = str.value().to_string();
   let specifier = context
-    .module_graph
-    .resolve_dependency(&value, context.specifier);
-  let specifier = match specifier {
-    Some(s) => s,
-    None => return,
-  };
+    .dependency_resolver
+    .resolve_dependency(&value, context.specifier)?;
 
   let new_text = if let Some(bare_specifier) =
     context.package_specifier_mappings.get(&specifier)
@@ -133,26 +371,271 @@ fn visit_module_specifier(str: &Str, context: &mut Context) {
     bare_specifier.to_string()
   } else {
     let specifier_file_path = context.mappings.get_file_path(&specifier);
-    get_relative_specifier(context.output_file_path, specifier_file_path)
+    let relative_specifier = get_relative_specifier(
+      context.output_file_path,
+      specifier_file_path,
+      context.relative_specifier_extension,
+    );
+    context.relative_specifiers.push(relative_specifier.clone());
+    relative_specifier
   };
 
   context.text_changes.push(TextChange {
     range: create_range(str.start() + 1, str.end() - 1, context),
     new_text,
   });
+  Some(specifier)
+}
+
+/// Adds a line comment with the original url at the end of an import or
+/// export statement of a remote module.
+fn annotate_statement(
+  range: SourceRange,
+  specifier: Option<ModuleSpecifier>,
+  context: &mut Context,
+) {
+  if let Some(url) = get_annotated_url(specifier, context) {
+    context.text_changes.push(TextChange {
+      range: create_range(range.end, range.end, context),
+      new_text: format!(" // was: {}", url),
+    });
+  }
+}
+
+/// Adds a block comment with the original url after the specifier of an
+/// import that's within an expression or type.
+fn annotate_expr(
+  range: SourceRange,
+  specifier: Option<ModuleSpecifier>,
+  context: &mut Context,
+) {
+  if let Some(url) = get_annotated_url(specifier, context) {
+    context.text_changes.push(TextChange {
+      range: create_range(range.end, range.end, context),
+      // a url can't end the comment early
+      new_text: format!(" /* was: {} */", url.replace("*/", "*%2F")),
+    });
+  }
+}
+
+fn get_annotated_url(
+  specifier: Option<ModuleSpecifier>,
+  context: &Context,
+) -> Option<String> {
+  match specifier {
+    Some(specifier)
+      if context.annotate_rewrites
+        && matches!(specifier.scheme(), "http" | "https") =>
+    {
+      Some(specifier.to_string())
+    }
+    _ => None,
+  }
+}
+
+/// Gets if the specifier was rewritten to a module in the output, which
+/// is always a JavaScript module (ex. JSON modules are output as
+/// `data.js`), so its attributes no longer apply.
+fn is_output_module(
+  specifier: Option<&ModuleSpecifier>,
+  context: &Context,
+) -> bool {
+  specifier.map_or(false, |specifier| {
+    !context.package_specifier_mappings.contains_key(specifier)
+  })
+}
+
+/// Gets if the specifier was rewritten to a declaration file in the output
+/// from a module that isn't one, such as an import of a remote package
+/// that only has type declarations.
+fn is_output_declaration_file(
+  specifier: Option<&ModuleSpecifier>,
+  context: &Context,
+) -> bool {
+  !context.is_declaration_file
+    && is_output_module(specifier, context)
+    && specifier
+      .and_then(|specifier| context.mappings.maybe_file_path(specifier))
+      .map_or(false, |file_path| {
+        file_path
+          .to_string_lossy()
+          .to_lowercase()
+          .ends_with(".d.ts")
+      })
+}
+
+/// Makes an import of a declaration file type-only, so that it's not
+/// imported at runtime where there's no code to import.
+fn visit_declaration_file_import(
+  import_decl: &ImportDecl,
+  context: &mut Context,
+) {
+  let has_default = import_decl
+    .specifiers
+    .iter()
+    .any(|s| matches!(s, ImportSpecifier::Default(_)));
+  let has_inline_type = import_decl.specifiers.iter().any(|s| match s {
+    ImportSpecifier::Named(named) => named.inner.is_type_only,
+    ImportSpecifier::Default(_) | ImportSpecifier::Namespace(_) => false,
+  });
+  // a type-only import can't have both a default and other imports and
+  // can't have type-only specifiers, so those are left for tsc to report
+  if (has_default && import_decl.specifiers.len() > 1) || has_inline_type {
+    return;
+  }
+  let keyword_end = import_decl.start() + "import".len();
+  let new_text = if import_decl.specifiers.is_empty() {
+    // a side effect import, which can still augment the global types
+    " type {} from"
+  } else {
+    " type"
+  };
+  context.text_changes.push(TextChange {
+    range: create_range(keyword_end, keyword_end, context),
+    new_text: new_text.to_string(),
+  });
 }
 
-fn visit_asserts(asserts: &ObjectLit, context: &mut Context) {
-  let assert_token = asserts.previous_token_fast(context.program).unwrap();
-  assert_eq!(assert_token.text_fast(context.program), "assert");
-  let previous_token =
-    assert_token.previous_token_fast(context.program).unwrap();
+/// Makes a re-export from a declaration file type-only. Namespace
+/// re-exports (ex. `export * as ns from "./types.d.ts"`) are left as is.
+fn visit_declaration_file_re_export(
+  named_export: &NamedExport,
+  context: &mut Context,
+) {
+  let can_be_type_only = named_export.specifiers.iter().all(|s| match s {
+    ExportSpecifier::Named(named) => !named.inner.is_type_only,
+    ExportSpecifier::Namespace(_) | ExportSpecifier::Default(_) => false,
+  });
+  if !can_be_type_only {
+    return;
+  }
+  let keyword_end = named_export.start() + "export".len();
   context.text_changes.push(TextChange {
-    range: create_range(previous_token.end(), asserts.end(), context),
-    new_text: String::new(),
+    range: create_range(keyword_end, keyword_end, context),
+    new_text: " type".to_string(),
   });
 }
 
+/// Handles the `with` or `assert` attributes of a static import or
+/// re-export based on their type.
+fn visit_asserts(
+  asserts: &ObjectLit,
+  is_output_module: bool,
+  context: &mut Context,
+) {
+  let keyword_token = asserts.previous_token_fast(context.program).unwrap();
+  let action = if is_output_module {
+    ImportAttributeAction::Strip
+  } else {
+    context
+      .import_attributes
+      .get_action(get_attribute_type(asserts).as_deref())
+  };
+  match action {
+    ImportAttributeAction::Strip => {
+      let previous_token =
+        keyword_token.previous_token_fast(context.program).unwrap();
+      context.text_changes.push(TextChange {
+        range: create_range(previous_token.end(), asserts.end(), context),
+        new_text: String::new(),
+      });
+    }
+    ImportAttributeAction::With => {
+      if keyword_token.text_fast(context.program) == "assert" {
+        context.text_changes.push(TextChange {
+          range: create_range(
+            keyword_token.start(),
+            keyword_token.end(),
+            context,
+          ),
+          new_text: "with".to_string(),
+        });
+      }
+    }
+    ImportAttributeAction::Keep => {}
+  }
+}
+
+/// Handles the options argument of a dynamic import
+/// (ex. `import("./data.json", { with: { type: "json" } })`) based on the
+/// type of its attributes.
+fn visit_dynamic_import_options(
+  call_expr: &CallExpr,
+  is_output_module: bool,
+  context: &mut Context,
+) {
+  let options_arg = call_expr.args[1];
+  let attributes_prop = match options_arg.expr {
+    Expr::Object(options) => options.props.iter().find_map(|prop| match prop {
+      PropOrSpread::Prop(Prop::KeyValue(key_value))
+        if matches!(
+          get_prop_name(key_value.key).as_deref(),
+          Some("with" | "assert")
+        ) =>
+      {
+        Some(*key_value)
+      }
+      _ => None,
+    }),
+    _ => None,
+  };
+  let attribute_type = match attributes_prop.map(|prop| prop.value) {
+    Some(Expr::Object(attributes)) => get_attribute_type(attributes),
+    _ => None,
+  };
+  let action = if is_output_module {
+    ImportAttributeAction::Strip
+  } else {
+    context
+      .import_attributes
+      .get_action(attribute_type.as_deref())
+  };
+  match action {
+    ImportAttributeAction::Strip => {
+      let comma_token =
+        options_arg.previous_token_fast(context.program).unwrap();
+      context.text_changes.push(TextChange {
+        range: create_range(comma_token.start(), options_arg.end(), context),
+        new_text: String::new(),
+      });
+    }
+    ImportAttributeAction::With => {
+      if let Some(prop) = attributes_prop {
+        if get_prop_name(prop.key).as_deref() == Some("assert") {
+          context.text_changes.push(TextChange {
+            range: create_range(prop.key.start(), prop.key.end(), context),
+            new_text: "with".to_string(),
+          });
+        }
+      }
+    }
+    ImportAttributeAction::Keep => {}
+  }
+}
+
+/// Gets the value of the `type` attribute when it's a string literal.
+fn get_attribute_type(attributes: &ObjectLit) -> Option<String> {
+  attributes.props.iter().find_map(|prop| match prop {
+    PropOrSpread::Prop(Prop::KeyValue(key_value))
+      if get_prop_name(key_value.key).as_deref() == Some("type") =>
+    {
+      match key_value.value {
+        Expr::Lit(Lit::Str(str)) => Some(str.value().to_string()),
+        _ => None,
+      }
+    }
+    _ => None,
+  })
+}
+
+fn get_prop_name(name: PropName) -> Option<String> {
+  match name {
+    PropName::Ident(ident) => Some(ident.sym().to_string()),
+    PropName::Str(str) => Some(str.value().to_string()),
+    _ => None,
+  }
+}
+
 fn create_range(
   start: SourcePos,
   end: SourcePos,
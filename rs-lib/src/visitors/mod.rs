@@ -1,11 +1,31 @@
-// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
-
-mod deno_comment_directives;
-mod globals;
-mod imports_exports;
-mod polyfill;
-
-pub use deno_comment_directives::*;
-pub use globals::*;
-pub use imports_exports::*;
-pub use polyfill::*;
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+mod commonjs;
+mod declarations;
+mod define;
+mod deno_api;
+mod deno_comment_directives;
+mod deno_env;
+mod deno_feature_detection;
+mod dom_lib;
+mod globals;
+mod imports_exports;
+mod polyfill;
+mod rules;
+mod server;
+mod unshimmable_apis;
+
+pub use commonjs::*;
+pub use declarations::*;
+pub use define::*;
+pub use deno_api::*;
+pub use deno_comment_directives::*;
+pub use deno_env::*;
+pub use deno_feature_detection::*;
+pub use dom_lib::*;
+pub use globals::*;
+pub use imports_exports::*;
+pub use polyfill::*;
+pub use rules::*;
+pub use server::*;
+pub use unshimmable_apis::*;
@@ -0,0 +1,100 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use anyhow::Result;
+use deno_ast::view::*;
+use deno_ast::TextChange;
+
+use super::get_deno_comment_directive_text_changes;
+use super::GetDenoCommentDirectiveTextChangesParams;
+use super::GetGlobalTextChangesParams;
+use super::GetImportExportsTextChangesParams;
+use super::GlobalsRule;
+use super::ImportExportsRule;
+use crate::CommentDirectiveOptions;
+
+/// A rule that inspects each node of a program during the single
+/// traversal done by `visit_program`.
+pub trait NodeRule<'a> {
+  fn visit_node(&mut self, node: Node<'a>) -> Result<()>;
+}
+
+/// Visits every node in the program once in pre-order, running each
+/// of the provided rules on it.
+pub fn visit_program<'a>(
+  program: &'a Program<'a>,
+  rules: &mut [&mut dyn NodeRule<'a>],
+) -> Result<()> {
+  visit_node(program.as_node(), rules)
+}
+
+fn visit_node<'a>(
+  node: Node<'a>,
+  rules: &mut [&mut dyn NodeRule<'a>],
+) -> Result<()> {
+  for rule in rules.iter_mut() {
+    rule.visit_node(node)?;
+  }
+  for child in node.children() {
+    visit_node(child, rules)?;
+  }
+  Ok(())
+}
+
+pub struct GetTextChangesParams<'a> {
+  pub globals: GetGlobalTextChangesParams<'a>,
+  pub import_exports: GetImportExportsTextChangesParams<'a>,
+  pub comment_directives: &'a CommentDirectiveOptions,
+}
+
+pub struct GetTextChangesResult {
+  pub text_changes: Vec<TextChange>,
+  /// Names of the globals that were replaced with their shims.
+  pub used_shim_globals: Vec<String>,
+  /// The relative specifiers the module specifiers were rewritten to.
+  pub relative_specifiers: Vec<String>,
+}
+
+/// Gets the text changes for the globals, deno comment directives, and
+/// module specifiers in a single traversal of the program.
+///
+/// Additional rules may be provided to run during the same traversal.
+pub fn get_text_changes<'a>(
+  params: &GetTextChangesParams<'a>,
+  additional_rules: &mut [&mut dyn NodeRule<'a>],
+) -> Result<GetTextChangesResult> {
+  let program = params.import_exports.program;
+  let mut globals_rule = GlobalsRule::new(&params.globals);
+  let mut import_exports_rule = ImportExportsRule::new(&params.import_exports);
+
+  {
+    let mut rules: Vec<&mut dyn NodeRule<'a>> =
+      Vec::with_capacity(additional_rules.len() + 2);
+    rules.push(&mut globals_rule);
+    rules.push(&mut import_exports_rule);
+    rules.extend(additional_rules.iter_mut().map(|rule| &mut **rule));
+    visit_program(program, &mut rules)?;
+  }
+
+  let globals_result = globals_rule.into_result();
+  let mut text_changes = globals_result.text_changes;
+  let import_exports = &params.import_exports;
+  text_changes.extend(get_deno_comment_directive_text_changes(
+    &GetDenoCommentDirectiveTextChangesParams {
+      specifier: import_exports.specifier,
+      program,
+      is_declaration_file: import_exports.is_declaration_file,
+      options: params.comment_directives,
+      dependency_resolver: import_exports.dependency_resolver,
+      mappings: import_exports.mappings,
+      package_specifier_mappings: import_exports.package_specifier_mappings,
+    },
+  ));
+  let import_exports_result = import_exports_rule.into_result();
+  text_changes.extend(import_exports_result.text_changes);
+
+  Ok(GetTextChangesResult {
+    text_changes,
+    used_shim_globals: globals_result.used_shim_globals,
+    relative_specifiers: import_exports_result.relative_specifiers,
+  })
+}
@@ -0,0 +1,135 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use deno_ast::swc::common::SyntaxContext;
+use deno_ast::view::*;
+use deno_ast::ModuleSpecifier;
+use deno_ast::SourceRanged;
+use deno_ast::SourceTextInfoProvider;
+
+use super::NodeRule;
+use crate::analyze::is_global_reference;
+use crate::ServerShim;
+
+/// Server APIs of the `Deno` namespace along with what they return, which
+/// may be missing features when running in Node.js.
+pub const DENO_SERVER_APIS: [(&str, &str); 3] = [
+  (
+    "serve",
+    "Deno.HttpServer (ex. `finished`, `ref()`, `unref()`, and `shutdown()`)",
+  ),
+  (
+    "listen",
+    "Deno.Listener (ex. `rid`, `ref()`, and `unref()`)",
+  ),
+  ("serveHttp", "Deno.HttpConn (ex. `rid` and `nextRequest()`)"),
+];
+
+/// Gets the member expression and name of the server API when the
+/// identifier is the `Deno` of `Deno.serve`, `Deno.listen`, or
+/// `Deno.serveHttp`.
+pub fn get_deno_server_api<'a>(
+  ident: &'a Ident<'a>,
+) -> Option<(&'a MemberExpr<'a>, &'static str)> {
+  if ident.sym().as_ref() != "Deno" {
+    return None;
+  }
+  let member_expr = ident.parent().to::<MemberExpr>()?;
+  if member_expr.obj.range() != ident.range() {
+    return None;
+  }
+  let prop_name = match member_expr.prop {
+    MemberProp::Ident(prop) => prop.sym().to_string(),
+    _ => return None,
+  };
+  DENO_SERVER_APIS
+    .iter()
+    .find(|(name, _)| *name == prop_name)
+    .map(|(name, _)| (member_expr, *name))
+}
+
+pub struct GetServerWarningsParams<'a> {
+  pub specifier: &'a ModuleSpecifier,
+  pub program: &'a Program<'a>,
+  pub is_declaration_file: bool,
+  pub unresolved_context: SyntaxContext,
+  pub ignore_line_indexes: &'a HashSet<usize>,
+  pub top_level_decls: &'a HashSet<String>,
+  pub server_shim: Option<&'a ServerShim>,
+}
+
+/// Rule that warns about the usages of the `Deno` server APIs, which either
+/// aren't supported in Node.js or are replaced by the server shim.
+pub struct ServerRule<'a> {
+  params: &'a GetServerWarningsParams<'a>,
+  warnings: Vec<String>,
+}
+
+impl<'a> ServerRule<'a> {
+  pub fn new(params: &'a GetServerWarningsParams<'a>) -> Self {
+    Self {
+      params,
+      warnings: Vec::new(),
+    }
+  }
+
+  pub fn into_warnings(self) -> Vec<String> {
+    self.warnings
+  }
+}
+
+impl<'a> NodeRule<'a> for ServerRule<'a> {
+  fn visit_node(&mut self, node: Node<'a>) -> Result<()> {
+    let params = self.params;
+    if params.is_declaration_file {
+      return Ok(());
+    }
+    let ident = match node {
+      Node::Ident(ident) => ident,
+      _ => return Ok(()),
+    };
+    if !is_global_reference(
+      ident,
+      params.unresolved_context,
+      params.top_level_decls,
+    ) || params
+      .ignore_line_indexes
+      .contains(&ident.start_line_fast(params.program))
+    {
+      return Ok(());
+    }
+    if let Some((member_expr, api)) = get_deno_server_api(ident) {
+      let message = match params.server_shim {
+        Some(server_shim) => {
+          let return_type = DENO_SERVER_APIS
+            .iter()
+            .find(|(name, _)| *name == api)
+            .map(|(_, return_type)| *return_type)
+            .unwrap();
+          format!(
+            "Deno.{} was replaced with the \"{}\" export of {}, which may not support every feature of {}.",
+            api,
+            server_shim.export_name(api),
+            server_shim.package.name,
+            return_type,
+          )
+        }
+        None => format!(
+          "Deno.{} is not supported in Node.js by the Deno shim. Provide a server shim to replace it.",
+          api
+        ),
+      };
+      let position = params
+        .program
+        .text_info()
+        .line_and_column_display(member_expr.start());
+      self.warnings.push(format!(
+        "{}\n    at {}:{}:{}",
+        message, params.specifier, position.line_number, position.column_number,
+      ));
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,115 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use deno_ast::swc::common::SyntaxContext;
+use deno_ast::view::*;
+use deno_ast::ModuleSpecifier;
+use deno_ast::SourceRanged;
+use deno_ast::SourceTextInfoProvider;
+
+use super::NodeRule;
+use crate::analyze::is_global_reference;
+use crate::UnshimmableApiCategory;
+use crate::UnshimmableApiUsage;
+
+/// Members of the `Deno` namespace that can't be shimmed in Node.js.
+const UNSHIMMABLE_DENO_APIS: [(&str, UnshimmableApiCategory); 7] = [
+  ("dlopen", UnshimmableApiCategory::Ffi),
+  ("UnsafeCallback", UnshimmableApiCategory::Ffi),
+  ("UnsafeFnPointer", UnshimmableApiCategory::Ffi),
+  ("UnsafePointer", UnshimmableApiCategory::Ffi),
+  ("UnsafePointerView", UnshimmableApiCategory::Ffi),
+  ("Command", UnshimmableApiCategory::Subprocess),
+  ("run", UnshimmableApiCategory::Subprocess),
+];
+
+/// Gets the category of an API (ex. `Deno.dlopen`) that can't be shimmed.
+pub fn get_unshimmable_api_category(
+  api: &str,
+) -> Option<UnshimmableApiCategory> {
+  let name = api.strip_prefix("Deno.")?;
+  UNSHIMMABLE_DENO_APIS
+    .iter()
+    .find(|(api_name, _)| *api_name == name)
+    .map(|(_, category)| *category)
+}
+
+pub struct GetUnshimmableApiUsagesParams<'a> {
+  pub specifier: &'a ModuleSpecifier,
+  pub program: &'a Program<'a>,
+  pub is_declaration_file: bool,
+  pub unresolved_context: SyntaxContext,
+  pub ignore_line_indexes: &'a HashSet<usize>,
+  pub top_level_decls: &'a HashSet<String>,
+}
+
+/// Rule that finds the usages of the FFI and subprocess APIs of the `Deno`
+/// namespace, which would throw at runtime when using the Deno shim.
+pub struct UnshimmableApisRule<'a> {
+  params: &'a GetUnshimmableApiUsagesParams<'a>,
+  usages: Vec<UnshimmableApiUsage>,
+}
+
+impl<'a> UnshimmableApisRule<'a> {
+  pub fn new(params: &'a GetUnshimmableApiUsagesParams<'a>) -> Self {
+    Self {
+      params,
+      usages: Vec::new(),
+    }
+  }
+
+  pub fn into_usages(self) -> Vec<UnshimmableApiUsage> {
+    self.usages
+  }
+}
+
+impl<'a> NodeRule<'a> for UnshimmableApisRule<'a> {
+  fn visit_node(&mut self, node: Node<'a>) -> Result<()> {
+    let params = self.params;
+    if params.is_declaration_file {
+      return Ok(());
+    }
+    let member_expr = match node {
+      Node::MemberExpr(member_expr) => member_expr,
+      _ => return Ok(()),
+    };
+    let is_deno_global = match member_expr.obj {
+      Expr::Ident(ident) => {
+        ident.sym().as_ref() == "Deno"
+          && is_global_reference(
+            ident,
+            params.unresolved_context,
+            params.top_level_decls,
+          )
+      }
+      _ => false,
+    };
+    if !is_deno_global
+      || params
+        .ignore_line_indexes
+        .contains(&member_expr.start_line_fast(params.program))
+    {
+      return Ok(());
+    }
+    let api = match member_expr.prop {
+      MemberProp::Ident(prop) => format!("Deno.{}", prop.sym()),
+      _ => return Ok(()),
+    };
+    if let Some(category) = get_unshimmable_api_category(&api) {
+      let position = params
+        .program
+        .text_info()
+        .line_and_column_display(member_expr.start());
+      self.usages.push(UnshimmableApiUsage {
+        specifier: params.specifier.clone(),
+        line_number: position.line_number,
+        column_number: position.column_number,
+        api,
+        category,
+      });
+    }
+    Ok(())
+  }
+}
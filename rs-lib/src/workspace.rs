@@ -0,0 +1,491 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use anyhow::Result;
+use deno_ast::ModuleSpecifier;
+use futures::Future;
+
+use crate::package::get_export_name;
+use crate::package::validate_package_name;
+use crate::package::validate_version_range;
+use crate::text_changes::TextChange;
+use crate::transform;
+use crate::utils::file_path_to_url;
+use crate::AssetFile;
+use crate::AssetHandler;
+use crate::AssetOutput;
+use crate::Dependency;
+use crate::FileTextHandler;
+use crate::LoadResponse;
+use crate::Loader;
+use crate::MappedSpecifier;
+use crate::OutputEnvironment;
+use crate::OutputFile;
+use crate::PackageMappedSpecifier;
+use crate::TransformError;
+use crate::TransformOptions;
+use crate::TransformOutput;
+use crate::TransformVisitor;
+use crate::TransformVisitorContext;
+use crate::VersionHandler;
+
+/// A package in a workspace.
+pub struct WorkspaceMember {
+  /// Name of the package.
+  pub name: String,
+  /// Version range that the other members depend on this one with.
+  pub version: String,
+  /// Directory of the member, which contains its entry points.
+  pub root: ModuleSpecifier,
+  pub entry_points: Vec<ModuleSpecifier>,
+  pub test_entry_points: Vec<ModuleSpecifier>,
+}
+
+pub struct TransformWorkspaceOptions {
+  pub members: Vec<WorkspaceMember>,
+  /// Options used for each member. The entry points should be empty
+  /// because they're specified by each member.
+  pub options: TransformOptions,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct TransformWorkspaceOutput {
+  /// Output of each member in the order they were specified in.
+  pub members: Vec<WorkspaceMemberOutput>,
+  /// Dependencies between the members, sorted by the dependent and
+  /// then the dependency.
+  pub dependencies: Vec<WorkspaceDependency>,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq)]
+pub struct WorkspaceMemberOutput {
+  pub name: String,
+  pub output: TransformOutput,
+}
+
+#[cfg_attr(feature = "serialization", derive(serde::Serialize))]
+#[cfg_attr(feature = "serialization", serde(rename_all = "camelCase"))]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WorkspaceDependency {
+  /// Name of the member that depends on the other.
+  pub dependent: String,
+  /// Name of the member being depended on.
+  pub dependency: String,
+  /// If only the test code depends on it.
+  pub test_only: bool,
+}
+
+/// Transforms each member of a workspace.
+///
+/// Imports of another member's entry points are mapped to that member's
+/// package (ex. `@scope/other/sub` for `sub/mod.ts`) instead of the
+/// module being included in the output. Importing another module of a
+/// member is an error because it would be copied into the output of the
+/// importing member, unless it's mapped by the specifier mappings.
+pub async fn transform_workspace(
+  options: TransformWorkspaceOptions,
+) -> Result<TransformWorkspaceOutput, TransformError> {
+  let TransformWorkspaceOptions {
+    members,
+    options: mut base_options,
+  } = options;
+  validate_members(&members, &base_options)?;
+
+  // share what can't be cloned between the members
+  let loader = base_options
+    .loader
+    .take()
+    .map(|loader| SharedLoader(Arc::from(loader)));
+  let custom_visitors = std::mem::take(&mut base_options.custom_visitors)
+    .into_iter()
+    .map(Arc::from)
+    .collect::<Vec<Arc<dyn TransformVisitor>>>();
+  let file_text_handler = base_options
+    .file_text_handler
+    .take()
+    .map(Arc::<dyn FileTextHandler>::from);
+  let version_handler = base_options
+    .version_handler
+    .take()
+    .map(Arc::<dyn VersionHandler>::from);
+  let asset_handlers = std::mem::take(&mut base_options.asset_handlers)
+    .into_iter()
+    .map(|(attribute_type, handler)| (attribute_type, Arc::from(handler)))
+    .collect::<HashMap<_, Arc<dyn AssetHandler>>>();
+
+  let member_mappings =
+    members.iter().map(get_member_mappings).collect::<Vec<_>>();
+  let mut outputs = Vec::with_capacity(members.len());
+  for (i, member) in members.iter().enumerate() {
+    let mut specifier_mappings = base_options.specifier_mappings.clone();
+    for (_, mappings) in member_mappings
+      .iter()
+      .enumerate()
+      .filter(|(other_index, _)| *other_index != i)
+    {
+      for (specifier, mapping) in mappings {
+        // the provided mappings take precedence
+        specifier_mappings
+          .entry(specifier.clone())
+          .or_insert_with(|| mapping.clone());
+      }
+    }
+    let output = transform(TransformOptions {
+      entry_points: member.entry_points.clone(),
+      test_entry_points: member.test_entry_points.clone(),
+      shims: base_options.shims.clone(),
+      test_shims: base_options.test_shims.clone(),
+      loader: loader
+        .as_ref()
+        .map(|loader| Box::new(loader.clone()) as Box<dyn Loader>),
+      specifier_mappings,
+      declaration_mappings: base_options.declaration_mappings.clone(),
+      target: base_options.target,
+      import_map: base_options.import_map.clone(),
+      jsx: base_options.jsx.clone(),
+      error_on_cycles: base_options.error_on_cycles,
+      error_on_duplicate_packages: base_options.error_on_duplicate_packages,
+      continue_on_parse_error: base_options.continue_on_parse_error,
+      normalize_output_text: base_options.normalize_output_text,
+      github_dependencies: base_options.github_dependencies,
+      remote_policy: base_options.remote_policy.clone(),
+      version_policy: base_options.version_policy,
+      asset_imports: base_options.asset_imports,
+      asset_handlers: asset_handlers
+        .iter()
+        .map(|(attribute_type, handler)| {
+          (
+            attribute_type.clone(),
+            Box::new(SharedAssetHandler(handler.clone()))
+              as Box<dyn AssetHandler>,
+          )
+        })
+        .collect(),
+      directory_index_files: base_options.directory_index_files.clone(),
+      local_roots: base_options.local_roots.clone(),
+      node_global_shims: base_options.node_global_shims.clone(),
+      dom_types_package: base_options.dom_types_package.clone(),
+      inject_dirname: base_options.inject_dirname,
+      commonjs_modules: base_options.commonjs_modules,
+      server_shim: base_options.server_shim.clone(),
+      comment_directives: base_options.comment_directives.clone(),
+      import_attributes: base_options.import_attributes.clone(),
+      inline_remote_module_max_size: base_options.inline_remote_module_max_size,
+      bundle: base_options.bundle,
+      flatten_barrels: base_options.flatten_barrels,
+      minify: base_options.minify,
+      entry_point_exports: base_options.entry_point_exports,
+      declarations_only: base_options.declarations_only,
+      third_party_licenses: base_options.third_party_licenses,
+      provenance: base_options.provenance,
+      define: base_options.define.clone(),
+      deno_env_to_process_env: base_options.deno_env_to_process_env,
+      translate_deno_apis: base_options.translate_deno_apis,
+      shim_import_style: base_options.shim_import_style,
+      deno_feature_detection: base_options.deno_feature_detection,
+      qualified_global_access: base_options.qualified_global_access,
+      relative_specifier_extension: base_options.relative_specifier_extension,
+      test_relative_specifier_extension: base_options
+        .test_relative_specifier_extension,
+      test_main_imports: base_options.test_main_imports.clone(),
+      annotate_rewrites: base_options.annotate_rewrites,
+      hash_remote_file_names: base_options.hash_remote_file_names,
+      typescript_paths: base_options.typescript_paths,
+      emit_manifest: base_options.emit_manifest,
+      size_budget: base_options.size_budget.clone(),
+      cache_dir: base_options.cache_dir.clone(),
+      custom_visitors: custom_visitors
+        .iter()
+        .map(|visitor| {
+          Box::new(SharedVisitor(visitor.clone())) as Box<dyn TransformVisitor>
+        })
+        .collect(),
+      file_text_handler: file_text_handler.as_ref().map(|handler| {
+        Box::new(SharedFileTextHandler(handler.clone()))
+          as Box<dyn FileTextHandler>
+      }),
+      version_handler: version_handler.as_ref().map(|handler| {
+        Box::new(SharedVersionHandler(handler.clone()))
+          as Box<dyn VersionHandler>
+      }),
+    })
+    .await?;
+    let messages =
+      get_other_member_module_messages(&members, i, &output, &base_options);
+    if !messages.is_empty() {
+      return Err(TransformError::InvalidOptions {
+        message: messages.join("\n"),
+      });
+    }
+    outputs.push(WorkspaceMemberOutput {
+      name: member.name.clone(),
+      output,
+    });
+  }
+
+  let dependencies = get_workspace_dependencies(&outputs);
+  Ok(TransformWorkspaceOutput {
+    members: outputs,
+    dependencies,
+  })
+}
+
+fn validate_members(
+  members: &[WorkspaceMember],
+  options: &TransformOptions,
+) -> Result<(), TransformError> {
+  let mut messages = Vec::new();
+  if members.is_empty() {
+    messages
+      .push("At least one workspace member must be specified.".to_string());
+  }
+  if !options.entry_points.is_empty() || !options.test_entry_points.is_empty() {
+    messages.push(
+      "The entry points of a workspace must be specified by its members."
+        .to_string(),
+    );
+  }
+  let mut names = HashSet::new();
+  for member in members {
+    if let Err(err) = validate_package_name(&member.name) {
+      messages.push(format!(
+        "Invalid workspace member name \"{}\". {}",
+        member.name, err
+      ));
+    } else if !names.insert(member.name.as_str()) {
+      messages.push(format!(
+        "Workspace member {} was specified more than once.",
+        member.name
+      ));
+    }
+    if let Err(err) = validate_version_range(&member.version) {
+      messages.push(format!(
+        "Invalid version \"{}\" of workspace member {}. {}",
+        member.version, member.name, err
+      ));
+    }
+    if !member.root.path().ends_with('/') {
+      messages.push(format!(
+        "Root {} of workspace member {} must be a directory ending with a slash.",
+        member.root, member.name
+      ));
+      continue;
+    }
+    if member.entry_points.is_empty() {
+      messages.push(format!(
+        "Workspace member {} must have at least one entry point.",
+        member.name
+      ));
+    }
+    for entry_point in member
+      .entry_points
+      .iter()
+      .chain(member.test_entry_points.iter())
+    {
+      if get_relative_path(&member.root, entry_point).is_none() {
+        messages.push(format!(
+          "Entry point {} must be within the root {} of workspace member {}.",
+          entry_point, member.root, member.name
+        ));
+      }
+    }
+  }
+
+  if messages.is_empty() {
+    Ok(())
+  } else {
+    Err(TransformError::InvalidOptions {
+      message: messages.join("\n"),
+    })
+  }
+}
+
+/// Gets the messages for the modules of other members that the member's
+/// output depends on, which aren't mapped to the other member's package
+/// because they aren't one of its entry points.
+fn get_other_member_module_messages(
+  members: &[WorkspaceMember],
+  member_index: usize,
+  output: &TransformOutput,
+  options: &TransformOptions,
+) -> Vec<String> {
+  let member = &members[member_index];
+  let mut messages = Vec::new();
+  for file_path in output.file_dependencies.iter() {
+    let specifier = file_path_to_url(file_path);
+    if options.specifier_mappings.contains_key(&specifier)
+      || options.import_map.as_ref() == Some(&specifier)
+    {
+      continue;
+    }
+    // the member with the most specific root owns the module
+    let owner_index = members
+      .iter()
+      .enumerate()
+      .filter(|(_, other)| get_relative_path(&other.root, &specifier).is_some())
+      .max_by_key(|(_, other)| other.root.as_str().len())
+      .map(|(index, _)| index);
+    let owner = match owner_index {
+      Some(index) if index != member_index => &members[index],
+      _ => continue,
+    };
+    // the entry points are mapped to the owner's package
+    if !owner.entry_points.contains(&specifier) {
+      messages.push(format!(
+        "Workspace member {} imports {}, which is a module of workspace member {} that isn't one of its entry points. Add it to the entry points of {} or map it to a package.",
+        member.name, specifier, owner.name, owner.name
+      ));
+    }
+  }
+  messages
+}
+
+/// Gets the mappings of the member's entry points to its package, which
+/// match the `exports` of its output.
+fn get_member_mappings(
+  member: &WorkspaceMember,
+) -> Vec<(ModuleSpecifier, MappedSpecifier)> {
+  member
+    .entry_points
+    .iter()
+    .enumerate()
+    .map(|(i, entry_point)| {
+      let sub_path = if i == 0 {
+        None
+      } else {
+        let relative_path = get_relative_path(&member.root, entry_point)
+          .expect("entry points should be validated");
+        let export_name = get_export_name(Path::new(relative_path));
+        Some(export_name["./".len()..].to_string())
+      };
+      (
+        entry_point.clone(),
+        MappedSpecifier::Package(PackageMappedSpecifier {
+          name: member.name.clone(),
+          version: Some(member.version.clone()),
+          sub_path,
+          peer_dependency: false,
+          types_package: None,
+        }),
+      )
+    })
+    .collect()
+}
+
+fn get_relative_path<'a>(
+  root: &ModuleSpecifier,
+  specifier: &'a ModuleSpecifier,
+) -> Option<&'a str> {
+  specifier
+    .as_str()
+    .strip_prefix(root.as_str())
+    .filter(|path| !path.is_empty())
+}
+
+fn get_workspace_dependencies(
+  outputs: &[WorkspaceMemberOutput],
+) -> Vec<WorkspaceDependency> {
+  let names = outputs
+    .iter()
+    .map(|o| o.name.as_str())
+    .collect::<HashSet<_>>();
+  let mut dependencies = BTreeSet::new();
+  for member in outputs {
+    let main_dependencies = member
+      .output
+      .main
+      .dependencies
+      .iter()
+      .map(|d| d.name.as_str())
+      .filter(|name| names.contains(name))
+      .collect::<HashSet<_>>();
+    let test_dependencies = member
+      .output
+      .test
+      .dependencies
+      .iter()
+      .map(|d| d.name.as_str())
+      .filter(|name| names.contains(name));
+    for name in main_dependencies.iter().copied().chain(test_dependencies) {
+      if name == member.name {
+        continue;
+      }
+      dependencies.insert(WorkspaceDependency {
+        dependent: member.name.clone(),
+        dependency: name.to_string(),
+        test_only: !main_dependencies.contains(name),
+      });
+    }
+  }
+  dependencies.into_iter().collect()
+}
+
+#[derive(Clone)]
+struct SharedLoader(Arc<dyn Loader>);
+
+impl Loader for SharedLoader {
+  fn load(
+    &self,
+    specifier: ModuleSpecifier,
+  ) -> Pin<Box<dyn Future<Output = Result<Option<LoadResponse>>> + 'static>> {
+    self.0.load(specifier)
+  }
+}
+
+struct SharedVisitor(Arc<dyn TransformVisitor>);
+
+impl TransformVisitor for SharedVisitor {
+  fn visit(
+    &self,
+    context: &TransformVisitorContext,
+  ) -> Result<Vec<TextChange>> {
+    self.0.visit(context)
+  }
+
+  fn cache_key(&self) -> Option<String> {
+    self.0.cache_key()
+  }
+}
+
+struct SharedFileTextHandler(Arc<dyn FileTextHandler>);
+
+impl FileTextHandler for SharedFileTextHandler {
+  fn handle_file_text(
+    &self,
+    environment: OutputEnvironment,
+    specifier: &ModuleSpecifier,
+    file: &mut OutputFile,
+  ) -> Result<()> {
+    self.0.handle_file_text(environment, specifier, file)
+  }
+}
+
+struct SharedVersionHandler(Arc<dyn VersionHandler>);
+
+impl VersionHandler for SharedVersionHandler {
+  fn handle_version(
+    &self,
+    environment: OutputEnvironment,
+    dependency: &Dependency,
+  ) -> Result<String> {
+    self.0.handle_version(environment, dependency)
+  }
+}
+
+struct SharedAssetHandler(Arc<dyn AssetHandler>);
+
+impl AssetHandler for SharedAssetHandler {
+  fn handle_asset(&self, asset: &AssetFile) -> Result<AssetOutput> {
+    self.0.handle_asset(asset)
+  }
+}
@@ -0,0 +1,144 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+use std::collections::HashSet;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::virtual_path::VirtualPath;
+use crate::TransformOutput;
+
+/// File in the output directory that lists the files that were written
+/// so that they can be removed by a later write when they're stale.
+pub const MANIFEST_FILE_NAME: &str = ".dnt_manifest";
+
+#[derive(Clone, Debug, Default)]
+pub struct WriteOptions {
+  /// Remove the files written by a previous write to the directory that
+  /// aren't in the output anymore.
+  pub remove_stale_files: bool,
+}
+
+/// What changed in the output directory. The paths are relative to it.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct WriteResult {
+  pub created: Vec<PathBuf>,
+  pub updated: Vec<PathBuf>,
+  /// Files that already had the same text, so weren't written.
+  pub unchanged: Vec<PathBuf>,
+  /// Stale files from a previous write that were removed.
+  pub removed: Vec<PathBuf>,
+}
+
+impl TransformOutput {
+  /// Writes the files of the main and test environments to a directory.
+  ///
+  /// Each file is written to a temporary file first and then renamed so
+  /// that a file is never partially written.
+  pub fn write_to(
+    &self,
+    dir: impl AsRef<Path>,
+    options: &WriteOptions,
+  ) -> Result<WriteResult> {
+    let dir = dir.as_ref();
+    let mut result = WriteResult::default();
+    let files = self.main.files.iter().chain(self.test.files.iter());
+    let mut written_paths = HashSet::new();
+    for file in files {
+      let file_path = dir.join(&file.file_path);
+      let existing_text = std::fs::read_to_string(&file_path).ok();
+      if existing_text.as_deref() == Some(file.file_text.as_str()) {
+        result.unchanged.push(file.file_path.clone());
+      } else {
+        write_file_atomic(&file_path, &file.file_text)
+          .with_context(|| format!("Error writing {}", file_path.display()))?;
+        if existing_text.is_some() {
+          result.updated.push(file.file_path.clone());
+        } else {
+          result.created.push(file.file_path.clone());
+        }
+      }
+      written_paths.insert(VirtualPath::new(&file.file_path).to_string());
+    }
+
+    let manifest_path = dir.join(MANIFEST_FILE_NAME);
+    if options.remove_stale_files {
+      let manifest_text =
+        std::fs::read_to_string(&manifest_path).unwrap_or_default();
+      for line in manifest_text.lines() {
+        if written_paths.contains(line) || !is_safe_relative_path(line) {
+          continue;
+        }
+        let file_path = dir.join(line);
+        match std::fs::remove_file(&file_path) {
+          Ok(()) => {
+            remove_empty_parent_dirs(dir, &file_path);
+            result.removed.push(PathBuf::from(line));
+          }
+          Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+          Err(err) => {
+            return Err(err).with_context(|| {
+              format!("Error removing stale file {}", file_path.display())
+            })
+          }
+        }
+      }
+    }
+
+    let mut manifest_lines = written_paths.into_iter().collect::<Vec<_>>();
+    manifest_lines.sort();
+    let mut manifest_text = manifest_lines.join("\n");
+    manifest_text.push('\n');
+    write_file_atomic(&manifest_path, &manifest_text)
+      .with_context(|| format!("Error writing {}", manifest_path.display()))?;
+
+    Ok(result)
+  }
+}
+
+fn write_file_atomic(file_path: &Path, text: &str) -> std::io::Result<()> {
+  if let Some(parent) = file_path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  let mut temp_path = file_path.as_os_str().to_owned();
+  temp_path.push(".tmp");
+  std::fs::write(&temp_path, text)?;
+  std::fs::rename(&temp_path, file_path)
+}
+
+/// Gets if a path from the manifest stays within the output directory,
+/// so that a modified manifest can't remove other files.
+fn is_safe_relative_path(path: &str) -> bool {
+  let path = Path::new(path);
+  !path.as_os_str().is_empty()
+    && path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+fn remove_empty_parent_dirs(dir: &Path, file_path: &Path) {
+  let mut current = file_path.parent();
+  while let Some(parent) = current {
+    // stops at the first directory that isn't empty
+    if parent == dir || std::fs::remove_dir(parent).is_err() {
+      break;
+    }
+    current = parent.parent();
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn safe_relative_paths() {
+    assert!(is_safe_relative_path("mod.ts"));
+    assert!(is_safe_relative_path("deps/a/mod.ts"));
+    assert!(!is_safe_relative_path(""));
+    assert!(!is_safe_relative_path("../mod.ts"));
+    assert!(!is_safe_relative_path("a/../../mod.ts"));
+    assert!(!is_safe_relative_path("/mod.ts"));
+  }
+}
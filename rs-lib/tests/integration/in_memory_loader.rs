@@ -9,6 +9,7 @@ use anyhow::anyhow;
 use anyhow::Result;
 use futures::Future;
 
+use deno_node_transform::file_path_to_url;
 use deno_node_transform::url_to_file_path;
 use deno_node_transform::LoadResponse;
 use deno_node_transform::Loader;
@@ -21,6 +22,7 @@ type RemoteFileResult = Result<(RemoteFileText, RemoteFileHeaders), String>;
 #[derive(Clone)]
 pub struct InMemoryLoader {
   local_files: HashMap<PathBuf, String>,
+  local_symlinks: HashMap<PathBuf, PathBuf>,
   remote_files: HashMap<ModuleSpecifier, RemoteFileResult>,
 }
 
@@ -28,6 +30,7 @@ impl InMemoryLoader {
   pub fn new() -> Self {
     Self {
       local_files: HashMap::new(),
+      local_symlinks: HashMap::new(),
       remote_files: HashMap::new(),
     }
   }
@@ -43,6 +46,28 @@ impl InMemoryLoader {
     self
   }
 
+  pub fn add_local_symlink(
+    &mut self,
+    path: impl AsRef<Path>,
+    target: impl AsRef<Path>,
+  ) -> &mut Self {
+    self
+      .local_symlinks
+      .insert(path.as_ref().to_path_buf(), target.as_ref().to_path_buf());
+    self
+  }
+
+  fn resolve_local_symlinks(&self, path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+      result.push(component);
+      if let Some(target) = self.local_symlinks.get(&result) {
+        result = target.clone();
+      }
+    }
+    result
+  }
+
   pub fn add_remote_file(
     &mut self,
     specifier: impl AsRef<str>,
@@ -92,7 +117,13 @@ impl Loader for InMemoryLoader {
   ) -> Pin<Box<dyn Future<Output = Result<Option<LoadResponse>>> + 'static>> {
     if specifier.scheme() == "file" {
       let file_path = url_to_file_path(&specifier).unwrap();
-      let result = self.local_files.get(&file_path).map(ToOwned::to_owned);
+      let resolved_path = self.resolve_local_symlinks(&file_path);
+      let specifier = if resolved_path == file_path {
+        specifier
+      } else {
+        file_path_to_url(&resolved_path)
+      };
+      let result = self.local_files.get(&resolved_path).map(ToOwned::to_owned);
       return Box::pin(async move {
         Ok(result.map(|result| LoadResponse {
           content: result,
@@ -1,18 +1,60 @@
 // Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
 
 use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use anyhow::Result;
+use deno_node_transform::analyze;
+use deno_node_transform::classify_modules;
+use deno_node_transform::resolve_mapped_specifier;
+use deno_node_transform::text_changes::TextChange;
 use deno_node_transform::transform;
+use deno_node_transform::transform_with_sink;
+use deno_node_transform::transform_workspace;
+use deno_node_transform::AssetFile;
+use deno_node_transform::AssetHandler;
+use deno_node_transform::AssetOutput;
+use deno_node_transform::CommentDirectiveOptions;
+use deno_node_transform::CommonJsModules;
+use deno_node_transform::DenoFeatureDetection;
+use deno_node_transform::Dependency;
+use deno_node_transform::FileTextHandler;
 use deno_node_transform::GlobalName;
+use deno_node_transform::GraphInfo;
+use deno_node_transform::ImportAttributeOptions;
+use deno_node_transform::JsxOptions;
+use deno_node_transform::JsxRuntime;
+use deno_node_transform::LocalRoot;
+use deno_node_transform::MappedResult;
 use deno_node_transform::MappedSpecifier;
+use deno_node_transform::ModuleClassification;
 use deno_node_transform::ModuleSpecifier;
+use deno_node_transform::NodeGlobalShims;
+use deno_node_transform::OutputEnvironment;
+use deno_node_transform::OutputFile;
+use deno_node_transform::OutputSink;
 use deno_node_transform::PackageMappedSpecifier;
 use deno_node_transform::PackageShim;
+use deno_node_transform::QualifiedGlobalAccess;
+use deno_node_transform::RelativeSpecifierExtension;
+use deno_node_transform::RemotePolicy;
 use deno_node_transform::ScriptTarget;
+use deno_node_transform::ServerShim;
 use deno_node_transform::Shim;
+use deno_node_transform::ShimImportStyle;
+use deno_node_transform::SizeBudget;
+use deno_node_transform::TestMainImports;
 use deno_node_transform::TransformOptions;
 use deno_node_transform::TransformOutput;
+use deno_node_transform::TransformVisitor;
+use deno_node_transform::TransformVisitorContext;
+use deno_node_transform::TransformWorkspaceOptions;
+use deno_node_transform::TransformWorkspaceOutput;
+use deno_node_transform::VersionHandler;
+use deno_node_transform::VersionPolicy;
+use deno_node_transform::WorkspaceMember;
 
 use super::InMemoryLoader;
 
@@ -22,10 +64,56 @@ pub struct TestBuilder {
   additional_entry_points: Vec<String>,
   test_entry_points: Vec<String>,
   specifier_mappings: HashMap<ModuleSpecifier, MappedSpecifier>,
+  declaration_mappings: HashMap<ModuleSpecifier, ModuleSpecifier>,
   shims: Vec<Shim>,
   test_shims: Vec<Shim>,
   target: ScriptTarget,
   import_map: Option<ModuleSpecifier>,
+  jsx: Option<JsxOptions>,
+  error_on_cycles: bool,
+  error_on_duplicate_packages: bool,
+  continue_on_parse_error: bool,
+  normalize_output_text: bool,
+  github_dependencies: bool,
+  remote_policy: Option<RemotePolicy>,
+  version_policy: VersionPolicy,
+  asset_imports: bool,
+  asset_handlers: HashMap<String, Arc<dyn AssetHandler>>,
+  directory_index_files: Vec<String>,
+  local_roots: Vec<LocalRoot>,
+  node_global_shims: NodeGlobalShims,
+  dom_types_package: Option<Dependency>,
+  inject_dirname: bool,
+  commonjs_modules: CommonJsModules,
+  server_shim: Option<ServerShim>,
+  comment_directives: CommentDirectiveOptions,
+  import_attributes: ImportAttributeOptions,
+  inline_remote_module_max_size: Option<usize>,
+  bundle: bool,
+  flatten_barrels: bool,
+  minify: bool,
+  entry_point_exports: bool,
+  declarations_only: bool,
+  third_party_licenses: bool,
+  provenance: bool,
+  define: HashMap<String, String>,
+  deno_env_to_process_env: bool,
+  translate_deno_apis: bool,
+  shim_import_style: ShimImportStyle,
+  deno_feature_detection: DenoFeatureDetection,
+  qualified_global_access: QualifiedGlobalAccess,
+  relative_specifier_extension: RelativeSpecifierExtension,
+  test_relative_specifier_extension: Option<RelativeSpecifierExtension>,
+  test_main_imports: TestMainImports,
+  annotate_rewrites: bool,
+  hash_remote_file_names: bool,
+  typescript_paths: bool,
+  emit_manifest: bool,
+  size_budget: Option<SizeBudget>,
+  cache_dir: Option<PathBuf>,
+  custom_visitors: Vec<Arc<dyn TransformVisitor>>,
+  file_text_handler: Option<Arc<dyn FileTextHandler>>,
+  version_handler: Option<Arc<dyn VersionHandler>>,
 }
 
 impl TestBuilder {
@@ -37,10 +125,56 @@ impl TestBuilder {
       additional_entry_points: Vec::new(),
       test_entry_points: Vec::new(),
       specifier_mappings: Default::default(),
+      declaration_mappings: Default::default(),
       shims: Default::default(),
       test_shims: Default::default(),
       target: ScriptTarget::ES5,
       import_map: None,
+      jsx: None,
+      error_on_cycles: false,
+      error_on_duplicate_packages: false,
+      continue_on_parse_error: false,
+      normalize_output_text: false,
+      github_dependencies: false,
+      remote_policy: None,
+      version_policy: VersionPolicy::Preserve,
+      asset_imports: false,
+      asset_handlers: HashMap::new(),
+      directory_index_files: Vec::new(),
+      local_roots: Vec::new(),
+      node_global_shims: Default::default(),
+      dom_types_package: None,
+      inject_dirname: false,
+      commonjs_modules: CommonJsModules::Error,
+      server_shim: None,
+      comment_directives: Default::default(),
+      import_attributes: Default::default(),
+      inline_remote_module_max_size: None,
+      bundle: false,
+      flatten_barrels: false,
+      minify: false,
+      entry_point_exports: false,
+      declarations_only: false,
+      third_party_licenses: false,
+      provenance: false,
+      define: Default::default(),
+      deno_env_to_process_env: false,
+      translate_deno_apis: false,
+      shim_import_style: ShimImportStyle::Namespace,
+      deno_feature_detection: DenoFeatureDetection::Native,
+      qualified_global_access: QualifiedGlobalAccess::GlobalObject,
+      relative_specifier_extension: RelativeSpecifierExtension::Js,
+      test_relative_specifier_extension: None,
+      test_main_imports: TestMainImports::Relative,
+      annotate_rewrites: false,
+      hash_remote_file_names: false,
+      typescript_paths: false,
+      emit_manifest: false,
+      size_budget: None,
+      cache_dir: None,
+      custom_visitors: Vec::new(),
+      file_text_handler: None,
+      version_handler: None,
     }
   }
 
@@ -74,6 +208,18 @@ impl TestBuilder {
     self
   }
 
+  pub fn set_jsx(
+    &mut self,
+    runtime: JsxRuntime,
+    import_source: Option<&str>,
+  ) -> &mut Self {
+    self.jsx = Some(JsxOptions {
+      runtime,
+      import_source: import_source.map(|s| s.to_string()),
+    });
+    self
+  }
+
   pub fn add_default_shims(&mut self) -> &mut Self {
     let deno_shim = Shim::Package(PackageShim {
       package: PackageMappedSpecifier {
@@ -81,6 +227,7 @@ impl TestBuilder {
         version: Some("^0.1.0".to_string()),
         sub_path: None,
         peer_dependency: false,
+        types_package: None,
       },
       types_package: None,
       global_names: vec![GlobalName {
@@ -97,6 +244,7 @@ impl TestBuilder {
         version: Some("^0.1.0".to_string()),
         sub_path: None,
         peer_dependency: false,
+        types_package: None,
       },
       types_package: None,
       global_names: vec![
@@ -141,11 +289,24 @@ impl TestBuilder {
         version: version.map(|v| v.to_string()),
         sub_path: path.map(|v| v.to_string()),
         peer_dependency: false,
+        types_package: None,
       }),
     );
     self
   }
 
+  pub fn add_specifier_mapping(
+    &mut self,
+    specifier: impl AsRef<str>,
+    mapped_specifier: MappedSpecifier,
+  ) -> &mut Self {
+    self.specifier_mappings.insert(
+      ModuleSpecifier::parse(specifier.as_ref()).unwrap(),
+      mapped_specifier,
+    );
+    self
+  }
+
   pub fn add_module_specifier_mapping(
     &mut self,
     from: impl AsRef<str>,
@@ -158,12 +319,345 @@ impl TestBuilder {
     self
   }
 
+  pub fn add_declaration_mapping(
+    &mut self,
+    code_specifier: impl AsRef<str>,
+    types_specifier: impl AsRef<str>,
+  ) -> &mut Self {
+    self.declaration_mappings.insert(
+      ModuleSpecifier::parse(code_specifier.as_ref()).unwrap(),
+      ModuleSpecifier::parse(types_specifier.as_ref()).unwrap(),
+    );
+    self
+  }
+
+  pub fn set_error_on_cycles(&mut self, value: bool) -> &mut Self {
+    self.error_on_cycles = value;
+    self
+  }
+
+  pub fn set_error_on_duplicate_packages(&mut self, value: bool) -> &mut Self {
+    self.error_on_duplicate_packages = value;
+    self
+  }
+
+  pub fn set_continue_on_parse_error(&mut self, value: bool) -> &mut Self {
+    self.continue_on_parse_error = value;
+    self
+  }
+
+  pub fn set_normalize_output_text(&mut self, value: bool) -> &mut Self {
+    self.normalize_output_text = value;
+    self
+  }
+
+  pub fn set_github_dependencies(&mut self, value: bool) -> &mut Self {
+    self.github_dependencies = value;
+    self
+  }
+
+  pub fn set_remote_policy(&mut self, value: RemotePolicy) -> &mut Self {
+    self.remote_policy = Some(value);
+    self
+  }
+
+  pub fn set_version_policy(&mut self, value: VersionPolicy) -> &mut Self {
+    self.version_policy = value;
+    self
+  }
+
+  pub fn set_asset_imports(&mut self, value: bool) -> &mut Self {
+    self.asset_imports = value;
+    self
+  }
+
+  pub fn add_asset_handler(
+    &mut self,
+    attribute_type: &str,
+    handler: impl AssetHandler + 'static,
+  ) -> &mut Self {
+    self
+      .asset_handlers
+      .insert(attribute_type.to_string(), Arc::new(handler));
+    self
+  }
+
+  pub fn add_directory_index_file(
+    &mut self,
+    value: impl AsRef<str>,
+  ) -> &mut Self {
+    self.directory_index_files.push(value.as_ref().to_string());
+    self
+  }
+
+  pub fn add_local_root(
+    &mut self,
+    dir: impl AsRef<str>,
+    output_dir: impl AsRef<str>,
+  ) -> &mut Self {
+    self.local_roots.push(LocalRoot {
+      dir: ModuleSpecifier::parse(dir.as_ref()).unwrap(),
+      output_dir: PathBuf::from(output_dir.as_ref()),
+    });
+    self
+  }
+
+  pub fn set_node_global_shims(
+    &mut self,
+    buffer: Option<PackageMappedSpecifier>,
+    process: Option<PackageMappedSpecifier>,
+  ) -> &mut Self {
+    self.node_global_shims = NodeGlobalShims { buffer, process };
+    self
+  }
+
+  pub fn set_dom_types_package(&mut self, package: Dependency) -> &mut Self {
+    self.dom_types_package = Some(package);
+    self
+  }
+
+  pub fn set_inject_dirname(&mut self, value: bool) -> &mut Self {
+    self.inject_dirname = value;
+    self
+  }
+
+  pub fn set_commonjs_modules(&mut self, value: CommonJsModules) -> &mut Self {
+    self.commonjs_modules = value;
+    self
+  }
+
+  pub fn set_server_shim(&mut self, server_shim: ServerShim) -> &mut Self {
+    self.server_shim = Some(server_shim);
+    self
+  }
+
+  pub fn set_comment_directives(
+    &mut self,
+    comment_directives: CommentDirectiveOptions,
+  ) -> &mut Self {
+    self.comment_directives = comment_directives;
+    self
+  }
+
+  pub fn set_import_attributes(
+    &mut self,
+    import_attributes: ImportAttributeOptions,
+  ) -> &mut Self {
+    self.import_attributes = import_attributes;
+    self
+  }
+
+  pub fn set_inline_remote_module_max_size(
+    &mut self,
+    max_size: usize,
+  ) -> &mut Self {
+    self.inline_remote_module_max_size = Some(max_size);
+    self
+  }
+
+  pub fn set_bundle(&mut self, value: bool) -> &mut Self {
+    self.bundle = value;
+    self
+  }
+
+  pub fn set_flatten_barrels(&mut self, value: bool) -> &mut Self {
+    self.flatten_barrels = value;
+    self
+  }
+
+  pub fn set_minify(&mut self, value: bool) -> &mut Self {
+    self.minify = value;
+    self
+  }
+
+  pub fn set_entry_point_exports(&mut self, value: bool) -> &mut Self {
+    self.entry_point_exports = value;
+    self
+  }
+
+  pub fn set_declarations_only(&mut self, value: bool) -> &mut Self {
+    self.declarations_only = value;
+    self
+  }
+
+  pub fn set_third_party_licenses(&mut self, value: bool) -> &mut Self {
+    self.third_party_licenses = value;
+    self
+  }
+
+  pub fn set_provenance(&mut self, value: bool) -> &mut Self {
+    self.provenance = value;
+    self
+  }
+
+  pub fn add_define(
+    &mut self,
+    expression: impl AsRef<str>,
+    value: impl AsRef<str>,
+  ) -> &mut Self {
+    self
+      .define
+      .insert(expression.as_ref().to_string(), value.as_ref().to_string());
+    self
+  }
+
+  pub fn set_deno_env_to_process_env(&mut self, value: bool) -> &mut Self {
+    self.deno_env_to_process_env = value;
+    self
+  }
+
+  pub fn set_translate_deno_apis(&mut self, value: bool) -> &mut Self {
+    self.translate_deno_apis = value;
+    self
+  }
+
+  pub fn set_shim_import_style(&mut self, value: ShimImportStyle) -> &mut Self {
+    self.shim_import_style = value;
+    self
+  }
+
+  pub fn set_deno_feature_detection(
+    &mut self,
+    value: DenoFeatureDetection,
+  ) -> &mut Self {
+    self.deno_feature_detection = value;
+    self
+  }
+
+  pub fn set_qualified_global_access(
+    &mut self,
+    value: QualifiedGlobalAccess,
+  ) -> &mut Self {
+    self.qualified_global_access = value;
+    self
+  }
+
+  pub fn set_relative_specifier_extension(
+    &mut self,
+    value: RelativeSpecifierExtension,
+  ) -> &mut Self {
+    self.relative_specifier_extension = value;
+    self
+  }
+
+  pub fn set_test_relative_specifier_extension(
+    &mut self,
+    value: RelativeSpecifierExtension,
+  ) -> &mut Self {
+    self.test_relative_specifier_extension = Some(value);
+    self
+  }
+
+  pub fn set_test_main_imports(&mut self, value: TestMainImports) -> &mut Self {
+    self.test_main_imports = value;
+    self
+  }
+
+  pub fn set_annotate_rewrites(&mut self, value: bool) -> &mut Self {
+    self.annotate_rewrites = value;
+    self
+  }
+
+  pub fn set_hash_remote_file_names(&mut self, value: bool) -> &mut Self {
+    self.hash_remote_file_names = value;
+    self
+  }
+
+  pub fn set_typescript_paths(&mut self, value: bool) -> &mut Self {
+    self.typescript_paths = value;
+    self
+  }
+
+  pub fn set_emit_manifest(&mut self, value: bool) -> &mut Self {
+    self.emit_manifest = value;
+    self
+  }
+
+  pub fn set_size_budget(&mut self, value: SizeBudget) -> &mut Self {
+    self.size_budget = Some(value);
+    self
+  }
+
+  pub fn set_cache_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+    self.cache_dir = Some(dir.as_ref().to_path_buf());
+    self
+  }
+
+  pub fn add_custom_visitor(
+    &mut self,
+    visitor: impl TransformVisitor + 'static,
+  ) -> &mut Self {
+    self.custom_visitors.push(Arc::new(visitor));
+    self
+  }
+
+  pub fn set_file_text_handler(
+    &mut self,
+    handler: impl FileTextHandler + 'static,
+  ) -> &mut Self {
+    self.file_text_handler = Some(Arc::new(handler));
+    self
+  }
+
+  pub fn set_version_handler(
+    &mut self,
+    handler: impl VersionHandler + 'static,
+  ) -> &mut Self {
+    self.version_handler = Some(Arc::new(handler));
+    self
+  }
+
   pub fn set_target(&mut self, target: ScriptTarget) -> &mut Self {
     self.target = target;
     self
   }
 
   pub async fn transform(&self) -> Result<TransformOutput> {
+    Ok(transform(self.options()).await?)
+  }
+
+  pub async fn transform_with_sink(
+    &self,
+    sink: &dyn OutputSink,
+  ) -> Result<TransformOutput> {
+    Ok(transform_with_sink(self.options(), sink).await?)
+  }
+
+  pub async fn transform_workspace(
+    &self,
+    members: Vec<WorkspaceMember>,
+  ) -> Result<TransformWorkspaceOutput> {
+    let mut options = self.options();
+    // the entry points are provided by the members
+    options.entry_points.clear();
+    options.test_entry_points.clear();
+    Ok(
+      transform_workspace(TransformWorkspaceOptions { members, options })
+        .await?,
+    )
+  }
+
+  pub async fn analyze(&self) -> Result<GraphInfo> {
+    Ok(analyze(self.options()).await?)
+  }
+
+  pub async fn classify_modules(&self) -> Result<ModuleClassification> {
+    Ok(classify_modules(self.options()).await?)
+  }
+
+  pub fn resolve_mapped_specifier(
+    &self,
+    raw: &str,
+    referrer: &str,
+  ) -> Result<MappedResult> {
+    Ok(resolve_mapped_specifier(
+      raw,
+      &ModuleSpecifier::parse(referrer).unwrap(),
+      &self.options(),
+    )?)
+  }
+
+  fn options(&self) -> TransformOptions {
     let mut entry_points =
       vec![ModuleSpecifier::parse(&self.entry_point).unwrap()];
     entry_points.extend(
@@ -172,7 +666,7 @@ impl TestBuilder {
         .iter()
         .map(|p| ModuleSpecifier::parse(p).unwrap()),
     );
-    transform(TransformOptions {
+    TransformOptions {
       entry_points,
       test_entry_points: self
         .test_entry_points
@@ -183,9 +677,123 @@ impl TestBuilder {
       test_shims: self.test_shims.clone(),
       loader: Some(Box::new(self.loader.clone())),
       specifier_mappings: self.specifier_mappings.clone(),
+      declaration_mappings: self.declaration_mappings.clone(),
       target: self.target,
       import_map: self.import_map.clone(),
-    })
-    .await
+      jsx: self.jsx.clone(),
+      error_on_cycles: self.error_on_cycles,
+      error_on_duplicate_packages: self.error_on_duplicate_packages,
+      continue_on_parse_error: self.continue_on_parse_error,
+      normalize_output_text: self.normalize_output_text,
+      github_dependencies: self.github_dependencies,
+      remote_policy: self.remote_policy.clone(),
+      version_policy: self.version_policy,
+      asset_imports: self.asset_imports,
+      asset_handlers: self
+        .asset_handlers
+        .iter()
+        .map(|(attribute_type, handler)| {
+          (
+            attribute_type.clone(),
+            Box::new(SharedAssetHandler(handler.clone()))
+              as Box<dyn AssetHandler>,
+          )
+        })
+        .collect(),
+      directory_index_files: self.directory_index_files.clone(),
+      local_roots: self.local_roots.clone(),
+      node_global_shims: self.node_global_shims.clone(),
+      dom_types_package: self.dom_types_package.clone(),
+      inject_dirname: self.inject_dirname,
+      commonjs_modules: self.commonjs_modules,
+      server_shim: self.server_shim.clone(),
+      comment_directives: self.comment_directives.clone(),
+      import_attributes: self.import_attributes.clone(),
+      inline_remote_module_max_size: self.inline_remote_module_max_size,
+      bundle: self.bundle,
+      flatten_barrels: self.flatten_barrels,
+      minify: self.minify,
+      entry_point_exports: self.entry_point_exports,
+      declarations_only: self.declarations_only,
+      third_party_licenses: self.third_party_licenses,
+      provenance: self.provenance,
+      define: self.define.clone(),
+      deno_env_to_process_env: self.deno_env_to_process_env,
+      translate_deno_apis: self.translate_deno_apis,
+      shim_import_style: self.shim_import_style,
+      deno_feature_detection: self.deno_feature_detection,
+      qualified_global_access: self.qualified_global_access,
+      relative_specifier_extension: self.relative_specifier_extension,
+      test_relative_specifier_extension: self.test_relative_specifier_extension,
+      test_main_imports: self.test_main_imports.clone(),
+      annotate_rewrites: self.annotate_rewrites,
+      hash_remote_file_names: self.hash_remote_file_names,
+      typescript_paths: self.typescript_paths,
+      emit_manifest: self.emit_manifest,
+      size_budget: self.size_budget.clone(),
+      cache_dir: self.cache_dir.clone(),
+      custom_visitors: self
+        .custom_visitors
+        .iter()
+        .map(|v| {
+          Box::new(SharedVisitor(v.clone())) as Box<dyn TransformVisitor>
+        })
+        .collect(),
+      file_text_handler: self.file_text_handler.clone().map(|h| {
+        Box::new(SharedFileTextHandler(h)) as Box<dyn FileTextHandler>
+      }),
+      version_handler: self
+        .version_handler
+        .clone()
+        .map(|h| Box::new(SharedVersionHandler(h)) as Box<dyn VersionHandler>),
+    }
+  }
+}
+
+struct SharedVisitor(Arc<dyn TransformVisitor>);
+
+impl TransformVisitor for SharedVisitor {
+  fn visit(
+    &self,
+    context: &TransformVisitorContext,
+  ) -> Result<Vec<TextChange>> {
+    self.0.visit(context)
+  }
+
+  fn cache_key(&self) -> Option<String> {
+    self.0.cache_key()
+  }
+}
+
+struct SharedFileTextHandler(Arc<dyn FileTextHandler>);
+
+impl FileTextHandler for SharedFileTextHandler {
+  fn handle_file_text(
+    &self,
+    environment: OutputEnvironment,
+    specifier: &ModuleSpecifier,
+    file: &mut OutputFile,
+  ) -> Result<()> {
+    self.0.handle_file_text(environment, specifier, file)
+  }
+}
+
+struct SharedVersionHandler(Arc<dyn VersionHandler>);
+
+impl VersionHandler for SharedVersionHandler {
+  fn handle_version(
+    &self,
+    environment: OutputEnvironment,
+    dependency: &Dependency,
+  ) -> Result<String> {
+    self.0.handle_version(environment, dependency)
+  }
+}
+
+struct SharedAssetHandler(Arc<dyn AssetHandler>);
+
+impl AssetHandler for SharedAssetHandler {
+  fn handle_asset(&self, asset: &AssetFile) -> Result<AssetOutput> {
+    self.0.handle_asset(asset)
   }
 }
@@ -1,14 +1,65 @@
 // Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::sync::Mutex;
 
+use deno_node_transform::package::PackageJsonExport;
+use deno_node_transform::package::PackageJsonExportTarget;
+use deno_node_transform::text_changes::TextChange;
+use deno_node_transform::url_to_file_path;
+use deno_node_transform::AssetFile;
+use deno_node_transform::AssetHandler;
+use deno_node_transform::AssetOutput;
+use deno_node_transform::CommentDirectiveAction;
+use deno_node_transform::CommentDirectiveOptions;
+use deno_node_transform::CommonJsModules;
+use deno_node_transform::DenoFeatureDetection;
 use deno_node_transform::Dependency;
+use deno_node_transform::DependencyChange;
+use deno_node_transform::EntryPointExports;
+use deno_node_transform::EnvironmentClassification;
+use deno_node_transform::FileShimUsage;
+use deno_node_transform::FileTextHandler;
 use deno_node_transform::GlobalName;
+use deno_node_transform::IgnoredTypes;
+use deno_node_transform::ImportAttributeAction;
+use deno_node_transform::ImportAttributeOptions;
+use deno_node_transform::JsxRuntime;
+use deno_node_transform::LocalRoot;
+use deno_node_transform::MappedResult;
+use deno_node_transform::MappedSpecifier;
+use deno_node_transform::ModuleProvenance;
 use deno_node_transform::ModuleShim;
+use deno_node_transform::ModuleSpecifier;
+use deno_node_transform::OutputEnvironment;
+use deno_node_transform::OutputFile;
+use deno_node_transform::OutputSink;
 use deno_node_transform::PackageMappedSpecifier;
 use deno_node_transform::PackageShim;
+use deno_node_transform::QualifiedGlobalAccess;
+use deno_node_transform::RelativeSpecifierExtension;
+use deno_node_transform::ResolvedTypes;
 use deno_node_transform::ScriptTarget;
+use deno_node_transform::ServerShim;
 use deno_node_transform::Shim;
+use deno_node_transform::ShimImportStyle;
+use deno_node_transform::SizeBudget;
+use deno_node_transform::TestMainImports;
+use deno_node_transform::TransformError;
+use deno_node_transform::TransformVisitor;
+use deno_node_transform::TransformVisitorContext;
+use deno_node_transform::UnshimmableApiCategory;
+use deno_node_transform::UnshimmableApiUsage;
+use deno_node_transform::UntypedDependency;
+use deno_node_transform::VersionHandler;
+use deno_node_transform::VersionPolicy;
+use deno_node_transform::WorkspaceDependency;
+use deno_node_transform::WorkspaceMember;
+use deno_node_transform::WriteOptions;
+use deno_node_transform::WriteResult;
+use deno_node_transform::MANIFEST_FILE_NAME;
+use deno_node_transform::THIRD_PARTY_LICENSES_FILE_NAME;
 use pretty_assertions::assert_eq;
 
 #[macro_use]
@@ -127,6 +178,7 @@ async fn transform_shim_custom_shims() {
         version: Some("~3.1.0".to_string()),
         sub_path: None,
         peer_dependency: false,
+        types_package: None,
       },
       types_package: None,
       global_names: vec![GlobalName {
@@ -141,6 +193,7 @@ async fn transform_shim_custom_shims() {
         version: Some("~3.1.0".to_string()),
         sub_path: Some("test".to_string()),
         peer_dependency: false,
+        types_package: None,
       },
       types_package: None,
       global_names: vec![GlobalName {
@@ -155,6 +208,7 @@ async fn transform_shim_custom_shims() {
         version: Some("^4.0.0".to_string()),
         sub_path: None,
         peer_dependency: false,
+        types_package: None,
       },
       types_package: Some(Dependency {
         name: "@types/domexception".to_string(),
@@ -173,6 +227,7 @@ async fn transform_shim_custom_shims() {
         version: None,
         sub_path: None,
         peer_dependency: false,
+        types_package: None,
       },
       types_package: None,
       global_names: vec![
@@ -194,6 +249,7 @@ async fn transform_shim_custom_shims() {
         version: None,
         sub_path: None,
         peer_dependency: false,
+        types_package: None,
       },
       types_package: None,
       global_names: vec![GlobalName {
@@ -387,207 +443,4862 @@ async fn transform_legacy_deno_shim_ignore_warnings() {
 }
 
 #[tokio::test]
-async fn transform_global_this_shim() {
-  assert_transforms(vec![(
-    concat!(
-      "globalThis.Deno.readTextFile();",
-      "globalThis.test();",
-      "globalThis.test.test();",
-      "globalThis['test']();",
-      r#"globalThis["test"]();"#,
-      "globalThis.Deno = 5;",
-      "true ? globalThis : globalThis;",
-      "typeof globalThis.Deno;",
-      "'Deno' in globalThis;",
-      "typeof globalThis;",
-      "globalThis == null;",
-      "globalThis ? true : false;",
-      "type Test1 = typeof globalThis;",
-      "type Test2 = typeof globalThis.Window;",
-      "type Test3 = typeof globalThis.Deno;",
-      "type Test4 = window.Something;",
-    ),
-    concat!(
-      r#"import * as dntShim from "./_dnt.shims.js";"#,
-      "\ndntShim.dntGlobalThis.Deno.readTextFile();",
-      "globalThis.test();",
-      "globalThis.test.test();",
-      "globalThis['test']();",
-      r#"globalThis["test"]();"#,
-      "dntShim.dntGlobalThis.Deno = 5;",
-      "true ? dntShim.dntGlobalThis : dntShim.dntGlobalThis;",
-      "typeof dntShim.dntGlobalThis.Deno;",
-      "'Deno' in dntShim.dntGlobalThis;",
-      "typeof dntShim.dntGlobalThis;",
-      "dntShim.dntGlobalThis == null;",
-      "dntShim.dntGlobalThis ? true : false;",
-      "type Test1 = typeof dntShim.dntGlobalThis;",
-      "type Test2 = typeof globalThis.Window;",
-      "type Test3 = typeof dntShim.Deno;",
-      "type Test4 = globalThis.Something;",
-    ),
-  )])
-  .await;
+async fn transform_commonjs_globals_warnings() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        concat!(
+          "const fs = require('fs');\n",
+          "module.exports = { dir: __dirname };\n",
+          "function test(process: number, module: string) {\n",
+          "  return process + module.exports;\n",
+          "}\n",
+          "const buffer: Buffer = Buffer.from('');\n",
+          "// dnt-shim-ignore\n",
+          "process.exit();\n",
+        ),
+      );
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.warnings,
+    vec![
+      "require is not available in ES modules. Use an import declaration or `createRequire` from \"node:module\" instead.\n    at file:///mod.ts:1:12",
+      "module is not available in ES modules. Use an export declaration instead of `module.exports`.\n    at file:///mod.ts:2:1",
+      "__dirname is not available in ES modules. Use `import.meta.url` with `fileURLToPath` from \"node:url\" instead.\n    at file:///mod.ts:2:25",
+      "Buffer is not available in ES modules. Import it from \"node:buffer\" or configure a shim for it.\n    at file:///mod.ts:6:24",
+    ]
+  );
 }
 
 #[tokio::test]
-async fn transform_window() {
-  assert_transforms(vec![
+async fn transform_import_equals_and_export_assignment() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import other = require('./other.ts');\n",
+            "export = other.value;\n",
+          ),
+        )
+        .add_local_file("/other.ts", "export const value = 5;\n");
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "import other = require('./other.js');\n",
+          "export = other.value;\n",
+        )
+      ),
+      ("other.ts", "export const value = 5;\n"),
+    ]
+  );
+  assert_eq!(
+    result.warnings,
+    vec![
+      "`export =` is not available in ES modules. Use `export default other.value;` instead.\n    at file:///mod.ts:2:1",
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_node_global_shims() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        "console.log(Buffer.from(''), process.argv);",
+      );
+    })
+    .set_node_global_shims(
+      Some(PackageMappedSpecifier {
+        name: "buffer".to_string(),
+        version: Some("^6.0.3".to_string()),
+        sub_path: None,
+        peer_dependency: false,
+        types_package: None,
+      }),
+      Some(PackageMappedSpecifier {
+        name: "process".to_string(),
+        version: Some("^0.11.10".to_string()),
+        sub_path: None,
+        peer_dependency: false,
+        types_package: None,
+      }),
+    )
+    .transform()
+    .await
+    .unwrap();
+
+  assert!(result.warnings.is_empty());
+  assert_files!(result.main.files, &[
     (
-      concat!("window.test = 5;", "window.Deno.test();",),
-      concat!(
-        r#"import * as dntShim from "./_dnt.shims.js";"#,
-        "\nglobalThis.test = 5;",
-        "dntShim.dntGlobalThis.Deno.test();",
+      "_dnt.shims.ts",
+      get_shim_file_text(
+        concat!(
+          "import { Buffer as Buffer } from \"buffer\";\n",
+          "export { Buffer as Buffer } from \"buffer\";\n",
+          "import { default as process } from \"process\";\n",
+          "export { default as process } from \"process\";\n",
+          "\n",
+          "const dntGlobals = {\n",
+          "  Buffer,\n",
+          "  process,\n",
+          "};\n",
+          "export const dntGlobalThis = createMergeProxy(globalThis, dntGlobals);\n",
+        ).to_string(),
       ),
     ),
     (
-      // should be as-is because there's a declaration
-      "const window = {}; window.test;",
-      "const window = {}; window.test;",
-    ),
-  ])
-  .await;
+      "mod.ts",
+      concat!(
+        "import * as dntShim from \"./_dnt.shims.js\";\n",
+        "console.log(dntShim.Buffer.from(''), dntShim.process.argv);"
+      ).to_string()
+    )
+  ]);
+  assert_eq!(
+    result
+      .main
+      .dependencies
+      .iter()
+      .map(|d| d.name.as_str())
+      .collect::<Vec<_>>(),
+    vec!["buffer", "process"]
+  );
 }
 
 #[tokio::test]
-async fn no_shim_situations() {
-  assert_identity_transforms(vec![
-    "const { Deno } = test; Deno.test;",
-    "const [ Deno ] = test; Deno.test;",
-    "const { asdf, ...Deno } = test;",
-    "const { Deno: test } = test;",
-    "const { test: Deno } = test;",
-    "const [Deno] = test;",
-    "const [test, ...Deno] = test;",
-    "const obj = { Deno: test };",
-    "interface Deno {} function test(d: Deno) {}",
-    "interface Test { Deno: string; }",
-    "interface Test { Deno(): string; }",
-    "class Deno {}",
-    "class Test { Deno: string; }",
-    "class Test { Deno() {} }",
-    "const t = class Deno {};",
-    "function Deno() {}",
-    "const t = function Deno() {};",
-    "import { Deno } from 'test';",
-    "import * as Deno from 'test';",
-    "import { test as Deno } from 'test';",
-    "import { Deno as test } from 'test';",
-    "export { Deno } from 'test';",
-    "export * as Deno from 'test';",
-    "export { test as Deno } from 'test';",
-    "export { Deno as test } from 'test';",
-    "try {} catch (Deno) {}",
-    "function test(Deno) {}",
-    "interface Response {} function test(r: Response) {}",
-  ])
-  .await;
+async fn transform_dom_apis_warning() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import './other.ts';\n",
+            "const el: HTMLElement = document.body;\n",
+            "// dnt-shim-ignore\n",
+            "requestAnimationFrame(() => {});\n",
+          ),
+        )
+        .add_local_file(
+          "/other.ts",
+          concat!(
+            "/// <reference lib=\"dom\" />\n",
+            "export const div = document.createElement('div');\n",
+          ),
+        );
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.warnings,
+    vec![
+      "file:///mod.ts uses DOM APIs (HTMLElement, document), which aren't in the types of Node.js. Specify a DOM types package (ex. @types/web) or add the \"dom\" lib to the compiler options.",
+    ]
+  );
+  assert!(result.main.dependencies.is_empty());
 }
 
 #[tokio::test]
-async fn transform_deno_collision() {
-  assert_transforms(vec![(
-    concat!(
-      "const Deno = {};",
-      "const { Deno: Deno2 } = globalThis;",
-      "Deno2.readTextFile();",
-      "Deno.test;"
-    ),
-    concat!(
-      r#"import * as dntShim from "./_dnt.shims.js";"#,
-      "\nconst Deno = {};",
-      "const { Deno: Deno2 } = dntShim.dntGlobalThis;",
-      "Deno2.readTextFile();",
-      "Deno.test;"
-    ),
-  )])
-  .await;
+async fn transform_dom_types_package() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "/// <reference lib=\"dom\" />\n",
+            "import './other.ts';\n",
+            "export const div = document.createElement('div');\n",
+          ),
+        )
+        .add_local_file(
+          "/other.ts",
+          "export const el: Element | null = null;\n",
+        );
+    })
+    .set_dom_types_package(Dependency {
+      name: "@types/web".to_string(),
+      version: "^0.0.99".to_string(),
+      peer_dependency: false,
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert!(result.warnings.is_empty());
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "/// <reference types=\"web\" />\n",
+          "import './other.js';\n",
+          "export const div = document.createElement('div');\n",
+        )
+      ),
+      ("other.ts", "export const el: Element | null = null;\n"),
+    ]
+  );
+  assert_eq!(
+    result
+      .main
+      .dependencies
+      .iter()
+      .map(|d| d.name.as_str())
+      .collect::<Vec<_>>(),
+    vec!["@types/web"]
+  );
+}
+
+#[tokio::test]
+async fn transform_inject_dirname() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import './other.ts';\n",
+            "console.log(__dirname, __filename);\n",
+          ),
+        )
+        .add_local_file(
+          "/other.ts",
+          "function test(__dirname: string) {\n  return __dirname;\n}\n",
+        );
+    })
+    .set_inject_dirname(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert!(result.warnings.is_empty());
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "import { fileURLToPath as dntFileURLToPath } from \"node:url\";\n",
+          "import { dirname as dntDirname } from \"node:path\";\n",
+          "const __filename = dntFileURLToPath(import.meta.url);\n",
+          "const __dirname = dntDirname(__filename);\n",
+          "import './other.js';\n",
+          "console.log(__dirname, __filename);\n",
+        )
+      ),
+      (
+        "other.ts",
+        "function test(__dirname: string) {\n  return __dirname;\n}\n"
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_inject_dirname_directive() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        concat!(
+          "// dnt-inject-dirname\n",
+          "const dntDirname = 5;\n",
+          "console.log(__dirname);\n",
+        ),
+      );
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert!(result.warnings.is_empty());
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "// dnt-inject-dirname\n",
+        "import { fileURLToPath as dntFileURLToPath } from \"node:url\";\n",
+        "import { dirname as dntDirname1 } from \"node:path\";\n",
+        "const __dirname = dntDirname1(dntFileURLToPath(import.meta.url));\n",
+        "\n",
+        "const dntDirname = 5;\n",
+        "console.log(__dirname);\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_commonjs_module_error() {
+  let error_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "import data from './data.js';")
+        .add_local_file("/data.js", "module.exports = { a: 1 };");
+    })
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(
+    error_message.to_string(),
+    concat!(
+      "file:///data.js is a CommonJS module, which can't be output as an ES module. ",
+      "Convert it to an ES module or wrap it by setting the CommonJS modules option to wrap."
+    )
+  );
+}
+
+#[tokio::test]
+async fn transform_commonjs_module_wrap() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import data from './data.cjs';\n",
+            "import other from './other.js';\n",
+            "import { esm } from './esm.js';\n",
+          ),
+        )
+        .add_local_file(
+          "/data.cjs",
+          "exports.value = require('node:path').sep;\n",
+        )
+        .add_local_file(
+          "/other.js",
+          "const dntModule = 1;\nmodule.exports = dntModule;",
+        )
+        .add_local_file(
+          "/esm.js",
+          "export const esm = require('./data.cjs');\n",
+        );
+    })
+    .set_commonjs_modules(CommonJsModules::Wrap)
+    .transform()
+    .await
+    .unwrap();
+
+  // modules with import or export declarations aren't CommonJS
+  assert_eq!(
+    result.warnings,
+    vec![concat!(
+      "require is not available in ES modules. Use an import declaration or ",
+      "`createRequire` from \"node:module\" instead.\n",
+      "    at file:///esm.js:1:20",
+    )]
+  );
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "data.js",
+        concat!(
+          "import { createRequire as dntCreateRequire } from \"node:module\";\n",
+          "const dntModule = { exports: {} };\n",
+          "(function (module, exports, require) {\n",
+          "exports.value = require('node:path').sep;\n",
+          "}).call(dntModule.exports, dntModule, dntModule.exports, dntCreateRequire(import.meta.url));\n",
+          "export default dntModule.exports;\n",
+        )
+      ),
+      ("esm.js", "export const esm = require('./data.cjs');\n"),
+      (
+        "mod.ts",
+        concat!(
+          "import data from './data.js';\n",
+          "import other from './other.js';\n",
+          "import { esm } from './esm.js';\n",
+        )
+      ),
+      (
+        "other.js",
+        concat!(
+          "import { createRequire as dntCreateRequire } from \"node:module\";\n",
+          "const dntModule1 = { exports: {} };\n",
+          "(function (module, exports, require) {\n",
+          "const dntModule = 1;\nmodule.exports = dntModule;\n",
+          "}).call(dntModule1.exports, dntModule1, dntModule1.exports, dntCreateRequire(import.meta.url));\n",
+          "export default dntModule1.exports;\n",
+        )
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_module_workers() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "const worker = new Worker(new URL('./worker.ts', import.meta.url), { type: 'module' });\n",
+            "const classic = new Worker(new URL('./classic.ts', import.meta.url));\n",
+          ),
+        )
+        .add_local_file("/worker.ts", "import { add } from './add.ts';\nself.postMessage(add(1, 2));")
+        .add_local_file("/add.ts", "export function add(a: number, b: number) { return a + b; }");
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "const worker = new Worker(new URL('./worker.js', import.meta.url), { type: 'module' });\n",
+          "const classic = new Worker(new URL('./classic.ts', import.meta.url));\n",
+        )
+      ),
+      ("worker.ts", "import { add } from './add.js';\nself.postMessage(add(1, 2));"),
+      ("add.ts", "export function add(a: number, b: number) { return a + b; }"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_deno_server_apis_warnings() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        "Deno.serve(() => new Response());\nDeno.listen({ port: 80 });",
+      );
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.warnings,
+    vec![
+      "Deno.serve is not supported in Node.js by the Deno shim. Provide a server shim to replace it.\n    at file:///mod.ts:1:1",
+      "Deno.listen is not supported in Node.js by the Deno shim. Provide a server shim to replace it.\n    at file:///mod.ts:2:1",
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_deno_server_shim() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        concat!(
+          "Deno.serve(() => new Response());\n",
+          "Deno.serveHttp(conn);\n",
+          "Deno.readTextFile('file.txt');\n",
+        ),
+      );
+    })
+    .add_default_shims()
+    .set_server_shim(ServerShim {
+      package: PackageMappedSpecifier {
+        name: "deno-server".to_string(),
+        version: Some("^1.0.0".to_string()),
+        sub_path: None,
+        peer_dependency: false,
+        types_package: None,
+      },
+      export_names: BTreeMap::from([(
+        "serveHttp".to_string(),
+        "serveHttpConn".to_string(),
+      )]),
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.warnings,
+    vec![
+      "Deno.serve was replaced with the \"serve\" export of deno-server, which may not support every feature of Deno.HttpServer (ex. `finished`, `ref()`, `unref()`, and `shutdown()`).\n    at file:///mod.ts:1:1",
+      "Deno.serveHttp was replaced with the \"serveHttpConn\" export of deno-server, which may not support every feature of Deno.HttpConn (ex. `rid` and `nextRequest()`).\n    at file:///mod.ts:2:1",
+    ]
+  );
+  let mod_file = result
+    .main
+    .files
+    .iter()
+    .find(|f| f.file_path == PathBuf::from("mod.ts"))
+    .unwrap();
+  assert_eq!(
+    mod_file.file_text,
+    concat!(
+      "import * as dntShim from \"./_dnt.shims.js\";\n",
+      "dntShim.dntServer.serve(() => new Response());\n",
+      "dntShim.dntServer.serveHttpConn(conn);\n",
+      "dntShim.Deno.readTextFile('file.txt');\n",
+    )
+  );
+  let shim_file = result
+    .main
+    .files
+    .iter()
+    .find(|f| f.file_path == PathBuf::from("_dnt.shims.ts"))
+    .unwrap();
+  assert!(shim_file
+    .file_text
+    .contains("export * as dntServer from \"deno-server\";\n"));
+  assert!(result
+    .main
+    .dependencies
+    .iter()
+    .any(|d| d.name == "deno-server" && d.version == "^1.0.0"));
+}
+
+#[tokio::test]
+async fn transform_unshimmable_api_usages() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import './other.ts';\n",
+            "const lib = Deno.dlopen('lib.so', {});\n",
+            "// dnt-shim-ignore\n",
+            "Deno.run({ cmd: ['ls'] });\n",
+          ),
+        )
+        .add_local_file(
+          "/other.ts",
+          concat!(
+            "function test(Deno: any) { Deno.run(); }\n",
+            "const output = new Deno.Command('ls').outputSync();\n",
+          ),
+        );
+    })
+    .add_default_shims()
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.unshimmable_api_usages,
+    vec![
+      UnshimmableApiUsage {
+        specifier: ModuleSpecifier::parse("file:///mod.ts").unwrap(),
+        line_number: 2,
+        column_number: 13,
+        api: "Deno.dlopen".to_string(),
+        category: UnshimmableApiCategory::Ffi,
+      },
+      UnshimmableApiUsage {
+        specifier: ModuleSpecifier::parse("file:///other.ts").unwrap(),
+        line_number: 2,
+        column_number: 20,
+        api: "Deno.Command".to_string(),
+        category: UnshimmableApiCategory::Subprocess,
+      },
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_shims_used() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import './a.ts';\n",
+            "import './b.ts';\n",
+            "import './c.ts';\n",
+            "setTimeout(() => Deno.exit(), 100);\n",
+          ),
+        )
+        .add_local_file("/a.ts", "Deno.readTextFile('file.txt');")
+        .add_local_file("/b.ts", "globalThis.Deno.cwd();")
+        .add_local_file("/c.ts", "export const a = 5;")
+        .add_local_file("/mod.test.ts", "import './mod.ts';\nDeno.test();");
+    })
+    .add_default_shims()
+    .add_test_entry_point("file:///mod.test.ts")
+    .transform()
+    .await
+    .unwrap();
+
+  let shim_usage =
+    |path: &str, globals: &[&str], shims: &[&str]| FileShimUsage {
+      specifier: ModuleSpecifier::parse(&format!("file:///{}", path)).unwrap(),
+      file_path: PathBuf::from(path),
+      globals: globals.iter().map(|g| g.to_string()).collect(),
+      shims: shims.iter().map(|s| s.to_string()).collect(),
+    };
+  assert_eq!(
+    result.main.shims_used,
+    vec![
+      shim_usage("a.ts", &["Deno"], &["@deno/shim-deno"]),
+      shim_usage(
+        "b.ts",
+        &["globalThis"],
+        &["@deno/shim-deno", "@deno/shim-timers"]
+      ),
+      shim_usage(
+        "mod.ts",
+        &["Deno", "setTimeout"],
+        &["@deno/shim-deno", "@deno/shim-timers"]
+      ),
+    ]
+  );
+  assert_eq!(
+    result.test.shims_used,
+    vec![shim_usage("mod.test.ts", &["Deno"], &["@deno/shim-deno"])]
+  );
+}
+
+#[tokio::test]
+async fn transform_global_this_shim() {
+  assert_transforms(vec![(
+    concat!(
+      "globalThis.Deno.readTextFile();",
+      "globalThis.test();",
+      "globalThis.test.test();",
+      "globalThis['test']();",
+      r#"globalThis["test"]();"#,
+      "globalThis.Deno = 5;",
+      "true ? globalThis : globalThis;",
+      "typeof globalThis.Deno;",
+      "'Deno' in globalThis;",
+      "typeof globalThis;",
+      "globalThis == null;",
+      "globalThis ? true : false;",
+      "type Test1 = typeof globalThis;",
+      "type Test2 = typeof globalThis.Window;",
+      "type Test3 = typeof globalThis.Deno;",
+      "type Test4 = window.Something;",
+    ),
+    concat!(
+      r#"import * as dntShim from "./_dnt.shims.js";"#,
+      "\ndntShim.dntGlobalThis.Deno.readTextFile();",
+      "globalThis.test();",
+      "globalThis.test.test();",
+      "globalThis['test']();",
+      r#"globalThis["test"]();"#,
+      "dntShim.dntGlobalThis.Deno = 5;",
+      "true ? dntShim.dntGlobalThis : dntShim.dntGlobalThis;",
+      "typeof globalThis.Deno;",
+      "'Deno' in globalThis;",
+      "typeof dntShim.dntGlobalThis;",
+      "dntShim.dntGlobalThis == null;",
+      "dntShim.dntGlobalThis ? true : false;",
+      "type Test1 = typeof dntShim.dntGlobalThis;",
+      "type Test2 = typeof globalThis.Window;",
+      "type Test3 = typeof dntShim.Deno;",
+      "type Test4 = globalThis.Something;",
+    ),
+  )])
+  .await;
+}
+
+#[tokio::test]
+async fn transform_deno_feature_detection() {
+  let get_result = |deno_feature_detection: DenoFeatureDetection| async move {
+    let result = TestBuilder::new()
+      .with_loader(|loader| {
+        loader.add_local_file(
+          "/mod.ts",
+          concat!(
+            "if (typeof Deno !== \"undefined\") Deno.exit();\n",
+            "typeof window.Deno;\n",
+            "const isDeno = \"Deno\" in globalThis;\n",
+            "typeof Deno.version;\n",
+            "type T = typeof globalThis.Deno;\n",
+          ),
+        );
+      })
+      .add_default_shims()
+      .set_deno_feature_detection(deno_feature_detection)
+      .transform()
+      .await
+      .unwrap();
+    let mod_text = result
+      .main
+      .files
+      .iter()
+      .find(|f| f.file_path == PathBuf::from("mod.ts"))
+      .unwrap()
+      .file_text
+      .clone();
+    (mod_text, result.warnings)
+  };
+
+  let (mod_text, warnings) = get_result(DenoFeatureDetection::Native).await;
+  assert_eq!(
+    mod_text,
+    concat!(
+      "import * as dntShim from \"./_dnt.shims.js\";\n",
+      "if (typeof Deno !== \"undefined\") dntShim.Deno.exit();\n",
+      "typeof globalThis.Deno;\n",
+      "const isDeno = \"Deno\" in globalThis;\n",
+      "typeof dntShim.Deno.version;\n",
+      "type T = typeof dntShim.Deno;\n",
+    )
+  );
+  assert_eq!(
+    warnings,
+    vec![
+      concat!(
+        "`typeof Deno` checks the global `Deno` rather than the `Deno` shim, so the code it guards only runs in Deno.\n",
+        "    at file:///mod.ts:1:5",
+      ),
+      concat!(
+        "`typeof window.Deno` checks the global `Deno` rather than the `Deno` shim, so the code it guards only runs in Deno.\n",
+        "    at file:///mod.ts:2:1",
+      ),
+      concat!(
+        "`\"Deno\" in globalThis` checks the global `Deno` rather than the `Deno` shim, so the code it guards only runs in Deno.\n",
+        "    at file:///mod.ts:3:16",
+      ),
+    ]
+  );
+
+  let (mod_text, warnings) = get_result(DenoFeatureDetection::Shim).await;
+  assert_eq!(
+    mod_text,
+    concat!(
+      "import * as dntShim from \"./_dnt.shims.js\";\n",
+      "if (typeof dntShim.Deno !== \"undefined\") dntShim.Deno.exit();\n",
+      "typeof dntShim.dntGlobalThis.Deno;\n",
+      "const isDeno = \"Deno\" in dntShim.dntGlobalThis;\n",
+      "typeof dntShim.Deno.version;\n",
+      "type T = typeof dntShim.Deno;\n",
+    )
+  );
+  assert_eq!(
+    warnings,
+    vec![
+      concat!(
+        "`typeof Deno` checks the `Deno` shim, which is always defined, so the code it guards also runs in Node.js.\n",
+        "    at file:///mod.ts:1:5",
+      ),
+      concat!(
+        "`typeof window.Deno` checks the `Deno` shim, which is always defined, so the code it guards also runs in Node.js.\n",
+        "    at file:///mod.ts:2:1",
+      ),
+      concat!(
+        "`\"Deno\" in globalThis` checks the `Deno` shim, which is always defined, so the code it guards also runs in Node.js.\n",
+        "    at file:///mod.ts:3:16",
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_shim_import_style() {
+  let get_files = |import_style: ShimImportStyle| async move {
+    let result = TestBuilder::new()
+      .with_loader(|loader| {
+        loader.add_local_file(
+          "/mod.ts",
+          concat!(
+            "Deno.readTextFile();\n",
+            "setTimeout(() => {}, 0);\n",
+            "globalThis.Deno.cwd();\n",
+            "const dntGlobalThis = 1;\n",
+            "window.Deno.exit();\n",
+            "type T = typeof globalThis.Deno;\n",
+          ),
+        );
+      })
+      .add_default_shims()
+      .set_shim_import_style(import_style)
+      .transform()
+      .await
+      .unwrap();
+    let get_file_text = |path: &str| {
+      result
+        .main
+        .files
+        .iter()
+        .find(|f| f.file_path == PathBuf::from(path))
+        .unwrap()
+        .file_text
+        .clone()
+    };
+    (get_file_text("mod.ts"), get_file_text("_dnt.shims.ts"))
+  };
+
+  let (mod_text, shims_text) = get_files(ShimImportStyle::Named).await;
+  assert_eq!(
+    mod_text,
+    concat!(
+      "import { Deno, dntGlobalThis as dntGlobalThis1, setTimeout } from \"./_dnt.shims.js\";\n",
+      "Deno.readTextFile();\n",
+      "setTimeout(() => {}, 0);\n",
+      "dntGlobalThis1.Deno.cwd();\n",
+      "const dntGlobalThis = 1;\n",
+      "dntGlobalThis1.Deno.exit();\n",
+      "type T = typeof Deno;\n",
+    )
+  );
+  assert!(!shims_text.contains("Object.defineProperty(globalThis"));
+
+  let (mod_text, shims_text) = get_files(ShimImportStyle::SideEffect).await;
+  assert_eq!(
+    mod_text,
+    concat!(
+      "import \"./_dnt.shims.js\";\n",
+      "Deno.readTextFile();\n",
+      "setTimeout(() => {}, 0);\n",
+      "globalThis.Deno.cwd();\n",
+      "const dntGlobalThis = 1;\n",
+      "globalThis.Deno.exit();\n",
+      "type T = typeof Deno;\n",
+    )
+  );
+  assert!(shims_text.contains(concat!(
+    "for (const [name, value] of Object.entries(dntGlobals)) {\n",
+    "  Object.defineProperty(globalThis, name, { value, writable: true, configurable: true });\n",
+    "}\n",
+  )));
+}
+
+#[tokio::test]
+async fn transform_qualified_global_access() {
+  let get_mod_text = |qualified_global_access: QualifiedGlobalAccess,
+                      import_style: ShimImportStyle,
+                      text: &'static str| async move {
+    let result = TestBuilder::new()
+      .with_loader(|loader| {
+        loader.add_local_file("/mod.ts", text);
+      })
+      .add_default_shims()
+      .set_qualified_global_access(qualified_global_access)
+      .set_shim_import_style(import_style)
+      .transform()
+      .await
+      .unwrap();
+    result
+      .main
+      .files
+      .iter()
+      .find(|f| f.file_path == PathBuf::from("mod.ts"))
+      .unwrap()
+      .file_text
+      .clone()
+  };
+  let text = concat!(
+    "globalThis.Deno.readTextFile();\n",
+    "window.setTimeout(() => {}, 0);\n",
+    "self[\"Deno\"].cwd();\n",
+    "globalThis.Deno = 5;\n",
+    "globalThis.test();\n",
+  );
+
+  assert_eq!(
+    get_mod_text(
+      QualifiedGlobalAccess::GlobalObject,
+      ShimImportStyle::Namespace,
+      text
+    )
+    .await,
+    concat!(
+      "import * as dntShim from \"./_dnt.shims.js\";\n",
+      "dntShim.dntGlobalThis.Deno.readTextFile();\n",
+      "dntShim.dntGlobalThis.setTimeout(() => {}, 0);\n",
+      "dntShim.dntGlobalThis[\"Deno\"].cwd();\n",
+      "dntShim.dntGlobalThis.Deno = 5;\n",
+      "globalThis.test();\n",
+    )
+  );
+  assert_eq!(
+    get_mod_text(
+      QualifiedGlobalAccess::Shim,
+      ShimImportStyle::Namespace,
+      text
+    )
+    .await,
+    concat!(
+      "import * as dntShim from \"./_dnt.shims.js\";\n",
+      "dntShim.Deno.readTextFile();\n",
+      "dntShim.setTimeout(() => {}, 0);\n",
+      "dntShim.Deno.cwd();\n",
+      "dntShim.dntGlobalThis.Deno = 5;\n",
+      "globalThis.test();\n",
+    )
+  );
+  assert_eq!(
+    get_mod_text(
+      QualifiedGlobalAccess::Preserve,
+      ShimImportStyle::Namespace,
+      text
+    )
+    .await,
+    concat!(
+      "globalThis.Deno.readTextFile();\n",
+      "globalThis.setTimeout(() => {}, 0);\n",
+      "globalThis[\"Deno\"].cwd();\n",
+      "globalThis.Deno = 5;\n",
+      "globalThis.test();\n",
+    )
+  );
+  // the declared `Deno` would shadow the named import
+  assert_eq!(
+    get_mod_text(
+      QualifiedGlobalAccess::Shim,
+      ShimImportStyle::Named,
+      concat!(
+        "export function cwd(Deno: string) {\n",
+        "  return globalThis.Deno.cwd() + Deno;\n",
+        "}\n",
+        "globalThis.setTimeout(() => {}, 0);\n",
+      ),
+    )
+    .await,
+    concat!(
+      "import { dntGlobalThis, setTimeout } from \"./_dnt.shims.js\";\n",
+      "export function cwd(Deno: string) {\n",
+      "  return dntGlobalThis.Deno.cwd() + Deno;\n",
+      "}\n",
+      "setTimeout(() => {}, 0);\n",
+    )
+  );
+}
+
+#[tokio::test]
+async fn transform_window() {
+  assert_transforms(vec![
+    (
+      concat!("window.test = 5;", "window.Deno.test();",),
+      concat!(
+        r#"import * as dntShim from "./_dnt.shims.js";"#,
+        "\nglobalThis.test = 5;",
+        "dntShim.dntGlobalThis.Deno.test();",
+      ),
+    ),
+    (
+      // should be as-is because there's a declaration
+      "const window = {}; window.test;",
+      "const window = {}; window.test;",
+    ),
+  ])
+  .await;
+}
+
+#[tokio::test]
+async fn no_shim_situations() {
+  assert_identity_transforms(vec![
+    "const { Deno } = test; Deno.test;",
+    "const [ Deno ] = test; Deno.test;",
+    "const { asdf, ...Deno } = test;",
+    "const { Deno: test } = test;",
+    "const { test: Deno } = test;",
+    "const [Deno] = test;",
+    "const [test, ...Deno] = test;",
+    "const obj = { Deno: test };",
+    "interface Deno {} function test(d: Deno) {}",
+    "interface Test { Deno: string; }",
+    "interface Test { Deno(): string; }",
+    "class Deno {}",
+    "class Test { Deno: string; }",
+    "class Test { Deno() {} }",
+    "const t = class Deno {};",
+    "function Deno() {}",
+    "const t = function Deno() {};",
+    "import { Deno } from 'test';",
+    "import * as Deno from 'test';",
+    "import { test as Deno } from 'test';",
+    "import { Deno as test } from 'test';",
+    "export { Deno } from 'test';",
+    "export * as Deno from 'test';",
+    "export { test as Deno } from 'test';",
+    "export { Deno as test } from 'test';",
+    "try {} catch (Deno) {}",
+    "function test(Deno) {}",
+    "interface Response {} function test(r: Response) {}",
+    "enum Deno { A } Deno.A;",
+    "namespace Test { export const a = 1; } import Deno = Test; Deno.a;",
+  ])
+  .await;
+}
+
+#[tokio::test]
+async fn no_shim_nested_declarations() {
+  assert_transforms(vec![(
+    concat!(
+      "function param(Deno: any) { Deno.exit(); }\n",
+      "try {} catch (Deno) { Deno.exit(); }\n",
+      "function objectPattern({ Deno }: any) { Deno.exit(); }\n",
+      "function keyValuePattern({ a: Deno }: any) { Deno.exit(); }\n",
+      "function arrayPattern([Deno]: any[]) { Deno.exit(); }\n",
+      "function restPattern(...Deno: any[]) { Deno.length; }\n",
+      "function defaultPattern({ Deno = 1 }: any) { Deno; }\n",
+      "const classExpr = class Deno { m() { Deno; } };\n",
+      "const fnExpr = function Deno() { Deno; };\n",
+      "function nestedClass() { class Deno {} new Deno(); }\n",
+      "namespace Ns { const Deno = 1; Deno; }\n",
+      "const shorthand = { Deno, window };\n",
+      "Deno.exit();\n",
+    ),
+    concat!(
+      "import * as dntShim from \"./_dnt.shims.js\";\n",
+      "function param(Deno: any) { Deno.exit(); }\n",
+      "try {} catch (Deno) { Deno.exit(); }\n",
+      "function objectPattern({ Deno }: any) { Deno.exit(); }\n",
+      "function keyValuePattern({ a: Deno }: any) { Deno.exit(); }\n",
+      "function arrayPattern([Deno]: any[]) { Deno.exit(); }\n",
+      "function restPattern(...Deno: any[]) { Deno.length; }\n",
+      "function defaultPattern({ Deno = 1 }: any) { Deno; }\n",
+      "const classExpr = class Deno { m() { Deno; } };\n",
+      "const fnExpr = function Deno() { Deno; };\n",
+      "function nestedClass() { class Deno {} new Deno(); }\n",
+      "namespace Ns { const Deno = 1; Deno; }\n",
+      "const shorthand = { Deno: dntShim.Deno, window: dntShim.dntGlobalThis };\n",
+      "dntShim.Deno.exit();\n",
+    ),
+  )])
+  .await;
+}
+
+#[tokio::test]
+async fn transform_deno_collision() {
+  assert_transforms(vec![(
+    concat!(
+      "const Deno = {};",
+      "const { Deno: Deno2 } = globalThis;",
+      "Deno2.readTextFile();",
+      "Deno.test;"
+    ),
+    concat!(
+      r#"import * as dntShim from "./_dnt.shims.js";"#,
+      "\nconst Deno = {};",
+      "const { Deno: Deno2 } = dntShim.dntGlobalThis;",
+      "Deno2.readTextFile();",
+      "Deno.test;"
+    ),
+  )])
+  .await;
+}
+
+#[tokio::test]
+async fn transform_relative_file() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "import * as other from './other.ts';")
+        .add_local_file("/other.ts", "5;");
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      ("mod.ts", "import * as other from './other.js';"),
+      ("other.ts", "5;")
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_remote_files() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import * as other from 'http://localhost/mod.ts';\n",
+            "import 'https://deno.land/std@0.143.0/mod.ts';",
+          ),
+        )
+        .add_remote_file(
+          "http://localhost/mod.ts",
+          "import * as myOther from './other.ts';",
+        )
+        .add_remote_file(
+          "http://localhost/other.ts",
+          "import * as folder from './folder';",
+        )
+        .add_remote_file_with_headers(
+          "http://localhost/folder",
+          "import * as folder2 from './folder.ts';",
+          &[("content-type", "application/javascript")],
+        )
+        .add_remote_file(
+          "http://localhost/folder.ts",
+          "import * as folder3 from './folder.js';",
+        )
+        .add_remote_file(
+          "http://localhost/folder.js",
+          "import * as otherFolder from './otherFolder';",
+        )
+        .add_remote_file_with_headers(
+          "http://localhost/otherFolder",
+          "import * as subFolder from './sub/subfolder';",
+          &[("content-type", "application/javascript")],
+        )
+        .add_remote_file_with_headers(
+          "http://localhost/sub/subfolder",
+          "import * as localhost2 from 'http://localhost2';",
+          &[("content-type", "application/javascript")],
+        )
+        .add_remote_file(
+          "https://deno.land/std@0.143.0/mod.ts",
+          "console.log(5);",
+        )
+        .add_remote_file_with_headers(
+          "http://localhost2",
+          "import * as localhost3Mod from 'https://localhost3/mod.ts';",
+          &[("content-type", "application/javascript")],
+        )
+        .add_remote_file(
+          "https://localhost3/mod.ts",
+          "import * as localhost3 from 'https://localhost3';",
+        )
+        .add_remote_file_with_headers(
+          "https://localhost3",
+          "5;",
+          &[("content-type", "application/typescript; charset=UTF-8")],
+        );
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "import * as other from './deps/localhost/mod.js';\n",
+          "import './deps/deno.land/std@0.143.0/mod.js';",
+        )
+      ),
+      (
+        "deps/localhost/mod.ts",
+        "import * as myOther from './other.js';"
+      ),
+      (
+        "deps/localhost/other.ts",
+        "import * as folder from './folder.js';"
+      ),
+      (
+        "deps/localhost/folder.js",
+        "import * as folder2 from './folder_3.js';"
+      ),
+      (
+        "deps/localhost/folder_2.js",
+        "import * as otherFolder from './otherFolder.js';"
+      ),
+      (
+        "deps/localhost/folder_3.ts",
+        "import * as folder3 from './folder_2.js';"
+      ),
+      (
+        "deps/localhost/otherFolder.js",
+        "import * as subFolder from './sub/subfolder.js';"
+      ),
+      (
+        "deps/localhost/sub/subfolder.js",
+        "import * as localhost2 from '../../localhost2.js';"
+      ),
+      ("deps/deno.land/std@0.143.0/mod.ts", "console.log(5);"),
+      (
+        "deps/localhost2.js",
+        "import * as localhost3Mod from './localhost3/mod.js';"
+      ),
+      (
+        "deps/localhost3/mod.ts",
+        "import * as localhost3 from '../localhost3.js';"
+      ),
+      ("deps/localhost3.ts", "5;"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_encoded_specifiers() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import './my dir/a b.ts';\n",
+            "import './100%25.ts';\n",
+            "import 'https://deno.land/x/caf%C3%A9@1.0.0+build/a%2Fb.ts';\n",
+          ),
+        )
+        .add_local_file("/my dir/a b.ts", "export const a = 5;")
+        .add_local_file("/100%.ts", "export const b = 5;")
+        .add_remote_file(
+          "https://deno.land/x/caf%C3%A9@1.0.0+build/a%2Fb.ts",
+          "import './%E6%97%A5%E6%9C%AC.ts';",
+        )
+        .add_remote_file(
+          "https://deno.land/x/caf%C3%A9@1.0.0+build/%E6%97%A5%E6%9C%AC.ts",
+          "export const c = 5;",
+        );
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "import './my dir/a b.js';\n",
+          "import './100%25.js';\n",
+          "import './deps/deno.land/x/caf\u{e9}@1.0.0+build/a_b.js';\n",
+        )
+      ),
+      ("my dir/a b.ts", "export const a = 5;"),
+      ("100%.ts", "export const b = 5;"),
+      (
+        "deps/deno.land/x/caf\u{e9}@1.0.0+build/a_b.ts",
+        "import './\u{65e5}\u{672c}.js';"
+      ),
+      (
+        "deps/deno.land/x/caf\u{e9}@1.0.0+build/\u{65e5}\u{672c}.ts",
+        "export const c = 5;"
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_inline_remote_modules() {
+  let large_text = format!("export const large = '{}';", "a".repeat(100));
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import { add } from 'http://localhost/add.ts';\n",
+            "import { large } from 'http://localhost/large.ts';\n",
+            "import { shared } from 'http://localhost/shared.ts';\n",
+            "import { other } from './other.ts';\n",
+            "console.log(add(1, 2), large, shared, other);",
+          ),
+        )
+        .add_local_file(
+          "/other.ts",
+          "import { shared } from 'http://localhost/shared.ts';\nexport const other = shared;",
+        )
+        .add_remote_file(
+          "http://localhost/add.ts",
+          "export function add(a: number, b: number) { return a + b; }",
+        )
+        .add_remote_file("http://localhost/large.ts", &large_text)
+        .add_remote_file("http://localhost/shared.ts", "export const shared = 5;");
+    })
+    .set_inline_remote_module_max_size(100)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "// inlined from http://localhost/add.ts\n",
+          "function add(a: number, b: number) { return a + b; }\n",
+          "import { large } from './deps/localhost/large.js';\n",
+          "import { shared } from './deps/localhost/shared.js';\n",
+          "import { other } from './other.js';\n",
+          "console.log(add(1, 2), large, shared, other);",
+        )
+      ),
+      (
+        "other.ts",
+        "import { shared } from './deps/localhost/shared.js';\nexport const other = shared;"
+      ),
+      ("deps/localhost/large.ts", large_text.as_str()),
+      ("deps/localhost/shared.ts", "export const shared = 5;"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_third_party_licenses() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "// Copyright 2022 Me. MIT license.\n",
+            "import 'http://localhost/a.ts';\n",
+            "import 'http://localhost/b.ts';\n",
+          ),
+        )
+        .add_remote_file(
+          "http://localhost/a.ts",
+          "/*!\n * Copyright 2022 Someone.\n */\nDeno.cwd();",
+        )
+        .add_remote_file("http://localhost/b.ts", "// Some comment.\n5;");
+    })
+    .add_default_shims()
+    .set_third_party_licenses(true)
+    .transform()
+    .await
+    .unwrap();
+
+  let get_file_text = |file_path: &str| {
+    &result
+      .main
+      .files
+      .iter()
+      .find(|f| f.file_path == PathBuf::from(file_path))
+      .unwrap()
+      .file_text
+  };
+  assert_eq!(
+    get_file_text("deps/localhost/a.ts"),
+    concat!(
+      "/*!\n * Copyright 2022 Someone.\n */\n",
+      "import * as dntShim from \"../../_dnt.shims.js\";\n",
+      "\ndntShim.Deno.cwd();",
+    ),
+  );
+  assert_eq!(
+    get_file_text(THIRD_PARTY_LICENSES_FILE_NAME),
+    "http://localhost/a.ts\n\nCopyright 2022 Someone.\n",
+  );
+}
+
+#[tokio::test]
+async fn transform_provenance() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import 'http://localhost/a.ts';\n",
+            "import 'https://deno.land/x/pkg@1.2.0/sub/mod.ts';\n",
+            "import 'http://localhost/mapped.ts';\n",
+          ),
+        )
+        .add_remote_file(
+          "http://localhost/a.ts",
+          "// SPDX-License-Identifier: Apache-2.0\nexport const a = 5;",
+        )
+        .add_remote_file(
+          "https://deno.land/x/pkg@1.2.0/sub/mod.ts",
+          "export const b = 5;",
+        )
+        .add_remote_file(
+          "https://deno.land/x/pkg@1.2.0/LICENSE.md",
+          "MIT License\n\nCopyright (c) 2022 Someone\n\nPermission is hereby granted, free of charge, ...",
+        );
+    })
+    .add_package_specifier_mapping(
+      "http://localhost/mapped.ts",
+      "mapped",
+      Some("^0.1.0"),
+      None,
+    )
+    .set_provenance(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.provenance,
+    vec![
+      ModuleProvenance {
+        specifier: ModuleSpecifier::parse("http://localhost/a.ts").unwrap(),
+        mapped_package: None,
+        version: None,
+        license: Some("Apache-2.0".to_string()),
+        license_file: None,
+      },
+      ModuleProvenance {
+        specifier: ModuleSpecifier::parse("http://localhost/mapped.ts")
+          .unwrap(),
+        mapped_package: Some("mapped".to_string()),
+        version: Some("^0.1.0".to_string()),
+        license: None,
+        license_file: None,
+      },
+      ModuleProvenance {
+        specifier: ModuleSpecifier::parse(
+          "https://deno.land/x/pkg@1.2.0/sub/mod.ts"
+        )
+        .unwrap(),
+        mapped_package: None,
+        version: Some("1.2.0".to_string()),
+        license: Some("MIT".to_string()),
+        license_file: Some(
+          ModuleSpecifier::parse("https://deno.land/x/pkg@1.2.0/LICENSE.md")
+            .unwrap()
+        ),
+      },
+    ],
+  );
+}
+
+#[tokio::test]
+async fn transform_typescript_paths() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import 'https://deno.land/x/pkg@1.2.0/mod.ts';\n",
+            "import data from 'https://deno.land/x/pkg@1.2.0/data.json' assert { type: 'json' };\n",
+            "import 'http://localhost/mapped.ts';\n",
+          ),
+        )
+        .add_remote_file(
+          "https://deno.land/x/pkg@1.2.0/mod.ts",
+          "export const b = 5;",
+        )
+        .add_remote_file("https://deno.land/x/pkg@1.2.0/data.json", "{}");
+    })
+    .add_package_specifier_mapping(
+      "http://localhost/mapped.ts",
+      "mapped",
+      Some("^0.1.0"),
+      None,
+    )
+    .set_typescript_paths(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.typescript_paths,
+    BTreeMap::from([
+      (
+        "http://localhost/mapped.ts".to_string(),
+        vec!["./node_modules/mapped".to_string()],
+      ),
+      (
+        "https://deno.land/*".to_string(),
+        vec!["./deps/deno.land/*".to_string()],
+      ),
+      (
+        "https://deno.land/x/pkg@1.2.0/data.json".to_string(),
+        vec!["./deps/deno.land/x/pkg@1.2.0/data.js".to_string()],
+      ),
+    ]),
+  );
+}
+
+#[tokio::test]
+async fn transform_define() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        concat!(
+          "const target = Deno.env.get('BUILD_TARGET');\n",
+          "if (import.meta.env.MODE === \"production\") {}\n",
+          "console.log(__DEV__, { __DEV__ }, process.env[\"NODE_ENV\"], VERSION.toFixed());\n",
+          "function f(__DEV__: boolean): typeof VERSION { return __DEV__ ? 1 : 2; }\n",
+          "import.meta.env.MODE = \"other\";\n",
+        ),
+      );
+    })
+    .add_define("Deno.env.get(\"BUILD_TARGET\")", "\"node\"")
+    .add_define("import.meta.env.MODE", "'production'")
+    .add_define("__DEV__", "false")
+    .add_define("process.env.NODE_ENV", "\"production\"")
+    .add_define("VERSION", "1")
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "const target = \"node\";\n",
+        "if ('production' === \"production\") {}\n",
+        "console.log(false, { __DEV__: false }, \"production\", (1).toFixed());\n",
+        "function f(__DEV__: boolean): typeof VERSION { return __DEV__ ? 1 : 2; }\n",
+        "import.meta.env.MODE = \"other\";\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_define_invalid() {
+  let error_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file("/mod.ts", "");
+    })
+    .add_define("a + b", "true")
+    .add_define("MODE", "production")
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(
+    error_message.to_string(),
+    concat!(
+      "Invalid define value production for \"MODE\". It must be a string, number, boolean, null, or undefined literal (ex. `\"production\"` or `true`).\n",
+      "Invalid define expression \"a + b\". It must be a global, a property access, or a call with string arguments (ex. `import.meta.env.MODE` or `Deno.env.get(\"MODE\")`).",
+    )
+  );
+}
+
+#[tokio::test]
+async fn transform_deno_env_to_process_env() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        concat!(
+          "const mode = Deno.env.get(\"MODE\") ?? Deno.env.get(Deno.env.get(\"KEY\")!);\n",
+          "if (!Deno.env.has('DEBUG')) Deno.env.set(\"DEBUG\", \"1\");\n",
+          "Deno.env.delete(\"OTHER\");\n",
+          "const env = Deno.env.toObject();\n",
+          "const target = Deno.env.get(\"TARGET\");\n",
+        ),
+      );
+    })
+    .add_default_shims()
+    .add_define("Deno.env.get(\"TARGET\")", "\"node\"")
+    .set_deno_env_to_process_env(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "const mode = process.env[\"MODE\"] ?? process.env[process.env[\"KEY\"]!];\n",
+        "if (!(process.env['DEBUG'] !== undefined)) (process.env[\"DEBUG\"] = \"1\");\n",
+        "(void delete process.env[\"OTHER\"]);\n",
+        "const env = ({ ...process.env } as { [index: string]: string });\n",
+        "const target = \"node\";\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_translate_deno_apis() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        concat!(
+          "const dntFs = 5;\n",
+          "const text = await Deno.readTextFile(Deno.cwd() + \"/a.txt\");\n",
+          "await Deno.writeTextFile(\"b.txt\", text);\n",
+          "const info = await Deno.stat(\"b.txt\");\n",
+          "await Deno.writeTextFile(\"c.txt\", text, { append: true });\n",
+          "Deno.exit(info.isFile ? 0 : 1);\n",
+        ),
+      );
+    })
+    .add_default_shims()
+    .set_translate_deno_apis(true)
+    .transform()
+    .await
+    .unwrap();
+
+  let mod_file = result
+    .main
+    .files
+    .iter()
+    .find(|f| f.file_path == PathBuf::from("mod.ts"))
+    .unwrap();
+  assert_eq!(
+    mod_file.file_text,
+    concat!(
+      "import * as dntShim from \"./_dnt.shims.js\";\n",
+      "import * as dntFs1 from \"node:fs/promises\";\n",
+      "import * as dntProcess from \"node:process\";\n",
+      "const dntFs = 5;\n",
+      "const text = await dntFs1.readFile(dntProcess.cwd() + \"/a.txt\", \"utf8\");\n",
+      "await dntFs1.writeFile(\"b.txt\", text);\n",
+      "const info = await dntFs1.stat(\"b.txt\").then((stat) => ({ isFile: stat.isFile(), isDirectory: stat.isDirectory(), isSymlink: stat.isSymbolicLink(), size: stat.size, mtime: stat.mtime, atime: stat.atime, birthtime: stat.birthtime, dev: stat.dev, ino: stat.ino, mode: stat.mode, nlink: stat.nlink, uid: stat.uid, gid: stat.gid, rdev: stat.rdev, blksize: stat.blksize, blocks: stat.blocks }));\n",
+      "await dntShim.Deno.writeTextFile(\"c.txt\", text, { append: true });\n",
+      "dntProcess.exit(info.isFile ? 0 : 1);\n",
+    )
+  );
+}
+
+#[tokio::test]
+async fn transform_annotate_rewrites() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import { a } from 'https://localhost/mod.ts';\n",
+            "export * from './local.ts';\n",
+            "const b = await import('https://localhost/b.ts');\n",
+          ),
+        )
+        .add_local_file("/local.ts", "export const c = 5;")
+        .add_remote_file(
+          "https://localhost/mod.ts",
+          "export { b as a } from './b.ts';",
+        )
+        .add_remote_file("https://localhost/b.ts", "export const b = 5;");
+    })
+    .set_annotate_rewrites(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "import { a } from './deps/localhost/mod.js'; // was: https://localhost/mod.ts\n",
+          "export * from './local.js';\n",
+          "const b = await import('./deps/localhost/b.js' /* was: https://localhost/b.ts */);\n",
+        )
+      ),
+      ("local.ts", "export const c = 5;"),
+      (
+        "deps/localhost/mod.ts",
+        "export { b as a } from './b.js'; // was: https://localhost/b.ts"
+      ),
+      ("deps/localhost/b.ts", "export const b = 5;"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_hash_remote_file_names() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import { a } from 'https://localhost/mod.ts';\n",
+            "import { b } from 'https://localhost/other/mod.ts';\n",
+            "export * from './local.ts';\n",
+          ),
+        )
+        .add_local_file("/local.ts", "export const c = 5;")
+        .add_remote_file(
+          "https://localhost/mod.ts",
+          "export { b as a } from './b.ts';",
+        )
+        .add_remote_file("https://localhost/b.ts", "export const b = 5;")
+        .add_remote_file(
+          "https://localhost/other/mod.ts",
+          "export const b = 6;",
+        );
+    })
+    .set_hash_remote_file_names(true)
+    .transform()
+    .await
+    .unwrap();
+
+  // local files keep their names
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "import { a } from './deps/localhost/mod.70bfb381.js';\n",
+          "import { b } from './deps/localhost/other/mod.5846bc3e.js';\n",
+          "export * from './local.js';\n",
+        )
+      ),
+      ("local.ts", "export const c = 5;"),
+      (
+        "deps/localhost/mod.70bfb381.ts",
+        "export { b as a } from './b.f270c5a9.js';"
+      ),
+      ("deps/localhost/b.f270c5a9.ts", "export const b = 5;"),
+      (
+        "deps/localhost/other/mod.5846bc3e.ts",
+        "export const b = 6;"
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_relative_specifier_extension() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import './a.ts';\n",
+            "export * from './b.js';\n",
+            "Deno.readTextFile('file.txt');\n",
+          ),
+        )
+        .add_local_file("/a.ts", "export const a = 5;")
+        .add_local_file("/b.js", "export const b = 5;")
+        .add_local_file("/mod.test.ts", "import './a.ts';");
+    })
+    .add_default_shims()
+    .add_test_entry_point("file:///mod.test.ts")
+    .set_relative_specifier_extension(RelativeSpecifierExtension::Preserve)
+    .set_test_relative_specifier_extension(RelativeSpecifierExtension::Strip)
+    .transform()
+    .await
+    .unwrap();
+
+  let get_file_text = |files: &[OutputFile], path: &str| {
+    files
+      .iter()
+      .find(|f| f.file_path == PathBuf::from(path))
+      .unwrap()
+      .file_text
+      .clone()
+  };
+  assert_eq!(
+    get_file_text(&result.main.files, "mod.ts"),
+    concat!(
+      "import * as dntShim from \"./_dnt.shims.ts\";\n",
+      "import './a.ts';\n",
+      "export * from './b.js';\n",
+      "dntShim.Deno.readTextFile('file.txt');\n",
+    )
+  );
+  assert_eq!(
+    get_file_text(&result.test.files, "mod.test.ts"),
+    "import './a';"
+  );
+}
+
+#[tokio::test]
+async fn transform_bundle() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import { add } from './math.ts';\n",
+            "import * as utils from './utils.ts';\n",
+            "export { sub } from './math.ts';\n",
+            "export const value = add(1, utils.double(2));\n",
+          ),
+        )
+        .add_local_file(
+          "/math.ts",
+          concat!(
+            "const value = 1;\n",
+            "export function add(a: number, b: number) {\n",
+            "  return a + b + value;\n",
+            "}\n",
+            "export function sub(a: number, b: number) {\n",
+            "  return a - b;\n",
+            "}\n",
+          ),
+        )
+        .add_local_file(
+          "/utils.ts",
+          concat!(
+            "export const double = (value: number) => value * 2;\n",
+            "export default 'utils';\n",
+          ),
+        );
+    })
+    .set_bundle(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "const value$1 = 1;\n",
+        "function add(a: number, b: number) {\n",
+        "  return a + b + value$1;\n",
+        "}\n",
+        "function sub(a: number, b: number) {\n",
+        "  return a - b;\n",
+        "}\n",
+        "const double = (value: number) => value * 2;\n",
+        "const _default = 'utils';\n",
+        "const value = add(1, double(2));\n",
+        "export { sub, value };\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_bundle_mts_module() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import { value } from './other.mts';\nexport const doubled = value * 2;\n",
+        )
+        .add_local_file("/other.mts", "export const value = 5;\n");
+    })
+    .set_bundle(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "const value = 5;\n",
+        "const doubled = value * 2;\n",
+        "export { doubled };\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_bundle_test_imports_main_modules() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import { double } from './utils.ts';\n",
+            "export const value = double(2);\n",
+          ),
+        )
+        .add_local_file(
+          "/utils.ts",
+          "import { one } from './one.ts';\nexport const double = (n: number) => n * 2 * one;\n",
+        )
+        .add_local_file("/one.ts", "export const one = 1;\n")
+        .add_local_file(
+          "/mod.test.ts",
+          concat!(
+            "import { value } from './mod.ts';\n",
+            "import { double } from './utils.ts';\n",
+            "console.log(double(value));\n",
+          ),
+        );
+    })
+    .add_test_entry_point("file:///mod.test.ts")
+    .set_bundle(true)
+    .transform()
+    .await
+    .unwrap();
+
+  // the test imports utils.ts, so it's kept along with what it imports
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "const one = 1;\n",
+          "const double = (n: number) => n * 2 * one;\n",
+          "const value = double(2);\n",
+          "export { value };\n",
+        )
+      ),
+      ("one.ts", "export const one = 1;\n"),
+      (
+        "utils.ts",
+        "import { one } from './one.js';\nexport const double = (n: number) => n * 2 * one;\n"
+      ),
+    ]
+  );
+  assert_files!(
+    result.test.files,
+    &[(
+      "mod.test.ts",
+      concat!(
+        "import { value } from './mod.js';\n",
+        "import { double } from './utils.js';\n",
+        "console.log(double(value));\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_bundle_multiple_entry_points() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import { shared } from './shared.ts';\nexport const a = shared + 1;\n",
+        )
+        .add_local_file(
+          "/other.ts",
+          "import { shared } from './shared.ts';\nexport const b = shared + 2;\n",
+        )
+        .add_local_file("/shared.ts", "export const shared = 1;\n");
+    })
+    .add_entry_point("file:///other.ts")
+    .set_bundle(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        "const shared = 1;\nconst a = shared + 1;\nexport { a };\n"
+      ),
+      (
+        "other.ts",
+        "const shared = 1;\nconst b = shared + 2;\nexport { b };\n"
+      ),
+    ]
+  );
+  assert_eq!(
+    result.main.entry_points,
+    &[PathBuf::from("mod.ts"), PathBuf::from("other.ts")]
+  );
+}
+
+#[tokio::test]
+async fn transform_bundle_star_exports() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "export * from './a.ts';\nexport * as b from './b.ts';\n",
+        )
+        .add_local_file(
+          "/a.ts",
+          "export const a = 1;\nexport function getA() { return a; }\n",
+        )
+        .add_local_file(
+          "/b.ts",
+          "export const value = 2;\nexport default value;\n",
+        );
+    })
+    .set_bundle(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "const a = 1;\n",
+        "function getA() { return a; }\n",
+        "const value = 2;\n",
+        "const _default = value;\n",
+        "const b$1 = Object.freeze({\n",
+        "  __proto__: null,\n",
+        "  get default() { return _default; },\n",
+        "  get value() { return value; },\n",
+        "});\n",
+        "export { a, b$1 as b, getA };\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_bundle_circular_imports() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import { isEven } from './even.ts';\nexport const result = isEven(4);\n",
+        )
+        .add_local_file(
+          "/even.ts",
+          concat!(
+            "import { isOdd } from './odd.ts';\n",
+            "export function isEven(n: number): boolean { return n === 0 || isOdd(n - 1); }\n",
+          ),
+        )
+        .add_local_file(
+          "/odd.ts",
+          concat!(
+            "import { isEven } from './even.ts';\n",
+            "export function isOdd(n: number): boolean { return n !== 0 && isEven(n - 1); }\n",
+          ),
+        );
+    })
+    .set_bundle(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "function isOdd(n: number): boolean { return n !== 0 && isEven(n - 1); }\n",
+        "function isEven(n: number): boolean { return n === 0 || isOdd(n - 1); }\n",
+        "const result = isEven(4);\n",
+        "export { result };\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_bundle_circular_imports_anonymous_default_export() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "export { default as a } from './a.ts';\nexport { value } from './b.ts';\n",
+        )
+        .add_local_file(
+          "/a.ts",
+          concat!(
+            "import { value } from './b.ts';\n",
+            "export default function () { return 'a'; }\n",
+            "export function getValue() { return value; }\n",
+          ),
+        )
+        .add_local_file(
+          "/b.ts",
+          "import getA from './a.ts';\nexport const value = getA();\n",
+        );
+    })
+    .set_bundle(true)
+    .transform()
+    .await
+    .unwrap();
+
+  // the function is called before its definition, so it must be hoisted
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "const value = _default();\n",
+        "function _default() { return 'a'; }\n",
+        "function getValue() { return value; }\n",
+        "export { _default as a, value };\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_bundle_default_and_namespace_re_exports() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "export { default } from './a.ts';\n",
+            "export { default as b, value } from './b.ts';\n",
+            "import * as c from './c.ts';\n",
+            "export { c };\n",
+          ),
+        )
+        .add_local_file("/a.ts", "export default function () { return 'a'; }\n")
+        .add_local_file(
+          "/b.ts",
+          "export const value = 2;\nexport default class B {}\n",
+        )
+        .add_local_file("/c.ts", "export const c = 3;\n");
+    })
+    .set_bundle(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "function _default() { return 'a'; }\n",
+        "const value = 2;\n",
+        "class B {}\n",
+        "const c = 3;\n",
+        "const c$1 = Object.freeze({\n",
+        "  __proto__: null,\n",
+        "  get c() { return c; },\n",
+        "});\n",
+        "export { B as b, c$1 as c, _default as default, value };\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_bundle_name_collisions() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import { value as otherValue } from './other.ts';\n",
+            "const value = 1;\n",
+            "export const total = value + otherValue + Math.max(1, 2);\n",
+          ),
+        )
+        .add_local_file(
+          "/other.ts",
+          "const Math = { max: () => 0 };\nexport const value = Math.max();\n",
+        );
+    })
+    .set_bundle(true)
+    .transform()
+    .await
+    .unwrap();
+
+  // the entry point's names are kept and the other module's names are
+  // changed when they conflict with them or with a global
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "const Math$1 = { max: () => 0 };\n",
+        "const value$1 = Math$1.max();\n",
+        "const value = 1;\n",
+        "const total = value + value$1 + Math.max(1, 2);\n",
+        "export { total };\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_bundle_shims() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import { cwd } from './other.ts';\nexport const dir = cwd();",
+        )
+        .add_local_file(
+          "/other.ts",
+          "export function cwd() { return Deno.cwd(); }",
+        );
+    })
+    .add_default_shims()
+    .set_bundle(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result
+      .main
+      .files
+      .iter()
+      .map(|f| f.file_path.clone())
+      .collect::<Vec<_>>(),
+    vec![PathBuf::from("mod.ts")]
+  );
+  let file_text = &result.main.files[0].file_text;
+  assert!(file_text.contains("from \"@deno/shim-deno\";"));
+  assert!(!file_text.contains("dntShim"));
+  assert!(file_text.contains("export { dir };"));
+}
+
+#[tokio::test]
+async fn transform_bundle_dynamic_import_error() {
+  let err_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "await import('./other.ts');")
+        .add_local_file("/other.ts", "export const value = 5;");
+    })
+    .set_bundle(true)
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(
+    err_message.to_string(),
+    "Could not bundle mod.ts because it dynamically imports ./other.js, which is in the bundle.",
+  );
+}
+
+#[tokio::test]
+async fn transform_minify() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "// adds one\nexport { add } from './add.js';\n",
+        )
+        .add_local_file(
+          "/add.js",
+          concat!(
+            "// adds the values\n",
+            "export function add(first, second) {\n",
+            "  return first + second;\n",
+            "}\n",
+          ),
+        );
+    })
+    .set_minify(true)
+    .transform()
+    .await
+    .unwrap();
+
+  let get_file_text = |path: &str| {
+    result
+      .main
+      .files
+      .iter()
+      .find(|f| f.file_path == PathBuf::from(path))
+      .unwrap()
+      .file_text
+      .clone()
+  };
+  // the typescript files are left as is
+  assert_eq!(
+    get_file_text("mod.ts"),
+    "// adds one\nexport { add } from './add.js';\n"
+  );
+  let js_text = get_file_text("add.js");
+  assert!(js_text.starts_with("export function add("));
+  assert!(!js_text.contains('\n'));
+  assert!(!js_text.contains("adds the values"));
+  assert!(!js_text.contains("first"));
+}
+
+#[tokio::test]
+async fn transform_flatten_barrels() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "export * from './sub/mod.ts';\nexport const version = 1;\n",
+        )
+        .add_local_file(
+          "/sub/mod.ts",
+          concat!(
+            "export * from './a.ts';\n",
+            "export { b, type B } from './b.ts';\n",
+            "export * from './nested/mod.ts';\n",
+          ),
+        )
+        .add_local_file("/sub/a.ts", "export const a = 1;\n")
+        .add_local_file(
+          "/sub/b.ts",
+          "export const b = 2;\nexport interface B {}\n",
+        )
+        .add_local_file("/sub/nested/mod.ts", "export * from './c.ts';\n")
+        .add_local_file("/sub/nested/c.ts", "export const c = 3;\n");
+    })
+    .set_flatten_barrels(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "export { b, type B } from './sub/b.js';\n",
+          "export * from './sub/a.js';\n",
+          "export * from './sub/nested/c.js';\n",
+          "export const version = 1;\n",
+        )
+      ),
+      ("sub/a.ts", "export const a = 1;\n"),
+      ("sub/b.ts", "export const b = 2;\nexport interface B {}\n"),
+      (
+        "sub/mod.ts",
+        concat!(
+          "export * from './a.js';\n",
+          "export { b, type B } from './b.js';\n",
+          "export * from './nested/c.js';\n",
+        )
+      ),
+      ("sub/nested/c.ts", "export const c = 3;\n"),
+      ("sub/nested/mod.ts", "export * from './c.js';\n"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_flatten_barrels_keeps_ambiguous_exports() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "export * from './barrel.ts';\nexport * from './other.ts';\n",
+        )
+        .add_local_file("/barrel.ts", "export { value } from './a.ts';\n")
+        .add_local_file("/a.ts", "export const value = 1;\n")
+        .add_local_file("/other.ts", "export const value = 2;\n");
+    })
+    .set_flatten_barrels(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      ("a.ts", "export const value = 1;\n"),
+      ("barrel.ts", "export { value } from './a.js';\n"),
+      (
+        "mod.ts",
+        "export * from './barrel.js';\nexport * from './other.js';\n"
+      ),
+      ("other.ts", "export const value = 2;\n"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_star_export_conflicts() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "export * from './a.ts';\nexport * from './b.ts';\n",
+        )
+        .add_local_file(
+          "/a.ts",
+          "export const value = 1;\nexport * from './c.ts';\n",
+        )
+        .add_local_file(
+          "/b.ts",
+          "export const value = 2;\nexport * from './c.ts';\n",
+        )
+        .add_local_file("/c.ts", "export const shared = 3;\n");
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.warnings,
+    vec![concat!(
+      "mod.ts exports `value` from more than one `export *` (./a.js, ./b.js), ",
+      "which declare it in a.ts and b.ts. The name is ambiguous, so it isn't ",
+      "exported and bundlers handle it differently. Export it explicitly from ",
+      "the module it should come from.",
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_entry_point_exports() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import type { Options } from './types.ts';\n",
+            "export * from './a.ts';\n",
+            "export * from './b.ts';\n",
+            "export { type Shape, area } from './shape.ts';\n",
+            "export type { Options };\n",
+            "export interface Config {}\n",
+            "export class Config {}\n",
+            "export type Id = string;\n",
+            "export default function main() {}\n",
+          ),
+        )
+        .add_local_file(
+          "/a.ts",
+          "export const value = 1;\nexport interface A {}\n",
+        )
+        .add_local_file("/b.ts", "export const value = 2;\n")
+        .add_local_file(
+          "/shape.ts",
+          "export interface Shape {}\nexport function area() {}\n",
+        )
+        .add_local_file("/types.ts", "export interface Options {}\n");
+    })
+    .set_entry_point_exports(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.main.entry_point_exports,
+    vec![EntryPointExports {
+      file_path: PathBuf::from("mod.ts"),
+      values: vec![
+        "Config".to_string(),
+        "area".to_string(),
+        "default".to_string(),
+      ],
+      types: vec![
+        "A".to_string(),
+        "Id".to_string(),
+        "Options".to_string(),
+        "Shape".to_string(),
+      ],
+    }]
+  );
+}
+
+#[tokio::test]
+async fn transform_declarations_only() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import { helper } from './helper.ts';\n",
+            "\n",
+            "/** Adds two numbers. */\n",
+            "export function add(a: number, b = 1): number {\n",
+            "  return helper(a) + b;\n",
+            "}\n",
+            "\n",
+            "export async function load(url: string) {\n",
+            "  await fetch(url);\n",
+            "}\n",
+            "\n",
+            "export function double(value: number) {\n",
+            "  return value * 2;\n",
+            "}\n",
+            "\n",
+            "export const version = '1.0.0';\n",
+            "export let count = 0;\n",
+            "const internal = helper(5);\n",
+            "\n",
+            "export class Counter {\n",
+            "  #value = 0;\n",
+            "  static instances: number = 0;\n",
+            "  constructor(private readonly step: number) {\n",
+            "    Counter.instances++;\n",
+            "  }\n",
+            "  get value(): number {\n",
+            "    return this.#value;\n",
+            "  }\n",
+            "  increment() {\n",
+            "    this.#value += this.step;\n",
+            "  }\n",
+            "}\n",
+            "\n",
+            "console.log(internal);\n",
+          ),
+        )
+        .add_local_file(
+          "/helper.ts",
+          concat!(
+            "export function helper(value: number): number {\n",
+            "  return value * 2;\n",
+            "}\n",
+          ),
+        );
+    })
+    .set_declarations_only(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.warnings,
+    vec![
+      "Declared the return type of `double` as `any` because its type can't be determined without its value. Add a type annotation to keep its type.\n    at file:///mod.ts:12:17",
+      "Declared `internal` as `any` because its type can't be determined without its value. Add a type annotation to keep its type.\n    at file:///mod.ts:18:7",
+    ]
+  );
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "helper.ts",
+        "export declare function helper(value: number): number;\n"
+      ),
+      (
+        "mod.ts",
+        concat!(
+          "import { helper } from './helper.js';\n",
+          "\n",
+          "/** Adds two numbers. */\n",
+          "export declare function add(a: number, b?: number): number;\n",
+          "\n",
+          "export declare function load(url: string): Promise<void>;\n",
+          "\n",
+          "export declare function double(value: number): any;\n",
+          "\n",
+          "export declare const version = '1.0.0';\n",
+          "export declare let count: number;\n",
+          "declare const internal: any;\n",
+          "\n",
+          "export declare class Counter {\n",
+          "  static instances: number;\n",
+          "  private readonly step: number;\n",
+          "  constructor(step: number);\n",
+          "  get value(): number;\n",
+          "  increment(): void;\n",
+          "}\n",
+          "\n",
+        )
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_declarations_only_overloads_and_namespaces() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        concat!(
+          "export function parse(value: string): number;\n",
+          "export function parse(value: number): number;\n",
+          "export function parse(value: string | number) {\n",
+          "  return Number(value);\n",
+          "}\n",
+          "\n",
+          "export enum Color {\n",
+          "  Red = 'red',\n",
+          "}\n",
+          "\n",
+          "export namespace Utils {\n",
+          "  export const enabled: boolean = true;\n",
+          "  export function check(): boolean {\n",
+          "    return enabled;\n",
+          "  }\n",
+          "}\n",
+          "\n",
+          "export default class {\n",
+          "  name = 'test';\n",
+          "}\n",
+        ),
+      );
+    })
+    .set_declarations_only(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert!(result.warnings.is_empty());
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "export declare function parse(value: string): number;\n",
+        "export declare function parse(value: number): number;\n",
+        "\n",
+        "export declare enum Color {\n",
+        "  Red = 'red',\n",
+        "}\n",
+        "\n",
+        "export declare namespace Utils {\n",
+        "  export const enabled: boolean;\n",
+        "  export function check(): boolean;\n",
+        "}\n",
+        "\n",
+        "declare class _default {\n",
+        "  name: string;\n",
+        "}\n",
+        "export default _default;\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_handle_local_deps_folder() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import 'http://localhost/mod.ts';\nimport './deps/localhost/mod.ts'",
+        )
+        .add_local_file("/deps/localhost/mod.ts", "local;")
+        .add_remote_file("http://localhost/mod.ts", "remote;");
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        "import './deps_2/localhost/mod.js';\nimport './deps/localhost/mod.js'"
+      ),
+      ("deps/localhost/mod.ts", "local;"),
+      ("deps_2/localhost/mod.ts", "remote;"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_local_file_not_exists() {
+  let err_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file("/mod.ts", "import * as other from './other.ts';");
+    })
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(
+    err_message.to_string(),
+    r#"Module not found "file:///other.ts"."#
+  );
+}
+
+#[tokio::test]
+async fn transform_structured_errors() {
+  let err = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file("/mod.ts", "import './other.ts';");
+    })
+    .transform()
+    .await
+    .err()
+    .unwrap();
+  match err.downcast_ref::<TransformError>().unwrap() {
+    TransformError::Load {
+      specifier,
+      referrer,
+      ..
+    } => {
+      assert_eq!(specifier.as_str(), "file:///other.ts");
+      assert_eq!(referrer.as_ref().unwrap().as_str(), "file:///mod.ts");
+    }
+    err => panic!("Unexpected error: {:?}", err),
+  }
+
+  let err = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file("/mod.ts", "\nconst a = ;");
+    })
+    .transform()
+    .await
+    .err()
+    .unwrap();
+  match err.downcast_ref::<TransformError>().unwrap() {
+    TransformError::Parse {
+      specifier,
+      line_number,
+      ..
+    } => {
+      assert_eq!(specifier.as_str(), "file:///mod.ts");
+      assert_eq!(*line_number, 2);
+    }
+    err => panic!("Unexpected error: {:?}", err),
+  }
+}
+
+#[tokio::test]
+async fn transform_continue_on_parse_error() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "import './a.ts';\nimport './b.ts';")
+        .add_local_file("/a.ts", "export class A {}")
+        .add_local_file("/b.ts", "\nconst b = ;");
+    })
+    .set_continue_on_parse_error(true)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      ("a.ts", "export class A {}"),
+      ("mod.ts", "import './a.js';\nimport './b.js';"),
+    ]
+  );
+  assert_eq!(result.parse_errors.len(), 1);
+  let parse_error = &result.parse_errors[0];
+  assert_eq!(parse_error.specifier.as_str(), "file:///b.ts");
+  assert_eq!(parse_error.line_number, 2);
+  assert_eq!(
+    parse_error.dependents,
+    vec![ModuleSpecifier::parse("file:///mod.ts").unwrap()]
+  );
+}
+
+#[tokio::test]
+async fn transform_preserves_line_endings_and_bom() {
+  let file_text = concat!(
+    "\u{FEFF}// copyright comment\r\n",
+    "Deno.readTextFile();\r\n",
+    "const a = `1\r\n2`;\r\n",
+  );
+  let get_mod_text = |normalize_output_text: bool| async move {
+    let result = TestBuilder::new()
+      .with_loader(|loader| {
+        loader.add_local_file("/mod.ts", file_text);
+      })
+      .add_default_shims()
+      .set_normalize_output_text(normalize_output_text)
+      .transform()
+      .await
+      .unwrap();
+    result
+      .main
+      .files
+      .into_iter()
+      .find(|f| f.file_path == PathBuf::from("mod.ts"))
+      .unwrap()
+      .file_text
+  };
+
+  assert_eq!(
+    get_mod_text(false).await,
+    concat!(
+      "\u{FEFF}// copyright comment\r\n",
+      r#"import * as dntShim from "./_dnt.shims.js";"#,
+      "\r\n\r\ndntShim.Deno.readTextFile();\r\n",
+      "const a = `1\r\n2`;\r\n",
+    )
+  );
+  assert_eq!(
+    get_mod_text(true).await,
+    concat!(
+      "// copyright comment\n",
+      r#"import * as dntShim from "./_dnt.shims.js";"#,
+      "\n\ndntShim.Deno.readTextFile();\n",
+      "const a = `1\n2`;\n",
+    )
+  );
+}
+
+#[tokio::test]
+async fn transform_output_is_deterministic() {
+  let mut test_builder = TestBuilder::new();
+  test_builder
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import './z.ts';\n",
+            "import './b/a.ts';\n",
+            "import 'http://localhost/mod.ts';\n",
+            "import './a.ts';\n",
+          ),
+        )
+        .add_local_file("/z.ts", "Deno.cwd();")
+        .add_local_file("/b/a.ts", "import './c.ts';")
+        .add_local_file("/b/c.ts", "setTimeout(() => {}, 0);")
+        .add_local_file("/a.ts", "export {};")
+        .add_local_file("/mod.test.ts", "import './mod.ts';\nDeno.test;")
+        .add_remote_file("http://localhost/mod.ts", "export class A {}");
+    })
+    .add_default_shims()
+    .add_test_entry_point("file:///mod.test.ts");
+
+  let output = test_builder.transform().await.unwrap();
+  for environment in [&output.main, &output.test] {
+    let file_paths = environment
+      .files
+      .iter()
+      .map(|f| f.file_path.clone())
+      .collect::<Vec<_>>();
+    let mut sorted_file_paths = file_paths.clone();
+    sorted_file_paths.sort();
+    assert_eq!(file_paths, sorted_file_paths);
+    let dependency_names = environment
+      .dependencies
+      .iter()
+      .map(|d| d.name.clone())
+      .collect::<Vec<_>>();
+    let mut sorted_dependency_names = dependency_names.clone();
+    sorted_dependency_names.sort();
+    assert_eq!(dependency_names, sorted_dependency_names);
+  }
+  assert_eq!(output.main.dependencies.len(), 2);
+
+  for _ in 0..5 {
+    assert_eq!(test_builder.transform().await.unwrap(), output);
+  }
+}
+
+#[tokio::test]
+async fn transform_exports() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "export * from './sub/mod.ts';")
+        .add_local_file("/sub/mod.ts", "export class A {}")
+        .add_local_file("/other.ts", "export class B {}");
+    })
+    .add_entry_point("file:///sub/mod.ts")
+    .add_entry_point("file:///other.ts")
+    .transform()
+    .await
+    .unwrap();
+
+  let export = |path: &str| PackageJsonExport {
+    import: PackageJsonExportTarget {
+      types: format!("./types/{}.d.ts", path),
+      default: format!("./esm/{}.js", path),
+    },
+    require: PackageJsonExportTarget {
+      types: format!("./types/{}.d.ts", path),
+      default: format!("./script/{}.js", path),
+    },
+  };
+  assert_eq!(
+    result.exports,
+    BTreeMap::from([
+      (".".to_string(), export("mod")),
+      ("./sub".to_string(), export("sub/mod")),
+      ("./other".to_string(), export("other")),
+    ])
+  );
+}
+
+#[tokio::test]
+async fn transform_invalid_entry_points() {
+  let err_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file("/mod.ts", "");
+    })
+    .add_entry_point("file:///other.ts")
+    .add_entry_point("file:///dir/")
+    .add_test_entry_point("file:///mod.ts")
+    .transform()
+    .await
+    .err()
+    .unwrap();
+  let file_path = |path: &str| {
+    url_to_file_path(&ModuleSpecifier::parse(path).unwrap())
+      .unwrap()
+      .display()
+      .to_string()
+  };
+  assert_eq!(
+    err_message.to_string(),
+    format!(
+      concat!(
+        "Entry point file:///other.ts was not found. Tried to load {}\n",
+        "Entry point file:///dir/ was a directory ({}). Specify a file instead.\n",
+        "Test entry point file:///mod.ts was specified more than once.",
+      ),
+      file_path("file:///other.ts"),
+      file_path("file:///dir/"),
+    )
+  );
+}
+
+#[tokio::test]
+async fn transform_invalid_package_mappings() {
+  let err = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file("/mod.ts", "");
+    })
+    .add_package_specifier_mapping(
+      "http://localhost/a.ts",
+      "@scope/Name",
+      Some("^1.0.0"),
+      None,
+    )
+    .add_package_specifier_mapping(
+      "http://localhost/b.ts",
+      "name/sub",
+      Some("1.0.0 ||"),
+      None,
+    )
+    .add_package_specifier_mapping(
+      "http://localhost/c.ts",
+      "@scope/name",
+      Some("~1.0.0"),
+      Some("sub/path.js"),
+    )
+    .add_package_specifier_mapping(
+      "http://localhost/d.ts",
+      "node:fs",
+      None,
+      None,
+    )
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert!(matches!(
+    err.downcast_ref::<TransformError>(),
+    Some(TransformError::InvalidOptions { .. })
+  ));
+  assert_eq!(
+    err.to_string(),
+    concat!(
+      "Invalid package name \"@scope/Name\" in the mapping for http://localhost/a.ts. Package name must be lowercase.\n",
+      "Invalid package name \"name/sub\" in the mapping for http://localhost/b.ts. ",
+      "Package name must not contain a path. Specify the path within the package as the sub path instead.\n",
+      "Invalid version \"1.0.0 ||\" of package name/sub in the mapping for http://localhost/b.ts. ",
+      "Version range must not have an empty part.",
+    )
+  );
+}
+
+#[tokio::test]
+async fn transform_jsx_import_source() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.tsx",
+          "import './other.tsx';\nexport const a = <div />;",
+        )
+        .add_local_file(
+          "/other.tsx",
+          "/** @jsxImportSource http://localhost/jsx */\nexport const b = <div />;",
+        )
+        .add_remote_file_with_headers(
+          "http://localhost/jsx/jsx-runtime",
+          "export function jsx() {}",
+          &[("content-type", "application/typescript")],
+        );
+    })
+    .entry_point("file:///mod.tsx")
+    .set_jsx(JsxRuntime::Automatic, Some("https://esm.sh/preact"))
+    .add_package_specifier_mapping(
+      "https://esm.sh/preact/jsx-runtime",
+      "preact",
+      Some("^10.0.0"),
+      Some("jsx-runtime"),
+    )
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.tsx",
+        concat!(
+          "/** @jsxImportSource preact */\n",
+          "import './other.js';\nexport const a = <div />;",
+        )
+      ),
+      (
+        "other.tsx",
+        "/** @jsxImportSource ./deps/localhost/jsx */\nexport const b = <div />;"
+      ),
+      (
+        "deps/localhost/jsx/jsx-runtime.ts",
+        "export function jsx() {}"
+      ),
+    ]
+  );
+  assert_eq!(
+    result.main.dependencies,
+    &[Dependency {
+      name: "preact".to_string(),
+      version: "^10.0.0".to_string(),
+      peer_dependency: false,
+    }]
+  );
+}
+
+#[tokio::test]
+async fn transform_workspace_members() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/a/mod.ts", "import './util.ts';\nexport const a = 1;")
+        .add_local_file("/a/util.ts", "export const util = 1;")
+        .add_local_file("/a/sub/mod.ts", "export const sub = 1;")
+        .add_local_file(
+          "/b/mod.ts",
+          "import '../a/mod.ts';\nimport '../a/sub/mod.ts';",
+        )
+        .add_local_file(
+          "/b/mod.test.ts",
+          "import '../a/mod.ts';\nimport './mod.ts';",
+        )
+        .add_local_file("/c/mod.ts", "import '../a/mod.ts';")
+        .add_local_file("/c/mod.test.ts", "import '../b/mod.ts';");
+    })
+    .transform_workspace(vec![
+      WorkspaceMember {
+        name: "@scope/a".to_string(),
+        version: "^1.0.0".to_string(),
+        root: ModuleSpecifier::parse("file:///a/").unwrap(),
+        entry_points: vec![
+          ModuleSpecifier::parse("file:///a/mod.ts").unwrap(),
+          ModuleSpecifier::parse("file:///a/sub/mod.ts").unwrap(),
+        ],
+        test_entry_points: Vec::new(),
+      },
+      WorkspaceMember {
+        name: "@scope/b".to_string(),
+        version: "^2.0.0".to_string(),
+        root: ModuleSpecifier::parse("file:///b/").unwrap(),
+        entry_points: vec![ModuleSpecifier::parse("file:///b/mod.ts").unwrap()],
+        test_entry_points: vec![ModuleSpecifier::parse(
+          "file:///b/mod.test.ts",
+        )
+        .unwrap()],
+      },
+      WorkspaceMember {
+        name: "@scope/c".to_string(),
+        version: "^3.0.0".to_string(),
+        root: ModuleSpecifier::parse("file:///c/").unwrap(),
+        entry_points: vec![ModuleSpecifier::parse("file:///c/mod.ts").unwrap()],
+        test_entry_points: vec![ModuleSpecifier::parse(
+          "file:///c/mod.test.ts",
+        )
+        .unwrap()],
+      },
+    ])
+    .await
+    .unwrap();
+
+  let member_names = result
+    .members
+    .iter()
+    .map(|m| m.name.as_str())
+    .collect::<Vec<_>>();
+  assert_eq!(member_names, ["@scope/a", "@scope/b", "@scope/c"]);
+  assert_files!(
+    result.members[0].output.main.files,
+    &[
+      ("mod.ts", "import './util.js';\nexport const a = 1;"),
+      ("util.ts", "export const util = 1;"),
+      ("sub/mod.ts", "export const sub = 1;"),
+    ]
+  );
+  assert_files!(
+    result.members[1].output.main.files,
+    &[("mod.ts", "import '@scope/a';\nimport '@scope/a/sub';")]
+  );
+  assert_files!(
+    result.members[1].output.test.files,
+    &[("mod.test.ts", "import '@scope/a';\nimport './mod.js';")]
+  );
+  assert_eq!(
+    result.members[1].output.main.dependencies,
+    &[Dependency {
+      name: "@scope/a".to_string(),
+      version: "^1.0.0".to_string(),
+      peer_dependency: false,
+    }]
+  );
+  assert_eq!(
+    result.dependencies,
+    &[
+      WorkspaceDependency {
+        dependent: "@scope/b".to_string(),
+        dependency: "@scope/a".to_string(),
+        test_only: false,
+      },
+      WorkspaceDependency {
+        dependent: "@scope/c".to_string(),
+        dependency: "@scope/a".to_string(),
+        test_only: false,
+      },
+      WorkspaceDependency {
+        dependent: "@scope/c".to_string(),
+        dependency: "@scope/b".to_string(),
+        test_only: true,
+      },
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_workspace_other_member_module() {
+  let err = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/a/mod.ts", "export const a = 1;")
+        .add_local_file("/a/util.ts", "export const util = 1;")
+        .add_local_file(
+          "/b/mod.ts",
+          "import '../a/mod.ts';\nimport '../a/util.ts';",
+        );
+    })
+    .transform_workspace(vec![
+      WorkspaceMember {
+        name: "a".to_string(),
+        version: "1.0.0".to_string(),
+        root: ModuleSpecifier::parse("file:///a/").unwrap(),
+        entry_points: vec![ModuleSpecifier::parse("file:///a/mod.ts").unwrap()],
+        test_entry_points: Vec::new(),
+      },
+      WorkspaceMember {
+        name: "b".to_string(),
+        version: "1.0.0".to_string(),
+        root: ModuleSpecifier::parse("file:///b/").unwrap(),
+        entry_points: vec![ModuleSpecifier::parse("file:///b/mod.ts").unwrap()],
+        test_entry_points: Vec::new(),
+      },
+    ])
+    .await
+    .err()
+    .unwrap();
+
+  assert!(matches!(
+    err.downcast_ref::<TransformError>(),
+    Some(TransformError::InvalidOptions { .. })
+  ));
+  assert_eq!(
+    err.to_string(),
+    "Workspace member b imports file:///a/util.ts, which is a module of workspace member a that isn't one of its entry points. Add it to the entry points of a or map it to a package.",
+  );
+}
+
+#[tokio::test]
+async fn transform_workspace_invalid_members() {
+  let err = TestBuilder::new()
+    .transform_workspace(vec![
+      WorkspaceMember {
+        name: "a".to_string(),
+        version: "1.0.0".to_string(),
+        root: ModuleSpecifier::parse("file:///a").unwrap(),
+        entry_points: vec![ModuleSpecifier::parse("file:///a/mod.ts").unwrap()],
+        test_entry_points: Vec::new(),
+      },
+      WorkspaceMember {
+        name: "b".to_string(),
+        version: "1.0.0".to_string(),
+        root: ModuleSpecifier::parse("file:///b/").unwrap(),
+        entry_points: vec![ModuleSpecifier::parse("file:///a/mod.ts").unwrap()],
+        test_entry_points: Vec::new(),
+      },
+      WorkspaceMember {
+        name: "b".to_string(),
+        version: "1.0.0".to_string(),
+        root: ModuleSpecifier::parse("file:///c/").unwrap(),
+        entry_points: Vec::new(),
+        test_entry_points: Vec::new(),
+      },
+    ])
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(
+    err.to_string(),
+    concat!(
+      "Root file:///a of workspace member a must be a directory ending with a slash.\n",
+      "Entry point file:///a/mod.ts must be within the root file:///b/ of workspace member b.\n",
+      "Workspace member b was specified more than once.\n",
+      "Workspace member b must have at least one entry point.",
+    )
+  );
+}
+
+#[tokio::test]
+async fn transform_local_symlinks() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import './link/a.ts';\nimport './real/a.ts';",
+        )
+        .add_local_symlink("/link", "/real")
+        .add_local_file("/real/a.ts", "import './b.ts';")
+        .add_local_file("/real/b.ts", "5;");
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      ("mod.ts", "import './real/a.js';\nimport './real/a.js';"),
+      ("real/a.ts", "import './b.js';"),
+      ("real/b.ts", "5;"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_directory_index_files() {
+  fn setup(builder: &mut TestBuilder) -> &mut TestBuilder {
+    builder.with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "import './utils';\nimport './sub/';")
+        .add_local_file("/utils/mod.ts", "import './other.ts';")
+        .add_local_file("/utils/other.ts", "5;")
+        .add_local_file("/sub/index.ts", "6;");
+    })
+  }
+
+  let err_message = setup(&mut TestBuilder::new())
+    .transform()
+    .await
+    .err()
+    .unwrap()
+    .to_string();
+  assert!(err_message.contains("file:///utils"), "{}", err_message);
+
+  let result = setup(&mut TestBuilder::new())
+    .add_directory_index_file("mod.ts")
+    .add_directory_index_file("index.ts")
+    .transform()
+    .await
+    .unwrap();
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        "import './utils/mod.js';\nimport './sub/index.js';"
+      ),
+      ("utils/mod.ts", "import './other.js';"),
+      ("utils/other.ts", "5;"),
+      ("sub/index.ts", "6;"),
+    ]
+  );
+  assert_eq!(
+    result.warnings,
+    vec![
+      "Resolved the directory import file:///sub/ to file:///sub/index.ts. Import the file directly to avoid relying on the directory index files.",
+      "Resolved the directory import file:///utils to file:///utils/mod.ts. Import the file directly to avoid relying on the directory index files.",
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_github_dependencies() {
+  fn setup(builder: &mut TestBuilder) -> &mut TestBuilder {
+    builder.with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import 'https://raw.githubusercontent.com/owner/repo/v1.0.0/src/mod.ts';",
+        )
+        .add_remote_file(
+          "https://raw.githubusercontent.com/owner/repo/v1.0.0/src/mod.ts",
+          "import './other.ts';",
+        )
+        .add_remote_file(
+          "https://raw.githubusercontent.com/owner/repo/v1.0.0/src/other.ts",
+          "5;",
+        );
+    })
+  }
+
+  // vendored with a path based on the repository and tag
+  let result = setup(&mut TestBuilder::new()).transform().await.unwrap();
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        "import './deps/github.com/owner/repo@v1.0.0/src/mod.js';"
+      ),
+      (
+        "deps/github.com/owner/repo@v1.0.0/src/mod.ts",
+        "import './other.js';"
+      ),
+      ("deps/github.com/owner/repo@v1.0.0/src/other.ts", "5;"),
+    ]
+  );
+  assert!(result.main.dependencies.is_empty());
+
+  // mapped to a git dependency
+  let result = setup(&mut TestBuilder::new())
+    .set_github_dependencies(true)
+    .transform()
+    .await
+    .unwrap();
+  assert_files!(
+    result.main.files,
+    &[("mod.ts", "import 'repo/src/mod.ts';")]
+  );
+  assert_eq!(
+    result.main.dependencies,
+    &[Dependency {
+      name: "repo".to_string(),
+      version: "github:owner/repo#v1.0.0".to_string(),
+      peer_dependency: false,
+    }]
+  );
+}
+
+#[tokio::test]
+async fn transform_remote_file_not_exists() {
+  let err_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_remote_file(
+        "http://localhost/mod.ts",
+        "import * as other from './other.ts';",
+      );
+    })
+    .entry_point("http://localhost/mod.ts")
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(
+    err_message.to_string(),
+    r#"Module not found "http://localhost/other.ts"."#
+  );
+}
+
+#[tokio::test]
+async fn transform_remote_file_error() {
+  let err_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_remote_file_with_error(
+        "http://localhost/mod.ts",
+        "Some error loading.",
+      );
+    })
+    .entry_point("http://localhost/mod.ts")
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(
+    err_message.to_string(),
+    "Some error loading. (http://localhost/mod.ts)"
+  );
+}
+
+#[tokio::test]
+async fn transform_parse_error() {
+  let err_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "export * from 'http://localhost/mod.js';")
+        .add_remote_file_with_headers(
+          "http://localhost/mod.js",
+          "",
+          &[("x-typescript-types", "./declarations.d.ts")],
+        )
+        .add_remote_file(
+          "http://localhost/declarations.d.ts",
+          "test test test",
+        );
+    })
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(err_message.to_string(), "The module's source code could not be parsed: Expected ';', '}' or <eof> at http://localhost/declarations.d.ts:1:6");
+}
+
+#[tokio::test]
+async fn transform_typescript_types_resolution_error() {
+  let err_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "export * from 'https://localhost/mod.js';")
+        .add_remote_file_with_headers(
+          "https://localhost/mod.js",
+          "",
+          &[("x-typescript-types", "http://localhost/declarations.d.ts")],
+        )
+        .add_remote_file("http://localhost/declarations.d.ts", "");
+    })
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(err_message.to_string(),
+    concat!(
+      "Error resolving types for https://localhost/mod.js with reference http://localhost/declarations.d.ts. ",
+      "Modules imported via https are not allowed to import http modules.\n",
+      "  Importing: http://localhost/declarations.d.ts"
+    )
+  );
+}
+
+#[tokio::test]
+async fn transform_typescript_types_in_headers() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "export * from 'http://localhost/mod.js';")
+        .add_remote_file_with_headers(
+          "http://localhost/mod.js",
+          "function test() { return 5; }",
+          &[("x-typescript-types", "./declarations.d.ts")],
+        )
+        .add_remote_file(
+          "http://localhost/declarations.d.ts",
+          "declare function test(): number;",
+        );
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      ("mod.ts", "export * from './deps/localhost/mod.js';"),
+      ("deps/localhost/mod.js", "function test() { return 5; }"),
+      (
+        "deps/localhost/mod.d.ts",
+        "declare function test(): number;"
+      ),
+    ]
+  );
+  assert_eq!(
+    result.main.declarations,
+    vec![(
+      PathBuf::from("deps/localhost/mod.js"),
+      PathBuf::from("deps/localhost/mod.d.ts")
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_typescript_types_in_deno_types() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file("/mod.ts", "// @deno-types='./declarations.d.ts';\nexport * from 'http://localhost/mod.js';")
+      .add_remote_file("http://localhost/mod.js", "function test() { return 5; }")
+      .add_local_file("/declarations.d.ts", "declare function test(): number;");
+    })
+    .transform().await.unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      ("mod.ts", "\nexport * from './deps/localhost/mod.js';"),
+      ("deps/localhost/mod.js", "function test() { return 5; }"),
+      (
+        "deps/localhost/mod.d.ts",
+        "declare function test(): number;"
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_prunes_ignored_declaration_file_dependencies() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "// @deno-types='./declarations.d.ts'\n",
+            "export * from 'http://localhost/mod.js';\n",
+            "export type { Other } from 'http://localhost/used.d.ts';",
+          ),
+        )
+        .add_local_file("/declarations.d.ts", "declare function test(): number;")
+        .add_remote_file_with_headers(
+          "http://localhost/mod.js",
+          "function test() { return 5; }",
+          &[("x-typescript-types", "./mod.d.ts")],
+        )
+        .add_remote_file(
+          "http://localhost/mod.d.ts",
+          "import './unused.d.ts';\nimport './used.d.ts';\ndeclare function test(): number;",
+        )
+        .add_remote_file("http://localhost/unused.d.ts", "export interface Unused {}")
+        .add_remote_file("http://localhost/used.d.ts", "export interface Other {}");
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert!(result.warnings.is_empty());
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "\nexport * from './deps/localhost/mod.js';\n",
+          "export type { Other } from './deps/localhost/used';",
+        ),
+      ),
+      ("deps/localhost/mod.js", "function test() { return 5; }"),
+      (
+        "deps/localhost/mod.d.ts",
+        "declare function test(): number;"
+      ),
+      ("deps/localhost/used.d.ts", "export interface Other {}"),
+    ]
+  );
+  assert_eq!(
+    result.excluded_modules,
+    vec![
+      ModuleSpecifier::parse("http://localhost/mod.d.ts").unwrap(),
+      ModuleSpecifier::parse("http://localhost/unused.d.ts").unwrap(),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_typescript_type_references() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file("/mod.ts", "export * from 'http://localhost/mod.js';")
+      .add_remote_file("http://localhost/mod.js", "/// <reference types='./declarations.d.ts' />\nfunction test() { return 5; }")
+      .add_remote_file("http://localhost/declarations.d.ts", "declare function test(): number;");
+    })
+    .transform().await.unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      ("mod.ts", "export * from './deps/localhost/mod.js';"),
+      ("deps/localhost/mod.js", "\nfunction test() { return 5; }"),
+      (
+        "deps/localhost/mod.d.ts",
+        "declare function test(): number;"
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_deno_types_and_type_ref_for_same_file() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file("/mod.ts", "// @deno-types='./declarations.d.ts'\nexport * from './file.js';\n// @deno-types='./declarations.d.ts'\nexport * as test2 from './file.js';\nexport * from './other.ts';")
+      .add_local_file("/file.js", "/// <reference types='./declarations.d.ts' />\nfunction test() { return 5; }")
+      .add_local_file("/other.ts", "// @deno-types='./declarations.d.ts'\nexport * as other from './file.js';")
+      .add_local_file("/declarations.d.ts", "declare function test(): number;");
+    })
+    .transform().await.unwrap();
+
+  assert!(result.warnings.is_empty());
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        "\nexport * from './file.js';\n\nexport * as test2 from './file.js';\nexport * from './other.js';"
+      ),
+      (
+        "other.ts",
+        "\nexport * as other from './file.js';"
+      ),
+      ("file.js", "\nfunction test() { return 5; }"),
+      ("file.d.ts", "declare function test(): number;"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_comment_directive_options() {
+  let mut builder = TestBuilder::new();
+  builder.with_loader(|loader| {
+    loader
+      .add_local_file(
+        "/mod.ts",
+        concat!(
+          "// deno-lint-ignore-file no-explicit-any\n",
+          "// @deno-types='./declarations.d.ts'\n",
+          "export * from './file.js';\n",
+          "export * from './other.js';\n",
+          "// deno-lint-ignore no-var\n",
+          "var test: any = 5;\n",
+        ),
+      )
+      .add_local_file(
+        "/file.js",
+        "// @ts-self-types=\"./declarations.d.ts\"\nexport function test() { return 5; }",
+      )
+      .add_local_file("/other.js", "export const other = 5;")
+      .add_local_file(
+        "/declarations.d.ts",
+        "export declare function test(): number;",
+      );
+  });
+
+  let result = builder.transform().await.unwrap();
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "// deno-lint-ignore-file no-explicit-any\n",
+          "\n",
+          "export * from './file.js';\n",
+          "export * from './other.js';\n",
+          "// deno-lint-ignore no-var\n",
+          "var test: any = 5;\n",
+        )
+      ),
+      ("file.js", "export function test() { return 5; }"),
+      ("file.d.ts", "export declare function test(): number;"),
+      ("other.js", "export const other = 5;"),
+    ]
+  );
+
+  let result = builder
+    .set_comment_directives(CommentDirectiveOptions {
+      deno_types: CommentDirectiveAction::Rewrite,
+      ts_self_types: CommentDirectiveAction::Rewrite,
+      deno_lint_ignore: CommentDirectiveAction::Remove,
+      ..Default::default()
+    })
+    .transform()
+    .await
+    .unwrap();
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "\n",
+          "// @deno-types='./file.d.ts'\n",
+          "export * from './file.js';\n",
+          "export * from './other.js';\n",
+          "\n",
+          "var test: any = 5;\n",
+        )
+      ),
+      (
+        "file.js",
+        "// @ts-self-types=\"./file.d.ts\"\nexport function test() { return 5; }"
+      ),
+      ("file.d.ts", "export declare function test(): number;"),
+      ("other.js", "export const other = 5;"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_comment_directive_rewrite_ignore_comments() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        concat!(
+          "// deno-lint-ignore-file no-explicit-any no-custom\n",
+          "// deno-fmt-ignore-file\n",
+          "// deno-lint-ignore no-var -- needs to be hoisted\n",
+          "var test: any = 5;\n",
+          "// deno-lint-ignore no-custom\n",
+          "const other = 5;\n",
+          "/* deno-lint-ignore */ const value = 5;\n",
+          "// deno-lint-ignore camelcase\n",
+          "const some_value = 5;\n",
+          "// deno-fmt-ignore\n",
+          "const matrix = [1,0,\n  0,1];\n",
+        ),
+      );
+    })
+    .set_comment_directives(CommentDirectiveOptions {
+      deno_lint_ignore: CommentDirectiveAction::Rewrite,
+      deno_fmt_ignore: CommentDirectiveAction::Rewrite,
+      eslint_rules: BTreeMap::from([(
+        "camelcase".to_string(),
+        "@typescript-eslint/naming-convention".to_string(),
+      )]),
+      ..Default::default()
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "/* eslint-disable @typescript-eslint/no-explicit-any */\n",
+        "\n",
+        "// eslint-disable-next-line no-var -- needs to be hoisted\n",
+        "var test: any = 5;\n",
+        "\n",
+        "const other = 5;\n",
+        "/* eslint-disable-next-line */ const value = 5;\n",
+        "// eslint-disable-next-line @typescript-eslint/naming-convention\n",
+        "const some_value = 5;\n",
+        "// prettier-ignore\n",
+        "const matrix = [1,0,\n  0,1];\n",
+      )
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_ts_types_and_ts_self_types() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "// @ts-types='./declarations.d.ts'\n",
+            "export * from './file.js';\n",
+            "export * from './other.js';\n",
+          ),
+        )
+        .add_local_file("/file.js", "export function test() { return 5; }")
+        .add_local_file(
+          "/declarations.d.ts",
+          "export declare function test(): number;",
+        )
+        .add_local_file(
+          "/other.js",
+          "/* @ts-self-types=\"./other_types.d.ts\" */\nexport const other = 5;",
+        )
+        .add_local_file("/other_types.d.ts", "export declare const other: number;");
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert!(result.warnings.is_empty());
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        "\nexport * from './file.js';\nexport * from './other.js';\n"
+      ),
+      ("file.js", "export function test() { return 5; }"),
+      ("file.d.ts", "export declare function test(): number;"),
+      ("other.js", "export const other = 5;"),
+      ("other.d.ts", "export declare const other: number;"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_deno_types_and_type_ref_for_different_local_file() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        "// @deno-types='./declarations.d.ts'\nexport * from './file.js';\nexport * from './other.ts';"
+      )
+      .add_local_file("/file.js", "/// <reference types='./declarations3.d.ts' />\nfunction test() { return 5; }")
+      .add_local_file("/other.ts", "// @deno-types='./declarations2.d.ts'\nexport * as other from './file.js';")
+      .add_local_file("/declarations.d.ts", "declare function test1(): number;")
+      .add_local_file("/declarations2.d.ts", "declare function test2(): number;")
+      .add_local_file("/declarations3.d.ts", "declare function test3(): number;");
+    })
+    .transform().await.unwrap();
+
+  assert_eq!(
+    result.warnings,
+    vec![
+      concat!(
+        "Duplicate declaration file found for file:///file.js\n",
+        "  Specified file:///declarations.d.ts in file:///mod.ts\n",
+        "  Selected file:///declarations3.d.ts\n",
+        "  Supress this warning by having only one local file specify the declaration file for this module.",
+      ),
+      concat!(
+        "Duplicate declaration file found for file:///file.js\n",
+        "  Specified file:///declarations2.d.ts in file:///other.ts\n",
+        "  Selected file:///declarations3.d.ts\n",
+        "  Supress this warning by having only one local file specify the declaration file for this module.",
+      ),
+    ]
+  );
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        "\nexport * from './file.js';\nexport * from './other.js';"
+      ),
+      ("other.ts", "\nexport * as other from './file.js';"),
+      ("file.js", "\nfunction test() { return 5; }"),
+      ("file.d.ts", "declare function test3(): number;"),
+    ]
+  );
+  assert_eq!(
+    result.types,
+    vec![ResolvedTypes {
+      code_specifier: ModuleSpecifier::parse("file:///file.js").unwrap(),
+      declaration_specifier: ModuleSpecifier::parse(
+        "file:///declarations3.d.ts"
+      )
+      .unwrap(),
+      declaration_path: PathBuf::from("file.d.ts"),
+      referrer: ModuleSpecifier::parse("file:///file.js").unwrap(),
+      ignored: vec![
+        IgnoredTypes {
+          declaration_specifier: ModuleSpecifier::parse(
+            "file:///declarations.d.ts"
+          )
+          .unwrap(),
+          referrer: ModuleSpecifier::parse("file:///mod.ts").unwrap(),
+        },
+        IgnoredTypes {
+          declaration_specifier: ModuleSpecifier::parse(
+            "file:///declarations2.d.ts"
+          )
+          .unwrap(),
+          referrer: ModuleSpecifier::parse("file:///other.ts").unwrap(),
+        },
+      ],
+    }]
+  );
+}
+
+#[tokio::test]
+async fn transform_deno_types_and_type_ref_for_different_remote_file() {
+  fn setup() -> TestBuilder {
+    let mut test_builder = TestBuilder::new();
+    test_builder .with_loader(|loader| {
+        loader.add_local_file(
+          "/mod.ts",
+          "import 'http://localhost/mod.ts';"
+        )
+        .add_remote_file(
+          "http://localhost/mod.ts",
+          "// @deno-types='./declarations.d.ts'\nexport * from './file.js';\nexport * from './other.ts';"
+        )
+        .add_remote_file("http://localhost/file.js", "/// <reference types='./declarations3.d.ts' />\nfunction test() { return 5; }")
+        .add_remote_file("http://localhost/other.ts", "// @deno-types='./declarations2.d.ts'\nexport * as other from './file.js';")
+        .add_remote_file("http://localhost/declarations.d.ts", "declare function test1(): number;")
+        .add_remote_file("http://localhost/declarations2.d.ts", "declare function test2(): number;")
+        .add_remote_file("http://localhost/declarations3.d.ts", "declare function test3(): number;");
+      });
+    test_builder
+  }
+
+  let result = setup().transform().await.unwrap();
+
+  assert_eq!(
+    result.warnings,
+    vec![
+      concat!(
+        "Duplicate declaration file found for http://localhost/file.js\n",
+        "  Specified http://localhost/declarations.d.ts in http://localhost/mod.ts\n",
+        "  Selected http://localhost/declarations3.d.ts\n",
+        "  Supress this warning by specifying a declaration file for this module locally via `@deno-types`.",
+      ),
+      concat!(
+        "Duplicate declaration file found for http://localhost/file.js\n",
+        "  Specified http://localhost/declarations2.d.ts in http://localhost/other.ts\n",
+        "  Selected http://localhost/declarations3.d.ts\n",
+        "  Supress this warning by specifying a declaration file for this module locally via `@deno-types`.",
+      ),
+    ]
+  );
+  assert_files!(
+    result.main.files,
+    &[
+      ("mod.ts", "import './deps/localhost/mod.js';",),
+      (
+        "deps/localhost/mod.ts",
+        "\nexport * from './file.js';\nexport * from './other.js';"
+      ),
+      (
+        "deps/localhost/other.ts",
+        "\nexport * as other from './file.js';"
+      ),
+      ("deps/localhost/file.js", "\nfunction test() { return 5; }"),
+      (
+        "deps/localhost/file.d.ts",
+        "declare function test3(): number;"
+      ),
+    ]
+  );
+
+  // Now specify the declaration file locally. This should clear out the warnings.
+  let mut test_builder = setup();
+  test_builder.with_loader(|loader| {
+    // overwrite the existing /mod.ts
+    loader.add_local_file(
+      "/mod.ts",
+      "import 'http://localhost/mod.ts';\n// @deno-types='http://localhost/declarations2.d.ts'\nimport * as test from 'http://localhost/file.js'",
+    );
+  });
+  let result = test_builder.transform().await.unwrap();
+
+  assert!(result.warnings.is_empty());
+  assert_eq!(result.main.files.len(), 5);
+  assert_eq!(
+    result
+      .main
+      .files
+      .iter()
+      .find(|f| f.file_path == PathBuf::from("deps/localhost/file.d.ts"))
+      .unwrap()
+      .file_text,
+    "declare function test2(): number;"
+  );
+}
+
+#[tokio::test]
+async fn transform_remote_declaration_only_package() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import { Options } from 'https://deno.land/x/types@1.0.0/mod.d.ts';\n",
+            "import 'https://deno.land/x/types@1.0.0/global.d.ts';\n",
+            "export { Result } from 'https://deno.land/x/types@1.0.0/mod.d.ts';\n",
+            "export function run(options: Options): void {}\n",
+          ),
+        )
+        .add_remote_file(
+          "https://deno.land/x/types@1.0.0/mod.d.ts",
+          concat!(
+            "import type { Base } from './base.d.ts';\n",
+            "export interface Options extends Base {}\n",
+            "export type Result = string;\n",
+          ),
+        )
+        .add_remote_file(
+          "https://deno.land/x/types@1.0.0/global.d.ts",
+          "declare global { interface Window { value: string; } }\nexport {};\n",
+        )
+        .add_remote_file(
+          "https://deno.land/x/types@1.0.0/base.d.ts",
+          "export interface Base {}\n",
+        );
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert!(result.warnings.is_empty());
+  assert!(result.untyped_dependencies.is_empty());
+  assert!(result.main.dependencies.is_empty());
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "import type { Options } from './deps/deno.land/x/types@1.0.0/mod';\n",
+          "import type {} from './deps/deno.land/x/types@1.0.0/global';\n",
+          "export type { Result } from './deps/deno.land/x/types@1.0.0/mod';\n",
+          "export function run(options: Options): void {}\n",
+        )
+      ),
+      (
+        "deps/deno.land/x/types@1.0.0/mod.d.ts",
+        concat!(
+          "import type { Base } from './base';\n",
+          "export interface Options extends Base {}\n",
+          "export type Result = string;\n",
+        )
+      ),
+      (
+        "deps/deno.land/x/types@1.0.0/global.d.ts",
+        "declare global { interface Window { value: string; } }\nexport {};\n"
+      ),
+      (
+        "deps/deno.land/x/types@1.0.0/base.d.ts",
+        "export interface Base {}\n"
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_declaration_mappings() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "import 'http://localhost/mod.ts';\n// @deno-types='./declarations.d.ts'\nimport './file.js';")
+        .add_local_file("/file.js", "function test() { return 5; }")
+        .add_local_file("/declarations.d.ts", "declare function test1(): number;")
+        .add_local_file("/user.d.ts", "declare function test(): number;")
+        .add_local_file("/remote.d.ts", "export declare const value: number;")
+        .add_remote_file_with_headers(
+          "http://localhost/mod.ts",
+          "export const value = 5;",
+          &[("content-type", "application/javascript"), ("x-typescript-types", "./mod.d.ts")],
+        )
+        .add_remote_file("http://localhost/mod.d.ts", "export const value: any;");
+    })
+    .add_declaration_mapping("file:///file.js", "file:///user.d.ts")
+    .add_declaration_mapping("http://localhost/mod.ts", "file:///remote.d.ts")
+    .transform()
+    .await
+    .unwrap();
+
+  assert!(result.warnings.is_empty());
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        "import './deps/localhost/mod.js';\n\nimport './file.js';"
+      ),
+      ("file.js", "function test() { return 5; }"),
+      ("file.d.ts", "declare function test(): number;"),
+      ("deps/localhost/mod.js", "export const value = 5;"),
+      (
+        "deps/localhost/mod.d.ts",
+        "export declare const value: number;"
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_declaration_mappings_not_found() {
+  let err_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "5;")
+        .add_local_file("/user.d.ts", "declare function test(): number;");
+    })
+    .add_declaration_mapping("file:///file.js", "file:///user.d.ts")
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(
+    err_message.to_string(),
+    concat!(
+      "The following specifiers were indicated to have a declaration file, but were not found:\n",
+      "  * file:///file.js",
+    ),
+  );
+}
+
+#[tokio::test]
+async fn transform_untyped_remote_js_dependency() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "import 'http://localhost/mod.ts';")
+        .add_remote_file("http://localhost/mod.ts", "import './file.js';")
+        .add_remote_file("http://localhost/file.js", "export const test = 5;");
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.untyped_dependencies,
+    vec![UntypedDependency {
+      specifier: ModuleSpecifier::parse("http://localhost/file.js").unwrap(),
+      referrer_chain: vec![
+        ModuleSpecifier::parse("file:///mod.ts").unwrap(),
+        ModuleSpecifier::parse("http://localhost/mod.ts").unwrap(),
+        ModuleSpecifier::parse("http://localhost/file.js").unwrap(),
+      ],
+    }]
+  );
+  assert_eq!(
+    result.warnings,
+    vec![concat!(
+      "Could not find a declaration file for http://localhost/file.js\n",
+      "  Referrer chain: file:///mod.ts -> http://localhost/mod.ts -> http://localhost/file.js\n",
+      "  Supress this warning by specifying a declaration file for this module locally via `@deno-types`.",
+    )]
+  );
+}
+
+#[tokio::test]
+async fn transform_specifier_mappings() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import * as remote from 'http://localhost/mod.ts';\n",
+            "import * as local from './file.ts';\n",
+            "import * as entryA from 'http://localhost/mod/entryA.ts';\n",
+            "import * as entryB from 'http://localhost/mod/entryB.ts';\n",
+            "import * as entryC from 'http://localhost/mod/entryC.ts';\n",
+          ),
+        )
+        .add_remote_file(
+          "http://localhost/mod.ts",
+          "import * as myOther from './other.ts';",
+        );
+    })
+    .add_package_specifier_mapping(
+      "http://localhost/mod.ts",
+      "remote-module",
+      Some("1.0.0"),
+      None,
+    )
+    .add_package_specifier_mapping(
+      "file:///file.ts",
+      "local-module",
+      None,
+      None,
+    )
+    .add_package_specifier_mapping(
+      "http://localhost/mod/entryA.ts",
+      "mod",
+      Some("~0.1.0"),
+      None,
+    )
+    .add_package_specifier_mapping(
+      "http://localhost/mod/entryB.ts",
+      "mod",
+      Some("~0.1.0"),
+      Some("entryB"),
+    )
+    .add_package_specifier_mapping(
+      "http://localhost/mod/entryC.ts",
+      "mod",
+      Some("~0.1.0"),
+      Some("other/entryC.js"),
+    )
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "import * as remote from 'remote-module';\n",
+        "import * as local from 'local-module';\n",
+        "import * as entryA from 'mod';\n",
+        "import * as entryB from 'mod/entryB';\n",
+        "import * as entryC from 'mod/other/entryC.js';\n",
+      )
+    )]
+  );
+  assert_eq!(
+    result.main.dependencies,
+    &[
+      Dependency {
+        name: "mod".to_string(),
+        version: "~0.1.0".to_string(),
+        peer_dependency: false,
+      },
+      Dependency {
+        name: "remote-module".to_string(),
+        version: "1.0.0".to_string(),
+        peer_dependency: false,
+      }
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_not_found_mappings() {
+  let error_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file("/mod.ts", "test");
+    })
+    .add_package_specifier_mapping(
+      "http://localhost/mod.ts",
+      "local-module",
+      None,
+      None,
+    )
+    .add_package_specifier_mapping(
+      "http://localhost/mod2.ts",
+      "local-module2",
+      None,
+      None,
+    )
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(
+    error_message.to_string(),
+    "The following specifiers were indicated to be mapped to a package, but were not found:\n  * http://localhost/mod.ts\n  * http://localhost/mod2.ts"
+  );
+}
+
+#[tokio::test]
+async fn transform_dnt_map_directives() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "// @dnt-map: npm:chalk@^5\n",
+            "import chalk from 'http://localhost/chalk.ts';\n",
+            "// @dnt-map: npm:@scope/mod@~0.1.0/sub.js\n",
+            "import * as sub from './sub.ts';\n",
+            "// @dnt-map: npm:other@1.0.0\n",
+            "import * as other from 'http://localhost/other.ts';\n",
+          ),
+        )
+        .add_remote_file(
+          "http://localhost/chalk.ts",
+          "import * as myOther from './chalk_other.ts';",
+        );
+    })
+    .add_package_specifier_mapping(
+      "http://localhost/other.ts",
+      "options-other",
+      Some("2.0.0"),
+      None,
+    )
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "\n",
+        "import chalk from 'chalk';\n",
+        "\n",
+        "import * as sub from '@scope/mod/sub.js';\n",
+        "\n",
+        "import * as other from 'options-other';\n",
+      )
+    )]
+  );
+  assert_eq!(
+    result.main.dependencies,
+    &[
+      Dependency {
+        name: "@scope/mod".to_string(),
+        version: "~0.1.0".to_string(),
+        peer_dependency: false,
+      },
+      Dependency {
+        name: "chalk".to_string(),
+        version: "^5".to_string(),
+        peer_dependency: false,
+      },
+      Dependency {
+        name: "options-other".to_string(),
+        version: "2.0.0".to_string(),
+        peer_dependency: false,
+      },
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_dnt_map_directive_errors() {
+  let error_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "// @dnt-map: chalk@^5\n",
+            "import chalk from 'http://localhost/chalk.ts';\n",
+            "// @dnt-map: npm:other@1\n",
+            "import * as other from 'http://localhost/other.ts';\n",
+            "// @dnt-map: npm:other@2\n",
+            "export * from 'http://localhost/other.ts';\n",
+          ),
+        )
+        .add_remote_file("http://localhost/chalk.ts", "export default 5;");
+    })
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(
+    error_message.to_string(),
+    concat!(
+      "Invalid @dnt-map directive \"chalk@^5\". Expected an npm package (ex. \"npm:chalk@^5\") or a Node.js module (ex. \"node:fs\").\n",
+      "    at file:///mod.ts:1:1\n\n",
+      "The @dnt-map directive for http://localhost/other.ts maps it to other@2, but another directive maps it to other@1.\n",
+      "    at file:///mod.ts:5:1",
+    )
+  );
+}
+
+#[tokio::test]
+async fn node_module_mapping() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import * as path from 'https://deno.land/std@0.143.0/node/path.ts';\n",
+            "import * as fs from 'https://deno.land/std/node/fs/promises.ts';",
+          ),
+        );
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[(
+      "mod.ts",
+      concat!(
+        "import * as path from 'path';\n",
+        "import * as fs from 'fs/promises';",
+      )
+    ),]
+  );
+}
+
+#[tokio::test]
+async fn skypack_esm_module_mapping() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import package1 from 'https://cdn.skypack.dev/preact@^10.5.0';\n",
+            "import package2 from 'https://cdn.skypack.dev/@scope/package-name@1';\n",
+            "import package3 from 'https://esm.sh/react@17.0.2';\n",
+            // custom esm.sh stuff like this should download the dependency
+            "import package4 from 'https://esm.sh/swr?deps=react@16.14.0';\n",
+            "import package5 from 'https://esm.sh/test@1.2.5?deps=react@16.14.0';\n",
+            "import package6 from 'https://cdn.skypack.dev/preact@^10.5.0/hooks?dts';\n",
+            "import package7 from 'https://esm.sh/react-dom@17.0.2/server';\n",
+          ),
+        )
+        .add_remote_file_with_headers(
+          "https://esm.sh/swr?deps=react@16.14.0", "",
+          &[("content-type", "application/typescript")]
+        )
+        .add_remote_file_with_headers(
+          "https://esm.sh/test@1.2.5?deps=react@16.14.0",
+          "",
+          &[("content-type", "application/typescript")]
+       );
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "import package1 from 'preact';\n",
+          "import package2 from '@scope/package-name';\n",
+          "import package3 from 'react';\n",
+          "import package4 from './deps/esm.sh/swr.js';\n",
+          "import package5 from './deps/esm.sh/test@1.2.5.js';\n",
+          "import package6 from 'preact/hooks';\n",
+          "import package7 from 'react-dom/server';\n",
+        )
+      ),
+      ("deps/esm.sh/swr.ts", "",),
+      ("deps/esm.sh/test@1.2.5.ts", "",)
+    ]
+  );
+  assert_eq!(
+    result.main.dependencies,
+    &[
+      Dependency {
+        name: "@scope/package-name".to_string(),
+        version: "1".to_string(),
+        peer_dependency: false,
+      },
+      Dependency {
+        name: "preact".to_string(),
+        version: "^10.5.0".to_string(),
+        peer_dependency: false,
+      },
+      Dependency {
+        name: "react".to_string(),
+        version: "17.0.2".to_string(),
+        peer_dependency: false,
+      },
+      Dependency {
+        name: "react-dom".to_string(),
+        version: "17.0.2".to_string(),
+        peer_dependency: false,
+      }
+    ]
+  );
+}
+
+#[tokio::test]
+async fn skypack_module_mapping_different_versions() {
+  let error_message = TestBuilder::new()
+    .with_loader(|loader| {
+      loader.add_local_file(
+        "/mod.ts",
+        concat!(
+          "import package1 from 'https://cdn.skypack.dev/preact@^10.5.0';\n",
+          "import package2 from 'https://cdn.skypack.dev/preact@^10.5.2';",
+        ),
+      );
+    })
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert_eq!(
+    error_message.to_string(),
+    "Specifier https://cdn.skypack.dev/preact@^10.5.0 with version ^10.5.0 did not match specifier https://cdn.skypack.dev/preact@^10.5.2 with version ^10.5.2."
+  );
+}
+
+#[tokio::test]
+async fn esm_module_with_deno_types() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "// @deno-types=\"https://localhost/mod.d.ts\"\n",
+            "import {test} from 'https://esm.sh/test@0.0.1/lib/mod.js';\n",
+          ),
+        )
+        .add_remote_file_with_headers(
+          "https://esm.sh/test@0.0.1/lib/mod.js",
+          "export function test() {return 5;}",
+          &[("content-type", "application/typescript")],
+        )
+        .add_remote_file_with_headers(
+          "https://localhost/mod.d.ts",
+          "declare function test(): number;",
+          &[("content-type", "application/typescript")],
+        );
+    })
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      // this is a bug... it should create a proxy here instead,
+      // but will wait for someone to open this as it's probably
+      // rare for this to occur in the wild
+      ("mod.ts", "\nimport {test} from 'test/lib/mod.js';\n"),
+      (
+        "deps/localhost/mod.d.ts",
+        "declare function test(): number;",
+      )
+    ]
+  );
+}
+
+#[tokio::test]
+async fn mapped_package_with_types_package() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "// @deno-types=\"https://localhost/mod.d.ts\"\n",
+            "import {test} from 'https://localhost/mod.js';\n",
+          ),
+        )
+        .add_remote_file(
+          "https://localhost/mod.d.ts",
+          "declare function test(): number;",
+        );
+    })
+    .add_specifier_mapping(
+      "https://localhost/mod.js",
+      MappedSpecifier::Package(PackageMappedSpecifier {
+        name: "test".to_string(),
+        version: Some("^1.0.0".to_string()),
+        sub_path: None,
+        peer_dependency: false,
+        types_package: Some(Dependency {
+          name: "@types/test".to_string(),
+          version: "^1.0.0".to_string(),
+          peer_dependency: false,
+        }),
+      }),
+    )
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[("mod.ts", "\nimport {test} from 'test';\n")]
+  );
+  assert_eq!(
+    result.main.dependencies,
+    &[
+      Dependency {
+        name: "@types/test".to_string(),
+        version: "^1.0.0".to_string(),
+        peer_dependency: false,
+      },
+      Dependency {
+        name: "test".to_string(),
+        version: "^1.0.0".to_string(),
+        peer_dependency: false,
+      },
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_import_map() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import * as remote from 'localhost/mod.ts';",
+        )
+        .add_local_file(
+          "/import_map.json",
+          r#"{
+  "imports": {
+    "localhost/": "/subdir/"
+  }
+}"#,
+        )
+        .add_local_file(
+          "/subdir/mod.ts",
+          "import * as myOther from './other.ts';",
+        )
+        .add_local_file("/subdir/other.ts", "export function test() {}");
+    })
+    .set_import_map("file:///import_map.json")
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      ("mod.ts", "import * as remote from './subdir/mod.js';",),
+      ("subdir/mod.ts", "import * as myOther from './other.js';",),
+      ("subdir/other.ts", "export function test() {}",)
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_file_dependencies() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import * as local from 'localhost/mod.ts';\n",
+            "import * as remote from 'https://localhost/mod.ts';",
+          ),
+        )
+        .add_local_file(
+          "/import_map.json",
+          r#"{
+  "imports": {
+    "localhost/": "/subdir/"
+  }
+}"#,
+        )
+        .add_local_file("/subdir/mod.ts", "export function test() {}")
+        .add_local_file("/subdir/mod.d.ts", "export function test(): void;")
+        .add_remote_file("https://localhost/mod.ts", "export class Test {}");
+    })
+    .add_declaration_mapping("file:///subdir/mod.ts", "file:///subdir/mod.d.ts")
+    .set_import_map("file:///import_map.json")
+    .transform()
+    .await
+    .unwrap();
+
+  assert_eq!(
+    result.file_dependencies,
+    vec![
+      PathBuf::from("/import_map.json"),
+      PathBuf::from("/mod.ts"),
+      PathBuf::from("/subdir/mod.d.ts"),
+      PathBuf::from("/subdir/mod.ts"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_multiple_entry_points() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "import './ref.ts';mod1;")
+        .add_local_file("/mod2.ts", "import './ref.ts';mod2;")
+        .add_local_file("/ref.ts", "export const test = 5;");
+    })
+    .add_entry_point("file:///mod2.ts")
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      ("mod.ts", "import './ref.js';mod1;"),
+      ("mod2.ts", "import './ref.js';mod2;"),
+      ("ref.ts", "export const test = 5;"),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn test_entry_points() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import package1 from 'https://cdn.skypack.dev/preact@^10.5.0';\n",
+        )
+        .add_local_file(
+          "/mod.test.ts",
+          concat!(
+            "import './mod.ts';\n",
+            "import package1 from 'https://cdn.skypack.dev/preact@^10.5.0';\n",
+            "import package3 from 'https://esm.sh/react@17.0.2';\n",
+            "Deno.writeTextFile('test', 'test')",
+          ),
+        );
+    })
+    .add_test_entry_point("file:///mod.test.ts")
+    .add_default_shims()
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[("mod.ts", "import package1 from 'preact';\n",)]
+  );
+  assert_eq!(
+    result.main.dependencies,
+    &[Dependency {
+      name: "preact".to_string(),
+      version: "^10.5.0".to_string(),
+      peer_dependency: false,
+    },]
+  );
+  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
+
+  assert_files!(
+    result.test.files,
+    &[
+      (
+        "mod.test.ts",
+        concat!(
+          "import * as dntShim from \"./_dnt.test_shims.js\";\n",
+          "import './mod.js';\n",
+          "import package1 from 'preact';\n",
+          "import package3 from 'react';\n",
+          "dntShim.Deno.writeTextFile('test', 'test')"
+        )
+        .to_string(),
+      ),
+      (
+        "_dnt.test_shims.ts",
+        get_shim_file_text(
+          concat!(
+            "import { Deno } from \"@deno/shim-deno\";\n",
+            "export { Deno } from \"@deno/shim-deno\";\n",
+            "import { setTimeout, setInterval } from \"@deno/shim-timers\";\n",
+            "export { setTimeout, setInterval } from \"@deno/shim-timers\";\n",
+            "\n",
+            "const dntGlobals = {\n",
+            "  Deno,\n",
+            "  setTimeout,\n",
+            "  setInterval,\n",
+            "};\n",
+            "export const dntGlobalThis = createMergeProxy(globalThis, dntGlobals);\n",
+          )
+          .to_string(),
+        ),
+      )
+    ]
+  );
+  assert_eq!(
+    result.test.dependencies,
+    &[
+      Dependency {
+        name: "react".to_string(),
+        version: "17.0.2".to_string(),
+        peer_dependency: false,
+      },
+      Dependency {
+        name: "@deno/shim-deno".to_string(),
+        version: "^0.1.0".to_string(),
+        peer_dependency: false,
+      },
+      Dependency {
+        name: "@deno/shim-timers".to_string(),
+        version: "^0.1.0".to_string(),
+        peer_dependency: false,
+      }
+    ]
+  );
+  assert_eq!(result.test.entry_points, &[PathBuf::from("mod.test.ts")]);
 }
 
 #[tokio::test]
-async fn transform_relative_file() {
+async fn test_main_imports_self_reference() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
-        .add_local_file("/mod.ts", "import * as other from './other.ts';")
-        .add_local_file("/other.ts", "5;");
+        .add_local_file("/mod.ts", "export class Error1 {}")
+        .add_local_file("/sub/mod.ts", "export * from '../utils.ts';")
+        .add_local_file("/utils.ts", "export const value = 5;")
+        .add_local_file(
+          "/mod.test.ts",
+          concat!(
+            "import { Error1 } from './mod.ts';\n",
+            "import { value } from './sub/mod.ts';\n",
+            "import { value as other } from './utils.ts';",
+          ),
+        );
     })
+    .add_entry_point("file:///sub/mod.ts")
+    .add_test_entry_point("file:///mod.test.ts")
+    .set_test_main_imports(TestMainImports::SelfReference(
+      "@scope/package".to_string(),
+    ))
     .transform()
     .await
     .unwrap();
 
+  // the main files are only output in the main environment
   assert_files!(
     result.main.files,
     &[
-      ("mod.ts", "import * as other from './other.js';"),
-      ("other.ts", "5;")
+      ("mod.ts", "export class Error1 {}"),
+      ("sub/mod.ts", "export * from '../utils.js';"),
+      ("utils.ts", "export const value = 5;"),
     ]
   );
+  assert_files!(
+    result.test.files,
+    &[(
+      "mod.test.ts",
+      concat!(
+        "import { Error1 } from '@scope/package';\n",
+        "import { value } from '@scope/package/sub';\n",
+        "import { value as other } from './utils.js';",
+      )
+    )]
+  );
+  assert!(result.test.dependencies.is_empty());
 }
 
 #[tokio::test]
-async fn transform_remote_files() {
+async fn test_main_imports_invalid_package_name() {
+  let err = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "export class Error1 {}")
+        .add_local_file("/mod.test.ts", "import './mod.ts';");
+    })
+    .add_test_entry_point("file:///mod.test.ts")
+    .set_test_main_imports(TestMainImports::SelfReference(
+      "Invalid Name".to_string(),
+    ))
+    .transform()
+    .await
+    .err()
+    .unwrap();
+
+  assert!(err
+    .to_string()
+    .starts_with("Invalid package name for the test main imports"));
+}
+
+#[tokio::test]
+async fn test_entry_points_same_module_multiple_places() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
         .add_local_file(
           "/mod.ts",
           concat!(
-            "import * as other from 'http://localhost/mod.ts';\n",
-            "import 'https://deno.land/std@0.143.0/mod.ts';",
+            "export * from 'https://deno.land/std@0.143.0/path.ts';\n",
+            "import * as deps from './deps.ts';",
           ),
         )
-        .add_remote_file(
-          "http://localhost/mod.ts",
-          "import * as myOther from './other.ts';",
-        )
-        .add_remote_file(
-          "http://localhost/other.ts",
-          "import * as folder from './folder';",
-        )
-        .add_remote_file_with_headers(
-          "http://localhost/folder",
-          "import * as folder2 from './folder.ts';",
-          &[("content-type", "application/javascript")],
-        )
-        .add_remote_file(
-          "http://localhost/folder.ts",
-          "import * as folder3 from './folder.js';",
-        )
-        .add_remote_file(
-          "http://localhost/folder.js",
-          "import * as otherFolder from './otherFolder';",
-        )
-        .add_remote_file_with_headers(
-          "http://localhost/otherFolder",
-          "import * as subFolder from './sub/subfolder';",
-          &[("content-type", "application/javascript")],
-        )
-        .add_remote_file_with_headers(
-          "http://localhost/sub/subfolder",
-          "import * as localhost2 from 'http://localhost2';",
-          &[("content-type", "application/javascript")],
+        // ensure that the path.ts in this file being already analyzed
+        // doesn't cause flags.ts to not be analyzed
+        .add_local_file(
+          "/deps.ts",
+          concat!(
+            "export * from 'https://deno.land/std@0.143.0/path.ts';\n",
+            "export * from 'https://deno.land/std@0.143.0/flags.ts';",
+          ),
         )
         .add_remote_file(
-          "https://deno.land/std@0.143.0/mod.ts",
-          "console.log(5);",
-        )
-        .add_remote_file_with_headers(
-          "http://localhost2",
-          "import * as localhost3Mod from 'https://localhost3/mod.ts';",
-          &[("content-type", "application/javascript")],
+          "https://deno.land/std@0.143.0/flags.ts",
+          "export class Flags {}",
         )
         .add_remote_file(
-          "https://localhost3/mod.ts",
-          "import * as localhost3 from 'https://localhost3';",
+          "https://deno.land/std@0.143.0/path.ts",
+          "export class Path {}",
         )
-        .add_remote_file_with_headers(
-          "https://localhost3",
-          "5;",
-          &[("content-type", "application/typescript; charset=UTF-8")],
-        );
+        .add_local_file("/mod.test.ts", "import * as deps from './deps.ts';");
     })
+    .add_test_entry_point("file:///mod.test.ts")
     .transform()
     .await
     .unwrap();
@@ -598,64 +5309,53 @@ async fn transform_remote_files() {
       (
         "mod.ts",
         concat!(
-          "import * as other from './deps/localhost/mod.js';\n",
-          "import './deps/deno.land/std@0.143.0/mod.js';",
+          "export * from './deps/deno.land/std@0.143.0/path.js';\n",
+          "import * as deps from './deps.js';",
         )
       ),
       (
-        "deps/localhost/mod.ts",
-        "import * as myOther from './other.js';"
-      ),
-      (
-        "deps/localhost/other.ts",
-        "import * as folder from './folder.js';"
-      ),
-      (
-        "deps/localhost/folder.js",
-        "import * as folder2 from './folder_2.js';"
-      ),
-      (
-        "deps/localhost/folder_2.ts",
-        "import * as folder3 from './folder_3.js';"
-      ),
-      (
-        "deps/localhost/folder_3.js",
-        "import * as otherFolder from './otherFolder.js';"
-      ),
-      (
-        "deps/localhost/otherFolder.js",
-        "import * as subFolder from './sub/subfolder.js';"
-      ),
-      (
-        "deps/localhost/sub/subfolder.js",
-        "import * as localhost2 from '../../localhost2.js';"
-      ),
-      ("deps/deno.land/std@0.143.0/mod.ts", "console.log(5);"),
-      (
-        "deps/localhost2.js",
-        "import * as localhost3Mod from './localhost3/mod.js';"
+        "deps.ts",
+        concat!(
+          "export * from './deps/deno.land/std@0.143.0/path.js';\n",
+          "export * from './deps/deno.land/std@0.143.0/flags.js';",
+        )
       ),
       (
-        "deps/localhost3/mod.ts",
-        "import * as localhost3 from '../localhost3.js';"
+        "deps/deno.land/std@0.143.0/flags.ts",
+        "export class Flags {}"
       ),
-      ("deps/localhost3.ts", "5;"),
+      ("deps/deno.land/std@0.143.0/path.ts", "export class Path {}")
     ]
   );
+  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
+
+  assert_files!(
+    result.test.files,
+    &[("mod.test.ts", "import * as deps from './deps.js';",)]
+  );
+  assert_eq!(result.test.entry_points, &[PathBuf::from("mod.test.ts")]);
 }
 
 #[tokio::test]
-async fn transform_handle_local_deps_folder() {
+async fn polyfills_all() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
         .add_local_file(
           "/mod.ts",
-          "import 'http://localhost/mod.ts';\nimport './deps/localhost/mod.ts'",
+          concat!(
+            "export const test = (obj) => Object.hasOwn(obj, 'test');\n",
+            "try {\n",
+            "} catch (err) {\n",
+            "  err.cause = new Error();\n",
+            "}\n",
+            "''.replaceAll('test', 'other');\n",
+            "[].findLast(() => true);\n",
+          ),
         )
-        .add_local_file("/deps/localhost/mod.ts", "local;")
-        .add_remote_file("http://localhost/mod.ts", "remote;");
+        .add_local_file("/mod.test.ts", "import * as mod from './mod.ts';");
     })
+    .add_test_entry_point("file:///mod.test.ts")
     .transform()
     .await
     .unwrap();
@@ -665,140 +5365,230 @@ async fn transform_handle_local_deps_folder() {
     &[
       (
         "mod.ts",
-        "import './deps_2/localhost/mod.js';\nimport './deps/localhost/mod.js'"
+        concat!(
+          "import \"./_dnt.polyfills.js\";\n",
+          "export const test = (obj) => Object.hasOwn(obj, 'test');\n",
+          "try {\n",
+          "} catch (err) {\n",
+          "  err.cause = new Error();\n",
+          "}\n",
+          "''.replaceAll('test', 'other');\n",
+          "[].findLast(() => true);\n",
+        ),
+      ),
+      (
+        "_dnt.polyfills.ts",
+        concat!(
+          include_str!("../src/polyfills/scripts/esnext.object-has-own.ts"),
+          include_str!("../src/polyfills/scripts/esnext.error-cause.ts"),
+          include_str!("../src/polyfills/scripts/es2021.string-replaceAll.ts"),
+          include_str!("../src/polyfills/scripts/esnext.array-findLast.ts"),
+        )
       ),
-      ("deps/localhost/mod.ts", "local;"),
-      ("deps_2/localhost/mod.ts", "remote;"),
     ]
   );
-}
-
-#[tokio::test]
-async fn transform_local_file_not_exists() {
-  let err_message = TestBuilder::new()
-    .with_loader(|loader| {
-      loader.add_local_file("/mod.ts", "import * as other from './other.ts';");
-    })
-    .transform()
-    .await
-    .err()
-    .unwrap();
+  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
 
-  assert_eq!(
-    err_message.to_string(),
-    r#"Module not found "file:///other.ts"."#
+  assert_files!(
+    result.test.files,
+    &[("mod.test.ts", concat!("import * as mod from './mod.js';",),)]
   );
+  assert_eq!(result.test.entry_points, &[PathBuf::from("mod.test.ts")]);
 }
 
 #[tokio::test]
-async fn transform_remote_file_not_exists() {
-  let err_message = TestBuilder::new()
+async fn polyfills_string_replaceall_target() {
+  test_string_replace_all_polyfill(ScriptTarget::ES2020, true).await;
+  test_string_replace_all_polyfill(ScriptTarget::ES2021, false).await;
+}
+
+async fn test_string_replace_all_polyfill(
+  target: ScriptTarget,
+  should_have_polyfill: bool,
+) {
+  let result = TestBuilder::new()
     .with_loader(|loader| {
-      loader.add_remote_file(
-        "http://localhost/mod.ts",
-        "import * as other from './other.ts';",
-      );
+      loader
+        .add_local_file("/mod.ts", "''.replaceAll('test', 'other');\n")
+        .add_local_file("/mod.test.ts", "import * as mod from './mod.ts';");
     })
-    .entry_point("http://localhost/mod.ts")
+    .add_test_entry_point("file:///mod.test.ts")
+    .set_target(target)
     .transform()
     .await
-    .err()
     .unwrap();
 
-  assert_eq!(
-    err_message.to_string(),
-    r#"Module not found "http://localhost/other.ts"."#
-  );
+  if should_have_polyfill {
+    assert_files!(
+      result.main.files,
+      &[
+        (
+          "mod.ts",
+          concat!(
+            "import \"./_dnt.polyfills.js\";\n",
+            "''.replaceAll('test', 'other');\n",
+          ),
+        ),
+        (
+          "_dnt.polyfills.ts",
+          concat!(include_str!(
+            "../src/polyfills/scripts/es2021.string-replaceAll.ts"
+          ),)
+        ),
+      ]
+    );
+  } else {
+    assert_files!(
+      result.main.files,
+      &[("mod.ts", "''.replaceAll('test', 'other');\n",)]
+    );
+  }
+  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
 }
 
 #[tokio::test]
-async fn transform_remote_file_error() {
-  let err_message = TestBuilder::new()
+async fn polyfills_test_files() {
+  let result = TestBuilder::new()
     .with_loader(|loader| {
-      loader.add_remote_file_with_error(
-        "http://localhost/mod.ts",
-        "Some error loading.",
+      loader.add_local_file("/mod.ts", "").add_local_file(
+        "/mod.test.ts",
+        "// Some copyright text\nObject.hasOwn({}, 'prop');",
       );
     })
-    .entry_point("http://localhost/mod.ts")
+    .add_test_entry_point("file:///mod.test.ts")
     .transform()
     .await
-    .err()
     .unwrap();
 
-  assert_eq!(
-    err_message.to_string(),
-    "Some error loading. (http://localhost/mod.ts)"
+  assert_files!(result.main.files, &[("mod.ts", "",)]);
+  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
+
+  assert_files!(
+    result.test.files,
+    &[
+      (
+        "mod.test.ts",
+        concat!(
+          "// Some copyright text\n",
+          "import \"./_dnt.test_polyfills.js\";\n\n",
+          "Object.hasOwn({}, 'prop');"
+        )
+      ),
+      (
+        "_dnt.test_polyfills.ts",
+        include_str!("../src/polyfills/scripts/esnext.object-has-own.ts"),
+      )
+    ]
   );
+  assert_eq!(result.test.entry_points, &[PathBuf::from("mod.test.ts")]);
 }
 
 #[tokio::test]
-async fn transform_parse_error() {
-  let err_message = TestBuilder::new()
+async fn polyfills_object_has_own_conflict() {
+  // should not do a polyfill because of Object
+  let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
-        .add_local_file("/mod.ts", "export * from 'http://localhost/mod.js';")
-        .add_remote_file_with_headers(
-          "http://localhost/mod.js",
-          "",
-          &[("x-typescript-types", "./declarations.d.ts")],
-        )
-        .add_remote_file(
-          "http://localhost/declarations.d.ts",
-          "test test test",
-        );
+        .add_local_file("/mod.ts", "export class Object {} Object.hasOwn();");
     })
     .transform()
     .await
-    .err()
     .unwrap();
 
-  assert_eq!(err_message.to_string(), "The module's source code could not be parsed: Expected ';', '}' or <eof> at http://localhost/declarations.d.ts:1:6");
+  assert_files!(
+    result.main.files,
+    &[("mod.ts", "export class Object {} Object.hasOwn();")]
+  );
 }
 
 #[tokio::test]
-async fn transform_typescript_types_resolution_error() {
-  let err_message = TestBuilder::new()
+async fn module_specifier_mapping_general() {
+  let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
-        .add_local_file("/mod.ts", "export * from 'https://localhost/mod.js';")
-        .add_remote_file_with_headers(
-          "https://localhost/mod.js",
-          "",
-          &[("x-typescript-types", "http://localhost/declarations.d.ts")],
+        .add_local_file("/mod.ts", "import './other.deno.ts';")
+        .add_local_file("/other.deno.ts", "console.log(5);")
+        .add_local_file(
+          "/other.node.ts",
+          concat!(
+            "import * as fs from 'fs';\n",
+            "import { myFunction } from './myFunction.ts'\n",
+            "export function test() {\n",
+            "  // dnt-shim-ignore\n",
+            "  Deno.readFileSync('test');\n",
+            "  Object.hasOwn({}, 'prop');\n",
+            "}",
+          ),
         )
-        .add_remote_file("http://localhost/declarations.d.ts", "");
+        .add_local_file("/myFunction.ts", "export function myFunction() {}");
     })
+    .add_module_specifier_mapping(
+      "file:///other.deno.ts",
+      "file:///other.node.ts",
+    )
     .transform()
     .await
-    .err()
     .unwrap();
 
-  assert_eq!(err_message.to_string(),
-    concat!(
-      "Error resolving types for https://localhost/mod.js with reference http://localhost/declarations.d.ts. ",
-      "Modules imported via https are not allowed to import http modules.\n",
-      "  Importing: http://localhost/declarations.d.ts"
-    )
+  assert_files!(
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "import \"./_dnt.polyfills.js\";\n",
+          "import './other.node.js';"
+        ),
+      ),
+      (
+        "other.node.ts",
+        concat!(
+          "import * as fs from 'fs';\n",
+          "import { myFunction } from './myFunction.js'\n",
+          "export function test() {\n",
+          "  // dnt-shim-ignore\n",
+          "  Deno.readFileSync('test');\n",
+          "  Object.hasOwn({}, 'prop');\n",
+          "}",
+        )
+      ),
+      ("myFunction.ts", "export function myFunction() {}",),
+      (
+        "_dnt.polyfills.ts",
+        include_str!("../src/polyfills/scripts/esnext.object-has-own.ts")
+      ),
+    ]
   );
+  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
 }
 
 #[tokio::test]
-async fn transform_typescript_types_in_headers() {
+async fn module_specifier_mapping_remote_to_local() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
-        .add_local_file("/mod.ts", "export * from 'http://localhost/mod.js';")
-        .add_remote_file_with_headers(
-          "http://localhost/mod.js",
-          "function test() { return 5; }",
-          &[("x-typescript-types", "./declarations.d.ts")],
+        .add_local_file(
+          "/src/mod.ts",
+          "import { add } from 'http://localhost/mod.ts';",
+        )
+        .add_local_file(
+          "/vendor/math.ts",
+          "export function add(a: number, b: number) { return a + b; }",
         )
         .add_remote_file(
-          "http://localhost/declarations.d.ts",
-          "declare function test(): number;",
+          "http://localhost/mod.ts",
+          "export * from './math.ts';",
+        )
+        .add_remote_file(
+          "http://localhost/math.ts",
+          "export function add(a: number, b: number) { return eval('a + b'); }",
         );
     })
+    .entry_point("file:///src/mod.ts")
+    .add_module_specifier_mapping(
+      "http://localhost/math.ts",
+      "file:///vendor/math.ts",
+    )
     .transform()
     .await
     .unwrap();
@@ -806,394 +5596,276 @@ async fn transform_typescript_types_in_headers() {
   assert_files!(
     result.main.files,
     &[
-      ("mod.ts", "export * from './deps/localhost/mod.js';"),
-      ("deps/localhost/mod.js", "function test() { return 5; }"),
+      ("mod.ts", "import { add } from './deps/localhost/mod.js';"),
+      ("deps/localhost/mod.ts", "export * from './math.js';"),
       (
-        "deps/localhost/mod.d.ts",
-        "declare function test(): number;"
+        "deps/localhost/math.ts",
+        "export function add(a: number, b: number) { return a + b; }"
       ),
     ]
   );
 }
 
-#[tokio::test]
-async fn transform_typescript_types_in_deno_types() {
-  let result = TestBuilder::new()
-    .with_loader(|loader| {
-      loader.add_local_file("/mod.ts", "// @deno-types='./declarations.d.ts';\nexport * from 'http://localhost/mod.js';")
-      .add_remote_file("http://localhost/mod.js", "function test() { return 5; }")
-      .add_local_file("/declarations.d.ts", "declare function test(): number;");
-    })
-    .transform().await.unwrap();
+#[test]
+fn resolve_mapped_specifiers() {
+  let mut test_builder = TestBuilder::new();
+  test_builder
+    .add_package_specifier_mapping(
+      "https://deno.land/x/preact/hooks.ts",
+      "preact",
+      Some("^10.0.0"),
+      Some("hooks"),
+    )
+    .add_module_specifier_mapping(
+      "https://deno.land/x/pkg/mod.ts",
+      "file:///pkg.ts",
+    );
 
-  assert_files!(
-    result.main.files,
-    &[
-      ("mod.ts", "\nexport * from './deps/localhost/mod.js';"),
-      ("deps/localhost/mod.js", "function test() { return 5; }"),
-      (
-        "deps/localhost/mod.d.ts",
-        "declare function test(): number;"
-      ),
-    ]
+  assert_eq!(
+    test_builder
+      .resolve_mapped_specifier(
+        "https://deno.land/x/preact/hooks.ts",
+        "file:///mod.ts"
+      )
+      .unwrap(),
+    MappedResult::Package {
+      specifier: "preact/hooks".to_string(),
+      mapping: PackageMappedSpecifier {
+        name: "preact".to_string(),
+        version: Some("^10.0.0".to_string()),
+        sub_path: Some("hooks".to_string()),
+        peer_dependency: false,
+        types_package: None,
+      },
+    }
+  );
+  // relative to the referrer
+  assert_eq!(
+    test_builder
+      .resolve_mapped_specifier("./mod.ts", "https://deno.land/x/pkg/other.ts")
+      .unwrap(),
+    MappedResult::Module(ModuleSpecifier::parse("file:///pkg.ts").unwrap())
+  );
+  assert_eq!(
+    test_builder
+      .resolve_mapped_specifier("https://esm.sh/react@18.2.0", "file:///mod.ts")
+      .unwrap(),
+    MappedResult::Package {
+      specifier: "react".to_string(),
+      mapping: PackageMappedSpecifier {
+        name: "react".to_string(),
+        version: Some("18.2.0".to_string()),
+        sub_path: None,
+        peer_dependency: false,
+        types_package: None,
+      },
+    }
   );
+  assert_eq!(
+    test_builder
+      .resolve_mapped_specifier("./other.ts", "file:///mod.ts")
+      .unwrap(),
+    MappedResult::Unmapped(ModuleSpecifier::parse("file:///other.ts").unwrap())
+  );
+  assert!(test_builder
+    .resolve_mapped_specifier("preact", "file:///mod.ts")
+    .is_err());
 }
 
 #[tokio::test]
-async fn transform_typescript_type_references() {
+async fn redirect_entrypoint() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
-      loader.add_local_file("/mod.ts", "export * from 'http://localhost/mod.js';")
-      .add_remote_file("http://localhost/mod.js", "/// <reference types='./declarations.d.ts' />\nfunction test() { return 5; }")
-      .add_remote_file("http://localhost/declarations.d.ts", "declare function test(): number;");
+      loader
+        .add_local_file("/mod.deno.ts", "console.log(5);")
+        .add_local_file("/mod.node.ts", "5;");
     })
-    .transform().await.unwrap();
+    .entry_point("file:///mod.deno.ts")
+    .add_module_specifier_mapping("file:///mod.deno.ts", "file:///mod.node.ts")
+    .transform()
+    .await
+    .unwrap();
 
-  assert_files!(
-    result.main.files,
-    &[
-      ("mod.ts", "export * from './deps/localhost/mod.js';"),
-      ("deps/localhost/mod.js", "\nfunction test() { return 5; }"),
-      (
-        "deps/localhost/mod.d.ts",
-        "declare function test(): number;"
-      ),
-    ]
-  );
+  assert_files!(result.main.files, &[("mod.node.ts", "5;")]);
+  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.node.ts")]);
 }
 
 #[tokio::test]
-async fn transform_deno_types_and_type_ref_for_same_file() {
-  let result = TestBuilder::new()
+async fn redirect_not_found() {
+  let err_message = TestBuilder::new()
     .with_loader(|loader| {
-      loader.add_local_file("/mod.ts", "// @deno-types='./declarations.d.ts'\nexport * from './file.js';\n// @deno-types='./declarations.d.ts'\nexport * as test2 from './file.js';\nexport * from './other.ts';")
-      .add_local_file("/file.js", "/// <reference types='./declarations.d.ts' />\nfunction test() { return 5; }")
-      .add_local_file("/other.ts", "// @deno-types='./declarations.d.ts'\nexport * as other from './file.js';")
-      .add_local_file("/declarations.d.ts", "declare function test(): number;");
+      loader.add_local_file("/mod.ts", "console.log(5);");
     })
-    .transform().await.unwrap();
+    .add_module_specifier_mapping("file:///mod.deno.ts", "file:///mod.node.ts")
+    .transform()
+    .await
+    .err()
+    .unwrap();
 
-  assert!(result.warnings.is_empty());
-  assert_files!(
-    result.main.files,
-    &[
-      (
-        "mod.ts",
-        "\nexport * from './file.js';\n\nexport * as test2 from './file.js';\nexport * from './other.js';"
-      ),
-      (
-        "other.ts",
-        "\nexport * as other from './file.js';"
-      ),
-      ("file.js", "\nfunction test() { return 5; }"),
-      ("file.d.ts", "declare function test(): number;"),
-    ]
+  assert_eq!(
+    err_message.to_string(),
+    concat!(
+      "The following specifiers were indicated to be mapped to a module, but were not found:\n",
+      "  * file:///mod.deno.ts",
+    ),
   );
 }
 
 #[tokio::test]
-async fn transform_deno_types_and_type_ref_for_different_local_file() {
+async fn json_module_import_default() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
-      loader.add_local_file(
-        "/mod.ts",
-        "// @deno-types='./declarations.d.ts'\nexport * from './file.js';\nexport * from './other.ts';"
-      )
-      .add_local_file("/file.js", "/// <reference types='./declarations3.d.ts' />\nfunction test() { return 5; }")
-      .add_local_file("/other.ts", "// @deno-types='./declarations2.d.ts'\nexport * as other from './file.js';")
-      .add_local_file("/declarations.d.ts", "declare function test1(): number;")
-      .add_local_file("/declarations2.d.ts", "declare function test2(): number;")
-      .add_local_file("/declarations3.d.ts", "declare function test3(): number;");
+      loader
+        .add_local_file(
+          "/mod.ts",
+          r#"import jsonData from './data.json' assert { type: 'json' };"#,
+        )
+        .add_local_file("/data.json", "\u{FEFF}{ \"prop\": 5 }");
     })
-    .transform().await.unwrap();
+    .transform()
+    .await
+    .unwrap();
 
-  assert_eq!(
-    result.warnings,
-    vec![
-      concat!(
-        "Duplicate declaration file found for file:///file.js\n",
-        "  Specified file:///declarations.d.ts in file:///mod.ts\n",
-        "  Selected file:///declarations3.d.ts\n",
-        "  Supress this warning by having only one local file specify the declaration file for this module.",
-      ),
-      concat!(
-        "Duplicate declaration file found for file:///file.js\n",
-        "  Specified file:///declarations2.d.ts in file:///other.ts\n",
-        "  Selected file:///declarations3.d.ts\n",
-        "  Supress this warning by having only one local file specify the declaration file for this module.",
-      ),
-    ]
-  );
   assert_files!(
     result.main.files,
     &[
-      (
-        "mod.ts",
-        "\nexport * from './file.js';\nexport * from './other.js';"
-      ),
-      ("other.ts", "\nexport * as other from './file.js';"),
-      ("file.js", "\nfunction test() { return 5; }"),
-      ("file.d.ts", "declare function test3(): number;"),
+      ("mod.ts", r#"import jsonData from './data.js';"#),
+      ("data.js", r#"export default { "prop": 5 };"#)
     ]
   );
 }
 
 #[tokio::test]
-async fn transform_deno_types_and_type_ref_for_different_remote_file() {
-  fn setup() -> TestBuilder {
-    let mut test_builder = TestBuilder::new();
-    test_builder .with_loader(|loader| {
-        loader.add_local_file(
+async fn json_module_dynamic_import() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
           "/mod.ts",
-          "import 'http://localhost/mod.ts';"
-        )
-        .add_remote_file(
-          "http://localhost/mod.ts",
-          "// @deno-types='./declarations.d.ts'\nexport * from './file.js';\nexport * from './other.ts';"
+          r#"const jsonData = (await import('./data.json', { assert: { type: 'json' } })).default;"#
         )
-        .add_remote_file("http://localhost/file.js", "/// <reference types='./declarations3.d.ts' />\nfunction test() { return 5; }")
-        .add_remote_file("http://localhost/other.ts", "// @deno-types='./declarations2.d.ts'\nexport * as other from './file.js';")
-        .add_remote_file("http://localhost/declarations.d.ts", "declare function test1(): number;")
-        .add_remote_file("http://localhost/declarations2.d.ts", "declare function test2(): number;")
-        .add_remote_file("http://localhost/declarations3.d.ts", "declare function test3(): number;");
-      });
-    test_builder
-  }
-
-  let result = setup().transform().await.unwrap();
+        .add_local_file("/data.json", r#"{ "prop": 5 }"#);
+    })
+    .transform()
+    .await
+    .unwrap();
 
-  assert_eq!(
-    result.warnings,
-    vec![
-      concat!(
-        "Duplicate declaration file found for http://localhost/file.js\n",
-        "  Specified http://localhost/declarations.d.ts in http://localhost/mod.ts\n",
-        "  Selected http://localhost/declarations3.d.ts\n",
-        "  Supress this warning by specifying a declaration file for this module locally via `@deno-types`.",
-      ),
-      concat!(
-        "Duplicate declaration file found for http://localhost/file.js\n",
-        "  Specified http://localhost/declarations2.d.ts in http://localhost/other.ts\n",
-        "  Selected http://localhost/declarations3.d.ts\n",
-        "  Supress this warning by specifying a declaration file for this module locally via `@deno-types`.",
-      ),
-    ]
-  );
   assert_files!(
     result.main.files,
     &[
-      ("mod.ts", "import './deps/localhost/mod.js';",),
-      (
-        "deps/localhost/mod.ts",
-        "\nexport * from './file.js';\nexport * from './other.js';"
-      ),
-      (
-        "deps/localhost/other.ts",
-        "\nexport * as other from './file.js';"
-      ),
-      ("deps/localhost/file.js", "\nfunction test() { return 5; }"),
       (
-        "deps/localhost/file.d.ts",
-        "declare function test3(): number;"
+        "mod.ts",
+        r#"const jsonData = (await import('./data.js')).default;"#
       ),
+      ("data.js", r#"export default { "prop": 5 };"#)
     ]
   );
-
-  // Now specify the declaration file locally. This should clear out the warnings.
-  let mut test_builder = setup();
-  test_builder.with_loader(|loader| {
-    // overwrite the existing /mod.ts
-    loader.add_local_file(
-      "/mod.ts",
-      "import 'http://localhost/mod.ts';\n// @deno-types='http://localhost/declarations2.d.ts'\nimport * as test from 'http://localhost/file.js'",
-    );
-  });
-  let result = test_builder.transform().await.unwrap();
-
-  assert!(result.warnings.is_empty());
-  assert_eq!(result.main.files.len(), 5);
-  assert_eq!(
-    result
-      .main
-      .files
-      .iter()
-      .find(|f| f.file_path == PathBuf::from("deps/localhost/file.d.ts"))
-      .unwrap()
-      .file_text,
-    "declare function test2(): number;"
-  );
 }
 
 #[tokio::test]
-async fn transform_specifier_mappings() {
+async fn json_module_re_export() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
         .add_local_file(
           "/mod.ts",
-          concat!(
-            "import * as remote from 'http://localhost/mod.ts';\n",
-            "import * as local from './file.ts';\n",
-            "import * as entryA from 'http://localhost/mod/entryA.ts';\n",
-            "import * as entryB from 'http://localhost/mod/entryB.ts';\n",
-            "import * as entryC from 'http://localhost/mod/entryC.ts';\n",
-          ),
+          r#"export { default as Test } from './data.json' assert { type: "json" };"#
         )
-        .add_remote_file(
-          "http://localhost/mod.ts",
-          "import * as myOther from './other.ts';",
-        );
+        .add_local_file("/data.json", r#"{ "prop": 5 }"#);
     })
-    .add_package_specifier_mapping(
-      "http://localhost/mod.ts",
-      "remote-module",
-      Some("1.0.0"),
-      None,
-    )
-    .add_package_specifier_mapping(
-      "file:///file.ts",
-      "local-module",
-      None,
-      None,
-    )
-    .add_package_specifier_mapping(
-      "http://localhost/mod/entryA.ts",
-      "mod",
-      Some("~0.1.0"),
-      None,
-    )
-    .add_package_specifier_mapping(
-      "http://localhost/mod/entryB.ts",
-      "mod",
-      Some("~0.1.0"),
-      Some("entryB"),
-    )
-    .add_package_specifier_mapping(
-      "http://localhost/mod/entryC.ts",
-      "mod",
-      Some("~0.1.0"),
-      Some("other/entryC.js"),
-    )
     .transform()
     .await
     .unwrap();
 
   assert_files!(
     result.main.files,
-    &[(
-      "mod.ts",
-      concat!(
-        "import * as remote from 'remote-module';\n",
-        "import * as local from 'local-module';\n",
-        "import * as entryA from 'mod';\n",
-        "import * as entryB from 'mod/entryB';\n",
-        "import * as entryC from 'mod/other/entryC.js';\n",
-      )
-    )]
-  );
-  assert_eq!(
-    result.main.dependencies,
     &[
-      Dependency {
-        name: "mod".to_string(),
-        version: "~0.1.0".to_string(),
-        peer_dependency: false,
-      },
-      Dependency {
-        name: "remote-module".to_string(),
-        version: "1.0.0".to_string(),
-        peer_dependency: false,
-      }
+      ("mod.ts", r#"export { default as Test } from './data.js';"#),
+      ("data.js", r#"export default { "prop": 5 };"#)
     ]
   );
 }
 
 #[tokio::test]
-async fn transform_not_found_mappings() {
-  let error_message = TestBuilder::new()
+async fn import_attributes_by_type() {
+  let result = TestBuilder::new()
     .with_loader(|loader| {
-      loader.add_local_file("/mod.ts", "test");
+      loader
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import text from 'http://localhost/text.txt' assert { type: 'text' };\n",
+            "import data from 'http://localhost/data.json' with { type: 'json' };\n",
+            "import local from './data.json' with { type: 'json' };\n",
+            "const bytes = await import('http://localhost/bytes.bin', { with: { type: 'bytes' } });\n",
+          ),
+        )
+        .add_local_file("/data.json", r#"{ "prop": 5 }"#);
     })
     .add_package_specifier_mapping(
-      "http://localhost/mod.ts",
-      "local-module",
-      None,
+      "http://localhost/text.txt",
+      "text-pkg",
+      Some("^1.0.0"),
       None,
     )
     .add_package_specifier_mapping(
-      "http://localhost/mod2.ts",
-      "local-module2",
+      "http://localhost/data.json",
+      "data-pkg",
+      Some("^1.0.0"),
       None,
+    )
+    .add_package_specifier_mapping(
+      "http://localhost/bytes.bin",
+      "bytes-pkg",
+      Some("^1.0.0"),
       None,
     )
-    .transform()
-    .await
-    .err()
-    .unwrap();
-
-  assert_eq!(
-    error_message.to_string(),
-    "The following specifiers were indicated to be mapped to a package, but were not found:\n  * http://localhost/mod.ts\n  * http://localhost/mod2.ts"
-  );
-}
-
-#[tokio::test]
-async fn node_module_mapping() {
-  let result = TestBuilder::new()
-    .with_loader(|loader| {
-      loader
-        .add_local_file(
-          "/mod.ts",
-          concat!(
-            "import * as path from 'https://deno.land/std@0.143.0/node/path.ts';\n",
-            "import * as fs from 'https://deno.land/std/node/fs/promises.ts';",
-          ),
-        );
+    .set_import_attributes(ImportAttributeOptions {
+      types: [
+        ("text".to_string(), ImportAttributeAction::With),
+        ("json".to_string(), ImportAttributeAction::Keep),
+      ]
+      .into_iter()
+      .collect(),
+      default: ImportAttributeAction::Strip,
     })
     .transform()
     .await
     .unwrap();
 
   assert_files!(
-    result.main.files,
-    &[(
-      "mod.ts",
-      concat!(
-        "import * as path from 'path';\n",
-        "import * as fs from 'fs/promises';",
-      )
-    ),]
+    result.main.files,
+    &[
+      (
+        "mod.ts",
+        concat!(
+          "import text from 'text-pkg' with { type: 'text' };\n",
+          "import data from 'data-pkg' with { type: 'json' };\n",
+          "import local from './data.js';\n",
+          "const bytes = await import('bytes-pkg');\n",
+        )
+      ),
+      ("data.js", r#"export default { "prop": 5 };"#)
+    ]
   );
 }
 
 #[tokio::test]
-async fn skypack_esm_module_mapping() {
+async fn asset_module_imports() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
         .add_local_file(
           "/mod.ts",
           concat!(
-            "import package1 from 'https://cdn.skypack.dev/preact@^10.5.0';\n",
-            "import package2 from 'https://cdn.skypack.dev/@scope/package-name@1';\n",
-            "import package3 from 'https://esm.sh/react@17.0.2';\n",
-            // custom esm.sh stuff like this should download the dependency
-            "import package4 from 'https://esm.sh/swr?deps=react@16.14.0';\n",
-            "import package5 from 'https://esm.sh/test@1.2.5?deps=react@16.14.0';\n",
-            "import package6 from 'https://cdn.skypack.dev/preact@^10.5.0/hooks?dts';\n",
-            "import package7 from 'https://esm.sh/react-dom@17.0.2/server';\n",
+            "import styles from './styles.css' assert { type: 'css' };\n",
+            "import text from './data.txt' assert { type: \"text\" };",
           ),
         )
-        .add_remote_file_with_headers(
-          "https://esm.sh/swr?deps=react@16.14.0", "",
-          &[("content-type", "application/typescript")]
-        )
-        .add_remote_file_with_headers(
-          "https://esm.sh/test@1.2.5?deps=react@16.14.0",
-          "",
-          &[("content-type", "application/typescript")]
-       );
+        .add_local_file("/styles.css", "a {\n  content: \"\\\";\n}\n")
+        .add_local_file("/data.txt", "Hello");
     })
+    .set_asset_imports(true)
     .transform()
     .await
     .unwrap();
@@ -1204,92 +5876,70 @@ async fn skypack_esm_module_mapping() {
       (
         "mod.ts",
         concat!(
-          "import package1 from 'preact';\n",
-          "import package2 from '@scope/package-name';\n",
-          "import package3 from 'react';\n",
-          "import package4 from './deps/esm.sh/swr.js';\n",
-          "import package5 from './deps/esm.sh/test@1.2.5.js';\n",
-          "import package6 from 'preact/hooks';\n",
-          "import package7 from 'react-dom/server';\n",
+          "import styles from './styles.css.js';\n",
+          "import text from './data.txt.js';",
         )
       ),
-      ("deps/esm.sh/swr.ts", "",),
-      ("deps/esm.sh/test@1.2.5.ts", "",)
-    ]
-  );
-  assert_eq!(
-    result.main.dependencies,
-    &[
-      Dependency {
-        name: "@scope/package-name".to_string(),
-        version: "1".to_string(),
-        peer_dependency: false,
-      },
-      Dependency {
-        name: "preact".to_string(),
-        version: "^10.5.0".to_string(),
-        peer_dependency: false,
-      },
-      Dependency {
-        name: "react".to_string(),
-        version: "17.0.2".to_string(),
-        peer_dependency: false,
-      },
-      Dependency {
-        name: "react-dom".to_string(),
-        version: "17.0.2".to_string(),
-        peer_dependency: false,
-      }
+      (
+        "styles.css.js",
+        "export default \"a {\\n  content: \\\"\\\\\\\";\\n}\\n\";\n"
+      ),
+      ("styles.css", "a {\n  content: \"\\\";\n}\n"),
+      ("data.txt.js", "export default \"Hello\";\n"),
+      ("data.txt", "Hello"),
     ]
   );
 }
 
 #[tokio::test]
-async fn skypack_module_mapping_different_versions() {
-  let error_message = TestBuilder::new()
+async fn asset_module_imports_disabled() {
+  let err = TestBuilder::new()
     .with_loader(|loader| {
-      loader.add_local_file(
-        "/mod.ts",
-        concat!(
-          "import package1 from 'https://cdn.skypack.dev/preact@^10.5.0';\n",
-          "import package2 from 'https://cdn.skypack.dev/preact@^10.5.2';",
-        ),
-      );
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import styles from './styles.css' assert { type: 'css' };",
+        )
+        .add_local_file("/styles.css", "a {}");
     })
     .transform()
     .await
     .err()
     .unwrap();
 
-  assert_eq!(
-    error_message.to_string(),
-    "Specifier https://cdn.skypack.dev/preact@^10.5.0 with version ^10.5.0 did not match specifier https://cdn.skypack.dev/preact@^10.5.2 with version ^10.5.2."
-  );
+  assert!(err.to_string().ends_with(
+    "\n\nEnable asset imports to output a module that exports the text of the file."
+  ));
 }
 
 #[tokio::test]
-async fn esm_module_with_deno_types() {
+async fn asset_module_imports_handler() {
+  struct SqlHandler;
+
+  impl AssetHandler for SqlHandler {
+    fn handle_asset(&self, asset: &AssetFile) -> anyhow::Result<AssetOutput> {
+      assert_eq!(asset.attribute_type, "sql");
+      assert_eq!(asset.specifier.as_str(), "file:///query.sql");
+      Ok(AssetOutput {
+        module_text: format!(
+          "export default {};\n",
+          asset.text.trim().split(' ').count()
+        ),
+        copy_file: false,
+      })
+    }
+  }
+
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
         .add_local_file(
           "/mod.ts",
-          concat!(
-            "// @deno-types=\"https://localhost/mod.d.ts\"\n",
-            "import {test} from 'https://esm.sh/test@0.0.1/lib/mod.js';\n",
-          ),
-        )
-        .add_remote_file_with_headers(
-          "https://esm.sh/test@0.0.1/lib/mod.js",
-          "export function test() {return 5;}",
-          &[("content-type", "application/typescript")],
+          "import count from './query.sql' assert { type: 'sql' };",
         )
-        .add_remote_file_with_headers(
-          "https://localhost/mod.d.ts",
-          "declare function test(): number;",
-          &[("content-type", "application/typescript")],
-        );
+        .add_local_file("/query.sql", "SELECT * FROM users\n");
     })
+    .add_asset_handler("sql", SqlHandler)
     .transform()
     .await
     .unwrap();
@@ -1297,42 +5947,20 @@ async fn esm_module_with_deno_types() {
   assert_files!(
     result.main.files,
     &[
-      // this is a bug... it should create a proxy here instead,
-      // but will wait for someone to open this as it's probably
-      // rare for this to occur in the wild
-      ("mod.ts", "\nimport {test} from 'test/lib/mod.js';\n"),
-      (
-        "deps/localhost/mod.d.ts",
-        "declare function test(): number;",
-      )
+      ("mod.ts", "import count from './query.sql.js';"),
+      ("query.sql.js", "export default 4;\n"),
     ]
   );
 }
 
 #[tokio::test]
-async fn transform_import_map() {
+async fn issue_104() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
-        .add_local_file(
-          "/mod.ts",
-          "import * as remote from 'localhost/mod.ts';",
-        )
-        .add_local_file(
-          "/import_map.json",
-          r#"{
-  "imports": {
-    "localhost/": "/subdir/"
-  }
-}"#,
-        )
-        .add_local_file(
-          "/subdir/mod.ts",
-          "import * as myOther from './other.ts';",
-        )
-        .add_local_file("/subdir/other.ts", "export function test() {}");
+        .add_local_file("/mod.ts", "import type { other } from './test.ts'; import { test } from './test.ts'; test();")
+        .add_local_file("/test.ts", "export function test() {} export type other = string;");
     })
-    .set_import_map("file:///import_map.json")
     .transform()
     .await
     .unwrap();
@@ -1340,23 +5968,20 @@ async fn transform_import_map() {
   assert_files!(
     result.main.files,
     &[
-      ("mod.ts", "import * as remote from './subdir/mod.js';",),
-      ("subdir/mod.ts", "import * as myOther from './other.js';",),
-      ("subdir/other.ts", "export function test() {}",)
+      ("mod.ts", "import type { other } from './test.js'; import { test } from './test.js'; test();"),
+      ("test.ts", "export function test() {} export type other = string;"),
     ]
   );
 }
 
 #[tokio::test]
-async fn transform_multiple_entry_points() {
+async fn local_declaration_file_import() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
-        .add_local_file("/mod.ts", "import './ref.ts';mod1;")
-        .add_local_file("/mod2.ts", "import './ref.ts';mod2;")
-        .add_local_file("/ref.ts", "export const test = 5;");
+        .add_local_file("/mod.ts", "import type { A } from './types.d.ts';")
+        .add_local_file("/types.d.ts", "export interface A {}");
     })
-    .add_entry_point("file:///mod2.ts")
     .transform()
     .await
     .unwrap();
@@ -1364,142 +5989,79 @@ async fn transform_multiple_entry_points() {
   assert_files!(
     result.main.files,
     &[
-      ("mod.ts", "import './ref.js';mod1;"),
-      ("mod2.ts", "import './ref.js';mod2;"),
-      ("ref.ts", "export const test = 5;"),
+      ("mod.ts", "import type { A } from './types';"),
+      ("types.d.ts", "export interface A {}"),
     ]
   );
 }
 
 #[tokio::test]
-async fn test_entry_points() {
+async fn remote_declaration_file_import() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
         .add_local_file(
           "/mod.ts",
-          "import package1 from 'https://cdn.skypack.dev/preact@^10.5.0';\n",
-        )
-        .add_local_file(
-          "/mod.test.ts",
           concat!(
-            "import './mod.ts';\n",
-            "import package1 from 'https://cdn.skypack.dev/preact@^10.5.0';\n",
-            "import package3 from 'https://esm.sh/react@17.0.2';\n",
-            "Deno.writeTextFile('test', 'test')",
-          ),
-        );
+            "import type { RawSourceMap } from 'https://esm.sh/source-map@0.7.3/source-map.d.ts';\n",
+            "import type { Other } from 'https://localhost/source-map.d.ts';",
+          )
+        )
+        .add_remote_file("https://esm.sh/source-map@0.7.3/source-map.d.ts", "export interface RawSourceMap {}")
+        .add_remote_file("https://localhost/source-map.d.ts", "export interface Other {}");
     })
-    .add_test_entry_point("file:///mod.test.ts")
-    .add_default_shims()
     .transform()
     .await
     .unwrap();
 
-  assert_files!(
-    result.main.files,
-    &[("mod.ts", "import package1 from 'preact';\n",)]
-  );
-  assert_eq!(
-    result.main.dependencies,
-    &[Dependency {
-      name: "preact".to_string(),
-      version: "^10.5.0".to_string(),
-      peer_dependency: false,
-    },]
-  );
-  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
-
-  assert_files!(
-    result.test.files,
-    &[
-      (
-        "mod.test.ts",
-        concat!(
-          "import * as dntShim from \"./_dnt.test_shims.js\";\n",
-          "import './mod.js';\n",
-          "import package1 from 'preact';\n",
-          "import package3 from 'react';\n",
-          "dntShim.Deno.writeTextFile('test', 'test')"
-        )
-        .to_string(),
-      ),
-      (
-        "_dnt.test_shims.ts",
-        get_shim_file_text(
-          concat!(
-            "import { Deno } from \"@deno/shim-deno\";\n",
-            "export { Deno } from \"@deno/shim-deno\";\n",
-            "import { setTimeout, setInterval } from \"@deno/shim-timers\";\n",
-            "export { setTimeout, setInterval } from \"@deno/shim-timers\";\n",
-            "\n",
-            "const dntGlobals = {\n",
-            "  Deno,\n",
-            "  setTimeout,\n",
-            "  setInterval,\n",
-            "};\n",
-            "export const dntGlobalThis = createMergeProxy(globalThis, dntGlobals);\n",
-          )
-          .to_string(),
-        ),
-      )
-    ]
-  );
-  assert_eq!(
-    result.test.dependencies,
-    &[
-      Dependency {
-        name: "react".to_string(),
-        version: "17.0.2".to_string(),
-        peer_dependency: false,
-      },
-      Dependency {
-        name: "@deno/shim-deno".to_string(),
-        version: "^0.1.0".to_string(),
-        peer_dependency: false,
-      },
-      Dependency {
-        name: "@deno/shim-timers".to_string(),
-        version: "^0.1.0".to_string(),
-        peer_dependency: false,
-      }
-    ]
-  );
-  assert_eq!(result.test.entry_points, &[PathBuf::from("mod.test.ts")]);
+  assert_files!(result.main.files, &[
+    (
+      "mod.ts",
+      concat!(
+        "import type { RawSourceMap } from './deps/esm.sh/source-map@0.7.3/source-map';\n",
+        "import type { Other } from './deps/localhost/source-map';",
+    )),
+    ("deps/esm.sh/source-map@0.7.3/source-map.d.ts", "export interface RawSourceMap {}"),
+    ("deps/localhost/source-map.d.ts", "export interface Other {}"),
+  ]);
 }
 
 #[tokio::test]
-async fn test_entry_points_same_module_multiple_places() {
+async fn remote_declaration_file_references() {
   let result = TestBuilder::new()
-    .with_loader(|loader| {
-      loader
-        .add_local_file(
-          "/mod.ts",
-          concat!(
-            "export * from 'https://deno.land/std@0.143.0/path.ts';\n",
-            "import * as deps from './deps.ts';",
-          ),
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "import 'http://localhost/mod.js';")
+        .add_remote_file_with_headers(
+          "http://localhost/mod.js",
+          "export const value = 5;",
+          &[
+            ("content-type", "application/javascript"),
+            ("x-typescript-types", "./types/mod.d.ts"),
+          ],
         )
-        // ensure that the path.ts in this file being already analyzed
-        // doesn't cause flags.ts to not be analyzed
-        .add_local_file(
-          "/deps.ts",
+        .add_remote_file(
+          "http://localhost/types/mod.d.ts",
           concat!(
-            "export * from 'https://deno.land/std@0.143.0/path.ts';\n",
-            "export * from 'https://deno.land/std@0.143.0/flags.ts';",
+            "/// <reference path=\"./globals.d.ts\" />\n",
+            "/// <reference types=\"./other.d.ts\" />\n",
+            "import type { A } from './a.d.ts';\n",
+            "export declare const value: A;",
           ),
         )
         .add_remote_file(
-          "https://deno.land/std@0.143.0/flags.ts",
-          "export class Flags {}",
+          "http://localhost/types/globals.d.ts",
+          "declare var Global: string;",
         )
         .add_remote_file(
-          "https://deno.land/std@0.143.0/path.ts",
-          "export class Path {}",
+          "http://localhost/types/other.d.ts",
+          "declare var Other: string;",
         )
-        .add_local_file("/mod.test.ts", "import * as deps from './deps.ts';");
+        .add_remote_file(
+          "http://localhost/types/a.d.ts",
+          "export type A = number;",
+        );
     })
-    .add_test_entry_point("file:///mod.test.ts")
     .transform()
     .await
     .unwrap();
@@ -1507,56 +6069,65 @@ async fn test_entry_points_same_module_multiple_places() {
   assert_files!(
     result.main.files,
     &[
+      ("mod.ts", "import './deps/localhost/mod.js';"),
+      ("deps/localhost/mod.js", "export const value = 5;"),
       (
-        "mod.ts",
+        "deps/localhost/mod.d.ts",
         concat!(
-          "export * from './deps/deno.land/std@0.143.0/path.js';\n",
-          "import * as deps from './deps.js';",
-        )
+          "/// <reference path=\"./types/globals.d.ts\" />\n",
+          "/// <reference types=\"./types/other\" />\n",
+          "import type { A } from './types/a';\n",
+          "export declare const value: A;",
+        ),
       ),
       (
-        "deps.ts",
-        concat!(
-          "export * from './deps/deno.land/std@0.143.0/path.js';\n",
-          "export * from './deps/deno.land/std@0.143.0/flags.js';",
-        )
+        "deps/localhost/types/globals.d.ts",
+        "declare var Global: string;"
       ),
       (
-        "deps/deno.land/std@0.143.0/flags.ts",
-        "export class Flags {}"
+        "deps/localhost/types/other.d.ts",
+        "declare var Other: string;"
       ),
-      ("deps/deno.land/std@0.143.0/path.ts", "export class Path {}")
+      ("deps/localhost/types/a.d.ts", "export type A = number;"),
     ]
   );
-  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
+}
+
+#[tokio::test]
+async fn import_type_change_specifier() {
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          r#"export type Test = import('./other.ts').Test"#,
+        )
+        .add_local_file("/other.ts", "export type Test = string;");
+    })
+    .transform()
+    .await
+    .unwrap();
 
   assert_files!(
-    result.test.files,
-    &[("mod.test.ts", "import * as deps from './deps.js';",)]
+    result.main.files,
+    &[
+      ("mod.ts", r#"export type Test = import('./other.js').Test"#),
+      ("other.ts", "export type Test = string;")
+    ]
   );
-  assert_eq!(result.test.entry_points, &[PathBuf::from("mod.test.ts")]);
 }
 
 #[tokio::test]
-async fn polyfills_all() {
+async fn module_decl_string_literal_change_specifier() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
         .add_local_file(
           "/mod.ts",
-          concat!(
-            "export const test = (obj) => Object.hasOwn(obj, 'test');\n",
-            "try {\n",
-            "} catch (err) {\n",
-            "  err.cause = new Error();\n",
-            "}\n",
-            "''.replaceAll('test', 'other');\n",
-            "[].findLast(() => true);\n",
-          ),
+          r#"import Test from './other.ts'; declare module './other.ts' {}"#,
         )
-        .add_local_file("/mod.test.ts", "import * as mod from './mod.ts';");
+        .add_local_file("/other.ts", "export type Test = string;");
     })
-    .add_test_entry_point("file:///mod.test.ts")
     .transform()
     .await
     .unwrap();
@@ -1566,446 +6137,1290 @@ async fn polyfills_all() {
     &[
       (
         "mod.ts",
-        concat!(
-          "import \"./_dnt.polyfills.js\";\n",
-          "export const test = (obj) => Object.hasOwn(obj, 'test');\n",
-          "try {\n",
-          "} catch (err) {\n",
-          "  err.cause = new Error();\n",
-          "}\n",
-          "''.replaceAll('test', 'other');\n",
-          "[].findLast(() => true);\n",
-        ),
+        r#"import Test from './other.js'; declare module './other.js' {}"#
       ),
-      (
-        "_dnt.polyfills.ts",
-        concat!(
-          include_str!("../src/polyfills/scripts/esnext.object-has-own.ts"),
-          include_str!("../src/polyfills/scripts/esnext.error-cause.ts"),
-          include_str!("../src/polyfills/scripts/es2021.string-replaceAll.ts"),
-          include_str!("../src/polyfills/scripts/esnext.array-findLast.ts"),
+      ("other.ts", "export type Test = string;")
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_write_to() {
+  let out_dir = std::env::temp_dir()
+    .join(format!("dnt_transform_write_to_{}", std::process::id()));
+  let _ = std::fs::remove_dir_all(&out_dir);
+  let mut test_builder = TestBuilder::new();
+  test_builder.with_loader(|loader| {
+    loader
+      .add_local_file("/mod.ts", "import './a.ts';\nimport './sub/b.ts';")
+      .add_local_file("/a.ts", "export {};")
+      .add_local_file("/sub/b.ts", "export {};");
+  });
+  let options = WriteOptions {
+    remove_stale_files: true,
+  };
+
+  let result = test_builder.transform().await.unwrap();
+  let write_result = result.write_to(&out_dir, &options).unwrap();
+  assert_eq!(
+    write_result.created,
+    vec![
+      PathBuf::from("a.ts"),
+      PathBuf::from("mod.ts"),
+      PathBuf::from("sub/b.ts")
+    ]
+  );
+  assert_eq!(
+    std::fs::read_to_string(out_dir.join(MANIFEST_FILE_NAME)).unwrap(),
+    "a.ts\nmod.ts\nsub/b.ts\n"
+  );
+
+  // writing the same output doesn't change anything
+  let write_result = result.write_to(&out_dir, &options).unwrap();
+  assert!(write_result.created.is_empty());
+  assert!(write_result.updated.is_empty());
+  assert_eq!(write_result.unchanged.len(), 3);
+
+  test_builder.with_loader(|loader| {
+    loader
+      .add_local_file("/mod.ts", "import './a.ts';")
+      .add_local_file("/a.ts", "export const a = 5;");
+  });
+  let result = test_builder.transform().await.unwrap();
+  let write_result = result.write_to(&out_dir, &options).unwrap();
+  assert_eq!(
+    write_result,
+    WriteResult {
+      created: Vec::new(),
+      updated: vec![PathBuf::from("a.ts"), PathBuf::from("mod.ts")],
+      unchanged: Vec::new(),
+      removed: vec![PathBuf::from("sub/b.ts")],
+    }
+  );
+  assert!(!out_dir.join("sub").exists());
+  assert_eq!(
+    std::fs::read_to_string(out_dir.join("a.ts")).unwrap(),
+    "export const a = 5;"
+  );
+
+  std::fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[tokio::test]
+async fn transform_output_diff() {
+  let mut test_builder = TestBuilder::new();
+  test_builder
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import './a.ts';\nimport 'https://localhost/preact.ts';",
+        )
+        .add_local_file("/a.ts", "export const a = 1;\n");
+    })
+    .add_package_specifier_mapping(
+      "https://localhost/preact.ts",
+      "preact",
+      Some("^10.0.0"),
+      None,
+    );
+  let previous = test_builder.transform().await.unwrap();
+  assert!(previous.diff(&previous).is_empty());
+
+  test_builder
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import './b.ts';\nimport 'https://localhost/preact.ts';",
+        )
+        .add_local_file("/b.ts", "export const b = 1;\n");
+    })
+    .add_package_specifier_mapping(
+      "https://localhost/preact.ts",
+      "preact",
+      Some("^10.1.0"),
+      None,
+    );
+  let result = test_builder.transform().await.unwrap();
+  let diff = result.diff(&previous);
+  assert_eq!(diff.added_files, vec![PathBuf::from("b.ts")]);
+  assert_eq!(diff.removed_files, vec![PathBuf::from("a.ts")]);
+  assert_eq!(
+    diff
+      .modified_files
+      .iter()
+      .map(|f| f.file_path.clone())
+      .collect::<Vec<_>>(),
+    vec![PathBuf::from("mod.ts")]
+  );
+  assert_eq!(
+    diff.dependency_changes,
+    vec![DependencyChange {
+      environment: OutputEnvironment::Main,
+      name: "preact".to_string(),
+      previous_version: Some("^10.0.0".to_string()),
+      version: Some("^10.1.0".to_string()),
+    }]
+  );
+  assert!(diff
+    .to_string()
+    .contains("Changed dependency preact from ^10.0.0 to ^10.1.0\n"));
+}
+
+#[tokio::test]
+async fn transform_manifest() {
+  let mut test_builder = TestBuilder::new();
+  test_builder
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import 'http://localhost/mod.ts';\nDeno.cwd();",
         )
+        .add_local_file("/mod.test.ts", "import './mod.ts';")
+        .add_remote_file("http://localhost/mod.ts", "export {};");
+    })
+    .add_default_shims()
+    .add_test_entry_point("file:///mod.test.ts");
+
+  let result = test_builder.transform().await.unwrap();
+  assert!(result.manifest.is_none());
+
+  let result = test_builder
+    .set_emit_manifest(true)
+    .transform()
+    .await
+    .unwrap();
+  let manifest = result.manifest.unwrap();
+  assert_eq!(manifest.entry_points, vec!["file:///mod.ts"]);
+  assert_eq!(manifest.test_entry_points, vec!["file:///mod.test.ts"]);
+  assert_eq!(
+    manifest.mappings,
+    BTreeMap::from([
+      ("file:///mod.ts".to_string(), "mod.ts".to_string()),
+      ("file:///mod.test.ts".to_string(), "mod.test.ts".to_string()),
+      (
+        "http://localhost/mod.ts".to_string(),
+        "deps/localhost/mod.ts".to_string()
       ),
+    ])
+  );
+  assert_eq!(manifest.remote_modules, vec!["http://localhost/mod.ts"]);
+  assert_eq!(
+    manifest
+      .dependencies
+      .iter()
+      .map(|d| d.name.as_str())
+      .collect::<Vec<_>>(),
+    vec!["@deno/shim-deno"]
+  );
+  assert!(manifest.used_shims);
+  assert!(!manifest.used_test_shims);
+
+  // the options hash only changes when the options change
+  let options_hash = manifest.options_hash;
+  let result = test_builder.transform().await.unwrap();
+  assert_eq!(result.manifest.unwrap().options_hash, options_hash);
+  let result = test_builder
+    .set_error_on_cycles(true)
+    .transform()
+    .await
+    .unwrap();
+  assert_ne!(result.manifest.unwrap().options_hash, options_hash);
+}
+
+#[tokio::test]
+async fn transform_with_cache_dir() {
+  let cache_dir = std::env::temp_dir().join(format!(
+    "dnt_transform_with_cache_dir_{}",
+    std::process::id()
+  ));
+  let _ = std::fs::remove_dir_all(&cache_dir);
+  let mut test_builder = TestBuilder::new();
+  test_builder
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "import './other.ts';")
+        .add_local_file("/other.ts", "window;");
+    })
+    .set_cache_dir(&cache_dir);
+
+  let result = test_builder.transform().await.unwrap();
+  assert_files!(
+    result.main.files,
+    &[
+      ("mod.ts", "import './other.js';"),
+      ("other.ts", "globalThis;")
     ]
   );
-  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
+  assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 2);
+
+  // same output from the cache
+  let cached_result = test_builder.transform().await.unwrap();
+  assert_eq!(cached_result.main.files, result.main.files);
+  assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 2);
 
+  // only the changed module gets a new entry
+  test_builder.with_loader(|loader| {
+    loader.add_local_file("/other.ts", "window.test;");
+  });
+  let result = test_builder.transform().await.unwrap();
   assert_files!(
-    result.test.files,
-    &[("mod.test.ts", concat!("import * as mod from './mod.js';",),)]
+    result.main.files,
+    &[
+      ("mod.ts", "import './other.js';"),
+      ("other.ts", "globalThis.test;")
+    ]
   );
-  assert_eq!(result.test.entry_points, &[PathBuf::from("mod.test.ts")]);
+  assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 3);
+
+  std::fs::remove_dir_all(&cache_dir).unwrap();
 }
 
 #[tokio::test]
-async fn polyfills_string_replaceall_target() {
-  test_string_replace_all_polyfill(ScriptTarget::ES2020, true).await;
-  test_string_replace_all_polyfill(ScriptTarget::ES2021, false).await;
-}
+async fn transform_with_sink() {
+  struct CollectingSink(Mutex<Vec<(OutputEnvironment, OutputFile)>>);
 
-async fn test_string_replace_all_polyfill(
-  target: ScriptTarget,
-  should_have_polyfill: bool,
-) {
+  impl OutputSink for CollectingSink {
+    fn write_file(
+      &self,
+      environment: OutputEnvironment,
+      file: OutputFile,
+    ) -> anyhow::Result<()> {
+      self.0.lock().unwrap().push((environment, file));
+      Ok(())
+    }
+  }
+
+  let sink = CollectingSink(Default::default());
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
-        .add_local_file("/mod.ts", "''.replaceAll('test', 'other');\n")
-        .add_local_file("/mod.test.ts", "import * as mod from './mod.ts';");
+        .add_local_file("/mod.ts", "import './other.ts';")
+        .add_local_file("/other.ts", "Deno.cwd();")
+        .add_local_file("/mod.test.ts", "import './mod.ts';");
     })
     .add_test_entry_point("file:///mod.test.ts")
-    .set_target(target)
-    .transform()
+    .add_default_shims()
+    .transform_with_sink(&sink)
     .await
     .unwrap();
 
-  if should_have_polyfill {
-    assert_files!(
-      result.main.files,
-      &[
-        (
-          "mod.ts",
-          concat!(
-            "import \"./_dnt.polyfills.js\";\n",
-            "''.replaceAll('test', 'other');\n",
-          ),
-        ),
-        (
-          "_dnt.polyfills.ts",
-          concat!(include_str!(
-            "../src/polyfills/scripts/es2021.string-replaceAll.ts"
-          ),)
+  assert!(result.main.files.is_empty());
+  assert!(result.test.files.is_empty());
+
+  let mut files = sink
+    .0
+    .into_inner()
+    .unwrap()
+    .into_iter()
+    .map(|(environment, file)| (environment, file.file_path))
+    .collect::<Vec<_>>();
+  files.sort_by(|a, b| a.1.cmp(&b.1));
+  assert_eq!(
+    files,
+    vec![
+      (OutputEnvironment::Main, PathBuf::from("_dnt.shims.ts")),
+      (OutputEnvironment::Main, PathBuf::from("mod.ts")),
+      (OutputEnvironment::Test, PathBuf::from("mod.test.ts")),
+      (OutputEnvironment::Main, PathBuf::from("other.ts")),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn transform_custom_visitors() {
+  struct HeaderVisitor;
+
+  impl TransformVisitor for HeaderVisitor {
+    fn visit(
+      &self,
+      context: &TransformVisitorContext,
+    ) -> anyhow::Result<Vec<TextChange>> {
+      Ok(vec![TextChange {
+        range: 0..0,
+        new_text: format!(
+          "// {} ({:?})\n",
+          context.output_path(context.specifier).unwrap().display(),
+          context.environment,
         ),
-      ]
-    );
-  } else {
-    assert_files!(
-      result.main.files,
-      &[("mod.ts", "''.replaceAll('test', 'other');\n",)]
-    );
+      }])
+    }
   }
-  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
+
+  let result = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file("/mod.ts", "import './other.ts';")
+        .add_local_file("/other.ts", "export class Test {}")
+        .add_local_file("/mod.test.ts", "import './mod.ts';");
+    })
+    .add_test_entry_point("file:///mod.test.ts")
+    .add_custom_visitor(HeaderVisitor)
+    .transform()
+    .await
+    .unwrap();
+
+  assert_files!(
+    result.main.files,
+    &[
+      ("mod.ts", "// mod.ts (Main)\nimport './other.js';"),
+      ("other.ts", "// other.ts (Main)\nexport class Test {}"),
+    ]
+  );
+  assert_files!(
+    result.test.files,
+    &[("mod.test.ts", "// mod.test.ts (Test)\nimport './mod.js';")]
+  );
 }
 
 #[tokio::test]
-async fn polyfills_test_files() {
+async fn transform_file_text_handler() {
+  struct BannerHandler;
+
+  impl FileTextHandler for BannerHandler {
+    fn handle_file_text(
+      &self,
+      environment: OutputEnvironment,
+      specifier: &ModuleSpecifier,
+      file: &mut OutputFile,
+    ) -> anyhow::Result<()> {
+      file.file_text =
+        format!("// {} ({:?})\n{}", specifier, environment, file.file_text);
+      Ok(())
+    }
+  }
+
   let result = TestBuilder::new()
     .with_loader(|loader| {
-      loader.add_local_file("/mod.ts", "").add_local_file(
-        "/mod.test.ts",
-        "// Some copyright text\nObject.hasOwn({}, 'prop');",
-      );
+      loader
+        .add_local_file("/mod.ts", "import './other.ts';")
+        .add_local_file("/other.ts", "export class Test {}")
+        .add_local_file("/mod.test.ts", "import './mod.ts';\nDeno.cwd();");
     })
     .add_test_entry_point("file:///mod.test.ts")
+    .add_default_shims()
+    .set_file_text_handler(BannerHandler)
     .transform()
     .await
     .unwrap();
 
-  assert_files!(result.main.files, &[("mod.ts", "",)]);
-  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
-
   assert_files!(
-    result.test.files,
+    result.main.files,
     &[
+      ("mod.ts", "// file:///mod.ts (Main)\nimport './other.js';"),
       (
-        "mod.test.ts",
-        concat!(
-          "// Some copyright text\n",
-          "import \"./_dnt.test_polyfills.js\";\n\n",
-          "Object.hasOwn({}, 'prop');"
-        )
+        "other.ts",
+        "// file:///other.ts (Main)\nexport class Test {}"
       ),
-      (
-        "_dnt.test_polyfills.ts",
-        include_str!("../src/polyfills/scripts/esnext.object-has-own.ts"),
-      )
     ]
   );
-  assert_eq!(result.test.entry_points, &[PathBuf::from("mod.test.ts")]);
+  let mut test_banners = result
+    .test
+    .files
+    .iter()
+    .map(|file| file.file_text.lines().next().unwrap())
+    .collect::<Vec<_>>();
+  test_banners.sort();
+  assert_eq!(
+    test_banners,
+    vec![
+      "// dnt://_dnt.test_shims.ts (Test)",
+      "// file:///mod.test.ts (Test)"
+    ]
+  );
 }
 
 #[tokio::test]
-async fn polyfills_object_has_own_conflict() {
-  // should not do a polyfill because of Object
+async fn transform_version_policy_and_handler() {
+  struct PeerRangeHandler;
+
+  impl VersionHandler for PeerRangeHandler {
+    fn handle_version(
+      &self,
+      _environment: OutputEnvironment,
+      dependency: &Dependency,
+    ) -> anyhow::Result<String> {
+      Ok(if dependency.peer_dependency {
+        format!(">={}", dependency.version)
+      } else {
+        dependency.version.clone()
+      })
+    }
+  }
+
   let result = TestBuilder::new()
     .with_loader(|loader| {
-      loader
-        .add_local_file("/mod.ts", "export class Object {} Object.hasOwn();");
+      loader.add_local_file(
+        "/mod.ts",
+        concat!(
+          "import 'http://localhost/a.ts';\n",
+          "import 'http://localhost/b.ts';\n",
+          "import 'http://localhost/c.ts';\n",
+        ),
+      );
     })
+    .add_package_specifier_mapping(
+      "http://localhost/a.ts",
+      "a",
+      Some("^1.2.3"),
+      None,
+    )
+    .add_package_specifier_mapping(
+      "http://localhost/b.ts",
+      "b",
+      Some(">=1.0.0 <2.0.0"),
+      None,
+    )
+    .add_specifier_mapping(
+      "http://localhost/c.ts",
+      MappedSpecifier::Package(PackageMappedSpecifier {
+        name: "c".to_string(),
+        version: Some("~2.0.1".to_string()),
+        sub_path: None,
+        peer_dependency: true,
+        types_package: None,
+      }),
+    )
+    .set_version_policy(VersionPolicy::Exact)
+    .set_version_handler(PeerRangeHandler)
     .transform()
     .await
     .unwrap();
 
-  assert_files!(
-    result.main.files,
-    &[("mod.ts", "export class Object {} Object.hasOwn();")]
+  assert_eq!(
+    result.main.dependencies,
+    vec![
+      Dependency {
+        name: "a".to_string(),
+        version: "1.2.3".to_string(),
+        peer_dependency: false,
+      },
+      Dependency {
+        name: "b".to_string(),
+        version: ">=1.0.0 <2.0.0".to_string(),
+        peer_dependency: false,
+      },
+      Dependency {
+        name: "c".to_string(),
+        version: ">=2.0.1".to_string(),
+        peer_dependency: true,
+      },
+    ]
   );
 }
 
 #[tokio::test]
-async fn module_specifier_mapping_general() {
-  let result = TestBuilder::new()
+async fn analyze_graph() {
+  let graph_info = TestBuilder::new()
     .with_loader(|loader| {
       loader
-        .add_local_file("/mod.ts", "import './other.deno.ts';")
-        .add_local_file("/other.deno.ts", "console.log(5);")
         .add_local_file(
-          "/other.node.ts",
+          "/mod.ts",
           concat!(
-            "import * as fs from 'fs';\n",
-            "import { myFunction } from './myFunction.ts'\n",
-            "export function test() {\n",
-            "  // dnt-shim-ignore\n",
-            "  Deno.readFileSync('test');\n",
-            "  Object.hasOwn({}, 'prop');\n",
-            "}",
+            "import './other.ts';\n",
+            "import 'https://localhost/mod.js';\n",
+            "import 'https://deno.land/x/mod.ts';",
           ),
         )
-        .add_local_file("/myFunction.ts", "export function myFunction() {}");
+        .add_local_file("/other.ts", "export class Other {}")
+        .add_local_file("/mod.test.ts", "import './mod.ts';")
+        .add_remote_file_with_headers(
+          "https://localhost/mod.js",
+          "export function test() {}",
+          &[("x-typescript-types", "./mod.d.ts")],
+        )
+        .add_remote_file(
+          "https://localhost/mod.d.ts",
+          "export function test(): void;",
+        )
+        .add_remote_file("https://deno.land/x/mod.ts", "");
     })
-    .add_module_specifier_mapping(
-      "file:///other.deno.ts",
-      "file:///other.node.ts",
+    .add_test_entry_point("file:///mod.test.ts")
+    .add_package_specifier_mapping(
+      "https://deno.land/x/mod.ts",
+      "mod",
+      Some("1.0.0"),
+      None,
     )
-    .transform()
+    .analyze()
     .await
     .unwrap();
 
-  assert_files!(
-    result.main.files,
-    &[
-      (
-        "mod.ts",
-        concat!(
-          "import \"./_dnt.polyfills.js\";\n",
-          "import './other.node.js';"
-        ),
-      ),
-      (
-        "other.node.ts",
-        concat!(
-          "import * as fs from 'fs';\n",
-          "import { myFunction } from './myFunction.js'\n",
-          "export function test() {\n",
-          "  // dnt-shim-ignore\n",
-          "  Deno.readFileSync('test');\n",
-          "  Object.hasOwn({}, 'prop');\n",
-          "}",
-        )
-      ),
-      ("myFunction.ts", "export function myFunction() {}",),
+  assert_eq!(
+    graph_info
+      .modules
+      .iter()
+      .map(|m| (m.specifier.as_str(), m.environment))
+      .collect::<Vec<_>>(),
+    vec![
+      ("file:///mod.test.ts", OutputEnvironment::Test),
+      ("file:///mod.ts", OutputEnvironment::Main),
+      ("file:///other.ts", OutputEnvironment::Main),
+      ("https://deno.land/x/mod.ts", OutputEnvironment::Main),
+      ("https://localhost/mod.d.ts", OutputEnvironment::Main),
+      ("https://localhost/mod.js", OutputEnvironment::Main),
+    ]
+  );
+
+  let mod_info = graph_info
+    .get(&ModuleSpecifier::parse("file:///mod.ts").unwrap())
+    .unwrap();
+  assert_eq!(mod_info.output_path, Some(PathBuf::from("mod.ts")));
+  assert_eq!(
+    mod_info
+      .dependencies
+      .iter()
+      .map(|d| (d.value.as_str(), d.code.as_ref().map(|s| s.as_str())))
+      .collect::<Vec<_>>(),
+    vec![
+      ("./other.ts", Some("file:///other.ts")),
       (
-        "_dnt.polyfills.ts",
-        include_str!("../src/polyfills/scripts/esnext.object-has-own.ts")
+        "https://deno.land/x/mod.ts",
+        Some("https://deno.land/x/mod.ts")
       ),
+      ("https://localhost/mod.js", Some("https://localhost/mod.js")),
     ]
   );
-  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.ts")]);
+
+  let remote_info = graph_info
+    .get(&ModuleSpecifier::parse("https://localhost/mod.js").unwrap())
+    .unwrap();
+  assert_eq!(
+    remote_info.declaration_file,
+    Some(ModuleSpecifier::parse("https://localhost/mod.d.ts").unwrap())
+  );
+  assert_eq!(remote_info.size, "export function test() {}".len());
+
+  let mapped_info = graph_info
+    .get(&ModuleSpecifier::parse("https://deno.land/x/mod.ts").unwrap())
+    .unwrap();
+  assert_eq!(mapped_info.output_path, None);
+  assert_eq!(mapped_info.mapped_package.as_ref().unwrap().name, "mod");
 }
 
 #[tokio::test]
-async fn redirect_entrypoint() {
-  let result = TestBuilder::new()
+async fn analyze_graph_dot_and_json() {
+  let graph_info = TestBuilder::new()
     .with_loader(|loader| {
       loader
-        .add_local_file("/mod.deno.ts", "console.log(5);")
-        .add_local_file("/mod.node.ts", "5;");
+        .add_local_file(
+          "/mod.ts",
+          "import './other.ts';\nimport 'https://deno.land/x/mod.ts';",
+        )
+        .add_local_file("/other.ts", "")
+        .add_local_file("/mod.test.ts", "import './mod.ts';");
     })
-    .entry_point("file:///mod.deno.ts")
-    .add_module_specifier_mapping("file:///mod.deno.ts", "file:///mod.node.ts")
-    .transform()
+    .add_test_entry_point("file:///mod.test.ts")
+    .add_package_specifier_mapping(
+      "https://deno.land/x/mod.ts",
+      "mod",
+      Some("1.0.0"),
+      None,
+    )
+    .analyze()
     .await
     .unwrap();
 
-  assert_files!(result.main.files, &[("mod.node.ts", "5;")]);
-  assert_eq!(result.main.entry_points, &[PathBuf::from("mod.node.ts")]);
+  assert_eq!(
+    graph_info.to_dot(),
+    concat!(
+      "digraph {\n",
+      "  \"file:///mod.test.ts\" [style=dashed];\n",
+      "  \"file:///mod.ts\";\n",
+      "  \"file:///other.ts\";\n",
+      "  \"https://deno.land/x/mod.ts\" [shape=box, label=\"mod\\nhttps://deno.land/x/mod.ts\"];\n",
+      "  \"file:///mod.test.ts\" -> \"file:///mod.ts\";\n",
+      "  \"file:///mod.ts\" -> \"file:///other.ts\";\n",
+      "  \"file:///mod.ts\" -> \"https://deno.land/x/mod.ts\";\n",
+      "}\n",
+    )
+  );
+  assert_eq!(
+    graph_info.to_json(),
+    concat!(
+      "{\"modules\":[",
+      "{\"specifier\":\"file:///mod.test.ts\",\"mediaType\":\"TypeScript\",\"environment\":\"test\",",
+      "\"size\":18,\"outputPath\":\"mod.test.ts\",\"mappedPackage\":null,\"declarationFile\":null,",
+      "\"referrerChain\":[\"file:///mod.test.ts\"],",
+      "\"dependencies\":[{\"value\":\"./mod.ts\",\"code\":\"file:///mod.ts\",\"types\":null}]},",
+      "{\"specifier\":\"file:///mod.ts\",\"mediaType\":\"TypeScript\",\"environment\":\"main\",",
+      "\"size\":57,\"outputPath\":\"mod.ts\",\"mappedPackage\":null,\"declarationFile\":null,",
+      "\"referrerChain\":[\"file:///mod.ts\"],",
+      "\"dependencies\":[{\"value\":\"./other.ts\",\"code\":\"file:///other.ts\",\"types\":null},",
+      "{\"value\":\"https://deno.land/x/mod.ts\",\"code\":\"https://deno.land/x/mod.ts\",\"types\":null}]},",
+      "{\"specifier\":\"file:///other.ts\",\"mediaType\":\"TypeScript\",\"environment\":\"main\",",
+      "\"size\":0,\"outputPath\":\"other.ts\",\"mappedPackage\":null,\"declarationFile\":null,",
+      "\"referrerChain\":[\"file:///mod.ts\",\"file:///other.ts\"],",
+      "\"dependencies\":[]},",
+      "{\"specifier\":\"https://deno.land/x/mod.ts\",\"mediaType\":\"JavaScript\",\"environment\":\"main\",",
+      "\"size\":0,\"outputPath\":null,",
+      "\"mappedPackage\":{\"name\":\"mod\",\"version\":\"1.0.0\",\"subPath\":null},",
+      "\"declarationFile\":null,",
+      "\"referrerChain\":[\"file:///mod.ts\",\"https://deno.land/x/mod.ts\"],",
+      "\"dependencies\":[]}",
+      "]}",
+    )
+  );
 }
 
 #[tokio::test]
-async fn redirect_not_found() {
-  let err_message = TestBuilder::new()
+async fn analyze_graph_explain() {
+  let graph_info = TestBuilder::new()
     .with_loader(|loader| {
-      loader.add_local_file("/mod.ts", "console.log(5);");
+      loader
+        .add_local_file("/mod.ts", "import './deps.ts';")
+        .add_local_file(
+          "/deps.ts",
+          "import 'https://localhost/large.js';\nimport 'https://deno.land/x/mod.ts';",
+        )
+        .add_remote_file("https://localhost/large.js", "export {};")
+        .add_remote_file("https://deno.land/x/mod.ts", "export {};")
+        .add_local_file("/mod.test.ts", "import './deps.ts';");
     })
-    .add_module_specifier_mapping("file:///mod.deno.ts", "file:///mod.node.ts")
-    .transform()
+    .add_test_entry_point("file:///mod.test.ts")
+    .add_package_specifier_mapping(
+      "https://deno.land/x/mod.ts",
+      "mod",
+      Some("1.0.0"),
+      None,
+    )
+    .analyze()
     .await
-    .err()
     .unwrap();
 
+  let explain = |specifier: &str| {
+    graph_info.explain(&ModuleSpecifier::parse(specifier).unwrap())
+  };
   assert_eq!(
-    err_message.to_string(),
+    explain("file:///mod.ts").unwrap(),
+    "file:///mod.ts is an entry point of the main environment."
+  );
+  assert_eq!(
+    explain("https://localhost/large.js").unwrap(),
     concat!(
-      "The following specifiers were indicated to be mapped to a module, but were not found:\n",
-      "  * file:///mod.deno.ts",
-    ),
+      "https://localhost/large.js is in the main environment because it's ",
+      "imported through file:///mod.ts -> file:///deps.ts -> https://localhost/large.js",
+    )
+  );
+  assert_eq!(
+    explain("https://deno.land/x/mod.ts").unwrap(),
+    concat!(
+      "https://deno.land/x/mod.ts is in the main environment because it's ",
+      "imported through file:///mod.ts -> file:///deps.ts -> https://deno.land/x/mod.ts\n",
+      "It's mapped to mod instead of being output.",
+    )
+  );
+  assert_eq!(
+    explain("file:///mod.test.ts").unwrap(),
+    "file:///mod.test.ts is an entry point of the test environment."
+  );
+  assert_eq!(explain("file:///other.ts"), None);
+}
+
+#[tokio::test]
+async fn classify_modules() {
+  let classification = TestBuilder::new()
+    .with_loader(|loader| {
+      loader
+        .add_local_file(
+          "/mod.ts",
+          "import 'https://localhost/mod.js';\nimport 'https://deno.land/x/mod.ts';",
+        )
+        .add_local_file(
+          "/mod.test.ts",
+          "import './mod.ts';\nimport 'https://localhost/test.ts';\nimport 'https://deno.land/x/test.ts';",
+        )
+        .add_remote_file_with_headers(
+          "https://localhost/mod.js",
+          "export function test() {}",
+          &[("x-typescript-types", "./mod.d.ts")],
+        )
+        .add_remote_file(
+          "https://localhost/mod.d.ts",
+          "export function test(): void;",
+        )
+        .add_remote_file("https://localhost/test.ts", "");
+    })
+    .add_test_entry_point("file:///mod.test.ts")
+    .add_package_specifier_mapping(
+      "https://deno.land/x/mod.ts",
+      "mod",
+      Some("1.0.0"),
+      None,
+    )
+    .add_package_specifier_mapping(
+      "https://deno.land/x/test.ts",
+      "test-package",
+      Some("2.0.0"),
+      None,
+    )
+    .classify_modules()
+    .await
+    .unwrap();
+
+  let specifier = |text: &str| ModuleSpecifier::parse(text).unwrap();
+  assert_eq!(
+    classification.main,
+    EnvironmentClassification {
+      local: vec![specifier("file:///mod.ts")],
+      remote: vec![specifier("https://localhost/mod.js")],
+      types: BTreeMap::from([(
+        specifier("https://localhost/mod.js"),
+        specifier("https://localhost/mod.d.ts"),
+      )]),
+      mapped: BTreeMap::from([(
+        specifier("https://deno.land/x/mod.ts"),
+        PackageMappedSpecifier {
+          name: "mod".to_string(),
+          version: Some("1.0.0".to_string()),
+          sub_path: None,
+          peer_dependency: false,
+          types_package: None,
+        },
+      )]),
+      dependencies: vec![Dependency {
+        name: "mod".to_string(),
+        version: "1.0.0".to_string(),
+        peer_dependency: false,
+      }],
+    }
+  );
+  assert_eq!(
+    classification.test,
+    EnvironmentClassification {
+      local: vec![specifier("file:///mod.test.ts")],
+      remote: vec![specifier("https://localhost/test.ts")],
+      types: BTreeMap::new(),
+      mapped: BTreeMap::from([(
+        specifier("https://deno.land/x/test.ts"),
+        PackageMappedSpecifier {
+          name: "test-package".to_string(),
+          version: Some("2.0.0".to_string()),
+          sub_path: None,
+          peer_dependency: false,
+          types_package: None,
+        },
+      )]),
+      dependencies: vec![Dependency {
+        name: "test-package".to_string(),
+        version: "2.0.0".to_string(),
+        peer_dependency: false,
+      }],
+    }
+  );
+  assert!(classification.untyped.is_empty());
+  assert!(classification.excluded.is_empty());
+}
+
+#[tokio::test]
+async fn transform_circular_dependencies() {
+  let mut test_builder = TestBuilder::new();
+  test_builder.with_loader(|loader| {
+    loader
+      .add_local_file("/mod.ts", "import './a.ts';")
+      .add_local_file("/a.ts", "import './b.ts';")
+      .add_local_file("/b.ts", "import './a.ts';");
+  });
+
+  let result = test_builder.transform().await.unwrap();
+  assert_eq!(
+    result.warnings,
+    vec![concat!(
+      "Circular dependency found: ",
+      "file:///a.ts -> file:///b.ts -> file:///a.ts"
+    )]
+  );
+
+  let err = test_builder
+    .set_error_on_cycles(true)
+    .transform()
+    .await
+    .err()
+    .unwrap();
+  assert_eq!(
+    err.to_string(),
+    "Circular dependency found: file:///a.ts -> file:///b.ts -> file:///a.ts"
   );
+  match err.downcast_ref::<TransformError>().unwrap() {
+    TransformError::Cycles { cycles, .. } => {
+      assert_eq!(cycles.len(), 1);
+      assert_eq!(cycles[0].len(), 3);
+    }
+    err => panic!("Unexpected error: {:?}", err),
+  }
 }
 
 #[tokio::test]
-async fn json_module_import_default() {
-  let result = TestBuilder::new()
-    .with_loader(|loader| {
-      loader
-        .add_local_file(
-          "/mod.ts",
-          r#"import jsonData from './data.json' assert { type: 'json' };"#,
-        )
-        .add_local_file("/data.json", "\u{FEFF}{ \"prop\": 5 }");
-    })
+async fn transform_duplicate_packages() {
+  let mut test_builder = TestBuilder::new();
+  test_builder.with_loader(|loader| {
+    loader
+      .add_local_file(
+        "/mod.ts",
+        "import 'https://deno.land/x/pkg@1.0.0/mod.ts';\nimport './a.ts';",
+      )
+      .add_local_file("/a.ts", "import 'https://deno.land/x/pkg@2.0.0/mod.ts';")
+      .add_remote_file("https://deno.land/x/pkg@1.0.0/mod.ts", "1;")
+      .add_remote_file("https://deno.land/x/pkg@2.0.0/mod.ts", "2;");
+  });
+
+  let expected_message = concat!(
+    "Multiple versions of https://deno.land/x/pkg were found:\n",
+    "  1.0.0 (output to deps/deno.land/x/pkg@1.0.0): file:///mod.ts -> https://deno.land/x/pkg@1.0.0/mod.ts\n",
+    "  2.0.0 (output to deps/deno.land/x/pkg@2.0.0): file:///mod.ts -> file:///a.ts -> https://deno.land/x/pkg@2.0.0/mod.ts",
+  );
+  let result = test_builder.transform().await.unwrap();
+  assert_eq!(result.warnings, vec![expected_message]);
+  assert_eq!(result.duplicate_packages.len(), 1);
+  assert_eq!(
+    result.duplicate_packages[0]
+      .versions
+      .iter()
+      .map(|v| v.version.as_str())
+      .collect::<Vec<_>>(),
+    vec!["1.0.0", "2.0.0"]
+  );
+
+  let err = test_builder
+    .set_error_on_duplicate_packages(true)
     .transform()
     .await
+    .err()
     .unwrap();
-
-  assert_files!(
-    result.main.files,
-    &[
-      ("mod.ts", r#"import jsonData from './data.js';"#),
-      ("data.js", r#"export default { "prop": 5 };"#)
-    ]
-  );
+  assert_eq!(err.to_string(), expected_message);
+  match err.downcast_ref::<TransformError>().unwrap() {
+    TransformError::DuplicatePackages { packages, .. } => {
+      assert_eq!(packages.len(), 1);
+      assert_eq!(packages[0].name, "https://deno.land/x/pkg");
+    }
+    err => panic!("Unexpected error: {:?}", err),
+  }
 }
 
 #[tokio::test]
-async fn json_module_dynamic_import() {
+async fn transform_duplicate_packages_coexist() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
         .add_local_file(
           "/mod.ts",
-          r#"const jsonData = (await import('./data.json', { assert: { type: 'json' } })).default;"#
+          concat!(
+            "import 'https://deno.land/x/a@1.0.0/mod.ts';\n",
+            "import 'https://deno.land/x/b@1.0.0/mod.ts';\n",
+          ),
         )
-        .add_local_file("/data.json", r#"{ "prop": 5 }"#);
+        .add_remote_file(
+          "https://deno.land/x/a@1.0.0/mod.ts",
+          "export * from 'https://deno.land/x/pkg@1.0.0/mod.ts';",
+        )
+        .add_remote_file(
+          "https://deno.land/x/b@1.0.0/mod.ts",
+          "export * from 'https://deno.land/x/pkg@2.0.0/mod.ts';",
+        )
+        .add_remote_file(
+          "https://deno.land/x/pkg@1.0.0/mod.ts",
+          "export * from './util.ts';",
+        )
+        .add_remote_file(
+          "https://deno.land/x/pkg@1.0.0/util.ts",
+          "export const version = 1;",
+        )
+        .add_remote_file(
+          "https://deno.land/x/pkg@2.0.0/mod.ts",
+          "export * from './util.ts';",
+        )
+        .add_remote_file(
+          "https://deno.land/x/pkg@2.0.0/util.ts",
+          "export const version = 2;",
+        );
     })
     .transform()
     .await
     .unwrap();
 
+  // each version imports its own copy of the modules
   assert_files!(
     result.main.files,
     &[
       (
         "mod.ts",
-        r#"const jsonData = (await import('./data.js')).default;"#
+        concat!(
+          "import './deps/deno.land/x/a@1.0.0/mod.js';\n",
+          "import './deps/deno.land/x/b@1.0.0/mod.js';\n",
+        )
       ),
-      ("data.js", r#"export default { "prop": 5 };"#)
+      (
+        "deps/deno.land/x/a@1.0.0/mod.ts",
+        "export * from '../pkg@1.0.0/mod.js';"
+      ),
+      (
+        "deps/deno.land/x/b@1.0.0/mod.ts",
+        "export * from '../pkg@2.0.0/mod.js';"
+      ),
+      (
+        "deps/deno.land/x/pkg@1.0.0/mod.ts",
+        "export * from './util.js';"
+      ),
+      (
+        "deps/deno.land/x/pkg@1.0.0/util.ts",
+        "export const version = 1;"
+      ),
+      (
+        "deps/deno.land/x/pkg@2.0.0/mod.ts",
+        "export * from './util.js';"
+      ),
+      (
+        "deps/deno.land/x/pkg@2.0.0/util.ts",
+        "export const version = 2;"
+      ),
+    ]
+  );
+  assert_eq!(
+    result.duplicate_packages[0]
+      .versions
+      .iter()
+      .map(|v| v.output_dir.clone().unwrap())
+      .collect::<Vec<_>>(),
+    vec![
+      PathBuf::from("deps/deno.land/x/pkg@1.0.0"),
+      PathBuf::from("deps/deno.land/x/pkg@2.0.0"),
     ]
   );
 }
 
 #[tokio::test]
-async fn json_module_re_export() {
-  let result = TestBuilder::new()
-    .with_loader(|loader| {
-      loader
-        .add_local_file(
-          "/mod.ts",
-          r#"export { default as Test } from './data.json' assert { type: "json" };"#
-        )
-        .add_local_file("/data.json", r#"{ "prop": 5 }"#);
+async fn transform_remote_policy() {
+  let mut test_builder = TestBuilder::new();
+  test_builder.with_loader(|loader| {
+    loader
+      .add_local_file("/mod.ts", "import 'https://deno.land/x/a.ts';")
+      .add_remote_file(
+        "https://deno.land/x/a.ts",
+        "import 'https://deno.land/x/b.ts';\nimport 'https://other.example/c.ts';",
+      )
+      .add_remote_file("https://deno.land/x/b.ts", "1;");
+  });
+
+  // the module of the denied origin isn't loaded
+  let err = test_builder
+    .set_remote_policy(RemotePolicy {
+      allowed_origins: vec!["https://deno.land".to_string()],
+      ..Default::default()
     })
     .transform()
     .await
+    .err()
     .unwrap();
-
-  assert_files!(
-    result.main.files,
-    &[
-      ("mod.ts", r#"export { default as Test } from './data.js';"#),
-      ("data.js", r#"export default { "prop": 5 };"#)
-    ]
+  assert_eq!(
+    err.to_string(),
+    concat!(
+      "The remote policy doesn't allow loading https://other.example/c.ts from https://other.example.\n",
+      "  Referrer chain: file:///mod.ts -> https://deno.land/x/a.ts -> https://other.example/c.ts",
+    )
   );
-}
+  match err.downcast_ref::<TransformError>().unwrap() {
+    TransformError::RemotePolicy { referrer_chain, .. } => {
+      assert_eq!(referrer_chain.len(), 3);
+    }
+    err => panic!("Unexpected error: {:?}", err),
+  }
 
-#[tokio::test]
-async fn issue_104() {
-  let result = TestBuilder::new()
-    .with_loader(|loader| {
-      loader
-        .add_local_file("/mod.ts", "import type { other } from './test.ts'; import { test } from './test.ts'; test();")
-        .add_local_file("/test.ts", "export function test() {} export type other = string;");
+  test_builder.with_loader(|loader| {
+    loader.add_remote_file("https://other.example/c.ts", "2;");
+  });
+  let err = test_builder
+    .set_remote_policy(RemotePolicy {
+      max_depth: Some(1),
+      ..Default::default()
     })
     .transform()
     .await
+    .err()
     .unwrap();
-
-  assert_files!(
-    result.main.files,
-    &[
-      ("mod.ts", "import type { other } from './test.js'; import { test } from './test.js'; test();"),
-      ("test.ts", "export function test() {} export type other = string;"),
-    ]
+  assert_eq!(
+    err.to_string(),
+    concat!(
+      "https://deno.land/x/b.ts is imported through 2 remote modules, which is more than the maximum depth of 1 in the remote policy.\n",
+      "  Referrer chain: file:///mod.ts -> https://deno.land/x/a.ts -> https://deno.land/x/b.ts",
+      "\n\n",
+      "https://other.example/c.ts is imported through 2 remote modules, which is more than the maximum depth of 1 in the remote policy.\n",
+      "  Referrer chain: file:///mod.ts -> https://deno.land/x/a.ts -> https://other.example/c.ts",
+    )
   );
+
+  let result = test_builder
+    .set_remote_policy(RemotePolicy {
+      denied_origins: vec!["https://evil.example".to_string()],
+      max_depth: Some(2),
+      ..Default::default()
+    })
+    .transform()
+    .await
+    .unwrap();
+  assert!(result
+    .main
+    .files
+    .iter()
+    .any(|f| f.file_path == PathBuf::from("deps/other.example/c.ts")));
 }
 
 #[tokio::test]
-async fn local_declaration_file_import() {
+async fn transform_output_stats() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
       loader
-        .add_local_file("/mod.ts", "import type { A } from './types.d.ts';")
-        .add_local_file("/types.d.ts", "export interface A {}");
+        .add_local_file(
+          "/mod.ts",
+          concat!(
+            "import 'https://localhost/a.ts';\n",
+            "import 'https://localhost/b.ts';\n",
+            "Deno.cwd();",
+          ),
+        )
+        .add_remote_file("https://localhost/a.ts", "export const a = 'a';")
+        .add_remote_file(
+          "https://localhost/b.ts",
+          "export const b = 'a much longer module';",
+        );
+    })
+    .add_default_shims()
+    .set_size_budget(SizeBudget {
+      max_total_bytes: Some(100),
+      max_file_bytes: Some(50),
     })
     .transform()
     .await
     .unwrap();
 
-  assert_files!(
-    result.main.files,
-    &[
-      ("mod.ts", "import type { A } from './types';"),
-      ("types.d.ts", "export interface A {}"),
+  let get_size = |path: &str| {
+    result
+      .main
+      .files
+      .iter()
+      .find(|f| f.file_path == PathBuf::from(path))
+      .unwrap()
+      .file_text
+      .len()
+  };
+  let stats = &result.main.stats;
+  assert_eq!(stats.file_count, result.main.files.len());
+  assert_eq!(
+    stats.total_bytes,
+    result
+      .main
+      .files
+      .iter()
+      .map(|f| f.file_text.len())
+      .sum::<usize>()
+  );
+  assert_eq!(
+    stats
+      .largest_remote_modules
+      .iter()
+      .map(|m| (m.specifier.as_str(), m.bytes))
+      .collect::<Vec<_>>(),
+    vec![
+      ("https://localhost/b.ts", get_size("deps/localhost/b.ts")),
+      ("https://localhost/a.ts", get_size("deps/localhost/a.ts")),
     ]
   );
+  assert_eq!(
+    stats.largest_remote_modules[0]
+      .referrer_chain
+      .iter()
+      .map(|s| s.as_str())
+      .collect::<Vec<_>>(),
+    vec!["file:///mod.ts", "https://localhost/b.ts"]
+  );
+  assert_eq!(stats.shimmed_api_count, 1);
+  assert_eq!(result.test.stats.file_count, 0);
+
+  let mut expected_warnings = vec![format!(
+    "The output is {} bytes, which is over the size budget of 100 bytes.",
+    stats.total_bytes
+  )];
+  for file in result.main.files.iter() {
+    if file.file_text.len() > 50 {
+      let mut warning = format!(
+        "{} is {} bytes, which is over the size budget of 50 bytes for a file.",
+        file.file_path.display(),
+        file.file_text.len()
+      );
+      if let Ok(path) = file.file_path.strip_prefix("deps/localhost") {
+        warning.push_str(&format!(
+          "\n  Referrer chain: file:///mod.ts -> https://localhost/{}",
+          path.display()
+        ));
+      }
+      expected_warnings.push(warning);
+    }
+  }
+  assert_eq!(expected_warnings.len(), 3);
+  assert_eq!(result.warnings, expected_warnings);
 }
 
 #[tokio::test]
-async fn remote_declaration_file_import() {
-  let result = TestBuilder::new()
+async fn transform_main_code_imports_test_entry_point() {
+  let err_message = TestBuilder::new()
     .with_loader(|loader| {
       loader
-        .add_local_file(
-          "/mod.ts",
-          concat!(
-            "import type { RawSourceMap } from 'https://esm.sh/source-map@0.7.3/source-map.d.ts';\n",
-            "import type { Other } from 'https://localhost/source-map.d.ts';",
-          )
-        )
-        .add_remote_file("https://esm.sh/source-map@0.7.3/source-map.d.ts", "export interface RawSourceMap {}")
-        .add_remote_file("https://localhost/source-map.d.ts", "export interface Other {}");
+        .add_local_file("/mod.ts", "import './other.ts';")
+        .add_local_file("/other.ts", "\nimport './mod.test.ts';")
+        .add_local_file("/mod.test.ts", "import './mod.ts';");
     })
+    .add_test_entry_point("file:///mod.test.ts")
     .transform()
     .await
+    .err()
     .unwrap();
+  assert_eq!(
+    err_message.to_string(),
+    concat!(
+      "Test entry point file:///mod.test.ts is imported by code in the main environment.\n",
+      "    at file:///other.ts:2:8",
+    )
+  );
+}
 
-  assert_files!(result.main.files, &[
-    (
-      "mod.ts",
-      concat!(
-        "import type { RawSourceMap } from './deps/esm.sh/source-map@0.7.3/source-map';\n",
-        "import type { Other } from './deps/localhost/source-map';",
-    )),
-    ("deps/esm.sh/source-map@0.7.3/source-map.d.ts", "export interface RawSourceMap {}"),
-    ("deps/localhost/source-map.d.ts", "export interface Other {}"),
-  ]);
+fn get_shim_file_text(mut text: String) -> String {
+  text.push('\n');
+  text.push_str(
+    &include_str!("../src/scripts/createMergeProxy.ts")
+      .replace("export function", "function"),
+  );
+  text
 }
 
 #[tokio::test]
-async fn import_type_change_specifier() {
+async fn transform_timings() {
   let result = TestBuilder::new()
     .with_loader(|loader| {
-      loader
-        .add_local_file(
-          "/mod.ts",
-          r#"export type Test = import('./other.ts').Test"#,
-        )
-        .add_local_file("/other.ts", "export type Test = string;");
+      loader.add_local_file("/mod.ts", "export const a = Deno.cwd();");
     })
+    .add_default_shims()
     .transform()
     .await
     .unwrap();
 
-  assert_files!(
-    result.main.files,
-    &[
-      ("mod.ts", r#"export type Test = import('./other.js').Test"#),
-      ("other.ts", "export type Test = string;")
-    ]
+  // only measured with the tracing feature
+  match result.timings {
+    Some(timings) => {
+      assert!(cfg!(feature = "tracing"));
+      assert!(timings.total >= timings.build_graph);
+    }
+    None => assert!(!cfg!(feature = "tracing")),
+  }
+}
+
+#[tokio::test]
+async fn transform_deterministic_paths() {
+  let files = [
+    (
+      "http://localhost/mod.ts",
+      "import './a.ts';\nimport './A.ts';\nimport './b/mod.ts';\nimport './B/mod.ts';",
+    ),
+    ("http://localhost/a.ts", "export const a = 1;"),
+    ("http://localhost/A.ts", "export const a = 2;"),
+    ("http://localhost/b/mod.ts", "export const b = 1;"),
+    ("http://localhost/B/mod.ts", "export const b = 2;"),
+  ];
+  let transform_with_order = |reverse: bool| async move {
+    let mut builder = TestBuilder::new();
+    builder.with_loader(|loader| {
+      loader.add_local_file("/mod.ts", "import 'http://localhost/mod.ts';");
+      let mut files = files.to_vec();
+      if reverse {
+        files.reverse();
+      }
+      for (specifier, text) in files {
+        loader.add_remote_file(specifier, text);
+      }
+    });
+    builder.transform().await.unwrap().main.files
+  };
+
+  // the files are transformed on multiple threads with the parallel
+  // feature, so run it a few times to catch any dependence on the order
+  let expected = transform_with_order(false).await;
+  for i in 0..10 {
+    assert_eq!(transform_with_order(i % 2 == 0).await, expected);
+  }
+  assert_eq!(
+    expected
+      .iter()
+      .map(|f| f.file_path.to_string_lossy().to_string())
+      .collect::<Vec<_>>(),
+    [
+      "deps/localhost/A.ts",
+      "deps/localhost/B/mod.ts",
+      "deps/localhost/a_2.ts",
+      "deps/localhost/b_2/mod.ts",
+      "deps/localhost/mod.ts",
+      "mod.ts",
+    ],
   );
 }
 
 #[tokio::test]
-async fn module_decl_string_literal_change_specifier() {
-  let result = TestBuilder::new()
+async fn transform_local_roots() {
+  let mut builder = TestBuilder::new();
+  builder
     .with_loader(|loader| {
       loader
         .add_local_file(
-          "/mod.ts",
-          r#"import Test from './other.ts'; declare module './other.ts' {}"#,
+          "/project/src/mod.ts",
+          "import { a } from '../../shared/src/a.ts';\nimport './sub/b.ts';\nexport { a };",
         )
-        .add_local_file("/other.ts", "export type Test = string;");
+        .add_local_file("/project/src/sub/b.ts", "import '../../../other/c.ts';")
+        .add_local_file("/other/c.ts", "export const c = 5;")
+        .add_local_file("/shared/src/a.ts", "export const a = 5;");
     })
-    .transform()
-    .await
-    .unwrap();
+    .entry_point("file:///project/src/mod.ts")
+    .add_local_root("file:///project/src", "")
+    .add_local_root("file:///shared/src/", "shared");
 
+  let result = builder.transform().await.unwrap();
   assert_files!(
     result.main.files,
     &[
       (
         "mod.ts",
-        r#"import Test from './other.js'; declare module './other.js' {}"#
+        "import { a } from './shared/a.js';\nimport './sub/b.js';\nexport { a };",
       ),
-      ("other.ts", "export type Test = string;")
+      ("sub/b.ts", "import '../c.js';"),
+      ("c.ts", "export const c = 5;"),
+      ("shared/a.ts", "export const a = 5;"),
+    ]
+  );
+  assert_eq!(
+    result.local_roots,
+    vec![
+      LocalRoot {
+        dir: ModuleSpecifier::parse("file:///other/").unwrap(),
+        output_dir: PathBuf::from(""),
+      },
+      LocalRoot {
+        dir: ModuleSpecifier::parse("file:///project/src/").unwrap(),
+        output_dir: PathBuf::from(""),
+      },
+      LocalRoot {
+        dir: ModuleSpecifier::parse("file:///shared/src/").unwrap(),
+        output_dir: PathBuf::from("shared"),
+      },
     ]
   );
-}
 
-fn get_shim_file_text(mut text: String) -> String {
-  text.push('\n');
-  text.push_str(
-    &include_str!("../src/scripts/createMergeProxy.ts")
-      .replace("export function", "function"),
+  // the output directory must be within the output
+  let err = builder
+    .add_local_root("file:///other/", "../other")
+    .transform()
+    .await
+    .err()
+    .unwrap();
+  assert_eq!(
+    err.to_string(),
+    "Invalid output directory \"../other\" of the local root file:///other/. It must be a relative path within the output.",
   );
-  text
 }
@@ -0,0 +1,39 @@
+// Copyright 2018-2022 the Deno authors. All rights reserved. MIT license.
+
+import { assertEquals } from "./lib/test.deps.ts";
+import { path } from "./lib/transform.deps.ts";
+import { transform } from "./transform.ts";
+
+Deno.test("transform output maps are plain objects", async () => {
+  const entryPoint = path.resolve("mod.ts");
+  const files: { [url: string]: string } = {
+    [path.toFileUrl(entryPoint).href]:
+      "export * from 'https://example.com/mod.ts';\n",
+    "https://example.com/mod.ts": "export const value = 5;\n",
+  };
+  const output = await transform({
+    entryPoints: [entryPoint],
+    typescriptPaths: true,
+    emitManifest: true,
+    load: (url) =>
+      files[url] == null ? undefined : { specifier: url, content: files[url] },
+  });
+
+  assertEquals(output.exports["."], {
+    import: {
+      types: "./types/mod.d.ts",
+      default: "./esm/mod.js",
+    },
+    require: {
+      types: "./types/mod.d.ts",
+      default: "./script/mod.js",
+    },
+  });
+  assertEquals(output.typescriptPaths["https://example.com/*"], [
+    "./deps/example.com/*",
+  ]);
+  assertEquals(
+    output.manifest!.mappings["https://example.com/mod.ts"],
+    "deps/example.com/mod.ts",
+  );
+});
@@ -6,7 +6,7 @@ import { ScriptTarget } from "./lib/types.ts";
 
 /** Specifier to specifier mappings. */
 export interface SpecifierMappings {
-  /** Map a specifier to another module or npm package. */
+  /** Map a specifier to another module (ex. a local copy of a remote module) or npm package. */
   [specifier: string]: PackageMappedSpecifier | string;
 }
 
@@ -28,6 +28,12 @@ export interface PackageMappedSpecifier {
   subPath?: string;
   /** If this should be a peer dependency. */
   peerDependency?: boolean;
+  /** Npm package that has the type declarations for this package (ex. `@types/node`).
+   *
+   * This is added as a dependency and any `@deno-types` declaration file
+   * for the specifier will not be output.
+   */
+  typesPackage?: Dependency;
 }
 
 export interface GlobalName {
@@ -59,15 +65,204 @@ export interface ModuleShim {
   globalNames: (GlobalName | string)[];
 }
 
+export interface JsxOptions {
+  runtime: "react" | "react-jsx" | "react-jsxdev" | "precompile";
+  /** Specifier the automatic runtime is imported from (ex. `https://esm.sh/preact`). */
+  importSource?: string;
+}
+
+/** Restrictions on the remote modules that may be loaded, which are errors with the chain of imports that led to the module when they're violated. */
+export interface RemotePolicy {
+  /** Origins remote modules may be loaded from (ex. `https://deno.land`). Any origin is allowed when this is empty. */
+  allowedOrigins?: string[];
+  /** Origins remote modules may not be loaded from, which takes precedence over the allowed origins. */
+  deniedOrigins?: string[];
+  /** Maximum number of remote modules a chain of imports from an entry point may go through. */
+  maxDepth?: number;
+}
+
+/** Limits on the size of the main output, which are reported as warnings when they're exceeded. */
+export interface SizeBudget {
+  /** Maximum total size of the output files in bytes. */
+  maxTotalBytes?: number;
+  /** Maximum size of a single output file in bytes. */
+  maxFileBytes?: number;
+}
+
+export interface LocalRoot {
+  /** Path or file url of the directory. */
+  dir: string;
+  /** Path in the output relative to the output root, which is empty for the output root. */
+  outputDir: string;
+}
+
+export interface NodeGlobalShims {
+  /** Package to import `Buffer` from (ex. the `buffer` package). */
+  buffer?: PackageMappedSpecifier;
+  /** Package whose default export is used for `process` (ex. the `process` package). */
+  process?: PackageMappedSpecifier;
+}
+
+export interface ServerShim {
+  /** Package to replace `Deno.serve`, `Deno.listen`, and `Deno.serveHttp` with. */
+  package: PackageMappedSpecifier;
+  /** Names of the package's exports by the name of the API they replace (ex. `serve`). Defaults to the name of the API. */
+  exportNames?: { [api: string]: string };
+}
+
+/** `"js"` for `./mod.js`, `"preserve"` for the extension of the output file (ex. `./mod.ts`), or `"strip"` for no extension (ex. `./mod`). Declaration files are always imported without their extension. */
+export type RelativeSpecifierExtension = "js" | "preserve" | "strip";
+
+/** How the test environment imports the modules of the main environment. `"relative"` imports the output file by its relative path and `"selfReference"` imports the main entry points by the given package name (ex. `my-package/sub`). */
+export type TestMainImports =
+  | { kind: "relative" }
+  | { kind: "selfReference"; value: string };
+
+/** `"namespace"` for `import * as dntShim from "./_dnt.shims.js"`, `"named"` for importing each global by name, or `"sideEffect"` for `import "./_dnt.shims.js"` with the shim file installing the globals on `globalThis`. */
+export type ShimImportStyle = "namespace" | "named" | "sideEffect";
+
+/** `"native"` to leave the checks for whether the code is running in Deno (ex. `typeof Deno !== "undefined"`) checking the global `Deno`, or `"shim"` to have them check the Deno shim, which is always defined. */
+export type DenoFeatureDetection = "native" | "shim";
+
+/** `"globalObject"` for `dntShim.dntGlobalThis.Deno`, `"shim"` for `dntShim.Deno`, or `"preserve"` to leave `globalThis.Deno` as is. `window` and `self` are always changed to `globalThis`. */
+export type QualifiedGlobalAccess = "globalObject" | "shim" | "preserve";
+
+/** `"error"` to error on JavaScript modules that are CommonJS, or `"wrap"` to wrap their code in a function that provides `module`, `exports`, and `require`, with `module.exports` as the default export. */
+export type CommonJsModules = "error" | "wrap";
+
+/** What to do with a kind of comment directive in the output. `"rewrite"` changes the file the comment points at to its output location. */
+export type CommentDirectiveAction = "keep" | "remove" | "rewrite";
+
+export interface CommentDirectiveOptions {
+  /** `// @deno-types="./mod.d.ts"` and `// @ts-types="./mod.d.ts"` comments. Defaults to `"remove"`. */
+  denoTypes?: CommentDirectiveAction;
+  /** `// @ts-self-types="./mod.d.ts"` comments. Defaults to `"remove"`. */
+  tsSelfTypes?: CommentDirectiveAction;
+  /** `// deno-lint-ignore` and `// deno-lint-ignore-file` comments, which are rewritten to ESLint directives. Defaults to `"keep"`. */
+  denoLintIgnore?: CommentDirectiveAction;
+  /** `// deno-fmt-ignore` and `// deno-fmt-ignore-file` comments, which are rewritten to `prettier-ignore` or removed when Prettier has no equivalent. Defaults to `"keep"`. */
+  denoFmtIgnore?: CommentDirectiveAction;
+  /** ESLint rule names by the Deno lint rule name they replace when rewriting `deno-lint-ignore` comments, in addition to the built-in ones. */
+  eslintRules?: Record<string, string>;
+}
+
+/** What to do with the attributes of an import in the output. `"with"` keeps them, changing the deprecated `assert` keyword to `with`. */
+export type ImportAttributeAction = "strip" | "with" | "keep";
+
+export interface ImportAttributeOptions {
+  /** Actions for the attributes by the value of their `type` attribute (ex. `json` or `text`). */
+  types?: Record<string, ImportAttributeAction>;
+  /** Action for the attributes whose type isn't in `types`. Defaults to `"strip"`. */
+  default?: ImportAttributeAction;
+}
+
+/** `"exact"` pins the dependencies to a single version (ex. `1.2.3`), which is common for applications, while `"caret"` (ex. `^1.2.3`) and `"tilde"` (ex. `~1.2.3`) allow compatible versions, which is common for libraries. */
+export type VersionPolicy = "preserve" | "exact" | "caret" | "tilde";
+
 export interface TransformOptions {
   entryPoints: string[];
   testEntryPoints?: string[];
   shims?: Shim[];
   testShims?: Shim[];
   mappings?: SpecifierMappings;
+  /** Maps a specifier to a declaration file to use for it. This takes
+   * precedence over any `@deno-types` or `X-TypeScript-Types` declaration file. */
+  declarationMappings?: { [specifier: string]: string };
   target: ScriptTarget;
   /// Path or url to the import map.
   importMap?: string;
+  /** How JSX is transformed, which is the `jsx` and `jsxImportSource` compiler options of a deno.json. */
+  jsx?: JsxOptions;
+  /** Error instead of warning when circular dependencies are found. */
+  errorOnCycles?: boolean;
+  /** Error instead of warning when more than one version of a package is in the module graph. */
+  errorOnDuplicatePackages?: boolean;
+  /** Leave modules that fail to parse out of the output and report them in `parseErrors` instead of erroring. */
+  continueOnParseError?: boolean;
+  /** Use `\n` line endings and remove any byte order mark in the output files instead of keeping the original ones. */
+  normalizeOutputText?: boolean;
+  /** Map imports of files on GitHub (`https://raw.githubusercontent.com/...`) to a git dependency on their repository instead of outputting them. */
+  githubDependencies?: boolean;
+  /** Restrict the origins remote modules are loaded from and how deep into remote modules the imports go. */
+  remotePolicy?: RemotePolicy;
+  /** How the versions of the dependencies are written in the output. Versions that aren't a single version or a caret or tilde range of one are kept as they are. Defaults to `"preserve"`. */
+  versionPolicy?: VersionPolicy;
+  /** Support importing CSS and text files with an import assertion (ex. `assert { type: "css" }`) by outputting a module that exports the text of the file along with a copy of the file. */
+  assetImports?: boolean;
+  /** File names to try in a directory (ex. `["mod.ts", "index.ts"]`) when a local import without an extension (ex. `./utils`) isn't found. Each expanded import is reported as a warning. */
+  directoryIndexFiles?: string[];
+  /** Directories of local files to output to a directory of the output (ex. `{ dir: "../shared/src", outputDir: "shared" }`), which gives local files from disjoint directories a predictable layout. The other local files are output relative to the directory they have in common. */
+  localRoots?: LocalRoot[];
+  /** Shims to add for `Buffer` and `process` when they aren't shimmed already. */
+  nodeGlobalShims?: NodeGlobalShims;
+  /** Package with the types of the DOM (ex. `@types/web`), which is added as a dependency when the modules use DOM APIs such as `document`. The `/// <reference lib="dom" />` comments are changed to reference its types. Otherwise, modules that use DOM APIs without referencing the DOM lib are reported as warnings. */
+  domTypesPackage?: Dependency;
+  /** Declare `__dirname` and `__filename` using `import.meta.url` in the modules that use them. */
+  injectDirname?: boolean;
+  /** What to do with the JavaScript modules that are CommonJS (ex. they use `module.exports` without any import or export declarations, or have a `.cjs` extension). Defaults to `"error"`. */
+  commonjsModules?: CommonJsModules;
+  /** Shim for `Deno.serve`, `Deno.listen`, and `Deno.serveHttp`, which are otherwise reported as unsupported. */
+  serverShim?: ServerShim;
+  /** How the `@deno-types`, `@ts-self-types`, and `deno-lint-ignore` comments are handled in the output. */
+  commentDirectives?: CommentDirectiveOptions;
+  /** How the attributes of imports (ex. `with { type: "text" }`) are handled in the output. They're stripped by default. */
+  importAttributes?: ImportAttributeOptions;
+  /** Inline the remote modules up to this size in bytes into the module that imports them when it's the only one. Only modules without imports or top level statements other than declarations are inlined. */
+  inlineRemoteModuleMaxSize?: number;
+  /** Output a single file for each entry point that contains the code of every module it statically imports. Declaration files are output as they are otherwise. */
+  bundle?: boolean;
+  /** Replace the `export * from` statements that point to barrel modules, which only consist of re-exports, with direct re-exports of the modules the barrels re-export. Reduces the module resolution depth and helps bundlers tree shake the package. */
+  flattenBarrels?: boolean;
+  /** Minify the JavaScript files of the output by removing their whitespace and comments and shortening their local identifiers. The TypeScript files are left as is. */
+  minify?: boolean;
+  /** Analyze the names each entry point exports, split into values and types, and report them in the `entryPointExports` of the output. Useful for generating the `exports` of a package.json or listing the public API. */
+  entryPointExports?: boolean;
+  /** Output only the type declarations of the TypeScript modules, which removes the bodies of functions and methods, the values of variables and properties, and the other statements. Useful for publishing a types only package or generating documentation. */
+  declarationsOnly?: boolean;
+  /** Collect the license banners at the top of the remote modules into a `THIRD_PARTY_LICENSES` file in the main output. */
+  thirdPartyLicenses?: boolean;
+  /** Record the origin url, version, and license of each remote module in the `provenance` of the output. Finding the licenses loads the `LICENSE` files next to the remote packages. */
+  provenance?: boolean;
+  /** Expressions to replace with literal values (ex. `{ "import.meta.env.MODE": "\"production\"" }`). The keys are globals, property accesses, or calls with string arguments and the values are string, number, boolean, null, or undefined literals. */
+  define?: { [expression: string]: string };
+  /** Rewrite the `Deno.env` calls to use `process.env` directly instead of the `Deno` shim. Requires the Node.js types for type checking. */
+  denoEnvToProcessEnv?: boolean;
+  /** Translate the simple calls of common Deno APIs (ex. `Deno.readTextFile`) to their `node:fs/promises` and `node:process` equivalents. Calls that can't be translated use the Deno shim. Requires the Node.js types for type checking. */
+  translateDenoApis?: boolean;
+  /** How the modules that use a shimmed global import the shim file, which is a namespace import by default. */
+  shimImportStyle?: ShimImportStyle;
+  /** What the checks for whether the code is running in Deno check, which is the global `Deno` by default. A warning is emitted for each check. */
+  denoFeatureDetection?: DenoFeatureDetection;
+  /** How the shimmed globals that are accessed on the global object (ex. `globalThis.Deno`) are output, which is on the shim's global object by default. */
+  qualifiedGlobalAccess?: QualifiedGlobalAccess;
+  /** The extension of the rewritten relative specifiers, which is `.js` by default. */
+  relativeSpecifierExtension?: RelativeSpecifierExtension;
+  /** The extension of the rewritten relative specifiers in the test environment when it differs from `relativeSpecifierExtension`. */
+  testRelativeSpecifierExtension?: RelativeSpecifierExtension;
+  /** How the test environment imports the modules of the main environment, which is by relative path by default. */
+  testMainImports?: TestMainImports;
+  /** Add a comment with the original url next to each import or export of a remote module that was rewritten (ex. `// was: https://deno.land/x/...`). */
+  annotateRewrites?: boolean;
+  /** Add a short hash of the source to the file names of the remote modules (ex. `deps/deno.land/x/mod.1a2b3c4d.ts`), which is useful for caching the files long term. */
+  hashRemoteFileNames?: boolean;
+  /** Provide a TypeScript `compilerOptions.paths` alias map for the remote modules in the output, so type checking the output with `tsc` resolves their original specifiers. */
+  typescriptPaths?: boolean;
+  /** Provide a manifest summarizing the transform in the output. */
+  emitManifest?: boolean;
+  /** Warn when the main output is over these limits. */
+  sizeBudget?: SizeBudget;
+  /** Loads a module by its url instead of loading it with the Deno cache,
+   * which resolves to `undefined` when the module isn't found. */
+  load?: (
+    url: string,
+  ) => Promise<LoadResponse | undefined> | LoadResponse | undefined;
+}
+
+export interface LoadResponse {
+  /** Url of the module after redirects. */
+  specifier: string;
+  headers?: { [name: string]: string };
+  content: string;
 }
 
 /** Dependency in a package.json file. */
@@ -80,16 +275,192 @@ export interface Dependency {
   peerDependency?: boolean;
 }
 
+/** The output of a transform, which is the same on every run with the same inputs. */
 export interface TransformOutput {
   main: TransformOutputEnvironment;
   test: TransformOutputEnvironment;
   warnings: string[];
+  untypedDependencies: UntypedDependency[];
+  /** Packages that more than one version of is in the module graph. */
+  duplicatePackages: DuplicatePackage[];
+  /** Declaration files that were selected for the modules that had one specified. */
+  types: ResolvedTypes[];
+  /** Modules that were loaded, but excluded from the output because nothing reachable from the entry points needed them. */
+  excludedModules: string[];
+  /** Local files the transform depended on, which can be watched to know when to re-run the transform. */
+  fileDependencies: string[];
+  /** Where the directories of the local files were output to, including the directory the files that aren't in a local root have in common. */
+  localRoots: LocalRoot[];
+  /** Modules that failed to parse when continuing on parse errors. */
+  parseErrors: ParseDiagnostic[];
+  /** Usages of `Deno` APIs that can't be shimmed and will throw at runtime, which packaging tools can use to exclude files or fail. */
+  unshimmableApiUsages: UnshimmableApiUsage[];
+  /** Node `exports` for the main entry points, where the first entry point is exported as `"."`. */
+  exports: { [name: string]: PackageJsonExport };
+  /** Where the remote modules that were copied to the output or mapped to a package came from when `provenance` is enabled. */
+  provenance: ModuleProvenance[];
+  /** TypeScript `compilerOptions.paths` alias map from the original specifiers of the remote modules to where they were output when `typescriptPaths` is enabled. */
+  typescriptPaths: Record<string, string[]>;
+  /** Summary of the transform when `emitManifest` is enabled. */
+  manifest?: TransformManifest;
+}
+
+/** Machine readable summary of a transform, which is useful for diffing between releases. */
+export interface TransformManifest {
+  entryPoints: string[];
+  testEntryPoints: string[];
+  /** Hash of the options that affect the output. */
+  optionsHash: string;
+  /** Output file path of each module by its specifier. */
+  mappings: { [specifier: string]: string };
+  /** Remote modules that were output. */
+  remoteModules: string[];
+  dependencies: Dependency[];
+  testDependencies: Dependency[];
+  usedShims: boolean;
+  usedTestShims: boolean;
+  warnings: string[];
+  parseErrors: string[];
+}
+
+/** Conditional export of an entry point for the ES module and CommonJS output. */
+export interface PackageJsonExport {
+  import: PackageJsonExportTarget;
+  require: PackageJsonExportTarget;
+}
+
+export interface PackageJsonExportTarget {
+  types: string;
+  default: string;
+}
+
+/** Origin of a remote module that was copied to the output or mapped to a package. */
+export interface ModuleProvenance {
+  /** The url of the module after redirects. */
+  specifier: string;
+  /** Name of the package the module was mapped to. */
+  mappedPackage?: string;
+  /** Version of the mapped package or otherwise the version in the url. */
+  version?: string;
+  /** SPDX identifier of the license (ex. `MIT`) when it could be detected from the license banner of the module or its license file. */
+  license?: string;
+  /** License file found at the root of the module's package, or in its directory when the package root isn't known from the url. */
+  licenseFile?: string;
+}
+
+/** Remote JavaScript module that no declaration file could be found for. */
+export interface UntypedDependency {
+  specifier: string;
+  /** Chain of modules from an entry point that led to this module. */
+  referrerChain: string[];
+}
+
+/** A package that more than one version of is in the module graph. */
+export interface DuplicatePackage {
+  /** The url of a remote package without the version (ex. `https://deno.land/x/oak`) or the name of a mapped package. */
+  name: string;
+  versions: DuplicatePackageVersion[];
+}
+
+export interface DuplicatePackageVersion {
+  version: string;
+  /** Directory the remote modules of this version are output to, which is undefined for a version of a mapped package. */
+  outputDir: string | undefined;
+  /** Chain of modules from an entry point that led to this version. */
+  referrerChain: string[];
+}
+
+/** The declaration file that was selected for a code module. */
+export interface ResolvedTypes {
+  codeSpecifier: string;
+  declarationSpecifier: string;
+  /** Output file path of the declaration file. */
+  declarationPath: string;
+  /** Module that specified the declaration file, which is the code module itself for a types reference or header. */
+  referrer: string;
+  /** Declaration files that were also specified for the code module, but weren't selected. */
+  ignored: IgnoredTypes[];
+}
+
+export interface IgnoredTypes {
+  declarationSpecifier: string;
+  referrer: string;
+}
+
+/** Module that failed to parse. */
+export interface ParseDiagnostic {
+  specifier: string;
+  lineNumber: number;
+  columnNumber: number;
+  message: string;
+  /** Modules that import the module that failed to parse. */
+  dependents: string[];
+}
+
+/** Usage of a `Deno` API that can't be shimmed in Node.js, such as `Deno.dlopen`. */
+export interface UnshimmableApiUsage {
+  specifier: string;
+  lineNumber: number;
+  columnNumber: number;
+  /** Name of the API (ex. `Deno.Command`). */
+  api: string;
+  category: "ffi" | "subprocess";
 }
 
 export interface TransformOutputEnvironment {
+  /** Entry points in the order they were specified in. */
   entryPoints: string[];
+  /** Sorted by name and then version. */
   dependencies: Dependency[];
+  /** Sorted by file path. */
   files: OutputFile[];
+  /** Output file paths of code files and their declaration file. */
+  declarations: [string, string][];
+  /** The shims each file that imports the shim file uses, sorted by file path. */
+  shimsUsed: FileShimUsage[];
+  /** Statistics about the output files, including ones already written by a sink. */
+  stats: OutputStats;
+  /** The names each entry point exports. Only analyzed when `entryPointExports` is enabled. */
+  entryPointExports: EntryPointExports[];
+}
+
+/** The names an entry point exports. */
+export interface EntryPointExports {
+  /** Output file path of the entry point. */
+  filePath: string;
+  /** Sorted names of the exported values, including `default`. */
+  values: string[];
+  /** Sorted names of the exports that are only types. */
+  types: string[];
+}
+
+/** Statistics about the output files of an environment. */
+export interface OutputStats {
+  fileCount: number;
+  /** Total size of the output files in bytes. */
+  totalBytes: number;
+  /** The ten largest remote modules that were output, largest first. */
+  largestRemoteModules: RemoteModuleSize[];
+  /** Number of distinct globals and APIs that were replaced with shims. */
+  shimmedApiCount: number;
+}
+
+export interface RemoteModuleSize {
+  specifier: string;
+  filePath: string;
+  bytes: number;
+  /** Shortest chain of imports from an entry point to the module, which explains why it's in the output. */
+  referrerChain: string[];
+}
+
+/** The shims a file in the output uses. */
+export interface FileShimUsage {
+  specifier: string;
+  filePath: string;
+  /** Names of the globals that were replaced with their shims (ex. `Deno`), where `globalThis` is the object of all the shimmed globals and `Deno.<api>` is a server API. */
+  globals: string[];
+  /** Names of the packages, or the modules, of the shims that provide the globals (ex. `@deno/shim-deno`). */
+  shims: string[];
 }
 
 export interface OutputFile {
@@ -106,14 +477,24 @@ export async function transform(
   if (options.entryPoints.length === 0) {
     throw new Error("Specify one or more entry points.");
   }
+  const { load, ...otherOptions } = options;
   const newOptions = {
-    ...options,
+    ...otherOptions,
     mappings: Object.fromEntries(
       Object.entries(options.mappings ?? {}).map(([key, value]) => {
         return [valueToUrl(key), mapMappedSpecifier(value)];
       }),
     ),
+    declarationMappings: Object.fromEntries(
+      Object.entries(options.declarationMappings ?? {}).map(([key, value]) => {
+        return [valueToUrl(key), valueToUrl(value)];
+      }),
+    ),
     entryPoints: options.entryPoints.map(valueToUrl),
+    localRoots: (options.localRoots ?? []).map((root) => ({
+      dir: valueToUrl(root.dir),
+      outputDir: root.outputDir,
+    })),
     testEntryPoints: (options.testEntryPoints ?? []).map(valueToUrl),
     shims: (options.shims ?? []).map(mapShim),
     testShims: (options.testShims ?? []).map(mapShim),
@@ -123,7 +504,7 @@ export async function transform(
       : valueToUrl(options.importMap),
   };
   const wasmFuncs = await instantiate();
-  return wasmFuncs.transform(newOptions);
+  return wasmFuncs.transform(newOptions, load);
 }
 
 type SerializableMappedSpecifier = {
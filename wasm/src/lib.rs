@@ -2,15 +2,12 @@
 
 mod utils;
 
-use std::collections::HashMap;
 use std::future::Future;
 
 use anyhow::Result;
-use dnt::MappedSpecifier;
 use dnt::ModuleSpecifier;
-use dnt::ScriptTarget;
-use dnt::Shim;
-use serde::Deserialize;
+use dnt::SerializableTransformOptions;
+use serde::Serialize;
 use utils::set_panic_hook;
 use wasm_bindgen::prelude::*;
 
@@ -25,7 +22,11 @@ extern "C" {
   async fn fetch_specifier(specifier: String) -> JsValue;
 }
 
-struct JsLoader {}
+/// Loads the modules with the `load` callback provided from JavaScript,
+/// which may return a promise, or otherwise with the Deno cache.
+struct JsLoader {
+  load: Option<js_sys::Function>,
+}
 
 impl dnt::Loader for JsLoader {
   fn load(
@@ -34,67 +35,91 @@ impl dnt::Loader for JsLoader {
   ) -> std::pin::Pin<
     Box<dyn Future<Output = Result<Option<dnt::LoadResponse>>> + 'static>,
   > {
+    let load = self.load.clone();
     Box::pin(async move {
-      let resp = fetch_specifier(url.to_string()).await;
+      let resp = match load {
+        Some(load) => {
+          let resp = load
+            .call1(&JsValue::NULL, &JsValue::from_str(url.as_str()))
+            .map_err(|err| js_error_to_anyhow(&url, err))?;
+          match resp.dyn_into::<js_sys::Promise>() {
+            Ok(promise) => wasm_bindgen_futures::JsFuture::from(promise)
+              .await
+              .map_err(|err| js_error_to_anyhow(&url, err))?,
+            Err(resp) => resp,
+          }
+        }
+        None => fetch_specifier(url.to_string()).await,
+      };
       if resp.is_null() || resp.is_undefined() {
         return Ok(None);
       }
       if !resp.is_object() {
         anyhow::bail!("fetch response wasn't an object");
       }
-      let load_response = serde_wasm_bindgen::from_value(resp).unwrap();
+      let load_response = serde_wasm_bindgen::from_value(resp)
+        .map_err(|err| anyhow::anyhow!("Error loading {}. {}", url, err))?;
       Ok(Some(load_response))
     })
   }
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct TransformOptions {
-  pub entry_points: Vec<String>,
-  pub test_entry_points: Vec<String>,
-  pub shims: Vec<Shim>,
-  pub test_shims: Vec<Shim>,
-  pub mappings: HashMap<ModuleSpecifier, MappedSpecifier>,
-  pub target: ScriptTarget,
-  pub import_map: Option<ModuleSpecifier>,
+fn js_error_to_anyhow(url: &ModuleSpecifier, err: JsValue) -> anyhow::Error {
+  let message = err
+    .dyn_ref::<js_sys::Error>()
+    .map(|err| String::from(err.message()))
+    .or_else(|| err.as_string())
+    .unwrap_or_else(|| format!("{:?}", err));
+  anyhow::anyhow!("Error loading {}. {}", url, message)
+}
+
+/// Removes the properties that are `undefined` in place (ex. `{ bundle:
+/// undefined }`), which would otherwise fail to deserialize as a unit value
+/// instead of using the default of the option.
+fn remove_undefined_properties(value: &JsValue) {
+  if js_sys::Array::is_array(value) {
+    for item in js_sys::Array::from(value).iter() {
+      remove_undefined_properties(&item);
+    }
+  } else if value.is_object() {
+    let object = js_sys::Object::from(value.clone());
+    for entry in js_sys::Object::entries(&object).iter() {
+      let entry = js_sys::Array::from(&entry);
+      let value = entry.get(1);
+      if value.is_undefined() {
+        js_sys::Reflect::delete_property(&object, &entry.get(0)).unwrap();
+      } else {
+        remove_undefined_properties(&value);
+      }
+    }
+  }
 }
 
+/// Transforms with the options and the output serialized to and from
+/// JavaScript values. Modules are loaded with the `load` callback when
+/// provided, which is called with the url of a module and returns the
+/// module, a promise of it, or `undefined` when it's not found.
 #[wasm_bindgen]
-pub async fn transform(options: JsValue) -> Result<JsValue, JsValue> {
+pub async fn transform(
+  options: JsValue,
+  load: Option<js_sys::Function>,
+) -> Result<JsValue, JsValue> {
   set_panic_hook();
 
-  #[allow(deprecated)]
-  let options: TransformOptions = options.into_serde().unwrap();
-  // todo(dsherret): try using this again sometime in the future... it errored
-  // with "invalid type: unit value, expected a boolean" and didn't say exactly
-  // where it errored.
-  // let options: TransformOptions = serde_wasm_bindgen::from_value(options)?;
-
-  let result = dnt::transform(dnt::TransformOptions {
-    entry_points: parse_module_specifiers(options.entry_points)?,
-    test_entry_points: parse_module_specifiers(options.test_entry_points)?,
-    shims: options.shims,
-    test_shims: options.test_shims,
-    loader: Some(Box::new(JsLoader {})),
-    specifier_mappings: options.mappings,
-    target: options.target,
-    import_map: options.import_map,
-  })
-  .await
-  .map_err(|err| format!("{:#}", err))?; // need to include the anyhow context
+  remove_undefined_properties(&options);
+  let options: SerializableTransformOptions =
+    serde_wasm_bindgen::from_value(options)
+      .map_err(|err| format!("Error deserializing the options. {}", err))?;
+  let options = options
+    .into_transform_options(Box::new(JsLoader { load }))
+    .map_err(|err| err.to_string())?;
 
-  Ok(serde_wasm_bindgen::to_value(&result).unwrap())
-}
+  let result = dnt::transform(options)
+    .await
+    .map_err(|err| format!("{:#}", err))?; // need to include the anyhow context
 
-fn parse_module_specifiers(
-  values: Vec<String>,
-) -> Result<Vec<ModuleSpecifier>, JsValue> {
-  let mut specifiers = Vec::new();
-  for value in values {
-    let entry_point = dnt::ModuleSpecifier::parse(&value)
-      .map_err(|err| format!("Error parsing {}. {}", value, err))?;
-    specifiers.push(entry_point);
-  }
-  Ok(specifiers)
+  // maps are output as plain objects rather than a `Map`
+  let serializer =
+    serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true);
+  Ok(result.serialize(&serializer).unwrap())
 }